@@ -11,17 +11,20 @@ async fn main() {
             vec!["Pineapple", "Rocks"], // documents
             vec!["Pizza Toppings"],     // tags (only used for filtering)
         )
-        .await;
+        .await
+        .unwrap();
 
     victor
         .add_single("Cheese pizza", vec!["Pizza Flavors"])
-        .await; // Add another entry with no tags
+        .await
+        .unwrap(); // Add another entry with no tags
 
     // read the 10 closest results from victor that are tagged with "Pizza Toppings"
     // (only 2 will be returned because we only inserted two embeddings)
     let nearest = victor
         .search("Hawaiian pizza", vec!["Pizza Toppings"], 10)
         .await
+        .unwrap()
         .first()
         .unwrap()
         .content
@@ -0,0 +1,26 @@
+//! When each document was added, recorded in `added.bin` for searching by [`crate::SearchOptions::added`].
+
+use std::collections::HashMap;
+
+use uuid::Uuid;
+
+use crate::{
+    db::{read_bin, write_bin},
+    error::Error,
+    filesystem::DirectoryHandle,
+};
+
+const FILENAME: &str = "added.bin";
+
+/// When each document was added, in milliseconds since the Unix epoch.
+/// Documents added before this was recorded have no entry.
+pub(crate) async fn read<D: DirectoryHandle>(root: &D) -> Result<HashMap<Uuid, u64>, Error> {
+    read_bin(root, FILENAME).await
+}
+
+pub(crate) async fn write<D: DirectoryHandle>(
+    root: &D,
+    added: &HashMap<Uuid, u64>,
+) -> Result<(), Error> {
+    write_bin(root, FILENAME, added).await
+}
@@ -0,0 +1,179 @@
+//! Documents as Arrow record batches, for Arrow-based tools (see [`crate::Victor::export_arrow`] and
+//! [`crate::Victor::import_arrow`]) and for writing Parquet (see [`crate::Victor::export_parquet`]).
+//!
+//! Every exported batch has the same columns: `id`, `content`, `tags` (a list of strings), `vector` (a fixed-size
+//! list of floats), and `metadata` (a JSON object, see [`crate::jsonl`]). Imported batches only need `content` and
+//! `vector`, which can also be a list of floats of any size, since that's what many tools write.
+
+use std::sync::Arc;
+
+use arrow_array::{
+    builder::{ListBuilder, StringBuilder},
+    cast::AsArray,
+    types::Float32Type,
+    Array, ArrayRef, FixedSizeListArray, Float32Array, RecordBatch, StringArray,
+};
+use arrow_schema::{ArrowError, DataType, Field, Schema, SchemaRef};
+
+use crate::{
+    db::StoredDocument,
+    document::Document,
+    error::Error,
+    jsonl::{metadata_from_json, metadata_to_json},
+};
+
+/// The schema of batches of documents with `dimension`-dimensional vectors.
+pub(crate) fn schema(dimension: usize) -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        Field::new("id", DataType::Utf8, false),
+        Field::new("content", DataType::Utf8, false),
+        Field::new(
+            "tags",
+            DataType::List(Arc::new(Field::new("item", DataType::Utf8, true))),
+            false,
+        ),
+        Field::new(
+            "vector",
+            DataType::FixedSizeList(vector_item(), dimension as i32),
+            false,
+        ),
+        Field::new("metadata", DataType::Utf8, false),
+    ]))
+}
+
+fn vector_item() -> Arc<Field> {
+    Arc::new(Field::new("item", DataType::Float32, false))
+}
+
+/// Convert `documents` to a record batch. Every vector must have `dimension` dimensions.
+pub(crate) fn record_batch(
+    documents: &[StoredDocument],
+    dimension: usize,
+) -> Result<RecordBatch, Error> {
+    let mut ids = Vec::with_capacity(documents.len());
+    let mut contents = Vec::with_capacity(documents.len());
+    let mut tags = ListBuilder::new(StringBuilder::new());
+    let mut vectors = Vec::with_capacity(documents.len() * dimension);
+    let mut metadata = Vec::with_capacity(documents.len());
+    for (document_tags, embedding, content) in documents {
+        if embedding.vector.len() != dimension {
+            return Err(Error::DimensionMismatch {
+                expected: dimension,
+                found: embedding.vector.len(),
+            });
+        }
+
+        ids.push(content.id_or(embedding.id));
+        contents.push(content.content.as_str());
+        tags.append_value(document_tags.iter().map(Some));
+        vectors.extend_from_slice(&embedding.vector);
+        metadata.push(serde_json::Value::from(metadata_to_json(&content.metadata)).to_string());
+    }
+
+    let vectors = FixedSizeListArray::try_new(
+        vector_item(),
+        dimension as i32,
+        Arc::new(Float32Array::from(vectors)),
+        None,
+    )
+    .map_err(into_error)?;
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(StringArray::from(ids)),
+        Arc::new(StringArray::from(contents)),
+        Arc::new(tags.finish()),
+        Arc::new(vectors),
+        Arc::new(StringArray::from(metadata)),
+    ];
+
+    RecordBatch::try_new(schema(dimension), columns).map_err(into_error)
+}
+
+/// Convert a record batch to the documents in it, each with the tags to add it with.
+pub(crate) fn documents(batch: &RecordBatch) -> Result<Vec<(Vec<String>, Document)>, Error> {
+    let column = |name: &str| batch.column_by_name(name);
+    let wrong_type = |name: &str, expected: &str| {
+        Error::InvalidInput(format!("the {name} column must be {expected}"))
+    };
+
+    let contents = column("content")
+        .ok_or_else(|| Error::InvalidInput("there's no content column".to_string()))?
+        .as_string_opt::<i32>()
+        .ok_or_else(|| wrong_type("content", "strings"))?;
+    let vectors = column("vector")
+        .ok_or_else(|| Error::InvalidInput("there's no vector column".to_string()))?;
+    let vector = |row: usize| -> Result<Vec<f32>, Error> {
+        let values = match (
+            vectors.as_fixed_size_list_opt(),
+            vectors.as_list_opt::<i32>(),
+        ) {
+            (Some(vectors), _) => vectors.value(row),
+            (_, Some(vectors)) => vectors.value(row),
+            _ => return Err(wrong_type("vector", "lists of 32-bit floats")),
+        };
+        let values = values
+            .as_primitive_opt::<Float32Type>()
+            .ok_or_else(|| wrong_type("vector", "lists of 32-bit floats"))?;
+        Ok(values.values().to_vec())
+    };
+    let ids = column("id")
+        .map(|ids| {
+            ids.as_string_opt::<i32>()
+                .ok_or_else(|| wrong_type("id", "strings"))
+        })
+        .transpose()?;
+    let tags = column("tags")
+        .map(|tags| {
+            tags.as_list_opt::<i32>()
+                .ok_or_else(|| wrong_type("tags", "lists of strings"))
+        })
+        .transpose()?;
+    let metadata = column("metadata")
+        .map(|metadata| {
+            metadata
+                .as_string_opt::<i32>()
+                .ok_or_else(|| wrong_type("metadata", "JSON objects"))
+        })
+        .transpose()?;
+
+    let mut documents = Vec::with_capacity(batch.num_rows());
+    for row in 0..batch.num_rows() {
+        if contents.is_null(row) || vectors.is_null(row) {
+            return Err(Error::InvalidInput(format!(
+                "row {row} is missing its content or vector"
+            )));
+        }
+        let id = ids
+            .filter(|ids| !ids.is_null(row))
+            .map(|ids| ids.value(row));
+        let mut document = Document::new(contents.value(row), vector(row)?);
+        if let Some(id) = id {
+            document = document.with_id(id);
+        }
+
+        if let Some(metadata) = metadata.filter(|metadata| !metadata.is_null(row)) {
+            let described = id.map_or_else(|| format!("row {row}"), str::to_string);
+            let json = serde_json::from_str(metadata.value(row)).map_err(|error| {
+                Error::InvalidInput(format!("the metadata of '{described}': {error}"))
+            })?;
+            document.metadata = metadata_from_json(json, &described)?;
+        }
+
+        let row_tags = match tags.filter(|tags| !tags.is_null(row)) {
+            Some(tags) => tags
+                .value(row)
+                .as_string_opt::<i32>()
+                .ok_or_else(|| wrong_type("tags", "lists of strings"))?
+                .iter()
+                .flatten()
+                .map(str::to_string)
+                .collect(),
+            None => Vec::new(),
+        };
+        documents.push((row_tags, document));
+    }
+    Ok(documents)
+}
+
+fn into_error(error: ArrowError) -> Error {
+    Error::InvalidInput(error.to_string())
+}
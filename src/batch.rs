@@ -0,0 +1,144 @@
+//! Batches: inserts, updates and deletes staged together and applied all at once, see [`crate::Victor::batch`].
+//!
+//! Before a batch is applied, `batch.bin` records what every file in the database was like: db files that the
+//! batch only appends to by their size, and every other file by its contents. Once the whole batch is applied
+//! it's removed. If applying it fails, or it's left over because the batch was interrupted,
+//! the files are put back the way they were (see [`crate::Victor::recover`]), so none of the batch is kept.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    db::{read_optional, write_bin, Victor},
+    document::Document,
+    error::Error,
+    filesystem::DirectoryHandle,
+};
+
+const FILENAME: &str = "batch.bin";
+
+/// What a file was like before the batch.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) enum Saved {
+    /// The file didn't exist, or was empty.
+    Missing,
+    /// The file was this many bytes long, and the batch only appends to it.
+    Size(usize),
+    /// The file's contents.
+    Bytes(Vec<u8>),
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub(crate) struct Backup {
+    /// Each file in the database before the batch, by name.
+    pub files: BTreeMap<String, Saved>,
+}
+
+/// A change staged in a [`Batch`].
+pub(crate) enum Operation {
+    Add {
+        documents: Vec<Document>,
+        tags: Vec<String>,
+    },
+    Update {
+        id: Uuid,
+        content: String,
+        vector: Vec<f32>,
+    },
+    Delete(Uuid),
+}
+
+impl Operation {
+    /// Whether the operation may rewrite embeddings already in a db file, rather than only appending new ones.
+    pub(crate) fn rewrites_in_place(&self) -> bool {
+        match self {
+            // documents with an id replace the document already stored with it, if there is one
+            Operation::Add { documents, .. } => {
+                documents.iter().any(|document| document.id.is_some())
+            }
+            Operation::Update { .. } => true,
+            Operation::Delete(_) => false,
+        }
+    }
+}
+
+/// Inserts, updates and deletes to apply together, from [`Victor::batch`].
+///
+/// Nothing is written until [`Batch::commit`], which applies every change in the order it was staged,
+/// or none of them if any fails.
+pub struct Batch<'a, D: DirectoryHandle> {
+    victor: &'a mut Victor<D>,
+    operations: Vec<Operation>,
+}
+
+impl<'a, D: DirectoryHandle> Batch<'a, D> {
+    pub(crate) fn new(victor: &'a mut Victor<D>) -> Self {
+        Self {
+            victor,
+            operations: Vec::new(),
+        }
+    }
+
+    /// Stage adding documents with `tags`, like [`Victor::add_documents`].
+    pub fn add_documents(
+        &mut self,
+        documents: Vec<Document>,
+        tags: Vec<impl Into<String>>,
+    ) -> &mut Self {
+        self.operations.push(Operation::Add {
+            documents,
+            tags: tags.into_iter().map(|tag| tag.into()).collect(),
+        });
+        self
+    }
+
+    /// Stage replacing a document's content and vector, like [`Victor::update`].
+    /// Updating a document that doesn't exist (or is deleted) does nothing.
+    pub fn update(&mut self, id: Uuid, content: impl Into<String>, vector: Vec<f32>) -> &mut Self {
+        self.operations.push(Operation::Update {
+            id,
+            content: content.into(),
+            vector,
+        });
+        self
+    }
+
+    /// Stage deleting a document, like [`Victor::delete`].
+    /// Deleting a document that doesn't exist does nothing.
+    pub fn delete(&mut self, id: Uuid) -> &mut Self {
+        self.operations.push(Operation::Delete(id));
+        self
+    }
+
+    /// The number of changes staged.
+    pub fn len(&self) -> usize {
+        self.operations.len()
+    }
+
+    /// Whether no changes are staged.
+    pub fn is_empty(&self) -> bool {
+        self.operations.is_empty()
+    }
+
+    /// Apply every staged change, or, if any of them fails, none of them, returning the error.
+    pub async fn commit(self) -> Result<(), Error> {
+        self.victor.apply_batch(self.operations).await
+    }
+}
+
+/// The backup of the batch in progress, if there is one.
+pub(crate) async fn read<D: DirectoryHandle>(root: &D) -> Result<Option<Backup>, Error> {
+    read_optional(root, FILENAME).await
+}
+
+pub(crate) async fn write<D: DirectoryHandle>(root: &D, backup: &Backup) -> Result<(), Error> {
+    write_bin(root, FILENAME, backup).await
+}
+
+/// Mark the batch in progress as finished.
+pub(crate) async fn remove<D: DirectoryHandle>(root: &mut D) -> Result<(), Error> {
+    let _ = root.remove_entry(FILENAME).await;
+    Ok(())
+}
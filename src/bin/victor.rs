@@ -0,0 +1,350 @@
+//! The `victor` command line tool, for using a native database from the shell. Run `victor help` for usage.
+
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, IsTerminal, Read, Write},
+    path::PathBuf,
+    process::ExitCode,
+};
+
+use victor_db::{native::Db, Metric, Progress, Settings, Storage};
+
+const USAGE: &str = "\
+Usage: victor <command> <database directory> [arguments]
+
+Commands:
+  init <dir> [--metric cosine|dot|euclidean|manhattan] [--storage full|half|packed] [--dimension N]
+       [--normalize]
+      Create a database. With --normalize, vectors are scaled to unit length.
+  add <dir> [--tag TAG]... [FILE]...
+      Add each file as a document, or each line of stdin if there are no files.
+  search <dir> <query> [--tag TAG]... [--top N] [--json]
+      Print the documents most similar to the query, best first.
+  stats <dir>
+      Print an overview of the database as JSON.
+  export <dir> [FILE]
+      Write every document as JSON Lines, to stdout if there's no file.
+  import <dir> [FILE]
+      Add documents from JSON Lines written by export, from stdin if there's no file.
+  compact <dir>
+      Reclaim the space taken up by deleted documents.
+  optimize <dir>
+      Merge small db files, so there are fewer to search.
+  help
+      Print this message.
+";
+
+#[derive(Debug, PartialEq)]
+enum Command {
+    Init {
+        dir: PathBuf,
+        settings: Settings,
+    },
+    Add {
+        dir: PathBuf,
+        tags: Vec<String>,
+        files: Vec<PathBuf>,
+    },
+    Search {
+        dir: PathBuf,
+        query: String,
+        tags: Vec<String>,
+        top_n: u32,
+        json: bool,
+    },
+    Stats {
+        dir: PathBuf,
+    },
+    Export {
+        dir: PathBuf,
+        file: Option<PathBuf>,
+    },
+    Import {
+        dir: PathBuf,
+        file: Option<PathBuf>,
+    },
+    Compact {
+        dir: PathBuf,
+    },
+    Optimize {
+        dir: PathBuf,
+    },
+    Help,
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> ExitCode {
+    let command = match parse(std::env::args().skip(1)) {
+        Ok(command) => command,
+        Err(error) => {
+            eprintln!("{error}\n\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+
+    match run(command).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("error: {error}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn parse(args: impl IntoIterator<Item = String>) -> Result<Command, String> {
+    let mut args = args.into_iter();
+    let command = args.next().unwrap_or_else(|| "help".to_string());
+    if matches!(command.as_str(), "help" | "--help" | "-h") {
+        return Ok(Command::Help);
+    }
+
+    let mut positional = Vec::new();
+    let mut tags = Vec::new();
+    let mut top_n = 10;
+    let mut json = false;
+    let mut settings = Settings::default();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{arg} needs a value"));
+        match arg.as_str() {
+            "--tag" => tags.push(value()?),
+            "--top" => top_n = number(&value()?)?,
+            "--json" => json = true,
+            "--metric" => settings.metric = metric(&value()?)?,
+            "--storage" => settings.storage = storage(&value()?)?,
+            "--dimension" => settings.dimension = Some(number(&value()?)?),
+            "--normalize" => settings.normalize = true,
+            _ if arg.starts_with("--") => return Err(format!("unknown option {arg}")),
+            _ => positional.push(arg),
+        }
+    }
+
+    let arguments = match command.as_str() {
+        "init" | "stats" | "compact" | "optimize" => 1,
+        "search" | "export" | "import" => 2,
+        _ => usize::MAX,
+    };
+    if positional.len() > arguments {
+        return Err("too many arguments".to_string());
+    }
+
+    let mut positional = positional.into_iter();
+    let dir = positional
+        .next()
+        .map(PathBuf::from)
+        .ok_or("missing the database directory")?;
+    let second = positional.next();
+    let file = second.clone().map(PathBuf::from);
+    let command = match command.as_str() {
+        "init" => Command::Init { dir, settings },
+        "add" => Command::Add {
+            dir,
+            tags,
+            files: file
+                .into_iter()
+                .chain(positional.map(PathBuf::from))
+                .collect(),
+        },
+        "search" => Command::Search {
+            dir,
+            query: second.ok_or("missing the query")?,
+            tags,
+            top_n,
+            json,
+        },
+        "stats" => Command::Stats { dir },
+        "export" => Command::Export { dir, file },
+        "import" => Command::Import { dir, file },
+        "compact" => Command::Compact { dir },
+        "optimize" => Command::Optimize { dir },
+        _ => return Err(format!("unknown command {command}")),
+    };
+    Ok(command)
+}
+
+fn number<T: std::str::FromStr>(value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("expected a number, found {value}"))
+}
+
+fn metric(value: &str) -> Result<Metric, String> {
+    match value {
+        "cosine" => Ok(Metric::Cosine),
+        "dot" => Ok(Metric::Dot),
+        "euclidean" => Ok(Metric::Euclidean),
+        "manhattan" => Ok(Metric::Manhattan),
+        _ => Err(format!("unknown metric {value}")),
+    }
+}
+
+fn storage(value: &str) -> Result<Storage, String> {
+    match value {
+        "full" => Ok(Storage::Full),
+        "half" => Ok(Storage::Half),
+        "packed" => Ok(Storage::Packed),
+        _ => Err(format!("unknown storage {value}")),
+    }
+}
+
+async fn run(command: Command) -> Result<(), Box<dyn std::error::Error>> {
+    match command {
+        Command::Help => print!("{USAGE}"),
+        Command::Init { dir, settings } => {
+            std::fs::create_dir_all(&dir)?;
+            Db::with_settings(dir, settings).await?.close().await?;
+        }
+        Command::Add { dir, tags, files } => {
+            let mut victor = open(dir)?;
+            let documents = match files.is_empty() {
+                true => std::io::stdin()
+                    .lock()
+                    .lines()
+                    .filter(|line| !matches!(line, Ok(line) if line.trim().is_empty()))
+                    .collect::<Result<Vec<_>, _>>()?,
+                false => files
+                    .iter()
+                    .map(std::fs::read_to_string)
+                    .collect::<Result<Vec<_>, _>>()?,
+            };
+
+            let count = documents.len();
+            let show_progress = std::io::stderr().is_terminal();
+            victor
+                .add_with_progress(documents, tags, |progress| {
+                    if show_progress {
+                        let stage = match progress {
+                            Progress::Embedding { .. } => "embedding",
+                            Progress::Writing { .. } => "writing",
+                        };
+                        eprint!("\r{stage}: {:.0}%", progress.fraction() * 100.0);
+                    }
+                })
+                .await?;
+            if show_progress {
+                eprintln!();
+            }
+            eprintln!("added {count} documents");
+        }
+        Command::Search {
+            dir,
+            query,
+            tags,
+            top_n,
+            json,
+        } => {
+            let victor = open(dir)?;
+            let mut stdout = std::io::stdout().lock();
+            for result in victor.search(query, tags, top_n).await? {
+                match json {
+                    true => writeln!(stdout, "{}", serde_json::to_string(&result)?)?,
+                    false => writeln!(
+                        stdout,
+                        "{:.4}\t{}",
+                        result.similarity,
+                        result.content.replace('\n', " ")
+                    )?,
+                }
+            }
+        }
+        Command::Stats { dir } => {
+            let stats = open(dir)?.stats().await?;
+            println!("{}", serde_json::to_string_pretty(&stats)?);
+        }
+        Command::Export { dir, file } => {
+            let victor = open(dir)?;
+            let count = match file {
+                Some(file) => {
+                    victor
+                        .export_jsonl(BufWriter::new(File::create(file)?))
+                        .await?
+                }
+                None => {
+                    victor
+                        .export_jsonl(BufWriter::new(std::io::stdout().lock()))
+                        .await?
+                }
+            };
+            eprintln!("exported {count} documents");
+        }
+        Command::Import { dir, file } => {
+            let mut victor = open(dir)?;
+            let reader: Box<dyn Read> = match file {
+                Some(file) => Box::new(File::open(file)?),
+                None => Box::new(std::io::stdin()),
+            };
+            let count = victor.import_jsonl(BufReader::new(reader)).await?;
+            eprintln!("imported {count} documents");
+        }
+        Command::Compact { dir } => {
+            let reclaimed = open(dir)?.compact().await?;
+            eprintln!("reclaimed {reclaimed} bytes");
+        }
+        Command::Optimize { dir } => {
+            let merged = open(dir)?.optimize().await?;
+            eprintln!("merged {merged} db files");
+        }
+    }
+    Ok(())
+}
+
+/// Open the database in `dir`, which must have been created already (say, with `victor init`).
+fn open(dir: PathBuf) -> Result<Db, String> {
+    if !dir.is_dir() {
+        return Err(format!(
+            "there's no database at {}, create one with `victor init`",
+            dir.display()
+        ));
+    }
+    Ok(Db::new(dir))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_args(args: &str) -> Result<Command, String> {
+        parse(args.split_whitespace().map(str::to_string))
+    }
+
+    #[test]
+    fn parses_commands() {
+        assert_eq!(parse_args(""), Ok(Command::Help));
+        assert_eq!(
+            parse_args("search db pizza --tag food --top 3"),
+            Ok(Command::Search {
+                dir: PathBuf::from("db"),
+                query: "pizza".to_string(),
+                tags: vec!["food".to_string()],
+                top_n: 3,
+                json: false,
+            })
+        );
+        assert_eq!(
+            parse_args("add db a.txt b.txt --tag docs"),
+            Ok(Command::Add {
+                dir: PathBuf::from("db"),
+                tags: vec!["docs".to_string()],
+                files: vec![PathBuf::from("a.txt"), PathBuf::from("b.txt")],
+            })
+        );
+        assert_eq!(
+            parse_args("init db --metric dot --dimension 384 --normalize"),
+            Ok(Command::Init {
+                dir: PathBuf::from("db"),
+                settings: Settings {
+                    metric: Metric::Dot,
+                    dimension: Some(384),
+                    normalize: true,
+                    ..Default::default()
+                },
+            })
+        );
+
+        assert!(parse_args("stats").is_err());
+        assert!(parse_args("search db").is_err());
+        assert!(parse_args("compact db extra").is_err());
+        assert!(parse_args("search db pizza --top").is_err());
+        assert!(parse_args("init db --metric hamming").is_err());
+        assert!(parse_args("frobnicate db").is_err());
+    }
+}
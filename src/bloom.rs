@@ -0,0 +1,152 @@
+//! Bloom filters of the ids in each db file, so finding an embedding by id without the catalog
+//! (see [`crate::catalog`]) only reads the db files that might hold it.
+//!
+//! Like HNSW graphs, each db file gets its own filter, stored next to it (see [`filename_for_segment`]).
+//! A filter records the checksum its db file had when the filter was last brought up to date, so a filter that
+//! fell behind its db file (because it was written by an older version, or by something that doesn't know about
+//! filters) is ignored instead of hiding embeddings.
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    db::{deserialize, write_bin},
+    error::Error,
+    filesystem::{DirectoryHandle, FileHandle, GetFileHandleOptions},
+};
+
+/// Bits per id, which with [`HASHES`] hashes gives a false positive rate of about 1%.
+const BITS_PER_ID: usize = 10;
+const HASHES: u64 = 7;
+/// The fewest ids a filter has room for, so small db files don't need rebuilding every few writes.
+const MIN_CAPACITY: usize = 256;
+
+/// The name of the file holding the bloom filter for the db file `segment`.
+pub(crate) fn filename_for_segment(segment: &str) -> String {
+    format!("{}.bloom", segment.trim_end_matches(".bin"))
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct Bloom {
+    /// The checksum of the db file the last time the filter was brought up to date with it.
+    checksum: u32,
+    /// How many ids the filter has room for before it has to be rebuilt bigger.
+    capacity: usize,
+    len: usize,
+    bits: Vec<u64>,
+}
+
+impl Bloom {
+    /// A filter of `ids`, all of the db file whose checksum is `checksum`, with room for as many again.
+    pub(crate) fn new(ids: impl IntoIterator<Item = Uuid>, checksum: u32) -> Self {
+        let ids = ids.into_iter().collect::<Vec<_>>();
+        let capacity = (ids.len() * 2).max(MIN_CAPACITY);
+        let mut bloom = Self {
+            checksum,
+            capacity,
+            len: 0,
+            bits: vec![0; (capacity * BITS_PER_ID).div_ceil(64)],
+        };
+        for id in ids {
+            bloom.insert(id);
+        }
+        bloom
+    }
+
+    /// Whether the filter is up to date with a db file whose checksum is `checksum`.
+    pub(crate) fn is_current(&self, checksum: u32) -> bool {
+        self.checksum == checksum
+    }
+
+    /// Record that the db file was changed without adding any ids, so its checksum is now `checksum`.
+    pub(crate) fn set_checksum(&mut self, checksum: u32) {
+        self.checksum = checksum;
+    }
+
+    /// Whether `additional` more ids can be inserted without the false positive rate going up.
+    pub(crate) fn has_room(&self, additional: usize) -> bool {
+        self.len + additional <= self.capacity
+    }
+
+    pub(crate) fn insert(&mut self, id: Uuid) {
+        for bit in self.bits_for(id) {
+            self.bits[bit / 64] |= 1 << (bit % 64);
+        }
+        self.len += 1;
+    }
+
+    /// Whether the db file might hold `id`. Never false for an id that was inserted.
+    pub(crate) fn may_contain(&self, id: &Uuid) -> bool {
+        self.bits_for(*id)
+            .all(|bit| self.bits[bit / 64] & (1 << (bit % 64)) != 0)
+    }
+
+    /// The bits set for `id`, by double hashing with its two halves. Ids are random (or hashes of external ids),
+    /// so they don't need hashing again.
+    fn bits_for(&self, id: Uuid) -> impl Iterator<Item = usize> {
+        let (first, second) = id.as_u64_pair();
+        let bits = self.bits.len() as u64 * 64;
+        (0..HASHES).map(move |i| (first.wrapping_add(i.wrapping_mul(second | 1)) % bits) as usize)
+    }
+}
+
+/// Read the bloom filter for the db file `segment`, if it has one.
+pub(crate) async fn read<D: DirectoryHandle>(
+    root: &D,
+    segment: &str,
+) -> Result<Option<Bloom>, Error> {
+    let filename = filename_for_segment(segment);
+    let Ok(bloom_file_handle) = root
+        .get_file_handle_with_options(&filename, &GetFileHandleOptions { create: false })
+        .await
+    else {
+        return Ok(None);
+    };
+
+    let bloom = bloom_file_handle.read().await?;
+
+    if bloom.is_empty() {
+        Ok(None)
+    } else {
+        deserialize(&filename, &bloom).map(Some)
+    }
+}
+
+pub(crate) async fn write<D: DirectoryHandle>(
+    root: &D,
+    segment: &str,
+    bloom: &Bloom,
+) -> Result<(), Error> {
+    write_bin(root, &filename_for_segment(segment), bloom).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_false_negatives() {
+        let ids = (0..1000).map(|_| Uuid::new_v4()).collect::<Vec<_>>();
+        let mut bloom = Bloom::new(ids[..500].iter().copied(), 1);
+        assert!(bloom.has_room(500));
+        assert!(!bloom.has_room(501));
+        for &id in &ids[500..] {
+            bloom.insert(id);
+        }
+
+        assert!(ids.iter().all(|id| bloom.may_contain(id)));
+        assert!(bloom.is_current(1));
+        bloom.set_checksum(2);
+        assert!(!bloom.is_current(1));
+    }
+
+    #[test]
+    fn few_false_positives() {
+        let bloom = Bloom::new((0..1000).map(|_| Uuid::new_v4()), 0);
+        let false_positives = (0..10_000)
+            .filter(|_| bloom.may_contain(&Uuid::new_v4()))
+            .count();
+        // about 1% when full, and this filter is only half full
+        assert!(false_positives < 200, "{false_positives} false positives");
+    }
+}
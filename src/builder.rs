@@ -0,0 +1,238 @@
+//! Configuring a database before opening it, see [`Builder`].
+
+use std::collections::BTreeSet;
+
+use uuid::Uuid;
+
+use crate::{
+    compression::Compression,
+    db::{NearestNeighborsResult, Victor},
+    decomposition::AutoProjection,
+    document::{Deduplication, Document},
+    embedder::Embedder,
+    error::Error,
+    filesystem::DirectoryHandle,
+    hooks::SearchRequest,
+    quantization::Storage,
+    reranker::Reranker,
+    settings::Settings,
+    similarity::Metric,
+};
+
+/// Configures and opens a database, from [`Victor::builder`].
+///
+/// Settings that are recorded in the database (the metric, dimension, storage, compression, normalization and
+/// allowed tags)
+/// default to what the database already has, or to [`Settings::default`] for a new database. Setting them to
+/// anything else returns an error from [`Builder::build`] once embeddings have been added,
+/// like [`Victor::with_settings`].
+/// Everything else only lasts as long as the opened database, like the `Victor::with_*` methods.
+///
+/// ```rust
+/// # tokio_test::block_on(async {
+/// # use victor_db::memory::{Db, DirectoryHandle};
+/// use victor_db::{Metric, Storage};
+///
+/// let directory = DirectoryHandle::default();
+/// let mut victor = Db::builder(directory.clone())
+///     .metric(Metric::Dot)
+///     .dimension(3)
+///     .storage(Storage::Full)
+///     .auto_projection(None)
+///     .build()
+///     .await
+///     .unwrap();
+/// victor.add_single_embedding("Pepperoni pizza", vec![0.1, 0.2, 0.3], vec!["Pizza Flavors"]).await.unwrap();
+///
+/// // reopening keeps the recorded settings, but they can't be changed
+/// let victor = Db::builder(directory.clone()).build().await.unwrap();
+/// assert_eq!(victor.settings().await.unwrap().metric, Metric::Dot);
+/// assert!(Db::builder(directory).metric(Metric::Cosine).build().await.is_err());
+/// # })
+/// ```
+pub struct Builder<D: DirectoryHandle> {
+    victor: Victor<D>,
+    metric: Option<Metric>,
+    dimension: Option<usize>,
+    storage: Option<Storage>,
+    compression: Option<Compression>,
+    normalize: Option<bool>,
+    allowed_tags: Option<BTreeSet<String>>,
+}
+
+impl<D: DirectoryHandle> Builder<D> {
+    pub(crate) fn new(victor: Victor<D>) -> Self {
+        Self {
+            victor,
+            metric: None,
+            dimension: None,
+            storage: None,
+            compression: None,
+            normalize: None,
+            allowed_tags: None,
+        }
+    }
+
+    /// How embeddings are compared, see [`Settings::metric`].
+    pub fn metric(mut self, metric: Metric) -> Self {
+        self.metric = Some(metric);
+        self
+    }
+
+    /// The dimension of every embedding, see [`Settings::dimension`].
+    pub fn dimension(mut self, dimension: usize) -> Self {
+        self.dimension = Some(dimension);
+        self
+    }
+
+    /// How vectors are stored, see [`Settings::storage`].
+    pub fn storage(mut self, storage: Storage) -> Self {
+        self.storage = Some(storage);
+        self
+    }
+
+    /// How files are compressed, see [`Settings::compression`].
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.compression = Some(compression);
+        self
+    }
+
+    /// Whether vectors are normalized, see [`Settings::normalize`].
+    pub fn normalize(mut self, normalize: bool) -> Self {
+        self.normalize = Some(normalize);
+        self
+    }
+
+    /// Only allow documents to be added with these tags, so a typo in a tag returns [`Error::TagNotAllowed`]
+    /// instead of quietly creating a new tag set. Recorded in the database (see [`Victor::allowed_tags`]),
+    /// so like the settings, they default to what the database already has and can't change once embeddings
+    /// have been added.
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// use victor_db::Error;
+    ///
+    /// let mut victor = Db::builder(DirectoryHandle::default())
+    ///     .allowed_tags(["Pizza Flavors", "Pizza Toppings"])
+    ///     .build()
+    ///     .await
+    ///     .unwrap();
+    ///
+    /// victor.add_single_embedding("Pineapple", vec![0.1, 0.2, 0.3], vec!["Pizza Toppings"]).await.unwrap();
+    /// let result = victor.add_single_embedding("Pineapple", vec![0.1, 0.2, 0.3], vec!["Pizza Topings"]).await;
+    /// assert!(matches!(result, Err(Error::TagNotAllowed { .. })));
+    /// # })
+    /// ```
+    pub fn allowed_tags(mut self, tags: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.allowed_tags = Some(tags.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// See [`Victor::with_max_segment_size`].
+    pub fn max_segment_size(mut self, max_segment_size: usize) -> Self {
+        self.victor = self.victor.with_max_segment_size(max_segment_size);
+        self
+    }
+
+    /// See [`Victor::with_embedder`].
+    pub fn embedder(mut self, embedder: impl Embedder + 'static) -> Self {
+        self.victor = self.victor.with_embedder(embedder);
+        self
+    }
+
+    /// See [`Victor::with_reranker`].
+    pub fn reranker(mut self, reranker: impl Reranker + 'static) -> Self {
+        self.victor = self.victor.with_reranker(reranker);
+        self
+    }
+
+    /// See [`Victor::with_on_insert`].
+    pub fn on_insert(
+        mut self,
+        hook: impl Fn(&mut Document, &[String]) -> Result<(), Error> + Send + Sync + 'static,
+    ) -> Self {
+        self.victor = self.victor.with_on_insert(hook);
+        self
+    }
+
+    /// See [`Victor::with_on_delete`].
+    pub fn on_delete(
+        mut self,
+        hook: impl Fn(Uuid) -> Result<(), Error> + Send + Sync + 'static,
+    ) -> Self {
+        self.victor = self.victor.with_on_delete(hook);
+        self
+    }
+
+    /// See [`Victor::with_before_search`].
+    pub fn before_search(
+        mut self,
+        hook: impl Fn(&SearchRequest) -> Result<(), Error> + Send + Sync + 'static,
+    ) -> Self {
+        self.victor = self.victor.with_before_search(hook);
+        self
+    }
+
+    /// See [`Victor::with_after_search`].
+    pub fn after_search(
+        mut self,
+        hook: impl Fn(&SearchRequest, &mut Vec<NearestNeighborsResult>) + Send + Sync + 'static,
+    ) -> Self {
+        self.victor = self.victor.with_after_search(hook);
+        self
+    }
+
+    /// See [`Victor::with_deduplication`].
+    pub fn deduplication(mut self, deduplication: Deduplication) -> Self {
+        self.victor = self.victor.with_deduplication(deduplication);
+        self
+    }
+
+    /// See [`Victor::with_auto_projection`].
+    pub fn auto_projection(mut self, auto_projection: Option<AutoProjection>) -> Self {
+        self.victor = self.victor.with_auto_projection(auto_projection);
+        self
+    }
+
+    /// See [`Victor::with_retained_originals`].
+    pub fn retained_originals(mut self, retain_originals: bool) -> Self {
+        self.victor = self.victor.with_retained_originals(retain_originals);
+        self
+    }
+
+    /// See [`Victor::with_cache_budget`].
+    pub fn cache_budget(mut self, bytes: usize) -> Self {
+        self.victor = self.victor.with_cache_budget(bytes);
+        self
+    }
+
+    /// See [`Victor::with_write_buffer`].
+    pub fn write_buffer(mut self, documents: usize) -> Self {
+        self.victor = self.victor.with_write_buffer(documents);
+        self
+    }
+
+    /// Open the database, recording its settings if it's new.
+    pub async fn build(self) -> Result<Victor<D>, Error> {
+        let mut victor = self.victor;
+        victor.migrate().await?;
+
+        let existing = match victor.has_settings().await? {
+            true => victor.settings().await?,
+            false => Settings::default(),
+        };
+        let settings = Settings {
+            metric: self.metric.unwrap_or(existing.metric),
+            dimension: self.dimension.or(existing.dimension),
+            storage: self.storage.unwrap_or(existing.storage),
+            compression: self.compression.unwrap_or(existing.compression),
+            normalize: self.normalize.unwrap_or(existing.normalize),
+        };
+        let mut victor = victor.open(settings).await?;
+        if let Some(allowed_tags) = self.allowed_tags {
+            victor.set_allowed_tags(allowed_tags).await?;
+        }
+        Ok(victor)
+    }
+}
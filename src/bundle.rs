@@ -0,0 +1,87 @@
+//! Bundles: a whole database packed into a single file, so a database built on a server can be shipped to the web
+//! (see [`crate::Victor::export_bundle`] and [`crate::Victor::load_bundle`]).
+//!
+//! A bundle starts with [`MAGIC`] and the format version of the database it holds (see [`crate::format`]),
+//! followed by every file in the database by name. The files keep their checksums, so they're verified as usual
+//! once they're loaded.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{db::deserialize, error::Error, format};
+
+/// The name used for the bundle in errors, since it isn't a file in the database.
+const FILENAME: &str = "bundle";
+
+/// Identifies a victor bundle.
+const MAGIC: [u8; 4] = *b"VCTB";
+
+#[derive(Serialize, Deserialize)]
+struct Header {
+    magic: [u8; 4],
+    version: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+pub(crate) struct Bundle {
+    /// The contents of each file in the database, by name.
+    pub files: BTreeMap<String, Vec<u8>>,
+}
+
+impl Bundle {
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let header = Header {
+            magic: MAGIC,
+            version: format::VERSION,
+        };
+        bincode::serialize(&(header, self)).expect("Failed to serialize bundle")
+    }
+
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let header: Header = deserialize(FILENAME, bytes)?;
+        if header.magic != MAGIC {
+            return Err(Error::Corrupted {
+                file: FILENAME.to_string(),
+                reason: "not a victor bundle".to_string(),
+            });
+        }
+        if header.version > format::VERSION {
+            return Err(Error::UnsupportedVersion {
+                found: header.version,
+                supported: format::VERSION,
+            });
+        }
+
+        let (_, bundle): (Header, Bundle) = deserialize(FILENAME, bytes)?;
+        Ok(bundle)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let bundle = Bundle {
+            files: BTreeMap::from([
+                ("index.bin".to_string(), vec![1, 2, 3]),
+                ("content.bin".to_string(), vec![4, 5]),
+            ]),
+        };
+        assert_eq!(Bundle::from_bytes(&bundle.to_bytes()).unwrap(), bundle);
+
+        assert!(matches!(
+            Bundle::from_bytes(b"not a bundle"),
+            Err(Error::Corrupted { .. })
+        ));
+
+        let mut newer = bundle.to_bytes();
+        newer[4..8].copy_from_slice(&(format::VERSION + 1).to_le_bytes());
+        assert!(matches!(
+            Bundle::from_bytes(&newer),
+            Err(Error::UnsupportedVersion { .. })
+        ));
+    }
+}
@@ -0,0 +1,179 @@
+//! A cache of the db files searches read, so hot files aren't read (and checked) again for every search,
+//! see [`crate::Victor::with_cache_budget`].
+//!
+//! Files are cached as they're read: db files' embeddings read, decompressed, checked against their checksums
+//! and decoded (unless they're quantized, since those are scored as they're encoded), and `content.bin` and
+//! `eigen.bin` decoded. Each counts for roughly what it takes up in memory, not what it takes up on disk.
+//!
+//! Each file is cached with its size and recorded checksum, and is only used while both are the same, so a file
+//! that's changed since (by this `Victor` or any other) is read again. Once the cache holds more than its
+//! budget, the least recently used files are evicted.
+
+use std::{collections::HashMap, sync::Arc};
+
+use uuid::Uuid;
+
+use crate::db::{Content, Embedding, VectorProjection};
+
+/// Which version of a file was cached: its size, and its recorded checksum, if it has one.
+pub(crate) type Version = (usize, Option<u32>);
+
+/// What's cached of a file.
+#[derive(Clone)]
+pub(crate) enum Cached {
+    /// A db file's decoded embeddings.
+    Embeddings {
+        embeddings: Arc<Vec<Embedding>>,
+        record_size: usize,
+    },
+    /// A quantized db file's encoded embeddings, without its header.
+    Records {
+        records: Arc<Vec<u8>>,
+        record_size: usize,
+    },
+    /// `content.bin`: the latest content of each document, and the number of entries in the log.
+    Contents(Arc<(HashMap<Uuid, Content>, usize)>),
+    /// `eigen.bin`.
+    Projection(Arc<VectorProjection>),
+}
+
+struct Entry {
+    version: Version,
+    cached: Cached,
+    /// How many bytes the file takes up in the cache.
+    size: usize,
+    /// When the file was last used, as the number of lookups before it.
+    last_used: u64,
+}
+
+#[derive(Default)]
+pub(crate) struct Cache {
+    /// How many bytes the cache can hold. With no budget, nothing is cached.
+    budget: usize,
+    /// How many bytes it holds.
+    size: usize,
+    /// How many lookups there have been, to tell which file was used least recently.
+    clock: u64,
+    entries: HashMap<String, Entry>,
+}
+
+impl Cache {
+    pub(crate) fn new(budget: usize) -> Self {
+        Self {
+            budget,
+            ..Default::default()
+        }
+    }
+
+    /// Whether a file taking up `size` bytes would be cached.
+    pub(crate) fn fits(&self, size: usize) -> bool {
+        size > 0 && size <= self.budget
+    }
+
+    /// What's cached of `filename`, if it's of the same `version` of it.
+    pub(crate) fn get(&mut self, filename: &str, version: Version) -> Option<Cached> {
+        self.clock += 1;
+        let entry = self.entries.get_mut(filename)?;
+        if entry.version != version {
+            let size = entry.size;
+            self.entries.remove(filename);
+            self.size -= size;
+            return None;
+        }
+        entry.last_used = self.clock;
+        Some(entry.cached.clone())
+    }
+
+    /// Cache `version` of `filename`, taking up `size` bytes, evicting the least recently used files to make room.
+    pub(crate) fn insert(
+        &mut self,
+        filename: String,
+        version: Version,
+        size: usize,
+        cached: Cached,
+    ) {
+        if !self.fits(size) {
+            return;
+        }
+        if let Some(replaced) = self.entries.remove(&filename) {
+            self.size -= replaced.size;
+        }
+        while self.size + size > self.budget {
+            let Some(evicted) = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(filename, _)| filename.clone())
+            else {
+                break;
+            };
+            let evicted = self
+                .entries
+                .remove(&evicted)
+                .expect("the file was just found");
+            self.size -= evicted.size;
+        }
+
+        self.size += size;
+        self.entries.insert(
+            filename,
+            Entry {
+                version,
+                cached,
+                size,
+                last_used: self.clock,
+            },
+        );
+    }
+
+    /// How many bytes the cache holds.
+    pub(crate) fn size(&self) -> usize {
+        self.size
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+        self.size = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cached(size: usize) -> Cached {
+        Cached::Records {
+            records: Arc::new(vec![0; size]),
+            record_size: 1,
+        }
+    }
+
+    #[test]
+    fn evicts_the_least_recently_used() {
+        let mut cache = Cache::new(10);
+        cache.insert("a".to_string(), (4, None), 4, cached(4));
+        cache.insert("b".to_string(), (4, None), 4, cached(4));
+        assert!(cache.get("a", (4, None)).is_some());
+
+        // "b" was used less recently than "a"
+        cache.insert("c".to_string(), (4, None), 4, cached(4));
+        assert!(cache.get("b", (4, None)).is_none());
+        assert!(cache.get("a", (4, None)).is_some());
+        assert!(cache.get("c", (4, None)).is_some());
+        assert_eq!(cache.size(), 8);
+
+        // files bigger than the budget aren't cached
+        cache.insert("d".to_string(), (11, None), 11, cached(11));
+        assert!(cache.get("d", (11, None)).is_none());
+        assert_eq!(cache.size(), 8);
+    }
+
+    #[test]
+    fn forgets_changed_files() {
+        let mut cache = Cache::new(10);
+        cache.insert("a".to_string(), (4, Some(1)), 4, cached(4));
+        assert!(cache.get("a", (4, Some(2))).is_none());
+        assert_eq!(cache.size(), 0);
+        assert!(cache.get("a", (4, Some(1))).is_none());
+    }
+}
@@ -0,0 +1,191 @@
+//! An in-memory catalog of where every embedding is stored, so finding an embedding by id
+//! doesn't mean reading every db file.
+//!
+//! The catalog is built from the db files the first time it's needed (or when the database is opened with
+//! [`crate::Victor::with_settings`]), then kept up to date as embeddings are written.
+//! It records the size and checksum of each db file as it last saw it, so files another `Victor` has written to
+//! since (say, in another tab) are noticed: a miss isn't trusted while any file has changed, and writing re-reads
+//! just the changed files.
+//! Until it's built, embeddings are found by id using the bloom filters of the db files (see [`crate::bloom`]).
+
+use std::collections::{HashMap, HashSet};
+
+use uuid::Uuid;
+
+use crate::cache::Version;
+
+#[derive(Debug, Default, Clone)]
+pub(crate) struct Catalog {
+    segments: HashMap<String, SegmentInfo>,
+    locations: HashMap<Uuid, Location>,
+    /// The size and recorded checksum of each db file, when the catalog last matched it.
+    versions: HashMap<String, Version>,
+}
+
+/// The layout of a db file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct SegmentInfo {
+    /// The size of each (encoded) embedding.
+    pub record_size: usize,
+    /// The number of embeddings.
+    pub len: usize,
+}
+
+/// Where an embedding is stored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Location {
+    /// The db file holding the embedding.
+    pub segment: String,
+    /// The embedding's position in the db file.
+    pub position: usize,
+}
+
+impl Location {
+    /// The offset of the embedding in its (uncompressed) db file, after the header.
+    pub(crate) fn offset(&self, record_size: usize) -> usize {
+        std::mem::size_of::<u32>() + self.position * record_size
+    }
+}
+
+impl Catalog {
+    pub(crate) fn get(&self, id: &Uuid) -> Option<&Location> {
+        self.locations.get(id)
+    }
+
+    pub(crate) fn contains(&self, id: &Uuid) -> bool {
+        self.locations.contains_key(id)
+    }
+
+    pub(crate) fn segment(&self, segment: &str) -> Option<SegmentInfo> {
+        self.segments.get(segment).copied()
+    }
+
+    /// Record that embeddings with `ids` were appended to `segment`.
+    pub(crate) fn append(
+        &mut self,
+        segment: &str,
+        record_size: usize,
+        ids: impl IntoIterator<Item = Uuid>,
+    ) {
+        let info = self
+            .segments
+            .entry(segment.to_string())
+            .or_insert(SegmentInfo {
+                record_size,
+                len: 0,
+            });
+        for id in ids {
+            self.locations.insert(
+                id,
+                Location {
+                    segment: segment.to_string(),
+                    position: info.len,
+                },
+            );
+            info.len += 1;
+        }
+    }
+
+    /// Which of `segments`, each db file with its current version, the catalog doesn't match anymore,
+    /// and which of the db files it's seen are gone.
+    pub(crate) fn changes(&self, segments: &[(String, Version)]) -> (Vec<String>, Vec<String>) {
+        let changed = segments
+            .iter()
+            .filter(|(segment, version)| self.versions.get(segment) != Some(version))
+            .map(|(segment, _)| segment.clone())
+            .collect();
+        let current = segments
+            .iter()
+            .map(|(segment, _)| segment.as_str())
+            .collect::<HashSet<_>>();
+        let removed = self
+            .versions
+            .keys()
+            .filter(|segment| !current.contains(segment.as_str()))
+            .cloned()
+            .collect();
+        (changed, removed)
+    }
+
+    /// Record that the catalog matches `version` of `segment`.
+    pub(crate) fn saw(&mut self, segment: &str, version: Version) {
+        self.versions.insert(segment.to_string(), version);
+    }
+
+    /// Forget `segment`, which was removed.
+    pub(crate) fn remove(&mut self, segment: &str) {
+        self.segments.remove(segment);
+        self.versions.remove(segment);
+        self.locations
+            .retain(|_, location| location.segment != segment);
+    }
+
+    /// Record that `segment` was rewritten to hold exactly the embeddings with `ids`, in order.
+    pub(crate) fn replace(
+        &mut self,
+        segment: &str,
+        record_size: usize,
+        ids: impl IntoIterator<Item = Uuid>,
+    ) {
+        self.segments.remove(segment);
+        self.locations
+            .retain(|_, location| location.segment != segment);
+        self.append(segment, record_size, ids);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tracks_locations() {
+        let ids = (0..3).map(|_| Uuid::new_v4()).collect::<Vec<_>>();
+        let mut catalog = Catalog::default();
+
+        catalog.append("a.bin", 10, ids[..2].iter().copied());
+        catalog.append("a.bin", 10, ids[2..].iter().copied());
+        assert_eq!(
+            catalog.segment("a.bin"),
+            Some(SegmentInfo {
+                record_size: 10,
+                len: 3
+            })
+        );
+        let location = catalog.get(&ids[2]).unwrap();
+        assert_eq!(location.position, 2);
+        assert_eq!(location.offset(10), 24);
+
+        catalog.replace("a.bin", 10, [ids[2]]);
+        assert!(!catalog.contains(&ids[0]));
+        assert_eq!(catalog.get(&ids[2]).unwrap().position, 0);
+        assert_eq!(catalog.segment("a.bin").unwrap().len, 1);
+    }
+
+    #[test]
+    fn notices_changed_files() {
+        let mut catalog = Catalog::default();
+        catalog.append("a.bin", 10, [Uuid::new_v4()]);
+        catalog.saw("a.bin", (14, Some(1)));
+        catalog.saw("b.bin", (0, None));
+
+        let segments = [("a.bin".to_string(), (14, Some(1)))];
+        assert_eq!(
+            catalog.changes(&segments),
+            (vec![], vec!["b.bin".to_string()])
+        );
+
+        let segments = [
+            ("a.bin".to_string(), (24, Some(2))),
+            ("b.bin".to_string(), (0, None)),
+            ("c.bin".to_string(), (14, Some(3))),
+        ];
+        let (mut changed, removed) = catalog.changes(&segments);
+        changed.sort();
+        assert_eq!(changed, vec!["a.bin".to_string(), "c.bin".to_string()]);
+        assert!(removed.is_empty());
+
+        catalog.remove("a.bin");
+        assert!(catalog.segment("a.bin").is_none());
+    }
+}
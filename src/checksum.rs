@@ -0,0 +1,144 @@
+//! CRC32 checksums of the files that make up a database, recorded in `checksums.bin`,
+//! so a corrupted (say, truncated) file is reported as [`Error::Corrupted`] instead of failing to deserialize,
+//! or worse, deserializing into garbage.
+//!
+//! Db files, `index.bin`, `combined.bin`, `content.bin`, `eigen.bin` and `originals.bin` are checksummed.
+//! Files written before checksums were recorded aren't verified until they're next rewritten.
+
+use std::collections::HashMap;
+
+use crate::{
+    db::{read_bin, write_bin},
+    error::Error,
+    filesystem::DirectoryHandle,
+};
+
+const FILENAME: &str = "checksums.bin";
+
+pub(crate) fn checksum(bytes: &[u8]) -> u32 {
+    crc32fast::hash(bytes)
+}
+
+/// Record that `filename` now holds `bytes`.
+pub(crate) async fn record<D: DirectoryHandle>(
+    root: &D,
+    filename: &str,
+    bytes: &[u8],
+) -> Result<(), Error> {
+    let mut checksums = read_all(root).await?;
+    checksums.insert(filename.to_string(), checksum(bytes));
+    write_all(root, &checksums).await
+}
+
+/// Record that `appended` was written to the end of `filename`, which was `previous_size` bytes long.
+pub(crate) async fn append<D: DirectoryHandle>(
+    root: &D,
+    filename: &str,
+    previous_size: usize,
+    appended: &[u8],
+) -> Result<(), Error> {
+    let mut checksums = read_all(root).await?;
+
+    let initial = match checksums.get(filename) {
+        _ if previous_size == 0 => 0,
+        Some(&initial) => initial,
+        // the beginning of the file was never checksummed, so the rest can't be either
+        None => return Ok(()),
+    };
+    let mut hasher = crc32fast::Hasher::new_with_initial(initial);
+    hasher.update(appended);
+    checksums.insert(filename.to_string(), hasher.finalize());
+
+    write_all(root, &checksums).await
+}
+
+/// Record that `from` was moved to `to`, so `to` is checked against what `from` held (if that was recorded).
+pub(crate) async fn rename<D: DirectoryHandle>(
+    root: &D,
+    from: &str,
+    to: &str,
+) -> Result<(), Error> {
+    let mut checksums = read_all(root).await?;
+    match checksums.remove(from) {
+        Some(checksum) => checksums.insert(to.to_string(), checksum),
+        None => checksums.remove(to),
+    };
+    write_all(root, &checksums).await
+}
+
+/// Forget the checksum of `filename`, which was removed, so a new file with the same name isn't checked against it.
+pub(crate) async fn remove<D: DirectoryHandle>(root: &D, filename: &str) -> Result<(), Error> {
+    let mut checksums = read_all(root).await?;
+    if checksums.remove(filename).is_some() {
+        write_all(root, &checksums).await?;
+    }
+    Ok(())
+}
+
+/// Check `bytes`, all of `filename`, against its recorded checksum.
+pub(crate) async fn verify<D: DirectoryHandle>(
+    root: &D,
+    filename: &str,
+    bytes: &[u8],
+) -> Result<(), Error> {
+    verify_checksum(root, filename, checksum(bytes)).await
+}
+
+/// Check the checksum of all of `filename`, computed by the caller, against its recorded checksum.
+pub(crate) async fn verify_checksum<D: DirectoryHandle>(
+    root: &D,
+    filename: &str,
+    actual: u32,
+) -> Result<(), Error> {
+    match read_all(root).await?.get(filename) {
+        Some(&expected) if expected != actual => Err(Error::Corrupted {
+            file: filename.to_string(),
+            reason: format!("checksum mismatch: expected {expected:08x}, found {actual:08x}"),
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// The recorded checksum of `filename`, if it has one.
+pub(crate) async fn recorded<D: DirectoryHandle>(
+    root: &D,
+    filename: &str,
+) -> Result<Option<u32>, Error> {
+    Ok(read_all(root).await?.get(filename).copied())
+}
+
+/// The recorded checksum of every checksummed file.
+pub(crate) async fn read_all<D: DirectoryHandle>(root: &D) -> Result<HashMap<String, u32>, Error> {
+    read_bin(root, FILENAME).await
+}
+
+async fn write_all<D: DirectoryHandle>(
+    root: &D,
+    checksums: &HashMap<String, u32>,
+) -> Result<(), Error> {
+    write_bin(root, FILENAME, checksums).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::DirectoryHandle;
+
+    #[tokio::test]
+    async fn detects_changes() {
+        let root = DirectoryHandle::default();
+        record(&root, "a.bin", b"hello").await.unwrap();
+        append(&root, "a.bin", 5, b" world").await.unwrap();
+
+        verify(&root, "a.bin", b"hello world").await.unwrap();
+        assert!(matches!(
+            verify(&root, "a.bin", b"hello").await,
+            Err(Error::Corrupted { .. })
+        ));
+
+        // unknown files can't be verified
+        verify(&root, "b.bin", b"anything").await.unwrap();
+        append(&root, "b.bin", 5, b"more").await.unwrap();
+        verify(&root, "b.bin", b"anything").await.unwrap();
+    }
+}
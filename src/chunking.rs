@@ -0,0 +1,235 @@
+//! Splitting long text into chunks that are small enough to embed well, see [`Chunker`]
+//! and [`crate::Victor::add_document_chunked`].
+//!
+//! Sizes are counted in characters, not tokens, so leave some room below your embedding model's limit.
+
+/// How to split text into chunks.
+///
+/// ```rust
+/// use victor_db::chunking::Chunker;
+///
+/// let chunker = Chunker::Sentences {
+///     max_size: 30,
+///     overlap: 0,
+/// };
+/// let chunks = chunker.split("Pizza is great. Pineapple is a topping. Some people hate it.");
+/// assert_eq!(chunks, vec!["Pizza is great.", "Pineapple is a topping.", "Some people hate it."]);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Chunker {
+    /// Chunks of exactly `size` characters (except the last), each starting `size - overlap` characters after
+    /// the one before it. Words and sentences are cut wherever the chunks end.
+    Fixed {
+        /// How many characters each chunk has.
+        size: usize,
+        /// How many characters each chunk repeats from the end of the one before it.
+        overlap: usize,
+    },
+    /// As many whole sentences as fit in `max_size` characters. Sentences longer than that are split like
+    /// [`Chunker::Fixed`].
+    Sentences {
+        /// The most characters a chunk can have.
+        max_size: usize,
+        /// Each chunk starts with as many of the last sentences of the one before it as fit in this many characters.
+        overlap: usize,
+    },
+    /// Split at paragraphs, then lines, then sentences, then words, going further only for pieces that are still
+    /// longer than `max_size` characters, then put the pieces back together into chunks of up to `max_size`.
+    /// This keeps related text together as much as possible, so it's usually the best choice.
+    Recursive {
+        /// The most characters a chunk can have.
+        max_size: usize,
+        /// Each chunk starts with as many of the last pieces of the one before it as fit in this many characters.
+        overlap: usize,
+    },
+}
+
+impl Default for Chunker {
+    fn default() -> Self {
+        Chunker::Recursive {
+            max_size: 1000,
+            overlap: 200,
+        }
+    }
+}
+
+impl Chunker {
+    /// Split `text` into chunks, in order. Chunks that would only be whitespace are left out.
+    pub fn split(&self, text: &str) -> Vec<String> {
+        match *self {
+            Chunker::Fixed { size, overlap } => fixed(text, size, overlap),
+            Chunker::Sentences { max_size, overlap } => {
+                let pieces = sentences(text)
+                    .into_iter()
+                    .flat_map(|sentence| match len(sentence) > max_size {
+                        true => fixed(sentence, max_size, 0),
+                        false => vec![sentence.to_string()],
+                    })
+                    .collect();
+                merge(pieces, max_size, overlap)
+            }
+            Chunker::Recursive { max_size, overlap } => {
+                merge(recursive(text, max_size, 0), max_size, overlap)
+            }
+        }
+    }
+}
+
+fn len(text: &str) -> usize {
+    text.chars().count()
+}
+
+/// Chunks of `size` characters, overlapping by `overlap`.
+fn fixed(text: &str, size: usize, overlap: usize) -> Vec<String> {
+    let size = size.max(1);
+    let step = size - overlap.min(size - 1);
+    let chars = text.chars().collect::<Vec<_>>();
+
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    while start < chars.len() {
+        let end = (start + size).min(chars.len());
+        let chunk = chars[start..end].iter().collect::<String>();
+        if !chunk.trim().is_empty() {
+            chunks.push(chunk);
+        }
+        if end == chars.len() {
+            break;
+        }
+        start += step;
+    }
+    chunks
+}
+
+/// Split after each sentence's closing punctuation and the whitespace after it.
+fn sentences(text: &str) -> Vec<&str> {
+    let mut sentences = Vec::new();
+    let mut start = 0;
+    let mut ended = false;
+    for (i, c) in text.char_indices() {
+        if ended && !c.is_whitespace() {
+            sentences.push(&text[start..i]);
+            start = i;
+        }
+        ended = match c {
+            '.' | '!' | '?' => true,
+            c if c.is_whitespace() => ended,
+            _ => false,
+        };
+    }
+    if start < text.len() {
+        sentences.push(&text[start..]);
+    }
+    sentences
+}
+
+/// Split `text` at the coarsest separator (from `level` on) that makes its pieces fit in `max_size`,
+/// keeping the separators so the pieces can be put back together.
+fn recursive(text: &str, max_size: usize, level: usize) -> Vec<String> {
+    if len(text) <= max_size {
+        return vec![text.to_string()];
+    }
+
+    let pieces = match level {
+        0 => text.split_inclusive("\n\n").collect::<Vec<_>>(),
+        1 => text.split_inclusive('\n').collect(),
+        2 => sentences(text),
+        3 => text.split_inclusive(' ').collect(),
+        _ => return fixed(text, max_size, 0),
+    };
+    pieces
+        .into_iter()
+        .flat_map(|piece| recursive(piece, max_size, level + 1))
+        .collect()
+}
+
+/// Put consecutive pieces back together into chunks of up to `max_size` characters, starting each chunk with the
+/// last pieces of the one before it that fit in `overlap` characters.
+fn merge(pieces: Vec<String>, max_size: usize, overlap: usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut current = Vec::<String>::new();
+    let mut current_len = 0;
+    for piece in pieces {
+        let piece_len = len(&piece);
+        if current_len + piece_len > max_size && !current.is_empty() {
+            chunks.push(current.concat());
+            while !current.is_empty()
+                && (current_len > overlap || current_len + piece_len > max_size)
+            {
+                current_len -= len(&current.remove(0));
+            }
+        }
+        current_len += piece_len;
+        current.push(piece);
+    }
+    if !current.is_empty() {
+        chunks.push(current.concat());
+    }
+
+    chunks
+        .into_iter()
+        .map(|chunk| chunk.trim().to_string())
+        .filter(|chunk| !chunk.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_text() {
+        assert_eq!(
+            Chunker::Fixed {
+                size: 4,
+                overlap: 2
+            }
+            .split("abcdefgh"),
+            vec!["abcd", "cdef", "efgh"]
+        );
+        assert_eq!(
+            Chunker::Fixed {
+                size: 3,
+                overlap: 0
+            }
+            .split("héllo"),
+            vec!["hél", "lo"]
+        );
+
+        let text = "One. Two! Three? Four.";
+        assert_eq!(sentences(text), vec!["One. ", "Two! ", "Three? ", "Four."]);
+        assert_eq!(
+            Chunker::Sentences {
+                max_size: 12,
+                overlap: 5
+            }
+            .split(text),
+            vec!["One. Two!", "Two! Three?", "Four."]
+        );
+
+        let text =
+            "A paragraph about pizza.\n\nAnother one, about pineapple. It's longer than the first.";
+        assert_eq!(
+            Chunker::Recursive {
+                max_size: 40,
+                overlap: 0
+            }
+            .split(text),
+            vec![
+                "A paragraph about pizza.",
+                "Another one, about pineapple.",
+                "It's longer than the first."
+            ]
+        );
+        // every chunk fits, even when there's no separator to split at
+        let chunks = Chunker::Recursive {
+            max_size: 10,
+            overlap: 3,
+        }
+        .split(&"x".repeat(25));
+        assert!(chunks.iter().all(|chunk| len(chunk) <= 10));
+        assert_eq!(chunks.concat().len(), 25);
+
+        assert!(Chunker::default().split("  \n\n ").is_empty());
+    }
+}
@@ -0,0 +1,28 @@
+//! The document each extra vector (see [`crate::Document::with_chunk`]) belongs to, recorded in `chunks.bin`.
+//!
+//! Extra vectors are stored as embeddings of their own, with ids of their own, so this is how searches find the
+//! document they belong to.
+
+use std::collections::HashMap;
+
+use uuid::Uuid;
+
+use crate::{
+    db::{read_bin, write_bin},
+    error::Error,
+    filesystem::DirectoryHandle,
+};
+
+const FILENAME: &str = "chunks.bin";
+
+/// The document each extra vector belongs to, by the id it's stored with.
+pub(crate) async fn read<D: DirectoryHandle>(root: &D) -> Result<HashMap<Uuid, Uuid>, Error> {
+    read_bin(root, FILENAME).await
+}
+
+pub(crate) async fn write<D: DirectoryHandle>(
+    root: &D,
+    chunks: &HashMap<Uuid, Uuid>,
+) -> Result<(), Error> {
+    write_bin(root, FILENAME, chunks).await
+}
@@ -0,0 +1,229 @@
+//! Grouping a database's documents by topic, see [`crate::Victor::clusters`],
+//! and finding the ones that don't fit in, see [`crate::Victor::outliers`].
+
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    db::NearestNeighborsResult,
+    similarity::{self, Metric},
+};
+
+/// Parameters for [`crate::Victor::clusters`].
+///
+/// ```rust
+/// use victor_db::ClusterConfig;
+///
+/// // a handful of broad topics, each shown with a single document
+/// let config = ClusterConfig {
+///     clusters: 4,
+///     representatives: 1,
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClusterConfig {
+    /// How many clusters to group documents into, at most. There are fewer if there are fewer documents.
+    pub clusters: usize,
+    /// How many of the documents closest to each cluster's centroid to return.
+    pub representatives: usize,
+    /// How many keywords to return for each cluster.
+    pub keywords: usize,
+    /// How many rounds of k-means to run. More rounds give tighter clusters, more slowly.
+    pub iterations: usize,
+}
+
+impl Default for ClusterConfig {
+    fn default() -> Self {
+        Self {
+            clusters: 8,
+            representatives: 3,
+            keywords: 5,
+            iterations: 20,
+        }
+    }
+}
+
+/// A group of similar documents, from [`crate::Victor::clusters`].
+#[derive(Debug, Clone)]
+pub struct Cluster {
+    /// How many documents are in the cluster.
+    pub size: usize,
+    /// The mean of the vectors of the documents in the cluster, as they're stored.
+    /// With cosine similarity, the vectors are normalized before they're averaged.
+    pub centroid: Vec<f32>,
+    /// The documents closest to the centroid, closest first,
+    /// with their similarity to the centroid and their tags.
+    pub representatives: Vec<NearestNeighborsResult>,
+    /// The terms in the content of the cluster's documents that set them apart from the rest of the database,
+    /// most characteristic first.
+    pub keywords: Vec<String>,
+}
+
+/// `vectors` as they're clustered for `metric`: normalized for cosine similarity, where only their direction
+/// matters.
+pub(crate) fn clustering_vectors<'a>(
+    metric: Metric,
+    vectors: impl IntoIterator<Item = &'a Vec<f32>>,
+) -> Vec<Vec<f32>> {
+    vectors
+        .into_iter()
+        .map(|vector| {
+            let mut vector = vector.clone();
+            if metric == Metric::Cosine {
+                similarity::normalize(&mut vector);
+            }
+            vector
+        })
+        .collect()
+}
+
+/// How similar each of `vectors` is to the mean of the other vectors in its cluster, or if it's alone in its
+/// cluster, to the closest mean of another cluster. `clusters` is the cluster each vector is in, out of `k`.
+/// `None` if there's no other vector to compare with.
+///
+/// The vector itself is left out of its cluster's mean, so a vector can't look typical just because it pulled
+/// the mean towards itself.
+pub(crate) fn centroid_similarities(
+    vectors: &[Vec<f32>],
+    clusters: &[usize],
+    k: usize,
+    metric: Metric,
+) -> Vec<Option<f32>> {
+    let dimension = vectors.first().map_or(0, Vec::len);
+    let mut sums = vec![vec![0.0; dimension]; k];
+    let mut counts = vec![0usize; k];
+    for (vector, &cluster) in vectors.iter().zip(clusters) {
+        counts[cluster] += 1;
+        for (sum, value) in sums[cluster].iter_mut().zip(vector) {
+            *sum += value;
+        }
+    }
+
+    vectors
+        .iter()
+        .zip(clusters)
+        .map(|(vector, &own)| {
+            (0..k)
+                .filter_map(|cluster| {
+                    let (sum, count) = (&sums[cluster], counts[cluster]);
+                    let mean = match cluster == own {
+                        true if count > 1 => sum
+                            .iter()
+                            .zip(vector)
+                            .map(|(sum, value)| (sum - value) / (count - 1) as f32)
+                            .collect::<Vec<_>>(),
+                        _ if cluster == own || count == 0 => return None,
+                        _ => sum.iter().map(|sum| sum / count as f32).collect(),
+                    };
+                    metric.similarity(vector, &mean).ok()
+                })
+                .max_by(f32::total_cmp)
+        })
+        .collect()
+}
+
+/// The `n` terms most characteristic of a group of documents, given the terms of each document in the group
+/// and how many of all `documents` have each term.
+///
+/// Terms score by how much more common they are in the group than in the database as a whole, weighted by how
+/// common they are in the group, so a term most of the group has beats one only a single document has.
+/// Terms every document has score nothing.
+pub(crate) fn characteristic_terms(
+    group: &[&HashSet<String>],
+    document_frequency: &HashMap<String, usize>,
+    documents: usize,
+    n: usize,
+) -> Vec<String> {
+    let mut group_frequency = HashMap::<&String, usize>::new();
+    for terms in group {
+        for term in *terms {
+            *group_frequency.entry(term).or_default() += 1;
+        }
+    }
+
+    let mut scored = group_frequency
+        .into_iter()
+        .map(|(term, count)| {
+            let in_group = count as f32 / group.len() as f32;
+            let overall = document_frequency[term] as f32 / documents as f32;
+            (in_group * (in_group / overall).ln(), term)
+        })
+        .filter(|(score, _)| *score > 0.0)
+        .collect::<Vec<_>>();
+    scored.sort_by(|(a, a_term), (b, b_term)| b.total_cmp(a).then(a_term.cmp(b_term)));
+
+    scored
+        .into_iter()
+        .take(n)
+        .map(|(_, term)| term.clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn characteristic_terms_are_common_in_the_group_and_rare_outside() {
+        let documents = [
+            "the pepperoni pizza",
+            "the mushroom pizza",
+            "the pizza oven",
+            "the chocolate cake",
+            "the carrot cake",
+        ]
+        .map(|content| content.split(' ').map(String::from).collect::<HashSet<_>>());
+        let mut document_frequency = HashMap::new();
+        for terms in &documents {
+            for term in terms {
+                *document_frequency.entry(term.clone()).or_default() += 1;
+            }
+        }
+
+        let pizzas = documents[..3].iter().collect::<Vec<_>>();
+        let terms = characteristic_terms(&pizzas, &document_frequency, documents.len(), 2);
+        // "the" is in every document, so it says nothing about the group
+        assert_eq!(terms, ["pizza", "mushroom"]);
+
+        let cakes = documents[3..].iter().collect::<Vec<_>>();
+        let terms = characteristic_terms(&cakes, &document_frequency, documents.len(), 10);
+        assert_eq!(terms[0], "cake");
+        assert!(!terms.contains(&"the".to_string()));
+    }
+
+    #[test]
+    fn outliers_are_far_from_the_rest_of_their_cluster() {
+        let vectors = vec![
+            vec![0.0, 0.0],
+            vec![0.1, 0.0],
+            vec![0.0, 0.1],
+            vec![5.0, 5.0],
+            vec![10.0, 10.0],
+            vec![10.1, 10.0],
+        ];
+        let clusters = [0, 0, 0, 0, 1, 1];
+        let similarities = centroid_similarities(&vectors, &clusters, 2, Metric::Euclidean);
+
+        // the far away vector is compared with the mean of the other three in its cluster
+        let expected = -(2.0f32).sqrt() * (5.0 - 0.1 / 3.0);
+        assert!((similarities[3].unwrap() - expected).abs() < 1e-4);
+        let least_similar = (0..vectors.len())
+            .min_by(|&a, &b| {
+                similarities[a]
+                    .unwrap()
+                    .total_cmp(&similarities[b].unwrap())
+            })
+            .unwrap();
+        assert_eq!(least_similar, 3);
+
+        // a vector alone in its cluster is compared with the closest other cluster
+        let similarities =
+            centroid_similarities(&vectors[..4], &[0, 0, 0, 1], 2, Metric::Euclidean);
+        assert!(similarities[3].unwrap() < -6.0);
+
+        assert_eq!(
+            centroid_similarities(&vectors[..1], &[0], 1, Metric::Euclidean),
+            vec![None]
+        );
+    }
+}
@@ -0,0 +1,144 @@
+//! Optional compression of db files and `content.bin`, see [`Compression`].
+//!
+//! Compressed files are a sequence of frames, each a `u32` length followed by that many compressed bytes.
+//! Appending to a file adds a frame, so appends don't need to rewrite the file, and truncating a file back to an
+//! earlier size (see [`crate::Victor::recover`]) leaves whole frames behind.
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+
+/// How a database compresses its files, see [`crate::Settings::compression`].
+///
+/// Compressed db files have to be read (and decompressed) whole, so they can't be memory-mapped
+/// and are rewritten whenever an embedding is updated.
+///
+/// ```rust
+/// use victor_db::{Compression, Settings};
+///
+/// // a browser database, where storage quota matters more than insert speed
+/// let settings = Settings {
+///     compression: Compression::Deflate,
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Compression {
+    /// Store files as they are.
+    #[default]
+    None,
+    /// Compress files with zstd, which is faster and compresses better than deflate, but isn't available on wasm.
+    Zstd,
+    /// Compress files with deflate, which works everywhere.
+    Deflate,
+}
+
+/// Zstd's default level, which is a good tradeoff between speed and size.
+#[cfg(not(target_arch = "wasm32"))]
+const ZSTD_LEVEL: i32 = 3;
+
+/// Deflate's default level.
+const DEFLATE_LEVEL: u8 = 6;
+
+impl Compression {
+    /// Check that files can be compressed this way on this platform.
+    pub(crate) fn check_supported(self) -> Result<(), Error> {
+        if cfg!(target_arch = "wasm32") && self == Compression::Zstd {
+            return Err(Error::InvalidInput(
+                "zstd compression isn't supported on wasm, use deflate instead".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Compress `bytes` into a single frame, which can be appended to an existing file.
+    pub(crate) fn compress(self, bytes: &[u8]) -> Result<Vec<u8>, Error> {
+        let compressed = match self {
+            Compression::None => return Ok(bytes.to_vec()),
+            #[cfg(not(target_arch = "wasm32"))]
+            Compression::Zstd => zstd::bulk::compress(bytes, ZSTD_LEVEL)?,
+            #[cfg(target_arch = "wasm32")]
+            Compression::Zstd => {
+                self.check_supported()?;
+                unreachable!()
+            }
+            Compression::Deflate => miniz_oxide::deflate::compress_to_vec(bytes, DEFLATE_LEVEL),
+        };
+
+        let mut frame = bincode::serialize(&(compressed.len() as u32))
+            .expect("Failed to serialize frame length");
+        frame.extend(compressed);
+        Ok(frame)
+    }
+
+    /// Decompress every frame of `filename`, which holds `bytes`.
+    pub(crate) fn decompress(self, filename: &str, bytes: Vec<u8>) -> Result<Vec<u8>, Error> {
+        if self == Compression::None {
+            return Ok(bytes);
+        }
+        self.check_supported()?;
+
+        let corrupted = |reason: String| Error::Corrupted {
+            file: filename.to_string(),
+            reason,
+        };
+
+        let header_size = std::mem::size_of::<u32>();
+        let mut decompressed = Vec::new();
+        let mut rest = &bytes[..];
+        while !rest.is_empty() {
+            let len = rest
+                .get(..header_size)
+                .map(|len| u32::from_le_bytes(len.try_into().unwrap()) as usize)
+                .ok_or_else(|| corrupted("frame is too short to have a header".to_string()))?;
+            let frame = rest
+                .get(header_size..header_size + len)
+                .ok_or_else(|| corrupted(format!("frame of {len} bytes was truncated")))?;
+
+            match self {
+                Compression::None => unreachable!(),
+                #[cfg(not(target_arch = "wasm32"))]
+                Compression::Zstd => decompressed
+                    .extend(zstd::decode_all(frame).map_err(|e| corrupted(e.to_string()))?),
+                #[cfg(target_arch = "wasm32")]
+                Compression::Zstd => unreachable!(),
+                Compression::Deflate => decompressed.extend(
+                    miniz_oxide::inflate::decompress_to_vec(frame)
+                        .map_err(|e| corrupted(e.to_string()))?,
+                ),
+            }
+
+            rest = &rest[header_size + len..];
+        }
+
+        Ok(decompressed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip_frames() {
+        for compression in [Compression::None, Compression::Zstd, Compression::Deflate] {
+            let mut file = compression.compress(&[1; 1000]).unwrap();
+            file.extend(compression.compress(&[2; 1000]).unwrap());
+            if compression != Compression::None {
+                assert!(file.len() < 2000);
+            }
+
+            let decompressed = compression.decompress("a.bin", file.clone()).unwrap();
+            assert_eq!(decompressed[..1000], [1; 1000]);
+            assert_eq!(decompressed[1000..], [2; 1000]);
+
+            if compression != Compression::None {
+                file.pop();
+                assert!(matches!(
+                    compression.decompress("a.bin", file),
+                    Err(Error::Corrupted { .. })
+                ));
+            }
+        }
+    }
+}
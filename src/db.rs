@@ -1,28 +1,105 @@
-use std::cmp::Reverse;
-use std::collections::{BTreeSet, BinaryHeap, HashMap, HashSet};
+use std::{
+    borrow::Cow,
+    cell::RefCell,
+    collections::{hash_map::Entry, BTreeMap, BTreeSet, HashMap, HashSet, VecDeque},
+    ops::Range,
+    pin::pin,
+    sync::{Arc, Mutex, MutexGuard},
+};
 
+use futures::{Stream, StreamExt};
 use nalgebra::DMatrix;
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use sha256::digest;
 use uuid::Uuid;
 
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::wasm_bindgen;
 
-use crate::decomposition::{center_data, embeddings_to_dmatrix, project_to_lower_dimension};
+use crate::decomposition::{
+    embeddings_to_dmatrix, project_to_lower_dimension, AutoProjection, Projection,
+};
 
 use crate::{
+    added,
+    batch::{self, Backup, Batch, Operation, Saved},
+    bloom::{self, Bloom},
+    builder::Builder,
+    bundle::Bundle,
+    cache::{Cache, Cached, Version},
+    catalog::{Catalog, Location, SegmentInfo},
+    checksum,
+    chunking::Chunker,
+    chunks,
+    clusters::{self, Cluster, ClusterConfig},
+    compression::Compression,
+    document::{Deduplication, Document, Metadata, MetadataValue},
+    duplicates::{self, DuplicateGroup},
+    embedder::Embedder,
+    error::Error,
+    events::{Event, Subscribers},
+    expiry,
     filesystem::{
-        CreateWritableOptions, DirectoryHandle, FileHandle, GetFileHandleOptions,
+        CreateWritableOptions, DirectoryHandle, FileHandle, GetFileHandleOptions, Mapped,
         WritableFileStream,
     },
-    similarity,
+    filter::{Filter, SegmentStats, TagFilter},
+    format,
+    hnsw::{self, Hnsw, HnswConfig, Vectors},
+    hooks::{Hooks, SearchRequest},
+    importers::{self, ImportOptions, VectorStore},
+    integrity::{Discarded, IntegrityReport, Problem, RepairReport},
+    journal::{self, Journal},
+    jsonl::Record,
+    keywords::{self, InvertedIndex},
+    kmeans,
+    lsh::{self, Lsh, LshConfig},
+    npy,
+    progress::Progress,
+    quantization::{
+        self, BinaryConfig, BinaryEmbedding, Codec, CodedEmbedding, DistanceTable, PqConfig,
+        ProductQuantizer,
+    },
+    reranker::Reranker,
+    scroll::{ScrollCursor, ScrollPage, ScrolledDocument},
+    search::{self, Aggregation, DocumentMeta, ScoreBreakdown, SearchOptions},
+    settings::{Settings, SettingsV1, SettingsV9},
+    similarity::{self, Metric},
+    stats::{ProjectionStats, Stats},
+    telemetry, tombstones, utils, versions,
 };
 
 /// The main database struct.
 /// Through this you can [`Victor::add`] and [`Victor::search`] for embeddings.
-pub struct Victor<D> {
+#[must_use = "documents buffered by a database are only written once it's flushed or closed"]
+pub struct Victor<D: DirectoryHandle> {
     root: D,
+    /// Once a db file is at least this big, documents are added to a new one, see [`Victor::with_max_segment_size`].
+    max_segment_size: usize,
+    /// Where each embedding is stored, built when it's first needed. See [`Victor::catalog`].
+    catalog: Option<Catalog>,
+    /// Generates embeddings for [`Victor::add`] and [`Victor::search`], see [`Victor::with_embedder`].
+    embedder: Option<Box<dyn Embedder>>,
+    /// Re-scores the best matches of text searches, see [`Victor::with_reranker`].
+    reranker: Option<Box<dyn Reranker>>,
+    /// What to do with documents whose content is already stored, see [`Victor::with_deduplication`].
+    deduplication: Deduplication,
+    /// When to project embeddings to fewer dimensions, see [`Victor::with_auto_projection`].
+    auto_projection: Option<AutoProjection>,
+    /// Whether projecting keeps the original vectors, see [`Victor::with_retained_originals`].
+    retain_originals: bool,
+    /// How many documents can be added before they're written, see [`Victor::with_write_buffer`].
+    write_buffer: usize,
+    /// Documents added but not written yet, in the order they were added, grouped by the tags they're added with.
+    buffered: VecDeque<(Vec<String>, Vec<Document>)>,
+    /// Whether a batch is being applied, so [`Victor::recover`] doesn't roll it back part way through.
+    batching: bool,
+    /// Where changes are reported, see [`Victor::subscribe`].
+    subscribers: Subscribers,
+    /// Run on every insert, delete and search, see [`Victor::with_on_insert`] and the like.
+    hooks: Hooks,
+    /// The db files searches read most recently, see [`Victor::with_cache_budget`].
+    cache: Mutex<Cache>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -35,21 +112,151 @@ pub struct Embedding {
     pub vector: Vec<f32>,
 }
 
+impl AsRef<[f32]> for Embedding {
+    fn as_ref(&self) -> &[f32] {
+        &self.vector
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
-struct VectorProjection {
+pub(crate) struct VectorProjection {
     pub eigen: DMatrix<f32>,
     pub means: Vec<f32>,
+    /// The fraction of the variance the projection keeps, or `None` if it was projected before format version 6.
+    pub explained_variance: Option<f32>,
+}
+
+/// The layout of `eigen.bin` before format version 6, which didn't record the explained variance.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct VectorProjectionV5 {
+    eigen: DMatrix<f32>,
+    means: Vec<f32>,
+}
+
+impl From<VectorProjectionV5> for VectorProjection {
+    fn from(projection: VectorProjectionV5) -> Self {
+        Self {
+            eigen: projection.eigen,
+            means: projection.means,
+            explained_variance: None,
+        }
+    }
 }
 
-#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+/// A document's stored content, keyed by its embedding id in `content.bin` (see [`Victor::read_content_log`]).
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct Content {
-    pub id: Uuid,
     pub content: String,
+    pub external_id: Option<String>,
+    pub metadata: Metadata,
+}
+
+impl Content {
+    /// The id the document is exported with: its own id if it has one, or the one generated for it.
+    pub(crate) fn id_or(&self, id: Uuid) -> String {
+        self.external_id.clone().unwrap_or_else(|| id.to_string())
+    }
+
+    /// Roughly how many bytes the content takes up in memory, along with the id it's kept by.
+    pub(crate) fn size(&self) -> usize {
+        let metadata = self
+            .metadata
+            .iter()
+            .map(|(key, value)| {
+                let value = match value {
+                    MetadataValue::String(value) => value.len(),
+                    MetadataValue::Number(_) => 0,
+                };
+                std::mem::size_of::<(String, MetadataValue)>() + key.len() + value
+            })
+            .sum::<usize>();
+        std::mem::size_of::<(Uuid, Content)>()
+            + self.content.len()
+            + self.external_id.as_ref().map_or(0, String::len)
+            + metadata
+    }
 }
 
+/// A document that's been added to the database, with its tags.
+pub(crate) type StoredDocument = (BTreeSet<String>, Embedding, Content);
+
+/// The contents of `index.bin`: every combination of tags that documents have been added with.
+/// Documents with the same set of tags share db files, named after a hash of the tags
+/// (see [`Index::filename_for_part`]). Tag sets start out with one db file,
+/// and get another whenever the last one grows too big.
+/// [`Victor::optimize`] merges small db files into combined files, listed in `combined.bin`.
 #[derive(Serialize, Deserialize, Debug, Default, Eq, PartialEq, Clone)]
 pub struct Index {
     files: HashSet<BTreeSet<String>>,
+    /// The number of db files for each tag set with more than one (or none, once they've been combined).
+    parts: HashMap<BTreeSet<String>, usize>,
+    /// Read from `combined.bin` rather than `index.bin`, whose layout predates them.
+    #[serde(skip)]
+    combined: Vec<Combined>,
+}
+
+/// A db file holding the embeddings of several tag sets, made by [`Victor::optimize`].
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone)]
+struct Combined {
+    filename: String,
+    /// The tags of the embeddings in the file, in order: the first `n` embeddings have the first tags, and so on.
+    tag_map: Vec<(BTreeSet<String>, usize)>,
+}
+
+impl Combined {
+    /// The positions in the file of the embeddings with each of its tag sets, along with the name search results
+    /// from them are tagged with (see [`Index::tags_by_filename`]) and the tags.
+    fn ranges(&self) -> Vec<(Range<usize>, String, &BTreeSet<String>)> {
+        let mut start = 0;
+        self.tag_map
+            .iter()
+            .enumerate()
+            .map(|(i, (tags, len))| {
+                start += len;
+                (start - len..start, format!("{}#{i}", self.filename), tags)
+            })
+            .collect()
+    }
+
+    /// Whether `filename` is the name of a combined db file, rather than one of the files stored next to it.
+    fn is_combined(filename: &str) -> bool {
+        filename.starts_with(COMBINED_PREFIX) && filename.ends_with(".bin")
+    }
+
+    /// How many embeddings the tag map covers.
+    fn len(&self) -> usize {
+        self.tag_map.iter().map(|(_, len)| len).sum()
+    }
+
+    /// Keep only the embeddings at the positions `keep` returns true for, when the file is rewritten without the
+    /// others. Tag sets with no embeddings left are dropped.
+    fn retain(&mut self, keep: impl Fn(usize) -> bool) {
+        let mut start = 0;
+        for (_, len) in &mut self.tag_map {
+            let kept = (start..start + *len)
+                .filter(|&position| keep(position))
+                .count();
+            start += *len;
+            *len = kept;
+        }
+        self.tag_map.retain(|(_, len)| *len > 0);
+    }
+}
+
+/// The layout of `index.bin` before format version 4, when every tag set had exactly one db file.
+#[derive(Serialize, Deserialize)]
+struct IndexV3 {
+    files: HashSet<BTreeSet<String>>,
+}
+
+impl From<IndexV3> for Index {
+    fn from(index: IndexV3) -> Self {
+        Self {
+            files: index.files,
+            parts: HashMap::new(),
+            combined: Vec::new(),
+        }
+    }
 }
 
 #[cfg(target_arch = "wasm32")]
@@ -80,553 +287,7372 @@ impl<D: DirectoryHandle> Victor<D> {
     /// Or you can use [`crate::memory::DirectoryHandle`] to use an in-memory database.
     pub fn new(root: impl Into<D>) -> Self {
         let root = root.into();
-        Self { root }
+        Self {
+            root,
+            max_segment_size: DEFAULT_MAX_SEGMENT_SIZE,
+            catalog: None,
+            embedder: None,
+            reranker: None,
+            deduplication: Deduplication::Off,
+            // storage is scarcer on the web
+            auto_projection: cfg!(target_arch = "wasm32").then(AutoProjection::default),
+            retain_originals: false,
+            write_buffer: 0,
+            buffered: VecDeque::new(),
+            batching: false,
+            subscribers: Subscribers::default(),
+            hooks: Hooks::default(),
+            cache: Mutex::new(Cache::default()),
+        }
     }
 
-    /// Add many documents to the database.
-    /// Embeddings will be generated for each document.
+    /// Limit how big db files grow: once the db file for a set of tags is at least `max_segment_size` bytes,
+    /// documents with those tags are added to a new one. Defaults to 32 MiB.
+    ///
+    /// Smaller db files are cheaper to rewrite (say, when compacting or updating compressed files),
+    /// and let searches read the database in smaller pieces.
+    /// Files aren't split after the fact, so a single large insert can still exceed the limit.
     ///
     /// ```rust
     /// # tokio_test::block_on(async {
     /// # use victor_db::memory::{Db, DirectoryHandle};
-    /// # let mut victor = Db::new(DirectoryHandle::default());
-    /// victor
-    ///     .add(
-    ///         vec!["Pineapple", "Rocks"], // documents
-    ///         vec!["Pizza Toppings"],     // tags (only used for filtering)
-    ///     )
-    ///     .await;
+    /// let mut victor = Db::new(DirectoryHandle::default()).with_max_segment_size(1024 * 1024);
     /// # })
     /// ```
-    #[cfg(not(target_arch = "wasm32"))]
-    pub async fn add(&mut self, content: Vec<impl Into<String>>, tags: Vec<impl Into<String>>) {
-        let tags = tags.into_iter().map(|t| t.into()).collect::<Vec<String>>();
-        let model = fastembed::TextEmbedding::try_new(Default::default()).unwrap();
-        let content = content
-            .into_iter()
-            .map(|c| c.into())
-            .collect::<Vec<String>>();
-
-        let vectors = model.embed(content.clone(), None).unwrap();
+    pub fn with_max_segment_size(mut self, max_segment_size: usize) -> Self {
+        self.max_segment_size = max_segment_size;
+        self
+    }
 
-        let to_add = content.into_iter().zip(vectors.into_iter()).collect();
-        self.add_embeddings(to_add, tags).await;
+    /// Generate embeddings for [`Victor::add`] and [`Victor::search`] with `embedder`,
+    /// instead of running a local model with fastembed (which needs the `embeddings` feature,
+    /// and isn't available on the web).
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// use async_trait::async_trait;
+    /// use victor_db::{Embedder, Error};
+    ///
+    /// struct LengthEmbedder;
+    ///
+    /// #[async_trait]
+    /// impl Embedder for LengthEmbedder {
+    ///     async fn embed(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>, Error> {
+    ///         Ok(texts.iter().map(|text| vec![text.len() as f32, 1.0]).collect())
+    ///     }
+    /// }
+    ///
+    /// let mut victor = Db::new(DirectoryHandle::default()).with_embedder(LengthEmbedder);
+    /// victor.add_single("Pepperoni pizza", vec!["Pizza Flavors"]).await.unwrap();
+    /// # })
+    /// ```
+    pub fn with_embedder(mut self, embedder: impl Embedder + 'static) -> Self {
+        self.embedder = Some(Box::new(embedder));
+        self
     }
 
-    /// Add a single document to the database.
-    /// Embedding will be generated for the document.
-    /// When adding many documents, it is more efficient to use `add`.
+    /// Re-score the best matches of [`Victor::search`] and [`Victor::search_hybrid`] with `reranker`,
+    /// like a cross-encoder, before returning the top `top_n`. Results are then ordered by the reranker's scores,
+    /// which become their `similarity`. See [`SearchOptions::rerank_candidates`] for how many are re-scored.
     ///
     /// ```rust
     /// # tokio_test::block_on(async {
     /// # use victor_db::memory::{Db, DirectoryHandle};
-    /// # let mut victor = Db::new(DirectoryHandle::default());
-    /// victor.add_single("Pepperoni pizza", vec!["Pizza Flavors"]).await;
+    /// use async_trait::async_trait;
+    /// use victor_db::{Error, Reranker};
+    ///
+    /// struct ShortestFirst;
+    ///
+    /// #[async_trait]
+    /// impl Reranker for ShortestFirst {
+    ///     async fn rerank(&self, query: &str, candidates: &[&str]) -> Result<Vec<f32>, Error> {
+    ///         Ok(candidates.iter().map(|content| -(content.len() as f32)).collect())
+    ///     }
+    /// }
+    ///
+    /// let mut victor = Db::new(DirectoryHandle::default()).with_reranker(ShortestFirst);
     /// # })
     /// ```
-    #[cfg(not(target_arch = "wasm32"))]
-    pub async fn add_single(&mut self, content: impl Into<String>, tags: Vec<impl Into<String>>) {
-        self.add(vec![content], tags).await;
+    pub fn with_reranker(mut self, reranker: impl Reranker + 'static) -> Self {
+        self.reranker = Some(Box::new(reranker));
+        self
     }
 
-    /// Add many document/embedding pairs to the database.
-    /// This is useful for adding embeddings that have already been generated.
+    /// Run `hook` on every document before it's added, with the tags it's added with, so it can change the
+    /// document (say, to add metadata) or reject it with an error, in which case nothing is added.
+    /// Hooks run in the order they're registered.
     ///
     /// ```rust
     /// # tokio_test::block_on(async {
     /// # use victor_db::memory::{Db, DirectoryHandle};
-    /// # let mut victor = Db::new(DirectoryHandle::default());
-    /// victor.add_embeddings(vec![("Pepperoni pizza", vec![0.1, 0.2, 0.3])], vec!["Pizza Flavors"]).await;
+    /// use victor_db::{Error, Filter, SearchOptions};
+    ///
+    /// let mut victor = Db::new(DirectoryHandle::default()).with_on_insert(|document, tags| {
+    ///     if document.content().is_empty() {
+    ///         return Err(Error::InvalidInput("documents can't be empty".to_string()));
+    ///     }
+    ///     document.metadata_mut().insert("tag_count".to_string(), (tags.len() as f64).into());
+    ///     Ok(())
+    /// });
+    ///
+    /// victor.add_single_embedding("Pepperoni pizza", vec![0.1, 0.2, 0.3], vec!["Pizza Flavors"]).await.unwrap();
+    /// assert!(victor.add_single_embedding("", vec![0.1, 0.2, 0.3], vec!["Pizza Flavors"]).await.is_err());
+    ///
+    /// let options = SearchOptions::default().with_filter(Filter::eq("tag_count", 1.0));
+    /// let results = victor.search_embedding_with_options(vec![0.1, 0.2, 0.3], vec!["Pizza Flavors"], 10, &options).await.unwrap();
+    /// assert_eq!(results.len(), 1);
     /// # })
     /// ```
-    pub async fn add_embeddings(
-        &mut self,
-        to_add: Vec<(impl Into<String>, Vec<f32>)>,
-        tags: Vec<impl Into<String>>,
-    ) {
-        let tags = tags.into_iter().map(|t| t.into()).collect::<Vec<String>>();
-        let (contents, embeddings) = to_add
-            .into_iter()
-            .map(|(content, embedding)| {
-                let uuid = Uuid::new_v4();
-                (
-                    (content.into(), uuid),
-                    Embedding {
-                        id: uuid,
-                        vector: embedding,
-                    },
-                )
-            })
-            .unzip();
+    pub fn with_on_insert(
+        mut self,
+        hook: impl Fn(&mut Document, &[String]) -> Result<(), Error> + Send + Sync + 'static,
+    ) -> Self {
+        self.hooks.on_insert.push(Box::new(hook));
+        self
+    }
 
-        self.write_embeddings(embeddings, tags).await.unwrap();
-        self.write_contents(contents).await.unwrap();
+    /// Run `hook` with the id of every document before it's deleted, so it can be logged, or kept with an error.
+    pub fn with_on_delete(
+        mut self,
+        hook: impl Fn(Uuid) -> Result<(), Error> + Send + Sync + 'static,
+    ) -> Self {
+        self.hooks.on_delete.push(Box::new(hook));
+        self
     }
 
-    /// Add a single document/embedding pair to the database.
-    /// This is useful for adding embeddings that have already been generated.
-    /// When adding many documents, it is more efficient to use `add_embeddings`.
+    /// Run `hook` before every search, so it can be logged, or rejected with an error.
     ///
     /// ```rust
     /// # tokio_test::block_on(async {
     /// # use victor_db::memory::{Db, DirectoryHandle};
-    /// # let mut victor = Db::new(DirectoryHandle::default());
-    /// victor.add_single_embedding("Pepperoni pizza", vec![0.1, 0.2, 0.3], vec!["Pizza Flavors"]).await;
+    /// use victor_db::Error;
+    ///
+    /// let victor = Db::new(DirectoryHandle::default()).with_before_search(|request| match request.top_n {
+    ///     0..=100 => Ok(()),
+    ///     _ => Err(Error::InvalidInput("too many results".to_string())),
+    /// });
+    /// assert!(victor.search_embedding(vec![0.1, 0.2, 0.3], vec!["Pizza Flavors"], 1000).await.is_err());
     /// # })
     /// ```
-    pub async fn add_single_embedding(
-        &mut self,
-        content: impl Into<String>,
-        vector: Vec<f32>,
-        tags: Vec<impl Into<String>>,
-    ) {
-        self.add_embeddings(vec![(content, vector)], tags).await;
+    pub fn with_before_search(
+        mut self,
+        hook: impl Fn(&SearchRequest) -> Result<(), Error> + Send + Sync + 'static,
+    ) -> Self {
+        self.hooks.before_search.push(Box::new(hook));
+        self
     }
 
-    /// Search the database for the nearest neighbors to a given document.
-    /// An embedding will be generated for the document being searched for.
-    /// This will return the top `top_n` nearest neighbors.
+    /// Run `hook` on the results of every search before they're returned, so they can be logged or changed.
     ///
     /// ```rust
     /// # tokio_test::block_on(async {
     /// # use victor_db::memory::{Db, DirectoryHandle};
-    /// # let mut victor = Db::new(DirectoryHandle::default());
-    /// victor.search("Pepperoni pizza", vec!["Pizza Flavors"], 10).await;
+    /// let mut victor = Db::new(DirectoryHandle::default())
+    ///     .with_after_search(|_, results| results.retain(|result| !result.content.contains("Pineapple")));
+    /// victor.add_single_embedding("Pineapple pizza", vec![0.1, 0.2, 0.3], vec!["Pizza Flavors"]).await.unwrap();
+    ///
+    /// let results = victor.search_embedding(vec![0.1, 0.2, 0.3], vec!["Pizza Flavors"], 10).await.unwrap();
+    /// assert!(results.is_empty());
     /// # })
     /// ```
-    #[cfg(not(target_arch = "wasm32"))]
-    pub async fn search(
-        &self,
-        content: impl Into<String>,
-        with_tags: Vec<impl Into<String>>,
-        top_n: u32,
-    ) -> Vec<NearestNeighborsResult> {
-        let model = fastembed::TextEmbedding::try_new(Default::default()).unwrap();
-        let content = content.into();
-        let vector = model
-            .embed(vec![content.clone()], None)
-            .unwrap()
-            .first()
-            .cloned()
-            .unwrap();
-        self.search_embedding(vector, with_tags, top_n).await
+    pub fn with_after_search(
+        mut self,
+        hook: impl Fn(&SearchRequest, &mut Vec<NearestNeighborsResult>) + Send + Sync + 'static,
+    ) -> Self {
+        self.hooks.after_search.push(Box::new(hook));
+        self
     }
 
-    /// Search the database for the nearest neighbors to a given embedding.
-    /// This will return the top `top_n` nearest neighbors.
-    pub async fn search_embedding(
-        &self,
-        mut vector: Vec<f32>,
-        with_tags: Vec<impl Into<String>>,
-        top_n: u32,
-    ) -> Vec<NearestNeighborsResult> {
-        let with_tags = with_tags
-            .into_iter()
-            .map(|t| t.into())
-            .collect::<Vec<String>>();
-        let top_n = top_n as usize;
-        let with_tags = with_tags.into_iter().collect::<BTreeSet<_>>();
-        let file_handles = Index::get_matching_db_files(&self.root, with_tags)
-            .await
-            .unwrap();
-
-        let is_projected: bool = self
-            .root
-            .get_file_handle_with_options("eigen.bin", &GetFileHandleOptions { create: false })
-            .await
-            .is_ok();
-
-        if is_projected {
-            let eigen_file = self.eigen_file().await;
-            vector = self.project_single_vector(vector, eigen_file);
-        }
-
-        let mut nearest_neighbors = BinaryHeap::with_capacity(top_n);
-        for file_handle in file_handles {
-            let file = file_handle.read().await.unwrap();
-            let embeddings = self.get_embeddings_by_file(file).await;
-
-            // find max similarity in this file
-            for potential_match in &embeddings {
-                let sim = if is_projected {
-                    similarity::euclidean(&potential_match.vector, &vector).unwrap()
-                } else {
-                    similarity::cosine(&potential_match.vector, &vector).unwrap()
-                };
-
-                if nearest_neighbors.len() < top_n {
-                    let result = NearestNeighborsResult {
-                        similarity: sim,
-                        embedding: potential_match.clone(),
-                        content: self.get_content(potential_match.id).await,
-                    };
-                    nearest_neighbors.push(Reverse(result));
-                } else if sim > nearest_neighbors.peek().unwrap().0.similarity {
-                    let result = NearestNeighborsResult {
-                        similarity: sim,
-                        embedding: potential_match.clone(),
-                        content: self.get_content(potential_match.id).await,
-                    };
-                    nearest_neighbors.pop();
-                    nearest_neighbors.push(Reverse(result));
-                }
-            }
-        }
-
-        let mut nearest = nearest_neighbors
-            .into_iter()
-            .map(|r| r.0)
-            .collect::<Vec<_>>();
-        nearest.sort();
-        nearest.reverse();
-        nearest
+    /// Decide what happens when documents are added with the same content as a document that's already stored
+    /// (or earlier in the same batch), instead of storing the content again. See [`Deduplication`].
+    ///
+    /// Documents whose id is already stored are updated as usual, whatever their content.
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// use victor_db::Deduplication;
+    ///
+    /// let mut victor = Db::new(DirectoryHandle::default()).with_deduplication(Deduplication::Skip);
+    ///
+    /// // running the same ingestion twice only stores the document once
+    /// for _ in 0..2 {
+    ///     victor.add_single_embedding("Pepperoni pizza", vec![0.1, 0.2, 0.3], vec!["Pizza Flavors"]).await.unwrap();
+    /// }
+    /// let nearest = victor.search_embedding(vec![0.1, 0.2, 0.3], vec!["Pizza Flavors"], 10).await.unwrap();
+    /// assert_eq!(nearest.len(), 1);
+    /// # })
+    /// ```
+    ///
+    /// [`Deduplication`]: crate::Deduplication
+    pub fn with_deduplication(mut self, deduplication: Deduplication) -> Self {
+        self.deduplication = deduplication;
+        self
     }
 
-    // utils
+    /// Project every embedding to fewer dimensions once a db file grows past a certain size, or never if `None`.
+    /// See [`Victor::project`] for what projecting means.
+    ///
+    /// By default, databases on the web project to 500 dimensions once a db file is bigger than 1 MB
+    /// (see [`AutoProjection::default`]), and native databases are never projected automatically.
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// use victor_db::{AutoProjection, Projection};
+    ///
+    /// let auto_projection = AutoProjection {
+    ///     segment_size: 64 * 1024 * 1024,
+    ///     projection: Projection::ExplainedVariance(0.95),
+    /// };
+    /// let mut victor = Db::new(DirectoryHandle::default()).with_auto_projection(Some(auto_projection));
+    /// # })
+    /// ```
+    ///
+    /// [`AutoProjection::default`]: crate::AutoProjection::default
+    pub fn with_auto_projection(mut self, auto_projection: Option<AutoProjection>) -> Self {
+        self.auto_projection = auto_projection;
+        self
+    }
 
-    async fn project_embeddings(&mut self) {
-        let prev_embeddings = self.get_all_embeddings().await;
+    /// Keep the full-precision vectors of embeddings in `originals.bin`, alongside the packed, projected or
+    /// quantized ones that are searched. Searches re-score their best matches with them
+    /// (see [`SearchOptions::rescore`]), and projected databases (see [`Victor::project`]) can be projected
+    /// again with different parameters, or [`Victor::unproject`]ed.
+    ///
+    /// Embeddings stored before this was set keep the vectors they were stored with, so it's best set when the
+    /// database is created. Once a database keeps its original vectors, it keeps them for embeddings added later
+    /// too. This only saves space in the db files that are searched, not in the database as a whole.
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// let mut victor = Db::new(DirectoryHandle::default()).with_retained_originals(true);
+    /// victor.add_single_embedding("Pepperoni pizza", vec![0.1, 0.2, 0.3], vec!["Pizza Flavors"]).await.unwrap();
+    /// victor.add_single_embedding("Pineapple", vec![0.3, 0.2, 0.1], vec!["Pizza Toppings"]).await.unwrap();
+    ///
+    /// victor.project(2).await.unwrap();
+    /// victor.project(1).await.unwrap();
+    /// victor.unproject().await.unwrap();
+    ///
+    /// let nearest = victor.search_embedding(vec![0.1, 0.2, 0.3], vec!["Pizza Flavors"], 1).await.unwrap();
+    /// assert_eq!(nearest[0].embedding.vector.len(), 3);
+    /// # })
+    /// ```
+    pub fn with_retained_originals(mut self, retain_originals: bool) -> Self {
+        self.retain_originals = retain_originals;
+        self
+    }
 
-        let (eigenvectors, means) = project_to_lower_dimension(prev_embeddings.clone(), 500);
-        let vector_projection = VectorProjection {
-            eigen: eigenvectors.clone(),
-            means,
-        };
+    /// Keep up to `bytes` of the db files searches read in memory, so the next search doesn't read them again.
+    /// By default nothing is kept.
+    ///
+    /// Files are kept decoded as they're read (or ahead of time, see [`Victor::warm`]), and count for what they
+    /// take up decoded: db files' embeddings (quantized ones stay encoded), `content.bin` and `eigen.bin`.
+    /// The least recently used files are evicted once there are more than `bytes` of them, and files bigger
+    /// than that are never kept. A file that's changed since it was kept
+    /// (even by another `Victor`) is read again. Memory-mapped files (see the `mmap` feature) aren't kept,
+    /// since they're already in memory.
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// let victor = Db::new(DirectoryHandle::default()).with_cache_budget(64 * 1024 * 1024);
+    /// # })
+    /// ```
+    pub fn with_cache_budget(mut self, bytes: usize) -> Self {
+        self.cache = Mutex::new(Cache::new(bytes));
+        self
+    }
 
-        self.write_projection(vector_projection.clone()).await;
+    /// How many bytes of db files are in memory, see [`Victor::with_cache_budget`].
+    pub fn cache_size(&self) -> usize {
+        self.cache().size()
+    }
 
-        self.update_all_embeddings(vector_projection).await;
+    fn cache(&self) -> MutexGuard<'_, Cache> {
+        // the cache is never left half-changed, so it's still usable if a search panicked
+        self.cache
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
     }
 
-    async fn update_all_embeddings(&mut self, vector_projection: VectorProjection) {
-        let file_handles = Index::get_matching_db_files(
-            &self.root,
-            Vec::new().into_iter().collect::<BTreeSet<_>>(),
-        )
-        .await
-        .unwrap();
+    /// Cache `records`, the encoded embeddings of `version` of `filename` (if it's cached at all), returning them
+    /// as a segment's data. Whole vectors are decoded first, so searches that hit the cache don't decode them
+    /// again, and take up their decoded size. Quantized embeddings are scored as they're encoded, so they stay
+    /// that way.
+    fn cache_records(
+        &self,
+        filename: &str,
+        version: Option<Version>,
+        records: Vec<u8>,
+        record_size: usize,
+        codec: &Codec,
+    ) -> Result<SegmentData, Error> {
+        let Some(version) = version else {
+            return Ok(SegmentData::Read(records));
+        };
+        if let Codec::Vector(_) = codec {
+            // check the decoded embeddings will fit before decoding them all
+            let dimension = match records.get(..record_size) {
+                Some(first) => codec.decode(filename, first)?.vector.len(),
+                None => return Ok(SegmentData::Read(records)),
+            };
+            let size = records.len() / record_size * decoded_size(dimension);
+            if !self.cache().fits(size) {
+                return Ok(SegmentData::Read(records));
+            }
 
-        for mut file_handle in file_handles {
-            let file = file_handle.read().await.unwrap();
-            // need to accumulate these over all the indices
-            let embeddings = self.get_embeddings_by_file(file).await;
-            let matrix = embeddings_to_dmatrix(
-                embeddings
-                    .clone()
-                    .into_iter()
-                    .map(|embedding| embedding.vector)
-                    .collect(),
+            let embeddings = Arc::new(
+                records
+                    .chunks(record_size)
+                    .map(|record| codec.decode(filename, record))
+                    .collect::<Result<Vec<_>, _>>()?,
             );
-            let (centered_data, _) = center_data(&matrix);
-
-            let projected_data = centered_data * &vector_projection.eigen;
-
-            let projected_vectors: Vec<Vec<f32>> = projected_data
-                .row_iter()
-                .map(|row| row.iter().cloned().collect())
-                .collect();
-
-            let new_embeddings: Vec<Embedding> = embeddings
-                .iter()
-                .enumerate()
-                .map(|(index, embedding)| Embedding {
-                    id: embedding.id,
-                    vector: projected_vectors[index].clone(),
-                })
-                .collect();
-
-            let len_as_u32 = bincode::serialize(&new_embeddings[0])
-                .expect("Failed to serialize embeddings")
-                .len() as u32;
-
-            let serialized_size =
-                bincode::serialize(&len_as_u32).expect("Failed to serialize size");
-
-            let serialized_embeddings =
-                bincode::serialize(&new_embeddings).expect("Failed to serialize embeddings");
+            let cached = Cached::Embeddings {
+                embeddings: embeddings.clone(),
+                record_size,
+            };
+            self.cache()
+                .insert(filename.to_string(), version, size, cached);
+            return Ok(SegmentData::Decoded(embeddings));
+        }
 
-            let mut writable = file_handle
-                .create_writable_with_options(&CreateWritableOptions {
-                    keep_existing_data: false,
-                })
-                .await
-                .unwrap();
+        let records = Arc::new(records);
+        let cached = Cached::Records {
+            records: records.clone(),
+            record_size,
+        };
+        let size = records.len();
+        self.cache()
+            .insert(filename.to_string(), version, size, cached);
+        Ok(SegmentData::Cached(records))
+    }
 
-            let mut combined = serialized_size;
-            combined.extend(
-                &serialized_embeddings
-                    [bincode::serialized_size(&Vec::<Embedding>::new()).unwrap() as usize..],
-            );
+    /// The version of `filename` to look up in the cache, or `None` if nothing is cached.
+    async fn cache_version(
+        &self,
+        filename: &str,
+        file_handle: &D::FileHandleT,
+    ) -> Result<Option<Version>, Error> {
+        if !self.cache().fits(1) {
+            return Ok(None);
+        }
+        let size = file_handle.size().await?;
+        Ok(Some((
+            size,
+            checksum::recorded(&self.root, filename).await?,
+        )))
+    }
 
-            writable.seek(0).await.unwrap();
+    /// What's cached of `version` of `filename`, reporting the lookup as one of the `kind` cache.
+    fn cached(
+        &self,
+        filename: &str,
+        kind: &'static str,
+        version: Option<Version>,
+    ) -> Option<Cached> {
+        let cached = self.cache().get(filename, version?);
+        telemetry::cache(kind, cached.is_some());
+        cached
+    }
 
-            writable.write_at_cursor_pos(combined).await.unwrap();
+    /// Read the db files searches for `with_tags` would read, along with `content.bin` and `eigen.bin`,
+    /// into the cache (see [`Victor::with_cache_budget`]), so the first search doesn't have to wait for them.
+    /// Without a cache budget, this does nothing.
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// let mut victor = Db::new(DirectoryHandle::default()).with_cache_budget(64 * 1024 * 1024);
+    /// victor.add_embeddings(vec![("Pepperoni pizza", vec![1.0, 0.0])], vec!["pizza"]).await.unwrap();
+    ///
+    /// victor.warm(vec!["pizza"]).await.unwrap();
+    /// assert!(victor.cache_size() > 0);
+    /// # })
+    /// ```
+    pub async fn warm(&self, with_tags: impl Into<TagFilter>) -> Result<(), Error> {
+        if !self.cache().fits(1) {
+            return Ok(());
+        }
+        format::check(&self.root).await?;
 
-            writable.close().await.unwrap();
+        let (_, index) = Index::load(&self.root).await?;
+        let with_tags = index.tag_filter(with_tags.into(), &SearchOptions::default());
+        let codec = self.codec().await?;
+        let hnsw_config = hnsw::read_config(&self.root).await?;
+        let lsh_config = lsh::read_config(&self.root).await?;
+        for (filename, file_handle) in index.open_files(&self.root, &with_tags).await? {
+            // files combined with others are read a tag at a time, so they aren't cached
+            if index.combined(&filename).is_none() {
+                self.read_segments(
+                    filename,
+                    file_handle,
+                    &codec,
+                    hnsw_config,
+                    lsh_config,
+                    false,
+                )
+                .await?;
+            }
         }
-    }
 
-    async fn write_projection(&mut self, vector_projection: VectorProjection) {
-        let mut eigen_file_handle = self
+        self.read_content_log().await?;
+        let is_projected = self
             .root
-            .get_file_handle_with_options("eigen.bin", &GetFileHandleOptions { create: true })
+            .get_file_handle_with_options("eigen.bin", &GetFileHandleOptions { create: false })
             .await
-            .unwrap();
-
+            .is_ok();
+        if is_projected {
+            self.read_projection().await?;
+        }
+        Ok(())
+    }
+
+    /// Hold up to `documents` added documents in memory instead of writing them right away, so many small inserts
+    /// are written together (which is much faster, especially on the web). By default nothing is buffered.
+    ///
+    /// Buffered documents are written once there are more than `documents` of them, when [`Victor::flush`] is
+    /// called, and before documents are updated, deleted or compacted. Until then, searches don't see them.
+    ///
+    /// Buffered documents can't be written when the database is dropped (they're only reported, through the `log`
+    /// crate), so [`Victor::close`] it, or call [`Victor::flush`], when you're done adding documents.
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// let mut victor = Db::new(DirectoryHandle::default()).with_write_buffer(1000);
+    /// for _ in 0..100 {
+    ///     victor.add_single_embedding("Pepperoni pizza", vec![0.1, 0.2, 0.3], vec!["Pizza Flavors"]).await.unwrap();
+    /// }
+    /// assert!(victor.search_embedding(vec![0.1, 0.2, 0.3], vec!["Pizza Flavors"], 1).await.unwrap().is_empty());
+    ///
+    /// victor.flush().await.unwrap();
+    /// assert_eq!(victor.search_embedding(vec![0.1, 0.2, 0.3], vec!["Pizza Flavors"], 1).await.unwrap().len(), 1);
+    /// # })
+    /// ```
+    pub fn with_write_buffer(mut self, documents: usize) -> Self {
+        self.write_buffer = documents;
+        self
+    }
+
+    /// Write every buffered document (see [`Victor::with_write_buffer`]) to the database.
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// let mut victor = Db::new(DirectoryHandle::default()).with_write_buffer(1000);
+    /// victor.add_single_embedding("Pepperoni pizza", vec![0.1, 0.2, 0.3], vec!["Pizza Flavors"]).await.unwrap();
+    /// victor.flush().await.unwrap();
+    /// # })
+    /// ```
+    pub async fn flush(&mut self) -> Result<(), Error> {
+        let mut buffered = std::mem::take(&mut self.buffered);
+        while let Some((tags, documents)) = buffered.front() {
+            if let Err(error) = self.write_documents(documents, tags).await {
+                // keep what wasn't written, so flushing again can retry
+                self.buffered = buffered;
+                return Err(error);
+            }
+            buffered.pop_front();
+        }
+        Ok(())
+    }
+
+    /// Write every buffered document (see [`Victor::with_write_buffer`]) and close the database.
+    ///
+    /// Writing is async, so dropping a database can't write what's buffered: close it instead.
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// let mut victor = Db::new(DirectoryHandle::default()).with_write_buffer(1000);
+    /// victor.add_single_embedding("Pepperoni pizza", vec![0.1, 0.2, 0.3], vec!["Pizza Flavors"]).await.unwrap();
+    /// victor.close().await.unwrap();
+    /// # })
+    /// ```
+    pub async fn close(mut self) -> Result<(), Error> {
+        self.flush().await
+    }
+
+    /// Open a database with `settings`, which are recorded in the database.
+    ///
+    /// Once embeddings have been added, the settings can't change,
+    /// so opening the database with different settings returns an error.
+    ///
+    /// By default vectors are packed to 8 bits per dimension, which makes similarity scores slightly inexact.
+    /// Creating a database with [`Storage::Full`] keeps every vector exactly as it was added, at four times the size.
+    /// [`Storage::Half`] is in between, at twice the size with negligible loss of precision.
+    /// Files can also be compressed, see [`Compression`].
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::DirectoryHandle;
+    /// use victor_db::{memory::Db, Settings, Storage};
+    ///
+    /// let settings = Settings {
+    ///     storage: Storage::Full,
+    ///     ..Default::default()
+    /// };
+    /// let mut victor = Db::with_settings(DirectoryHandle::default(), settings).await.unwrap();
+    ///
+    /// victor.add_single_embedding("Pineapple", vec![0.1, 0.2, 0.3], vec!["Pizza Toppings"]).await.unwrap();
+    ///
+    /// // the vector comes back exactly as it was added
+    /// let nearest = victor.search_embedding(vec![0.1, 0.2, 0.3], vec!["Pizza Toppings"], 1).await.unwrap();
+    /// assert_eq!(nearest[0].embedding.vector, vec![0.1, 0.2, 0.3]);
+    /// # })
+    /// ```
+    pub async fn with_settings(root: impl Into<D>, settings: Settings) -> Result<Self, Error> {
+        Self::new(root).open(settings).await
+    }
+
+    /// Configure a database before opening it, see [`Builder`].
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// use victor_db::Metric;
+    ///
+    /// let mut victor = Db::builder(DirectoryHandle::default())
+    ///     .metric(Metric::Euclidean)
+    ///     .dimension(3)
+    ///     .build()
+    ///     .await
+    ///     .unwrap();
+    /// # })
+    /// ```
+    pub fn builder(root: impl Into<D>) -> Builder<D> {
+        Builder::new(Self::new(root))
+    }
+
+    /// Open the database with `settings`, see [`Victor::with_settings`].
+    pub(crate) async fn open(mut self, settings: Settings) -> Result<Self, Error> {
+        settings.compression.check_supported()?;
+        self.migrate().await?;
+
+        let db_files = Index::get_matching_db_files(&self.root, &TagFilter::default()).await?;
+        let settings = if db_files.is_empty() {
+            settings
+        } else {
+            self.settings().await?.reconcile(settings)?
+        };
+        self.write_settings(settings).await?;
+        self.recover().await?;
+        self.catalog().await?;
+
+        Ok(self)
+    }
+
+    /// Add many documents to the database.
+    /// Embeddings will be generated for each document.
+    ///
+    /// ```rust
+    /// # #[cfg(feature = "embeddings")]
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// # let mut victor = Db::new(DirectoryHandle::default());
+    /// victor
+    ///     .add(
+    ///         vec!["Pineapple", "Rocks"], // documents
+    ///         vec!["Pizza Toppings"],     // tags (only used for filtering)
+    ///     )
+    ///     .await
+    ///     .unwrap();
+    /// # });
+    /// ```
+    pub async fn add(
+        &mut self,
+        content: Vec<impl Into<String>>,
+        tags: Vec<impl Into<String>>,
+    ) -> Result<(), Error> {
+        self.add_with_progress(content, tags, |_| {}).await
+    }
+
+    /// Add many documents like [`Victor::add`], calling `on_progress` as each batch is embedded and then written,
+    /// so a progress bar can be shown while adding a large corpus.
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// use async_trait::async_trait;
+    /// use victor_db::{Embedder, Error, Progress};
+    ///
+    /// # struct LengthEmbedder;
+    /// # #[async_trait]
+    /// # impl Embedder for LengthEmbedder {
+    /// #     async fn embed(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>, Error> {
+    /// #         Ok(texts.iter().map(|text| vec![text.len() as f32, 1.0]).collect())
+    /// #     }
+    /// # }
+    /// let mut victor = Db::new(DirectoryHandle::default()).with_embedder(LengthEmbedder);
+    ///
+    /// let mut last = None;
+    /// victor
+    ///     .add_with_progress(vec!["Pineapple", "Rocks"], vec!["Pizza Toppings"], |progress| {
+    ///         println!("{:.0}% done", progress.fraction() * 100.0);
+    ///         last = Some(progress);
+    ///     })
+    ///     .await
+    ///     .unwrap();
+    /// assert_eq!(last, Some(Progress::Writing { done: 2, total: 2 }));
+    /// # })
+    /// ```
+    pub async fn add_with_progress(
+        &mut self,
+        content: Vec<impl Into<String>>,
+        tags: Vec<impl Into<String>>,
+        mut on_progress: impl FnMut(Progress),
+    ) -> Result<(), Error> {
+        let tags = tags.into_iter().map(|t| t.into()).collect::<Vec<String>>();
+        let content = content
+            .into_iter()
+            .map(|c| c.into())
+            .collect::<Vec<String>>();
+        let total = content.len();
+
+        let mut vectors = Vec::with_capacity(total);
+        for batch in content.chunks(EMBED_BATCH_SIZE) {
+            vectors.extend(self.embed(batch.to_vec()).await?);
+            on_progress(Progress::Embedding {
+                done: vectors.len(),
+                total,
+            });
+        }
+
+        let mut documents = content
+            .into_iter()
+            .zip(vectors)
+            .map(|(content, vector)| Document::new(content, vector));
+        let mut done = 0;
+        while done < total {
+            let batch = documents
+                .by_ref()
+                .take(IMPORT_BATCH_SIZE)
+                .collect::<Vec<_>>();
+            done += batch.len();
+            self.add_documents(batch, tags.clone()).await?;
+            on_progress(Progress::Writing { done, total });
+        }
+        Ok(())
+    }
+
+    /// Add documents as they arrive from `stream`, as `(content, tags)` pairs, returning how many were added.
+    ///
+    /// Documents are embedded and written in batches of whatever has arrived (up to a limit), and the stream isn't
+    /// polled again until the batch is written, so only a batch of documents is in memory at a time.
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// use async_trait::async_trait;
+    /// use victor_db::{Embedder, Error};
+    ///
+    /// # struct LengthEmbedder;
+    /// # #[async_trait]
+    /// # impl Embedder for LengthEmbedder {
+    /// #     async fn embed(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>, Error> {
+    /// #         Ok(texts.iter().map(|text| vec![text.len() as f32, 1.0]).collect())
+    /// #     }
+    /// # }
+    /// let mut victor = Db::new(DirectoryHandle::default()).with_embedder(LengthEmbedder);
+    ///
+    /// // say, pages from a crawl
+    /// let pages = futures::stream::iter(vec![
+    ///     ("Pineapple".to_string(), vec!["Pizza Toppings".to_string()]),
+    ///     ("Cheese pizza".to_string(), vec!["Pizza Flavors".to_string()]),
+    /// ]);
+    /// assert_eq!(victor.add_stream(pages).await.unwrap(), 2);
+    /// # })
+    /// ```
+    pub async fn add_stream(
+        &mut self,
+        stream: impl Stream<Item = (String, Vec<String>)>,
+    ) -> Result<usize, Error> {
+        let mut batches = pin!(stream.ready_chunks(EMBED_BATCH_SIZE));
+        let mut added = 0;
+        while let Some(batch) = batches.next().await {
+            let (contents, tags): (Vec<_>, Vec<_>) = batch.into_iter().unzip();
+            let vectors = self.embed(contents.clone()).await?;
+
+            let mut by_tags = HashMap::<Vec<String>, Vec<Document>>::new();
+            for ((content, vector), tags) in contents.into_iter().zip(vectors).zip(tags) {
+                by_tags
+                    .entry(tags)
+                    .or_default()
+                    .push(Document::new(content, vector));
+            }
+            added += self.add_batch(by_tags).await?;
+        }
+        Ok(added)
+    }
+
+    /// Add a single document to the database.
+    /// Embedding will be generated for the document.
+    /// When adding many documents, it is more efficient to use `add`.
+    ///
+    /// ```rust
+    /// # #[cfg(feature = "embeddings")]
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// # let mut victor = Db::new(DirectoryHandle::default());
+    /// victor.add_single("Pepperoni pizza", vec!["Pizza Flavors"]).await.unwrap();
+    /// # });
+    /// ```
+    pub async fn add_single(
+        &mut self,
+        content: impl Into<String>,
+        tags: Vec<impl Into<String>>,
+    ) -> Result<(), Error> {
+        self.add(vec![content], tags).await
+    }
+
+    /// Add many document/embedding pairs to the database.
+    /// This is useful for adding embeddings that have already been generated.
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// # let mut victor = Db::new(DirectoryHandle::default());
+    /// victor.add_embeddings(vec![("Pepperoni pizza", vec![0.1, 0.2, 0.3])], vec!["Pizza Flavors"]).await.unwrap();
+    /// # })
+    /// ```
+    pub async fn add_embeddings(
+        &mut self,
+        to_add: Vec<(impl Into<String>, Vec<f32>)>,
+        tags: Vec<impl Into<String>>,
+    ) -> Result<(), Error> {
+        let documents = to_add
+            .into_iter()
+            .map(|(content, vector)| Document::new(content, vector))
+            .collect();
+        self.add_documents(documents, tags).await
+    }
+
+    /// Add many id/document/embedding triples to the database, using your own ids.
+    /// The id is returned in the `external_id` field of search results, so they can be correlated with your own
+    /// records.
+    ///
+    /// If a document with the same id already exists, it is updated in place (see [`Victor::update`]) instead of
+    /// being added a second time.
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// # let mut victor = Db::new(DirectoryHandle::default());
+    /// victor
+    ///     .add_embeddings_with_ids(vec![("pizza-1", "Pepperoni pizza", vec![0.1, 0.2, 0.3])], vec!["Pizza Flavors"])
+    ///     .await
+    ///     .unwrap();
+    ///
+    /// let nearest = victor.search_embedding(vec![0.1, 0.2, 0.3], vec!["Pizza Flavors"], 1).await.unwrap();
+    /// assert_eq!(nearest[0].external_id.as_deref(), Some("pizza-1"));
+    /// # })
+    /// ```
+    pub async fn add_embeddings_with_ids(
+        &mut self,
+        to_add: Vec<(impl Into<String>, impl Into<String>, Vec<f32>)>,
+        tags: Vec<impl Into<String>>,
+    ) -> Result<(), Error> {
+        let documents = to_add
+            .into_iter()
+            .map(|(id, content, vector)| Document::new(content, vector).with_id(id))
+            .collect();
+        self.add_documents(documents, tags).await
+    }
+
+    /// Add a matrix of precomputed embeddings, stored row by row in `data`, with one row of `dimension` values per
+    /// entry in `contents`. Returns the number of documents added.
+    ///
+    /// This is the fastest way to add lots of embeddings, say a million rows straight from a model's output
+    /// buffer: rows are added in batches, and as long as nothing needs a copy of each vector, they're encoded into
+    /// db files straight from `data` instead of being copied into a [`Document`] each.
+    /// Deduplication (see [`Victor::with_deduplication`]), the write buffer (see [`Victor::with_write_buffer`]),
+    /// retaining originals and projections all need the copies, so with any of them, rows are added like
+    /// [`Victor::add_documents`] would add them.
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// # let mut victor = Db::new(DirectoryHandle::default());
+    /// let data = [0.1, 0.2, 0.3, 0.3, 0.2, 0.1];
+    /// let added = victor.add_embeddings_from_slice(vec!["Pepperoni pizza", "Pineapple"], &data, 3, vec!["Pizza"]).await.unwrap();
+    /// assert_eq!(added, 2);
+    ///
+    /// let nearest = victor.search_embedding(vec![0.3, 0.2, 0.1], vec!["Pizza"], 1).await.unwrap();
+    /// assert_eq!(nearest[0].content, "Pineapple");
+    /// # })
+    /// ```
+    pub async fn add_embeddings_from_slice(
+        &mut self,
+        contents: Vec<impl Into<String>>,
+        data: &[f32],
+        dimension: usize,
+        tags: Vec<impl Into<String>>,
+    ) -> Result<usize, Error> {
+        if dimension == 0 || data.len() != dimension * contents.len() {
+            return Err(Error::InvalidInput(format!(
+                "expected {} rows of {dimension} values, but there are {} values",
+                contents.len(),
+                data.len()
+            )));
+        }
+        let tags = tags.into_iter().map(Into::into).collect::<Vec<String>>();
+        self.settings().await?.check_dimension(dimension)?;
+        self.check_schema(&[], &tags).await?;
+
+        let mut rows = data.chunks_exact(dimension).zip(contents);
+        let mut added = 0;
+        loop {
+            let batch = rows
+                .by_ref()
+                .take(IMPORT_BATCH_SIZE)
+                .map(|(vector, content)| (vector, content.into()))
+                .collect::<Vec<_>>();
+            if batch.is_empty() {
+                return Ok(added);
+            }
+            added += batch.len();
+
+            // checked for every batch, since a batch can set off an automatic projection
+            let needs_documents = self.write_buffer > 0
+                || !self.hooks.on_insert.is_empty()
+                || self.deduplication != Deduplication::Off
+                || self.retain_originals
+                || self.retains_originals().await
+                || self
+                    .root
+                    .get_file_handle_with_options(
+                        "eigen.bin",
+                        &GetFileHandleOptions { create: false },
+                    )
+                    .await
+                    .is_ok();
+            match needs_documents {
+                true => {
+                    let documents = batch
+                        .into_iter()
+                        .map(|(vector, content)| Document::new(content, vector.to_vec()))
+                        .collect();
+                    self.add_documents(documents, tags.clone()).await?;
+                }
+                false => self.write_rows(batch, &tags).await?,
+            }
+        }
+    }
+
+    /// Add documents with no ids, metadata or extra vectors, encoding their vectors straight into a db file,
+    /// see [`Victor::add_embeddings_from_slice`]. The database mustn't need copies of the vectors.
+    async fn write_rows(
+        &mut self,
+        rows: Vec<(&[f32], String)>,
+        tags: &[String],
+    ) -> Result<(), Error> {
+        self.migrate().await?;
+        self.recover().await?;
+
+        // the first embeddings added decide the database's dimension
+        let dimension = rows[0].0.len();
+        let mut settings = self.settings().await?;
+        if settings.dimension.is_none() {
+            settings.dimension = Some(dimension);
+            self.write_settings(settings).await?;
+        }
+        settings.check_dimension(dimension)?;
+
+        let tag_set = tags.iter().cloned().collect::<BTreeSet<_>>();
+        let (filename, file_handle, is_new) =
+            Index::get_writable_db_file(&mut self.root, tag_set, self.max_segment_size).await?;
+        let mut segment_stats = self.read_segment_stats().await?;
+        if is_new {
+            segment_stats.insert(filename.clone(), SegmentStats::default());
+        }
+
+        // journal the insert first, so it can be rolled back if it's interrupted
+        let ids = rows.iter().map(|_| Uuid::new_v4()).collect::<Vec<_>>();
+        let mut journal = journal::start(&self.root).await?;
+        journal.segment = Some((filename.clone(), file_handle.size().await?));
+        journal.ids = ids.clone();
+        journal::write(&self.root, &journal).await?;
+
+        // normalized vectors need changing, so they're copied into the same buffer one at a time
+        let codec = self.codec().await?;
+        let mut records = Vec::new();
+        let mut normalized = Vec::new();
+        for ((vector, _), &id) in rows.iter().zip(&ids) {
+            let vector = match settings.normalize {
+                true => {
+                    normalized.clear();
+                    normalized.extend_from_slice(vector);
+                    settings.normalize(&mut normalized);
+                    &normalized
+                }
+                false => *vector,
+            };
+            codec.encode_into(id, vector, &mut records)?;
+        }
+        self.append_records(&filename, &ids, records, dimension)
+            .await?;
+
+        let contents = ids
+            .into_iter()
+            .zip(rows)
+            .map(|(id, (_, content))| {
+                let content = Content {
+                    content,
+                    external_id: None,
+                    metadata: Metadata::new(),
+                };
+                (id, content)
+            })
+            .collect();
+        self.write_contents(contents).await?;
+        let now = utils::now_millis();
+        let mut added = added::read(&self.root).await?;
+        added.extend(journal.ids.iter().map(|id| (*id, now)));
+        added::write(&self.root, &added).await?;
+
+        self.write_segment_stats(&segment_stats).await?;
+        journal::remove(&mut self.root).await?;
+        telemetry::inserted(journal.ids.len());
+        self.subscribers.send(Event::Inserted { ids: journal.ids });
+        Ok(())
+    }
+
+    /// Add many [`Document`]s to the database, with their ids and metadata.
+    ///
+    /// Documents with an id that already exists are updated in place (keeping their original tags) instead of
+    /// being added a second time.
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// # let mut victor = Db::new(DirectoryHandle::default());
+    /// use victor_db::Document;
+    ///
+    /// victor
+    ///     .add_documents(
+    ///         vec![Document::new("Pepperoni pizza", vec![0.1, 0.2, 0.3]).with_metadata("author", "alice")],
+    ///         vec!["Pizza Flavors"],
+    ///     )
+    ///     .await
+    ///     .unwrap();
+    /// # })
+    /// ```
+    pub async fn add_documents(
+        &mut self,
+        mut documents: Vec<Document>,
+        tags: Vec<impl Into<String>>,
+    ) -> Result<(), Error> {
+        let tags = tags.into_iter().map(|t| t.into()).collect::<Vec<String>>();
+        self.hooks.inserting(&mut documents, &tags)?;
+        self.check_schema(&documents, &tags).await?;
+        if self.write_buffer == 0 {
+            return self.write_documents(&documents, &tags).await;
+        }
+
+        match self.buffered.back_mut() {
+            Some((last_tags, buffered)) if *last_tags == tags => buffered.extend(documents),
+            _ => self.buffered.push_back((tags, documents)),
+        }
+        let buffered = self
+            .buffered
+            .iter()
+            .map(|(_, documents)| documents.len())
+            .sum::<usize>();
+        if buffered > self.write_buffer {
+            self.flush().await?;
+        }
+        Ok(())
+    }
+
+    /// Check that `documents` can be added with `tags`: that their vectors have the database's dimension, if it has
+    /// one yet, and that its allowed tags (see [`Victor::allowed_tags`]) include every tag. Checked before documents
+    /// are buffered, so they fail where they're added rather than when they're flushed.
+    async fn check_schema(&self, documents: &[Document], tags: &[String]) -> Result<(), Error> {
+        let settings = self.settings().await?;
+        for document in documents {
+            settings.check_dimension(document.vector.len())?;
+            for chunk in &document.chunks {
+                settings.check_dimension(chunk.len())?;
+            }
+        }
+
+        if let Some(allowed) = self.allowed_tags().await? {
+            if let Some(tag) = tags.iter().find(|tag| !allowed.contains(tag)) {
+                return Err(Error::TagNotAllowed { tag: tag.clone() });
+            }
+        }
+        Ok(())
+    }
+
+    async fn write_documents(
+        &mut self,
+        documents: &[Document],
+        tags: &[String],
+    ) -> Result<(), Error> {
+        self.migrate().await?;
+        self.recover().await?;
+
+        // documents without an id get a random one, which can't already exist
+        let ids = documents
+            .iter()
+            .filter_map(|document| document.id.as_deref())
+            .map(Self::uuid_for_external_id)
+            .collect();
+        let existing = self.stored_ids(ids).await?;
+        let mut segment_stats = self.read_segment_stats().await?;
+        let removed = tombstones::removed(&self.root).await?;
+        let mut tombstones = tombstones::read(&self.root).await?;
+        let previous_tombstones = tombstones.clone();
+        let mut chunks = chunks::read(&self.root).await?;
+        let chunk_count = chunks.len();
+        let mut hashes = match self.deduplication {
+            Deduplication::Off => HashMap::new(),
+            _ => self.content_hashes(&removed).await?,
+        };
+        let mut expiry = expiry::read(&self.root).await?;
+        let mut expiry_changed = false;
+        let now = utils::now_millis();
+        let mut readded = Vec::new();
+        let mut replaced = Vec::new();
+        let mut revived = Vec::new();
+        let mut journal = journal::start(&self.root).await?;
+
+        // later duplicates of an id win, like they would if they were added one at a time
+        let mut new_documents: Vec<(Uuid, Content, Vec<f32>)> = Vec::new();
+        // each extra vector is stored as an embedding of its own, as `(id, document id, vector)`
+        let mut new_chunks: Vec<(Uuid, Uuid, Vec<f32>)> = Vec::new();
+        for document in documents {
+            let mut uuid = match &document.id {
+                Some(external_id) => Self::uuid_for_external_id(external_id),
+                None => Uuid::new_v4(),
+            };
+            let mut external_id = document.id.clone();
+            let mut is_stored = existing.contains(&uuid);
+
+            if self.deduplication != Deduplication::Off && !is_stored {
+                match hashes.entry(digest(document.content.as_str())) {
+                    Entry::Vacant(entry) => {
+                        entry.insert((uuid, external_id.clone(), false));
+                    }
+                    Entry::Occupied(_) if self.deduplication == Deduplication::Skip => continue,
+                    // the duplicate takes the place of the document it duplicates
+                    Entry::Occupied(entry) => {
+                        (uuid, external_id, is_stored) = entry.get().clone();
+                    }
+                }
+            }
+
+            // adding a document again replaces its expiry, even if it had one and now doesn't
+            expiry_changed |= match document.ttl {
+                Some(ttl) => {
+                    let expires_at = now.saturating_add(ttl.as_millis() as u64);
+                    expiry.insert(uuid, expires_at) != Some(expires_at)
+                }
+                None => expiry.remove(&uuid).is_some(),
+            };
+
+            let content = Content {
+                content: document.content.clone(),
+                external_id,
+                metadata: document.metadata.clone(),
+            };
+
+            if is_stored {
+                let segment = self
+                    .rewrite_embedding(uuid, document.vector.clone(), &mut journal)
+                    .await?;
+                if let Some(stats) = segment.and_then(|segment| segment_stats.get_mut(&segment)) {
+                    stats.include(&content.metadata);
+                }
+                self.write_contents(vec![(uuid, content)]).await?;
+                replaced.push(uuid);
+                // adding a deleted (or expired) document again brings it back, as if it was just added
+                tombstones.remove(&uuid);
+                if removed.contains(&uuid) {
+                    readded.push(uuid);
+                    revived.push(uuid);
+                }
+                // with only the vectors it's added with now
+                chunks.retain(|chunk, document| {
+                    *document != uuid || {
+                        tombstones.insert(*chunk);
+                        false
+                    }
+                });
+            } else if let Some(new_document) =
+                new_documents.iter_mut().find(|(id, _, _)| *id == uuid)
+            {
+                *new_document = (uuid, content, document.vector.clone());
+            } else {
+                new_documents.push((uuid, content, document.vector.clone()));
+            }
+            new_chunks.retain(|(_, document, _)| *document != uuid);
+            new_chunks.extend(
+                document
+                    .chunks
+                    .iter()
+                    .map(|vector| (Uuid::new_v4(), uuid, vector.clone())),
+            );
+        }
+
+        if tombstones != previous_tombstones {
+            tombstones::write(&self.root, &tombstones).await?;
+        }
+        chunks.extend(
+            new_chunks
+                .iter()
+                .map(|(chunk, document, _)| (*chunk, *document)),
+        );
+        if chunks.len() != chunk_count || !new_chunks.is_empty() {
+            chunks::write(&self.root, &chunks).await?;
+        }
+        if expiry_changed {
+            expiry::write(&self.root, &expiry).await?;
+        }
+
+        if !new_documents.is_empty() || !new_chunks.is_empty() {
+            // stats are only tracked for db files that have had them since they were created,
+            // since older files may hold documents we never saw
+            let tag_set = tags.iter().cloned().collect::<BTreeSet<_>>();
+            let (filename, file_handle, is_new) =
+                Index::get_writable_db_file(&mut self.root, tag_set, self.max_segment_size).await?;
+            if is_new {
+                segment_stats.insert(filename.clone(), SegmentStats::default());
+            }
+            if let Some(stats) = segment_stats.get_mut(&filename) {
+                for (_, content, _) in &new_documents {
+                    stats.include(&content.metadata);
+                }
+            }
+
+            // journal the insert first, so it can be rolled back if it's interrupted
+            journal.segment = Some((filename.clone(), file_handle.size().await?));
+            journal.ids = new_documents
+                .iter()
+                .map(|(id, _, _)| *id)
+                .chain(new_chunks.iter().map(|(id, _, _)| *id))
+                .collect();
+            journal::write(&self.root, &journal).await?;
+
+            readded.extend(new_documents.iter().map(|(id, _, _)| *id));
+            revived.extend(new_documents.iter().map(|(id, _, _)| *id));
+            let (contents, mut embeddings): (Vec<_>, Vec<_>) = new_documents
+                .into_iter()
+                .map(|(id, content, vector)| ((id, content), Embedding { id, vector }))
+                .unzip();
+            embeddings.extend(
+                new_chunks
+                    .into_iter()
+                    .map(|(id, _, vector)| Embedding { id, vector }),
+            );
+
+            self.write_embeddings(embeddings, &filename).await?;
+            self.write_contents(contents).await?;
+        }
+        if !readded.is_empty() {
+            let mut added = added::read(&self.root).await?;
+            added.extend(readded.into_iter().map(|id| (id, now)));
+            added::write(&self.root, &added).await?;
+        }
+        if !replaced.is_empty() {
+            versions::bump(&self.root, replaced.iter().copied()).await?;
+        }
+
+        self.write_segment_stats(&segment_stats).await?;
+        journal::remove(&mut self.root).await?;
+
+        replaced.retain(|id| !revived.contains(id));
+        telemetry::inserted(revived.len());
+        telemetry::updated(replaced.len());
+        if !revived.is_empty() {
+            self.subscribers.send(Event::Inserted { ids: revived });
+        }
+        if !replaced.is_empty() {
+            self.subscribers.send(Event::Updated { ids: replaced });
+        }
+        Ok(())
+    }
+
+    /// Split a long document into chunks with `chunker`, embed each one, and add them as documents of their own,
+    /// returning how many chunks there were.
+    ///
+    /// Chunk `n` gets the id `"{id}#{n}"`, and its parent's id in its `parent_id` metadata, so searches can be
+    /// limited to one document with [`Filter::eq`](crate::Filter::eq). Adding a document with the same `id`
+    /// again replaces its chunks, deleting any it no longer has.
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// use async_trait::async_trait;
+    /// use victor_db::{chunking::Chunker, Embedder, Error, Filter, MetadataValue, SearchOptions};
+    ///
+    /// # struct LengthEmbedder;
+    /// # #[async_trait]
+    /// # impl Embedder for LengthEmbedder {
+    /// #     async fn embed(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>, Error> {
+    /// #         Ok(texts.iter().map(|text| vec![text.len() as f32, 1.0]).collect())
+    /// #     }
+    /// # }
+    /// let mut victor = Db::new(DirectoryHandle::default()).with_embedder(LengthEmbedder);
+    ///
+    /// let chunker = Chunker::Sentences { max_size: 30, overlap: 0 };
+    /// let text = "Pizza is great. Pineapple is a topping. Some people hate it.";
+    /// let chunks = victor.add_document_chunked("pizza-essay", text, &chunker, vec!["Essays"]).await.unwrap();
+    /// assert_eq!(chunks, 3);
+    ///
+    /// let options = SearchOptions::default().with_filter(Filter::eq("parent_id", "pizza-essay"));
+    /// let nearest = victor.search_embedding_with_options(vec![20.0, 1.0], vec!["Essays"], 1, &options).await.unwrap();
+    /// assert_eq!(nearest[0].content, "Some people hate it.");
+    /// # })
+    /// ```
+    pub async fn add_document_chunked(
+        &mut self,
+        id: impl Into<String>,
+        content: &str,
+        chunker: &Chunker,
+        tags: Vec<impl Into<String>>,
+    ) -> Result<usize, Error> {
+        let id = id.into();
+        let chunks = chunker.split(content);
+        let vectors = self.embed(chunks.clone()).await?;
+        let count = chunks.len();
+
+        let documents = chunks
+            .into_iter()
+            .zip(vectors)
+            .enumerate()
+            .map(|(n, (chunk, vector))| {
+                Document::new(chunk, vector)
+                    .with_id(format!("{id}#{n}"))
+                    .with_metadata("parent_id", id.as_str())
+                    .with_metadata("chunk", n as f64)
+            })
+            .collect();
+        self.add_documents(documents, tags).await?;
+
+        // the chunks a longer version of the document had past the end of this one
+        for n in count.. {
+            let uuid = Self::uuid_for_external_id(&format!("{id}#{n}"));
+            if !self.delete(uuid).await? {
+                break;
+            }
+        }
+
+        Ok(count)
+    }
+
+    /// Roll back an insert or [batch](Victor::batch) that was interrupted before it finished (say, because the
+    /// process crashed), returning whether there was one.
+    ///
+    /// This happens automatically before adding documents and when opening a database with
+    /// [`Victor::with_settings`], so it's only needed to make sure searches don't see a partial insert
+    /// after opening a database with [`Victor::new`].
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// # let mut victor = Db::new(DirectoryHandle::default());
+    /// victor.add_single_embedding("Pepperoni pizza", vec![0.1, 0.2, 0.3], vec!["Pizza Flavors"]).await.unwrap();
+    ///
+    /// // every insert finished, so there's nothing to roll back
+    /// assert!(!victor.recover().await.unwrap());
+    /// # })
+    /// ```
+    pub async fn recover(&mut self) -> Result<bool, Error> {
+        let rolled_back = !self.batching && self.roll_back_batch().await?;
+        let Some(journal) = journal::read(&self.root).await? else {
+            return Ok(rolled_back);
+        };
+
+        // the embeddings were appended, so dropping everything after the old end of the file removes them
+        let mut segments = BTreeSet::new();
+        if let Some((segment, size)) = &journal.segment {
+            if self.truncate(segment, *size).await? {
+                // the truncated embeddings may be in the catalog
+                self.catalog = None;
+            }
+            segments.insert(segment.clone());
+        }
+
+        // latest first, so a record rewritten twice ends up the way it was before either
+        for rewritten in journal.rewritten.iter().rev() {
+            let mut file_handle = self
+                .root
+                .get_file_handle_with_options(
+                    &rewritten.segment,
+                    &GetFileHandleOptions { create: false },
+                )
+                .await?;
+            self.write_record(
+                &rewritten.segment,
+                &mut file_handle,
+                rewritten.offset,
+                &rewritten.record,
+            )
+            .await?;
+            segments.insert(rewritten.segment.clone());
+        }
+
+        // the logs were only appended to, so this drops the contents (and keywords and originals) that were written
+        for (log, size) in &journal.logs {
+            self.truncate(log, *size).await?;
+        }
+
+        for segment in &segments {
+            self.sync_indexes(segment, Changed::Unknown).await?;
+        }
+        journal::remove(&mut self.root).await?;
+
+        Ok(true)
+    }
+
+    /// Subscribe to changes to the database, as a stream of [`Event`]s, so whatever is kept in sync with it
+    /// (like caches, a UI, or a replica) can be updated without polling.
+    ///
+    /// Only changes made through this `Victor` are reported, from when it's subscribed to. Events are queued until
+    /// they're read, and dropping the stream unsubscribes.
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// use futures::StreamExt;
+    /// use victor_db::Event;
+    ///
+    /// # let mut victor = Db::new(DirectoryHandle::default());
+    /// let mut events = victor.subscribe();
+    /// victor.add_single_embedding("Pepperoni pizza", vec![0.1, 0.2, 0.3], vec!["Pizza Flavors"]).await.unwrap();
+    ///
+    /// let Some(Event::Inserted { ids }) = events.next().await else { panic!("expected an insert") };
+    /// assert_eq!(ids.len(), 1);
+    /// # })
+    /// ```
+    pub fn subscribe(&mut self) -> impl Stream<Item = Event> + Unpin {
+        self.subscribers.subscribe()
+    }
+
+    /// Stage inserts, updates and deletes to apply all at once with [`Batch::commit`]: either every one of them
+    /// is applied, or, if any fails, none are, so a failed ingestion run doesn't leave half its documents behind.
+    ///
+    /// Every file the batch could change is copied before it's applied (db files that it only appends to are
+    /// just truncated again), so batches that update documents, or add documents with ids, cost a copy of the
+    /// whole database. A batch that's interrupted is rolled back by [`Victor::recover`].
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// use victor_db::Document;
+    ///
+    /// # let mut victor = Db::new(DirectoryHandle::default());
+    /// let mut batch = victor.batch();
+    /// batch.add_documents(vec![Document::new("Pepperoni pizza", vec![0.1, 0.2, 0.3])], vec!["Pizza Flavors"]);
+    /// // this vector has the wrong number of dimensions, so the whole batch fails
+    /// batch.add_documents(vec![Document::new("Cheese pizza", vec![0.1, 0.2])], vec!["Pizza Flavors"]);
+    /// assert!(batch.commit().await.is_err());
+    ///
+    /// let results = victor.search_embedding(vec![0.1, 0.2, 0.3], vec!["Pizza Flavors"], 1).await.unwrap();
+    /// assert!(results.is_empty());
+    /// # })
+    /// ```
+    pub fn batch(&mut self) -> Batch<'_, D> {
+        Batch::new(self)
+    }
+
+    pub(crate) async fn apply_batch(
+        &mut self,
+        mut operations: Vec<Operation>,
+    ) -> Result<(), Error> {
+        self.flush().await?;
+        self.migrate().await?;
+        self.recover().await?;
+        for operation in &mut operations {
+            if let Operation::Add { documents, tags } = operation {
+                self.hooks.inserting(documents, tags)?;
+                self.check_schema(documents, tags).await?;
+            }
+        }
+
+        // duplicates and projections can rewrite db files too
+        let rewrites_in_place = self.deduplication != Deduplication::Off
+            || self.auto_projection.is_some()
+            || operations.iter().any(Operation::rewrites_in_place);
+        self.back_up(rewrites_in_place).await?;
+
+        self.batching = true;
+        self.subscribers.hold();
+        let result = self.apply_operations(operations).await;
+        self.batching = false;
+
+        match result {
+            Ok(()) => {
+                batch::remove(&mut self.root).await?;
+                self.subscribers.release();
+                Ok(())
+            }
+            Err(error) => {
+                self.subscribers.discard();
+                self.roll_back_batch().await?;
+                Err(error)
+            }
+        }
+    }
+
+    async fn apply_operations(&mut self, operations: Vec<Operation>) -> Result<(), Error> {
+        for operation in operations {
+            match operation {
+                Operation::Add { documents, tags } => {
+                    self.write_documents(&documents, &tags).await?
+                }
+                Operation::Update {
+                    id,
+                    content,
+                    vector,
+                } => {
+                    self.update(id, content, vector).await?;
+                }
+                Operation::Delete(id) => {
+                    self.delete(id).await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Record what every file in the database is like before a batch, so it can be rolled back.
+    /// Unless the batch `rewrites_in_place`, db files are only ever appended to, so their sizes are enough.
+    async fn back_up(&mut self, rewrites_in_place: bool) -> Result<(), Error> {
+        let segments = Index::get_all_db_filenames(&mut self.root)
+            .await?
+            .into_iter()
+            .collect::<HashSet<_>>();
+
+        let mut backup = Backup::default();
+        for filename in self.database_filenames().await? {
+            let saved = if segments.contains(&filename) && !rewrites_in_place {
+                let file_handle = self
+                    .root
+                    .get_file_handle_with_options(&filename, &GetFileHandleOptions { create: true })
+                    .await?;
+                Saved::Size(file_handle.size().await?)
+            } else {
+                match self.read_database_file(&filename).await? {
+                    Some(bytes) => Saved::Bytes(bytes),
+                    None => Saved::Missing,
+                }
+            };
+            backup.files.insert(filename, saved);
+        }
+
+        batch::write(&self.root, &backup).await
+    }
+
+    /// Put every file back the way it was before the batch in progress, returning whether there was one.
+    async fn roll_back_batch(&mut self) -> Result<bool, Error> {
+        let Some(backup) = batch::read(&self.root).await? else {
+            return Ok(false);
+        };
+
+        // files the batch created, like new db files and their indexes
+        for filename in self.database_filenames().await? {
+            if !backup.files.contains_key(&filename) {
+                let _ = self.root.remove_entry(&filename).await;
+            }
+        }
+
+        for (filename, saved) in backup.files {
+            match saved {
+                Saved::Missing => {
+                    let _ = self.root.remove_entry(&filename).await;
+                }
+                Saved::Size(size) => {
+                    let mut file_handle = self
+                        .root
+                        .get_file_handle_with_options(
+                            &filename,
+                            &GetFileHandleOptions { create: true },
+                        )
+                        .await?;
+                    if file_handle.size().await? > size {
+                        let kept = file_handle.read_range(0, size).await?;
+                        let mut writable = file_handle
+                            .create_writable_with_options(&CreateWritableOptions {
+                                keep_existing_data: false,
+                            })
+                            .await?;
+                        writable.write_at_cursor_pos(kept).await?;
+                        writable.close().await?;
+                    }
+                }
+                Saved::Bytes(bytes) => self.write_database_file(&filename, bytes).await?,
+            }
+        }
+
+        // the checksums were put back with everything else, and the catalog may be of the batch's db files
+        self.catalog = None;
+        journal::remove(&mut self.root).await?;
+        batch::remove(&mut self.root).await?;
+
+        Ok(true)
+    }
+
+    /// Check the database for damage, like files that were truncated or changed since they were written,
+    /// embeddings without content (or content without embeddings), db files missing from the directory,
+    /// and vectors with the wrong number of dimensions.
+    ///
+    /// Unlike searching a damaged database, which fails at the first problem, this looks at everything it can and
+    /// reports every problem it finds. It doesn't change anything: see [`Victor::repair`] for fixing them.
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// # let mut victor = Db::new(DirectoryHandle::default());
+    /// victor.add_single_embedding("Pepperoni pizza", vec![0.1, 0.2, 0.3], vec!["Pizza Flavors"]).await.unwrap();
+    ///
+    /// let report = victor.verify_integrity().await.unwrap();
+    /// assert!(report.is_ok());
+    /// assert_eq!(report.embeddings, 1);
+    /// # })
+    /// ```
+    pub async fn verify_integrity(&self) -> Result<IntegrityReport, Error> {
+        format::check(&self.root).await?;
+
+        let mut report = IntegrityReport::default();
+        let problems = &mut report.problems;
+        let settings = self.settings().await?;
+        let codec = self.codec().await?;
+
+        let mut index = match Index::read_files(&self.root).await {
+            Ok((_, index)) => index,
+            Err(error) => {
+                problems.push(Self::unreadable("index.bin", error));
+                Index::default()
+            }
+        };
+        index.combined = Index::read_combined(&self.root)
+            .await
+            .unwrap_or_else(|error| {
+                problems.push(Self::unreadable("combined.bin", error));
+                Vec::new()
+            });
+
+        // stored vectors are projected, if the database is
+        let mut dimension = settings.dimension;
+        let is_projected = self
+            .root
+            .get_file_handle_with_options("eigen.bin", &GetFileHandleOptions { create: false })
+            .await
+            .is_ok();
+        if is_projected {
+            match self.read_projection().await {
+                Ok(projection) => {
+                    if let Some(expected) = settings.dimension {
+                        if projection.means.len() != expected {
+                            problems.push(Problem::DimensionMismatch {
+                                file: "eigen.bin".to_string(),
+                                expected,
+                                found: projection.means.len(),
+                            });
+                        }
+                    }
+                    dimension = Some(projection.eigen.ncols());
+                }
+                Err(error) => problems.push(Self::unreadable("eigen.bin", error)),
+            }
+        }
+
+        let mut filenames = index.filenames();
+        filenames.sort();
+
+        let mut ids = HashMap::new();
+        for filename in filenames {
+            let Ok(file_handle) = self
+                .root
+                .get_file_handle_with_options(&filename, &GetFileHandleOptions { create: false })
+                .await
+            else {
+                problems.push(Problem::MissingFile { file: filename });
+                continue;
+            };
+            report.db_files += 1;
+
+            let file = file_handle.read().await?;
+            if checksum::verify(&self.root, &filename, &file)
+                .await
+                .is_err()
+            {
+                problems.push(Problem::ChecksumMismatch {
+                    file: filename.clone(),
+                });
+            }
+            let file = match settings.compression.decompress(&filename, file) {
+                Ok(file) => file,
+                Err(error) => {
+                    problems.push(Self::unreadable(&filename, error));
+                    continue;
+                }
+            };
+            if file.is_empty() {
+                continue;
+            }
+
+            let header_size = std::mem::size_of::<u32>();
+            let record_size = match Self::get_embedding_size(&filename, &file) {
+                Ok(0) => {
+                    problems.push(Problem::Unreadable {
+                        file: filename,
+                        reason: "the header says embeddings are 0 bytes".to_string(),
+                    });
+                    continue;
+                }
+                Ok(record_size) => record_size as usize,
+                Err(error) => {
+                    problems.push(Self::unreadable(&filename, error));
+                    continue;
+                }
+            };
+            let records = file[header_size..].chunks_exact(record_size);
+            if !records.remainder().is_empty() {
+                problems.push(Problem::PartialRecord {
+                    file: filename.clone(),
+                    record_size,
+                    extra_bytes: records.remainder().len(),
+                });
+            }
+
+            if let Some(combined) = index.combined(&filename) {
+                if combined.len() != records.len() {
+                    problems.push(Problem::TagMapMismatch {
+                        file: filename.clone(),
+                        expected: combined.len(),
+                        found: records.len(),
+                    });
+                }
+            }
+
+            let mut mismatched = None;
+            for (record, bytes) in records.enumerate() {
+                report.embeddings += 1;
+                let Ok(embedding) = codec.decode(&filename, bytes) else {
+                    problems.push(Problem::UndecodableRecord {
+                        file: filename.clone(),
+                        record,
+                    });
+                    continue;
+                };
+                match dimension {
+                    Some(expected) if embedding.vector.len() != expected => {
+                        mismatched.get_or_insert((expected, embedding.vector.len()));
+                    }
+                    _ => {}
+                }
+                ids.insert(embedding.id, filename.clone());
+            }
+            if let Some((expected, found)) = mismatched {
+                problems.push(Problem::DimensionMismatch {
+                    file: filename,
+                    expected,
+                    found,
+                });
+            }
+        }
+
+        let contents = self.read_contents().await.unwrap_or_else(|error| {
+            problems.push(Self::unreadable("content.bin", error));
+            HashMap::new()
+        });
+        let tombstones = tombstones::removed(&self.root)
+            .await
+            .unwrap_or_else(|error| {
+                problems.push(Self::unreadable("tombstones.bin", error));
+                HashSet::new()
+            });
+        let chunks = chunks::read(&self.root).await.unwrap_or_else(|error| {
+            problems.push(Self::unreadable("chunks.bin", error));
+            HashMap::new()
+        });
+
+        // a document's extra vectors share its content, and deleted documents don't need any
+        // (sorted, so reports can be compared)
+        let mut missing = ids
+            .iter()
+            .filter(|(id, _)| {
+                !contents.contains_key(id) && !tombstones.contains(id) && !chunks.contains_key(id)
+            })
+            .collect::<Vec<_>>();
+        missing.sort();
+        problems.extend(
+            missing
+                .into_iter()
+                .map(|(&id, file)| Problem::MissingContent {
+                    file: file.clone(),
+                    id,
+                }),
+        );
+        let mut orphaned = contents
+            .keys()
+            .filter(|id| !ids.contains_key(id) && !tombstones.contains(id))
+            .collect::<Vec<_>>();
+        orphaned.sort();
+        problems.extend(
+            orphaned
+                .into_iter()
+                .map(|&id| Problem::OrphanedContent { id }),
+        );
+
+        Ok(report)
+    }
+
+    /// Repair a damaged database (see [`Victor::verify_integrity`]) by throwing away whatever can't be read,
+    /// so the rest of it can be used again, say after the browser crashed while writing to it.
+    ///
+    /// This cuts partial embeddings off the end of db files and drops embeddings that can't be decoded,
+    /// embeddings without content, and content without embeddings. What's left of `content.bin` is kept up to
+    /// the first entry that can't be read. Checksums, the catalog and any HNSW or LSH indexes are rebuilt
+    /// for what's left. Embeddings that still decode are kept even if their file didn't match its checksum.
+    ///
+    /// Interrupted inserts are rolled back first (see [`Victor::recover`]), and the database is upgraded to the
+    /// current format (see [`Victor::migrate`]). The report lists exactly what was thrown away.
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// # let mut victor = Db::new(DirectoryHandle::default());
+    /// victor.add_single_embedding("Pepperoni pizza", vec![0.1, 0.2, 0.3], vec!["Pizza Flavors"]).await.unwrap();
+    ///
+    /// // there's nothing to repair
+    /// let report = victor.repair().await.unwrap();
+    /// assert!(report.found.is_ok());
+    /// assert!(report.discarded.is_empty());
+    /// # })
+    /// ```
+    pub async fn repair(&mut self) -> Result<RepairReport, Error> {
+        self.recover().await?;
+        self.flush().await?;
+        self.migrate().await?;
+
+        let found = self.verify_integrity().await?;
+        let mut discarded = Vec::new();
+        let settings = self.settings().await?;
+        let codec = self.codec().await?;
+
+        let (mut contents, extra_bytes, contents_intact) = self.salvage_contents().await?;
+        if extra_bytes > 0 {
+            discarded.push(Discarded::Bytes {
+                file: "content.bin".to_string(),
+                count: extra_bytes,
+            });
+        }
+        let tombstones = tombstones::removed(&self.root).await?;
+        let chunks = chunks::read(&self.root).await?;
+
+        // stored vectors are projected, if the database is
+        let is_projected = self
+            .root
+            .get_file_handle_with_options("eigen.bin", &GetFileHandleOptions { create: false })
+            .await
+            .is_ok();
+        let dimension = match is_projected {
+            true => Some(self.read_projection().await?.eigen.ncols()),
+            false => settings.dimension,
+        };
+
+        let (_, mut index) = Index::load(&self.root).await?;
+        let mut filenames = index.filenames();
+        filenames.sort();
+
+        let header_size = std::mem::size_of::<u32>();
+        let mut ids = HashSet::new();
+        for filename in filenames {
+            // embeddings past the end of a combined file's tag map have no tags
+            let tag_map_len = index.combined(&filename).map(Combined::len);
+            // missing db files are recreated empty
+            let mut file_handle = self
+                .root
+                .get_file_handle_with_options(&filename, &GetFileHandleOptions { create: true })
+                .await?;
+            let file = file_handle.read().await?;
+            let size = file.len();
+            let mut changed = checksum::verify(&self.root, &filename, &file)
+                .await
+                .is_err();
+
+            let mut record_size = 0;
+            let mut kept = Vec::new();
+            let mut kept_positions = HashSet::new();
+            match settings.compression.decompress(&filename, file) {
+                Ok(file) if file.is_empty() => {}
+                Ok(file) => match Self::get_embedding_size(&filename, &file) {
+                    Ok(size) if size > 0 => {
+                        record_size = size as usize;
+                        let records = file[header_size..].chunks_exact(record_size);
+                        if !records.remainder().is_empty() {
+                            discarded.push(Discarded::Bytes {
+                                file: filename.clone(),
+                                count: records.remainder().len(),
+                            });
+                            changed = true;
+                        }
+
+                        for (record, bytes) in records.enumerate() {
+                            if tag_map_len.is_some_and(|len| record >= len) {
+                                discarded.push(Discarded::Record {
+                                    file: filename.clone(),
+                                    record,
+                                });
+                                changed = true;
+                                continue;
+                            }
+                            let embedding = match codec.decode(&filename, bytes) {
+                                Ok(embedding)
+                                    if dimension.is_none_or(|dimension| {
+                                        embedding.vector.len() == dimension
+                                    }) =>
+                                {
+                                    embedding
+                                }
+                                _ => {
+                                    discarded.push(Discarded::Record {
+                                        file: filename.clone(),
+                                        record,
+                                    });
+                                    changed = true;
+                                    continue;
+                                }
+                            };
+
+                            // a document's extra vectors share its content, and deleted documents don't need any
+                            let id = embedding.id;
+                            if !contents.contains_key(&id)
+                                && !tombstones.contains(&id)
+                                && !chunks.contains_key(&id)
+                            {
+                                discarded.push(Discarded::Embedding {
+                                    file: filename.clone(),
+                                    id,
+                                });
+                                changed = true;
+                                continue;
+                            }
+                            ids.insert(id);
+                            kept.push(bytes.to_vec());
+                            kept_positions.insert(record);
+                        }
+                    }
+                    _ => {
+                        discarded.push(Discarded::File {
+                            file: filename.clone(),
+                            size,
+                        });
+                        changed = true;
+                    }
+                },
+                Err(_) => {
+                    discarded.push(Discarded::File {
+                        file: filename.clone(),
+                        size,
+                    });
+                    changed = true;
+                }
+            }
+
+            if changed {
+                let mut stored = Vec::new();
+                if !kept.is_empty() {
+                    let mut combined = bincode::serialize(&(record_size as u32))
+                        .expect("Failed to serialize size");
+                    combined.extend(kept.concat());
+                    stored = settings.compression.compress(&combined)?;
+                }
+
+                let mut writable = file_handle
+                    .create_writable_with_options(&CreateWritableOptions {
+                        keep_existing_data: false,
+                    })
+                    .await?;
+                writable.write_at_cursor_pos(stored.clone()).await?;
+                writable.close().await?;
+                checksum::record(&self.root, &filename, &stored).await?;
+            }
+            if let Some(combined) = index.combined_mut(&filename) {
+                combined.retain(|position| kept_positions.contains(&position));
+            }
+        }
+        if !index.combined.is_empty() {
+            index.write_combined(&self.root).await?;
+        }
+
+        let mut orphaned = contents
+            .keys()
+            .filter(|id| !ids.contains(id) && !tombstones.contains(id))
+            .copied()
+            .collect::<Vec<_>>();
+        orphaned.sort();
+        for id in &orphaned {
+            contents.remove(id);
+            discarded.push(Discarded::Content { id: *id });
+        }
+        if !contents_intact || !orphaned.is_empty() {
+            self.write_all_contents(&contents).await?;
+        }
+
+        // the catalog and indexes may refer to embeddings that were dropped
+        self.catalog = None;
+        self.rebuild_indexes().await?;
+
+        Ok(RepairReport { found, discarded })
+    }
+
+    /// Read as much of `content.bin` as can be, for [`Victor::repair`]: every entry up to the first one that can't
+    /// be read, along with how many bytes were left after that, and whether the file was intact.
+    async fn salvage_contents(&self) -> Result<(HashMap<Uuid, Content>, usize, bool), Error> {
+        let content_file_handle = self
+            .root
+            .get_file_handle_with_options("content.bin", &GetFileHandleOptions { create: true })
+            .await?;
+
+        let existing_content = content_file_handle.read().await?;
+        let size = existing_content.len();
+        let intact = checksum::verify(&self.root, "content.bin", &existing_content)
+            .await
+            .is_ok();
+        let Ok(existing_content) = self
+            .settings()
+            .await?
+            .compression
+            .decompress("content.bin", existing_content)
+        else {
+            return Ok((HashMap::new(), size, false));
+        };
+
+        let mut hashmap = HashMap::new();
+        let mut rest = &existing_content[..];
+        while !rest.is_empty() {
+            let left = rest.len();
+            match bincode::deserialize_from::<_, (Uuid, Content)>(&mut rest) {
+                Ok((id, content)) => hashmap.insert(id, content),
+                Err(_) => return Ok((hashmap, left, false)),
+            };
+        }
+
+        Ok((hashmap, 0, intact))
+    }
+
+    /// Rebuild `index.bin` from the db files in the database directory, for when it's been lost or corrupted
+    /// and the documents in the db files can't be found anymore.
+    ///
+    /// Db files are named after a hash of their tags, so the tags of a db file can only be recovered if they're
+    /// one of the `known_tag_sets`, or still in the index (if it can be read). The documents in every other db
+    /// file are kept under a new tag set, `["recovered:<hash>"]`, where searches that don't filter by tag still
+    /// find them. Returns those new tag sets, sorted.
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// # let mut victor = Db::new(DirectoryHandle::default());
+    /// victor.add_single_embedding("Pepperoni pizza", vec![0.1, 0.2, 0.3], vec!["Pizza Flavors"]).await.unwrap();
+    /// victor.add_single_embedding("Pineapple", vec![0.3, 0.2, 0.1], vec!["Pizza Toppings"]).await.unwrap();
+    ///
+    /// // the index is intact, so the tags of every db file are still known
+    /// let recovered = victor.rebuild_index(vec![vec!["Pizza Flavors"]]).await.unwrap();
+    /// assert_eq!(recovered.len(), 0);
+    /// # })
+    /// ```
+    pub async fn rebuild_index(
+        &mut self,
+        known_tag_sets: impl IntoIterator<Item = Vec<impl Into<String>>>,
+    ) -> Result<Vec<Vec<String>>, Error> {
+        format::check(&self.root).await?;
+
+        let existing = Index::load(&self.root)
+            .await
+            .map(|(_, index)| index.files)
+            .unwrap_or_default();
+        let known = known_tag_sets
+            .into_iter()
+            .map(|tags| tags.into_iter().map(Into::into).collect::<BTreeSet<_>>())
+            .chain(existing)
+            .map(|tags| (Index::hash_tags(tags.clone()), tags))
+            .collect::<HashMap<_, _>>();
+
+        // how many parts there are of each tag set's db files
+        let mut parts = BTreeMap::new();
+        for filename in self.root.list_entries().await? {
+            if let Some((hash, part)) = Index::parse_filename(&filename) {
+                let count = parts.entry(hash.to_string()).or_insert(0);
+                *count = (part + 1).max(*count);
+            }
+        }
+
+        // combined db files keep their tags in combined.bin, so only the ones missing from it are recovered
+        let mut index = Index::default();
+        let mut recovered = Vec::new();
+        let listed = Index::read_combined(&self.root).await.unwrap_or_default();
+        for filename in self.root.list_entries().await? {
+            if !Combined::is_combined(&filename) {
+                continue;
+            }
+            let combined = match listed.iter().find(|combined| combined.filename == filename) {
+                Some(combined) => combined.clone(),
+                None => {
+                    let file_handle = self
+                        .root
+                        .get_file_handle_with_options(
+                            &filename,
+                            &GetFileHandleOptions { create: false },
+                        )
+                        .await?;
+                    let file = self.read_segment(&filename, &file_handle).await?;
+                    let len = Self::get_records_by_file(&filename, &file)?.len();
+                    let name = filename.trim_end_matches(".bin");
+                    let tags = BTreeSet::from([format!("recovered:{name}")]);
+                    recovered.push(tags.iter().cloned().collect());
+                    Combined {
+                        filename,
+                        tag_map: vec![(tags, len)],
+                    }
+                }
+            };
+            for (tags, _) in &combined.tag_map {
+                if index.files.insert(tags.clone()) {
+                    index.parts.insert(tags.clone(), 0);
+                }
+            }
+            index.combined.push(combined);
+        }
+
+        for (hash, count) in parts {
+            let tags = match known.get(&hash) {
+                Some(tags) => tags.clone(),
+                None => {
+                    // move the files to where the index will look for them
+                    let tags = BTreeSet::from([format!("recovered:{hash}")]);
+                    for part in 0..count {
+                        let from = Index::filename_for_hash(&hash, part);
+                        let to = Index::filename_for_part(tags.clone(), part);
+                        self.rename_segment(&from, &to).await?;
+                    }
+                    recovered.push(tags.iter().cloned().collect());
+                    tags
+                }
+            };
+            index.files.insert(tags.clone());
+            match count {
+                1 => index.parts.remove(&tags),
+                count => index.parts.insert(tags, count),
+            };
+        }
+
+        let mut index_file = self
+            .root
+            .get_file_handle_with_options("index.bin", &GetFileHandleOptions { create: true })
+            .await?;
+        index.write(&self.root, &mut index_file).await?;
+        index.write_combined(&self.root).await?;
+
+        self.catalog = None;
+        self.rebuild_indexes().await?;
+
+        recovered.sort();
+        Ok(recovered)
+    }
+
+    /// Move the db file `from` to `to`, dropping its HNSW graph, LSH signatures and bloom filter,
+    /// which are rebuilt after.
+    async fn rename_segment(&mut self, from: &str, to: &str) -> Result<(), Error> {
+        // parts may be missing, if they were lost too
+        let Ok(file_handle) = self
+            .root
+            .get_file_handle_with_options(from, &GetFileHandleOptions { create: false })
+            .await
+        else {
+            return Ok(());
+        };
+        let file = file_handle.read().await?;
+
+        let mut file_handle = self
+            .root
+            .get_file_handle_with_options(to, &GetFileHandleOptions { create: true })
+            .await?;
+        let mut writable = file_handle
+            .create_writable_with_options(&CreateWritableOptions {
+                keep_existing_data: false,
+            })
+            .await?;
+        writable.write_at_cursor_pos(file).await?;
+        writable.close().await?;
+        checksum::rename(&self.root, from, to).await?;
+
+        self.root.remove_entry(from).await?;
+        let _ = self
+            .root
+            .remove_entry(&hnsw::filename_for_segment(from))
+            .await;
+        let _ = self
+            .root
+            .remove_entry(&lsh::filename_for_segment(from))
+            .await;
+        let _ = self
+            .root
+            .remove_entry(&bloom::filename_for_segment(from))
+            .await;
+        Ok(())
+    }
+
+    /// A [`Problem::Unreadable`] for `file`, which failed to read with `error`.
+    fn unreadable(file: &str, error: Error) -> Problem {
+        Problem::Unreadable {
+            file: file.to_string(),
+            reason: error.to_string(),
+        }
+    }
+
+    /// Upgrade a database written by an older version of victor to the current on-disk format,
+    /// returning whether it needed upgrading.
+    ///
+    /// This happens automatically before adding documents and when opening a database with
+    /// [`Victor::with_settings`]. Databases written by a newer version of victor can't be read,
+    /// and return [`Error::UnsupportedVersion`].
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// # let mut victor = Db::new(DirectoryHandle::default());
+    /// victor.add_single_embedding("Pepperoni pizza", vec![0.1, 0.2, 0.3], vec!["Pizza Flavors"]).await.unwrap();
+    ///
+    /// // the database was created with the current format
+    /// assert!(!victor.migrate().await.unwrap());
+    /// # })
+    /// ```
+    pub async fn migrate(&mut self) -> Result<bool, Error> {
+        let from = match format::read(&self.root).await? {
+            Some(version) => version,
+            // new databases start out with the current format
+            None if Index::get_all_db_filenames(&mut self.root)
+                .await?
+                .is_empty()
+                && !self.has_settings().await? =>
+            {
+                format::write(&self.root, format::VERSION).await?;
+                return Ok(false);
+            }
+            // databases written before the format was versioned
+            None => 0,
+        };
+        if from > format::VERSION {
+            return Err(Error::UnsupportedVersion {
+                found: from,
+                supported: format::VERSION,
+            });
+        }
+
+        for version in from..format::VERSION {
+            match version {
+                0 => self.migrate_from_v0().await?,
+                1 => self.migrate_from_v1().await?,
+                // only new databases have tombstones
+                2 => {}
+                3 => self.migrate_from_v3().await?,
+                4 => self.migrate_from_v4().await?,
+                5 => self.migrate_from_v5().await?,
+                // only new databases have chunks
+                6 => {}
+                // or combined db files
+                7 => {}
+                // or allowed tags
+                8 => {}
+                9 => self.migrate_from_v9().await?,
+                _ => unreachable!("no migration from format version {version}"),
+            }
+            // record each step, so an interrupted migration picks up where it left off
+            format::write(&self.root, version + 1).await?;
+        }
+
+        Ok(from < format::VERSION)
+    }
+
+    /// Record the checksums that databases written before format version 1 may be missing.
+    async fn migrate_from_v0(&mut self) -> Result<(), Error> {
+        let mut filenames = Index::get_all_db_filenames(&mut self.root).await?;
+        filenames.extend(["index.bin", "content.bin", "eigen.bin"].map(String::from));
+        for filename in filenames {
+            let Ok(file_handle) = self
+                .root
+                .get_file_handle_with_options(&filename, &GetFileHandleOptions { create: false })
+                .await
+            else {
+                continue;
+            };
+            // files that already have a checksum must still match it, rather than having their corruption recorded
+            let file = file_handle.read().await?;
+            checksum::verify(&self.root, &filename, &file).await?;
+            checksum::record(&self.root, &filename, &file).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Record the settings, which databases written before format version 2 may be missing,
+    /// or have recorded without their compression.
+    async fn migrate_from_v1(&mut self) -> Result<(), Error> {
+        // these databases were implicitly using the inferred settings
+        let settings = self.settings().await?;
+        self.write_settings(settings).await
+    }
+
+    /// Rewrite `settings.bin`, which didn't record whether vectors are normalized before format version 10.
+    async fn migrate_from_v9(&mut self) -> Result<(), Error> {
+        if self.has_settings().await? {
+            let settings = self.settings().await?;
+            self.write_settings(settings).await?;
+        }
+        Ok(())
+    }
+
+    /// Rewrite `index.bin`, which didn't track how many db files each tag set has before format version 4.
+    async fn migrate_from_v3(&mut self) -> Result<(), Error> {
+        let (mut file_handle, index) = Index::load(&self.root).await?;
+        index.write(&self.root, &mut file_handle).await
+    }
+
+    /// Rewrite `content.bin`, which was a single map rather than a log before format version 5.
+    async fn migrate_from_v4(&mut self) -> Result<(), Error> {
+        let contents = self.read_contents().await?;
+        self.write_all_contents(&contents).await
+    }
+
+    /// Rewrite `eigen.bin`, which didn't record the explained variance before format version 6.
+    async fn migrate_from_v5(&mut self) -> Result<(), Error> {
+        let is_projected: bool = self
+            .root
+            .get_file_handle_with_options("eigen.bin", &GetFileHandleOptions { create: false })
+            .await
+            .is_ok();
+        if is_projected {
+            let vector_projection = self.read_projection().await?;
+            self.write_projection(vector_projection).await?;
+        }
+        Ok(())
+    }
+
+    /// Add a single document/embedding pair to the database.
+    /// This is useful for adding embeddings that have already been generated.
+    /// When adding many documents, it is more efficient to use `add_embeddings`.
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// # let mut victor = Db::new(DirectoryHandle::default());
+    /// victor.add_single_embedding("Pepperoni pizza", vec![0.1, 0.2, 0.3], vec!["Pizza Flavors"]).await.unwrap();
+    /// # })
+    /// ```
+    pub async fn add_single_embedding(
+        &mut self,
+        content: impl Into<String>,
+        vector: Vec<f32>,
+        tags: Vec<impl Into<String>>,
+    ) -> Result<(), Error> {
+        self.add_embeddings(vec![(content, vector)], tags).await
+    }
+
+    /// Update the content and embedding of an existing document, keeping its id and tags.
+    /// The stored vector is rewritten in place, so searches will no longer return the old version.
+    /// Returns `false` if no document with the given id exists.
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// # let mut victor = Db::new(DirectoryHandle::default());
+    /// victor.add_single_embedding("Pepperoni pizza", vec![0.1, 0.2, 0.3], vec!["Pizza Flavors"]).await.unwrap();
+    /// let id = victor.search_embedding(vec![0.1, 0.2, 0.3], vec!["Pizza Flavors"], 1).await.unwrap()[0].embedding.id;
+    ///
+    /// let updated = victor.update(id, "Vegan pepperoni pizza", vec![0.3, 0.2, 0.1]).await.unwrap();
+    /// assert!(updated);
+    /// # })
+    /// ```
+    pub async fn update(
+        &mut self,
+        id: Uuid,
+        content: impl Into<String>,
+        vector: Vec<f32>,
+    ) -> Result<bool, Error> {
+        self.flush().await?;
+        self.migrate().await?;
+        if tombstones::removed(&self.root).await?.contains(&id) {
+            return Ok(false);
+        }
+        let mut journal = journal::start(&self.root).await?;
+        if self
+            .rewrite_embedding(id, vector, &mut journal)
+            .await?
+            .is_none()
+        {
+            return Ok(false);
+        }
+
+        let mut stored = self.get_content(id).await?;
+        stored.content = content.into();
+        self.write_contents(vec![(id, stored)]).await?;
+        versions::bump(&self.root, [id]).await?;
+        journal::remove(&mut self.root).await?;
+        telemetry::updated(1);
+        self.subscribers.send(Event::Updated { ids: vec![id] });
+
+        Ok(true)
+    }
+
+    /// Delete a document, returning `false` if no document with the given id exists.
+    ///
+    /// Deleted documents are left out of searches right away, but they're only marked as deleted,
+    /// so they keep taking up space until [`Victor::compact`] is called.
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// # let mut victor = Db::new(DirectoryHandle::default());
+    /// victor.add_single_embedding("Pepperoni pizza", vec![0.1, 0.2, 0.3], vec!["Pizza Flavors"]).await.unwrap();
+    /// let id = victor.search_embedding(vec![0.1, 0.2, 0.3], vec!["Pizza Flavors"], 1).await.unwrap()[0].embedding.id;
+    ///
+    /// assert!(victor.delete(id).await.unwrap());
+    /// assert!(victor.search_embedding(vec![0.1, 0.2, 0.3], vec!["Pizza Flavors"], 1).await.unwrap().is_empty());
+    /// # })
+    /// ```
+    pub async fn delete(&mut self, id: Uuid) -> Result<bool, Error> {
+        self.flush().await?;
+        self.migrate().await?;
+        if !self.stored_ids(HashSet::from([id])).await?.contains(&id) {
+            return Ok(false);
+        }
+
+        // documents that expired are already gone
+        if tombstones::removed(&self.root).await?.contains(&id) {
+            return Ok(false);
+        }
+        let mut tombstones = tombstones::read(&self.root).await?;
+        tombstones.insert(id);
+        self.hooks.deleting(id)?;
+        tombstones::write(&self.root, &tombstones).await?;
+
+        keywords::append_changes(&self.root, &[keywords::Change::Remove(id)]).await?;
+        telemetry::deleted(1);
+        self.subscribers.send(Event::Deleted { ids: vec![id] });
+
+        Ok(true)
+    }
+
+    /// The version of a document, or `None` if no document with the given id exists (or it's deleted).
+    ///
+    /// Documents start at version 1, and every update (with [`Victor::update`], or by adding a document with the
+    /// same id again) moves them to the next one, so a document that's changed since its version was read can be
+    /// told apart, see [`Victor::update_if_version`].
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// # let mut victor = Db::new(DirectoryHandle::default());
+    /// victor.add_single_embedding("Pepperoni pizza", vec![0.1, 0.2, 0.3], vec!["Pizza Flavors"]).await.unwrap();
+    /// let id = victor.search_embedding(vec![0.1, 0.2, 0.3], vec!["Pizza Flavors"], 1).await.unwrap()[0].embedding.id;
+    /// assert_eq!(victor.version(id).await.unwrap(), Some(1));
+    ///
+    /// victor.update(id, "Vegan pepperoni pizza", vec![0.3, 0.2, 0.1]).await.unwrap();
+    /// assert_eq!(victor.version(id).await.unwrap(), Some(2));
+    /// # })
+    /// ```
+    pub async fn version(&self, id: Uuid) -> Result<Option<u64>, Error> {
+        if tombstones::removed(&self.root).await?.contains(&id)
+            || !self.stored_ids(HashSet::from([id])).await?.contains(&id)
+        {
+            return Ok(None);
+        }
+        // only documents that have changed since they were added have a recorded version
+        Ok(Some(
+            versions::read(&self.root)
+                .await?
+                .get(&id)
+                .copied()
+                .unwrap_or(1),
+        ))
+    }
+
+    /// Update a document like [`Victor::update`], but only if it's still at `version` (see [`Victor::version`]),
+    /// returning its new version. Otherwise, nothing changes, and [`Error::VersionConflict`] is returned,
+    /// so writers sharing a database (say, in several tabs) don't overwrite each other's changes unawares.
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// use victor_db::Error;
+    ///
+    /// # let mut victor = Db::new(DirectoryHandle::default());
+    /// victor.add_single_embedding("Pepperoni pizza", vec![0.1, 0.2, 0.3], vec!["Pizza Flavors"]).await.unwrap();
+    /// let id = victor.search_embedding(vec![0.1, 0.2, 0.3], vec!["Pizza Flavors"], 1).await.unwrap()[0].embedding.id;
+    ///
+    /// let version = victor.update_if_version(id, 1, "Vegan pepperoni pizza", vec![0.3, 0.2, 0.1]).await.unwrap();
+    /// assert_eq!(version, 2);
+    ///
+    /// // this update was based on the document as it was before the last one
+    /// let conflict = victor.update_if_version(id, 1, "Spicy pepperoni pizza", vec![0.1, 0.2, 0.3]).await;
+    /// assert!(matches!(conflict, Err(Error::VersionConflict { found: Some(2), .. })));
+    /// # })
+    /// ```
+    pub async fn update_if_version(
+        &mut self,
+        id: Uuid,
+        version: u64,
+        content: impl Into<String>,
+        vector: Vec<f32>,
+    ) -> Result<u64, Error> {
+        self.check_version(id, version).await?;
+        self.update(id, content, vector).await?;
+        Ok(version + 1)
+    }
+
+    /// Delete a document like [`Victor::delete`], but only if it's still at `version` (see [`Victor::version`]).
+    /// Otherwise, nothing changes, and [`Error::VersionConflict`] is returned.
+    pub async fn delete_if_version(&mut self, id: Uuid, version: u64) -> Result<(), Error> {
+        self.check_version(id, version).await?;
+        self.delete(id).await?;
+        Ok(())
+    }
+
+    async fn check_version(&mut self, id: Uuid, version: u64) -> Result<(), Error> {
+        self.flush().await?;
+        self.migrate().await?;
+        let found = self.version(id).await?;
+        if found != Some(version) {
+            return Err(Error::VersionConflict {
+                id,
+                expected: version,
+                found,
+            });
+        }
+        Ok(())
+    }
+
+    /// Rewrite db files without the embeddings of deleted (or expired) documents, and drop content that no embedding
+    /// refers to,
+    /// returning the number of bytes reclaimed.
+    ///
+    /// Embeddings are identified by their position in their db file, so the approximate indexes of rewritten files
+    /// are rebuilt.
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// # let mut victor = Db::new(DirectoryHandle::default());
+    /// victor.add_single_embedding("Pepperoni pizza", vec![0.1, 0.2, 0.3], vec!["Pizza Flavors"]).await.unwrap();
+    /// let id = victor.search_embedding(vec![0.1, 0.2, 0.3], vec!["Pizza Flavors"], 1).await.unwrap()[0].embedding.id;
+    /// victor.delete(id).await.unwrap();
+    ///
+    /// assert!(victor.compact().await.unwrap() > 0);
+    /// // there's nothing left to reclaim
+    /// assert_eq!(victor.compact().await.unwrap(), 0);
+    /// # })
+    /// ```
+    pub async fn compact(&mut self) -> Result<u64, Error> {
+        self.flush().await?;
+        self.migrate().await?;
+        self.recover().await?;
+
+        let tombstones = tombstones::removed(&self.root).await?;
+        let codec = self.codec().await?;
+        let mut reclaimed = 0;
+
+        let (_, mut index) = Index::load(&self.root).await?;
+        let mut live = HashSet::new();
+        for (filename, mut file_handle) in
+            index.open_files(&self.root, &TagFilter::default()).await?
+        {
+            let file = self.read_segment(&filename, &file_handle).await?;
+            let mut embeddings = self.get_embeddings_by_file(&codec, &filename, file)?;
+            let before = embeddings.len();
+            if let Some(combined) = index.combined_mut(&filename) {
+                Self::check_tag_map(combined, before)?;
+                combined.retain(|position| !tombstones.contains(&embeddings[position].id));
+            }
+            embeddings.retain(|embedding| !tombstones.contains(&embedding.id));
+            live.extend(embeddings.iter().map(|embedding| embedding.id));
+            if embeddings.len() == before {
+                continue;
+            }
+
+            let old_size = file_handle.size().await?;
+            self.write_segment(&filename, &mut file_handle, &codec, &embeddings)
+                .await?;
+            reclaimed += old_size.saturating_sub(file_handle.size().await?) as u64;
+
+            // the remaining embeddings have moved, so the file's indexes are out of date
+            let _ = self
+                .root
+                .remove_entry(&hnsw::filename_for_segment(&filename))
+                .await;
+            let _ = self
+                .root
+                .remove_entry(&lsh::filename_for_segment(&filename))
+                .await;
+            self.sync_indexes(&filename, Changed::Unknown).await?;
+        }
+        if !index.combined.is_empty() {
+            index.write_combined(&self.root).await?;
+        }
+
+        // drop the content of deleted documents, along with any other content without an embedding,
+        // and content that was replaced by later entries in the log
+        let (mut contents, entries) = Arc::unwrap_or_clone(self.read_content_log().await?);
+        contents.retain(|id, _| live.contains(id));
+        if contents.len() != entries {
+            let old_size = self.content_size().await?;
+            self.write_all_contents(&contents).await?;
+            reclaimed += old_size.saturating_sub(self.content_size().await?) as u64;
+        } else if let Some(index) = keywords::read_index(&self.root).await? {
+            // rewriting the contents rebuilds the keyword index, otherwise the changes appended to it are folded in
+            if index.appended() > 0 {
+                keywords::write_index(&self.root, &index).await?;
+            }
+        }
+
+        let mut chunks = chunks::read(&self.root).await?;
+        let chunk_count = chunks.len();
+        chunks.retain(|chunk, _| live.contains(chunk));
+        if chunks.len() != chunk_count {
+            chunks::write(&self.root, &chunks).await?;
+        }
+
+        // the same goes for the original vectors of a projected database
+        if self.retains_originals().await {
+            let (mut originals, entries) = self.read_original_log().await?;
+            originals.retain(|id, _| live.contains(id));
+            if originals.len() != entries {
+                let old_size = self.originals_size().await?;
+                self.write_all_originals(&originals).await?;
+                reclaimed += old_size.saturating_sub(self.originals_size().await?) as u64;
+            }
+        }
+
+        tombstones::write(&self.root, &HashSet::new()).await?;
+        let mut expiry = expiry::read(&self.root).await?;
+        let expiry_count = expiry.len();
+        expiry.retain(|id, _| !tombstones.contains(id));
+        if expiry.len() != expiry_count {
+            expiry::write(&self.root, &expiry).await?;
+        }
+        let mut added = added::read(&self.root).await?;
+        let added_count = added.len();
+        added.retain(|id, _| !tombstones.contains(id));
+        if added.len() != added_count {
+            added::write(&self.root, &added).await?;
+        }
+        let mut versions = versions::read(&self.root).await?;
+        let version_count = versions.len();
+        versions.retain(|id, _| !tombstones.contains(id));
+        if versions.len() != version_count {
+            versions::write(&self.root, &versions).await?;
+        }
+        self.subscribers.send(Event::Compacted { reclaimed });
+
+        Ok(reclaimed)
+    }
+
+    /// Merge small db files into a few combined files, returning how many db files were merged.
+    ///
+    /// Every combination of tags gets db files of its own, so a database with many tag sets ends up with many small
+    /// files, which are slow to search one by one (especially in the browser). This merges the db files of tag sets
+    /// with a single file under 1 MiB into combined files of up to the maximum segment size
+    /// (see [`Victor::with_max_segment_size`]), along with the combined files of earlier optimizations.
+    /// Combined files record which tags each of their embeddings has, so searches can still filter by tag.
+    ///
+    /// Documents added later go in new db files of their own, until the next optimization.
+    /// Combined files are always scanned rather than searched with an HNSW or LSH index.
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// # let mut victor = Db::new(DirectoryHandle::default());
+    /// victor.add_single_embedding("Pepperoni pizza", vec![0.1, 0.2, 0.3], vec!["Pizza Flavors"]).await.unwrap();
+    /// victor.add_single_embedding("Pineapple", vec![0.3, 0.2, 0.1], vec!["Pizza Toppings"]).await.unwrap();
+    ///
+    /// assert_eq!(victor.optimize().await.unwrap(), 2);
+    ///
+    /// let results = victor.search_embedding(vec![0.3, 0.2, 0.1], vec!["Pizza Flavors"], 1).await.unwrap();
+    /// assert_eq!(results[0].content, "Pepperoni pizza");
+    /// # })
+    /// ```
+    pub async fn optimize(&mut self) -> Result<usize, Error> {
+        self.flush().await?;
+        self.migrate().await?;
+        self.recover().await?;
+
+        let (mut index_file, mut index) = Index::load(&self.root).await?;
+
+        // the embeddings of each tag set to merge, grouped by their size, since a db file's are all the same size
+        let mut merged = Vec::new();
+        let mut by_size = BTreeMap::<usize, Vec<(BTreeSet<String>, Vec<u8>)>>::new();
+        let mut tag_sets = index.files.iter().cloned().collect::<Vec<_>>();
+        tag_sets.sort();
+        for tags in tag_sets {
+            if index.parts.contains_key(&tags) {
+                continue;
+            }
+            let filename = Index::filename_for_part(tags.clone(), 0);
+            let Ok(file_handle) = self
+                .root
+                .get_file_handle_with_options(&filename, &GetFileHandleOptions { create: false })
+                .await
+            else {
+                continue;
+            };
+            if file_handle.size().await? >= SMALL_SEGMENT_SIZE {
+                continue;
+            }
+
+            let file = self.read_segment(&filename, &file_handle).await?;
+            let records = Self::get_records_by_file(&filename, &file)?;
+            if let Some(record_size) = records.first().map(|record| record.len()) {
+                by_size
+                    .entry(record_size)
+                    .or_default()
+                    .push((tags.clone(), records.concat()));
+            }
+            merged.push((filename, Some(tags)));
+        }
+        for combined in &index.combined {
+            let file_handle = self
+                .root
+                .get_file_handle_with_options(
+                    &combined.filename,
+                    &GetFileHandleOptions { create: false },
+                )
+                .await?;
+            let file = self.read_segment(&combined.filename, &file_handle).await?;
+            let records = Self::get_records_by_file(&combined.filename, &file)?;
+            Self::check_tag_map(combined, records.len())?;
+            if let Some(record_size) = records.first().map(|record| record.len()) {
+                for (range, _, tags) in combined.ranges() {
+                    by_size
+                        .entry(record_size)
+                        .or_default()
+                        .push((tags.clone(), records[range].concat()));
+                }
+            }
+            merged.push((combined.filename.clone(), None));
+        }
+
+        // merging one file into another file would gain nothing
+        if merged.len() < 2 {
+            return Ok(0);
+        }
+
+        // pack the tag sets into as few files as fit, keeping each tag set's embeddings together
+        let compression = self.settings().await?.compression;
+        let codec = self.codec().await?;
+        let mut combined = Vec::new();
+        for (record_size, mut pieces) in by_size {
+            pieces.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+            let mut files = Vec::<(Combined, Vec<u8>)>::new();
+            for (tags, records) in pieces {
+                let len = records.len() / record_size;
+                let fits = files.last().is_some_and(|(_, file)| {
+                    file.len() + records.len() <= self.max_segment_size.max(1)
+                });
+                if !fits {
+                    let file = Combined {
+                        filename: format!("{COMBINED_PREFIX}{}.bin", Uuid::new_v4().simple()),
+                        tag_map: Vec::new(),
+                    };
+                    let header = bincode::serialize(&(record_size as u32))
+                        .expect("Failed to serialize size");
+                    files.push((file, header));
+                }
+
+                let (file, data) = files.last_mut().expect("a file was just added");
+                match file.tag_map.last_mut() {
+                    Some((last, count)) if *last == tags => *count += len,
+                    _ => file.tag_map.push((tags, len)),
+                }
+                data.extend(records);
+            }
+
+            for (file, data) in files {
+                let stored = compression.compress(&data)?;
+                let mut file_handle = self
+                    .root
+                    .get_file_handle_with_options(
+                        &file.filename,
+                        &GetFileHandleOptions { create: true },
+                    )
+                    .await?;
+                let mut writable = file_handle
+                    .create_writable_with_options(&CreateWritableOptions {
+                        keep_existing_data: false,
+                    })
+                    .await?;
+                writable.write_at_cursor_pos(stored.clone()).await?;
+                writable.close().await?;
+                checksum::record(&self.root, &file.filename, &stored).await?;
+                let ids = self
+                    .get_embeddings_by_file(&codec, &file.filename, data)?
+                    .into_iter()
+                    .map(|embedding| embedding.id);
+                bloom::write(
+                    &self.root,
+                    &file.filename,
+                    &Bloom::new(ids, checksum::checksum(&stored)),
+                )
+                .await?;
+                combined.push(file);
+            }
+        }
+
+        // the combined files are listed before the merged ones are dropped from the index,
+        // so an interruption leaves documents in both places rather than in neither
+        index.combined = combined;
+        index.write_combined(&self.root).await?;
+        for (_, tags) in &merged {
+            if let Some(tags) = tags {
+                index.parts.insert(tags.clone(), 0);
+            }
+        }
+        index.write(&self.root, &mut index_file).await?;
+
+        let mut segment_stats = self.read_segment_stats().await?;
+        for (filename, _) in &merged {
+            self.root.remove_entry(filename).await?;
+            checksum::remove(&self.root, filename).await?;
+            let _ = self
+                .root
+                .remove_entry(&hnsw::filename_for_segment(filename))
+                .await;
+            let _ = self
+                .root
+                .remove_entry(&lsh::filename_for_segment(filename))
+                .await;
+            let _ = self
+                .root
+                .remove_entry(&bloom::filename_for_segment(filename))
+                .await;
+            segment_stats.remove(filename);
+        }
+        self.write_segment_stats(&segment_stats).await?;
+
+        // the catalog locates embeddings by file
+        self.catalog = None;
+
+        Ok(merged.len())
+    }
+
+    /// Pack the whole database into a single file, which [`Victor::load_bundle`] can load into another database.
+    /// Useful for building a database on a server, then querying it in the browser.
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// let mut server = Db::new(DirectoryHandle::default());
+    /// server.add_single_embedding("Pepperoni pizza", vec![0.1, 0.2, 0.3], vec!["Pizza Flavors"]).await.unwrap();
+    /// let bundle = server.export_bundle().await.unwrap();
+    ///
+    /// let mut client = Db::new(DirectoryHandle::default());
+    /// client.load_bundle(&bundle).await.unwrap();
+    /// let results = client.search_embedding(vec![0.1, 0.2, 0.3], vec!["Pizza Flavors"], 1).await.unwrap();
+    /// assert_eq!(results[0].content, "Pepperoni pizza");
+    /// # })
+    /// ```
+    pub async fn export_bundle(&mut self) -> Result<Vec<u8>, Error> {
+        self.migrate().await?;
+        self.recover().await?;
+
+        let mut bundle = Bundle::default();
+        for filename in self.database_filenames().await? {
+            if let Some(bytes) = self.read_database_file(&filename).await? {
+                bundle.files.insert(filename, bytes);
+            }
+        }
+
+        Ok(bundle.to_bytes())
+    }
+
+    /// Replace the database with one packed by [`Victor::export_bundle`].
+    ///
+    /// Bundles from older versions of victor are upgraded like any other database,
+    /// but bundles from newer versions are rejected with [`Error::UnsupportedVersion`].
+    pub async fn load_bundle(&mut self, bundle: &[u8]) -> Result<(), Error> {
+        let bundle = Bundle::from_bytes(bundle)?;
+
+        self.clear_db().await?;
+        for (filename, bytes) in bundle.files {
+            self.write_database_file(&filename, bytes).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Copy the whole database into `dest`, replacing whatever database was there.
+    /// The copy can be opened like any other database, on the same kind of filesystem or another.
+    ///
+    /// Nothing can be written through this `Victor` while the copy is made, and any interrupted insert is
+    /// rolled back first (see [`Victor::recover`]), so the copy is of the database at a single point in time.
+    /// Writes through another `Victor` on the same directory aren't prevented, though.
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// let mut victor = Db::new(DirectoryHandle::default());
+    /// victor.add_single_embedding("Pepperoni pizza", vec![0.1, 0.2, 0.3], vec!["Pizza Flavors"]).await.unwrap();
+    ///
+    /// let backup = DirectoryHandle::default();
+    /// victor.snapshot(backup.clone()).await.unwrap();
+    ///
+    /// // later changes don't affect the snapshot
+    /// victor.clear_db().await.unwrap();
+    /// let backup = Db::new(backup);
+    /// let results = backup.search_embedding(vec![0.1, 0.2, 0.3], vec!["Pizza Flavors"], 1).await.unwrap();
+    /// assert_eq!(results[0].content, "Pepperoni pizza");
+    /// # })
+    /// ```
+    pub async fn snapshot<T: DirectoryHandle>(&mut self, dest: T) -> Result<(), Error> {
+        self.migrate().await?;
+        self.recover().await?;
+
+        let mut copy = Victor::<T>::new(dest);
+        copy.clear_db().await?;
+        for filename in self.database_filenames().await? {
+            if let Some(bytes) = self.read_database_file(&filename).await? {
+                copy.write_database_file(&filename, bytes).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The contents of a file in the database, or `None` if it doesn't exist or is empty.
+    async fn read_database_file(&self, filename: &str) -> Result<Option<Vec<u8>>, Error> {
+        let Ok(file_handle) = self
+            .root
+            .get_file_handle_with_options(filename, &GetFileHandleOptions { create: false })
+            .await
+        else {
+            return Ok(None);
+        };
+        let bytes = file_handle.read().await?;
+        Ok((!bytes.is_empty()).then_some(bytes))
+    }
+
+    /// Replace a file in the database with `bytes`, as is.
+    async fn write_database_file(&mut self, filename: &str, bytes: Vec<u8>) -> Result<(), Error> {
+        let mut file_handle = self
+            .root
+            .get_file_handle_with_options(filename, &GetFileHandleOptions { create: true })
+            .await?;
+        let mut writable = file_handle
+            .create_writable_with_options(&CreateWritableOptions {
+                keep_existing_data: false,
+            })
+            .await?;
+        writable.write_at_cursor_pos(bytes).await?;
+        writable.close().await?;
+        Ok(())
+    }
+
+    /// Write the whole database to `writer`, as an archive that [`Victor::import_from`] can load into a database
+    /// on any backend, so databases can be moved between the memory, native and web filesystems.
+    ///
+    /// The archive is a bundle, like [`Victor::export_bundle`] returns.
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// let path = std::env::temp_dir().join(format!("victor-{}.bundle", uuid::Uuid::new_v4()));
+    ///
+    /// let mut victor = Db::new(DirectoryHandle::default());
+    /// victor.add_single_embedding("Pepperoni pizza", vec![0.1, 0.2, 0.3], vec!["Pizza Flavors"]).await.unwrap();
+    /// victor.export_to(std::fs::File::create(&path).unwrap()).await.unwrap();
+    ///
+    /// let mut copy = Db::new(DirectoryHandle::default());
+    /// copy.import_from(std::fs::File::open(&path).unwrap()).await.unwrap();
+    /// let results = copy.search_embedding(vec![0.1, 0.2, 0.3], vec!["Pizza Flavors"], 1).await.unwrap();
+    /// assert_eq!(results[0].content, "Pepperoni pizza");
+    /// # std::fs::remove_file(path).unwrap();
+    /// # })
+    /// ```
+    pub async fn export_to(&mut self, mut writer: impl std::io::Write) -> Result<(), Error> {
+        let bundle = self.export_bundle().await?;
+        writer.write_all(&bundle)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Replace the database with an archive written by [`Victor::export_to`], from any backend.
+    pub async fn import_from(&mut self, mut reader: impl std::io::Read) -> Result<(), Error> {
+        let mut bundle = Vec::new();
+        reader.read_to_end(&mut bundle)?;
+        self.load_bundle(&bundle).await
+    }
+
+    /// List the documents with tags matching `with_tags` and metadata matching `filter`, `limit` at a time,
+    /// for going through more of them than fit in memory at once, like paging through a long listing or exporting
+    /// a big database in a way that can be picked up again if it's interrupted.
+    ///
+    /// Start with no `cursor`, then pass each page's [`ScrollPage::cursor`] (with the same filters) to get the
+    /// next page, until there's none. Documents added during a scroll may or may not be listed, and documents
+    /// deleted during it aren't listed once they're deleted. Compacting or optimizing the database during a
+    /// scroll may make it skip or repeat documents.
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// # let mut victor = Db::new(DirectoryHandle::default());
+    /// use victor_db::{Filter, TagFilter};
+    ///
+    /// for topping in ["Pepperoni", "Mushroom", "Pineapple", "Olive", "Basil"] {
+    ///     victor.add_single_embedding(topping, vec![0.1, 0.2, 0.3], vec!["Pizza Toppings"]).await.unwrap();
+    /// }
+    ///
+    /// let mut toppings = Vec::new();
+    /// let mut cursor = None;
+    /// loop {
+    ///     let page = victor.scroll(TagFilter::default(), &Filter::All, cursor, 2).await.unwrap();
+    ///     assert!(page.documents.len() <= 2);
+    ///     toppings.extend(page.documents.into_iter().map(|document| document.content));
+    ///     match page.cursor {
+    ///         Some(next) => cursor = Some(next),
+    ///         None => break,
+    ///     }
+    /// }
+    /// assert_eq!(toppings, ["Pepperoni", "Mushroom", "Pineapple", "Olive", "Basil"]);
+    /// # })
+    /// ```
+    pub async fn scroll(
+        &self,
+        with_tags: impl Into<TagFilter>,
+        filter: &Filter,
+        cursor: Option<ScrollCursor>,
+        limit: usize,
+    ) -> Result<ScrollPage, Error> {
+        format::check(&self.root).await?;
+        if limit == 0 {
+            return Err(Error::InvalidInput(
+                "scrolling needs a limit of at least one document".to_string(),
+            ));
+        }
+        let with_tags = with_tags.into();
+
+        let (_, index) = Index::load(&self.root).await?;
+        let codec = self.codec().await?;
+        let tombstones = tombstones::removed(&self.root).await?;
+        let chunks = chunks::read(&self.root).await?;
+        let segment_stats = self.read_segment_stats().await?;
+        let mut contents = self.read_contents().await?;
+        let tags_by_filename = index.tags_by_filename();
+
+        // db files in a fixed order, so a cursor says which ones have already been listed
+        let mut filenames = index.matching_filenames(&with_tags);
+        filenames.sort();
+        let start = cursor.unwrap_or(ScrollCursor {
+            filename: String::new(),
+            position: 0,
+        });
+
+        let mut documents = Vec::new();
+        for filename in filenames {
+            if filename < start.filename {
+                continue;
+            }
+            if let Some(stats) = segment_stats.get(&filename) {
+                if !filter.may_match(stats) {
+                    continue;
+                }
+            }
+            let Ok(file_handle) = self
+                .root
+                .get_file_handle_with_options(&filename, &GetFileHandleOptions { create: false })
+                .await
+            else {
+                continue;
+            };
+
+            let skip = match filename == start.filename {
+                true => start.position,
+                false => 0,
+            };
+            let embeddings = self
+                .read_matching_embeddings(&index, &filename, &file_handle, &codec, &with_tags)
+                .await?;
+            for (position, (name, embedding)) in embeddings.into_iter().enumerate().skip(skip) {
+                // like exports, only each document's first vector is listed
+                if tombstones.contains(&embedding.id) || chunks.contains_key(&embedding.id) {
+                    continue;
+                }
+                let Some(content) = contents.remove(&embedding.id) else {
+                    return Err(Error::Corrupted {
+                        file: "content.bin".to_string(),
+                        reason: format!("missing the content of {}", embedding.id),
+                    });
+                };
+                if !filter.matches(&content.metadata) {
+                    continue;
+                }
+
+                // only hand out a cursor if there's really another document, so the last page is never empty
+                if documents.len() == limit {
+                    return Ok(ScrollPage {
+                        documents,
+                        cursor: Some(ScrollCursor { filename, position }),
+                    });
+                }
+                documents.push(ScrolledDocument {
+                    id: embedding.id,
+                    external_id: content.external_id,
+                    content: content.content,
+                    tags: tags_by_filename[&name].iter().cloned().collect(),
+                    vector: embedding.vector,
+                    metadata: content.metadata,
+                });
+            }
+        }
+
+        Ok(ScrollPage {
+            documents,
+            cursor: None,
+        })
+    }
+
+    /// Write every document to `writer` as JSON Lines, one `{"id", "content", "tags", "vector", "metadata"}` object
+    /// per line, returning the number of documents written.
+    ///
+    /// Documents added without an id are exported with their generated one. Vectors are exported as they're
+    /// stored, so they may have lost precision to [`crate::Storage::Half`] or quantization.
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// # let mut victor = Db::new(DirectoryHandle::default());
+    /// victor
+    ///     .add_embeddings_with_ids(vec![("pizza-1", "Pepperoni pizza", vec![0.5, 1.0])], vec!["Pizza Flavors"])
+    ///     .await
+    ///     .unwrap();
+    ///
+    /// let mut jsonl = Vec::new();
+    /// assert_eq!(victor.export_jsonl(&mut jsonl).await.unwrap(), 1);
+    /// assert_eq!(
+    ///     String::from_utf8(jsonl).unwrap(),
+    ///     r#"{"id":"pizza-1","content":"Pepperoni pizza","tags":["Pizza Flavors"],"vector":[0.5,1.0]}"#.to_string() + "\n"
+    /// );
+    /// # })
+    /// ```
+    pub async fn export_jsonl(&self, mut writer: impl std::io::Write) -> Result<usize, Error> {
+        let documents = self.read_documents().await?;
+        for (tags, embedding, content) in &documents {
+            let record = Record::new(
+                content.id_or(embedding.id),
+                content.content.clone(),
+                tags.iter().cloned().collect(),
+                embedding.vector.clone(),
+                &content.metadata,
+            );
+            serde_json::to_writer(&mut writer, &record).expect("Failed to serialize record");
+            writer.write_all(b"\n")?;
+        }
+        writer.flush()?;
+
+        Ok(documents.len())
+    }
+
+    /// Write every document to `writer` as a Parquet file, returning the number of documents written,
+    /// so the database can be analyzed with tools like Polars or DuckDB.
+    ///
+    /// The columns are `id`, `content`, `tags` (a list of strings), `vector` (a fixed-size list of floats) and
+    /// `metadata` (a JSON object). Like [`Victor::export_jsonl`], documents added without an id are exported with
+    /// their generated one, and vectors are exported as they're stored.
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// # let mut victor = Db::new(DirectoryHandle::default());
+    /// victor.add_single_embedding("Pepperoni pizza", vec![0.1, 0.2, 0.3], vec!["Pizza Flavors"]).await.unwrap();
+    ///
+    /// let path = std::env::temp_dir().join(format!("victor-{}.parquet", uuid::Uuid::new_v4()));
+    /// let exported = victor.export_parquet(std::fs::File::create(&path).unwrap()).await.unwrap();
+    /// assert_eq!(exported, 1);
+    /// # std::fs::remove_file(path).unwrap();
+    /// # })
+    /// ```
+    #[cfg(feature = "parquet")]
+    pub async fn export_parquet(&self, writer: impl std::io::Write + Send) -> Result<usize, Error> {
+        let batch = self.export_arrow().await?;
+        let into_error =
+            |error: parquet::errors::ParquetError| Error::Io(std::io::Error::other(error));
+        let mut writer = parquet::arrow::ArrowWriter::try_new(writer, batch.schema(), None)
+            .map_err(into_error)?;
+        writer.write(&batch).map_err(into_error)?;
+        writer.close().map_err(into_error)?;
+
+        Ok(batch.num_rows())
+    }
+
+    /// Every document as an Arrow record batch, for Arrow-based tools like DuckDB or Polars.
+    ///
+    /// The columns are `id`, `content`, `tags` (a list of strings), `vector` (a fixed-size list of floats) and
+    /// `metadata` (a JSON object), like [`Victor::export_parquet`], which writes the same batch.
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// # let mut victor = Db::new(DirectoryHandle::default());
+    /// victor.add_single_embedding("Pepperoni pizza", vec![0.1, 0.2, 0.3], vec!["Pizza Flavors"]).await.unwrap();
+    ///
+    /// let batch = victor.export_arrow().await.unwrap();
+    /// assert_eq!(batch.num_rows(), 1);
+    /// assert!(batch.column_by_name("vector").is_some());
+    /// # })
+    /// ```
+    #[cfg(feature = "arrow")]
+    pub async fn export_arrow(&self) -> Result<arrow_array::RecordBatch, Error> {
+        let documents = self.read_documents().await?;
+        let dimension = match documents.first() {
+            Some((_, embedding, _)) => embedding.vector.len(),
+            None => self.settings().await?.dimension.unwrap_or_default(),
+        };
+        crate::arrow::record_batch(&documents, dimension)
+    }
+
+    /// Add the documents in an Arrow record batch, returning the number of documents added.
+    ///
+    /// The batch needs a `content` column of strings and a `vector` column of lists of 32-bit floats (fixed-size or
+    /// not). It can also have an `id` column of strings, a `tags` column of lists of strings and a `metadata` column
+    /// of JSON objects, like the batches written by [`Victor::export_arrow`]; missing columns and nulls in them are
+    /// treated as no id, no tags and no metadata. Documents with an id that already exists are updated in place,
+    /// like [`Victor::add_documents`].
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// # let mut victor = Db::new(DirectoryHandle::default());
+    /// # let mut source = Db::new(DirectoryHandle::default());
+    /// # source.add_single_embedding("Pineapple", vec![0.3, 0.2, 0.1], vec!["Pizza Toppings"]).await.unwrap();
+    /// // a batch from anywhere, here another database
+    /// let batch = source.export_arrow().await.unwrap();
+    /// assert_eq!(victor.import_arrow(&batch).await.unwrap(), 1);
+    ///
+    /// let nearest = victor.search_embedding(vec![0.3, 0.2, 0.1], vec!["Pizza Toppings"], 1).await.unwrap();
+    /// assert_eq!(nearest[0].content, "Pineapple");
+    /// # })
+    /// ```
+    #[cfg(feature = "arrow")]
+    pub async fn import_arrow(&mut self, batch: &arrow_array::RecordBatch) -> Result<usize, Error> {
+        let mut documents: HashMap<Vec<String>, Vec<Document>> = HashMap::new();
+        for (tags, document) in crate::arrow::documents(batch)? {
+            documents.entry(tags).or_default().push(document);
+        }
+        self.add_batch(documents).await
+    }
+
+    /// Every vector with tags matching `with_tags` as a matrix, one row per document, along with the id of the
+    /// document in each row, for analysis the database doesn't do itself, like other dimensionality reductions
+    /// or plotting.
+    ///
+    /// Like [`Victor::export_jsonl`], vectors are as they're stored, so they may have lost precision to
+    /// [`crate::Storage::Half`] or quantization, and documents with more than one vector only have their first.
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// # let mut victor = Db::new(DirectoryHandle::default());
+    /// victor.add_single_embedding("Pepperoni pizza", vec![1.0, 2.0, 3.0], vec!["Pizza Flavors"]).await.unwrap();
+    /// victor.add_single_embedding("Pineapple", vec![3.0, 2.0, 1.0], vec!["Pizza Toppings"]).await.unwrap();
+    ///
+    /// let (matrix, ids) = victor.to_matrix(vec!["Pizza Toppings"]).await.unwrap();
+    /// assert_eq!(matrix.shape(), (1, 3));
+    /// assert_eq!(ids.len(), 1);
+    ///
+    /// // the mean of every vector
+    /// let (matrix, _) = victor.to_matrix(Vec::<String>::new()).await.unwrap();
+    /// assert!(matrix.row_mean().iter().all(|mean| (mean - 2.0).abs() < 0.01));
+    /// # })
+    /// ```
+    pub async fn to_matrix(
+        &self,
+        with_tags: impl Into<TagFilter>,
+    ) -> Result<(DMatrix<f32>, Vec<Uuid>), Error> {
+        let documents = self.read_matching_documents(&with_tags.into()).await?;
+        let dimension = match documents.first() {
+            Some((_, embedding, _)) => embedding.vector.len(),
+            None => self.settings().await?.dimension.unwrap_or_default(),
+        };
+
+        let ids = documents
+            .iter()
+            .map(|(_, embedding, _)| embedding.id)
+            .collect::<Vec<_>>();
+        let matrix = DMatrix::from_row_iterator(
+            documents.len(),
+            dimension,
+            documents
+                .into_iter()
+                .flat_map(|(_, embedding, _)| embedding.vector),
+        );
+        Ok((matrix, ids))
+    }
+
+    /// Add documents from JSON Lines, in the format written by [`Victor::export_jsonl`], returning the number of
+    /// documents added. `metadata` can be left out.
+    ///
+    /// Documents are added in batches, so the whole file is never in memory at once.
+    /// Documents with an id that already exists are updated in place, like [`Victor::add_documents`].
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// # let mut victor = Db::new(DirectoryHandle::default());
+    /// let jsonl = r#"{"id":"pizza-1","content":"Pepperoni pizza","tags":["Pizza Flavors"],"vector":[0.5,1.0]}
+    /// {"id":"pizza-2","content":"Pineapple","tags":["Pizza Toppings"],"vector":[1.0,0.5],"metadata":{"price":2}}"#;
+    /// assert_eq!(victor.import_jsonl(jsonl.as_bytes()).await.unwrap(), 2);
+    ///
+    /// let nearest = victor.search_embedding(vec![1.0, 0.5], vec!["Pizza Toppings"], 1).await.unwrap();
+    /// assert_eq!(nearest[0].content, "Pineapple");
+    /// # })
+    /// ```
+    pub async fn import_jsonl(&mut self, reader: impl std::io::BufRead) -> Result<usize, Error> {
+        let mut imported = 0;
+        let mut batch: HashMap<Vec<String>, Vec<Document>> = HashMap::new();
+        let mut batch_len = 0;
+        for (index, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let (tags, document) = Record::parse(index + 1, &line)?.into_document()?;
+            batch.entry(tags).or_default().push(document);
+            batch_len += 1;
+
+            if batch_len == IMPORT_BATCH_SIZE {
+                imported += self.add_batch(std::mem::take(&mut batch)).await?;
+                batch_len = 0;
+            }
+        }
+        imported += self.add_batch(batch).await?;
+
+        Ok(imported)
+    }
+
+    /// Add a matrix of precomputed embeddings, stored row by row in `vectors`, with one row per entry in
+    /// `contents`. Returns the number of documents added.
+    ///
+    /// Like [`Victor::add_embeddings_from_slice`], with the arguments in the order of the matrix's shape.
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// # let mut victor = Db::new(DirectoryHandle::default());
+    /// let vectors = [0.1, 0.2, 0.3, 0.3, 0.2, 0.1];
+    /// victor.import_matrix(&vectors, 3, vec!["Pepperoni pizza", "Pineapple"], vec!["Pizza"]).await.unwrap();
+    ///
+    /// let nearest = victor.search_embedding(vec![0.3, 0.2, 0.1], vec!["Pizza"], 1).await.unwrap();
+    /// assert_eq!(nearest[0].content, "Pineapple");
+    /// # })
+    /// ```
+    pub async fn import_matrix(
+        &mut self,
+        vectors: &[f32],
+        dimension: usize,
+        contents: Vec<impl Into<String>>,
+        tags: Vec<impl Into<String>>,
+    ) -> Result<usize, Error> {
+        self.add_embeddings_from_slice(contents, vectors, dimension, tags)
+            .await
+    }
+
+    /// Add embeddings from a NumPy `.npy` file holding a 2-dimensional `float32` or `float64` array,
+    /// with one row per entry in `contents`. Returns the number of documents added.
+    ///
+    /// See [`Victor::import_matrix`].
+    pub async fn import_npy(
+        &mut self,
+        npy: &[u8],
+        contents: Vec<impl Into<String>>,
+        tags: Vec<impl Into<String>>,
+    ) -> Result<usize, Error> {
+        let matrix = npy::read(npy)?;
+        if matrix.rows != contents.len() {
+            return Err(Error::InvalidInput(format!(
+                "the .npy file has {} rows, but there are {} contents",
+                matrix.rows,
+                contents.len()
+            )));
+        }
+
+        self.import_matrix(&matrix.values, matrix.dimension, contents, tags)
+            .await
+    }
+
+    /// Add the documents from another vector database's JSON export, returning the number of documents added.
+    /// See [`VectorStore`] for the formats each store's exports are read in, and [`ImportOptions`] for how their
+    /// payloads are mapped to tags and metadata.
+    ///
+    /// Documents with an id that already exists are updated in place, like [`Victor::add_documents`].
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// # let mut victor = Db::new(DirectoryHandle::default());
+    /// use victor_db::{ImportOptions, VectorStore};
+    ///
+    /// let export = r#"[
+    ///     {"id": 1, "vector": [0.1, 0.2, 0.3], "payload": {"text": "Pepperoni pizza", "tags": ["Pizza Flavors"]}},
+    ///     {"id": 2, "vector": [0.3, 0.2, 0.1], "payload": {"text": "Pineapple", "tags": ["Pizza Toppings"]}}
+    /// ]"#;
+    /// let imported = victor
+    ///     .import_vector_store(VectorStore::Qdrant, export.as_bytes(), &ImportOptions::default())
+    ///     .await
+    ///     .unwrap();
+    /// assert_eq!(imported, 2);
+    ///
+    /// let nearest = victor.search_embedding(vec![0.3, 0.2, 0.1], vec!["Pizza Toppings"], 1).await.unwrap();
+    /// assert_eq!(nearest[0].external_id.as_deref(), Some("2"));
+    /// # })
+    /// ```
+    pub async fn import_vector_store(
+        &mut self,
+        store: VectorStore,
+        export: &[u8],
+        options: &ImportOptions,
+    ) -> Result<usize, Error> {
+        let documents = importers::parse(store, export, options)?;
+
+        let mut imported = 0;
+        let mut documents = documents.into_iter().peekable();
+        while documents.peek().is_some() {
+            let mut batch: HashMap<Vec<String>, Vec<Document>> = HashMap::new();
+            for (tags, document) in documents.by_ref().take(IMPORT_BATCH_SIZE) {
+                batch.entry(tags).or_default().push(document);
+            }
+            imported += self.add_batch(batch).await?;
+        }
+
+        Ok(imported)
+    }
+
+    /// Add documents grouped by their tags, returning how many there were.
+    async fn add_batch(
+        &mut self,
+        batch: HashMap<Vec<String>, Vec<Document>>,
+    ) -> Result<usize, Error> {
+        let mut added = 0;
+        for (tags, documents) in batch {
+            added += documents.len();
+            self.add_documents(documents, tags).await?;
+        }
+        Ok(added)
+    }
+
+    /// A hash of the content of every document that hasn't been deleted, with the document's id, its own id,
+    /// and that it's stored, see [`Victor::with_deduplication`].
+    async fn content_hashes(
+        &self,
+        tombstones: &HashSet<Uuid>,
+    ) -> Result<HashMap<String, (Uuid, Option<String>, bool)>, Error> {
+        Ok(self
+            .read_content_log()
+            .await?
+            .0
+            .iter()
+            .filter(|(id, _)| !tombstones.contains(id))
+            .map(|(id, content)| {
+                (
+                    digest(content.content.as_str()),
+                    (*id, content.external_id.clone(), true),
+                )
+            })
+            .collect())
+    }
+
+    /// Every document that hasn't been deleted, with its tags.
+    async fn read_documents(&self) -> Result<Vec<StoredDocument>, Error> {
+        self.read_matching_documents(&TagFilter::default()).await
+    }
+
+    /// Every document with tags matching `with_tags` that hasn't been deleted, with its tags.
+    async fn read_matching_documents(
+        &self,
+        with_tags: &TagFilter,
+    ) -> Result<Vec<StoredDocument>, Error> {
+        format::check(&self.root).await?;
+
+        let (_, index) = Index::load(&self.root).await?;
+        let codec = self.codec().await?;
+        let tombstones = tombstones::removed(&self.root).await?;
+        let chunks = chunks::read(&self.root).await?;
+        let mut contents = self.read_contents().await?;
+
+        let tags_by_filename = index.tags_by_filename();
+        let mut documents = Vec::new();
+        for filename in index.matching_filenames(with_tags) {
+            let Ok(file_handle) = self
+                .root
+                .get_file_handle_with_options(&filename, &GetFileHandleOptions { create: false })
+                .await
+            else {
+                continue;
+            };
+            for (name, embedding) in self
+                .read_matching_embeddings(&index, &filename, &file_handle, &codec, with_tags)
+                .await?
+            {
+                // only each document's first vector is exported
+                if tombstones.contains(&embedding.id) || chunks.contains_key(&embedding.id) {
+                    continue;
+                }
+                let Some(content) = contents.remove(&embedding.id) else {
+                    return Err(Error::Corrupted {
+                        file: "content.bin".to_string(),
+                        reason: format!("missing the content of {}", embedding.id),
+                    });
+                };
+                documents.push((tags_by_filename[&name].clone(), embedding, content));
+            }
+        }
+
+        Ok(documents)
+    }
+
+    /// The name of every file the database can have, other than the journal and the backup of a batch.
+    async fn database_filenames(&mut self) -> Result<Vec<String>, Error> {
+        let mut filenames = [
+            "format.bin",
+            "settings.bin",
+            "index.bin",
+            "combined.bin",
+            "allowed_tags.bin",
+            "content.bin",
+            "eigen.bin",
+            "originals.bin",
+            "stats.bin",
+            "pq.bin",
+            "binary.bin",
+            "hnsw.bin",
+            "lsh.bin",
+            "keywords.bin",
+            "tombstones.bin",
+            "expiry.bin",
+            "added.bin",
+            "versions.bin",
+            "chunks.bin",
+            "checksums.bin",
+        ]
+        .map(String::from)
+        .to_vec();
+
+        for segment in Index::get_all_db_filenames(&mut self.root).await? {
+            filenames.push(hnsw::filename_for_segment(&segment));
+            filenames.push(lsh::filename_for_segment(&segment));
+            filenames.push(bloom::filename_for_segment(&segment));
+            filenames.push(segment);
+        }
+
+        Ok(filenames)
+    }
+
+    /// Search the database for the nearest neighbors to a given document.
+    /// An embedding will be generated for the document being searched for.
+    /// This will return the top `top_n` nearest neighbors.
+    ///
+    /// ```rust
+    /// # #[cfg(feature = "embeddings")]
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// # let mut victor = Db::new(DirectoryHandle::default());
+    /// victor.search("Pepperoni pizza", vec!["Pizza Flavors"], 10).await.unwrap();
+    /// # });
+    /// ```
+    pub async fn search(
+        &self,
+        content: impl Into<String>,
+        with_tags: impl Into<TagFilter>,
+        top_n: u32,
+    ) -> Result<Vec<NearestNeighborsResult>, Error> {
+        self.search_with_options(content, with_tags, top_n, &SearchOptions::default())
+            .await
+    }
+
+    /// Search the database for the nearest neighbors to a given document, with additional [`SearchOptions`].
+    /// An embedding will be generated for the document being searched for.
+    /// This will return the top `top_n` nearest neighbors, re-ranked if there's a reranker
+    /// (see [`Victor::with_reranker`]).
+    ///
+    /// ```rust
+    /// # #[cfg(feature = "embeddings")]
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// # let mut victor = Db::new(DirectoryHandle::default());
+    /// use victor_db::{Filter, SearchOptions};
+    ///
+    /// let options = SearchOptions::default().with_filter(Filter::eq("author", "alice"));
+    /// victor.search_with_options("Pepperoni pizza", vec!["Pizza Flavors"], 10, &options).await.unwrap();
+    /// # });
+    /// ```
+    pub async fn search_with_options(
+        &self,
+        content: impl Into<String>,
+        with_tags: impl Into<TagFilter>,
+        top_n: u32,
+        options: &SearchOptions,
+    ) -> Result<Vec<NearestNeighborsResult>, Error> {
+        let content = content.into();
+        let with_tags = with_tags.into();
+        let vector = self
+            .embed(vec![content.clone()])
+            .await?
+            .into_iter()
+            .next()
+            .ok_or_else(|| Error::Embedding("no embedding was generated".to_string()))?;
+        let request = SearchRequest::new(Some(&content), Some(&vector), &with_tags, top_n, options);
+        self.hooks.searching(&request)?;
+        let timer = telemetry::Search::start("text");
+
+        let candidates = self
+            .find_nearest(
+                vector.clone(),
+                with_tags.clone(),
+                self.rerank_candidates(top_n, options),
+                &options.with_all_fields(),
+                NO_PROGRESS,
+            )
+            .await?;
+        let mut results = self.rerank(&content, candidates, top_n, options).await?;
+        self.hooks.searched(&request, &mut results);
+        timer.finish(results.len());
+        Ok(results)
+    }
+
+    /// The tags of the documents in `filename`, if they were looked up (see [`SearchOptions::include_tags`]).
+    fn tags_for(
+        tags_by_filename: &HashMap<String, BTreeSet<String>>,
+        filename: &str,
+    ) -> Vec<String> {
+        tags_by_filename
+            .get(filename)
+            .map(|tags| tags.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// How many results to find before re-ranking them, see [`SearchOptions::rerank_candidates`].
+    fn rerank_candidates(&self, top_n: u32, options: &SearchOptions) -> u32 {
+        match self.reranker {
+            Some(_) => top_n.max(options.rerank_candidates),
+            None => top_n,
+        }
+    }
+
+    /// Re-score `candidates` with the reranker (if there is one), returning the top `top_n`
+    /// without the fields `options` leaves out.
+    async fn rerank(
+        &self,
+        query: &str,
+        mut candidates: Vec<NearestNeighborsResult>,
+        top_n: u32,
+        options: &SearchOptions,
+    ) -> Result<Vec<NearestNeighborsResult>, Error> {
+        if let Some(reranker) = &self.reranker {
+            let contents = candidates
+                .iter()
+                .map(|candidate| candidate.content.as_str())
+                .collect::<Vec<_>>();
+            let scores = reranker.rerank(query, &contents).await?;
+            if scores.len() != candidates.len() {
+                return Err(Error::Reranking(format!(
+                    "expected {} scores, but got {}",
+                    candidates.len(),
+                    scores.len()
+                )));
+            }
+
+            for (candidate, score) in candidates.iter_mut().zip(scores) {
+                candidate.similarity = score;
+                candidate.score = similarity::logistic(score);
+                candidate.raw = None;
+                candidate
+                    .breakdown
+                    .get_or_insert_with(Default::default)
+                    .rerank = Some(score);
+            }
+            candidates.sort();
+            candidates.reverse();
+        }
+
+        options.limit(&mut candidates, top_n as usize, |candidate| {
+            candidate.tags.clone()
+        });
+        for candidate in &mut candidates {
+            options.exclude_fields(candidate);
+        }
+        Ok(candidates)
+    }
+
+    /// Embed `texts` with the embedder from [`Victor::with_embedder`], or a local model if there isn't one.
+    async fn embed(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>, Error> {
+        let count = texts.len();
+        let embeddings =
+            match &self.embedder {
+                Some(embedder) => embedder.embed(texts).await?,
+                #[cfg(all(feature = "embeddings", not(target_arch = "wasm32")))]
+                None => fastembed::TextEmbedding::try_new(Default::default())
+                    .and_then(|model| model.embed(texts, None))
+                    .map_err(|e| Error::Embedding(e.to_string()))?,
+                // the local model needs the `embeddings` feature, and doesn't run on the web
+                #[cfg(not(all(feature = "embeddings", not(target_arch = "wasm32"))))]
+                None => return Err(Error::Embedding(
+                    "there's no local model to generate embeddings with, so an embedder is needed"
+                        .to_string(),
+                )),
+            };
+
+        if embeddings.len() != count {
+            return Err(Error::Embedding(format!(
+                "expected {count} embeddings, but got {}",
+                embeddings.len()
+            )));
+        }
+        Ok(embeddings)
+    }
+
+    /// Search the database for the nearest neighbors to a given embedding.
+    /// This will return the top `top_n` nearest neighbors.
+    ///
+    /// `with_tags` can be a list of tags (documents must have all of them), or a [`TagFilter`] for more complex
+    /// queries:
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// # let mut victor = Db::new(DirectoryHandle::default());
+    /// use victor_db::TagFilter;
+    ///
+    /// // documents tagged "Pizza Flavors" or "Pizza Toppings", and "Vegetarian"
+    /// let tags = TagFilter::any(["Pizza Flavors", "Pizza Toppings"]) & TagFilter::tag("Vegetarian");
+    /// victor.search_embedding(vec![0.1, 0.2, 0.3], tags, 10).await.unwrap();
+    /// # })
+    /// ```
+    pub async fn search_embedding(
+        &self,
+        vector: Vec<f32>,
+        with_tags: impl Into<TagFilter>,
+        top_n: u32,
+    ) -> Result<Vec<NearestNeighborsResult>, Error> {
+        self.search_embedding_with_options(vector, with_tags, top_n, &SearchOptions::default())
+            .await
+    }
+
+    /// Search the database for the nearest neighbors to a given embedding, with additional [`SearchOptions`]
+    /// such as a metadata [`crate::Filter`].
+    /// This will return the top `top_n` nearest neighbors.
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// # let mut victor = Db::new(DirectoryHandle::default());
+    /// use victor_db::{Document, Filter, SearchOptions};
+    ///
+    /// victor
+    ///     .add_documents(
+    ///         vec![
+    ///             Document::new("Pepperoni pizza", vec![0.1, 0.2, 0.3]).with_metadata("author", "alice"),
+    ///             Document::new("Cheese pizza", vec![0.1, 0.2, 0.3]).with_metadata("author", "bob"),
+    ///         ],
+    ///         vec!["Pizza Flavors"],
+    ///     )
+    ///     .await
+    ///     .unwrap();
+    ///
+    /// let options = SearchOptions::default().with_filter(Filter::eq("author", "alice"));
+    /// let nearest = victor
+    ///     .search_embedding_with_options(vec![0.1, 0.2, 0.3], vec!["Pizza Flavors"], 10, &options)
+    ///     .await
+    ///     .unwrap();
+    /// assert_eq!(nearest.len(), 1);
+    /// assert_eq!(nearest[0].content, "Pepperoni pizza");
+    /// # })
+    /// ```
+    pub async fn search_embedding_with_options(
+        &self,
+        vector: Vec<f32>,
+        with_tags: impl Into<TagFilter>,
+        top_n: u32,
+        options: &SearchOptions,
+    ) -> Result<Vec<NearestNeighborsResult>, Error> {
+        self.search_embedding_reporting(vector, with_tags.into(), top_n, options, NO_PROGRESS)
+            .await
+    }
+
+    /// Search like [`Victor::search_embedding_with_options`], but call `on_progress` with the best matches found
+    /// so far each time another part of the database has been scanned, so they can be shown before the search
+    /// finishes. The final results are returned as usual.
+    ///
+    /// Provisional matches are only as good as what's been scanned so far: later ones can replace them,
+    /// and their order can change.
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// # let mut victor = Db::new(DirectoryHandle::default());
+    /// use victor_db::SearchOptions;
+    ///
+    /// victor.add_single_embedding("Pepperoni pizza", vec![0.1, 0.2, 0.3], vec!["Pizza Flavors"]).await.unwrap();
+    ///
+    /// let mut updates = 0;
+    /// let nearest = victor
+    ///     .search_embedding_with_progress(vec![0.1, 0.2, 0.3], vec!["Pizza Flavors"], 10, &SearchOptions::default(), |provisional| {
+    ///         updates += 1;
+    ///         println!("{} matches so far", provisional.len());
+    ///     })
+    ///     .await
+    ///     .unwrap();
+    /// assert_eq!(nearest.len(), 1);
+    /// assert_eq!(updates, 1);
+    /// # })
+    /// ```
+    pub async fn search_embedding_with_progress(
+        &self,
+        vector: Vec<f32>,
+        with_tags: impl Into<TagFilter>,
+        top_n: u32,
+        options: &SearchOptions,
+        on_progress: impl FnMut(&[NearestNeighborsResult]),
+    ) -> Result<Vec<NearestNeighborsResult>, Error> {
+        self.search_embedding_reporting(vector, with_tags.into(), top_n, options, Some(on_progress))
+            .await
+    }
+
+    async fn search_embedding_reporting(
+        &self,
+        vector: Vec<f32>,
+        with_tags: TagFilter,
+        top_n: u32,
+        options: &SearchOptions,
+        on_progress: Option<impl FnMut(&[NearestNeighborsResult])>,
+    ) -> Result<Vec<NearestNeighborsResult>, Error> {
+        let request = SearchRequest::new(None, Some(&vector), &with_tags, top_n, options);
+        self.hooks.searching(&request)?;
+        let timer = telemetry::Search::start("vector");
+        let mut results = self
+            .find_nearest(
+                vector.clone(),
+                with_tags.clone(),
+                top_n,
+                options,
+                on_progress,
+            )
+            .await?;
+        self.hooks.searched(&request, &mut results);
+        timer.finish(results.len());
+        Ok(results)
+    }
+
+    /// Search for the nearest neighbors to `vector`, without running the search hooks, see
+    /// [`Victor::search_embedding_with_options`].
+    async fn find_nearest(
+        &self,
+        mut vector: Vec<f32>,
+        with_tags: TagFilter,
+        top_n: u32,
+        options: &SearchOptions,
+        on_progress: Option<impl FnMut(&[NearestNeighborsResult])>,
+    ) -> Result<Vec<NearestNeighborsResult>, Error> {
+        format::check(&self.root).await?;
+        let settings = self.settings().await?;
+        settings.check_dimension(vector.len())?;
+        settings.normalize(&mut vector);
+
+        let is_projected: bool = self
+            .root
+            .get_file_handle_with_options("eigen.bin", &GetFileHandleOptions { create: false })
+            .await
+            .is_ok();
+
+        let original = (options.rescore && self.retains_originals().await).then(|| vector.clone());
+        if is_projected {
+            let vector_projection = self.read_projection().await?;
+            vector = self.project_single_vector(vector, &vector_projection)?;
+        }
+
+        self.search_stored_vector(vector, original, with_tags, top_n, options, on_progress)
+            .await
+    }
+
+    /// Search the database for the other documents nearest to the document with the given id,
+    /// using its stored vector, so there's no need to keep a copy of it.
+    /// This will return the top `top_n` nearest neighbors, or `None` if no document with the given id exists.
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// # let mut victor = Db::new(DirectoryHandle::default());
+    /// use victor_db::Document;
+    ///
+    /// victor
+    ///     .add_documents(
+    ///         vec![
+    ///             Document::new("Pepperoni pizza", vec![0.1, 0.2, 0.3]),
+    ///             Document::new("Salami pizza", vec![0.1, 0.2, 0.25]),
+    ///             Document::new("Pineapple", vec![0.3, 0.2, 0.1]),
+    ///         ],
+    ///         vec!["Pizza Flavors"],
+    ///     )
+    ///     .await
+    ///     .unwrap();
+    ///
+    /// let id = victor.search_embedding(vec![0.1, 0.2, 0.3], vec!["Pizza Flavors"], 1).await.unwrap()[0].embedding.id;
+    /// let related = victor.search_similar(id, vec!["Pizza Flavors"], 1).await.unwrap().unwrap();
+    /// assert_eq!(related[0].content, "Salami pizza");
+    /// # })
+    /// ```
+    pub async fn search_similar(
+        &self,
+        id: Uuid,
+        with_tags: impl Into<TagFilter>,
+        top_n: u32,
+    ) -> Result<Option<Vec<NearestNeighborsResult>>, Error> {
+        format::check(&self.root).await?;
+        if tombstones::removed(&self.root).await?.contains(&id) {
+            return Ok(None);
+        }
+        let Some((_, _, _, embedding)) = self.locate_embedding(id).await? else {
+            return Ok(None);
+        };
+
+        let original = match self.retains_originals().await {
+            true => self.read_original_log().await?.0.remove(&id),
+            false => None,
+        };
+        let (with_tags, options) = (with_tags.into(), SearchOptions::default());
+        let request =
+            SearchRequest::new(None, Some(&embedding.vector), &with_tags, top_n, &options);
+        self.hooks.searching(&request)?;
+        let timer = telemetry::Search::start("similar");
+
+        // the document itself is the nearest, so look for one more
+        let mut nearest = self
+            .search_stored_vector(
+                embedding.vector.clone(),
+                original,
+                with_tags.clone(),
+                top_n.saturating_add(1),
+                &options,
+                NO_PROGRESS,
+            )
+            .await?;
+        nearest.retain(|result| result.embedding.id != id);
+        nearest.truncate(top_n as usize);
+        self.hooks.searched(&request, &mut nearest);
+        timer.finish(nearest.len());
+        Ok(Some(nearest))
+    }
+
+    /// Search the database for documents like the `positive` examples and unlike the `negative` ones,
+    /// by their stored vectors, like [`Victor::search_similar`] with more than one document.
+    /// The examples are combined into a single query, the mean of the positives pushed away from the mean of the
+    /// negatives, and are left out of the results.
+    /// This will return the top `top_n` nearest neighbors, or `None` if any of the examples doesn't exist.
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// # let mut victor = Db::new(DirectoryHandle::default());
+    /// use victor_db::Document;
+    ///
+    /// victor
+    ///     .add_documents(
+    ///         vec![
+    ///             Document::new("Pepperoni pizza", vec![1.0, 0.0, 0.1]),
+    ///             Document::new("Mushroom pizza", vec![1.0, 0.1, 0.0]),
+    ///             Document::new("Salami pizza", vec![0.9, 0.0, 0.5]),
+    ///             Document::new("Veggie pizza", vec![0.9, 0.5, 0.0]),
+    ///         ],
+    ///         vec!["Pizza Flavors"],
+    ///     )
+    ///     .await
+    ///     .unwrap();
+    /// let results = victor.search_embedding(vec![1.0, 0.0, 0.0], vec!["Pizza Flavors"], 4).await.unwrap();
+    /// let id = |content| results.iter().find(|result| result.content == content).unwrap().embedding.id;
+    /// let (pepperoni, mushroom) = (id("Pepperoni pizza"), id("Mushroom pizza"));
+    ///
+    /// // more meat, less vegetables
+    /// let recommended = victor.recommend([pepperoni], [mushroom], vec!["Pizza Flavors"], 1).await.unwrap().unwrap();
+    /// assert_eq!(recommended[0].content, "Salami pizza");
+    /// # })
+    /// ```
+    pub async fn recommend(
+        &self,
+        positive: impl IntoIterator<Item = Uuid>,
+        negative: impl IntoIterator<Item = Uuid>,
+        with_tags: impl Into<TagFilter>,
+        top_n: u32,
+    ) -> Result<Option<Vec<NearestNeighborsResult>>, Error> {
+        format::check(&self.root).await?;
+        let positive = positive.into_iter().collect::<Vec<_>>();
+        let negative = negative.into_iter().collect::<Vec<_>>();
+        if positive.is_empty() {
+            return Err(Error::InvalidInput(
+                "recommending needs at least one positive example".to_string(),
+            ));
+        }
+
+        let tombstones = tombstones::removed(&self.root).await?;
+        let mut originals = match self.retains_originals().await {
+            true => Some(self.read_original_log().await?.0),
+            false => None,
+        };
+        let (mut vectors, mut original_vectors) = (Vec::new(), Vec::new());
+        for &id in positive.iter().chain(&negative) {
+            if tombstones.contains(&id) {
+                return Ok(None);
+            }
+            let Some((_, _, _, embedding)) = self.locate_embedding(id).await? else {
+                return Ok(None);
+            };
+            vectors.push(embedding.vector);
+            original_vectors.push(
+                originals
+                    .as_mut()
+                    .and_then(|originals| originals.remove(&id)),
+            );
+        }
+
+        let metric = self.settings().await?.metric;
+        let (positive_vectors, negative_vectors) = vectors.split_at(positive.len());
+        let vector = similarity::recommendation(positive_vectors, negative_vectors, metric);
+        // only re-score by original vectors if every example has one
+        let original = original_vectors
+            .into_iter()
+            .collect::<Option<Vec<_>>>()
+            .map(|originals| {
+                let (positive, negative) = originals.split_at(positive.len());
+                similarity::recommendation(positive, negative, metric)
+            });
+
+        let (with_tags, options) = (with_tags.into(), SearchOptions::default());
+        let request = SearchRequest::new(None, Some(&vector), &with_tags, top_n, &options);
+        self.hooks.searching(&request)?;
+        let timer = telemetry::Search::start("recommend");
+
+        // the examples may be among the nearest, so look for that many more
+        let examples = positive.len() + negative.len();
+        let mut nearest = self
+            .search_stored_vector(
+                vector.clone(),
+                original,
+                with_tags.clone(),
+                top_n.saturating_add(examples as u32),
+                &options,
+                NO_PROGRESS,
+            )
+            .await?;
+        nearest.retain(|result| {
+            !positive.contains(&result.embedding.id) && !negative.contains(&result.embedding.id)
+        });
+        nearest.truncate(top_n as usize);
+        self.hooks.searched(&request, &mut nearest);
+        timer.finish(nearest.len());
+        Ok(Some(nearest))
+    }
+
+    /// Search for the nearest neighbors to a vector that's already like the stored ones (projected, if the
+    /// database is), see [`Victor::search_embedding_with_options`].
+    /// With the `original` full-precision vector, the best matches are re-scored by their original vectors.
+    /// `on_progress` is called with the best matches so far after each segment is scanned.
+    async fn search_stored_vector(
+        &self,
+        vector: Vec<f32>,
+        original: Option<Vec<f32>>,
+        with_tags: TagFilter,
+        top_n: u32,
+        options: &SearchOptions,
+        mut on_progress: Option<impl FnMut(&[NearestNeighborsResult])>,
+    ) -> Result<Vec<NearestNeighborsResult>, Error> {
+        let top_n = top_n as usize;
+        let settings = self.settings().await?;
+
+        // scan for more candidates than needed, so the ones that only look worse because of packing or
+        // projecting still get re-scored
+        let originals = match &original {
+            Some(_) if options.rescore => Some(self.read_original_log().await?.0),
+            _ => None,
+        };
+        // documents with more than one vector may take up several of the closest matches
+        let chunks = chunks::read(&self.root).await?;
+        let candidates = if originals.is_some() || !chunks.is_empty() {
+            top_n.saturating_mul(options.oversample.max(1))
+        } else {
+            top_n
+        };
+
+        // skip db files whose numeric metadata can't match the filter
+        let (_, index) = Index::load(&self.root).await?;
+        let with_tags = index.tag_filter(with_tags, options);
+        let segment_stats = self.read_segment_stats().await?;
+        let mut file_handles = Vec::new();
+        for (filename, file_handle) in index.open_files(&self.root, &with_tags).await? {
+            if let Some(stats) = segment_stats.get(&filename) {
+                if !options.filter.may_match(stats) {
+                    continue;
+                }
+            }
+            file_handles.push((filename, file_handle));
+        }
+
+        // metadata lives alongside the content, so only load it if we need to filter or score by it
+        // (or to show the matches found so far)
+        let contents = if !options.needs_documents() && on_progress.is_none() {
+            None
+        } else {
+            Some(self.read_content_log().await?)
+        };
+        let contents = contents.as_ref().map(|log| &log.0);
+        let tags_by_filename = match options.needs_tags() {
+            true => index.tags_by_filename(),
+            false => HashMap::new(),
+        };
+
+        let tombstones = tombstones::removed(&self.root).await?;
+        let hnsw_config = hnsw::read_config(&self.root).await?;
+        let lsh_config = lsh::read_config(&self.root).await?;
+
+        // with product quantization, score the stored codes directly instead of reconstructing every vector,
+        // and with binary quantization, compare bits before (optionally) re-ranking by vector
+        let codec = self.codec().await?;
+        let distance_table = match &codec {
+            Codec::Product(quantizer) => Some(quantizer.distance_table(&vector)?),
+            _ => None,
+        };
+        let query_bits = match &codec {
+            Codec::Binary(_) => Some(quantization::binarize(&vector)),
+            _ => None,
+        };
+        let added = match options.needs_added() {
+            true => Some(added::read(&self.root).await?),
+            false => None,
+        };
+        let query = Query {
+            vector: &vector,
+            top_n: candidates,
+            options,
+            metric: options.metric.unwrap_or(settings.metric),
+            codec: &codec,
+            distance_table: distance_table.as_ref(),
+            query_bits: query_bits.as_deref(),
+            contents,
+            tombstones: &tombstones,
+            chunks: &chunks,
+            added: added.as_ref(),
+            tags_by_filename: &tags_by_filename,
+        };
+
+        // read db files (or chunks of them) in batches, scanning each batch before reading the next,
+        // so only a bounded amount of the database is in memory at once
+        let mut scanned = Vec::new();
+        let mut batch = Vec::new();
+        let mut batch_size = 0;
+        for (filename, file_handle) in file_handles {
+            let segments = match index.combined(&filename) {
+                Some(combined) => {
+                    self.read_combined_segments(combined, &with_tags, &file_handle)
+                        .await?
+                }
+                None => {
+                    self.read_segments(
+                        filename,
+                        file_handle,
+                        &codec,
+                        hnsw_config,
+                        lsh_config,
+                        false,
+                    )
+                    .await?
+                }
+            };
+            for segment in segments {
+                telemetry::scanned(segment.data.size());
+                batch_size += segment.data.size();
+                batch.push(segment);
+
+                if batch_size >= SCAN_BATCH_SIZE || on_progress.is_some() {
+                    scanned.extend(Self::scan_segments(&query, &batch)?);
+                    batch.clear();
+                    batch_size = 0;
+
+                    // merge the best matches so far
+                    scanned.sort_by(|(a, _, _), (b, _, _)| b.total_cmp(a));
+                    Self::limit_scanned(&mut scanned, candidates, &tags_by_filename, options);
+
+                    if let (Some(on_progress), Some(contents)) = (&mut on_progress, &contents) {
+                        let best = Self::best_matches(
+                            scanned.clone(),
+                            originals.as_ref(),
+                            original.as_ref(),
+                            &tags_by_filename,
+                            top_n,
+                            &query,
+                        )?;
+                        let ids = best
+                            .iter()
+                            .map(|(_, embedding, _)| embedding.id)
+                            .collect::<Vec<_>>();
+                        let found = Self::find_contents(contents, &ids)?;
+                        on_progress(&Self::neighbors(
+                            best,
+                            found,
+                            &tags_by_filename,
+                            query.metric,
+                            options,
+                        ));
+                    }
+                }
+            }
+        }
+        scanned.extend(Self::scan_segments(&query, &batch)?);
+
+        // merge the best matches from each db file
+        scanned.sort_by(|(a, _, _), (b, _, _)| b.total_cmp(a));
+        Self::limit_scanned(&mut scanned, candidates, &tags_by_filename, options);
+        let scanned = Self::best_matches(
+            scanned,
+            originals.as_ref(),
+            original.as_ref(),
+            &tags_by_filename,
+            top_n,
+            &query,
+        )?;
+
+        // only the final results need their content, which is read all at once
+        // (or reused, if it was already read for filtering)
+        let ids = scanned
+            .iter()
+            .map(|(_, embedding, _)| embedding.id)
+            .collect::<Vec<_>>();
+        let found = match &contents {
+            Some(contents) => Self::find_contents(contents, &ids)?,
+            None => self.get_contents(&ids).await?,
+        };
+        Ok(Self::neighbors(
+            scanned,
+            found,
+            &tags_by_filename,
+            query.metric,
+            options,
+        ))
+    }
+
+    /// The exact similarity of every document to `vector`, found by comparing it with every stored vector
+    /// instead of searching. Each vector is compared at full precision: its original, if the database retains
+    /// originals, or else the stored vector (which is projected and quantized like the database is). Documents
+    /// with several vectors get the similarity of their best one. For [`crate::eval::evaluate_recall`].
+    pub(crate) async fn exact_similarities(
+        &self,
+        vector: &[f32],
+    ) -> Result<HashMap<Uuid, f32>, Error> {
+        format::check(&self.root).await?;
+        let settings = self.settings().await?;
+        settings.check_dimension(vector.len())?;
+        let mut vector = vector.to_vec();
+        settings.normalize(&mut vector);
+        let vector = vector.as_slice();
+
+        let is_projected = self
+            .root
+            .get_file_handle_with_options("eigen.bin", &GetFileHandleOptions { create: false })
+            .await
+            .is_ok();
+        let projected = match is_projected {
+            true => {
+                let vector_projection = self.read_projection().await?;
+                Some(self.project_single_vector(vector.to_vec(), &vector_projection)?)
+            }
+            false => None,
+        };
+
+        let originals = self.read_original_log().await?.0;
+        let tombstones = tombstones::removed(&self.root).await?;
+        let chunks = chunks::read(&self.root).await?;
+
+        let mut similarities = HashMap::new();
+        for embedding in self.get_all_embeddings().await? {
+            if tombstones.contains(&embedding.id) {
+                continue;
+            }
+            let (stored, query) = match (originals.get(&embedding.id), &projected) {
+                (Some(original), _) => (original, vector),
+                (None, Some(projected)) => (&embedding.vector, projected.as_slice()),
+                (None, None) => (&embedding.vector, vector),
+            };
+            let similarity = settings.metric.similarity(stored, query).map_err(|_| {
+                Error::DimensionMismatch {
+                    expected: stored.len(),
+                    found: query.len(),
+                }
+            })?;
+
+            let document = chunks.get(&embedding.id).copied().unwrap_or(embedding.id);
+            let best = similarities.entry(document).or_insert(f32::NEG_INFINITY);
+            *best = best.max(similarity);
+        }
+
+        Ok(similarities)
+    }
+
+    /// The best `top_n` of the `candidates` a search scanned (which must be sorted best first), re-scored by their
+    /// original vectors if there are `originals` to compare with the original query.
+    fn best_matches(
+        mut candidates: Vec<(f32, Embedding, String)>,
+        originals: Option<&HashMap<Uuid, Vec<f32>>>,
+        original: Option<&Vec<f32>>,
+        tags_by_filename: &HashMap<String, BTreeSet<String>>,
+        top_n: usize,
+        query: &Query,
+    ) -> Result<Vec<(f32, Embedding, String)>, Error> {
+        let Query {
+            metric,
+            chunks,
+            options,
+            ..
+        } = *query;
+        if let (Some(originals), Some(original)) = (originals, original) {
+            for (similarity, embedding, filename) in &mut candidates {
+                // embeddings stored before their originals were kept keep their score
+                if let Some(vector) = originals.get(&embedding.id) {
+                    let rescored = metric.similarity(vector, original).map_err(|_| {
+                        Error::DimensionMismatch {
+                            expected: vector.len(),
+                            found: original.len(),
+                        }
+                    })?;
+                    *similarity = query.score(rescored, embedding.id, filename);
+                }
+            }
+            candidates.sort_by(|(a, _, _), (b, _, _)| b.total_cmp(a));
+        }
+        if !chunks.is_empty() {
+            candidates = Self::aggregate(candidates, chunks, options.aggregation);
+        }
+        Self::limit_scanned(&mut candidates, top_n, tags_by_filename, options);
+        if let Some(min_similarity) = options.min_similarity {
+            candidates.retain(|(similarity, _, _)| *similarity >= min_similarity);
+        }
+        Ok(candidates)
+    }
+
+    /// Keep the best `top_n` of the `scanned` matches (which must be sorted best first), or the best `top_n` of each
+    /// group, see [`SearchOptions::group_by`].
+    fn limit_scanned(
+        scanned: &mut Vec<(f32, Embedding, String)>,
+        top_n: usize,
+        tags_by_filename: &HashMap<String, BTreeSet<String>>,
+        options: &SearchOptions,
+    ) {
+        options.limit(scanned, top_n, |(_, _, filename)| {
+            tags_by_filename
+                .get(filename)
+                .map(|tags| tags.iter().cloned().collect())
+                .unwrap_or_default()
+        });
+    }
+
+    /// Search results for the best matches of a search by `metric`, with their `contents` in the same order.
+    fn neighbors(
+        best: Vec<(f32, Embedding, String)>,
+        contents: Vec<Content>,
+        tags_by_filename: &HashMap<String, BTreeSet<String>>,
+        metric: Metric,
+        options: &SearchOptions,
+    ) -> Vec<NearestNeighborsResult> {
+        let mut nearest = Vec::with_capacity(best.len());
+        for ((similarity, embedding, filename), content) in best.into_iter().zip(contents) {
+            let boost = options.boost(tags_by_filename.get(&filename).into_iter().flatten());
+            let mut result = NearestNeighborsResult {
+                similarity,
+                score: metric.score(similarity),
+                raw: (options.scorer.is_none() && boost.is_none()).then(|| metric.raw(similarity)),
+                breakdown: Some(ScoreBreakdown {
+                    vector: Some(match boost {
+                        Some(boost) => search::boosted(similarity, 1.0 / boost),
+                        None => similarity,
+                    }),
+                    boost,
+                    ..Default::default()
+                }),
+                embedding,
+                content: content.content,
+                external_id: content.external_id,
+                tags: Self::tags_for(tags_by_filename, &filename),
+            };
+            options.exclude_fields(&mut result);
+            nearest.push(result);
+        }
+        nearest.sort();
+        nearest.reverse();
+        nearest
+    }
+
+    /// Combine the matches of each document's vectors into one, as the document's own embedding with the vector
+    /// that matched best. `scanned` must be sorted best first, and so is the result.
+    fn aggregate(
+        scanned: Vec<(f32, Embedding, String)>,
+        chunks: &HashMap<Uuid, Uuid>,
+        aggregation: Aggregation,
+    ) -> Vec<(f32, Embedding, String)> {
+        let mut aggregated: Vec<(f32, Embedding, String)> = Vec::with_capacity(scanned.len());
+        let mut positions = HashMap::new();
+        for (similarity, embedding, filename) in scanned {
+            let id = chunks.get(&embedding.id).copied().unwrap_or(embedding.id);
+            match positions.entry(id) {
+                Entry::Vacant(entry) => {
+                    entry.insert(aggregated.len());
+                    let embedding = Embedding {
+                        id,
+                        vector: embedding.vector,
+                    };
+                    aggregated.push((similarity, embedding, filename));
+                }
+                Entry::Occupied(entry) => {
+                    if aggregation == Aggregation::Sum {
+                        aggregated[*entry.get()].0 += similarity;
+                    }
+                }
+            }
+        }
+        aggregated.sort_by(|(a, _, _), (b, _, _)| b.total_cmp(a));
+        aggregated
+    }
+
+    /// Search the database by both meaning and keywords, so exact terms like error codes and product SKUs
+    /// aren't missed. An embedding will be generated for `content`, which is also searched for word by word.
+    /// This will return the top `top_n` results, scored as described in [`Fusion`]
+    /// (or re-ranked, if there's a reranker, see [`Victor::with_reranker`]).
+    ///
+    /// ```rust
+    /// # #[cfg(feature = "embeddings")]
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// # let mut victor = Db::new(DirectoryHandle::default());
+    /// victor.search_hybrid("error E1234", vec!["Support Tickets"], 10).await.unwrap();
+    /// # });
+    /// ```
+    pub async fn search_hybrid(
+        &self,
+        content: impl Into<String>,
+        with_tags: impl Into<TagFilter>,
+        top_n: u32,
+    ) -> Result<Vec<NearestNeighborsResult>, Error> {
+        let content = content.into();
+        let vector = self
+            .embed(vec![content.clone()])
+            .await?
+            .into_iter()
+            .next()
+            .ok_or_else(|| Error::Embedding("no embedding was generated".to_string()))?;
+        self.search_hybrid_embedding(
+            vector,
+            &content,
+            with_tags,
+            top_n,
+            &SearchOptions::default(),
+        )
+        .await
+    }
+
+    /// Search the database for the nearest neighbors to `vector` and for documents containing the terms in
+    /// `keywords`, combining the two with [`SearchOptions::fusion`].
+    /// This will return the top `top_n` results, whose `similarity` is their combined score
+    /// (or with a reranker, their score for `keywords`, see [`Victor::with_reranker`]).
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// # let mut victor = Db::new(DirectoryHandle::default());
+    /// use victor_db::{Document, Fusion, SearchOptions};
+    ///
+    /// victor
+    ///     .add_documents(
+    ///         vec![
+    ///             Document::new("The printer shows error E1234", vec![0.0, 1.0]),
+    ///             Document::new("The printer is out of paper", vec![1.0, 0.0]),
+    ///         ],
+    ///         Vec::<String>::new(),
+    ///     )
+    ///     .await
+    ///     .unwrap();
+    ///
+    /// // the closest vector is about paper, but only one document mentions the error code
+    /// let options = SearchOptions::default().with_fusion(Fusion::Weighted { keyword_weight: 0.7 });
+    /// let results = victor
+    ///     .search_hybrid_embedding(vec![1.0, 0.1], "E1234", Vec::<String>::new(), 1, &options)
+    ///     .await
+    ///     .unwrap();
+    /// assert_eq!(results[0].content, "The printer shows error E1234");
+    /// # })
+    /// ```
+    ///
+    /// [`Fusion`]: crate::Fusion
+    pub async fn search_hybrid_embedding(
+        &self,
+        vector: Vec<f32>,
+        keywords: &str,
+        with_tags: impl Into<TagFilter>,
+        top_n: u32,
+        options: &SearchOptions,
+    ) -> Result<Vec<NearestNeighborsResult>, Error> {
+        let with_tags = with_tags.into();
+        let request = SearchRequest::new(Some(keywords), Some(&vector), &with_tags, top_n, options);
+        self.hooks.searching(&request)?;
+        let timer = telemetry::Search::start("hybrid");
+        let reranked = self.rerank_candidates(top_n, options);
+        let candidates = reranked.saturating_mul(HYBRID_CANDIDATES);
+
+        let by_vector = self
+            .find_nearest(
+                vector.clone(),
+                with_tags.clone(),
+                candidates,
+                &options.with_all_fields(),
+                NO_PROGRESS,
+            )
+            .await?;
+        let mut by_keyword = self.keyword_matches(keywords, &with_tags, options).await?;
+        by_keyword.truncate(candidates as usize);
+
+        let ranked = |results: &[NearestNeighborsResult]| {
+            results
+                .iter()
+                .map(|result| (result.similarity, result.embedding.id))
+                .collect::<Vec<_>>()
+        };
+        let fused = keywords::fuse(&ranked(&by_vector), &ranked(&by_keyword), options.fusion);
+
+        let keyword_scores = by_keyword
+            .iter()
+            .filter_map(|result| Some((result.embedding.id, result.breakdown?.keyword?)))
+            .collect::<HashMap<_, _>>();
+        let mut results = by_keyword
+            .into_iter()
+            .chain(by_vector)
+            .map(|result| (result.embedding.id, result))
+            .collect::<HashMap<_, _>>();
+        let fused = fused
+            .into_iter()
+            .take(reranked as usize)
+            .filter_map(|(score, id)| {
+                let result = results.remove(&id)?;
+                let breakdown = ScoreBreakdown {
+                    keyword: keyword_scores.get(&id).copied(),
+                    fused: Some(score),
+                    ..result.breakdown.unwrap_or_default()
+                };
+                Some(NearestNeighborsResult {
+                    similarity: score,
+                    score: keywords::fused_score(score, options.fusion),
+                    raw: None,
+                    breakdown: Some(breakdown),
+                    ..result
+                })
+            })
+            .collect();
+        let mut results = self.rerank(keywords, fused, top_n, options).await?;
+        self.hooks.searched(&request, &mut results);
+        timer.finish(results.len());
+        Ok(results)
+    }
+
+    /// Search the database for documents containing the terms in `keywords`, scored with BM25.
+    /// This will return the top `top_n` matches, whose `similarity` is their BM25 score.
+    ///
+    /// Searches are faster with a keyword index, see [`Victor::build_keyword_index`].
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// # let mut victor = Db::new(DirectoryHandle::default());
+    /// victor.add_single_embedding("The printer shows error E1234", vec![0.1, 0.2, 0.3], vec!["Support"]).await.unwrap();
+    /// victor.add_single_embedding("The printer is out of paper", vec![0.3, 0.2, 0.1], vec!["Support"]).await.unwrap();
+    ///
+    /// let results = victor.search_keywords("e1234", vec!["Support"], 10).await.unwrap();
+    /// assert_eq!(results.len(), 1);
+    /// assert_eq!(results[0].content, "The printer shows error E1234");
+    /// # })
+    /// ```
+    pub async fn search_keywords(
+        &self,
+        keywords: &str,
+        with_tags: impl Into<TagFilter>,
+        top_n: u32,
+    ) -> Result<Vec<NearestNeighborsResult>, Error> {
+        let (with_tags, options) = (with_tags.into(), SearchOptions::default());
+        let request = SearchRequest::new(Some(keywords), None, &with_tags, top_n, &options);
+        self.hooks.searching(&request)?;
+        let timer = telemetry::Search::start("keyword");
+        let mut matches = self.keyword_matches(keywords, &with_tags, &options).await?;
+        matches.truncate(top_n as usize);
+        for result in &mut matches {
+            options.exclude_fields(result);
+        }
+        self.hooks.searched(&request, &mut matches);
+        timer.finish(matches.len());
+        Ok(matches)
+    }
+
+    /// Documents with any of the terms in `keywords` that match the tags, [`SearchOptions::filter`] and
+    /// [`SearchOptions::added`],
+    /// with their BM25 scores as their similarity, best first.
+    /// Fields aren't left out (see [`SearchOptions::exclude_fields`]), since they may still be re-ranked.
+    async fn keyword_matches(
+        &self,
+        keywords: &str,
+        with_tags: &TagFilter,
+        options: &SearchOptions,
+    ) -> Result<Vec<NearestNeighborsResult>, Error> {
+        format::check(&self.root).await?;
+
+        let tombstones = tombstones::removed(&self.root).await?;
+        let mut contents = self.read_contents().await?;
+        contents.retain(|id, _| !tombstones.contains(id));
+
+        // without an index, the terms of every document have to be found first
+        let index = match keywords::read_index(&self.root).await? {
+            Some(index) => index,
+            None => Self::keyword_index_for(&contents),
+        };
+        let mut scores = index
+            .search(keywords)
+            .into_iter()
+            .map(|(score, id)| (id, score))
+            .collect::<HashMap<_, _>>();
+        let added = match options.needs_added() {
+            true => Some(added::read(&self.root).await?),
+            false => None,
+        };
+        if let Some(added) = &added {
+            scores.retain(|id, _| options.matches_added(added.get(id)));
+        }
+
+        // scores don't depend on tags, so only the matching db files need to be read for the embeddings
+        let codec = self.codec().await?;
+        let (_, index) = Index::load(&self.root).await?;
+        let with_tags = &index.tag_filter(with_tags.clone(), options);
+        let tags_by_filename = match options.needs_tags() {
+            true => index.tags_by_filename(),
+            false => HashMap::new(),
+        };
+        let mut matches = Vec::new();
+        for (filename, file_handle) in index.open_files(&self.root, with_tags).await? {
+            for (filename, embedding) in self
+                .read_matching_embeddings(&index, &filename, &file_handle, &codec, with_tags)
+                .await?
+            {
+                let Some(score) = scores.remove(&embedding.id) else {
+                    continue;
+                };
+                let Some(content) = contents.remove(&embedding.id) else {
+                    continue;
+                };
+                let document = DocumentMeta {
+                    id: embedding.id,
+                    external_id: content.external_id.as_deref(),
+                    metadata: &content.metadata,
+                    added: added
+                        .as_ref()
+                        .and_then(|added| added.get(&embedding.id).copied()),
+                    tags: tags_by_filename.get(&filename).unwrap_or(&NO_TAGS),
+                };
+                if options.matches_document(&document) {
+                    let boost =
+                        options.boost(tags_by_filename.get(&filename).into_iter().flatten());
+                    let similarity = match boost {
+                        Some(boost) => search::boosted(score, boost),
+                        None => score,
+                    };
+                    matches.push(NearestNeighborsResult {
+                        similarity,
+                        score: keywords::bm25_score(similarity),
+                        raw: None,
+                        breakdown: Some(ScoreBreakdown {
+                            keyword: Some(score),
+                            boost,
+                            ..Default::default()
+                        }),
+                        embedding,
+                        content: content.content,
+                        external_id: content.external_id,
+                        tags: Self::tags_for(&tags_by_filename, &filename),
+                    });
+                }
+            }
+        }
+
+        matches.sort();
+        matches.reverse();
+        Ok(matches)
+    }
+
+    /// Read a db file to be searched, along with its graph or signatures if they're up to date.
+    ///
+    /// Indexes refer to every embedding in the file, so indexed files are read whole,
+    /// but other files are read in chunks of about [`SCAN_CHUNK_SIZE`] bytes.
+    /// Compressed files are always read whole.
+    /// Either way, the file is checked against its checksum before it's searched.
+    async fn read_segments(
+        &self,
+        filename: String,
+        file_handle: D::FileHandleT,
+        codec: &Codec,
+        hnsw_config: Option<HnswConfig>,
+        lsh_config: Option<LshConfig>,
+        whole: bool,
+    ) -> Result<Vec<Segment>, Error> {
+        let header_size = std::mem::size_of::<u32>();
+
+        // a file that hasn't changed since it was cached doesn't need reading again
+        let version = self.cache_version(&filename, &file_handle).await?;
+        let cached = match self.cached(&filename, "segments", version) {
+            Some(Cached::Embeddings {
+                embeddings,
+                record_size,
+            }) => Some((SegmentData::Decoded(embeddings), record_size)),
+            Some(Cached::Records {
+                records,
+                record_size,
+            }) => Some((SegmentData::Cached(records), record_size)),
+            _ => None,
+        };
+        if let Some((data, record_size)) = cached {
+            let mut segment = Segment {
+                filename,
+                data,
+                record_size,
+                graph: None,
+                signatures: None,
+            };
+            (segment.graph, segment.signatures) = self
+                .read_segment_indexes(&segment.filename, segment.len(), hnsw_config, lsh_config)
+                .await?;
+            return Ok(vec![segment]);
+        }
+
+        if self.settings().await?.compression != Compression::None {
+            let file = self.read_segment(&filename, &file_handle).await?;
+            let len = Self::get_records_by_file(&filename, &file)?.len();
+            if len == 0 {
+                return Ok(Vec::new());
+            }
+            let record_size = Self::get_embedding_size(&filename, &file)? as usize;
+            let (graph, signatures) = self
+                .read_segment_indexes(&filename, len, hnsw_config, lsh_config)
+                .await?;
+            let records = file[header_size..].to_vec();
+            return Ok(vec![Segment {
+                data: self.cache_records(&filename, version, records, record_size, codec)?,
+                filename,
+                record_size,
+                graph,
+                signatures,
+            }]);
+        }
+
+        let Some(record_size) = Self::read_record_size(&filename, &file_handle).await? else {
+            return Ok(Vec::new());
+        };
+
+        // mapped files are paged in by the OS as they're scanned, so they never need to be chunked
+        let map = file_handle.map().await?;
+        let data_size = file_handle.size().await? - header_size;
+        let len = data_size / record_size;
+
+        let (graph, signatures) = self
+            .read_segment_indexes(&filename, len, hnsw_config, lsh_config)
+            .await?;
+
+        if let Some(map) = map {
+            checksum::verify(&self.root, &filename, (*map).as_ref()).await?;
+            return Ok(vec![Segment {
+                filename,
+                data: SegmentData::Mapped(map),
+                record_size,
+                graph,
+                signatures,
+            }]);
+        }
+
+        // the file is checksummed as it's read, header included
+        let mut hasher = crc32fast::Hasher::new();
+        hasher
+            .update(&bincode::serialize(&(record_size as u32)).expect("Failed to serialize size"));
+
+        // files that will be cached are read whole, like indexed ones, rather than in chunks
+        if whole
+            || graph.is_some()
+            || signatures.is_some()
+            || (version.is_some() && self.cache().fits(data_size))
+        {
+            let data = file_handle.read_range(header_size, data_size).await?;
+            hasher.update(&data);
+            checksum::verify_checksum(&self.root, &filename, hasher.finalize()).await?;
+            let data = self.cache_records(&filename, version, data, record_size, codec)?;
+            return Ok(vec![Segment {
+                filename,
+                data,
+                record_size,
+                graph,
+                signatures,
+            }]);
+        }
+
+        let chunk_size = (SCAN_CHUNK_SIZE / record_size).max(1) * record_size;
+        let mut segments = Vec::new();
+        for offset in (0..data_size).step_by(chunk_size) {
+            let data = file_handle
+                .read_range(header_size + offset, chunk_size)
+                .await?;
+            hasher.update(&data);
+            segments.push(Segment {
+                filename: filename.clone(),
+                data: SegmentData::Read(data),
+                record_size,
+                graph: None,
+                signatures: None,
+            });
+        }
+        checksum::verify_checksum(&self.root, &filename, hasher.finalize()).await?;
+        Ok(segments)
+    }
+
+    /// Read the embeddings in a combined db file whose tags match `tags`, as a segment for each of its tag sets,
+    /// named like [`Combined::ranges`] so their results get the right tags. Combined files are never indexed,
+    /// and they're small, so they're read whole.
+    async fn read_combined_segments(
+        &self,
+        combined: &Combined,
+        tags: &TagFilter,
+        file_handle: &D::FileHandleT,
+    ) -> Result<Vec<Segment>, Error> {
+        let file = self.read_segment(&combined.filename, file_handle).await?;
+        let records = Self::get_records_by_file(&combined.filename, &file)?;
+        Self::check_tag_map(combined, records.len())?;
+        let Some(record_size) = records.first().map(|record| record.len()) else {
+            return Ok(Vec::new());
+        };
+
+        let header_size = std::mem::size_of::<u32>();
+        Ok(combined
+            .ranges()
+            .into_iter()
+            .filter(|(_, _, range_tags)| tags.matches(range_tags))
+            .map(|(range, filename, _)| Segment {
+                filename,
+                data: SegmentData::Read(
+                    file[header_size + range.start * record_size
+                        ..header_size + range.end * record_size]
+                        .to_vec(),
+                ),
+                record_size,
+                graph: None,
+                signatures: None,
+            })
+            .collect())
+    }
+
+    /// Make sure a combined db file's tag map covers all `len` of its embeddings.
+    fn check_tag_map(combined: &Combined, len: usize) -> Result<(), Error> {
+        if combined.len() != len {
+            return Err(Error::Corrupted {
+                file: combined.filename.clone(),
+                reason: format!(
+                    "combined.bin lists {} embeddings, but the file has {len}",
+                    combined.len()
+                ),
+            });
+        }
+        Ok(())
+    }
+
+    /// The graph and signatures for the db file `filename`, if it's indexed and they cover all `len` of its embeddings.
+    async fn read_segment_indexes(
+        &self,
+        filename: &str,
+        len: usize,
+        hnsw_config: Option<HnswConfig>,
+        lsh_config: Option<LshConfig>,
+    ) -> Result<(Option<Hnsw>, Option<Lsh>), Error> {
+        let graph = match hnsw_config {
+            Some(_) => hnsw::read_graph(&self.root, filename)
+                .await?
+                .filter(|graph| graph.len() == len),
+            None => None,
+        };
+        let signatures = match lsh_config {
+            Some(config) if len >= config.exact_below => lsh::read_signatures(&self.root, filename)
+                .await?
+                .filter(|lsh| lsh.len() == len),
+            _ => None,
+        };
+        Ok((graph, signatures))
+    }
+
+    /// The size of each embedding in a db file, read from its header without reading the rest of the file.
+    /// Returns `None` if the file is empty.
+    async fn read_record_size(
+        filename: &str,
+        file_handle: &D::FileHandleT,
+    ) -> Result<Option<usize>, Error> {
+        let header_size = std::mem::size_of::<u32>();
+
+        // files are created empty, and only get a header once embeddings are written
+        let file_size = file_handle.size().await?;
+        if file_size == 0 {
+            return Ok(None);
+        }
+
+        let header = file_handle.read_range(0, header_size).await?;
+        let record_size = Self::get_embedding_size(filename, &header)? as usize;
+
+        // sanity check
+        let data_size = file_size.saturating_sub(header_size);
+        if record_size == 0 || !data_size.is_multiple_of(record_size) {
+            return Err(Error::Corrupted {
+                file: filename.to_string(),
+                reason: format!(
+                    "file_size ({data_size} after subtracting header size {header_size}) was not a multiple of embedding_size ({record_size})"
+                ),
+            });
+        }
+
+        Ok(Some(record_size))
+    }
+
+    /// Scan each of `segments`, in parallel on native.
+    /// Find the `query.top_n` embeddings in each of `segments` most similar to the query,
+    /// as `(similarity, embedding, filename)` in no particular order.
+    fn scan_segments(
+        query: &Query,
+        segments: &[Segment],
+    ) -> Result<Vec<(f32, Embedding, String)>, Error> {
+        #[cfg(not(target_arch = "wasm32"))]
+        let results = {
+            use rayon::prelude::*;
+
+            segments
+                .par_iter()
+                .map(|segment| Self::scan_segment(query, segment))
+                .collect::<Vec<_>>()
+        };
+        #[cfg(target_arch = "wasm32")]
+        let results = segments
+            .iter()
+            .map(|segment| Self::scan_segment(query, segment))
+            .collect::<Vec<_>>();
+
+        let mut scanned = Vec::new();
+        for (result, segment) in results.into_iter().zip(segments) {
+            scanned.extend(
+                result?.into_iter().map(|(similarity, embedding)| {
+                    (similarity, embedding, segment.filename.clone())
+                }),
+            );
+        }
+        Ok(scanned)
+    }
+
+    /// Find the `query.top_n` embeddings in `segment` most similar to the query,
+    /// as `(similarity, embedding)` pairs in no particular order.
+    fn scan_segment(query: &Query, segment: &Segment) -> Result<Vec<(f32, Embedding)>, Error> {
+        let Query {
+            vector,
+            top_n,
+            options,
+            metric,
+            codec,
+            distance_table,
+            query_bits,
+            contents,
+            tombstones,
+            chunks,
+            added,
+            tags_by_filename: _,
+        } = *query;
+        let filename = &segment.filename;
+        let len = segment.len();
+
+        let compare = |stored: &[f32]| {
+            metric
+                .similarity(stored, vector)
+                .map_err(|_| Error::DimensionMismatch {
+                    expected: stored.len(),
+                    found: vector.len(),
+                })
+        };
+
+        // use the file's graph or signatures to narrow down the candidates,
+        // otherwise fall back to comparing against every embedding
+        let vectors = SegmentVectors::new(segment, codec);
+        let nodes = match (&segment.graph, &segment.signatures) {
+            (Some(graph), _) if len > 0 && vectors.vector(0).len() == vector.len() => {
+                let score = hnsw_score(metric);
+                graph.search(vector, options.ef_search.max(top_n), &vectors, &score)
+            }
+            // if too few embeddings share a bucket with the query, scan everything instead
+            (_, Some(signatures)) if signatures.dimension() == vector.len() => {
+                match signatures.candidates(vector) {
+                    nodes if nodes.len() >= top_n => nodes,
+                    _ => (0..len).collect(),
+                }
+            }
+            _ => (0..len).collect(),
+        };
+        vectors.check()?;
+
+        if query_bits.is_some() && len > 0 {
+            let first = Candidate::decode(segment, query, 0)?;
+            if let Candidate::Binary(embedding) = first {
+                if embedding.dimension as usize != vector.len() {
+                    return Err(Error::DimensionMismatch {
+                        expected: embedding.dimension as usize,
+                        found: vector.len(),
+                    });
+                }
+            }
+        }
+
+        // only the candidates are decoded, in the form they're scored in
+        let mut candidates = nodes
+            .into_iter()
+            .map(|node| Ok((node, Candidate::decode(segment, query, node)?)))
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        if !tombstones.is_empty() {
+            candidates.retain(|(_, candidate)| !tombstones.contains(&candidate.id()));
+        }
+        if let Some(added) = added {
+            // extra vectors were added with their document
+            candidates.retain(|(_, candidate)| {
+                let id = candidate.id();
+                options.matches_added(added.get(chunks.get(&id).unwrap_or(&id)))
+            });
+        }
+        if contents.is_some() {
+            // extra vectors are filtered by their document
+            candidates.retain(|(_, candidate)| {
+                query
+                    .document(candidate.id(), filename)
+                    .is_some_and(|document| options.matches_document(&document))
+            });
+        }
+
+        // only the closest matches by Hamming distance are re-ranked by their vectors
+        if let (Some(query_bits), Codec::Binary(BinaryConfig { rerank: true })) =
+            (query_bits, codec)
+        {
+            candidates.sort_by_cached_key(|(_, candidate)| match candidate {
+                Candidate::Binary(binary) => quantization::hamming(&binary.bits, query_bits),
+                _ => 0,
+            });
+            candidates.truncate(top_n.saturating_mul(options.oversample.max(1)));
+        }
+
+        let mut scored = candidates
+            .into_iter()
+            .map(|(node, candidate)| {
+                let sim = match (&candidate, distance_table, query_bits) {
+                    (Candidate::Coded(coded), Some(table), _) => {
+                        table.similarity(metric, &coded.codes)
+                    }
+                    (Candidate::Binary(binary), _, Some(query_bits)) => {
+                        if binary.vector.is_empty() {
+                            let distance = quantization::hamming(&binary.bits, query_bits);
+                            quantization::hamming_similarity(distance, vector.len())
+                        } else {
+                            compare(&binary.vector)?
+                        }
+                    }
+                    (Candidate::Vector(embedding), _, _) => compare(&embedding.vector)?,
+                    _ => unreachable!("candidates are decoded in the form they're scored in"),
+                };
+                Ok((query.score(sim, candidate.id(), filename), node, candidate))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        // find max similarity in this file
+        if scored.len() > top_n {
+            scored.select_nth_unstable_by(top_n, |(a, _, _), (b, _, _)| b.total_cmp(a));
+            scored.truncate(top_n);
+        }
+
+        scored
+            .into_iter()
+            .map(|(sim, node, candidate)| {
+                let embedding = match candidate {
+                    Candidate::Vector(embedding) => embedding.into_owned(),
+                    _ => segment.embedding(codec, node)?.into_owned(),
+                };
+                Ok((sim, embedding))
+            })
+            .collect()
+    }
+
+    /// Build an HNSW graph for every db file, so searches don't have to compare against every embedding.
+    /// Once built, the graphs are kept up to date as documents are added or updated.
+    ///
+    /// Searching a graph is approximate: raise [`SearchOptions::ef_search`] to trade speed for recall.
+    /// Calling this again rebuilds the graphs with the new `config`.
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// # let mut victor = Db::new(DirectoryHandle::default());
+    /// use victor_db::HnswConfig;
+    ///
+    /// victor.add_single_embedding("Pepperoni pizza", vec![0.1, 0.2, 0.3], vec!["Pizza Flavors"]).await.unwrap();
+    /// victor.build_hnsw_index(HnswConfig::default()).await.unwrap();
+    ///
+    /// // added after the index was built, but still indexed
+    /// victor.add_single_embedding("Cheese pizza", vec![0.3, 0.2, 0.1], vec!["Pizza Flavors"]).await.unwrap();
+    ///
+    /// let nearest = victor.search_embedding(vec![0.3, 0.2, 0.1], vec!["Pizza Flavors"], 1).await.unwrap();
+    /// assert_eq!(nearest[0].content, "Cheese pizza");
+    /// # })
+    /// ```
+    pub async fn build_hnsw_index(&mut self, config: HnswConfig) -> Result<(), Error> {
+        self.remove_lsh_index().await?;
+        hnsw::write_config(&self.root, &config).await?;
+        self.rebuild_indexes().await
+    }
+
+    /// Build an LSH index for every db file: a cheaper, more insert-friendly alternative to
+    /// [`Victor::build_hnsw_index`], which works well for very high-dimensional embeddings.
+    /// Once built, the index is kept up to date as documents are added or updated.
+    ///
+    /// Searches only compare against embeddings that share a hash bucket with the query,
+    /// falling back to comparing against everything for small db files (see [`LshConfig::exact_below`])
+    /// or when too few embeddings share a bucket.
+    /// A database has at most one approximate index, so this replaces any HNSW index.
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// # let mut victor = Db::new(DirectoryHandle::default());
+    /// use victor_db::LshConfig;
+    ///
+    /// victor.add_single_embedding("Pepperoni pizza", vec![0.1, 0.2, 0.3], vec!["Pizza Flavors"]).await.unwrap();
+    /// victor.build_lsh_index(LshConfig::default()).await.unwrap();
+    ///
+    /// let nearest = victor.search_embedding(vec![0.1, 0.2, 0.3], vec!["Pizza Flavors"], 1).await.unwrap();
+    /// assert_eq!(nearest[0].content, "Pepperoni pizza");
+    /// # })
+    /// ```
+    pub async fn build_lsh_index(&mut self, config: LshConfig) -> Result<(), Error> {
+        self.remove_hnsw_index().await?;
+        lsh::write_config(&self.root, &config).await?;
+        self.rebuild_indexes().await
+    }
+
+    /// Build an inverted index of the terms in every document's content, for [`Victor::search_keywords`] and
+    /// [`Victor::search_hybrid`]. Without one, every document's content is split into terms for each search.
+    /// Once built, the index is kept up to date as documents are added, updated, or deleted.
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// # let mut victor = Db::new(DirectoryHandle::default());
+    /// victor.build_keyword_index().await.unwrap();
+    /// victor.add_single_embedding("Pepperoni pizza", vec![0.1, 0.2, 0.3], vec!["Pizza Flavors"]).await.unwrap();
+    ///
+    /// let results = victor.search_keywords("pepperoni", vec!["Pizza Flavors"], 1).await.unwrap();
+    /// assert_eq!(results[0].content, "Pepperoni pizza");
+    /// # })
+    /// ```
+    pub async fn build_keyword_index(&mut self) -> Result<(), Error> {
+        self.migrate().await?;
+        self.recover().await?;
+
+        let tombstones = tombstones::removed(&self.root).await?;
+        let mut contents = self.read_contents().await?;
+        contents.retain(|id, _| !tombstones.contains(id));
+        keywords::write_index(&self.root, &Self::keyword_index_for(&contents)).await
+    }
+
+    /// Project every embedding to fewer dimensions, to shrink the database at the cost of some accuracy.
+    /// Embeddings added (and searched for) afterwards are projected the same way, so they keep the dimension
+    /// they had before.
+    ///
+    /// Projecting uses PCA, trained on the embeddings already in the database: embeddings are projected onto the
+    /// directions they vary the most in. Either a fixed number of dimensions is kept, or as few as explain enough
+    /// of the variance between embeddings (see [`Projection`]); [`Victor::stats`] reports how much was kept.
+    ///
+    /// Unless the original vectors are kept (see [`Victor::with_retained_originals`]), a database can only be
+    /// projected once. Quantized databases can't be projected.
+    /// See [`Victor::with_auto_projection`] to project automatically.
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// # let mut victor = Db::new(DirectoryHandle::default());
+    /// victor.add_single_embedding("Pepperoni pizza", vec![0.1, 0.2, 0.3], vec!["Pizza Flavors"]).await.unwrap();
+    /// victor.add_single_embedding("Pineapple", vec![0.3, 0.2, 0.1], vec!["Pizza Toppings"]).await.unwrap();
+    /// victor.project(2).await.unwrap();
+    ///
+    /// let nearest = victor.search_embedding(vec![0.1, 0.2, 0.3], vec!["Pizza Flavors"], 1).await.unwrap();
+    /// assert_eq!(nearest[0].embedding.vector.len(), 2);
+    /// # })
+    /// ```
+    ///
+    /// [`Projection`]: crate::Projection
+    pub async fn project(&mut self, projection: impl Into<Projection>) -> Result<(), Error> {
+        let projection = projection.into();
+
+        self.migrate().await?;
+        self.recover().await?;
+
+        let is_projected: bool = self
+            .root
+            .get_file_handle_with_options("eigen.bin", &GetFileHandleOptions { create: false })
+            .await
+            .is_ok();
+        if is_projected && !self.retains_originals().await {
+            return Err(Error::InvalidInput(
+                "the database is already projected, and its original vectors weren't kept"
+                    .to_string(),
+            ));
+        }
+        if !matches!(self.codec().await?, Codec::Vector(_)) {
+            return Err(Error::InvalidInput(
+                "quantized databases can't be projected".to_string(),
+            ));
+        }
+        match (projection, self.settings().await?.dimension) {
+            (Projection::Dimensions(dimensions), Some(dimension))
+                if dimensions == 0 || dimensions >= dimension =>
+            {
+                return Err(Error::InvalidInput(format!(
+                    "{dimension}-dimensional embeddings can't be projected to {dimensions} dimensions"
+                )));
+            }
+            (Projection::ExplainedVariance(ratio), _) if !(ratio > 0.0 && ratio <= 1.0) => {
+                return Err(Error::InvalidInput(format!(
+                    "the explained variance must be between 0 and 1, not {ratio}"
+                )));
+            }
+            _ => {}
+        }
+
+        self.project_embeddings(projection).await
+    }
+
+    /// Restore the original vectors of a projected database, kept because of [`Victor::with_retained_originals`].
+    /// See [`Victor::with_retained_originals`] for an example.
+    pub async fn unproject(&mut self) -> Result<(), Error> {
+        self.migrate().await?;
+        self.recover().await?;
+
+        let is_projected: bool = self
+            .root
+            .get_file_handle_with_options("eigen.bin", &GetFileHandleOptions { create: false })
+            .await
+            .is_ok();
+        if !is_projected {
+            return Err(Error::InvalidInput(
+                "the database isn't projected".to_string(),
+            ));
+        }
+        if !self.retains_originals().await {
+            return Err(Error::InvalidInput(
+                "the database's original vectors weren't kept".to_string(),
+            ));
+        }
+        let codec = self.codec().await?;
+        if !matches!(codec, Codec::Vector(_)) {
+            return Err(Error::InvalidInput(
+                "quantized databases can't be unprojected".to_string(),
+            ));
+        }
+
+        let mut segments = self.read_all_segments().await?;
+        self.restore_originals(&mut segments).await?;
+        for (filename, mut file_handle, embeddings) in segments {
+            self.write_segment(&filename, &mut file_handle, &codec, &embeddings)
+                .await?;
+        }
+        self.root.remove_entry("eigen.bin").await?;
+
+        // the indexes were built using the projected vectors
+        self.rebuild_indexes().await?;
+        self.subscribers.send(Event::Unprojected);
+        Ok(())
+    }
+
+    /// An overview of the database: how many documents it has, and how they're stored.
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// # let mut victor = Db::new(DirectoryHandle::default());
+    /// victor.add_single_embedding("Pepperoni pizza", vec![0.1, 0.2, 0.3], vec!["Pizza Flavors"]).await.unwrap();
+    /// victor.add_single_embedding("Pineapple", vec![0.3, 0.2, 0.1], vec!["Pizza Toppings"]).await.unwrap();
+    ///
+    /// let stats = victor.stats().await.unwrap();
+    /// assert_eq!(stats.documents, 2);
+    /// assert_eq!(stats.dimension, Some(3));
+    /// assert_eq!(stats.projection, None);
+    /// # })
+    /// ```
+    pub async fn stats(&self) -> Result<Stats, Error> {
+        format::check(&self.root).await?;
+
+        let removed = tombstones::removed(&self.root).await?;
+        let chunks = chunks::read(&self.root).await?;
+        let documents = self
+            .get_all_embeddings()
+            .await?
+            .iter()
+            .filter(|embedding| {
+                !removed.contains(&embedding.id) && !chunks.contains_key(&embedding.id)
+            })
+            .count();
+        let db_files = Index::get_matching_db_files(&self.root, &TagFilter::default())
+            .await?
+            .len();
+
+        let is_projected: bool = self
+            .root
+            .get_file_handle_with_options("eigen.bin", &GetFileHandleOptions { create: false })
+            .await
+            .is_ok();
+        let projection = if is_projected {
+            let vector_projection = self.read_projection().await?;
+            Some(ProjectionStats {
+                dimensions: vector_projection.eigen.ncols(),
+                explained_variance: vector_projection.explained_variance,
+                retains_originals: self.retains_originals().await,
+            })
+        } else {
+            None
+        };
+
+        Ok(Stats {
+            documents,
+            db_files,
+            dimension: self.settings().await?.dimension,
+            projection,
+        })
+    }
+
+    /// Switch the database to product quantization, so each embedding is stored in only a few bytes
+    /// (one per [`PqConfig::subspaces`]) and searches score the stored codes directly.
+    ///
+    /// Codebooks are trained with k-means on a sample of the embeddings already in the database, and every
+    /// embedding is re-encoded with them. Embeddings added afterwards are encoded with the same codebooks, so
+    /// train once the database holds a representative sample, and again if the data drifts.
+    ///
+    /// This is lossy: stored vectors (and so search scores) become approximations of the originals.
+    /// All embeddings must have the same dimension.
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// # let mut victor = Db::new(DirectoryHandle::default());
+    /// use victor_db::PqConfig;
+    ///
+    /// victor.add_single_embedding("Pepperoni pizza", vec![0.1, 0.2, 0.3], vec!["Pizza Flavors"]).await.unwrap();
+    /// victor.add_single_embedding("Pineapple", vec![0.3, 0.2, 0.1], vec!["Pizza Toppings"]).await.unwrap();
+    /// victor.train_product_quantizer(PqConfig::default()).await.unwrap();
+    ///
+    /// let nearest = victor.search_embedding(vec![0.3, 0.2, 0.1], Vec::<String>::new(), 1).await.unwrap();
+    /// assert_eq!(nearest[0].content, "Pineapple");
+    /// # })
+    /// ```
+    pub async fn train_product_quantizer(&mut self, config: PqConfig) -> Result<(), Error> {
+        let segments = self.read_all_segments().await?;
+
+        let vectors = segments
+            .iter()
+            .flat_map(|(_, _, embeddings)| {
+                embeddings.iter().map(|embedding| embedding.vector.clone())
+            })
+            .collect::<Vec<_>>();
+        let quantizer = ProductQuantizer::train(&vectors, config)?;
+
+        let _ = self.root.remove_entry("binary.bin").await;
+        self.write_product_quantizer(&quantizer).await?;
+
+        self.write_all_segments(segments, &Codec::Product(quantizer))
+            .await
+    }
+
+    /// Switch the database to binary quantization, so each embedding is stored in one bit per dimension
+    /// and searches compare embeddings by Hamming distance, which is extremely cheap.
+    ///
+    /// By default the vectors are also kept, so the best matches can be re-ranked by their actual similarity
+    /// (see [`BinaryConfig::rerank`] and [`SearchOptions::oversample`]).
+    /// Binary quantization works best with high-dimensional embeddings centered around zero.
+    /// This replaces product quantization, if it was enabled.
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// # let mut victor = Db::new(DirectoryHandle::default());
+    /// use victor_db::BinaryConfig;
+    ///
+    /// victor.add_single_embedding("Pepperoni pizza", vec![0.1, -0.2, 0.3], vec!["Pizza Flavors"]).await.unwrap();
+    /// victor.add_single_embedding("Pineapple", vec![-0.3, 0.2, -0.1], vec!["Pizza Toppings"]).await.unwrap();
+    /// victor.enable_binary_quantization(BinaryConfig::default()).await.unwrap();
+    ///
+    /// let nearest = victor.search_embedding(vec![-0.3, 0.2, -0.1], Vec::<String>::new(), 1).await.unwrap();
+    /// assert_eq!(nearest[0].content, "Pineapple");
+    /// # })
+    /// ```
+    pub async fn enable_binary_quantization(&mut self, config: BinaryConfig) -> Result<(), Error> {
+        let segments = self.read_all_segments().await?;
+
+        let _ = self.root.remove_entry("pq.bin").await;
+        self.write_binary_config(config).await?;
+
+        self.write_all_segments(segments, &Codec::Binary(config))
+            .await
+    }
+
+    /// List every tag used in the database, sorted alphabetically.
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// # let mut victor = Db::new(DirectoryHandle::default());
+    /// victor.add_single_embedding("Pepperoni pizza", vec![0.1, 0.2, 0.3], vec!["Pizza Flavors"]).await.unwrap();
+    /// victor.add_single_embedding("Pineapple", vec![0.3, 0.2, 0.1], vec!["Pizza Toppings", "Fruit"]).await.unwrap();
+    ///
+    /// assert_eq!(victor.tags().await.unwrap(), vec!["Fruit", "Pizza Flavors", "Pizza Toppings"]);
+    /// # })
+    /// ```
+    pub async fn tags(&self) -> Result<Vec<String>, Error> {
+        let tags = self
+            .tag_sets()
+            .await?
+            .into_iter()
+            .flatten()
+            .collect::<BTreeSet<_>>();
+        Ok(tags.into_iter().collect())
+    }
+
+    /// List every combination of tags that documents have been added with, sorted.
+    /// Each document belongs to exactly one of these combinations.
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// # let mut victor = Db::new(DirectoryHandle::default());
+    /// victor.add_single_embedding("Pepperoni pizza", vec![0.1, 0.2, 0.3], vec!["Pizza Flavors"]).await.unwrap();
+    /// victor.add_single_embedding("Pineapple", vec![0.3, 0.2, 0.1], vec!["Pizza Toppings", "Fruit"]).await.unwrap();
+    ///
+    /// assert_eq!(
+    ///     victor.tag_sets().await.unwrap(),
+    ///     vec![vec!["Fruit", "Pizza Toppings"], vec!["Pizza Flavors"]]
+    /// );
+    /// # })
+    /// ```
+    pub async fn tag_sets(&self) -> Result<Vec<Vec<String>>, Error> {
+        let (_, index) = Index::load(&self.root).await?;
+
+        let mut tag_sets = index
+            .files
+            .into_iter()
+            .map(|tags| tags.into_iter().collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        tag_sets.sort();
+        Ok(tag_sets)
+    }
+
+    /// Group the documents into clusters of similar ones with k-means, biggest first, each with the documents
+    /// closest to its centroid and the keywords that set its documents apart from the rest, to give an overview
+    /// of the topics in the database.
+    ///
+    /// Only each document's first vector is clustered (see [`Document::with_chunk`]).
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// # let mut victor = Db::new(DirectoryHandle::default());
+    /// use victor_db::ClusterConfig;
+    ///
+    /// victor.add_single_embedding("Pepperoni pizza", vec![1.0, 0.1, 0.0], vec!["Food"]).await.unwrap();
+    /// victor.add_single_embedding("Mushroom pizza", vec![0.9, 0.2, 0.0], vec!["Food"]).await.unwrap();
+    /// victor.add_single_embedding("Chocolate cake", vec![0.0, 0.1, 1.0], vec!["Food"]).await.unwrap();
+    ///
+    /// let config = ClusterConfig { clusters: 2, ..Default::default() };
+    /// let clusters = victor.clusters(config).await.unwrap();
+    /// assert_eq!(clusters[0].size, 2);
+    /// assert_eq!(clusters[0].keywords, vec!["pizza", "mushroom", "pepperoni"]);
+    /// assert_eq!(clusters[1].representatives[0].content, "Chocolate cake");
+    /// # })
+    /// ```
+    pub async fn clusters(&self, config: ClusterConfig) -> Result<Vec<Cluster>, Error> {
+        let settings = self.settings().await?;
+        let documents = self.read_documents().await?;
+
+        let vectors = clusters::clustering_vectors(
+            settings.metric,
+            documents.iter().map(|(_, embedding, _)| &embedding.vector),
+        );
+        let centroids = kmeans::kmeans(&vectors, config.clusters, config.iterations);
+        let mut members = vec![Vec::new(); centroids.len()];
+        for (i, vector) in vectors.iter().enumerate() {
+            members[kmeans::nearest(&centroids, vector)].push(i);
+        }
+
+        let terms = documents
+            .iter()
+            .map(|(_, _, content)| keywords::tokenize(&content.content).collect::<HashSet<_>>())
+            .collect::<Vec<_>>();
+        let mut document_frequency = HashMap::<String, usize>::new();
+        for term in terms.iter().flatten() {
+            *document_frequency.entry(term.clone()).or_default() += 1;
+        }
+
+        let mut clusters = Vec::new();
+        for (centroid, mut members) in centroids.into_iter().zip(members) {
+            if members.is_empty() {
+                continue;
+            }
+            members.sort_by(|&a, &b| {
+                kmeans::squared_distance(&vectors[a], &centroid)
+                    .total_cmp(&kmeans::squared_distance(&vectors[b], &centroid))
+            });
+
+            let group = members.iter().map(|&i| &terms[i]).collect::<Vec<_>>();
+            let keywords = clusters::characteristic_terms(
+                &group,
+                &document_frequency,
+                documents.len(),
+                config.keywords,
+            );
+            let representatives = members
+                .iter()
+                .take(config.representatives)
+                .map(|&i| {
+                    let (tags, embedding, content) = &documents[i];
+                    let similarity = settings
+                        .metric
+                        .similarity(&vectors[i], &centroid)
+                        .map_err(Error::InvalidInput)?;
+                    Ok(NearestNeighborsResult {
+                        similarity,
+                        score: settings.metric.score(similarity),
+                        raw: Some(settings.metric.raw(similarity)),
+                        breakdown: None,
+                        embedding: embedding.clone(),
+                        content: content.content.clone(),
+                        external_id: content.external_id.clone(),
+                        tags: tags.iter().cloned().collect(),
+                    })
+                })
+                .collect::<Result<_, Error>>()?;
+
+            clusters.push(Cluster {
+                size: members.len(),
+                centroid,
+                representatives,
+                keywords,
+            });
+        }
+        clusters.sort_by_key(|cluster| std::cmp::Reverse(cluster.size));
+
+        Ok(clusters)
+    }
+
+    /// Find the `n` documents that fit in least with the rest, least first, to spot content that was ingested by
+    /// mistake or is off topic. Each result's similarity is to the mean of the other documents in its cluster,
+    /// or to the closest other cluster if it's alone in its own.
+    ///
+    /// Documents are clustered with k-means, into about the square root of their number of clusters, so a handful
+    /// of documents that are similar to each other but to nothing else can end up in a cluster together and
+    /// not look out of place.
+    /// Only each document's first vector is compared (see [`Document::with_chunk`]).
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// # let mut victor = Db::new(DirectoryHandle::default());
+    /// victor.add_single_embedding("Pepperoni pizza", vec![1.0, 0.1, 0.0], vec!["Food"]).await.unwrap();
+    /// victor.add_single_embedding("Mushroom pizza", vec![0.9, 0.2, 0.0], vec!["Food"]).await.unwrap();
+    /// victor.add_single_embedding("Pineapple pizza", vec![1.0, 0.0, 0.1], vec!["Food"]).await.unwrap();
+    /// victor.add_single_embedding("<html><body>404", vec![0.0, 0.1, 1.0], vec!["Food"]).await.unwrap();
+    ///
+    /// let outliers = victor.outliers(1).await.unwrap();
+    /// assert_eq!(outliers[0].content, "<html><body>404");
+    /// # })
+    /// ```
+    pub async fn outliers(&self, n: usize) -> Result<Vec<NearestNeighborsResult>, Error> {
+        let settings = self.settings().await?;
+        let documents = self.read_documents().await?;
+
+        let vectors = clusters::clustering_vectors(
+            settings.metric,
+            documents.iter().map(|(_, embedding, _)| &embedding.vector),
+        );
+        let k = ((vectors.len() as f64).sqrt() as usize).max(1);
+        let centroids = kmeans::kmeans(&vectors, k, OUTLIER_ITERATIONS);
+        let assignments = vectors
+            .iter()
+            .map(|vector| kmeans::nearest(&centroids, vector))
+            .collect::<Vec<_>>();
+        let similarities = clusters::centroid_similarities(
+            &vectors,
+            &assignments,
+            centroids.len(),
+            settings.metric,
+        );
+
+        let mut outliers = documents
+            .into_iter()
+            .zip(similarities)
+            .filter_map(|((tags, embedding, content), similarity)| {
+                let similarity = similarity?;
+                Some(NearestNeighborsResult {
+                    similarity,
+                    score: settings.metric.score(similarity),
+                    raw: Some(settings.metric.raw(similarity)),
+                    breakdown: None,
+                    embedding,
+                    content: content.content,
+                    external_id: content.external_id,
+                    tags: tags.into_iter().collect(),
+                })
+            })
+            .collect::<Vec<_>>();
+        outliers.sort_by(|a, b| a.similarity.total_cmp(&b.similarity));
+        outliers.truncate(n);
+
+        Ok(outliers)
+    }
+
+    /// Find groups of documents that are nearly the same: every pair of documents more similar than `threshold`,
+    /// grouped together with the documents they're similar to, in the order the documents are stored in.
+    /// Useful for cleaning up a corpus that was ingested from noisy sources, say by deleting all but the first
+    /// document of each group.
+    ///
+    /// Rather than comparing every document with every other one, documents are split into blocks of similar
+    /// ones first, so a pair of near-duplicates can occasionally be missed.
+    /// Only each document's first vector is compared (see [`Document::with_chunk`]).
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// # let mut victor = Db::new(DirectoryHandle::default());
+    /// victor.add_single_embedding("Pepperoni pizza", vec![1.0, 0.1, 0.0], vec!["Food"]).await.unwrap();
+    /// victor.add_single_embedding("Pepperoni pizza!", vec![1.0, 0.11, 0.0], vec!["Food"]).await.unwrap();
+    /// victor.add_single_embedding("Chocolate cake", vec![0.0, 0.1, 1.0], vec!["Food"]).await.unwrap();
+    ///
+    /// let duplicates = victor.find_duplicates(0.99).await.unwrap();
+    /// assert_eq!(duplicates.len(), 1);
+    /// assert_eq!(duplicates[0].ids.len(), 2);
+    ///
+    /// for group in duplicates {
+    ///     for &id in &group.ids[1..] {
+    ///         victor.delete(id).await.unwrap();
+    ///     }
+    /// }
+    /// # })
+    /// ```
+    pub async fn find_duplicates(&self, threshold: f32) -> Result<Vec<DuplicateGroup>, Error> {
+        let settings = self.settings().await?;
+        let (ids, vectors): (Vec<_>, Vec<_>) = self
+            .read_documents()
+            .await?
+            .into_iter()
+            .map(|(_, embedding, _)| (embedding.id, embedding.vector))
+            .unzip();
+
+        let blocking = clusters::clustering_vectors(settings.metric, &vectors);
+        let pairs = duplicates::similar_pairs(&vectors, &blocking, settings.metric, threshold);
+
+        let groups = duplicates::group(&pairs, ids.len());
+        let group_of = groups
+            .iter()
+            .enumerate()
+            .flat_map(|(group, members)| members.iter().map(move |&i| (i, group)))
+            .collect::<HashMap<_, _>>();
+        let mut duplicates = groups
+            .into_iter()
+            .map(|members| DuplicateGroup {
+                ids: members.into_iter().map(|i| ids[i]).collect(),
+                pairs: Vec::new(),
+            })
+            .collect::<Vec<_>>();
+        for (i, j, similarity) in pairs {
+            duplicates[group_of[&i]]
+                .pairs
+                .push((ids[i], ids[j], similarity));
+        }
+        for group in &mut duplicates {
+            group.pairs.sort_by(|(_, _, a), (_, _, b)| b.total_cmp(a));
+        }
+
+        Ok(duplicates)
+    }
+
+    // utils
+
+    async fn project_embeddings(&mut self, projection: Projection) -> Result<(), Error> {
+        let mut segments = self.read_all_segments().await?;
+        // projecting again starts from the original vectors, so the loss doesn't compound
+        if self.retains_originals().await {
+            self.restore_originals(&mut segments).await?;
+        }
+        let prev_embeddings = segments
+            .iter()
+            .flat_map(|(_, _, embeddings)| embeddings.iter().cloned())
+            .collect::<Vec<_>>();
+        if prev_embeddings.is_empty() {
+            return Err(Error::InvalidInput(
+                "there are no embeddings to project".to_string(),
+            ));
+        }
+
+        if self.retain_originals && !self.retains_originals().await {
+            let originals = prev_embeddings
+                .iter()
+                .map(|embedding| (embedding.id, embedding.vector.clone()))
+                .collect();
+            self.write_all_originals(&originals).await?;
+        }
+
+        let (eigenvectors, means, explained_variance) =
+            project_to_lower_dimension(prev_embeddings, projection);
+        let vector_projection = VectorProjection {
+            eigen: eigenvectors,
+            means,
+            explained_variance: Some(explained_variance),
+        };
+
+        self.write_projection(vector_projection.clone()).await?;
+
+        self.update_all_embeddings(segments, vector_projection)
+            .await?;
+
+        // the indexes were built using the old vectors
+        self.rebuild_indexes().await?;
+        self.subscribers.send(Event::Projected);
+        Ok(())
+    }
+
+    /// Replace the vectors in `segments` with the ones kept in `originals.bin`.
+    async fn restore_originals(
+        &self,
+        segments: &mut [(String, D::FileHandleT, Vec<Embedding>)],
+    ) -> Result<(), Error> {
+        let (originals, _) = self.read_original_log().await?;
+        for (_, _, embeddings) in segments {
+            for embedding in embeddings {
+                embedding.vector =
+                    originals
+                        .get(&embedding.id)
+                        .cloned()
+                        .ok_or_else(|| Error::Corrupted {
+                            file: "originals.bin".to_string(),
+                            reason: format!("the original vector of {} is missing", embedding.id),
+                        })?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn update_all_embeddings(
+        &mut self,
+        segments: Vec<(String, D::FileHandleT, Vec<Embedding>)>,
+        vector_projection: VectorProjection,
+    ) -> Result<(), Error> {
+        let codec = self.codec().await?;
+
+        for (filename, mut file_handle, embeddings) in segments {
+            if embeddings.is_empty() {
+                continue;
+            }
+            let matrix = embeddings_to_dmatrix(
+                embeddings
+                    .clone()
+                    .into_iter()
+                    .map(|embedding| embedding.vector)
+                    .collect(),
+            );
+            // center with the means of every embedding, like queries are (see `project_single_vector`)
+            let centered_data = matrix
+                .map_with_location(|_, column, value| value - vector_projection.means[column]);
+
+            let projected_data = centered_data * &vector_projection.eigen;
+
+            let projected_vectors: Vec<Vec<f32>> = projected_data
+                .row_iter()
+                .map(|row| row.iter().cloned().collect())
+                .collect();
+
+            let new_embeddings: Vec<Embedding> = embeddings
+                .iter()
+                .enumerate()
+                .map(|(index, embedding)| Embedding {
+                    id: embedding.id,
+                    vector: projected_vectors[index].clone(),
+                })
+                .collect();
+
+            self.write_segment(&filename, &mut file_handle, &codec, &new_embeddings)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Read every db file, so they can be re-encoded with [`Victor::write_all_segments`].
+    async fn read_all_segments(
+        &self,
+    ) -> Result<Vec<(String, D::FileHandleT, Vec<Embedding>)>, Error> {
+        let codec = self.codec().await?;
+
+        let mut segments = Vec::new();
+        for (filename, file_handle) in
+            Index::get_matching_db_files(&self.root, &TagFilter::default()).await?
+        {
+            let file = self.read_segment(&filename, &file_handle).await?;
+            let embeddings = self.get_embeddings_by_file(&codec, &filename, file)?;
+            segments.push((filename, file_handle, embeddings));
+        }
+
+        Ok(segments)
+    }
+
+    /// Rewrite every db file with a new codec.
+    async fn write_all_segments(
+        &mut self,
+        segments: Vec<(String, D::FileHandleT, Vec<Embedding>)>,
+        codec: &Codec,
+    ) -> Result<(), Error> {
+        for (filename, mut file_handle, embeddings) in segments {
+            self.write_segment(&filename, &mut file_handle, codec, &embeddings)
+                .await?;
+        }
+
+        // the indexes were built using the old vectors
+        self.rebuild_indexes().await
+    }
+
+    /// Replace the contents of a db file with `embeddings`.
+    async fn write_segment(
+        &mut self,
+        filename: &str,
+        file_handle: &mut D::FileHandleT,
+        codec: &Codec,
+        embeddings: &[Embedding],
+    ) -> Result<(), Error> {
+        let serialized_embeddings = embeddings
+            .iter()
+            .map(|embedding| codec.encode(embedding))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut writable = file_handle
+            .create_writable_with_options(&CreateWritableOptions {
+                keep_existing_data: false,
+            })
+            .await?;
+
+        let mut stored = Vec::new();
+        if let Some(first) = serialized_embeddings.first() {
+            if let Some(catalog) = &mut self.catalog {
+                let ids = embeddings.iter().map(|embedding| embedding.id);
+                catalog.replace(filename, first.len(), ids);
+            }
+
+            let len_as_u32 = first.len() as u32;
+            let mut combined = bincode::serialize(&len_as_u32).expect("Failed to serialize size");
+            combined.extend(serialized_embeddings.into_iter().flatten());
+            stored = self.settings().await?.compression.compress(&combined)?;
+
+            writable.seek(0).await?;
+            writable.write_at_cursor_pos(stored.clone()).await?;
+        } else if let Some(catalog) = &mut self.catalog {
+            catalog.replace(filename, 0, []);
+        }
+
+        writable.close().await?;
+
+        checksum::record(&self.root, filename, &stored).await?;
+        self.saw_segment(filename, file_handle).await?;
+        let ids = embeddings.iter().map(|embedding| embedding.id);
+        bloom::write(
+            &self.root,
+            filename,
+            &Bloom::new(ids, checksum::checksum(&stored)),
+        )
+        .await
+    }
+
+    /// Read all of a db file, checking it against its checksum and decompressing it.
+    async fn read_segment(
+        &self,
+        filename: &str,
+        file_handle: &D::FileHandleT,
+    ) -> Result<Vec<u8>, Error> {
+        let file = file_handle.read().await?;
+        checksum::verify(&self.root, filename, &file).await?;
+        self.settings()
+            .await?
+            .compression
+            .decompress(filename, file)
+    }
+
+    async fn write_projection(&mut self, vector_projection: VectorProjection) -> Result<(), Error> {
+        let mut eigen_file_handle = self
+            .root
+            .get_file_handle_with_options("eigen.bin", &GetFileHandleOptions { create: true })
+            .await?;
+
         let mut writable = eigen_file_handle
             .create_writable_with_options(&CreateWritableOptions {
-                keep_existing_data: false,
+                keep_existing_data: false,
+            })
+            .await?;
+
+        let vector_projection_bytes =
+            bincode::serialize(&vector_projection).expect("Failed to serialize embedding");
+
+        writable
+            .write_at_cursor_pos(vector_projection_bytes.clone())
+            .await?;
+
+        writable.close().await?;
+
+        checksum::record(&self.root, "eigen.bin", &vector_projection_bytes).await
+    }
+
+    async fn get_all_embeddings(&self) -> Result<Vec<Embedding>, Error> {
+        let file_handles = Index::get_matching_db_files(&self.root, &TagFilter::default()).await?;
+        let codec = self.codec().await?;
+
+        let mut prev_embeddings: Vec<Embedding> = Vec::new();
+
+        for (filename, file_handle) in file_handles {
+            let file = self.read_segment(&filename, &file_handle).await?;
+            let mut embeddings = self.get_embeddings_by_file(&codec, &filename, file)?;
+            prev_embeddings.append(&mut embeddings);
+        }
+
+        Ok(prev_embeddings)
+    }
+
+    fn get_embeddings_by_file(
+        &self,
+        codec: &Codec,
+        filename: &str,
+        file: Vec<u8>,
+    ) -> Result<Vec<Embedding>, Error> {
+        Self::get_records_by_file(filename, &file)?
+            .into_iter()
+            .map(|record| codec.decode(filename, record))
+            .collect()
+    }
+
+    /// Read the embeddings in a db file whose tags match `tags`, each with the name its tags are looked up by
+    /// (see [`Index::tags_by_filename`]). That's the file's own name, unless it's a combined db file.
+    async fn read_matching_embeddings(
+        &self,
+        index: &Index,
+        filename: &str,
+        file_handle: &D::FileHandleT,
+        codec: &Codec,
+        tags: &TagFilter,
+    ) -> Result<Vec<(String, Embedding)>, Error> {
+        let file = self.read_segment(filename, file_handle).await?;
+        let embeddings = self.get_embeddings_by_file(codec, filename, file)?;
+        let Some(combined) = index.combined(filename) else {
+            return Ok(embeddings
+                .into_iter()
+                .map(|embedding| (filename.to_string(), embedding))
+                .collect());
+        };
+
+        Self::check_tag_map(combined, embeddings.len())?;
+        let mut embeddings = embeddings.into_iter();
+        let mut matching = Vec::new();
+        for (range, name, range_tags) in combined.ranges() {
+            let range_embeddings = embeddings.by_ref().take(range.len());
+            if tags.matches(range_tags) {
+                matching.extend(range_embeddings.map(|embedding| (name.clone(), embedding)));
+            } else {
+                range_embeddings.for_each(drop);
+            }
+        }
+        Ok(matching)
+    }
+
+    /// Split a db file into its (still encoded) embeddings.
+    fn get_records_by_file<'a>(filename: &str, file: &'a [u8]) -> Result<Vec<&'a [u8]>, Error> {
+        let header_size = std::mem::size_of::<u32>();
+
+        // files are created empty, and only get a header once embeddings are written
+        if file.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let embedding_size = Self::get_embedding_size(filename, file)? as usize;
+
+        let file_content = &file[header_size..];
+
+        // sanity check
+        let file_size = file_content.len();
+        if embedding_size == 0 || !file_size.is_multiple_of(embedding_size) {
+            return Err(Error::Corrupted {
+                file: filename.to_string(),
+                reason: format!(
+                    "file_size ({file_size} after subtracting header size {header_size}) was not a multiple of embedding_size ({embedding_size})"
+                ),
+            });
+        }
+
+        Ok(file_content.chunks(embedding_size).collect())
+    }
+
+    /// Overwrite the stored vector of an existing embedding in its db file, saving the record it replaces in
+    /// `journal` (and writing the journal) first, so [`Victor::recover`] can put it back.
+    /// Returns the name of the db file, or `None` if no embedding with the given id exists.
+    async fn rewrite_embedding(
+        &mut self,
+        id: Uuid,
+        mut vector: Vec<f32>,
+        journal: &mut Journal,
+    ) -> Result<Option<String>, Error> {
+        // the catalog is taken to match the file once it's rewritten, so it has to before
+        self.refresh_catalog().await?;
+        let Some((filename, mut file_handle, offset, existing)) = self.locate_embedding(id).await?
+        else {
+            return Ok(None);
+        };
+
+        let settings = self.settings().await?;
+        settings.check_dimension(vector.len())?;
+        settings.normalize(&mut vector);
+
+        let is_projected: bool = self
+            .root
+            .get_file_handle_with_options("eigen.bin", &GetFileHandleOptions { create: false })
+            .await
+            .is_ok();
+
+        let original = self.retains_originals().await.then(|| vector.clone());
+        if is_projected {
+            let vector_projection = self.read_projection().await?;
+            vector = self.project_single_vector(vector, &vector_projection)?;
+        }
+
+        if vector.len() != existing.vector.len() {
+            return Err(Error::DimensionMismatch {
+                expected: existing.vector.len(),
+                found: vector.len(),
+            });
+        }
+
+        let record = self.codec().await?.encode(&Embedding { id, vector })?;
+        let node = (offset - std::mem::size_of::<u32>()) / record.len();
+
+        // journal the record as it was first, so it can be put back if the rewrite is interrupted
+        let previous = match self.settings().await?.compression {
+            Compression::None => file_handle.read_range(offset, record.len()).await?,
+            _ => self.read_segment(&filename, &file_handle).await?[offset..offset + record.len()]
+                .to_vec(),
+        };
+        journal.rewritten.push(journal::Rewritten {
+            segment: filename.clone(),
+            offset,
+            record: previous,
+        });
+        journal::write(&self.root, journal).await?;
+
+        if let Some(original) = original {
+            self.append_originals(&[(id, original)]).await?;
+        }
+        self.write_record(&filename, &mut file_handle, offset, &record)
+            .await?;
+        self.sync_indexes(
+            &filename,
+            Changed::Rewritten {
+                node,
+                record: &record,
+            },
+        )
+        .await?;
+
+        Ok(Some(filename))
+    }
+
+    /// Overwrite the record at `offset` in the db file `filename` with `record`.
+    async fn write_record(
+        &mut self,
+        filename: &str,
+        file_handle: &mut D::FileHandleT,
+        offset: usize,
+        record: &[u8],
+    ) -> Result<(), Error> {
+        let compression = self.settings().await?.compression;
+        let bloom = self.current_bloom(filename).await?;
+
+        if compression == Compression::None {
+            let mut writable = file_handle
+                .create_writable_with_options(&CreateWritableOptions {
+                    keep_existing_data: true,
+                })
+                .await?;
+            writable.seek(offset).await?;
+            writable.write_at_cursor_pos(record.to_vec()).await?;
+            writable.close().await?;
+
+            checksum::record(&self.root, filename, &file_handle.read().await?).await?;
+        } else {
+            // compressed files can't be written in place, so the whole file is rewritten
+            let mut file = self.read_segment(filename, file_handle).await?;
+            file[offset..offset + record.len()].copy_from_slice(record);
+            let stored = compression.compress(&file)?;
+
+            let mut writable = file_handle
+                .create_writable_with_options(&CreateWritableOptions {
+                    keep_existing_data: false,
+                })
+                .await?;
+            writable.write_at_cursor_pos(stored.clone()).await?;
+            writable.close().await?;
+
+            checksum::record(&self.root, filename, &stored).await?;
+        }
+        self.saw_segment(filename, file_handle).await?;
+
+        // the ids in the file are the same, so an up to date filter only needs the new checksum
+        if bloom.is_some() {
+            self.sync_bloom(filename, bloom, &[]).await?;
+        }
+        Ok(())
+    }
+
+    /// Cut `filename` back to its first `size` bytes, returning whether it had grown past them.
+    async fn truncate(&mut self, filename: &str, size: usize) -> Result<bool, Error> {
+        let mut file_handle = self
+            .root
+            .get_file_handle_with_options(filename, &GetFileHandleOptions { create: true })
+            .await?;
+        if file_handle.size().await? <= size {
+            return Ok(false);
+        }
+
+        let kept = file_handle.read_range(0, size).await?;
+        let mut writable = file_handle
+            .create_writable_with_options(&CreateWritableOptions {
+                keep_existing_data: false,
+            })
+            .await?;
+        writable.write_at_cursor_pos(kept.clone()).await?;
+        writable.close().await?;
+        if checksum::recorded(&self.root, filename).await?.is_some() {
+            checksum::record(&self.root, filename, &kept).await?;
+        }
+
+        Ok(true)
+    }
+
+    /// Find the db file holding the embedding with the given id, using the catalog if it's been built,
+    /// and bloom filters otherwise (or if another handle has written to the database since the catalog was).
+    /// Returns the file's name and handle, the byte offset of the record, and the stored embedding.
+    async fn locate_embedding(
+        &self,
+        id: Uuid,
+    ) -> Result<Option<(String, D::FileHandleT, usize, Embedding)>, Error> {
+        if let Some(catalog) = &self.catalog {
+            if let Some(located) = self.read_catalog_location(catalog, id).await? {
+                return Ok(Some(located));
+            }
+            if !self.catalog_is_stale(catalog).await? {
+                return Ok(None);
+            }
+        }
+
+        let Some((location, record_size, embedding)) =
+            self.scan_for_ids(&HashSet::from([id])).await?.remove(&id)
+        else {
+            return Ok(None);
+        };
+        let file_handle = self
+            .root
+            .get_file_handle_with_options(&location.segment, &GetFileHandleOptions { create: true })
+            .await?;
+        let offset = location.offset(record_size);
+        Ok(Some((location.segment, file_handle, offset, embedding)))
+    }
+
+    /// Read the embedding with the given id from where the catalog says it is, if it's really there.
+    async fn read_catalog_location(
+        &self,
+        catalog: &Catalog,
+        id: Uuid,
+    ) -> Result<Option<(String, D::FileHandleT, usize, Embedding)>, Error> {
+        let Some(location) = catalog.get(&id).cloned() else {
+            return Ok(None);
+        };
+        let Some(SegmentInfo { record_size, .. }) = catalog.segment(&location.segment) else {
+            return Ok(None);
+        };
+
+        let offset = location.offset(record_size);
+        let file_handle = self
+            .root
+            .get_file_handle_with_options(&location.segment, &GetFileHandleOptions { create: true })
+            .await?;
+
+        // compressed files have to be read whole, but other files can just read the one record
+        let record = if self.settings().await?.compression == Compression::None {
+            if file_handle.size().await? < offset + record_size {
+                return Ok(None);
+            }
+            file_handle.read_range(offset, record_size).await?
+        } else {
+            let file = self.read_segment(&location.segment, &file_handle).await?;
+            match file.get(offset..offset + record_size) {
+                Some(record) => record.to_vec(),
+                None => return Ok(None),
+            }
+        };
+
+        let embedding = self.codec().await?.decode(&location.segment, &record)?;
+        if embedding.id != id {
+            return Ok(None);
+        }
+
+        Ok(Some((location.segment, file_handle, offset, embedding)))
+    }
+
+    /// Which of `ids` are stored in a db file, using the catalog if it's been built,
+    /// and bloom filters otherwise (or for ids the catalog doesn't have, if another handle has written to the
+    /// database since it was built).
+    async fn stored_ids(&self, ids: HashSet<Uuid>) -> Result<HashSet<Uuid>, Error> {
+        let catalog = match &self.catalog {
+            _ if ids.is_empty() => return Ok(ids),
+            Some(catalog) => catalog,
+            None => return Ok(self.scan_for_ids(&ids).await?.into_keys().collect()),
+        };
+        let (mut stored, missing): (HashSet<_>, HashSet<_>) =
+            ids.into_iter().partition(|id| catalog.contains(id));
+        if !missing.is_empty() && self.catalog_is_stale(catalog).await? {
+            stored.extend(self.scan_for_ids(&missing).await?.into_keys());
+        }
+        Ok(stored)
+    }
+
+    /// The size and recorded checksum of every db file, to tell which ones the catalog doesn't match.
+    async fn segment_versions(&self) -> Result<Vec<(String, Version)>, Error> {
+        let checksums = checksum::read_all(&self.root).await?;
+        let mut versions = Vec::new();
+        for (filename, file_handle) in
+            Index::get_matching_db_files(&self.root, &TagFilter::default()).await?
+        {
+            let version = (file_handle.size().await?, checksums.get(&filename).copied());
+            versions.push((filename, version));
+        }
+        Ok(versions)
+    }
+
+    /// Whether another handle has written to the database since the catalog last matched it,
+    /// so it may be missing embeddings.
+    async fn catalog_is_stale(&self, catalog: &Catalog) -> Result<bool, Error> {
+        let (changed, removed) = catalog.changes(&self.segment_versions().await?);
+        Ok(!changed.is_empty() || !removed.is_empty())
+    }
+
+    /// Bring the catalog (if it's been built) up to date with the db files another handle has written to,
+    /// re-reading just those.
+    async fn refresh_catalog(&mut self) -> Result<(), Error> {
+        let Some(catalog) = &self.catalog else {
+            return Ok(());
+        };
+        let versions = self.segment_versions().await?;
+        let (changed, removed) = catalog.changes(&versions);
+        if changed.is_empty() && removed.is_empty() {
+            return Ok(());
+        }
+
+        let codec = self.codec().await?;
+        let mut reread = Vec::new();
+        for (filename, version) in versions {
+            if !changed.contains(&filename) {
+                continue;
+            }
+            let file_handle = self
+                .root
+                .get_file_handle_with_options(&filename, &GetFileHandleOptions { create: true })
+                .await?;
+            let file = self.read_segment(&filename, &file_handle).await?;
+            let layout = match file.is_empty() {
+                true => None,
+                false => Some((
+                    Self::get_embedding_size(&filename, &file)? as usize,
+                    self.get_embeddings_by_file(&codec, &filename, file)?
+                        .into_iter()
+                        .map(|embedding| embedding.id)
+                        .collect::<Vec<_>>(),
+                )),
+            };
+            reread.push((filename, version, layout));
+        }
+
+        let catalog = self.catalog.as_mut().expect("the catalog was checked");
+        for filename in removed {
+            catalog.remove(&filename);
+        }
+        for (filename, version, layout) in reread {
+            // empty files don't have a record size yet, which is set by the first embeddings appended
+            match layout {
+                Some((record_size, ids)) => catalog.replace(&filename, record_size, ids),
+                None => catalog.remove(&filename),
+            }
+            catalog.saw(&filename, version);
+        }
+        Ok(())
+    }
+
+    /// Record that the catalog (if it's been built), which was kept up to date as this handle wrote `filename`,
+    /// matches it as it is now.
+    async fn saw_segment(
+        &mut self,
+        filename: &str,
+        file_handle: &D::FileHandleT,
+    ) -> Result<(), Error> {
+        if self.catalog.is_none() {
+            return Ok(());
+        }
+        let version = (
+            file_handle.size().await?,
+            checksum::recorded(&self.root, filename).await?,
+        );
+        if let Some(catalog) = &mut self.catalog {
+            catalog.saw(filename, version);
+        }
+        Ok(())
+    }
+
+    /// Find the embeddings with `ids` without the catalog, only reading the db files whose bloom filters say
+    /// they might hold one of them, along with any without an up to date filter.
+    /// Returns where each one found is stored, the size of the records in its db file, and the embedding.
+    async fn scan_for_ids(
+        &self,
+        ids: &HashSet<Uuid>,
+    ) -> Result<HashMap<Uuid, (Location, usize, Embedding)>, Error> {
+        let codec = self.codec().await?;
+        let checksums = checksum::read_all(&self.root).await?;
+        let mut found = HashMap::new();
+        for (filename, file_handle) in
+            Index::get_matching_db_files(&self.root, &TagFilter::default()).await?
+        {
+            let bloom = match checksums.get(&filename) {
+                Some(&checksum) => bloom::read(&self.root, &filename)
+                    .await?
+                    .filter(|bloom| bloom.is_current(checksum)),
+                None => None,
+            };
+            if bloom.is_some_and(|bloom| !ids.iter().any(|id| bloom.may_contain(id))) {
+                continue;
+            }
+
+            let file = self.read_segment(&filename, &file_handle).await?;
+            if file.is_empty() {
+                continue;
+            }
+            let record_size = Self::get_embedding_size(&filename, &file)? as usize;
+            let embeddings = self.get_embeddings_by_file(&codec, &filename, file)?;
+            for (position, embedding) in embeddings.into_iter().enumerate() {
+                if ids.contains(&embedding.id) {
+                    let location = Location {
+                        segment: filename.clone(),
+                        position,
+                    };
+                    found.insert(embedding.id, (location, record_size, embedding));
+                }
+            }
+        }
+
+        Ok(found)
+    }
+
+    /// Where every embedding is stored, building the catalog if it hasn't been built yet.
+    async fn catalog(&mut self) -> Result<&Catalog, Error> {
+        telemetry::cache("catalog", self.catalog.is_some());
+        if self.catalog.is_none() {
+            // every db file has changed since an empty catalog, so refreshing it reads them all
+            self.catalog = Some(Catalog::default());
+            if let Err(error) = self.refresh_catalog().await {
+                self.catalog = None;
+                return Err(error);
+            }
+        }
+
+        Ok(self.catalog.as_ref().expect("the catalog was just built"))
+    }
+
+    fn get_embedding_size(filename: &str, file: &[u8]) -> Result<u32, Error> {
+        // Read the embedding size from the header.
+        let header_size = std::mem::size_of::<u32>(); // Assuming your header is u32
+
+        let embedding_size_bytes = file.get(0..header_size).ok_or_else(|| Error::Corrupted {
+            file: filename.to_string(),
+            reason: "file is too short to have a header".to_string(),
+        })?;
+
+        deserialize(filename, embedding_size_bytes)
+    }
+
+    async fn read_projection(&self) -> Result<VectorProjection, Error> {
+        let eigen_file_handle = self
+            .root
+            .get_file_handle_with_options("eigen.bin", &GetFileHandleOptions { create: true })
+            .await?;
+
+        let version = self.cache_version("eigen.bin", &eigen_file_handle).await?;
+        if let Some(Cached::Projection(projection)) =
+            self.cached("eigen.bin", "projection", version)
+        {
+            return Ok(projection.as_ref().clone());
+        }
+        let projection = self.decode_projection(eigen_file_handle).await?;
+        if let Some(version) = version.filter(|(size, _)| self.cache().fits(*size)) {
+            let cached = Cached::Projection(Arc::new(projection.clone()));
+            self.cache()
+                .insert("eigen.bin".to_string(), version, version.0, cached);
+        }
+        Ok(projection)
+    }
+
+    async fn decode_projection(
+        &self,
+        eigen_file_handle: D::FileHandleT,
+    ) -> Result<VectorProjection, Error> {
+        let eigen_file = eigen_file_handle.read().await?;
+        checksum::verify(&self.root, "eigen.bin", &eigen_file).await?;
+
+        // the explained variance wasn't recorded until format version 6
+        match format::read(&self.root).await? {
+            Some(version) if version >= 6 => deserialize("eigen.bin", &eigen_file),
+            _ => deserialize::<VectorProjectionV5>("eigen.bin", &eigen_file)
+                .map(VectorProjection::from),
+        }
+    }
+
+    fn project_single_vector(
+        &self,
+        vector: Vec<f32>,
+        vector_projection: &VectorProjection,
+    ) -> Result<Vec<f32>, Error> {
+        if vector.len() != vector_projection.means.len() {
+            return Err(Error::DimensionMismatch {
+                expected: vector_projection.means.len(),
+                found: vector.len(),
+            });
+        }
+
+        let centered_vector = vector
+            .iter()
+            .zip(vector_projection.means.iter())
+            .map(|(x, mean)| x - mean)
+            .collect::<Vec<_>>();
+
+        let centered_matrix = embeddings_to_dmatrix(vec![centered_vector]);
+
+        let projected_vector = (centered_matrix * &vector_projection.eigen)
+            .as_mut_slice()
+            .to_vec();
+        Ok(projected_vector)
+    }
+
+    async fn write_embeddings(
+        &mut self,
+        mut embeddings: Vec<Embedding>,
+        filename: &str,
+    ) -> Result<(), Error> {
+        // the first embeddings added decide the database's dimension
+        let mut settings = self.settings().await?;
+        for embedding in &mut embeddings {
+            settings.normalize(&mut embedding.vector);
+        }
+        if let Some(embedding) = embeddings.first() {
+            let is_first = settings.dimension.is_none();
+            settings.dimension = Some(settings.dimension.unwrap_or(embedding.vector.len()));
+            for embedding in &embeddings {
+                settings.check_dimension(embedding.vector.len())?;
+            }
+            if is_first {
+                self.write_settings(settings).await?;
+            }
+        }
+
+        let is_projected: bool = self
+            .root
+            .get_file_handle_with_options("eigen.bin", &GetFileHandleOptions { create: false })
+            .await
+            .is_ok();
+
+        // keep the full-precision vectors, before they're projected or packed
+        if self.retain_originals && !is_projected && !self.retains_originals().await {
+            // the vectors already stored are the best there is of the ones added before
+            let originals = self
+                .get_all_embeddings()
+                .await?
+                .into_iter()
+                .map(|embedding| (embedding.id, embedding.vector))
+                .collect();
+            self.write_all_originals(&originals).await?;
+        }
+        if self.retains_originals().await {
+            let originals = embeddings
+                .iter()
+                .map(|embedding| (embedding.id, embedding.vector.clone()))
+                .collect::<Vec<_>>();
+            self.append_originals(&originals).await?;
+        }
+
+        if is_projected {
+            let vector_projection = self.read_projection().await?;
+            embeddings = embeddings
+                .into_iter()
+                .map(|embedding| {
+                    let vector =
+                        self.project_single_vector(embedding.vector, &vector_projection)?;
+                    Ok(Embedding {
+                        id: embedding.id,
+                        vector,
+                    })
+                })
+                .collect::<Result<_, Error>>()?;
+        }
+
+        // check that the embeddings all have the same dimension as each other,
+        // and as the embeddings already in the file
+        let Some(dimension) = embeddings.first().map(|embedding| embedding.vector.len()) else {
+            return Ok(());
+        };
+        if let Some(embedding) = embeddings
+            .iter()
+            .find(|embedding| embedding.vector.len() != dimension)
+        {
+            return Err(Error::DimensionMismatch {
+                expected: dimension,
+                found: embedding.vector.len(),
+            });
+        }
+        let codec = self.codec().await?;
+        let mut records = Vec::new();
+        for embedding in &embeddings {
+            codec.encode_into(embedding.id, &embedding.vector, &mut records)?;
+        }
+        let ids = embeddings
+            .iter()
+            .map(|embedding| embedding.id)
+            .collect::<Vec<_>>();
+        self.append_records(filename, &ids, records, dimension)
+            .await
+    }
+
+    /// Append embeddings already encoded with the database's codec, one after another, to the db file
+    /// `filename`, and bring everything kept about the file up to date. `ids` are the ids of the embeddings, in
+    /// order, and `dimension` the dimension of their vectors as they're stored.
+    async fn append_records(
+        &mut self,
+        filename: &str,
+        ids: &[Uuid],
+        records: Vec<u8>,
+        dimension: usize,
+    ) -> Result<(), Error> {
+        // the records are cataloged where the catalog thinks the file ends, so it has to be up to date
+        self.refresh_catalog().await?;
+        let mut file_handle = self
+            .root
+            .get_file_handle_with_options(filename, &GetFileHandleOptions { create: true })
+            .await?;
+        let settings = self.settings().await?;
+        let is_projected: bool = self
+            .root
+            .get_file_handle_with_options("eigen.bin", &GetFileHandleOptions { create: false })
+            .await
+            .is_ok();
+        let codec = self.codec().await?;
+
+        // compressed databases always record their dimension, which was checked already,
+        // and reading their first embedding would mean decompressing the whole file
+        let record_size = match settings.compression {
+            Compression::None => Self::read_record_size(filename, &file_handle).await?,
+            _ => None,
+        };
+        if let Some(record_size) = record_size {
+            let header_size = std::mem::size_of::<u32>();
+            let first = file_handle.read_range(header_size, record_size).await?;
+            let existing_dimension = codec.decode(filename, &first)?.vector.len();
+            if existing_dimension != dimension {
+                return Err(Error::DimensionMismatch {
+                    expected: existing_dimension,
+                    found: dimension,
+                });
+            }
+        }
+
+        let previous_size = file_handle.size().await?;
+        let bloom = match previous_size {
+            0 => Some(Bloom::new([], 0)),
+            _ => self.current_bloom(filename).await?,
+        };
+
+        let mut writable = file_handle
+            .create_writable_with_options(&CreateWritableOptions {
+                keep_existing_data: true,
             })
-            .await
-            .unwrap();
+            .await?;
+        writable.seek(previous_size).await?;
 
-        let vector_projection_bytes =
-            bincode::serialize(&vector_projection).expect("Failed to serialize embedding");
+        // embeddings with the same dimension always serialize to the same size
+        let embedding_size = (records.len() / ids.len()) as u32;
 
-        writable
-            .write_at_cursor_pos(vector_projection_bytes)
-            .await
-            .unwrap();
+        if let Some(catalog) = &mut self.catalog {
+            catalog.append(filename, embedding_size as usize, ids.iter().copied());
+        }
 
-        writable.close().await.unwrap();
-    }
+        let appended = match previous_size {
+            0 => {
+                let mut appended =
+                    bincode::serialize(&embedding_size).expect("Failed to serialize size");
+                appended.extend(&records);
+                Cow::Owned(appended)
+            }
+            _ => Cow::Borrowed(&records),
+        };
+        // compressed files get a new frame, so the existing data doesn't need to be rewritten
+        let appended = settings.compression.compress(&appended)?;
+        writable.write_at_cursor_pos(appended.clone()).await?;
 
-    async fn get_all_embeddings(&self) -> Vec<Embedding> {
-        let file_handles = Index::get_matching_db_files(
-            &self.root,
-            Vec::new().into_iter().collect::<BTreeSet<_>>(),
-        )
-        .await
-        .unwrap();
+        writable.close().await?;
 
-        let mut prev_embeddings: Vec<Embedding> = Vec::new();
+        checksum::append(&self.root, filename, previous_size, &appended).await?;
+        telemetry::file_size(filename, previous_size + appended.len());
+        self.saw_segment(filename, &file_handle).await?;
 
-        for file_handle in file_handles {
-            let file = file_handle.read().await.unwrap();
-            let mut embeddings = self.get_embeddings_by_file(file).await;
-            prev_embeddings.append(&mut embeddings);
+        self.sync_bloom(filename, bloom, ids).await?;
+        self.sync_indexes(
+            filename,
+            Changed::Appended {
+                records: &records,
+                record_size: embedding_size as usize,
+            },
+        )
+        .await?;
+
+        // quantized embeddings are already small, and product quantization codebooks can't be projected
+        let is_quantized = !matches!(codec, Codec::Vector(_));
+        if let Some(auto_projection) = self.auto_projection {
+            let reduces_dimension = match auto_projection.projection {
+                Projection::Dimensions(dimensions) => dimensions < dimension,
+                Projection::ExplainedVariance(_) => true,
+            };
+            if file_handle.size().await? > auto_projection.segment_size
+                && !is_projected
+                && !is_quantized
+                && reduces_dimension
+            {
+                self.project_embeddings(auto_projection.projection).await?;
+            }
         }
 
-        prev_embeddings
+        Ok(())
     }
 
-    async fn get_embeddings_by_file(&self, file: Vec<u8>) -> Vec<Embedding> {
-        let header_size = std::mem::size_of::<u32>();
-
-        let embedding_size: u32 = Self::get_embedding_size(file.clone());
+    /// Read the latest content of each document, to change it. Searches only look contents up,
+    /// so they share the log instead (see [`Victor::read_content_log`]).
+    async fn read_contents(&self) -> Result<HashMap<Uuid, Content>, Error> {
+        Ok(Arc::unwrap_or_clone(self.read_content_log().await?).0)
+    }
 
-        let file_content = &file[header_size..];
+    /// Read `content.bin`, a log of `(id, content)` entries where later entries replace earlier ones,
+    /// so adding or updating documents only appends to it.
+    /// Returns the latest content of each document, along with the number of entries in the log,
+    /// shared with the cache if it's kept there.
+    async fn read_content_log(&self) -> Result<Arc<(HashMap<Uuid, Content>, usize)>, Error> {
+        let content_file_handle = self
+            .root
+            .get_file_handle_with_options("content.bin", &GetFileHandleOptions { create: true })
+            .await?;
 
-        // sanity check
-        {
-            let file_size = file_content.len() as u32;
-            assert_eq!(
-                file_size % embedding_size,
-                0,
-                "file_size ({file_size} after subtracting header size {header_size}) was not a multiple of embedding_size ({embedding_size})",
+        let version = self
+            .cache_version("content.bin", &content_file_handle)
+            .await?;
+        if let Some(Cached::Contents(log)) = self.cached("content.bin", "contents", version) {
+            return Ok(log);
+        }
+        let log = Arc::new(self.decode_content_log(content_file_handle).await?);
+        if let Some(version) = version {
+            let size = log.0.values().map(Content::size).sum();
+            self.cache().insert(
+                "content.bin".to_string(),
+                version,
+                size,
+                Cached::Contents(log.clone()),
             );
         }
+        Ok(log)
+    }
 
-        let embeddings = file_content
-            .chunks(embedding_size as usize)
-            .map(|chunk| bincode::deserialize::<Embedding>(chunk).unwrap());
+    async fn decode_content_log(
+        &self,
+        content_file_handle: D::FileHandleT,
+    ) -> Result<(HashMap<Uuid, Content>, usize), Error> {
+        let existing_content = content_file_handle.read().await?;
+        checksum::verify(&self.root, "content.bin", &existing_content).await?;
+        let existing_content = self
+            .settings()
+            .await?
+            .compression
+            .decompress("content.bin", existing_content)?;
 
-        embeddings.collect()
-    }
+        if existing_content.is_empty() {
+            return Ok((HashMap::new(), 0));
+        }
 
-    fn get_embedding_size(file: Vec<u8>) -> u32 {
-        // Read the embedding size from the header.
-        let header_size = std::mem::size_of::<u32>(); // Assuming your header is u32
+        // the whole map was written at once before format version 5
+        if format::read(&self.root).await?.unwrap_or(0) < 5 {
+            let hashmap: HashMap<Uuid, Content> = deserialize("content.bin", &existing_content)?;
+            let entries = hashmap.len();
+            return Ok((hashmap, entries));
+        }
 
-        let embedding_size_bytes = &file[0..header_size];
+        let mut hashmap = HashMap::new();
+        let mut entries = 0;
+        let mut rest = &existing_content[..];
+        while !rest.is_empty() {
+            let (id, content): (Uuid, Content) =
+                bincode::deserialize_from(&mut rest).map_err(|e| Error::Corrupted {
+                    file: "content.bin".to_string(),
+                    reason: e.to_string(),
+                })?;
+            hashmap.insert(id, content);
+            entries += 1;
+        }
 
-        bincode::deserialize::<u32>(embedding_size_bytes).expect("Failed to deserialize header")
+        Ok((hashmap, entries))
     }
 
-    async fn eigen_file(&self) -> Vec<u8> {
-        let eigen_file_handle = self
+    /// Append `content` to the log in `content.bin`, replacing any existing content for the same ids.
+    async fn write_contents(&mut self, content: Vec<(Uuid, Content)>) -> Result<(), Error> {
+        let changes = content
+            .iter()
+            .map(|(id, content)| keywords::Change::insert(*id, &content.content))
+            .collect::<Vec<_>>();
+        keywords::append_changes(&self.root, &changes).await?;
+
+        let mut entries = Vec::new();
+        for entry in &content {
+            bincode::serialize_into(&mut entries, entry).expect("Failed to serialize content");
+        }
+        // compressed files get a new frame, so the existing data doesn't need to be rewritten
+        let appended = self.settings().await?.compression.compress(&entries)?;
+
+        let mut content_file_handle = self
             .root
-            .get_file_handle_with_options("eigen.bin", &GetFileHandleOptions { create: true })
-            .await
-            .unwrap();
+            .get_file_handle_with_options("content.bin", &GetFileHandleOptions { create: true })
+            .await?;
+
+        let previous_size = content_file_handle.size().await?;
+        let mut content_writable = content_file_handle
+            .create_writable_with_options(&CreateWritableOptions {
+                keep_existing_data: true,
+            })
+            .await?;
+        content_writable.seek(previous_size).await?;
+        content_writable
+            .write_at_cursor_pos(appended.clone())
+            .await?;
+        content_writable.close().await?;
 
-        eigen_file_handle.read().await.unwrap()
+        checksum::append(&self.root, "content.bin", previous_size, &appended).await?;
+        telemetry::file_size("content.bin", previous_size + appended.len());
+        Ok(())
     }
 
-    fn project_single_vector(&self, vector: Vec<f32>, eigen_file: Vec<u8>) -> Vec<f32> {
-        let vector_projection: VectorProjection = bincode::deserialize(&eigen_file).unwrap();
+    /// Replace `content.bin` with `hashmap`, leaving one entry per document.
+    /// The keyword index is rebuilt from it too, if there is one, folding in the changes appended to it.
+    async fn write_all_contents(&mut self, hashmap: &HashMap<Uuid, Content>) -> Result<(), Error> {
+        if keywords::has_index(&self.root).await? {
+            keywords::write_index(&self.root, &Self::keyword_index_for(hashmap)).await?;
+        }
 
-        let centered_vector = vector
-            .iter()
-            .zip(vector_projection.means.iter())
-            .map(|(x, mean)| x - mean)
-            .collect::<Vec<_>>();
+        let mut entries = Vec::new();
+        for entry in hashmap {
+            bincode::serialize_into(&mut entries, &entry).expect("Failed to serialize content");
+        }
+        let updated_data = self.settings().await?.compression.compress(&entries)?;
 
-        let centered_matrix = embeddings_to_dmatrix(vec![centered_vector]);
+        let mut content_file_handle = self
+            .root
+            .get_file_handle_with_options("content.bin", &GetFileHandleOptions { create: true })
+            .await?;
 
-        let projected_vector = (centered_matrix * vector_projection.eigen)
-            .as_mut_slice()
-            .to_vec();
-        projected_vector
+        // the whole log is rewritten, so drop the old data
+        let mut content_writable = content_file_handle
+            .create_writable_with_options(&CreateWritableOptions {
+                keep_existing_data: false,
+            })
+            .await?;
+
+        content_writable
+            .write_at_cursor_pos(updated_data.clone())
+            .await?;
+        content_writable.close().await?;
+
+        checksum::record(&self.root, "content.bin", &updated_data).await
     }
 
-    async fn write_embeddings(
+    async fn read_segment_stats(&self) -> Result<HashMap<String, SegmentStats>, Error> {
+        read_bin(&self.root, "stats.bin").await
+    }
+
+    async fn write_segment_stats(
         &mut self,
-        mut embeddings: Vec<Embedding>,
-        tags: Vec<String>,
-    ) -> Result<(), D::Error> {
-        let mut file_handle = Index::get_exact_db_file(&mut self.root, tags).await?;
+        stats: &HashMap<String, SegmentStats>,
+    ) -> Result<(), Error> {
+        write_bin(&self.root, "stats.bin", stats).await
+    }
 
-        let is_projected: bool = self
+    /// Whether the database keeps the original vectors of its projected embeddings in `originals.bin`.
+    async fn retains_originals(&self) -> bool {
+        self.root
+            .get_file_handle_with_options("originals.bin", &GetFileHandleOptions { create: false })
+            .await
+            .is_ok()
+    }
+
+    /// Read `originals.bin`, a log of `(id, vector)` entries like `content.bin`, holding the full-precision
+    /// vectors of a projected database (see [`Victor::with_retained_originals`]).
+    /// Returns the latest original vector of each embedding, along with the number of entries in the log.
+    async fn read_original_log(&self) -> Result<(HashMap<Uuid, Vec<f32>>, usize), Error> {
+        let Ok(originals_file_handle) = self
             .root
-            .get_file_handle_with_options("eigen.bin", &GetFileHandleOptions { create: false })
+            .get_file_handle_with_options("originals.bin", &GetFileHandleOptions { create: false })
             .await
-            .is_ok();
+        else {
+            return Ok((HashMap::new(), 0));
+        };
 
-        if is_projected {
-            let eigen_file = self.eigen_file().await;
-            embeddings = embeddings
-                .into_iter()
-                .map(|embedding| {
-                    let vector =
-                        self.project_single_vector(embedding.vector.clone(), eigen_file.clone());
-                    Embedding {
-                        id: embedding.id,
-                        vector,
-                    }
-                })
-                .collect();
+        let originals = originals_file_handle.read().await?;
+        checksum::verify(&self.root, "originals.bin", &originals).await?;
+        let originals = self
+            .settings()
+            .await?
+            .compression
+            .decompress("originals.bin", originals)?;
+
+        let mut hashmap = HashMap::new();
+        let mut entries = 0;
+        let mut rest = &originals[..];
+        while !rest.is_empty() {
+            let (id, vector): (Uuid, Vec<f32>) =
+                bincode::deserialize_from(&mut rest).map_err(|e| Error::Corrupted {
+                    file: "originals.bin".to_string(),
+                    reason: e.to_string(),
+                })?;
+            hashmap.insert(id, vector);
+            entries += 1;
         }
 
-        let mut writable = file_handle
+        Ok((hashmap, entries))
+    }
+
+    /// Append original vectors to `originals.bin`, replacing any earlier ones for the same embeddings.
+    async fn append_originals(&mut self, originals: &[(Uuid, Vec<f32>)]) -> Result<(), Error> {
+        let mut entries = Vec::new();
+        for entry in originals {
+            bincode::serialize_into(&mut entries, entry).expect("Failed to serialize vector");
+        }
+        // compressed files get a new frame, so the existing data doesn't need to be rewritten
+        let appended = self.settings().await?.compression.compress(&entries)?;
+
+        let mut originals_file_handle = self
+            .root
+            .get_file_handle_with_options("originals.bin", &GetFileHandleOptions { create: true })
+            .await?;
+
+        let previous_size = originals_file_handle.size().await?;
+        let mut writable = originals_file_handle
             .create_writable_with_options(&CreateWritableOptions {
                 keep_existing_data: true,
             })
             .await?;
+        writable.seek(previous_size).await?;
+        writable.write_at_cursor_pos(appended.clone()).await?;
+        writable.close().await?;
 
-        writable.seek(file_handle.size().await?).await?;
+        checksum::append(&self.root, "originals.bin", previous_size, &appended).await
+    }
 
-        let embeddings_serialized = embeddings
-            .into_iter()
-            .map(|embedding| bincode::serialize(&embedding).expect("Failed to serialize embedding"))
-            .collect::<Vec<_>>();
+    /// Replace `originals.bin` with `originals`, leaving one entry per embedding.
+    async fn write_all_originals(
+        &mut self,
+        originals: &HashMap<Uuid, Vec<f32>>,
+    ) -> Result<(), Error> {
+        let mut entries = Vec::new();
+        for entry in originals {
+            bincode::serialize_into(&mut entries, &entry).expect("Failed to serialize vector");
+        }
+        let updated_data = self.settings().await?.compression.compress(&entries)?;
 
-        // check that the embeddings are all the same size
-        // and get that size
-        let embedding_size = match &embeddings_serialized
-            .iter()
-            .map(|embedding| embedding.len())
-            .collect::<HashSet<_>>()
-            .into_iter()
-            .collect::<Vec<_>>()[..]
-        {
-            [size] => *size as u32,
-            _ => panic!("All embeddings must be the same size"),
+        let mut originals_file_handle = self
+            .root
+            .get_file_handle_with_options("originals.bin", &GetFileHandleOptions { create: true })
+            .await?;
+
+        let mut writable = originals_file_handle
+            .create_writable_with_options(&CreateWritableOptions {
+                keep_existing_data: false,
+            })
+            .await?;
+        writable.write_at_cursor_pos(updated_data.clone()).await?;
+        writable.close().await?;
+
+        checksum::record(&self.root, "originals.bin", &updated_data).await
+    }
+
+    async fn originals_size(&self) -> Result<usize, Error> {
+        let originals_file_handle = self
+            .root
+            .get_file_handle_with_options("originals.bin", &GetFileHandleOptions { create: true })
+            .await?;
+
+        Ok(originals_file_handle.size().await?)
+    }
+
+    async fn content_size(&self) -> Result<usize, Error> {
+        let content_file_handle = self
+            .root
+            .get_file_handle_with_options("content.bin", &GetFileHandleOptions { create: true })
+            .await?;
+
+        Ok(content_file_handle.size().await?)
+    }
+
+    /// Bring the graph for the db file `segment` up to date with the embeddings in it,
+    /// linking in any new embeddings and relinking the embedding at position `relink` if its vector was rewritten.
+    /// Only the embeddings the graph visits while linking are decoded, and only the nodes whose links changed
+    /// are written, appended to the graph's file. Does nothing if the database isn't indexed.
+    async fn sync_hnsw(&mut self, segment: &str, relink: Option<usize>) -> Result<(), Error> {
+        let Some(config) = hnsw::read_config(&self.root).await? else {
+            return Ok(());
+        };
+
+        let score = hnsw_score(self.settings().await?.metric);
+        let codec = self.codec().await?;
+
+        let file_handle = self
+            .root
+            .get_file_handle_with_options(segment, &GetFileHandleOptions { create: true })
+            .await?;
+        let Some(data) = self
+            .read_segments(segment.to_string(), file_handle, &codec, None, None, true)
+            .await?
+            .pop()
+        else {
+            return hnsw::write_graph(&self.root, segment, &Hnsw::new(config)).await;
+        };
+        let vectors = SegmentVectors::new(&data, &codec);
+
+        // a graph with more nodes than the file has embeddings is stale, so it's rebuilt
+        let existing = hnsw::read_graph(&self.root, segment)
+            .await?
+            .filter(|graph| graph.len() <= data.len());
+        let Some(mut graph) = existing else {
+            let ids = (0..data.len())
+                .map(|node| Ok(data.embedding(&codec, node)?.id))
+                .collect::<Result<Vec<_>, Error>>()?;
+            let graph = Hnsw::build(config, &ids, &vectors, &score);
+            vectors.check()?;
+            return hnsw::write_graph(&self.root, segment, &graph).await;
+        };
+
+        if let Some(node) = relink.filter(|&node| node < graph.len()) {
+            graph.insert(node, &data.embedding(&codec, node)?.id, &vectors, &score);
+        }
+        for node in graph.len()..data.len() {
+            graph.insert(node, &data.embedding(&codec, node)?.id, &vectors, &score);
+        }
+        vectors.check()?;
+
+        match graph.take_changes() {
+            Some(changes) => hnsw::append_changes(&self.root, segment, &changes).await,
+            None => hnsw::write_graph(&self.root, segment, &graph).await,
+        }
+    }
+
+    fn keyword_index_for(contents: &HashMap<Uuid, Content>) -> InvertedIndex {
+        let mut index = InvertedIndex::default();
+        for (id, content) in contents {
+            index.insert(*id, &content.content);
+        }
+        index
+    }
+
+    /// Bring the signatures for the db file `segment` up to date with what `changed` in it, like
+    /// [`Victor::sync_hnsw`]. Appended or rewritten embeddings only need their own signatures, so only they're
+    /// decoded, and their signatures are appended to the existing ones. Otherwise (or if the signatures don't
+    /// line up with the file) the whole file is read. Does nothing if the database doesn't have an LSH index.
+    async fn sync_lsh(&mut self, segment: &str, changed: Changed<'_>) -> Result<(), Error> {
+        let Some(config) = lsh::read_config(&self.root).await? else {
+            return Ok(());
+        };
+
+        let file_handle = self
+            .root
+            .get_file_handle_with_options(segment, &GetFileHandleOptions { create: true })
+            .await?;
+        let codec = self.codec().await?;
+
+        // where the changed embeddings start in the file, which for appends to a compressed file
+        // can't be told without reading it
+        let changed = match changed {
+            Changed::Appended {
+                records,
+                record_size,
+            } => match self.settings().await?.compression {
+                Compression::None => {
+                    let len =
+                        (file_handle.size().await? - std::mem::size_of::<u32>()) / record_size;
+                    Some((len - records.len() / record_size, records, record_size))
+                }
+                _ => None,
+            },
+            Changed::Rewritten { node, record } => Some((node, record, record.len())),
+            Changed::Unknown => None,
+        };
+        if let Some((first, records, record_size)) = changed {
+            let vectors = records
+                .chunks(record_size)
+                .map(|record| Ok(codec.decode(segment, record)?.vector))
+                .collect::<Result<Vec<_>, Error>>()?;
+            let dimension = vectors.first().map_or(0, Vec::len);
+            match lsh::read_signatures(&self.root, segment).await? {
+                Some(mut signatures)
+                    if signatures.dimension() == dimension
+                        && (first == signatures.len()
+                            || first + vectors.len() <= signatures.len()) =>
+                {
+                    for (node, vector) in (first..).zip(&vectors) {
+                        signatures.insert(node, vector);
+                    }
+                    return match signatures.take_changes() {
+                        Some(changes) => lsh::append_changes(&self.root, segment, &changes).await,
+                        None => lsh::write_signatures(&self.root, segment, &signatures).await,
+                    };
+                }
+                _ => {}
+            }
+        }
+
+        let file = self.read_segment(segment, &file_handle).await?;
+        let embeddings = self.get_embeddings_by_file(&codec, segment, file)?;
+        let Some(dimension) = embeddings.first().map(|embedding| embedding.vector.len()) else {
+            return Ok(());
+        };
+
+        let signatures = match lsh::read_signatures(&self.root, segment).await? {
+            Some(mut signatures)
+                if signatures.len() <= embeddings.len() && signatures.dimension() == dimension =>
+            {
+                for (node, embedding) in embeddings.iter().enumerate().skip(signatures.len()) {
+                    signatures.insert(node, &embedding.vector);
+                }
+                signatures
+            }
+            _ => {
+                let mut signatures = Lsh::new(config, dimension);
+                for (node, embedding) in embeddings.iter().enumerate() {
+                    signatures.insert(node, &embedding.vector);
+                }
+                signatures
+            }
+        };
+
+        lsh::write_signatures(&self.root, segment, &signatures).await
+    }
+
+    /// Read the bloom filter for the db file `segment`, if it has one that's up to date with the file.
+    async fn current_bloom(&self, segment: &str) -> Result<Option<Bloom>, Error> {
+        let Some(&checksum) = checksum::read_all(&self.root).await?.get(segment) else {
+            return Ok(None);
+        };
+        Ok(bloom::read(&self.root, segment)
+            .await?
+            .filter(|bloom| bloom.is_current(checksum)))
+    }
+
+    /// Bring the bloom filter for the db file `segment` up to date after `ids` were written to it,
+    /// given the filter as it was before, if it was up to date then.
+    /// Without one, or if it's run out of room, the filter is rebuilt from the whole file.
+    async fn sync_bloom(
+        &mut self,
+        segment: &str,
+        bloom: Option<Bloom>,
+        ids: &[Uuid],
+    ) -> Result<(), Error> {
+        let Some(&checksum) = checksum::read_all(&self.root).await?.get(segment) else {
+            // without a checksum, there's no telling whether a filter is up to date
+            let _ = self
+                .root
+                .remove_entry(&bloom::filename_for_segment(segment))
+                .await;
+            return Ok(());
+        };
+
+        let bloom = match bloom {
+            Some(mut bloom) if bloom.has_room(ids.len()) => {
+                for &id in ids {
+                    bloom.insert(id);
+                }
+                bloom.set_checksum(checksum);
+                bloom
+            }
+            _ => {
+                let file_handle = self
+                    .root
+                    .get_file_handle_with_options(segment, &GetFileHandleOptions { create: false })
+                    .await?;
+                let file = self.read_segment(segment, &file_handle).await?;
+                let embeddings = match file.is_empty() {
+                    true => Vec::new(),
+                    false => self.get_embeddings_by_file(&self.codec().await?, segment, file)?,
+                };
+                Bloom::new(
+                    embeddings.into_iter().map(|embedding| embedding.id),
+                    checksum,
+                )
+            }
+        };
+
+        bloom::write(&self.root, segment, &bloom).await
+    }
+
+    /// Bring any approximate indexes for the db file `segment` up to date with what `changed` in it.
+    async fn sync_indexes(&mut self, segment: &str, changed: Changed<'_>) -> Result<(), Error> {
+        // combined db files are never indexed, see [`Victor::read_combined_segments`]
+        if Combined::is_combined(segment) {
+            return Ok(());
+        }
+        let relink = match changed {
+            Changed::Rewritten { node, .. } => Some(node),
+            _ => None,
         };
+        self.sync_hnsw(segment, relink).await?;
+        self.sync_lsh(segment, changed).await
+    }
+
+    /// Rebuild the approximate indexes for every db file from scratch.
+    async fn rebuild_indexes(&mut self) -> Result<(), Error> {
+        for segment in Index::get_all_db_filenames(&mut self.root).await? {
+            let _ = self
+                .root
+                .remove_entry(&hnsw::filename_for_segment(&segment))
+                .await;
+            let _ = self
+                .root
+                .remove_entry(&lsh::filename_for_segment(&segment))
+                .await;
+            self.sync_indexes(&segment, Changed::Unknown).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn remove_hnsw_index(&mut self) -> Result<(), Error> {
+        for segment in Index::get_all_db_filenames(&mut self.root).await? {
+            let _ = self
+                .root
+                .remove_entry(&hnsw::filename_for_segment(&segment))
+                .await;
+        }
+        let _ = self.root.remove_entry("hnsw.bin").await;
+
+        Ok(())
+    }
+
+    async fn remove_lsh_index(&mut self) -> Result<(), Error> {
+        for segment in Index::get_all_db_filenames(&mut self.root).await? {
+            let _ = self
+                .root
+                .remove_entry(&lsh::filename_for_segment(&segment))
+                .await;
+        }
+        let _ = self.root.remove_entry("lsh.bin").await;
+
+        Ok(())
+    }
+
+    /// How embeddings are encoded in db files: product quantized if a quantizer has been trained,
+    /// binary if binary quantization is enabled, and as whole vectors otherwise.
+    async fn codec(&self) -> Result<Codec, Error> {
+        let quantizer_file_handle = self
+            .root
+            .get_file_handle_with_options("pq.bin", &GetFileHandleOptions { create: true })
+            .await?;
+
+        let quantizer = quantizer_file_handle.read().await?;
+
+        if !quantizer.is_empty() {
+            return deserialize("pq.bin", &quantizer).map(Codec::Product);
+        }
+
+        let binary_file_handle = self
+            .root
+            .get_file_handle_with_options("binary.bin", &GetFileHandleOptions { create: true })
+            .await?;
 
-        if file_handle.size().await? == 0 {
-            let serialized_size =
-                bincode::serialize(&embedding_size).expect("Failed to serialize size");
+        let binary = binary_file_handle.read().await?;
 
-            writable.write_at_cursor_pos(serialized_size).await?;
+        if binary.is_empty() {
+            self.settings()
+                .await
+                .map(|settings| Codec::Vector(settings.storage))
         } else {
-            let previous_embedding_size = Self::get_embedding_size(file_handle.read().await?);
-            assert_eq!(
-                embedding_size, previous_embedding_size,
-                "Embedding size mismatch: expected {} but got {}",
-                previous_embedding_size, embedding_size
-            );
+            deserialize("binary.bin", &binary).map(Codec::Binary)
         }
+    }
 
-        let all_embeddings_serialized = embeddings_serialized
-            .into_iter()
-            .flatten()
-            .collect::<Vec<_>>();
-        writable
-            .write_at_cursor_pos(all_embeddings_serialized)
+    /// The database's settings, chosen when it was created (see [`Victor::with_settings`]).
+    pub async fn settings(&self) -> Result<Settings, Error> {
+        let settings_file_handle = self
+            .root
+            .get_file_handle_with_options("settings.bin", &GetFileHandleOptions { create: true })
             .await?;
 
-        writable.close().await?;
+        let settings = settings_file_handle.read().await?;
 
-        if cfg!(target_arch = "wasm32") && file_handle.size().await? > 1000000 && !is_projected {
-            self.project_embeddings().await;
+        if !settings.is_empty() {
+            // the compression wasn't recorded until format version 2, or normalization until 10
+            return match format::read(&self.root).await? {
+                Some(version) if version >= 10 => deserialize("settings.bin", &settings),
+                Some(version) if version >= 2 => {
+                    deserialize::<SettingsV9>("settings.bin", &settings).map(Settings::from)
+                }
+                _ => deserialize::<SettingsV1>("settings.bin", &settings).map(Settings::from),
+            };
         }
 
-        Ok(())
-    }
-
-    async fn write_contents(&mut self, content: Vec<(String, Uuid)>) -> Result<(), D::Error> {
-        let mut content_file_handle = self
+        // databases that were created before settings were recorded were searched with euclidean distance
+        // once their embeddings were projected
+        let is_projected: bool = self
             .root
-            .get_file_handle_with_options("content.bin", &GetFileHandleOptions { create: true })
-            .await?;
+            .get_file_handle_with_options("eigen.bin", &GetFileHandleOptions { create: false })
+            .await
+            .is_ok();
 
-        let existing_content = content_file_handle.read().await?;
+        Ok(Settings {
+            metric: Metric::default_for(is_projected),
+            ..Default::default()
+        })
+    }
 
-        let mut hashmap: HashMap<Uuid, String> = if existing_content.is_empty() {
-            HashMap::new()
-        } else {
-            bincode::deserialize(&existing_content).expect("Failed to deserialize existing data")
+    /// The only tags documents can be added with, sorted, or `None` if any tags can be used.
+    /// Chosen when the database is created, see [`Builder::allowed_tags`].
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// let victor = Db::new(DirectoryHandle::default());
+    /// assert_eq!(victor.allowed_tags().await.unwrap(), None);
+    /// # })
+    /// ```
+    pub async fn allowed_tags(&self) -> Result<Option<Vec<String>>, Error> {
+        let Ok(file_handle) = self
+            .root
+            .get_file_handle_with_options(
+                "allowed_tags.bin",
+                &GetFileHandleOptions { create: false },
+            )
+            .await
+        else {
+            return Ok(None);
         };
 
-        for (content, id) in content {
-            hashmap.insert(id, content);
+        let allowed_tags = file_handle.read().await?;
+        deserialize("allowed_tags.bin", &allowed_tags).map(Some)
+    }
+
+    /// Record the only tags documents can be added with. Like the settings, they can't change once embeddings
+    /// have been added.
+    pub(crate) async fn set_allowed_tags(&mut self, tags: BTreeSet<String>) -> Result<(), Error> {
+        let tags = tags.into_iter().collect::<Vec<_>>();
+        let existing = self.allowed_tags().await?;
+        if existing.as_ref() == Some(&tags) {
+            return Ok(());
+        }
+        if !Index::get_all_db_filenames(&mut self.root)
+            .await?
+            .is_empty()
+        {
+            return Err(Error::InvalidInput(format!(
+                "the database was created with allowed tags {existing:?}, not {tags:?}"
+            )));
         }
 
-        let updated_data = bincode::serialize(&hashmap).expect("Failed to serialize hashmap");
+        write_bin(&self.root, "allowed_tags.bin", &tags).await
+    }
 
-        let mut content_writable = content_file_handle
-            .create_writable_with_options(&CreateWritableOptions {
-                keep_existing_data: true,
-            })
+    /// Whether the database has recorded its settings.
+    pub(crate) async fn has_settings(&self) -> Result<bool, Error> {
+        let settings_file_handle = self
+            .root
+            .get_file_handle_with_options("settings.bin", &GetFileHandleOptions { create: true })
             .await?;
 
-        content_writable.write_at_cursor_pos(updated_data).await?;
-        content_writable.close().await?;
+        Ok(settings_file_handle.size().await? > 0)
+    }
 
-        Ok(())
+    async fn write_settings(&mut self, settings: Settings) -> Result<(), Error> {
+        write_bin(&self.root, "settings.bin", &settings).await
     }
 
-    async fn get_content(&self, id: Uuid) -> String {
-        let content_file_handle = self
-            .root
-            .get_file_handle_with_options("content.bin", &GetFileHandleOptions { create: true })
-            .await
-            .unwrap();
+    async fn write_binary_config(&mut self, config: BinaryConfig) -> Result<(), Error> {
+        write_bin(&self.root, "binary.bin", &config).await
+    }
+
+    async fn write_product_quantizer(&mut self, quantizer: &ProductQuantizer) -> Result<(), Error> {
+        write_bin(&self.root, "pq.bin", quantizer).await
+    }
 
-        let existing_content = content_file_handle.read().await.unwrap();
+    async fn get_content(&self, id: Uuid) -> Result<Content, Error> {
+        let mut contents = self.get_contents(&[id]).await?;
+        Ok(contents.remove(0))
+    }
+
+    /// The content of each of `ids`, reading `content.bin` once (and not at all if there are no ids).
+    async fn get_contents(&self, ids: &[Uuid]) -> Result<Vec<Content>, Error> {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
 
-        let hashmap: HashMap<Uuid, String> =
-            bincode::deserialize(&existing_content).expect("Failed to deserialize existing data");
+        Self::find_contents(&self.read_content_log().await?.0, ids)
+    }
 
-        let content = hashmap.get(&id).unwrap();
+    fn find_contents(
+        hashmap: &HashMap<Uuid, Content>,
+        ids: &[Uuid],
+    ) -> Result<Vec<Content>, Error> {
+        ids.iter()
+            .map(|id| {
+                hashmap.get(id).cloned().ok_or_else(|| Error::Corrupted {
+                    file: "content.bin".to_string(),
+                    reason: format!("no content for embedding {id}"),
+                })
+            })
+            .collect()
+    }
 
-        content.to_string()
+    /// Ids supplied by the caller are mapped to a stable uuid, so the same id always refers to the same document.
+    fn uuid_for_external_id(external_id: &str) -> Uuid {
+        Uuid::new_v5(&Uuid::NAMESPACE_OID, external_id.as_bytes())
     }
 
     /// Clear the database, deleting all data.
-    pub async fn clear_db(&mut self) -> Result<(), D::Error> {
+    /// The database keeps its settings (see [`Victor::with_settings`]) and format version.
+    pub async fn clear_db(&mut self) -> Result<(), Error> {
+        // buffered documents would be written after everything else is gone
+        self.buffered.clear();
+
         // clear db files
         let files = Index::get_all_db_filenames(&mut self.root).await?;
         for file in files {
             self.root.remove_entry(&file).await?;
+            let _ = self
+                .root
+                .remove_entry(&hnsw::filename_for_segment(&file))
+                .await;
+            let _ = self
+                .root
+                .remove_entry(&lsh::filename_for_segment(&file))
+                .await;
+            let _ = self
+                .root
+                .remove_entry(&bloom::filename_for_segment(&file))
+                .await;
         }
 
-        // clear index file
+        // clear index files
         let _ = self.root.remove_entry("index.bin").await;
+        let _ = self.root.remove_entry("combined.bin").await;
 
         // clear content file
         let _ = self.root.remove_entry("content.bin").await;
 
-        // clear content file
+        // clear allowed tags file
+        let _ = self.root.remove_entry("allowed_tags.bin").await;
+
+        // clear projection file
         let _ = self.root.remove_entry("eigen.bin").await;
+        let _ = self.root.remove_entry("originals.bin").await;
+
+        // clear segment stats file
+        let _ = self.root.remove_entry("stats.bin").await;
+
+        // clear quantization files
+        let _ = self.root.remove_entry("pq.bin").await;
+        let _ = self.root.remove_entry("binary.bin").await;
+
+        // clear approximate index config files
+        let _ = self.root.remove_entry("hnsw.bin").await;
+        let _ = self.root.remove_entry("lsh.bin").await;
+
+        // clear keyword index file
+        let _ = self.root.remove_entry("keywords.bin").await;
+
+        // clear deleted and expiring documents, and when documents were added and changed
+        let _ = self.root.remove_entry("tombstones.bin").await;
+        let _ = self.root.remove_entry("expiry.bin").await;
+        let _ = self.root.remove_entry("added.bin").await;
+        let _ = self.root.remove_entry("versions.bin").await;
+        let _ = self.root.remove_entry("chunks.bin").await;
+
+        self.catalog = None;
+        self.cache().clear();
 
+        // clear checksums, now that the files they're for are gone
+        let _ = self.root.remove_entry("checksums.bin").await;
+
+        batch::remove(&mut self.root).await?;
+        journal::remove(&mut self.root).await?;
+        self.subscribers.send(Event::Cleared);
         Ok(())
     }
 }
 
+impl<D: DirectoryHandle> Drop for Victor<D> {
+    fn drop(&mut self) {
+        let unwritten = self
+            .buffered
+            .iter()
+            .map(|(_, documents)| documents.len())
+            .sum::<usize>();
+        if unwritten == 0 {
+            return;
+        }
+
+        // writing is async, and blocking on it here could stall (or deadlock) whatever runtime is dropping the
+        // database, so unwritten documents are only reported, see [`Victor::close`]
+        log::warn!(
+            "dropped victor with {unwritten} unwritten documents, call close before dropping it"
+        );
+    }
+}
+
 impl Index {
-    async fn load<D: DirectoryHandle>(root: &D) -> Result<(D::FileHandleT, Self), D::Error> {
+    async fn load<D: DirectoryHandle>(root: &D) -> Result<(D::FileHandleT, Self), Error> {
+        let (file_handle, mut index) = Self::read_files(root).await?;
+        index.combined = Self::read_combined(root).await?;
+        Ok((file_handle, index))
+    }
+
+    /// Read `index.bin`, without the combined db files.
+    async fn read_files<D: DirectoryHandle>(root: &D) -> Result<(D::FileHandleT, Self), Error> {
         let file_handle = root
             .get_file_handle_with_options("index.bin", &GetFileHandleOptions { create: true })
             .await?;
@@ -636,93 +7662,286 @@ impl Index {
             Ok((file_handle, index))
         } else {
             let index_bytes = file_handle.read().await?;
-            let index =
-                bincode::deserialize::<Self>(&index_bytes).expect("Failed to deserialize index");
+            checksum::verify(root, "index.bin", &index_bytes).await?;
+            // parts weren't tracked until format version 4
+            let index = match format::read(root).await? {
+                Some(version) if version >= 4 => deserialize("index.bin", &index_bytes)?,
+                _ => deserialize::<IndexV3>("index.bin", &index_bytes)?.into(),
+            };
             Ok((file_handle, index))
         }
     }
 
-    fn filename_for_tags(tags: BTreeSet<String>) -> String {
+    /// Read `combined.bin`, the combined db files and their tag maps.
+    async fn read_combined<D: DirectoryHandle>(root: &D) -> Result<Vec<Combined>, Error> {
+        let Ok(file_handle) = root
+            .get_file_handle_with_options("combined.bin", &GetFileHandleOptions { create: false })
+            .await
+        else {
+            return Ok(Vec::new());
+        };
+
+        let combined = file_handle.read().await?;
+        if combined.is_empty() {
+            return Ok(Vec::new());
+        }
+        checksum::verify(root, "combined.bin", &combined).await?;
+        deserialize("combined.bin", &combined)
+    }
+
+    /// Write the combined db files and their tag maps to `combined.bin`.
+    async fn write_combined<D: DirectoryHandle>(&self, root: &D) -> Result<(), Error> {
+        let mut file_handle = root
+            .get_file_handle_with_options("combined.bin", &GetFileHandleOptions { create: true })
+            .await?;
+        let combined_bytes =
+            bincode::serialize(&self.combined).expect("Failed to serialize combined db files");
+        let mut writable = file_handle
+            .create_writable_with_options(&CreateWritableOptions {
+                keep_existing_data: false,
+            })
+            .await?;
+        writable.write_at_cursor_pos(combined_bytes.clone()).await?;
+        writable.close().await?;
+        checksum::record(root, "combined.bin", &combined_bytes).await
+    }
+
+    /// The combined db file named `filename`, if it is one.
+    fn combined(&self, filename: &str) -> Option<&Combined> {
+        self.combined
+            .iter()
+            .find(|combined| combined.filename == filename)
+    }
+
+    fn combined_mut(&mut self, filename: &str) -> Option<&mut Combined> {
+        self.combined
+            .iter_mut()
+            .find(|combined| combined.filename == filename)
+    }
+
+    /// The name of every db file, combined ones included.
+    fn filenames(&self) -> Vec<String> {
+        self.files
+            .iter()
+            .flat_map(|tags| self.filenames_for_tags(tags))
+            .chain(
+                self.combined
+                    .iter()
+                    .map(|combined| combined.filename.clone()),
+            )
+            .collect()
+    }
+
+    /// `tags`, restricted to documents with no other tags if the search is for [`SearchOptions::exact_tags`].
+    fn tag_filter(&self, tags: TagFilter, options: &SearchOptions) -> TagFilter {
+        match options.exact_tags {
+            true => tags.exactly(self.files.iter().flatten()),
+            false => tags,
+        }
+    }
+
+    /// The name of every db file with embeddings that match `tags`, combined ones included.
+    fn matching_filenames(&self, tags: &TagFilter) -> Vec<String> {
+        self.files
+            .iter()
+            .filter(|file_tags| tags.matches(file_tags))
+            .flat_map(|file_tags| self.filenames_for_tags(file_tags))
+            .chain(
+                self.combined
+                    .iter()
+                    .filter(|combined| {
+                        combined
+                            .tag_map
+                            .iter()
+                            .any(|(file_tags, _)| tags.matches(file_tags))
+                    })
+                    .map(|combined| combined.filename.clone()),
+            )
+            .collect()
+    }
+
+    async fn write<D: DirectoryHandle>(
+        &self,
+        root: &D,
+        index_file: &mut D::FileHandleT,
+    ) -> Result<(), Error> {
+        let index_bytes = bincode::serialize(self).expect("Failed to serialize index");
+        let mut writable = index_file
+            .create_writable_with_options(&CreateWritableOptions {
+                keep_existing_data: false,
+            })
+            .await?;
+        writable.write_at_cursor_pos(index_bytes.clone()).await?;
+        writable.close().await?;
+        checksum::record(root, "index.bin", &index_bytes).await
+    }
+
+    /// The name of the `part`th db file for `tags`.
+    /// The first part is named after the tags alone, like db files were before they were split.
+    pub(crate) fn filename_for_part(tags: BTreeSet<String>, part: usize) -> String {
+        Self::filename_for_hash(&Self::hash_tags(tags), part)
+    }
+
+    /// The hash of `tags` that their db files are named after.
+    fn hash_tags(tags: BTreeSet<String>) -> String {
         let mut tags = tags.into_iter().collect::<Vec<_>>();
         tags.sort();
-        let input = format!("{:?}", tags);
-        format!("{}.bin", digest(input))
+        digest(format!("{:?}", tags))
     }
 
-    async fn file_handle_for_tag<D: DirectoryHandle>(
-        root: &D,
-        tags: BTreeSet<String>,
-    ) -> Result<D::FileHandleT, D::Error> {
-        // Get the filename by just hashing the tags
-        let filename = Self::filename_for_tags(tags);
+    /// The name of the `part`th db file for the tags with the given hash.
+    fn filename_for_hash(hash: &str, part: usize) -> String {
+        match part {
+            0 => format!("{hash}.bin"),
+            part => format!("{hash}.{part:04}.bin"),
+        }
+    }
 
-        root.get_file_handle_with_options(&filename, &GetFileHandleOptions { create: true })
-            .await
+    /// The hash of the tags a db file is for, and which part of them it is,
+    /// or `None` if `filename` isn't the name of a db file.
+    fn parse_filename(filename: &str) -> Option<(&str, usize)> {
+        let name = filename.strip_suffix(".bin")?;
+        let (hash, part) = match name.split_once('.') {
+            Some((hash, part))
+                if part.len() == 4 && part.bytes().all(|byte| byte.is_ascii_digit()) =>
+            {
+                (hash, part.parse().ok()?)
+            }
+            Some(_) => return None,
+            None => (name, 0),
+        };
+        let is_hash = hash.len() == 64 && hash.bytes().all(|byte| byte.is_ascii_hexdigit());
+        is_hash.then_some((hash, part))
     }
 
-    async fn get_exact_db_file<D: DirectoryHandle>(
+    /// The tags of the documents in each db file,
+    /// and in each of the ranges of combined db files (see [`Combined::ranges`]).
+    fn tags_by_filename(&self) -> HashMap<String, BTreeSet<String>> {
+        self.files
+            .iter()
+            .flat_map(|tags| {
+                self.filenames_for_tags(tags)
+                    .into_iter()
+                    .map(move |filename| (filename, tags.clone()))
+            })
+            .chain(self.combined.iter().flat_map(|combined| {
+                combined
+                    .ranges()
+                    .into_iter()
+                    .map(|(_, name, tags)| (name, tags.clone()))
+            }))
+            .collect()
+    }
+
+    /// The names of every db file for `tags`, oldest first.
+    fn filenames_for_tags(&self, tags: &BTreeSet<String>) -> Vec<String> {
+        let parts = self.parts.get(tags).copied().unwrap_or(1);
+        (0..parts)
+            .map(|part| Self::filename_for_part(tags.clone(), part))
+            .collect()
+    }
+
+    /// The db file new documents with `tags` should be added to: the last one for `tags`,
+    /// unless it's at least `max_segment_size` bytes, in which case a new one is started.
+    /// Also returns whether the file is new.
+    async fn get_writable_db_file<D: DirectoryHandle>(
         root: &mut D,
-        tags: Vec<String>,
-    ) -> Result<D::FileHandleT, D::Error> {
+        tags: BTreeSet<String>,
+        max_segment_size: usize,
+    ) -> Result<(String, D::FileHandleT, bool), Error> {
         let (mut index_file, mut index) = Self::load(root).await?;
-        let tags = tags.into_iter().collect::<BTreeSet<_>>();
 
         // If the set of tags isn't in the index, add it
-        if !index.files.contains(&tags) {
-            index.files.insert(tags.clone());
+        let mut is_new = index.files.insert(tags.clone());
 
-            let index_bytes = bincode::serialize(&index).expect("Failed to serialize index");
-            let mut writable = index_file
-                .create_writable_with_options(&CreateWritableOptions {
-                    keep_existing_data: false,
-                })
+        // tag sets whose db files were all combined start over with a new one
+        let mut parts = index.parts.get(&tags).copied().unwrap_or(1);
+        if parts == 0 {
+            parts = 1;
+            index.parts.remove(&tags);
+            is_new = true;
+        }
+        let filename = Self::filename_for_part(tags.clone(), parts - 1);
+        let file_handle = root
+            .get_file_handle_with_options(&filename, &GetFileHandleOptions { create: true })
+            .await?;
+        let (filename, file_handle) = if file_handle.size().await? >= max_segment_size.max(1) {
+            parts += 1;
+            index.parts.insert(tags.clone(), parts);
+            is_new = true;
+
+            let filename = Self::filename_for_part(tags, parts - 1);
+            let file_handle = root
+                .get_file_handle_with_options(&filename, &GetFileHandleOptions { create: true })
                 .await?;
-            writable.write_at_cursor_pos(index_bytes).await?;
-            writable.close().await?;
+            (filename, file_handle)
+        } else {
+            (filename, file_handle)
+        };
+
+        if is_new {
+            index.write(root, &mut index_file).await?;
         }
 
-        Self::file_handle_for_tag(root, tags).await
+        Ok((filename, file_handle, is_new))
     }
 
+    /// The db files with embeddings that match `tags`. Combined db files may have embeddings that don't match,
+    /// see [`Combined::ranges`].
     async fn get_matching_db_files<D: DirectoryHandle>(
         root: &D,
-        tags: BTreeSet<String>,
-    ) -> Result<Vec<D::FileHandleT>, D::Error> {
+        tags: &TagFilter,
+    ) -> Result<Vec<(String, D::FileHandleT)>, Error> {
         let (_, index) = Self::load(root).await?;
+        index.open_files(root, tags).await
+    }
 
-        let matching_tags = index
-            .files
-            .iter()
-            .filter(|file_tags| file_tags.is_superset(&tags))
-            .cloned();
-
+    /// Open each of the db files with embeddings that match `tags`.
+    async fn open_files<D: DirectoryHandle>(
+        &self,
+        root: &D,
+        tags: &TagFilter,
+    ) -> Result<Vec<(String, D::FileHandleT)>, Error> {
         let mut files = Vec::new();
-        for tags in matching_tags {
-            let file = Self::file_handle_for_tag(root, tags.clone()).await?;
-            files.push(file)
+        for filename in self.matching_filenames(tags) {
+            let file = root
+                .get_file_handle_with_options(&filename, &GetFileHandleOptions { create: true })
+                .await?;
+            files.push((filename, file))
         }
 
         Ok(files)
     }
 
-    async fn get_all_db_filenames<D: DirectoryHandle>(
-        root: &mut D,
-    ) -> Result<Vec<String>, D::Error> {
+    async fn get_all_db_filenames<D: DirectoryHandle>(root: &mut D) -> Result<Vec<String>, Error> {
         let (_, index) = Self::load(root).await?;
-
-        Ok(index
-            .files
-            .into_iter()
-            .map(Self::filename_for_tags)
-            .collect())
+        Ok(index.filenames())
     }
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct NearestNeighborsResult {
     pub similarity: f32,
+    /// How good a match the document is, from 0 to 1 where higher is better, whatever the metric
+    /// (see [`Metric::score`]). Keyword matches and fused or re-ranked results have their own scores
+    /// scaled to between 0 and 1 instead.
+    #[serde(default)]
+    pub score: f32,
+    /// The metric's own value for the match, like the cosine similarity or the distance (see [`Metric::raw`]).
+    /// `None` for results that weren't scored by the metric: keyword matches, and fused or re-ranked results.
+    #[serde(default)]
+    pub raw: Option<f32>,
+    /// The scores that went into `similarity`, if [`SearchOptions::include_breakdown`] is on.
+    #[serde(default)]
+    pub breakdown: Option<ScoreBreakdown>,
+    /// Empty if [`SearchOptions::include_vector`] is off, though the id is always there.
     pub embedding: Embedding,
+    /// Empty if [`SearchOptions::include_content`] is off.
     pub content: String,
+    pub external_id: Option<String>,
+    /// The document's tags, sorted, if [`SearchOptions::include_tags`] is on.
+    #[serde(default)]
+    pub tags: Vec<String>,
 }
 
 impl PartialEq for NearestNeighborsResult {
@@ -741,8 +7960,353 @@ impl PartialOrd for NearestNeighborsResult {
 
 impl Ord for NearestNeighborsResult {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.similarity
-            .partial_cmp(&other.similarity)
-            .expect("could not compare, most likely a NaN is involved")
+        self.similarity.total_cmp(&other.similarity)
+    }
+}
+
+/// Deserialize the contents of a file in the database, reporting failures as corruption of that file.
+pub(crate) fn deserialize<T: DeserializeOwned>(filename: &str, bytes: &[u8]) -> Result<T, Error> {
+    bincode::deserialize(bytes).map_err(|e| Error::Corrupted {
+        file: filename.to_string(),
+        reason: e.to_string(),
+    })
+}
+
+/// Read the file `filename`, which holds a single value written by [`write_bin`],
+/// or `None` if it's empty (or didn't exist).
+pub(crate) async fn read_optional<T: DeserializeOwned, D: DirectoryHandle>(
+    root: &D,
+    filename: &str,
+) -> Result<Option<T>, Error> {
+    let file_handle = root
+        .get_file_handle_with_options(filename, &GetFileHandleOptions { create: true })
+        .await?;
+
+    let bytes = file_handle.read().await?;
+
+    if bytes.is_empty() {
+        Ok(None)
+    } else {
+        deserialize(filename, &bytes).map(Some)
+    }
+}
+
+/// Read the file `filename`, which holds a single value written by [`write_bin`],
+/// or the default value (like an empty map) if it's empty.
+pub(crate) async fn read_bin<T: DeserializeOwned + Default, D: DirectoryHandle>(
+    root: &D,
+    filename: &str,
+) -> Result<T, Error> {
+    Ok(read_optional(root, filename).await?.unwrap_or_default())
+}
+
+/// Replace the contents of the file `filename` with `value`.
+pub(crate) async fn write_bin<T: Serialize + ?Sized, D: DirectoryHandle>(
+    root: &D,
+    filename: &str,
+    value: &T,
+) -> Result<(), Error> {
+    let mut file_handle = root
+        .get_file_handle_with_options(filename, &GetFileHandleOptions { create: true })
+        .await?;
+
+    let bytes =
+        bincode::serialize(value).unwrap_or_else(|e| panic!("Failed to serialize {filename}: {e}"));
+
+    let mut writable = file_handle
+        .create_writable_with_options(&CreateWritableOptions {
+            keep_existing_data: false,
+        })
+        .await?;
+    writable.write_at_cursor_pos(bytes).await?;
+    writable.close().await?;
+
+    Ok(())
+}
+
+/// Append `values` to the end of the file `filename`, one after another, rather than rewriting it.
+pub(crate) async fn append_bin<T: Serialize, D: DirectoryHandle>(
+    root: &D,
+    filename: &str,
+    values: &[T],
+) -> Result<(), Error> {
+    if values.is_empty() {
+        return Ok(());
+    }
+    let mut file_handle = root
+        .get_file_handle_with_options(filename, &GetFileHandleOptions { create: true })
+        .await?;
+
+    let mut bytes = Vec::new();
+    for value in values {
+        bincode::serialize_into(&mut bytes, value)
+            .unwrap_or_else(|e| panic!("Failed to serialize {filename}: {e}"));
+    }
+
+    let previous_size = file_handle.size().await?;
+    let mut writable = file_handle
+        .create_writable_with_options(&CreateWritableOptions {
+            keep_existing_data: true,
+        })
+        .await?;
+    writable.seek(previous_size).await?;
+    writable.write_at_cursor_pos(bytes).await?;
+    writable.close().await?;
+
+    Ok(())
+}
+
+/// A query vector, prepared for comparing against the embeddings in each db file.
+#[derive(Clone, Copy)]
+struct Query<'a> {
+    vector: &'a [f32],
+    top_n: usize,
+    options: &'a SearchOptions,
+    metric: Metric,
+    codec: &'a Codec,
+    distance_table: Option<&'a DistanceTable>,
+    query_bits: Option<&'a [u64]>,
+    /// Only loaded when searching with a metadata filter.
+    contents: Option<&'a HashMap<Uuid, Content>>,
+    /// Deleted documents, which are skipped.
+    tombstones: &'a HashSet<Uuid>,
+    /// The document each extra vector belongs to, see [`chunks::read`].
+    chunks: &'a HashMap<Uuid, Uuid>,
+    /// When each document was added. Only loaded when searching by [`SearchOptions::added`],
+    /// or with a [`SearchOptions::scorer`].
+    added: Option<&'a HashMap<Uuid, u64>>,
+    /// The tags of each db file, only looked up when they're needed (see [`SearchOptions::needs_tags`]).
+    tags_by_filename: &'a HashMap<String, BTreeSet<String>>,
+}
+
+impl Query<'_> {
+    /// The document the embedding `id` in the db file `filename` belongs to, if its contents were loaded.
+    /// Extra vectors belong to the document they were added with.
+    fn document(&self, id: Uuid, filename: &str) -> Option<DocumentMeta<'_>> {
+        let id = *self.chunks.get(&id).unwrap_or(&id);
+        let content = self.contents?.get(&id)?;
+        Some(DocumentMeta {
+            id,
+            external_id: content.external_id.as_deref(),
+            metadata: &content.metadata,
+            added: self.added.and_then(|added| added.get(&id).copied()),
+            tags: self.tags_by_filename.get(filename).unwrap_or(&NO_TAGS),
+        })
+    }
+
+    /// The `similarity` of the embedding `id` in the db file `filename` to the query, adjusted by the
+    /// [`SearchOptions::scorer`] and [`SearchOptions::tag_boosts`], if there are any.
+    fn score(&self, similarity: f32, id: Uuid, filename: &str) -> f32 {
+        let similarity = match (&self.options.scorer, self.document(id, filename)) {
+            (Some(scorer), Some(document)) => scorer.score(similarity, &document),
+            _ => similarity,
+        };
+        match self
+            .options
+            .boost(self.tags_by_filename.get(filename).into_iter().flatten())
+        {
+            Some(boost) => search::boosted(similarity, boost),
+            None => similarity,
+        }
+    }
+}
+
+/// What changed in a db file, to bring its indexes up to date with (see [`Victor::sync_indexes`]).
+#[derive(Clone, Copy)]
+enum Changed<'a> {
+    /// These encoded embeddings were appended to it.
+    Appended {
+        records: &'a [u8],
+        record_size: usize,
+    },
+    /// The embedding at position `node` was rewritten, and is now encoded as `record`.
+    Rewritten { node: usize, record: &'a [u8] },
+    /// Anything could have, like after compaction or recovering from a crash.
+    Unknown,
+}
+
+/// A db file (or a chunk of one) read for searching, see [`Victor::read_segments`].
+struct Segment {
+    filename: String,
+    data: SegmentData,
+    record_size: usize,
+    graph: Option<Hnsw>,
+    signatures: Option<Lsh>,
+}
+
+enum SegmentData {
+    /// The encoded embeddings, without the file's header.
+    Read(Vec<u8>),
+    /// The encoded embeddings, shared with the cache (see [`Victor::with_cache_budget`]).
+    Cached(Arc<Vec<u8>>),
+    /// The decoded embeddings, shared with the cache.
+    Decoded(Arc<Vec<Embedding>>),
+    /// The whole file, including its header.
+    Mapped(Mapped),
+}
+
+impl SegmentData {
+    /// The encoded embeddings, unless they're cached decoded.
+    fn records(&self) -> Option<&[u8]> {
+        match self {
+            SegmentData::Read(data) => Some(data),
+            SegmentData::Cached(data) => Some(data),
+            SegmentData::Decoded(_) => None,
+            SegmentData::Mapped(map) => Some(&(**map).as_ref()[std::mem::size_of::<u32>()..]),
+        }
+    }
+
+    /// How many bytes of embeddings there are to scan.
+    fn size(&self) -> usize {
+        match self {
+            SegmentData::Decoded(embeddings) => embeddings.first().map_or(0, |embedding| {
+                embeddings.len() * decoded_size(embedding.vector.len())
+            }),
+            data => data.records().map_or(0, <[u8]>::len),
+        }
+    }
+}
+
+/// How many bytes a decoded embedding with `dimension` dimensions takes up.
+fn decoded_size(dimension: usize) -> usize {
+    std::mem::size_of::<Embedding>() + dimension * std::mem::size_of::<f32>()
+}
+
+impl Segment {
+    /// The number of embeddings in the segment.
+    fn len(&self) -> usize {
+        match (&self.data, self.data.records()) {
+            (SegmentData::Decoded(embeddings), _) => embeddings.len(),
+            (_, records) => records.map_or(0, <[u8]>::len) / self.record_size,
+        }
+    }
+
+    /// The encoded embedding at position `node`.
+    fn record(&self, node: usize) -> &[u8] {
+        let records = self
+            .data
+            .records()
+            .expect("only whole vectors are cached decoded, and they're never read encoded");
+        &records[node * self.record_size..(node + 1) * self.record_size]
+    }
+
+    /// The embedding at position `node`, decoding it unless it's cached decoded.
+    fn embedding(&self, codec: &Codec, node: usize) -> Result<Cow<'_, Embedding>, Error> {
+        match &self.data {
+            SegmentData::Decoded(embeddings) => Ok(Cow::Borrowed(&embeddings[node])),
+            _ => codec
+                .decode(&self.filename, self.record(node))
+                .map(Cow::Owned),
+        }
+    }
+}
+
+/// The vectors of a segment's embeddings, decoded as an HNSW graph visits them rather than all up front.
+/// The first embedding that fails to decode is kept for [`SegmentVectors::check`], and scores as dissimilar.
+struct SegmentVectors<'a> {
+    segment: &'a Segment,
+    codec: &'a Codec,
+    error: RefCell<Option<Error>>,
+}
+
+impl<'a> SegmentVectors<'a> {
+    fn new(segment: &'a Segment, codec: &'a Codec) -> Self {
+        Self {
+            segment,
+            codec,
+            error: RefCell::new(None),
+        }
     }
+
+    /// Fail if any embedding visited so far couldn't be decoded.
+    fn check(&self) -> Result<(), Error> {
+        match self.error.borrow_mut().take() {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Vectors for SegmentVectors<'_> {
+    fn vector(&self, node: usize) -> Cow<'_, [f32]> {
+        match self.segment.embedding(self.codec, node) {
+            Ok(Cow::Borrowed(embedding)) => Cow::Borrowed(&embedding.vector),
+            Ok(Cow::Owned(embedding)) => Cow::Owned(embedding.vector),
+            Err(error) => {
+                self.error.borrow_mut().get_or_insert(error);
+                Cow::Owned(Vec::new())
+            }
+        }
+    }
+}
+
+/// An embedding [`Victor::scan_segment`] is considering, decoded in the form it's scored in.
+enum Candidate<'a> {
+    Vector(Cow<'a, Embedding>),
+    /// Scored with the query's [`DistanceTable`].
+    Coded(CodedEmbedding),
+    /// Scored by Hamming distance, or by its vector if it kept one.
+    Binary(BinaryEmbedding),
+}
+
+impl<'a> Candidate<'a> {
+    fn decode(segment: &'a Segment, query: &Query, node: usize) -> Result<Self, Error> {
+        if query.distance_table.is_some() {
+            deserialize(&segment.filename, segment.record(node)).map(Candidate::Coded)
+        } else if query.query_bits.is_some() {
+            deserialize(&segment.filename, segment.record(node)).map(Candidate::Binary)
+        } else {
+            segment.embedding(query.codec, node).map(Candidate::Vector)
+        }
+    }
+
+    fn id(&self) -> Uuid {
+        match self {
+            Candidate::Vector(embedding) => embedding.id,
+            Candidate::Coded(coded) => coded.id,
+            Candidate::Binary(binary) => binary.id,
+        }
+    }
+}
+
+/// The start of the name of every combined db file, see [`Combined`].
+const COMBINED_PREFIX: &str = "combined-";
+
+/// [`Victor::optimize`] combines db files smaller than this.
+const SMALL_SEGMENT_SIZE: usize = 1024 * 1024;
+
+/// Db files stop growing once they're this big, unless [`Victor::with_max_segment_size`] says otherwise.
+const DEFAULT_MAX_SEGMENT_SIZE: usize = 32 * 1024 * 1024;
+
+/// Imports like [`Victor::import_jsonl`] and [`Victor::import_matrix`] add this many documents at a time.
+const IMPORT_BATCH_SIZE: usize = 1000;
+
+/// [`Victor::add_with_progress`] generates this many embeddings at a time.
+const EMBED_BATCH_SIZE: usize = 256;
+
+/// Hybrid searches fuse this many times `top_n` of the best vector and keyword matches.
+const HYBRID_CANDIDATES: u32 = 4;
+
+/// How many rounds of k-means [`Victor::outliers`] runs to cluster documents.
+const OUTLIER_ITERATIONS: usize = 10;
+
+/// Unindexed db files are searched this many bytes at a time.
+const SCAN_CHUNK_SIZE: usize = 8 * 1024 * 1024;
+
+/// How many bytes of db files are read before scanning them.
+/// Native searches scan each batch in parallel, so they read more at once.
+#[cfg(not(target_arch = "wasm32"))]
+const SCAN_BATCH_SIZE: usize = 64 * 1024 * 1024;
+#[cfg(target_arch = "wasm32")]
+const SCAN_BATCH_SIZE: usize = SCAN_CHUNK_SIZE;
+
+/// For searches that don't report the matches they find along the way.
+const NO_PROGRESS: Option<fn(&[NearestNeighborsResult])> = None;
+
+/// The tags of documents in db files whose tags weren't looked up.
+static NO_TAGS: BTreeSet<String> = BTreeSet::new();
+
+/// The similarity used to link and navigate HNSW graphs, where higher is always more similar.
+fn hnsw_score(metric: Metric) -> impl Fn(&[f32], &[f32]) -> f32 {
+    move |a, b| metric.similarity(a, b).unwrap_or(f32::NEG_INFINITY)
 }
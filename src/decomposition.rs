@@ -26,6 +26,52 @@ extern "C" {
     fn warn(s: &str);
 }
 
+/// How many dimensions embeddings are projected to, see [`crate::Victor::project`].
+///
+/// ```rust
+/// use victor_db::Projection;
+///
+/// // keep 95% of the variance between embeddings, with as few dimensions as that takes
+/// let projection = Projection::ExplainedVariance(0.95);
+/// assert_eq!(Projection::from(256), Projection::Dimensions(256));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Projection {
+    /// Exactly this many dimensions.
+    Dimensions(usize),
+    /// The fewest dimensions that explain at least this fraction of the variance between embeddings,
+    /// between 0 and 1.
+    ExplainedVariance(f32),
+}
+
+impl From<usize> for Projection {
+    fn from(dimensions: usize) -> Self {
+        Self::Dimensions(dimensions)
+    }
+}
+
+/// When to reduce the dimension of every embedding automatically, see [`crate::Victor::with_auto_projection`].
+///
+/// Projecting uses PCA: embeddings are projected onto the directions they vary the most in,
+/// which shrinks the database at the cost of some accuracy.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AutoProjection {
+    /// Project once a db file grows past this many bytes.
+    pub segment_size: usize,
+    /// How many dimensions embeddings are projected to.
+    /// Embeddings with a fixed number of dimensions or fewer aren't projected.
+    pub projection: Projection,
+}
+
+impl Default for AutoProjection {
+    fn default() -> Self {
+        Self {
+            segment_size: 1_000_000,
+            projection: Projection::Dimensions(500),
+        }
+    }
+}
+
 pub fn embeddings_to_dmatrix(embeddings: Vec<Vec<f32>>) -> DMatrix<f32> {
     // Get the number of rows and columns
     let nrows = embeddings.len();
@@ -72,7 +118,7 @@ fn sort_eigenvectors_and_eigenvalues(
         .collect();
 
     // Sort pairs in descending order based on the eigenvalues.
-    pairs.sort_by(|(val1, _vec1), (val2, _vec2)| val2.partial_cmp(val1).unwrap());
+    pairs.sort_by(|(val1, _vec1), (val2, _vec2)| val2.total_cmp(val1));
 
     // Unzip the sorted pairs.
     let (sorted_eigenvalues, sorted_eigenvectors_list): (Vec<_>, Vec<_>) =
@@ -85,7 +131,12 @@ fn sort_eigenvectors_and_eigenvalues(
     (sorted_eigenvalues, sorted_eigenvectors)
 }
 
-pub fn project_to_lower_dimension(data: Vec<Embedding>, k: usize) -> (DMatrix<f32>, Vec<f32>) {
+/// The `k` directions `data` varies the most in, as the columns of a matrix, and the means they're centered on.
+/// Also returns the fraction of the variance those directions explain.
+pub fn project_to_lower_dimension(
+    data: Vec<Embedding>,
+    projection: Projection,
+) -> (DMatrix<f32>, Vec<f32>, f32) {
     let matrix =
         embeddings_to_dmatrix(data.into_iter().map(|embedding| embedding.vector).collect());
 
@@ -93,10 +144,36 @@ pub fn project_to_lower_dimension(data: Vec<Embedding>, k: usize) -> (DMatrix<f3
     let covariance_matrix = compute_covariance_matrix(&centered_data);
 
     let (eigenvalues, eigenvectors) = compute_eigenvectors_and_eigenvalues(&covariance_matrix);
-    let (_sorted_eigenvalues, sorted_eigenvectors) =
+    let (sorted_eigenvalues, sorted_eigenvectors) =
         sort_eigenvectors_and_eigenvalues(eigenvalues, eigenvectors);
 
+    // each eigenvalue is the variance along its eigenvector, though rounding can make tiny ones negative
+    let variances = sorted_eigenvalues
+        .iter()
+        .map(|variance| variance.max(0.0))
+        .collect::<Vec<_>>();
+    let total_variance = variances.iter().sum::<f32>();
+
+    let k = match projection {
+        Projection::Dimensions(k) => k,
+        Projection::ExplainedVariance(ratio) => {
+            let mut explained = 0.0;
+            variances
+                .iter()
+                .position(|variance| {
+                    explained += variance;
+                    explained >= ratio * total_variance
+                })
+                .map_or(variances.len(), |index| index + 1)
+        }
+    };
+    let explained_variance = if total_variance > 0.0 {
+        variances[..k].iter().sum::<f32>() / total_variance
+    } else {
+        1.0
+    };
+
     let top_k_eigenvectors = sorted_eigenvectors.columns(0, k);
 
-    (top_k_eigenvectors.into(), means)
+    (top_k_eigenvectors.into(), means, explained_variance)
 }
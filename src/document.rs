@@ -0,0 +1,164 @@
+//! Documents and the metadata attached to them.
+
+use std::{collections::BTreeMap, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+/// A value stored in a document's [`Metadata`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum MetadataValue {
+    /// A string value, like an author or a category.
+    String(String),
+    /// A numeric value, like a price or a timestamp. Can be filtered by range.
+    Number(f64),
+}
+
+/// Key/value metadata attached to a document, which can be used to [`crate::Filter`] searches.
+pub type Metadata = BTreeMap<String, MetadataValue>;
+
+impl From<String> for MetadataValue {
+    fn from(value: String) -> Self {
+        Self::String(value)
+    }
+}
+
+impl From<&str> for MetadataValue {
+    fn from(value: &str) -> Self {
+        Self::String(value.to_string())
+    }
+}
+
+impl From<f64> for MetadataValue {
+    fn from(value: f64) -> Self {
+        Self::Number(value)
+    }
+}
+
+impl From<i64> for MetadataValue {
+    fn from(value: i64) -> Self {
+        Self::Number(value as f64)
+    }
+}
+
+impl MetadataValue {
+    pub(crate) fn as_number(&self) -> Option<f64> {
+        match self {
+            Self::Number(number) => Some(*number),
+            Self::String(_) => None,
+        }
+    }
+}
+
+/// A document and its embedding, ready to be added to the database.
+///
+/// ```rust
+/// use victor_db::Document;
+///
+/// let document = Document::new("Pepperoni pizza", vec![0.1, 0.2, 0.3])
+///     .with_id("pizza-1")
+///     .with_metadata("author", "alice");
+/// ```
+#[derive(Debug, Clone)]
+pub struct Document {
+    pub(crate) content: String,
+    pub(crate) vector: Vec<f32>,
+    pub(crate) id: Option<String>,
+    pub(crate) metadata: Metadata,
+    pub(crate) ttl: Option<Duration>,
+    pub(crate) chunks: Vec<Vec<f32>>,
+}
+
+impl Document {
+    /// Create a new document from its content and embedding.
+    pub fn new(content: impl Into<String>, vector: Vec<f32>) -> Self {
+        Self {
+            content: content.into(),
+            vector,
+            id: None,
+            metadata: Metadata::new(),
+            ttl: None,
+            chunks: Vec::new(),
+        }
+    }
+
+    /// Use your own id for this document.
+    /// The id is returned with search results, and adding a document with the same id again updates it.
+    pub fn with_id(mut self, id: impl Into<String>) -> Self {
+        self.id = Some(id.into());
+        self
+    }
+
+    /// Attach a metadata field to this document.
+    pub fn with_metadata(
+        mut self,
+        key: impl Into<String>,
+        value: impl Into<MetadataValue>,
+    ) -> Self {
+        self.metadata.insert(key.into(), value.into());
+        self
+    }
+
+    /// Add another embedding for this document, say for one of the chunks or passages of its content.
+    /// Searches match the document by its vectors together (see [`crate::SearchOptions::aggregation`]),
+    /// and return it once.
+    ///
+    /// ```rust
+    /// use victor_db::Document;
+    ///
+    /// let document = Document::new("Pepperoni pizza. Pineapple pizza.", vec![0.1, 0.2, 0.3])
+    ///     .with_chunk(vec![0.3, 0.2, 0.1]);
+    /// ```
+    pub fn with_chunk(mut self, vector: Vec<f32>) -> Self {
+        self.chunks.push(vector);
+        self
+    }
+
+    /// Expire this document `ttl` after it's added. Expired documents are left out of searches right away,
+    /// like deleted ones, and take up space until [`crate::Victor::compact`] is called.
+    /// Adding the document again (with or without a TTL) replaces its expiry.
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// This document's content.
+    pub fn content(&self) -> &str {
+        &self.content
+    }
+
+    /// This document's embedding.
+    pub fn vector(&self) -> &[f32] {
+        &self.vector
+    }
+
+    /// This document's id, if it has one of your own.
+    pub fn id(&self) -> Option<&str> {
+        self.id.as_deref()
+    }
+
+    /// This document's metadata.
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    /// This document's metadata, to change, say from [`crate::Victor::with_on_insert`].
+    pub fn metadata_mut(&mut self) -> &mut Metadata {
+        &mut self.metadata
+    }
+}
+
+/// What to do when a document is added with the same content as one already in the database,
+/// see [`crate::Victor::with_deduplication`].
+///
+/// Content is compared by its SHA-256 hash, exactly: documents that differ only in whitespace are different.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Deduplication {
+    /// Add the document anyway, so the same content can be stored more than once.
+    #[default]
+    Off,
+    /// Leave the existing document as it is, and don't add the new one.
+    Skip,
+    /// Replace the existing document's vector and metadata with the new one's,
+    /// keeping its id and tags (like [`crate::Victor::update`]).
+    Update,
+}
@@ -0,0 +1,136 @@
+//! Finding documents that are nearly the same, see [`crate::Victor::find_duplicates`].
+//!
+//! Comparing every document with every other one is quadratic, so documents are first split into blocks with
+//! k-means, about the square root of their number, and only compared within blocks. A pair of near-duplicates
+//! can straddle the boundary between two blocks, so each document is also compared with the block whose
+//! centroid is second closest to it.
+
+use std::collections::HashSet;
+
+use uuid::Uuid;
+
+use crate::{kmeans, similarity::Metric};
+
+/// How many rounds of k-means are run to split documents into blocks.
+const ITERATIONS: usize = 10;
+
+/// Documents that are nearly the same, from [`crate::Victor::find_duplicates`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct DuplicateGroup {
+    /// The ids of the documents, in the order they're stored in (usually the order they were added).
+    pub ids: Vec<Uuid>,
+    /// Each pair of documents in the group whose similarity is above the threshold, with their similarity,
+    /// most similar first. Every document is in at least one pair, but not every pair of documents is
+    /// necessarily similar enough: a group is everything linked together by a chain of pairs.
+    pub pairs: Vec<(Uuid, Uuid, f32)>,
+}
+
+/// Each pair of `vectors` (by position, lower first) whose similarity by `metric` is above `threshold`.
+/// `blocking` are the vectors to split them into blocks by, which are normalized for cosine similarity.
+pub(crate) fn similar_pairs(
+    vectors: &[Vec<f32>],
+    blocking: &[Vec<f32>],
+    metric: Metric,
+    threshold: f32,
+) -> Vec<(usize, usize, f32)> {
+    let blocks = (vectors.len() as f64).sqrt() as usize;
+    let centroids = kmeans::kmeans(blocking, blocks, ITERATIONS);
+
+    // each vector's closest and second closest block
+    let mut members = vec![Vec::new(); centroids.len()];
+    let mut probes = Vec::new();
+    for (i, vector) in blocking.iter().enumerate() {
+        let mut closest = centroids
+            .iter()
+            .map(|centroid| kmeans::squared_distance(centroid, vector))
+            .enumerate()
+            .collect::<Vec<_>>();
+        closest.sort_by(|(_, a), (_, b)| a.total_cmp(b));
+        members[closest[0].0].push(i);
+        if let Some(&(second, _)) = closest.get(1) {
+            probes.push((i, second));
+        }
+    }
+
+    let mut candidates = HashSet::new();
+    for block in &members {
+        for (n, &i) in block.iter().enumerate() {
+            candidates.extend(block[n + 1..].iter().map(|&j| (i, j)));
+        }
+    }
+    for (i, block) in probes {
+        candidates.extend(members[block].iter().map(|&j| (i.min(j), i.max(j))));
+    }
+
+    let mut pairs = candidates
+        .into_iter()
+        .filter_map(|(i, j)| {
+            let similarity = metric.similarity(&vectors[i], &vectors[j]).ok()?;
+            (similarity > threshold).then_some((i, j, similarity))
+        })
+        .collect::<Vec<_>>();
+    pairs.sort_by(|(a_i, a_j, _), (b_i, b_j, _)| (a_i, a_j).cmp(&(b_i, b_j)));
+    pairs
+}
+
+/// Group `pairs` of positions into sets linked together by a chain of pairs, each sorted, in order of their first
+/// position.
+pub(crate) fn group(pairs: &[(usize, usize, f32)], len: usize) -> Vec<Vec<usize>> {
+    // union-find, always linking to the lower position so each group's root is its first position
+    let mut parents = (0..len).collect::<Vec<_>>();
+    fn root(parents: &mut [usize], mut i: usize) -> usize {
+        while parents[i] != i {
+            parents[i] = parents[parents[i]];
+            i = parents[i];
+        }
+        i
+    }
+    for &(i, j, _) in pairs {
+        let (i, j) = (root(&mut parents, i), root(&mut parents, j));
+        parents[i.max(j)] = i.min(j);
+    }
+
+    let mut groups = vec![Vec::new(); len];
+    let linked = pairs
+        .iter()
+        .flat_map(|&(i, j, _)| [i, j])
+        .collect::<HashSet<_>>();
+    for i in 0..len {
+        if linked.contains(&i) {
+            groups[root(&mut parents, i)].push(i);
+        }
+    }
+    groups.retain(|group| !group.is_empty());
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_pairs_across_blocks() {
+        // a grid of distinct vectors, with a near-copy of every tenth one
+        let mut vectors = (0..100)
+            .map(|i| vec![(i % 10) as f32, (i / 10) as f32])
+            .collect::<Vec<_>>();
+        for i in (0..100).step_by(10) {
+            let mut copy = vectors[i].clone();
+            copy[0] += 0.01;
+            vectors.push(copy);
+        }
+
+        let pairs = similar_pairs(&vectors, &vectors, Metric::Euclidean, -0.1);
+        assert_eq!(pairs.len(), 10);
+        assert!(pairs
+            .iter()
+            .all(|&(i, j, similarity)| j == 100 + i / 10 && similarity > -0.1));
+    }
+
+    #[test]
+    fn groups_chains_of_pairs() {
+        let pairs = [(0, 2, 0.9), (2, 5, 0.9), (3, 4, 0.95)];
+        assert_eq!(group(&pairs, 6), vec![vec![0, 2, 5], vec![3, 4]]);
+        assert!(group(&[], 3).is_empty());
+    }
+}
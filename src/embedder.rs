@@ -0,0 +1,28 @@
+//! Generating embeddings for text, for [`crate::Victor::add`] and [`crate::Victor::search`].
+
+use async_trait::async_trait;
+
+use crate::{error::Error, filesystem::MaybeSendSync};
+
+/// Generates embeddings for documents and queries, see [`crate::Victor::with_embedder`].
+///
+/// ```rust
+/// use async_trait::async_trait;
+/// use victor_db::{Embedder, Error};
+///
+/// /// Embeds text by its length, which isn't very useful.
+/// struct LengthEmbedder;
+///
+/// #[async_trait]
+/// impl Embedder for LengthEmbedder {
+///     async fn embed(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>, Error> {
+///         Ok(texts.iter().map(|text| vec![text.len() as f32, 1.0]).collect())
+///     }
+/// }
+/// ```
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+pub trait Embedder: MaybeSendSync {
+    /// Embed each of `texts`, returning one embedding per text, in the same order.
+    async fn embed(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>, Error>;
+}
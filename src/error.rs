@@ -0,0 +1,118 @@
+//! The error type returned by victor.
+
+use std::fmt;
+
+/// An error returned by a [`crate::Victor`] database.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// Reading or writing a file on the native filesystem failed.
+    Io(std::io::Error),
+    /// The in-memory or web filesystem returned an error.
+    Filesystem(String),
+    /// A file in the database couldn't be read, most likely because it was corrupted or truncated.
+    Corrupted {
+        /// The name of the file in the database directory.
+        file: String,
+        /// What was wrong with the file.
+        reason: String,
+    },
+    /// An embedding doesn't have the same number of dimensions as the embeddings it's being compared or stored
+    /// with.
+    DimensionMismatch {
+        /// The number of dimensions of the existing embeddings.
+        expected: usize,
+        /// The number of dimensions of the new embedding.
+        found: usize,
+    },
+    /// Generating an embedding for a document failed.
+    Embedding(String),
+    /// Re-ranking search results failed, see [`crate::Victor::with_reranker`].
+    Reranking(String),
+    /// An operation was given arguments it can't work with.
+    InvalidInput(String),
+    /// Documents were added with a tag the database doesn't allow, see [`crate::Builder::allowed_tags`].
+    TagNotAllowed {
+        /// The tag that isn't allowed.
+        tag: String,
+    },
+    /// The database was written by a newer version of victor, in a format this version can't read.
+    UnsupportedVersion {
+        /// The version of the database's format.
+        found: u32,
+        /// The newest version this version of victor can read.
+        supported: u32,
+    },
+    /// A conditional update or delete found the document at another version than it expected,
+    /// see [`crate::Victor::update_if_version`].
+    VersionConflict {
+        /// The id of the document.
+        id: uuid::Uuid,
+        /// The version the document was expected to be at.
+        expected: u64,
+        /// The version the document is at, or `None` if it doesn't exist.
+        found: Option<u64>,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(error) => write!(f, "IO error: {error}"),
+            Error::Filesystem(error) => write!(f, "filesystem error: {error}"),
+            Error::Corrupted { file, reason } => write!(f, "'{file}' is corrupted: {reason}"),
+            Error::DimensionMismatch { expected, found } => write!(
+                f,
+                "embedding dimension mismatch: expected {expected} but got {found}"
+            ),
+            Error::Embedding(error) => write!(f, "failed to generate embedding: {error}"),
+            Error::Reranking(error) => write!(f, "failed to re-rank results: {error}"),
+            Error::InvalidInput(error) => write!(f, "invalid input: {error}"),
+            Error::TagNotAllowed { tag } => {
+                write!(f, "the tag '{tag}' isn't one of the database's allowed tags")
+            }
+            Error::UnsupportedVersion { found, supported } => write!(
+                f,
+                "the database has format version {found}, but only versions up to {supported} are supported"
+            ),
+            Error::VersionConflict {
+                id,
+                expected,
+                found: Some(found),
+            } => write!(
+                f,
+                "document {id} is at version {found}, not version {expected}"
+            ),
+            Error::VersionConflict {
+                id,
+                expected,
+                found: None,
+            } => write!(
+                f,
+                "document {id} doesn't exist, so it isn't at version {expected}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl<E: crate::filesystem::FilesystemError> From<E> for Error {
+    fn from(error: E) -> Self {
+        error.into_error()
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+impl From<Error> for wasm_bindgen::JsValue {
+    fn from(error: Error) -> Self {
+        js_sys::Error::new(&error.to_string()).into()
+    }
+}
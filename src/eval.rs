@@ -0,0 +1,93 @@
+//! Measuring what approximate search costs in accuracy, see [`evaluate_recall`].
+
+use serde::Serialize;
+
+use crate::{db::Victor, error::Error, filesystem::DirectoryHandle, TagFilter};
+
+/// How closely a database's searches match exact ones, from [`evaluate_recall`].
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct RecallReport {
+    /// How many queries were searched for.
+    pub queries: usize,
+    /// How many results each search asked for.
+    pub k: u32,
+    /// The average fraction of the exact top `k` documents that search found, between 0 and 1.
+    pub recall: f32,
+    /// The average difference between the similarity search reported for a result and its exact similarity.
+    pub mean_score_error: f32,
+    /// The largest difference between the similarity search reported for a result and its exact similarity.
+    pub max_score_error: f32,
+}
+
+/// Search `db` for each of the `queries` the way it's configured to (with quantized or projected vectors and
+/// approximate indexes, if it has them), and compare the results with an exact search that compares the query
+/// with every document at full precision, to see what those cost in accuracy.
+///
+/// Full precision means each document's original vector when the database retains them
+/// (see [`Victor::with_retained_originals`]), and otherwise the vector as it's stored. Without originals, the
+/// report only covers what approximate indexes cost, not packing or projecting vectors.
+///
+/// Documents whose exact similarity ties with the `k`th best count as found, whichever of them search returned.
+///
+/// ```rust
+/// # tokio_test::block_on(async {
+/// # use victor_db::memory::{Db, DirectoryHandle};
+/// # let mut victor = Db::new(DirectoryHandle::default());
+/// use victor_db::eval::evaluate_recall;
+///
+/// victor.add_single_embedding("Pepperoni pizza", vec![0.1, 0.2, 0.3], vec!["Pizza Flavors"]).await.unwrap();
+/// victor.add_single_embedding("Pineapple", vec![0.3, 0.2, 0.1], vec!["Pizza Flavors"]).await.unwrap();
+///
+/// let report = evaluate_recall(&victor, &[vec![0.1, 0.2, 0.3]], 1).await.unwrap();
+/// assert_eq!(report.recall, 1.0);
+/// # })
+/// ```
+pub async fn evaluate_recall<D: DirectoryHandle>(
+    db: &Victor<D>,
+    queries: &[Vec<f32>],
+    k: u32,
+) -> Result<RecallReport, Error> {
+    let mut recall = 0.0;
+    let mut score_errors = Vec::new();
+    for query in queries {
+        let exact = db.exact_similarities(query).await?;
+        let results = db
+            .search_embedding(query.clone(), TagFilter::default(), k)
+            .await?;
+
+        let mut best = exact.values().copied().collect::<Vec<_>>();
+        best.sort_by(|a, b| b.total_cmp(a));
+        best.truncate(k as usize);
+        let Some(&threshold) = best.last() else {
+            // nothing to find, so nothing was missed
+            recall += 1.0;
+            continue;
+        };
+
+        let mut found = 0;
+        for result in &results {
+            let Some(&similarity) = exact.get(&result.embedding.id) else {
+                continue;
+            };
+            if similarity >= threshold {
+                found += 1;
+            }
+            score_errors.push((result.similarity - similarity).abs());
+        }
+        recall += found.min(best.len()) as f32 / best.len() as f32;
+    }
+
+    Ok(RecallReport {
+        queries: queries.len(),
+        k,
+        recall: match queries.len() {
+            0 => 1.0,
+            queries => recall / queries as f32,
+        },
+        mean_score_error: match score_errors.len() {
+            0 => 0.0,
+            errors => score_errors.iter().sum::<f32>() / errors as f32,
+        },
+        max_score_error: score_errors.into_iter().fold(0.0, f32::max),
+    })
+}
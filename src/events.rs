@@ -0,0 +1,84 @@
+//! Events describing changes to the database, sent to every subscriber (see [`crate::Victor::subscribe`]).
+//!
+//! Events are sent once a change is written, in the order the changes were made. The changes of a
+//! [batch](crate::Victor::batch) are held back until the batch is committed, and dropped if it's rolled back.
+
+use futures::channel::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use uuid::Uuid;
+
+/// A change to the database, from [`crate::Victor::subscribe`].
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum Event {
+    /// Documents were added, or added again after being deleted.
+    Inserted {
+        /// The ids of the documents.
+        ids: Vec<Uuid>,
+    },
+    /// Documents were changed, with [`crate::Victor::update`] or by adding documents with the same ids again.
+    Updated {
+        /// The ids of the documents.
+        ids: Vec<Uuid>,
+    },
+    /// Documents were deleted.
+    Deleted {
+        /// The ids of the documents.
+        ids: Vec<Uuid>,
+    },
+    /// The space taken by deleted documents was reclaimed, see [`crate::Victor::compact`].
+    /// No document changed, but the positions of embeddings within db files may have.
+    Compacted {
+        /// The number of bytes reclaimed.
+        reclaimed: u64,
+    },
+    /// Every vector was projected to fewer dimensions, see [`crate::Victor::project`].
+    Projected,
+    /// Every vector was put back the way it was before it was projected, see [`crate::Victor::unproject`].
+    Unprojected,
+    /// The database was cleared (or replaced, by [`crate::Victor::load_bundle`]),
+    /// so anything kept in sync with it needs rebuilding.
+    Cleared,
+}
+
+/// Everyone subscribed to a database's events.
+#[derive(Default)]
+pub(crate) struct Subscribers {
+    senders: Vec<UnboundedSender<Event>>,
+    /// Events held back until a batch is committed, if one is being applied.
+    held: Option<Vec<Event>>,
+}
+
+impl Subscribers {
+    pub(crate) fn subscribe(&mut self) -> UnboundedReceiver<Event> {
+        let (sender, receiver) = mpsc::unbounded();
+        self.senders.push(sender);
+        receiver
+    }
+
+    pub(crate) fn send(&mut self, event: Event) {
+        if let Some(held) = &mut self.held {
+            held.push(event);
+            return;
+        }
+        // subscribers that dropped their stream are forgotten
+        self.senders
+            .retain(|sender| sender.unbounded_send(event.clone()).is_ok());
+    }
+
+    /// Hold back events until [`Subscribers::release`] or [`Subscribers::discard`].
+    pub(crate) fn hold(&mut self) {
+        self.held = Some(Vec::new());
+    }
+
+    /// Send the events held back.
+    pub(crate) fn release(&mut self) {
+        for event in self.held.take().unwrap_or_default() {
+            self.send(event);
+        }
+    }
+
+    /// Drop the events held back, since the changes they describe were undone.
+    pub(crate) fn discard(&mut self) {
+        self.held = None;
+    }
+}
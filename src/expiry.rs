@@ -0,0 +1,36 @@
+//! When documents added with a TTL (see [`crate::Document::with_ttl`]) expire, recorded in `expiry.bin`.
+//!
+//! Expired documents are left out of searches right away (see [`crate::tombstones::removed`]),
+//! and dropped by [`crate::Victor::compact`] like deleted ones.
+
+use std::collections::HashMap;
+
+use uuid::Uuid;
+
+use crate::{
+    db::{read_bin, write_bin},
+    error::Error,
+    filesystem::DirectoryHandle,
+};
+
+const FILENAME: &str = "expiry.bin";
+
+/// When each document with a TTL expires, in milliseconds since the Unix epoch.
+pub(crate) async fn read<D: DirectoryHandle>(root: &D) -> Result<HashMap<Uuid, u64>, Error> {
+    read_bin(root, FILENAME).await
+}
+
+pub(crate) async fn write<D: DirectoryHandle>(
+    root: &D,
+    expiry: &HashMap<Uuid, u64>,
+) -> Result<(), Error> {
+    write_bin(root, FILENAME, expiry).await
+}
+
+/// The documents in `expiry` that have expired by `now`.
+pub(crate) fn expired(expiry: &HashMap<Uuid, u64>, now: u64) -> impl Iterator<Item = Uuid> + '_ {
+    expiry
+        .iter()
+        .filter(move |(_, expires_at)| **expires_at <= now)
+        .map(|(id, _)| *id)
+}
@@ -0,0 +1,198 @@
+//! A filesystem implemented in JavaScript, for runtimes without the origin private file system, like Node.
+//! See [`StorageAdapter`] for what the JavaScript side implements.
+
+use async_trait::async_trait;
+use js_sys::{Array, Promise, Uint8Array};
+use wasm_bindgen::{prelude::wasm_bindgen, JsValue};
+use wasm_bindgen_futures::JsFuture;
+
+use crate::filesystem;
+
+#[wasm_bindgen(typescript_custom_section)]
+const STORAGE_ADAPTER: &'static str = r#"
+/**
+ * Where a database stores its files, for runtimes without the origin private file system, like Node.
+ * Every file is in the same directory, and every method is given the file's name.
+ */
+export interface StorageAdapter {
+  /** Whether the file exists. */
+  exists(name: string): Promise<boolean>;
+  /** The file's size in bytes. */
+  size(name: string): Promise<number>;
+  /** Up to `length` bytes of the file starting at `offset`, fewer if the file ends first. */
+  read(name: string, offset: number, length: number): Promise<Uint8Array>;
+  /** Write `data` to the file at `offset`, overwriting what's there and growing the file if needed. */
+  write(name: string, offset: number, data: Uint8Array): Promise<void>;
+  /** Cut the file down (or grow it with zeroes) to `size` bytes, creating it if it doesn't exist. */
+  truncate(name: string, size: number): Promise<void>;
+  /** Delete the file. */
+  remove(name: string): Promise<void>;
+  /** The names of every file. */
+  list(): Promise<string[]>;
+}
+"#;
+
+#[wasm_bindgen]
+extern "C" {
+    /// A JavaScript object implementing the `StorageAdapter` interface.
+    #[wasm_bindgen(typescript_type = "StorageAdapter")]
+    #[derive(Debug, Clone)]
+    pub type StorageAdapter;
+
+    #[wasm_bindgen(method, catch)]
+    fn exists(this: &StorageAdapter, name: &str) -> Result<Promise, JsValue>;
+
+    #[wasm_bindgen(method, catch)]
+    fn size(this: &StorageAdapter, name: &str) -> Result<Promise, JsValue>;
+
+    #[wasm_bindgen(method, catch)]
+    fn read(
+        this: &StorageAdapter,
+        name: &str,
+        offset: f64,
+        length: f64,
+    ) -> Result<Promise, JsValue>;
+
+    #[wasm_bindgen(method, catch)]
+    fn write(
+        this: &StorageAdapter,
+        name: &str,
+        offset: f64,
+        data: &Uint8Array,
+    ) -> Result<Promise, JsValue>;
+
+    #[wasm_bindgen(method, catch)]
+    fn truncate(this: &StorageAdapter, name: &str, size: f64) -> Result<Promise, JsValue>;
+
+    #[wasm_bindgen(method, catch)]
+    fn remove(this: &StorageAdapter, name: &str) -> Result<Promise, JsValue>;
+
+    #[wasm_bindgen(method, catch)]
+    fn list(this: &StorageAdapter) -> Result<Promise, JsValue>;
+}
+
+/// Wait for a call to the adapter to finish.
+async fn call(promise: Result<Promise, JsValue>) -> Result<JsValue, JsValue> {
+    JsFuture::from(promise?).await
+}
+
+#[derive(Debug)]
+pub(crate) struct DirectoryHandle(StorageAdapter);
+
+#[derive(Debug)]
+pub(crate) struct FileHandle {
+    storage: StorageAdapter,
+    name: String,
+}
+
+#[derive(Debug)]
+pub(crate) struct WritableFileStream {
+    storage: StorageAdapter,
+    name: String,
+    cursor_pos: usize,
+}
+
+impl From<StorageAdapter> for DirectoryHandle {
+    fn from(storage: StorageAdapter) -> Self {
+        Self(storage)
+    }
+}
+
+#[async_trait(?Send)]
+impl filesystem::DirectoryHandle for DirectoryHandle {
+    type Error = JsValue;
+    type FileHandleT = FileHandle;
+
+    async fn get_file_handle_with_options(
+        &self,
+        name: &str,
+        options: &filesystem::GetFileHandleOptions,
+    ) -> Result<Self::FileHandleT, Self::Error> {
+        let exists = call(self.0.exists(name)).await?.is_truthy();
+        if !exists {
+            if !options.create {
+                return Err(JsValue::from_str(&format!("'{name}' does not exist")));
+            }
+            call(self.0.truncate(name, 0.0)).await?;
+        }
+        Ok(FileHandle {
+            storage: self.0.clone(),
+            name: name.to_string(),
+        })
+    }
+
+    async fn remove_entry(&mut self, name: &str) -> Result<(), Self::Error> {
+        call(self.0.remove(name)).await?;
+        Ok(())
+    }
+
+    async fn list_entries(&self) -> Result<Vec<String>, Self::Error> {
+        let names = call(self.0.list()).await?;
+        Ok(Array::from(&names)
+            .iter()
+            .filter_map(|name| name.as_string())
+            .collect())
+    }
+}
+
+#[async_trait(?Send)]
+impl filesystem::FileHandle for FileHandle {
+    type Error = JsValue;
+    type WritableFileStreamT = WritableFileStream;
+
+    async fn create_writable_with_options(
+        &mut self,
+        options: &filesystem::CreateWritableOptions,
+    ) -> Result<Self::WritableFileStreamT, Self::Error> {
+        if !options.keep_existing_data {
+            call(self.storage.truncate(&self.name, 0.0)).await?;
+        }
+        Ok(WritableFileStream {
+            storage: self.storage.clone(),
+            name: self.name.clone(),
+            cursor_pos: 0,
+        })
+    }
+
+    async fn read(&self) -> Result<Vec<u8>, Self::Error> {
+        let size = self.size().await?;
+        self.read_range(0, size).await
+    }
+
+    async fn read_range(&self, offset: usize, len: usize) -> Result<Vec<u8>, Self::Error> {
+        let data = call(self.storage.read(&self.name, offset as f64, len as f64)).await?;
+        Ok(Uint8Array::new(&data).to_vec())
+    }
+
+    async fn size(&self) -> Result<usize, Self::Error> {
+        let size = call(self.storage.size(&self.name)).await?;
+        Ok(size.as_f64().unwrap_or_default() as usize)
+    }
+}
+
+#[async_trait(?Send)]
+impl filesystem::WritableFileStream for WritableFileStream {
+    type Error = JsValue;
+
+    async fn write_at_cursor_pos(&mut self, data: Vec<u8>) -> Result<(), Self::Error> {
+        // copied into JavaScript, since wasm memory can move while the write is pending
+        let array = Uint8Array::from(data.as_slice());
+        call(
+            self.storage
+                .write(&self.name, self.cursor_pos as f64, &array),
+        )
+        .await?;
+        self.cursor_pos += data.len();
+        Ok(())
+    }
+
+    async fn close(&mut self) -> Result<(), Self::Error> {
+        // every write goes straight to the adapter
+        Ok(())
+    }
+
+    async fn seek(&mut self, offset: usize) -> Result<(), Self::Error> {
+        self.cursor_pos = offset;
+        Ok(())
+    }
+}
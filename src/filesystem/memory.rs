@@ -1,6 +1,9 @@
 //! "in-memory" filesystem for use in tests or when persistence isn't necessary
 
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, MutexGuard},
+};
 
 use async_trait::async_trait;
 
@@ -16,7 +19,7 @@ pub enum DirectoryEntry {
 
 /// A virtual directory in the in-memory filesystem.
 #[derive(Debug, Clone)]
-pub struct DirectoryHandle(Rc<RefCell<HashMap<String, DirectoryEntry>>>);
+pub struct DirectoryHandle(Arc<Mutex<HashMap<String, DirectoryEntry>>>);
 
 /// A virtual file in the in-memory filesystem.
 #[derive(Debug, Clone)]
@@ -26,10 +29,11 @@ pub struct FileHandle(WritableFileStream);
 #[derive(Debug, Clone)]
 pub struct WritableFileStream {
     cursor_pos: usize,
-    stream: Rc<RefCell<Vec<u8>>>,
+    stream: Arc<Mutex<Vec<u8>>>,
 }
 
-#[async_trait(?Send)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
 impl filesystem::DirectoryHandle for DirectoryHandle {
     type Error = String;
     type FileHandleT = FileHandle;
@@ -39,7 +43,7 @@ impl filesystem::DirectoryHandle for DirectoryHandle {
         name: &str,
         options: &filesystem::GetFileHandleOptions,
     ) -> Result<Self::FileHandleT, Self::Error> {
-        let mut directory = self.0.borrow_mut();
+        let mut directory = lock(&self.0);
         let entry = match directory.entry(name.to_string()) {
             std::collections::hash_map::Entry::Occupied(entry) => entry.get().clone(),
             std::collections::hash_map::Entry::Vacant(entry) => {
@@ -60,18 +64,28 @@ impl filesystem::DirectoryHandle for DirectoryHandle {
     }
 
     async fn remove_entry(&mut self, name: &str) -> Result<(), Self::Error> {
-        let mut directory = self.0.borrow_mut();
+        let mut directory = lock(&self.0);
         directory.remove(name);
         Ok(())
     }
+
+    async fn list_entries(&self) -> Result<Vec<String>, Self::Error> {
+        let directory = lock(&self.0);
+        Ok(directory
+            .iter()
+            .filter(|(_, entry)| matches!(entry, DirectoryEntry::File(_)))
+            .map(|(name, _)| name.clone())
+            .collect())
+    }
 }
 impl Default for DirectoryHandle {
     fn default() -> Self {
-        Self(Rc::new(RefCell::new(HashMap::new())))
+        Self(Arc::new(Mutex::new(HashMap::new())))
     }
 }
 
-#[async_trait(?Send)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
 impl filesystem::FileHandle for FileHandle {
     type Error = String;
     type WritableFileStreamT = WritableFileStream;
@@ -81,7 +95,7 @@ impl filesystem::FileHandle for FileHandle {
         options: &filesystem::CreateWritableOptions,
     ) -> Result<Self::WritableFileStreamT, Self::Error> {
         if !options.keep_existing_data {
-            self.0.stream.borrow_mut().clear();
+            lock(&self.0.stream).clear();
         }
         Ok(WritableFileStream {
             cursor_pos: 0,
@@ -90,9 +104,14 @@ impl filesystem::FileHandle for FileHandle {
     }
 
     async fn read(&self) -> Result<Vec<u8>, Self::Error> {
-        let stream = self.0.stream.clone();
-        let data = stream.borrow().clone();
-        Ok(data)
+        Ok(lock(&self.0.stream).clone())
+    }
+
+    async fn read_range(&self, offset: usize, len: usize) -> Result<Vec<u8>, Self::Error> {
+        let stream = lock(&self.0.stream);
+        let start = offset.min(stream.len());
+        let end = offset.saturating_add(len).min(stream.len());
+        Ok(stream[start..end].to_vec())
     }
 
     async fn size(&self) -> Result<usize, Self::Error> {
@@ -100,21 +119,22 @@ impl filesystem::FileHandle for FileHandle {
     }
 }
 
-#[async_trait(?Send)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
 impl filesystem::WritableFileStream for WritableFileStream {
     type Error = String;
 
     async fn write_at_cursor_pos(&mut self, data: Vec<u8>) -> Result<(), Self::Error> {
-        let data_len = data.len();
+        let end = self.cursor_pos + data.len();
 
-        let mut stream = self.stream.borrow_mut();
-        *stream = stream[0..self.cursor_pos]
-            .iter()
-            .cloned()
-            .chain(data)
-            .collect::<Vec<u8>>();
+        // overwrite in place (like the native and web filesystems), growing the file if needed
+        let mut stream = lock(&self.stream);
+        if stream.len() < end {
+            stream.resize(end, 0);
+        }
+        stream[self.cursor_pos..end].copy_from_slice(&data);
 
-        self.cursor_pos += data_len;
+        self.cursor_pos = end;
 
         Ok(())
     }
@@ -146,11 +166,19 @@ impl WritableFileStream {
     fn new() -> Self {
         Self {
             cursor_pos: 0,
-            stream: Rc::new(RefCell::new(Vec::new())),
+            stream: Arc::new(Mutex::new(Vec::new())),
         }
     }
 
     fn len(&self) -> usize {
-        self.stream.borrow().len()
+        lock(&self.stream).len()
     }
 }
+
+/// Lock `mutex`, even if a thread panicked while holding it: every change to the files is a single step,
+/// so they're never left half-changed.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
@@ -3,13 +3,81 @@ pub mod memory;
 #[cfg(target_arch = "wasm32")]
 pub mod web;
 
+#[cfg(target_arch = "wasm32")]
+pub mod adapter;
+
 #[cfg(not(target_arch = "wasm32"))]
 pub mod native;
 
+#[cfg(all(feature = "object-store", not(target_arch = "wasm32")))]
+pub mod object_store;
+
 use std::fmt::Debug;
 
 use async_trait::async_trait;
 
+/// Send + Sync on native, so databases can be shared between threads (see [`crate::Shared`]), and nothing on wasm,
+/// where JavaScript values (like the web filesystem's handles) can't be sent between threads anyway.
+#[cfg(not(target_arch = "wasm32"))]
+pub trait MaybeSendSync: Send + Sync {}
+#[cfg(not(target_arch = "wasm32"))]
+impl<T: Send + Sync> MaybeSendSync for T {}
+
+/// Send + Sync on native, so databases can be shared between threads, and nothing on wasm,
+/// where JavaScript values (like the web filesystem's handles) can't be sent between threads anyway.
+#[cfg(target_arch = "wasm32")]
+pub trait MaybeSendSync {}
+#[cfg(target_arch = "wasm32")]
+impl<T> MaybeSendSync for T {}
+
+/// An error from a filesystem, which can be converted into a [`crate::Error`].
+pub trait FilesystemError: Debug + MaybeSendSync {
+    fn into_error(self) -> crate::Error;
+
+    /// Whether the error is because the file doesn't exist.
+    fn is_not_found(&self) -> bool;
+}
+
+impl FilesystemError for String {
+    fn into_error(self) -> crate::Error {
+        crate::Error::Filesystem(self)
+    }
+
+    fn is_not_found(&self) -> bool {
+        // see memory::DirectoryHandle::get_file_handle_with_options
+        self.ends_with("does not exist")
+    }
+}
+
+impl FilesystemError for std::io::Error {
+    fn into_error(self) -> crate::Error {
+        crate::Error::Io(self)
+    }
+
+    fn is_not_found(&self) -> bool {
+        self.kind() == std::io::ErrorKind::NotFound
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+impl FilesystemError for wasm_bindgen::JsValue {
+    fn into_error(self) -> crate::Error {
+        crate::Error::Filesystem(format!("{self:?}"))
+    }
+
+    fn is_not_found(&self) -> bool {
+        // the web filesystem throws a `NotFoundError` DOMException, and the adapter a message like the memory one
+        let name = js_sys::Reflect::get(self, &"name".into()).ok();
+        name.and_then(|name| name.as_string()).as_deref() == Some("NotFoundError")
+            || self
+                .as_string()
+                .is_some_and(|message| message.ends_with("does not exist"))
+    }
+}
+
+/// A file's contents that can be read without copying them into memory, like a memory-mapped file.
+pub type Mapped = Box<dyn AsRef<[u8]> + Send + Sync>;
+
 pub struct GetFileHandleOptions {
     pub create: bool,
 }
@@ -18,9 +86,10 @@ pub struct CreateWritableOptions {
     pub keep_existing_data: bool,
 }
 
-#[async_trait(?Send)]
-pub trait DirectoryHandle: Debug {
-    type Error: Debug;
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+pub trait DirectoryHandle: Debug + MaybeSendSync {
+    type Error: FilesystemError;
     type FileHandleT: FileHandle<Error = Self::Error>;
 
     async fn get_file_handle_with_options(
@@ -30,11 +99,15 @@ pub trait DirectoryHandle: Debug {
     ) -> Result<Self::FileHandleT, Self::Error>;
 
     async fn remove_entry(&mut self, name: &str) -> Result<(), Self::Error>;
+
+    /// The names of the files in the directory, in no particular order.
+    async fn list_entries(&self) -> Result<Vec<String>, Self::Error>;
 }
 
-#[async_trait(?Send)]
-pub trait FileHandle: Debug {
-    type Error: Debug;
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+pub trait FileHandle: Debug + MaybeSendSync {
+    type Error: Debug + MaybeSendSync;
     type WritableFileStreamT: WritableFileStream<Error = Self::Error>;
 
     async fn create_writable_with_options(
@@ -44,12 +117,22 @@ pub trait FileHandle: Debug {
 
     async fn read(&self) -> Result<Vec<u8>, Self::Error>;
 
+    /// Read `len` bytes starting at `offset`, or fewer if the file ends first,
+    /// so large files can be read a chunk at a time.
+    async fn read_range(&self, offset: usize, len: usize) -> Result<Vec<u8>, Self::Error>;
+
     async fn size(&self) -> Result<usize, Self::Error>;
+
+    /// Map the whole file into memory, if the filesystem supports it, so it can be read without copying.
+    async fn map(&self) -> Result<Option<Mapped>, Self::Error> {
+        Ok(None)
+    }
 }
 
-#[async_trait(?Send)]
-pub trait WritableFileStream: Debug {
-    type Error: Debug;
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+pub trait WritableFileStream: Debug + MaybeSendSync {
+    type Error: Debug + MaybeSendSync;
 
     async fn write_at_cursor_pos(&mut self, data: Vec<u8>) -> Result<(), Self::Error>;
 
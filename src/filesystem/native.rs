@@ -5,34 +5,95 @@ use tokio::io::{AsyncSeekExt, AsyncWriteExt};
 
 use crate::filesystem;
 
+/// When writes are synced to disk with `fsync`, trading write speed for how much survives a crash or power loss.
+///
+/// Without syncing, the operating system writes data to disk in its own time, so the last writes can be lost
+/// (even after the process exits normally) if the machine goes down before then.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Durability {
+    /// Never sync, leaving it to the operating system. The fastest.
+    #[default]
+    None,
+    /// Sync each file when victor finishes writing to it, so every completed write is on disk.
+    SyncOnClose,
+    /// Sync after every write is flushed to a file, as well as when it's closed. The slowest, but the least is
+    /// lost if a write is interrupted partway through.
+    SyncOnFlush,
+}
+
+/// A directory on disk, where the database's files are stored.
+///
+/// ```rust
+/// # tokio_test::block_on(async {
+/// use victor_db::native::{Db, DirectoryHandle, Durability};
+///
+/// let dir = std::env::temp_dir().join("victor_durability_example");
+/// std::fs::create_dir_all(&dir).unwrap();
+/// let mut victor = Db::new(DirectoryHandle::from(dir).with_durability(Durability::SyncOnClose));
+///
+/// victor
+///     .add_single_embedding("Pepperoni pizza", vec![0.1, 0.2, 0.3], vec!["Pizza Flavors"])
+///     .await
+///     .unwrap();
+/// # victor.clear_db().await.unwrap();
+/// # })
+/// ```
 #[derive(Debug)]
-pub struct DirectoryHandle(PathBuf);
+pub struct DirectoryHandle {
+    path: PathBuf,
+    durability: Durability,
+}
 
+/// A file on disk.
 #[derive(Debug)]
-pub struct FileHandle(PathBuf);
+pub struct FileHandle {
+    path: PathBuf,
+    durability: Durability,
+}
 
+/// A file on disk that's open for writing.
 #[derive(Debug)]
-pub struct WritableFileStream(tokio::fs::File);
+pub struct WritableFileStream {
+    file: tokio::fs::File,
+    durability: Durability,
+}
+
+impl DirectoryHandle {
+    /// Sync writes to disk according to `durability`, see [`Durability`]. The default is [`Durability::None`].
+    pub fn with_durability(mut self, durability: Durability) -> Self {
+        self.durability = durability;
+        self
+    }
+}
 
 impl From<PathBuf> for DirectoryHandle {
-    fn from(handle: PathBuf) -> Self {
-        Self(handle)
+    fn from(path: PathBuf) -> Self {
+        Self {
+            path,
+            durability: Durability::default(),
+        }
     }
 }
 
 impl From<PathBuf> for FileHandle {
-    fn from(handle: PathBuf) -> Self {
-        Self(handle)
+    fn from(path: PathBuf) -> Self {
+        Self {
+            path,
+            durability: Durability::default(),
+        }
     }
 }
 
 impl From<tokio::fs::File> for WritableFileStream {
-    fn from(handle: tokio::fs::File) -> Self {
-        Self(handle)
+    fn from(file: tokio::fs::File) -> Self {
+        Self {
+            file,
+            durability: Durability::default(),
+        }
     }
 }
 
-#[async_trait(?Send)]
+#[async_trait]
 impl filesystem::DirectoryHandle for DirectoryHandle {
     type Error = std::io::Error;
     type FileHandleT = FileHandle;
@@ -42,7 +103,7 @@ impl filesystem::DirectoryHandle for DirectoryHandle {
         name: &str,
         options: &filesystem::GetFileHandleOptions,
     ) -> Result<Self::FileHandleT, Self::Error> {
-        let mut path = self.0.clone();
+        let mut path = self.path.clone();
         path.push(name);
 
         // Make sure the file exists
@@ -53,11 +114,14 @@ impl filesystem::DirectoryHandle for DirectoryHandle {
             .open(&path)
             .await?;
 
-        Ok(FileHandle(path))
+        Ok(FileHandle {
+            path,
+            durability: self.durability,
+        })
     }
 
     async fn remove_entry(&mut self, name: &str) -> Result<(), Self::Error> {
-        let mut path = self.0.clone();
+        let mut path = self.path.clone();
         path.push(name);
 
         let metadata = tokio::fs::metadata(&path).await?;
@@ -69,9 +133,20 @@ impl filesystem::DirectoryHandle for DirectoryHandle {
 
         Ok(())
     }
+
+    async fn list_entries(&self) -> Result<Vec<String>, Self::Error> {
+        let mut names = Vec::new();
+        let mut entries = tokio::fs::read_dir(&self.path).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            if entry.file_type().await?.is_file() {
+                names.extend(entry.file_name().to_str().map(str::to_string));
+            }
+        }
+        Ok(names)
+    }
 }
 
-#[async_trait(?Send)]
+#[async_trait]
 impl filesystem::FileHandle for FileHandle {
     type Error = std::io::Error;
     type WritableFileStreamT = WritableFileStream;
@@ -84,43 +159,79 @@ impl filesystem::FileHandle for FileHandle {
             .write(true)
             .create(true)
             .truncate(!options.keep_existing_data)
-            .open(&self.0)
+            .open(&self.path)
             .await?;
 
-        Ok(WritableFileStream(file))
+        Ok(WritableFileStream {
+            file,
+            durability: self.durability,
+        })
     }
 
     async fn read(&self) -> Result<Vec<u8>, Self::Error> {
         use tokio::io::AsyncReadExt;
 
-        let mut file = tokio::fs::File::open(&self.0).await?;
+        let mut file = tokio::fs::File::open(&self.path).await?;
         let mut buffer = Vec::new();
         file.read_to_end(&mut buffer).await?;
         Ok(buffer)
     }
 
+    async fn read_range(&self, offset: usize, len: usize) -> Result<Vec<u8>, Self::Error> {
+        use tokio::io::AsyncReadExt;
+
+        let mut file = tokio::fs::File::open(&self.path).await?;
+        file.seek(SeekFrom::Start(offset as u64)).await?;
+        let mut buffer = Vec::with_capacity(len);
+        file.take(len as u64).read_to_end(&mut buffer).await?;
+        Ok(buffer)
+    }
+
     async fn size(&self) -> Result<usize, Self::Error> {
-        let metadata = tokio::fs::metadata(&self.0).await?;
+        let metadata = tokio::fs::metadata(&self.path).await?;
         Ok(metadata.len() as usize)
     }
+
+    #[cfg(feature = "mmap")]
+    async fn map(&self) -> Result<Option<filesystem::Mapped>, Self::Error> {
+        let file = std::fs::File::open(&self.path)?;
+
+        // empty files can't be mapped on every platform, and there's nothing to read anyway
+        if file.metadata()?.len() == 0 {
+            return Ok(None);
+        }
+
+        // Safety: the map is only read while searching, and the database never truncates a db file.
+        // Other processes modifying the database while it's searched could still cause garbled reads,
+        // just as they can without mapping.
+        let map = unsafe { memmap2::Mmap::map(&file)? };
+        Ok(Some(Box::new(map)))
+    }
 }
 
-#[async_trait(?Send)]
+#[async_trait]
 impl filesystem::WritableFileStream for WritableFileStream {
     type Error = std::io::Error;
 
     async fn write_at_cursor_pos(&mut self, data: Vec<u8>) -> Result<(), Self::Error> {
-        self.0.write_all(&data).await?;
+        self.file.write_all(&data).await?;
+        if self.durability == Durability::SyncOnFlush {
+            self.file.flush().await?;
+            self.file.sync_data().await?;
+        }
         Ok(())
     }
 
     async fn close(&mut self) -> Result<(), Self::Error> {
-        self.0.shutdown().await?;
+        self.file.shutdown().await?;
+        if self.durability != Durability::None {
+            self.file.sync_all().await?;
+        }
         Ok(())
     }
 
     async fn seek(&mut self, offset: usize) -> Result<(), Self::Error> {
-        self.0.seek(SeekFrom::Start(offset as u64)).await?;
+        self.file.seek(SeekFrom::Start(offset as u64)).await?;
         Ok(())
     }
 }
@@ -0,0 +1,185 @@
+//! A filesystem over an [`ObjectStore`] (S3, GCS, Azure, ...), so a database can be hosted remotely.
+//!
+//! Objects can't be modified in place, so writes are buffered and the whole object is uploaded when the
+//! writable stream is closed, like the web's `FileSystemWritableFileStream`.
+//! Reads only fetch the requested range, so searches that read a file a chunk at a time stay cheap.
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use object_store::{path::Path, ObjectStore, PutPayload};
+
+use crate::filesystem;
+
+impl filesystem::FilesystemError for object_store::Error {
+    fn into_error(self) -> crate::Error {
+        crate::Error::Filesystem(self.to_string())
+    }
+
+    fn is_not_found(&self) -> bool {
+        matches!(self, object_store::Error::NotFound { .. })
+    }
+}
+
+/// A "directory" in an object store: every file is an object under `prefix`.
+#[derive(Debug, Clone)]
+pub struct DirectoryHandle {
+    store: Arc<dyn ObjectStore>,
+    prefix: Path,
+}
+
+/// An object in an object store.
+#[derive(Debug, Clone)]
+pub struct FileHandle {
+    store: Arc<dyn ObjectStore>,
+    path: Path,
+}
+
+/// Writes to an object, which are uploaded when the stream is closed.
+#[derive(Debug)]
+pub struct WritableFileStream {
+    file: FileHandle,
+    cursor_pos: usize,
+    buffer: Vec<u8>,
+}
+
+impl DirectoryHandle {
+    /// Store the database's files under `prefix` in `store`.
+    pub fn new(store: Arc<dyn ObjectStore>, prefix: impl Into<Path>) -> Self {
+        Self {
+            store,
+            prefix: prefix.into(),
+        }
+    }
+}
+
+#[async_trait]
+impl filesystem::DirectoryHandle for DirectoryHandle {
+    type Error = object_store::Error;
+    type FileHandleT = FileHandle;
+
+    async fn get_file_handle_with_options(
+        &self,
+        name: &str,
+        options: &filesystem::GetFileHandleOptions,
+    ) -> Result<Self::FileHandleT, Self::Error> {
+        let path = self.prefix.child(name);
+
+        // Make sure the object exists
+        match self.store.head(&path).await {
+            Ok(_) => {}
+            Err(object_store::Error::NotFound { .. }) if options.create => {
+                self.store.put(&path, PutPayload::new()).await?;
+            }
+            Err(error) => return Err(error),
+        }
+
+        Ok(FileHandle {
+            store: self.store.clone(),
+            path,
+        })
+    }
+
+    async fn remove_entry(&mut self, name: &str) -> Result<(), Self::Error> {
+        self.store.delete(&self.prefix.child(name)).await
+    }
+
+    async fn list_entries(&self) -> Result<Vec<String>, Self::Error> {
+        // only the objects directly under the prefix, like the files in a directory
+        let listed = self.store.list_with_delimiter(Some(&self.prefix)).await?;
+        Ok(listed
+            .objects
+            .into_iter()
+            .filter_map(|object| object.location.filename().map(str::to_string))
+            .collect())
+    }
+}
+
+#[async_trait]
+impl filesystem::FileHandle for FileHandle {
+    type Error = object_store::Error;
+    type WritableFileStreamT = WritableFileStream;
+
+    async fn create_writable_with_options(
+        &mut self,
+        options: &filesystem::CreateWritableOptions,
+    ) -> Result<Self::WritableFileStreamT, Self::Error> {
+        let buffer = if options.keep_existing_data {
+            self.read().await?
+        } else {
+            Vec::new()
+        };
+
+        Ok(WritableFileStream {
+            file: self.clone(),
+            cursor_pos: 0,
+            buffer,
+        })
+    }
+
+    async fn read(&self) -> Result<Vec<u8>, Self::Error> {
+        let bytes = self.store.get(&self.path).await?.bytes().await?;
+        Ok(bytes.to_vec())
+    }
+
+    async fn read_range(&self, offset: usize, len: usize) -> Result<Vec<u8>, Self::Error> {
+        // object stores reject ranges past the end of the object, so clamp the range first
+        let size = self.size().await?;
+        let start = offset.min(size);
+        let end = offset.saturating_add(len).min(size);
+        if start == end {
+            return Ok(Vec::new());
+        }
+
+        let bytes = self
+            .store
+            .get_range(&self.path, start as u64..end as u64)
+            .await?;
+        Ok(bytes.to_vec())
+    }
+
+    async fn size(&self) -> Result<usize, Self::Error> {
+        let meta = self.store.head(&self.path).await?;
+        Ok(meta.size as usize)
+    }
+}
+
+#[async_trait]
+impl filesystem::WritableFileStream for WritableFileStream {
+    type Error = object_store::Error;
+
+    async fn write_at_cursor_pos(&mut self, data: Vec<u8>) -> Result<(), Self::Error> {
+        let end = self.cursor_pos + data.len();
+
+        // overwrite in place (like the native and web filesystems), growing the file if needed
+        if self.buffer.len() < end {
+            self.buffer.resize(end, 0);
+        }
+        self.buffer[self.cursor_pos..end].copy_from_slice(&data);
+
+        self.cursor_pos = end;
+
+        Ok(())
+    }
+
+    async fn close(&mut self) -> Result<(), Self::Error> {
+        let payload = PutPayload::from(self.buffer.clone());
+        self.file.store.put(&self.file.path, payload).await?;
+        Ok(())
+    }
+
+    async fn seek(&mut self, offset: usize) -> Result<(), Self::Error> {
+        if offset > self.buffer.len() {
+            return Err(object_store::Error::Generic {
+                store: "victor",
+                source: format!(
+                    "cannot seek to {offset} because the file is only {len} bytes long",
+                    len = self.buffer.len()
+                )
+                .into(),
+            });
+        }
+        self.cursor_pos = offset;
+        Ok(())
+    }
+}
@@ -1,5 +1,5 @@
 use async_trait::async_trait;
-use js_sys::{ArrayBuffer, Uint8Array};
+use js_sys::{Array, ArrayBuffer, IteratorNext, Uint8Array};
 use wasm_bindgen::{JsCast, JsValue};
 use wasm_bindgen_futures::JsFuture;
 use web_sys::{
@@ -67,6 +67,23 @@ impl filesystem::DirectoryHandle for DirectoryHandle {
         JsFuture::from(self.0.remove_entry(name)).await?;
         Ok(())
     }
+
+    async fn list_entries(&self) -> Result<Vec<String>, Self::Error> {
+        // entries() is an async iterator of [name, handle] pairs
+        let entries = self.0.entries();
+        let mut names = Vec::new();
+        loop {
+            let next = IteratorNext::from(JsFuture::from(entries.next()?).await?);
+            if next.done() {
+                break;
+            }
+            let entry = Array::from(&next.value());
+            if entry.get(1).is_instance_of::<FileSystemFileHandle>() {
+                names.extend(entry.get(0).as_string());
+            }
+        }
+        Ok(names)
+    }
 }
 
 #[async_trait(?Send)]
@@ -90,6 +107,14 @@ impl filesystem::FileHandle for FileHandle {
         self.get_file().await?.read().await
     }
 
+    async fn read_range(&self, offset: usize, len: usize) -> Result<Vec<u8>, Self::Error> {
+        self.get_file()
+            .await?
+            .slice(offset, offset.saturating_add(len))?
+            .read()
+            .await
+    }
+
     async fn size(&self) -> Result<usize, Self::Error> {
         let size = self.get_file().await?.size();
         Ok(size)
@@ -128,6 +153,12 @@ impl Blob {
         self.0.size() as usize
     }
 
+    /// The bytes from `start` to `end`, clamped to the end of the blob.
+    fn slice(&self, start: usize, end: usize) -> Result<Blob, JsValue> {
+        let blob = self.0.slice_with_f64_and_f64(start as f64, end as f64)?;
+        Ok(Blob(blob))
+    }
+
     async fn read(&self) -> Result<Vec<u8>, JsValue> {
         let buffer = ArrayBuffer::unchecked_from_js(JsFuture::from(self.0.array_buffer()).await?);
         let uint8_array = Uint8Array::new(&buffer);
@@ -0,0 +1,459 @@
+//! Filters on document metadata, applied while scanning for nearest neighbors.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ops::{BitAnd, BitOr, Bound, Not},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::document::{Metadata, MetadataValue};
+
+/// A condition on document [`Metadata`].
+/// Documents that don't match are skipped during the search, so they never take the place of a matching result.
+///
+/// ```rust
+/// use victor_db::Filter;
+///
+/// // author == "alice" AND category IN ("pizza", "pasta") AND price < 100
+/// let filter = Filter::eq("author", "alice")
+///     .and(Filter::is_in("category", ["pizza", "pasta"]))
+///     .and(Filter::lt("price", 100.0));
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub enum Filter {
+    /// Matches every document.
+    #[default]
+    All,
+    /// Matches documents where the field equals the value.
+    Eq(String, MetadataValue),
+    /// Matches documents where the field equals any of the values.
+    In(String, Vec<MetadataValue>),
+    /// Matches documents where the field is a number within the bounds.
+    Range(String, Bound<f64>, Bound<f64>),
+    /// Matches documents that match every one of the filters.
+    And(Vec<Filter>),
+}
+
+impl Filter {
+    /// Match documents where `field` equals `value`.
+    pub fn eq(field: impl Into<String>, value: impl Into<MetadataValue>) -> Self {
+        Self::Eq(field.into(), value.into())
+    }
+
+    /// Match documents where `field` equals any of `values`.
+    pub fn is_in(
+        field: impl Into<String>,
+        values: impl IntoIterator<Item = impl Into<MetadataValue>>,
+    ) -> Self {
+        Self::In(field.into(), values.into_iter().map(Into::into).collect())
+    }
+
+    /// Match documents where `field` is a number less than `value`.
+    pub fn lt(field: impl Into<String>, value: f64) -> Self {
+        Self::Range(field.into(), Bound::Unbounded, Bound::Excluded(value))
+    }
+
+    /// Match documents where `field` is a number less than or equal to `value`.
+    pub fn lte(field: impl Into<String>, value: f64) -> Self {
+        Self::Range(field.into(), Bound::Unbounded, Bound::Included(value))
+    }
+
+    /// Match documents where `field` is a number greater than `value`.
+    pub fn gt(field: impl Into<String>, value: f64) -> Self {
+        Self::Range(field.into(), Bound::Excluded(value), Bound::Unbounded)
+    }
+
+    /// Match documents where `field` is a number greater than or equal to `value`.
+    pub fn gte(field: impl Into<String>, value: f64) -> Self {
+        Self::Range(field.into(), Bound::Included(value), Bound::Unbounded)
+    }
+
+    /// Match documents where `field` is a number between `min` and `max` (inclusive).
+    pub fn between(field: impl Into<String>, min: f64, max: f64) -> Self {
+        Self::Range(field.into(), Bound::Included(min), Bound::Included(max))
+    }
+
+    /// Match documents that match both this filter and `other`.
+    pub fn and(self, other: Filter) -> Self {
+        match self {
+            Self::All => other,
+            Self::And(mut filters) => {
+                filters.push(other);
+                Self::And(filters)
+            }
+            filter => Self::And(vec![filter, other]),
+        }
+    }
+
+    pub(crate) fn is_all(&self) -> bool {
+        matches!(self, Self::All)
+    }
+
+    pub(crate) fn matches(&self, metadata: &Metadata) -> bool {
+        match self {
+            Self::All => true,
+            Self::Eq(field, value) => metadata.get(field) == Some(value),
+            Self::In(field, values) => metadata
+                .get(field)
+                .is_some_and(|field_value| values.contains(field_value)),
+            Self::Range(field, min, max) => metadata
+                .get(field)
+                .and_then(MetadataValue::as_number)
+                .is_some_and(|number| in_bounds(number, *min, *max)),
+            Self::And(filters) => filters.iter().all(|filter| filter.matches(metadata)),
+        }
+    }
+
+    /// Whether any document in a db file with these stats could match the filter.
+    /// `false` means the whole file can be skipped.
+    pub(crate) fn may_match(&self, stats: &SegmentStats) -> bool {
+        match self {
+            Self::Range(field, min, max) => {
+                stats.ranges.get(field).is_some_and(|(lowest, highest)| {
+                    // the file's range and the filter's range overlap
+                    in_bounds(*highest, *min, Bound::Unbounded)
+                        && in_bounds(*lowest, Bound::Unbounded, *max)
+                })
+            }
+            Self::And(filters) => filters.iter().all(|filter| filter.may_match(stats)),
+            Self::All | Self::Eq(..) | Self::In(..) => true,
+        }
+    }
+}
+
+/// A query on the tags of documents.
+///
+/// A list of tags converts into a `TagFilter` that matches documents with all of them,
+/// and filters can be combined with `&`, `|` and `!`.
+///
+/// ```rust
+/// use victor_db::TagFilter;
+///
+/// // ("a" OR "b") AND "c"
+/// let filter = TagFilter::any(["a", "b"]) & TagFilter::tag("c");
+///
+/// // "docs" AND NOT "archived"
+/// let filter = TagFilter::tag("docs") & !TagFilter::tag("archived");
+///
+/// // "a" AND "b"
+/// let filter: TagFilter = vec!["a", "b"].into();
+/// ```
+///
+/// Tags can form a hierarchy of paths separated by `/`, like `docs/api/v2`. A `*` in a tag matches any one part of a
+/// path, or at the end, any number of parts, so `docs/*` matches every tag below `docs` (but not `docs` itself)
+/// and `docs/*/v2` matches `docs/api/v2` and `docs/guides/v2`.
+///
+/// ```rust
+/// use victor_db::TagFilter;
+///
+/// // everything below "docs", like "docs/api" and "docs/api/v2"
+/// let filter = TagFilter::tag("docs/*");
+/// let filter = TagFilter::below("docs");
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum TagFilter {
+    /// Matches documents with this tag, or with a tag matching it if it has a `*` in it.
+    Tag(String),
+    /// Matches documents that match every one of the filters. An empty list matches every document.
+    And(Vec<TagFilter>),
+    /// Matches documents that match at least one of the filters. An empty list matches no documents.
+    Or(Vec<TagFilter>),
+    /// Matches documents that don't match the filter.
+    Not(Box<TagFilter>),
+}
+
+impl TagFilter {
+    /// Match documents with this tag.
+    pub fn tag(tag: impl Into<String>) -> Self {
+        Self::Tag(tag.into())
+    }
+
+    /// Match documents with a tag below `path` in a hierarchy of tags, like `docs/api/v2` below `docs`.
+    pub fn below(path: impl Into<String>) -> Self {
+        Self::Tag(format!("{}/*", path.into()))
+    }
+
+    /// Match documents with all of these tags.
+    pub fn all(tags: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self::And(tags.into_iter().map(Self::tag).collect())
+    }
+
+    /// Match documents with at least one of these tags.
+    pub fn any(tags: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self::Or(tags.into_iter().map(Self::tag).collect())
+    }
+
+    /// Match documents with none of these tags.
+    pub fn none(tags: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        !Self::any(tags)
+    }
+
+    /// Whether a db file holding documents with exactly these tags matches the filter.
+    pub(crate) fn matches(&self, file_tags: &BTreeSet<String>) -> bool {
+        match self {
+            Self::Tag(tag) if tag.contains('*') => {
+                file_tags.iter().any(|file_tag| matches_path(tag, file_tag))
+            }
+            Self::Tag(tag) => file_tags.contains(tag),
+            Self::And(filters) => filters.iter().all(|filter| filter.matches(file_tags)),
+            Self::Or(filters) => filters.iter().any(|filter| filter.matches(file_tags)),
+            Self::Not(filter) => !filter.matches(file_tags),
+        }
+    }
+
+    /// Whether `tag` is one the filter names, or matches one it names with a `*` in it.
+    fn names(&self, tag: &str) -> bool {
+        match self {
+            Self::Tag(pattern) if pattern.contains('*') => matches_path(pattern, tag),
+            Self::Tag(pattern) => pattern == tag,
+            Self::And(filters) | Self::Or(filters) => {
+                filters.iter().any(|filter| filter.names(tag))
+            }
+            Self::Not(filter) => filter.names(tag),
+        }
+    }
+
+    /// The filter, but only matching documents without any of `tags` that it doesn't name,
+    /// see [`crate::SearchOptions::exact_tags`].
+    pub(crate) fn exactly<'a>(self, tags: impl IntoIterator<Item = &'a String>) -> Self {
+        let others = tags
+            .into_iter()
+            .filter(|tag| !self.names(tag))
+            .cloned()
+            .collect::<BTreeSet<_>>();
+        match others.is_empty() {
+            true => self,
+            false => self & Self::none(others),
+        }
+    }
+}
+
+impl Default for TagFilter {
+    /// Matches every document.
+    fn default() -> Self {
+        Self::And(Vec::new())
+    }
+}
+
+impl<T: Into<String>> From<Vec<T>> for TagFilter {
+    fn from(tags: Vec<T>) -> Self {
+        Self::all(tags)
+    }
+}
+
+impl BitAnd for TagFilter {
+    type Output = TagFilter;
+
+    fn bitand(self, other: TagFilter) -> TagFilter {
+        match self {
+            Self::And(mut filters) => {
+                filters.push(other);
+                Self::And(filters)
+            }
+            filter => Self::And(vec![filter, other]),
+        }
+    }
+}
+
+impl BitOr for TagFilter {
+    type Output = TagFilter;
+
+    fn bitor(self, other: TagFilter) -> TagFilter {
+        match self {
+            Self::Or(mut filters) => {
+                filters.push(other);
+                Self::Or(filters)
+            }
+            filter => Self::Or(vec![filter, other]),
+        }
+    }
+}
+
+impl Not for TagFilter {
+    type Output = TagFilter;
+
+    fn not(self) -> TagFilter {
+        match self {
+            Self::Not(filter) => *filter,
+            filter => Self::Not(Box::new(filter)),
+        }
+    }
+}
+
+/// Whether `tag` matches `pattern`, a path of `/`-separated parts where `*` matches any one part,
+/// or if it's the last part, one or more.
+fn matches_path(pattern: &str, tag: &str) -> bool {
+    let mut parts = tag.split('/');
+    let mut pattern_parts = pattern.split('/').peekable();
+    while let Some(pattern_part) = pattern_parts.next() {
+        let Some(part) = parts.next() else {
+            return false;
+        };
+        match pattern_part {
+            "*" if pattern_parts.peek().is_none() => return true,
+            "*" => {}
+            pattern_part if pattern_part != part => return false,
+            _ => {}
+        }
+    }
+    parts.next().is_none()
+}
+
+fn in_bounds(number: f64, min: Bound<f64>, max: Bound<f64>) -> bool {
+    let above_min = match min {
+        Bound::Included(min) => number >= min,
+        Bound::Excluded(min) => number > min,
+        Bound::Unbounded => true,
+    };
+    let below_max = match max {
+        Bound::Included(max) => number <= max,
+        Bound::Excluded(max) => number < max,
+        Bound::Unbounded => true,
+    };
+    above_min && below_max
+}
+
+/// The lowest and highest value of each numeric metadata field in a db file.
+/// Used to skip files that can't match a range [`Filter`] without reading them.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub(crate) struct SegmentStats {
+    ranges: BTreeMap<String, (f64, f64)>,
+}
+
+impl SegmentStats {
+    pub(crate) fn include(&mut self, metadata: &Metadata) {
+        for (field, value) in metadata {
+            let Some(number) = value.as_number() else {
+                continue;
+            };
+            let range = self.ranges.entry(field.clone()).or_insert((number, number));
+            range.0 = range.0.min(number);
+            range.1 = range.1.max(number);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_eq_and_in() {
+        let metadata = Metadata::from([
+            ("author".to_string(), MetadataValue::from("alice")),
+            ("category".to_string(), MetadataValue::from("pizza")),
+        ]);
+
+        assert!(Filter::All.matches(&metadata));
+        assert!(Filter::eq("author", "alice").matches(&metadata));
+        assert!(!Filter::eq("author", "bob").matches(&metadata));
+        assert!(!Filter::eq("missing", "alice").matches(&metadata));
+        assert!(Filter::is_in("category", ["pasta", "pizza"]).matches(&metadata));
+        assert!(!Filter::is_in("category", ["pasta", "salad"]).matches(&metadata));
+        assert!(Filter::eq("author", "alice")
+            .and(Filter::is_in("category", ["pizza"]))
+            .matches(&metadata));
+        assert!(!Filter::eq("author", "alice")
+            .and(Filter::eq("category", "pasta"))
+            .matches(&metadata));
+    }
+
+    #[test]
+    fn matches_ranges() {
+        let metadata = Metadata::from([("price".to_string(), MetadataValue::from(50.0))]);
+
+        assert!(Filter::lt("price", 100.0).matches(&metadata));
+        assert!(!Filter::lt("price", 50.0).matches(&metadata));
+        assert!(Filter::lte("price", 50.0).matches(&metadata));
+        assert!(Filter::gt("price", 10.0).matches(&metadata));
+        assert!(!Filter::gte("price", 50.5).matches(&metadata));
+        assert!(Filter::between("price", 50.0, 60.0).matches(&metadata));
+        assert!(!Filter::between("price", 0.0, 49.0).matches(&metadata));
+        assert!(!Filter::lt("missing", 100.0).matches(&metadata));
+    }
+
+    #[test]
+    fn tag_filters() {
+        let file_tags = BTreeSet::from(["a".to_string(), "c".to_string()]);
+
+        assert!(TagFilter::default().matches(&file_tags));
+        assert!(TagFilter::from(vec!["a", "c"]).matches(&file_tags));
+        assert!(!TagFilter::from(vec!["a", "b"]).matches(&file_tags));
+        assert!((TagFilter::any(["a", "b"]) & TagFilter::tag("c")).matches(&file_tags));
+        assert!(!(TagFilter::any(["b", "d"]) & TagFilter::tag("c")).matches(&file_tags));
+        assert!((TagFilter::tag("b") | TagFilter::tag("c")).matches(&file_tags));
+        assert!(!TagFilter::any(Vec::<String>::new()).matches(&file_tags));
+    }
+
+    #[test]
+    fn exact_tag_filters() {
+        let tags = ["a", "b", "docs/api"].map(String::from);
+        let exactly = |filter: TagFilter, file_tags: &[&str]| {
+            let file_tags = file_tags.iter().map(|tag| tag.to_string()).collect();
+            filter.exactly(&tags).matches(&file_tags)
+        };
+
+        assert!(exactly(TagFilter::tag("a"), &["a"]));
+        assert!(!exactly(TagFilter::tag("a"), &["a", "b"]));
+        assert!(exactly(TagFilter::from(vec!["a", "b"]), &["a", "b"]));
+        assert!(exactly(TagFilter::any(["a", "b"]), &["b"]));
+        assert!(exactly(TagFilter::any(["a", "b"]), &["a", "b"]));
+        assert!(!exactly(TagFilter::any(["a", "b"]), &["a", "docs/api"]));
+        assert!(exactly(TagFilter::below("docs"), &["docs/api"]));
+        assert!(exactly(TagFilter::default(), &[]));
+        assert!(!exactly(TagFilter::default(), &["a"]));
+    }
+
+    #[test]
+    fn tag_paths() {
+        let file_tags = BTreeSet::from(["docs/api/v2".to_string(), "pizza".to_string()]);
+
+        assert!(TagFilter::tag("docs/*").matches(&file_tags));
+        assert!(TagFilter::below("docs").matches(&file_tags));
+        assert!(TagFilter::below("docs/api").matches(&file_tags));
+        assert!(TagFilter::tag("docs/*/v2").matches(&file_tags));
+        assert!(TagFilter::tag("*/api/*").matches(&file_tags));
+        assert!(!TagFilter::tag("docs/*/v1").matches(&file_tags));
+        assert!(!TagFilter::below("docs/api/v2").matches(&file_tags));
+        assert!(!TagFilter::below("doc").matches(&file_tags));
+        assert!(!TagFilter::tag("docs").matches(&file_tags));
+        assert!(TagFilter::tag("*").matches(&file_tags));
+
+        assert!(matches_path("docs/*", "docs/api"));
+        assert!(!matches_path("docs/*", "docs"));
+        assert!(!matches_path("docs/*/v2", "docs/api/v2/beta"));
+        assert!(!matches_path("docs/api", "docs/api/v2"));
+    }
+
+    #[test]
+    fn excluded_tags() {
+        let file_tags = BTreeSet::from(["docs".to_string(), "archived".to_string()]);
+
+        assert!(!(TagFilter::tag("docs") & !TagFilter::tag("archived")).matches(&file_tags));
+        assert!((TagFilter::tag("docs") & !TagFilter::tag("draft")).matches(&file_tags));
+        assert!(!TagFilter::none(["draft", "archived"]).matches(&file_tags));
+        assert!(TagFilter::none(["draft"]).matches(&file_tags));
+        assert!((!!TagFilter::tag("docs")).matches(&file_tags));
+    }
+
+    #[test]
+    fn skips_segments_by_stats() {
+        let mut stats = SegmentStats::default();
+        stats.include(&Metadata::from([(
+            "price".to_string(),
+            MetadataValue::from(10.0),
+        )]));
+        stats.include(&Metadata::from([(
+            "price".to_string(),
+            MetadataValue::from(20.0),
+        )]));
+
+        assert!(Filter::between("price", 15.0, 30.0).may_match(&stats));
+        assert!(Filter::gte("price", 20.0).may_match(&stats));
+        assert!(!Filter::gt("price", 20.0).may_match(&stats));
+        assert!(!Filter::lt("price", 10.0).may_match(&stats));
+        assert!(!Filter::lt("weight", 10.0).may_match(&stats));
+        assert!(Filter::eq("author", "alice").may_match(&stats));
+    }
+}
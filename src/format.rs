@@ -0,0 +1,108 @@
+//! The version of the on-disk format, recorded in `format.bin` so databases written by older versions of victor
+//! can be upgraded when they're opened, and databases written by newer versions aren't misread.
+//!
+//! `format.bin` starts with [`MAGIC`], followed by the version. Databases written before it existed are version 0,
+//! and [`crate::Victor::migrate`] upgrades them one version at a time.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    db::{deserialize, write_bin},
+    error::Error,
+    filesystem::{DirectoryHandle, FileHandle, FilesystemError, GetFileHandleOptions},
+};
+
+const FILENAME: &str = "format.bin";
+
+/// Identifies `format.bin` as belonging to a victor database.
+const MAGIC: [u8; 4] = *b"VCTR";
+
+/// The version of the format written by this version of victor.
+///
+/// - 0: no `format.bin`, and files may be missing their checksums or `settings.bin`.
+/// - 1: every file is checksummed.
+/// - 2: the settings are recorded, including the compression.
+/// - 3: deleted documents are recorded in `tombstones.bin`, which older versions would ignore.
+/// - 4: tag sets can have more than one db file, tracked in `index.bin`.
+/// - 5: `content.bin` is a log that's appended to, rather than a single map.
+/// - 6: `eigen.bin` records how much of the variance the projection explains.
+/// - 7: documents can have more than one vector, tracked in `chunks.bin`, which older versions would ignore.
+/// - 8: small db files can be merged into combined files, tracked in `combined.bin`.
+/// - 9: the tags documents can be added with can be restricted in `allowed_tags.bin`, which older versions would
+///   ignore.
+/// - 10: the settings record whether vectors are normalized.
+pub(crate) const VERSION: u32 = 10;
+
+#[derive(Serialize, Deserialize)]
+struct Header {
+    magic: [u8; 4],
+    version: u32,
+}
+
+/// The version of the database's format, or `None` if it doesn't record one.
+/// Reading it doesn't create `format.bin`, so checking a directory that isn't a database leaves it as it was.
+pub(crate) async fn read<D: DirectoryHandle>(root: &D) -> Result<Option<u32>, Error> {
+    let file_handle = match root
+        .get_file_handle_with_options(FILENAME, &GetFileHandleOptions { create: false })
+        .await
+    {
+        Ok(file_handle) => file_handle,
+        Err(error) if error.is_not_found() => return Ok(None),
+        Err(error) => return Err(error.into()),
+    };
+
+    let header = file_handle.read().await?;
+
+    if header.is_empty() {
+        return Ok(None);
+    }
+
+    let header: Header = deserialize(FILENAME, &header)?;
+    if header.magic != MAGIC {
+        return Err(Error::Corrupted {
+            file: FILENAME.to_string(),
+            reason: "not a victor database".to_string(),
+        });
+    }
+    Ok(Some(header.version))
+}
+
+/// Check that this version of victor can read the database.
+pub(crate) async fn check<D: DirectoryHandle>(root: &D) -> Result<(), Error> {
+    match read(root).await? {
+        Some(version) if version > VERSION => Err(Error::UnsupportedVersion {
+            found: version,
+            supported: VERSION,
+        }),
+        _ => Ok(()),
+    }
+}
+
+pub(crate) async fn write<D: DirectoryHandle>(root: &D, version: u32) -> Result<(), Error> {
+    write_bin(
+        root,
+        FILENAME,
+        &Header {
+            magic: MAGIC,
+            version,
+        },
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filesystem::memory;
+
+    #[tokio::test]
+    async fn reading_creates_nothing() {
+        let root = memory::DirectoryHandle::default();
+        assert_eq!(read(&root).await.unwrap(), None);
+        check(&root).await.unwrap();
+        assert!(root.list_entries().await.unwrap().is_empty());
+
+        write(&root, VERSION).await.unwrap();
+        assert_eq!(read(&root).await.unwrap(), Some(VERSION));
+    }
+}
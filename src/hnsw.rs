@@ -0,0 +1,556 @@
+//! A hierarchical navigable small world (HNSW) graph, for approximate nearest neighbor search.
+//!
+//! Each db file gets its own graph, stored next to it (see [`filename_for_segment`]).
+//! Nodes are identified by the position of their embedding in the db file, so the graph only stores links,
+//! and the vectors are always read from the db file itself, as the search visits them (see [`Vectors`]).
+//!
+//! Linking in new nodes only touches a few others, so rather than rewriting the file each time,
+//! the nodes whose links changed are appended to it as [`Change`]s, which are replayed when it's read.
+
+use std::borrow::Cow;
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BTreeSet, BinaryHeap, HashSet};
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    db::{append_bin, read_optional, write_bin},
+    error::Error,
+    filesystem::{DirectoryHandle, FileHandle, GetFileHandleOptions},
+};
+
+const CONFIG_FILENAME: &str = "hnsw.bin";
+
+/// The highest layer a node can be assigned to.
+const MAX_LEVEL: usize = 16;
+
+/// Parameters for building an HNSW index, see [`crate::Victor::build_hnsw_index`].
+///
+/// ```rust
+/// use victor_db::HnswConfig;
+///
+/// // link each node to more neighbors, for better recall on a large database
+/// let config = HnswConfig {
+///     m: 32,
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HnswConfig {
+    /// The number of neighbors each node is linked to.
+    /// Higher values improve recall, at the cost of storage and insertion speed.
+    pub m: usize,
+    /// How many candidates are considered when linking a new node.
+    /// Higher values build a better graph, more slowly.
+    pub ef_construction: usize,
+}
+
+impl Default for HnswConfig {
+    fn default() -> Self {
+        Self {
+            m: 16,
+            ef_construction: 100,
+        }
+    }
+}
+
+/// The name of the file holding the graph for the db file `segment`.
+pub(crate) fn filename_for_segment(segment: &str) -> String {
+    format!("{}.hnsw", segment.trim_end_matches(".bin"))
+}
+
+/// The vectors of a graph's nodes, by node.
+///
+/// Searching a graph only visits a small part of it, so the vectors can be decoded as they're needed
+/// rather than all up front.
+pub(crate) trait Vectors {
+    /// The vector of `node`.
+    fn vector(&self, node: usize) -> Cow<'_, [f32]>;
+}
+
+impl<V: AsRef<[f32]>> Vectors for [V] {
+    fn vector(&self, node: usize) -> Cow<'_, [f32]> {
+        Cow::Borrowed(self[node].as_ref())
+    }
+}
+
+impl<V: AsRef<[f32]>> Vectors for Vec<V> {
+    fn vector(&self, node: usize) -> Cow<'_, [f32]> {
+        self.as_slice().vector(node)
+    }
+}
+
+/// The new links of a node (which may be new to the graph), as appended to the graph's file.
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct Change {
+    node: u32,
+    links: Vec<Vec<u32>>,
+    entry_point: Option<usize>,
+}
+
+/// A node's similarity to a query, ordered so that the most similar node is the greatest.
+#[derive(Debug, Clone, Copy)]
+struct Scored {
+    score: f32,
+    node: usize,
+}
+
+impl PartialEq for Scored {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Scored {}
+
+impl PartialOrd for Scored {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Scored {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.score
+            .total_cmp(&other.score)
+            .then(self.node.cmp(&other.node))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct Hnsw {
+    config: HnswConfig,
+    entry_point: Option<usize>,
+    /// `links[node][layer]` are the neighbors of `node` on `layer`.
+    links: Vec<Vec<Vec<u32>>>,
+    /// The nodes whose links changed since the graph was read.
+    #[serde(skip)]
+    changed: BTreeSet<usize>,
+    /// How many changes follow the graph in its file.
+    #[serde(skip)]
+    appended: usize,
+}
+
+impl Hnsw {
+    pub(crate) fn new(config: HnswConfig) -> Self {
+        Self {
+            config,
+            entry_point: None,
+            links: Vec::new(),
+            changed: BTreeSet::new(),
+            appended: 0,
+        }
+    }
+
+    /// Build a graph over all of `vectors`, where `ids` are the ids of the corresponding embeddings.
+    pub(crate) fn build<V: Vectors + ?Sized>(
+        config: HnswConfig,
+        ids: &[Uuid],
+        vectors: &V,
+        score: &dyn Fn(&[f32], &[f32]) -> f32,
+    ) -> Self {
+        let mut graph = Self::new(config);
+        for (node, id) in ids.iter().enumerate() {
+            graph.insert(node, id, vectors, score);
+        }
+        graph
+    }
+
+    /// The number of nodes in the graph.
+    pub(crate) fn len(&self) -> usize {
+        self.links.len()
+    }
+
+    /// Read a graph from its file, replaying the changes appended to it.
+    pub(crate) fn read(mut bytes: &[u8]) -> bincode::Result<Self> {
+        let mut graph: Self = bincode::deserialize_from(&mut bytes)?;
+        while !bytes.is_empty() {
+            graph.apply(bincode::deserialize_from(&mut bytes)?);
+        }
+        Ok(graph)
+    }
+
+    fn apply(&mut self, change: Change) {
+        let node = change.node as usize;
+        if node < self.links.len() {
+            self.links[node] = change.links;
+        } else {
+            self.links.push(change.links);
+        }
+        self.entry_point = change.entry_point;
+        self.appended += 1;
+    }
+
+    /// The changes to append to the graph's file since it was read,
+    /// or `None` if so many have piled up that it should be rewritten whole instead.
+    pub(crate) fn take_changes(&mut self) -> Option<Vec<Change>> {
+        let changes = std::mem::take(&mut self.changed)
+            .into_iter()
+            .map(|node| Change {
+                node: node as u32,
+                links: self.links[node].clone(),
+                entry_point: self.entry_point,
+            })
+            .collect::<Vec<_>>();
+        self.appended += changes.len();
+        (self.appended <= self.links.len()).then_some(changes)
+    }
+
+    /// Link the vector of `node` into the graph.
+    ///
+    /// New nodes must be inserted in order. If `node` is already in the graph, its links are recomputed instead,
+    /// which should be done whenever its vector changes.
+    pub(crate) fn insert<V: Vectors + ?Sized>(
+        &mut self,
+        node: usize,
+        id: &Uuid,
+        vectors: &V,
+        score: &dyn Fn(&[f32], &[f32]) -> f32,
+    ) {
+        let level = if node < self.links.len() {
+            self.links[node].len() - 1
+        } else {
+            debug_assert_eq!(node, self.links.len(), "nodes must be inserted in order");
+            self.links
+                .push(vec![Vec::new(); level_for(id, self.config.m) + 1]);
+            self.links[node].len() - 1
+        };
+        self.changed.insert(node);
+
+        let Some(entry_point) = self.entry_point else {
+            self.entry_point = Some(node);
+            return;
+        };
+        if self.links.len() == 1 {
+            return;
+        }
+
+        let query = vectors.vector(node);
+        let query = query.as_ref();
+        let top = self.links[entry_point].len() - 1;
+
+        let mut current = entry_point;
+        for layer in (level + 1..=top).rev() {
+            if let Some(nearest) = self
+                .search_layer(query, current, 1, layer, Some(node), vectors, score)
+                .first()
+            {
+                current = nearest.node;
+            }
+        }
+
+        for layer in (0..=level.min(top)).rev() {
+            let candidates = self.search_layer(
+                query,
+                current,
+                self.config.ef_construction,
+                layer,
+                Some(node),
+                vectors,
+                score,
+            );
+            let Some(nearest) = candidates.first() else {
+                continue;
+            };
+            current = nearest.node;
+
+            let neighbors = candidates
+                .iter()
+                .take(self.config.m)
+                .map(|candidate| candidate.node)
+                .collect::<Vec<_>>();
+            self.links[node][layer] = neighbors.iter().map(|&n| n as u32).collect();
+
+            let max_links = if layer == 0 {
+                self.config.m * 2
+            } else {
+                self.config.m
+            };
+            for neighbor in neighbors {
+                let links = &mut self.links[neighbor][layer];
+                if !links.contains(&(node as u32)) {
+                    links.push(node as u32);
+                    self.changed.insert(neighbor);
+                }
+                if links.len() > max_links {
+                    self.prune(neighbor, layer, max_links, vectors, score);
+                }
+            }
+        }
+
+        if level > top {
+            self.entry_point = Some(node);
+        }
+    }
+
+    /// Find (approximately) the `ef` nodes most similar to `query`, most similar first.
+    pub(crate) fn search<V: Vectors + ?Sized>(
+        &self,
+        query: &[f32],
+        ef: usize,
+        vectors: &V,
+        score: &dyn Fn(&[f32], &[f32]) -> f32,
+    ) -> Vec<usize> {
+        let Some(entry_point) = self.entry_point else {
+            return Vec::new();
+        };
+
+        let mut current = entry_point;
+        for layer in (1..self.links[entry_point].len()).rev() {
+            if let Some(nearest) = self
+                .search_layer(query, current, 1, layer, None, vectors, score)
+                .first()
+            {
+                current = nearest.node;
+            }
+        }
+
+        self.search_layer(query, current, ef, 0, None, vectors, score)
+            .into_iter()
+            .map(|scored| scored.node)
+            .collect()
+    }
+
+    /// Best-first search of a single layer, starting from `entry`.
+    /// Returns up to `ef` nodes (never including `exclude`), most similar first.
+    #[allow(clippy::too_many_arguments)]
+    fn search_layer<V: Vectors + ?Sized>(
+        &self,
+        query: &[f32],
+        entry: usize,
+        ef: usize,
+        layer: usize,
+        exclude: Option<usize>,
+        vectors: &V,
+        score: &dyn Fn(&[f32], &[f32]) -> f32,
+    ) -> Vec<Scored> {
+        let ef = ef.max(1);
+        let scored = |node: usize| Scored {
+            score: score(&vectors.vector(node), query),
+            node,
+        };
+
+        let mut visited = HashSet::from([entry]);
+        let mut candidates = BinaryHeap::from([scored(entry)]);
+        let mut results: BinaryHeap<Reverse<Scored>> = BinaryHeap::new();
+        if Some(entry) != exclude {
+            results.push(Reverse(scored(entry)));
+        }
+
+        while let Some(candidate) = candidates.pop() {
+            if results.len() >= ef && results.peek().is_some_and(|worst| candidate < worst.0) {
+                break;
+            }
+
+            let Some(links) = self.links[candidate.node].get(layer) else {
+                continue;
+            };
+            for &neighbor in links {
+                let neighbor = neighbor as usize;
+                if !visited.insert(neighbor) {
+                    continue;
+                }
+
+                let neighbor = scored(neighbor);
+                if results.len() < ef || results.peek().is_some_and(|worst| neighbor > worst.0) {
+                    candidates.push(neighbor);
+                    if Some(neighbor.node) != exclude {
+                        results.push(Reverse(neighbor));
+                        if results.len() > ef {
+                            results.pop();
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut results = results.into_iter().map(|r| r.0).collect::<Vec<_>>();
+        results.sort_by(|a, b| b.cmp(a));
+        results
+    }
+
+    /// Keep only the `max_links` neighbors of `node` on `layer` that are most similar to it.
+    fn prune<V: Vectors + ?Sized>(
+        &mut self,
+        node: usize,
+        layer: usize,
+        max_links: usize,
+        vectors: &V,
+        score: &dyn Fn(&[f32], &[f32]) -> f32,
+    ) {
+        let vector = vectors.vector(node);
+        let mut neighbors = self.links[node][layer]
+            .iter()
+            .map(|&neighbor| Scored {
+                score: score(&vectors.vector(neighbor as usize), &vector),
+                node: neighbor as usize,
+            })
+            .collect::<Vec<_>>();
+        neighbors.sort_by(|a, b| b.cmp(a));
+        self.links[node][layer] = neighbors
+            .into_iter()
+            .take(max_links)
+            .map(|neighbor| neighbor.node as u32)
+            .collect();
+    }
+}
+
+/// Pick the highest layer a node will be linked on.
+/// This is derived from the embedding's id, so the graph is the same no matter when it's built.
+fn level_for(id: &Uuid, m: usize) -> usize {
+    let hash = digest_u64(id);
+    // uniform in (0, 1]
+    let uniform = (hash as f64 + 1.0) / (u64::MAX as f64 + 1.0);
+    let level = -uniform.ln() / (m.max(2) as f64).ln();
+    (level as usize).min(MAX_LEVEL)
+}
+
+fn digest_u64(id: &Uuid) -> u64 {
+    // v4 ids are already random, but ids derived from external ids (v5) share their version bits,
+    // so mix all of the bytes together
+    let (high, low) = id.as_u64_pair();
+    let mut hash = high ^ low.rotate_left(32);
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51afd7ed558ccd);
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xc4ceb9fe1a85ec53);
+    hash ^ (hash >> 33)
+}
+
+/// The settings graphs are built with, or `None` if the database doesn't have an HNSW index.
+pub(crate) async fn read_config<D: DirectoryHandle>(root: &D) -> Result<Option<HnswConfig>, Error> {
+    read_optional(root, CONFIG_FILENAME).await
+}
+
+pub(crate) async fn write_config<D: DirectoryHandle>(
+    root: &D,
+    config: &HnswConfig,
+) -> Result<(), Error> {
+    write_bin(root, CONFIG_FILENAME, config).await
+}
+
+/// Read the graph for the db file `segment`, if it has one.
+pub(crate) async fn read_graph<D: DirectoryHandle>(
+    root: &D,
+    segment: &str,
+) -> Result<Option<Hnsw>, Error> {
+    let filename = filename_for_segment(segment);
+    let graph_file_handle = root
+        .get_file_handle_with_options(&filename, &GetFileHandleOptions { create: true })
+        .await?;
+
+    let graph = graph_file_handle.read().await?;
+
+    if graph.is_empty() {
+        Ok(None)
+    } else {
+        Hnsw::read(&graph).map(Some).map_err(|e| Error::Corrupted {
+            file: filename,
+            reason: e.to_string(),
+        })
+    }
+}
+
+pub(crate) async fn write_graph<D: DirectoryHandle>(
+    root: &D,
+    segment: &str,
+    graph: &Hnsw,
+) -> Result<(), Error> {
+    write_bin(root, &filename_for_segment(segment), graph).await
+}
+
+/// Append `changes` to the graph for the db file `segment`, rather than rewriting it.
+pub(crate) async fn append_changes<D: DirectoryHandle>(
+    root: &D,
+    segment: &str,
+    changes: &[Change],
+) -> Result<(), Error> {
+    append_bin(root, &filename_for_segment(segment), changes).await
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use super::*;
+    use crate::similarity;
+
+    fn cosine(a: &[f32], b: &[f32]) -> f32 {
+        similarity::cosine(a, b).unwrap()
+    }
+
+    #[test]
+    fn finds_exact_neighbors() {
+        let mut rng = rand::thread_rng();
+        let vectors = (0..500)
+            .map(|_| {
+                (0..16)
+                    .map(|_| rng.gen_range(-1.0..1.0))
+                    .collect::<Vec<f32>>()
+            })
+            .collect::<Vec<_>>();
+        let ids = (0..vectors.len())
+            .map(|_| Uuid::new_v4())
+            .collect::<Vec<_>>();
+
+        let graph = Hnsw::build(HnswConfig::default(), &ids, &vectors, &cosine);
+        assert_eq!(graph.len(), vectors.len());
+
+        // every vector should be found as its own nearest neighbor
+        let mut found = 0;
+        for (node, vector) in vectors.iter().enumerate() {
+            if graph.search(vector, 10, &vectors, &cosine).first() == Some(&node) {
+                found += 1;
+            }
+        }
+        assert!(found >= 490, "only found {found} of {}", vectors.len());
+    }
+
+    #[test]
+    fn relinks_updated_nodes() {
+        let mut vectors = vec![vec![1.0, 0.0], vec![0.0, 1.0], vec![-1.0, 0.0]];
+        let ids = (0..vectors.len())
+            .map(|_| Uuid::new_v4())
+            .collect::<Vec<_>>();
+        let mut graph = Hnsw::build(HnswConfig::default(), &ids, &vectors, &cosine);
+
+        vectors[0] = vec![0.0, -1.0];
+        graph.insert(0, &ids[0], &vectors, &cosine);
+
+        assert_eq!(graph.len(), 3);
+        assert_eq!(graph.search(&[0.1, -1.0], 1, &vectors, &cosine), vec![0]);
+    }
+
+    #[test]
+    fn replays_appended_changes() {
+        let mut rng = rand::thread_rng();
+        let vectors = (0..50)
+            .map(|_| {
+                (0..8)
+                    .map(|_| rng.gen_range(-1.0..1.0))
+                    .collect::<Vec<f32>>()
+            })
+            .collect::<Vec<_>>();
+        let ids = (0..vectors.len())
+            .map(|_| Uuid::new_v4())
+            .collect::<Vec<_>>();
+
+        let graph = Hnsw::build(HnswConfig::default(), &ids[..40], &vectors, &cosine);
+        let mut file = bincode::serialize(&graph).unwrap();
+
+        let mut graph = Hnsw::read(&file).unwrap();
+        for (node, id) in ids.iter().enumerate().skip(40) {
+            graph.insert(node, id, &vectors, &cosine);
+        }
+        graph.insert(0, &ids[0], &vectors, &cosine);
+        for change in graph.take_changes().unwrap() {
+            file.extend(bincode::serialize(&change).unwrap());
+        }
+
+        let read = Hnsw::read(&file).unwrap();
+        assert_eq!(read.len(), vectors.len());
+        assert_eq!(read.entry_point, graph.entry_point);
+        assert_eq!(read.links, graph.links);
+    }
+}
@@ -0,0 +1,93 @@
+//! Hooks run on every insert, delete and search, see [`crate::Victor::with_on_insert`],
+//! [`crate::Victor::with_on_delete`], [`crate::Victor::with_before_search`] and
+//! [`crate::Victor::with_after_search`].
+
+use uuid::Uuid;
+
+use crate::{
+    db::NearestNeighborsResult, document::Document, error::Error, filter::TagFilter,
+    search::SearchOptions,
+};
+
+/// A search, as the hooks from [`crate::Victor::with_before_search`] and [`crate::Victor::with_after_search`]
+/// see it.
+#[derive(Debug, Clone, Copy)]
+#[non_exhaustive]
+pub struct SearchRequest<'a> {
+    /// The text searched for, by text, hybrid and keyword searches.
+    pub query: Option<&'a str>,
+    /// The vector searched for, by every search but keyword searches.
+    pub vector: Option<&'a [f32]>,
+    /// The tags of the documents searched.
+    pub tags: &'a TagFilter,
+    /// The number of results asked for.
+    pub top_n: u32,
+    /// The search's options.
+    pub options: &'a SearchOptions,
+}
+
+impl<'a> SearchRequest<'a> {
+    pub(crate) fn new(
+        query: Option<&'a str>,
+        vector: Option<&'a [f32]>,
+        tags: &'a TagFilter,
+        top_n: u32,
+        options: &'a SearchOptions,
+    ) -> Self {
+        Self {
+            query,
+            vector,
+            tags,
+            top_n,
+            options,
+        }
+    }
+}
+
+type InsertHook = dyn Fn(&mut Document, &[String]) -> Result<(), Error> + Send + Sync;
+type DeleteHook = dyn Fn(Uuid) -> Result<(), Error> + Send + Sync;
+type BeforeSearchHook = dyn Fn(&SearchRequest) -> Result<(), Error> + Send + Sync;
+type AfterSearchHook = dyn Fn(&SearchRequest, &mut Vec<NearestNeighborsResult>) + Send + Sync;
+
+/// The hooks registered on a database, each kind run in the order they were registered.
+#[derive(Default)]
+pub(crate) struct Hooks {
+    pub on_insert: Vec<Box<InsertHook>>,
+    pub on_delete: Vec<Box<DeleteHook>>,
+    pub before_search: Vec<Box<BeforeSearchHook>>,
+    pub after_search: Vec<Box<AfterSearchHook>>,
+}
+
+impl Hooks {
+    /// Run the insert hooks on each of `documents`, stopping at the first error.
+    pub(crate) fn inserting(
+        &self,
+        documents: &mut [Document],
+        tags: &[String],
+    ) -> Result<(), Error> {
+        for hook in &self.on_insert {
+            for document in documents.iter_mut() {
+                hook(document, tags)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn deleting(&self, id: Uuid) -> Result<(), Error> {
+        self.on_delete.iter().try_for_each(|hook| hook(id))
+    }
+
+    pub(crate) fn searching(&self, request: &SearchRequest) -> Result<(), Error> {
+        self.before_search.iter().try_for_each(|hook| hook(request))
+    }
+
+    pub(crate) fn searched(
+        &self,
+        request: &SearchRequest,
+        results: &mut Vec<NearestNeighborsResult>,
+    ) {
+        for hook in &self.after_search {
+            hook(request, results);
+        }
+    }
+}
@@ -0,0 +1,334 @@
+//! Readers for the JSON exports of other vector databases, see [`crate::Victor::import_vector_store`].
+//!
+//! Each store's records are mapped to documents the same way: the id becomes the document's id,
+//! the content and tags are taken from the payload fields named in [`ImportOptions`],
+//! and the rest of the payload's strings and numbers become metadata.
+
+use serde_json::{Map, Value};
+
+use crate::{
+    document::{Document, MetadataValue},
+    error::Error,
+};
+
+/// A vector database whose exports can be imported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VectorStore {
+    /// Points from Qdrant's scroll API: `[{"id", "vector", "payload"}]`, optionally wrapped in
+    /// `{"result": {"points": [...]}}`. Named vectors are supported, see [`ImportOptions::vector_name`].
+    Qdrant,
+    /// A Chroma collection's `get()`: `{"ids", "embeddings", "documents", "metadatas"}`.
+    /// The content comes from `documents`, falling back to the metadata if there aren't any.
+    Chroma,
+    /// Pinecone records: `[{"id", "values", "metadata"}]`, or a fetch (`{"vectors": {...}}`)
+    /// or query (`{"matches": [...]}`) response.
+    Pinecone,
+}
+
+/// Options for [`crate::Victor::import_vector_store`].
+///
+/// ```rust
+/// use victor_db::ImportOptions;
+///
+/// // the content is under "page_content", like LangChain stores it
+/// let options = ImportOptions {
+///     content_field: "page_content".to_string(),
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Debug, Clone)]
+pub struct ImportOptions {
+    /// The payload field holding each document's content. Documents without it have empty content.
+    pub content_field: String,
+    /// The payload field holding each document's tags, either a string or a list of strings.
+    pub tags_field: String,
+    /// For Qdrant points with more than one named vector, which vector to import.
+    pub vector_name: Option<String>,
+}
+
+impl Default for ImportOptions {
+    fn default() -> Self {
+        Self {
+            content_field: "text".to_string(),
+            tags_field: "tags".to_string(),
+            vector_name: None,
+        }
+    }
+}
+
+/// Parse an export from `store` into documents, with the tags to add each one with.
+///
+/// Exports are JSON, or JSON Lines with one record per line.
+pub(crate) fn parse(
+    store: VectorStore,
+    export: &[u8],
+    options: &ImportOptions,
+) -> Result<Vec<(Vec<String>, Document)>, Error> {
+    let export = parse_json(export)?;
+    match store {
+        VectorStore::Qdrant => {
+            let points = match export {
+                Value::Object(mut object) => match object.remove("result") {
+                    Some(Value::Object(mut result)) => result.remove("points"),
+                    _ => object.remove("points"),
+                },
+                points => Some(points),
+            };
+            records(points)?
+                .into_iter()
+                .map(|mut point| {
+                    let id = id(point.remove("id"))?;
+                    let vector = qdrant_vector(&id, point.remove("vector"), options)?;
+                    let payload = object(point.remove("payload"));
+                    document(id, vector, None, payload, options)
+                })
+                .collect()
+        }
+        VectorStore::Chroma => {
+            let Value::Object(mut collection) = export else {
+                return Err(invalid("expected a Chroma collection object".to_string()));
+            };
+            let mut column = |name: &str| match collection.remove(name) {
+                Some(Value::Array(values)) => values,
+                _ => Vec::new(),
+            };
+            let (ids, embeddings, documents, metadatas) = (
+                column("ids"),
+                column("embeddings"),
+                column("documents"),
+                column("metadatas"),
+            );
+            if embeddings.len() != ids.len() {
+                return Err(invalid(format!(
+                    "there are {} ids but {} embeddings",
+                    ids.len(),
+                    embeddings.len()
+                )));
+            }
+
+            let mut documents = documents.into_iter();
+            let mut metadatas = metadatas.into_iter();
+            ids.into_iter()
+                .zip(embeddings)
+                .map(|(id_value, vector)| {
+                    let content = match documents.next() {
+                        Some(Value::String(content)) => Some(content),
+                        _ => None,
+                    };
+                    let payload = object(metadatas.next());
+                    document(id(Some(id_value))?, Some(vector), content, payload, options)
+                })
+                .collect()
+        }
+        VectorStore::Pinecone => {
+            let vectors = match export {
+                Value::Object(mut object) => {
+                    match (object.remove("vectors"), object.remove("matches")) {
+                        // fetch responses are keyed by id
+                        (Some(Value::Object(vectors)), _) => {
+                            Some(Value::Array(vectors.into_values().collect()))
+                        }
+                        (vectors, matches) => vectors.or(matches),
+                    }
+                }
+                vectors => Some(vectors),
+            };
+            records(vectors)?
+                .into_iter()
+                .map(|mut record| {
+                    let id = id(record.remove("id"))?;
+                    let payload = object(record.remove("metadata"));
+                    document(id, record.remove("values"), None, payload, options)
+                })
+                .collect()
+        }
+    }
+}
+
+/// A Qdrant point's vector, picking one if it has several named vectors.
+fn qdrant_vector(
+    id: &str,
+    vector: Option<Value>,
+    options: &ImportOptions,
+) -> Result<Option<Value>, Error> {
+    let Some(Value::Object(mut vectors)) = vector else {
+        return Ok(vector);
+    };
+    match &options.vector_name {
+        Some(name) => Ok(vectors.remove(name)),
+        None if vectors.len() == 1 => Ok(vectors.into_iter().next().map(|(_, vector)| vector)),
+        None => Err(invalid(format!(
+            "'{id}' has more than one named vector, so a vector name is needed"
+        ))),
+    }
+}
+
+/// Parse a JSON document, or JSON Lines as an array.
+fn parse_json(export: &[u8]) -> Result<Value, Error> {
+    if let Ok(value) = serde_json::from_slice(export) {
+        return Ok(value);
+    }
+
+    let export =
+        std::str::from_utf8(export).map_err(|error| invalid(format!("not JSON: {error}")))?;
+    export
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            serde_json::from_str(line)
+                .map_err(|error| invalid(format!("line {}: {error}", index + 1)))
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map(Value::Array)
+}
+
+/// An array of records, each an object.
+fn records(records: Option<Value>) -> Result<Vec<Map<String, Value>>, Error> {
+    let Some(Value::Array(records)) = records else {
+        return Err(invalid("expected a list of records".to_string()));
+    };
+    records
+        .into_iter()
+        .map(|record| match record {
+            Value::Object(record) => Ok(record),
+            other => Err(invalid(format!("expected a record, found {other}"))),
+        })
+        .collect()
+}
+
+fn object(value: Option<Value>) -> Map<String, Value> {
+    match value {
+        Some(Value::Object(object)) => object,
+        _ => Map::new(),
+    }
+}
+
+/// Ids can be strings or numbers (like Qdrant's integer ids).
+fn id(id: Option<Value>) -> Result<String, Error> {
+    match id {
+        Some(Value::String(id)) => Ok(id),
+        Some(Value::Number(id)) => Ok(id.to_string()),
+        _ => Err(invalid(
+            "every record needs a string or numeric id".to_string(),
+        )),
+    }
+}
+
+fn document(
+    id: String,
+    vector: Option<Value>,
+    content: Option<String>,
+    mut payload: Map<String, Value>,
+    options: &ImportOptions,
+) -> Result<(Vec<String>, Document), Error> {
+    let vector = match vector {
+        Some(Value::Array(values)) => values
+            .iter()
+            .map(|value| value.as_f64().map(|value| value as f32))
+            .collect::<Option<Vec<_>>>(),
+        _ => None,
+    }
+    .ok_or_else(|| invalid(format!("'{id}' doesn't have a vector of numbers")))?;
+
+    let content = content
+        .or_else(|| match payload.remove(&options.content_field) {
+            Some(Value::String(content)) => Some(content),
+            _ => None,
+        })
+        .unwrap_or_default();
+    let tags = match payload.remove(&options.tags_field) {
+        Some(Value::String(tag)) => vec![tag],
+        Some(Value::Array(tags)) => tags
+            .into_iter()
+            .filter_map(|tag| match tag {
+                Value::String(tag) => Some(tag),
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+
+    // only strings and numbers can be metadata, so anything else is left out
+    let mut document = Document::new(content, vector).with_id(id);
+    for (key, value) in payload {
+        let value = match value {
+            Value::String(value) => MetadataValue::String(value),
+            Value::Number(value) => MetadataValue::Number(value.as_f64().unwrap_or_default()),
+            _ => continue,
+        };
+        document = document.with_metadata(key, value);
+    }
+
+    Ok((tags, document))
+}
+
+fn invalid(reason: String) -> Error {
+    Error::InvalidInput(reason)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_one(store: VectorStore, export: &str) -> (Vec<String>, Document) {
+        let mut documents = parse(store, export.as_bytes(), &ImportOptions::default()).unwrap();
+        assert_eq!(documents.len(), 1);
+        documents.remove(0)
+    }
+
+    #[test]
+    fn parses_exports() {
+        let (tags, document) = parse_one(
+            VectorStore::Qdrant,
+            r#"{"result": {"points": [{"id": 7, "vector": {"text": [1, 2]}, "payload": {"text": "pizza", "tags": "food", "price": 3, "nested": {}}}]}}"#,
+        );
+        assert_eq!(tags, vec!["food"]);
+        assert_eq!(document.id.as_deref(), Some("7"));
+        assert_eq!(document.content, "pizza");
+        assert_eq!(document.vector, vec![1.0, 2.0]);
+        assert_eq!(document.metadata.len(), 1);
+        assert_eq!(document.metadata["price"], MetadataValue::Number(3.0));
+
+        let (tags, document) = parse_one(
+            VectorStore::Chroma,
+            r#"{"ids": ["a"], "embeddings": [[1, 2]], "documents": ["pizza"], "metadatas": [{"tags": ["food", "italian"], "author": "alice"}]}"#,
+        );
+        assert_eq!(tags, vec!["food", "italian"]);
+        assert_eq!(document.content, "pizza");
+        assert_eq!(document.metadata["author"], MetadataValue::from("alice"));
+
+        let (_, document) = parse_one(
+            VectorStore::Pinecone,
+            r#"{"vectors": {"a": {"id": "a", "values": [1, 2], "metadata": {"text": "pizza"}}}}"#,
+        );
+        assert_eq!(document.id.as_deref(), Some("a"));
+        assert_eq!(document.content, "pizza");
+
+        // JSON Lines work too
+        let documents = parse(
+            VectorStore::Pinecone,
+            b"{\"id\": \"a\", \"values\": [1]}\n{\"id\": \"b\", \"values\": [2]}\n",
+            &ImportOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(documents.len(), 2);
+
+        assert!(matches!(
+            parse(
+                VectorStore::Qdrant,
+                br#"[{"id": 1, "vector": {"a": [1], "b": [2]}}]"#,
+                &ImportOptions::default()
+            ),
+            Err(Error::InvalidInput(_))
+        ));
+        assert!(matches!(
+            parse(
+                VectorStore::Chroma,
+                br#"{"ids": ["a"], "embeddings": []}"#,
+                &ImportOptions::default()
+            ),
+            Err(Error::InvalidInput(_))
+        ));
+    }
+}
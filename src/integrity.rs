@@ -0,0 +1,140 @@
+//! Checking a database for damage and repairing it, see [`crate::Victor::verify_integrity`]
+//! and [`crate::Victor::repair`].
+
+use serde::Serialize;
+use uuid::Uuid;
+
+/// What [`crate::Victor::verify_integrity`] found wrong with a database.
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct IntegrityReport {
+    /// How many db files were checked.
+    pub db_files: usize,
+    /// How many embeddings the db files hold, including deleted ones that haven't been compacted away yet.
+    pub embeddings: usize,
+    /// Everything that's wrong, in the order it was found. Empty if the database is intact.
+    pub problems: Vec<Problem>,
+}
+
+impl IntegrityReport {
+    /// Whether nothing is wrong with the database.
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// Something wrong with a database, see [`IntegrityReport`].
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub enum Problem {
+    /// `index.bin` lists a db file that doesn't exist, so the documents in it are gone.
+    MissingFile {
+        /// The name of the db file.
+        file: String,
+    },
+    /// A file couldn't be read at all, so nothing more about it could be checked.
+    Unreadable {
+        /// The name of the file in the database directory.
+        file: String,
+        /// Why it couldn't be read.
+        reason: String,
+    },
+    /// A file doesn't match the checksum recorded when it was last written, so it was changed or damaged since.
+    ChecksumMismatch {
+        /// The name of the file in the database directory.
+        file: String,
+    },
+    /// A db file ends partway through an embedding, usually because writing it was interrupted.
+    PartialRecord {
+        /// The name of the db file.
+        file: String,
+        /// The size of each embedding in the file, from its header.
+        record_size: usize,
+        /// How many bytes there are after the last whole embedding.
+        extra_bytes: usize,
+    },
+    /// An embedding in a db file couldn't be decoded.
+    UndecodableRecord {
+        /// The name of the db file.
+        file: String,
+        /// The position of the embedding in the file, counting from 0.
+        record: usize,
+    },
+    /// An embedding has no content, so it can't be returned by a search.
+    MissingContent {
+        /// The name of the db file the embedding is in.
+        file: String,
+        /// The embedding's id.
+        id: Uuid,
+    },
+    /// Content whose embedding isn't in any db file, so it can never be found.
+    OrphanedContent {
+        /// The id of the missing embedding.
+        id: Uuid,
+    },
+    /// `combined.bin` lists a different number of embeddings for a combined db file than the file holds,
+    /// so the tags of some of them are wrong or unknown.
+    TagMapMismatch {
+        /// The name of the combined db file.
+        file: String,
+        /// How many embeddings `combined.bin` lists for it.
+        expected: usize,
+        /// How many embeddings it holds.
+        found: usize,
+    },
+    /// A file's vectors have a different number of dimensions than the database's.
+    DimensionMismatch {
+        /// The name of the file: a db file, or `eigen.bin` for the projection.
+        file: String,
+        /// The number of dimensions the database expects.
+        expected: usize,
+        /// The number of dimensions in the file.
+        found: usize,
+    },
+}
+
+/// What [`crate::Victor::repair`] did to a database.
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct RepairReport {
+    /// What was wrong with the database before it was repaired.
+    pub found: IntegrityReport,
+    /// Everything that was thrown away to repair it, in the order it was thrown away.
+    pub discarded: Vec<Discarded>,
+}
+
+/// Something [`crate::Victor::repair`] threw away, see [`RepairReport`].
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub enum Discarded {
+    /// The end of a file, after the last whole embedding (or content) in it.
+    Bytes {
+        /// The name of the file in the database directory.
+        file: String,
+        /// How many bytes were cut off.
+        count: usize,
+    },
+    /// Everything in a db file that couldn't be read at all.
+    File {
+        /// The name of the db file.
+        file: String,
+        /// How big the file was, in bytes.
+        size: usize,
+    },
+    /// An embedding that couldn't be decoded, had the wrong number of dimensions,
+    /// or was past the end of its combined db file's tag map.
+    Record {
+        /// The name of the db file.
+        file: String,
+        /// The position of the embedding in the file before it was repaired, counting from 0.
+        record: usize,
+    },
+    /// An embedding without content.
+    Embedding {
+        /// The name of the db file.
+        file: String,
+        /// The embedding's id.
+        id: Uuid,
+    },
+    /// Content without an embedding.
+    Content {
+        /// The id of the missing embedding.
+        id: Uuid,
+    },
+}
@@ -0,0 +1,80 @@
+//! A journal of the write in progress, so a write that was interrupted (say, by a crash) can be rolled back.
+//!
+//! An insert appends embeddings to a db file, then appends their content to `content.bin`, and an update rewrites
+//! an embedding's record in place, then appends its new content. Before any of that happens, the journal records
+//! how big the db file and the logs (see [`LOGS`]) were, and the bytes of every record before it's rewritten,
+//! and once the write is done the journal is removed. If a journal is left over, the write didn't finish,
+//! so [`crate::Victor::recover`] truncates the files back to their old sizes and puts the old records back.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    db::{read_optional, write_bin},
+    error::Error,
+    filesystem::{DirectoryHandle, FileHandle, GetFileHandleOptions},
+};
+
+const FILENAME: &str = "journal.bin";
+
+/// The files writes only ever append to, besides db files.
+const LOGS: [&str; 3] = ["content.bin", "originals.bin", "keywords.bin"];
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub(crate) struct Journal {
+    /// The db file the embeddings are appended to, with its size before the insert, if any are.
+    pub segment: Option<(String, usize)>,
+    /// The ids of the inserted embeddings.
+    pub ids: Vec<Uuid>,
+    /// The size of each log before the write, by name. Logs that didn't exist aren't included.
+    pub logs: BTreeMap<String, usize>,
+    /// The records rewritten in place, in the order they were rewritten.
+    pub rewritten: Vec<Rewritten>,
+}
+
+/// A record in a db file, as it was before it was rewritten.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct Rewritten {
+    /// The db file the record is in.
+    pub segment: String,
+    /// Where the record starts in the (decompressed) db file.
+    pub offset: usize,
+    /// The record's bytes.
+    pub record: Vec<u8>,
+}
+
+/// A journal for a write that's about to start, recording the size of the logs.
+/// Nothing is written until [`write()`] is called.
+pub(crate) async fn start<D: DirectoryHandle>(root: &D) -> Result<Journal, Error> {
+    let mut logs = BTreeMap::new();
+    for log in LOGS {
+        if let Ok(file_handle) = root
+            .get_file_handle_with_options(log, &GetFileHandleOptions { create: false })
+            .await
+        {
+            logs.insert(log.to_string(), file_handle.size().await?);
+        }
+    }
+
+    Ok(Journal {
+        logs,
+        ..Default::default()
+    })
+}
+
+/// The journal of the write in progress, if there is one.
+pub(crate) async fn read<D: DirectoryHandle>(root: &D) -> Result<Option<Journal>, Error> {
+    read_optional(root, FILENAME).await
+}
+
+pub(crate) async fn write<D: DirectoryHandle>(root: &D, journal: &Journal) -> Result<(), Error> {
+    write_bin(root, FILENAME, journal).await
+}
+
+/// Mark the write in progress as finished.
+pub(crate) async fn remove<D: DirectoryHandle>(root: &mut D) -> Result<(), Error> {
+    let _ = root.remove_entry(FILENAME).await;
+    Ok(())
+}
@@ -0,0 +1,132 @@
+//! JSON Lines import and export, see [`crate::Victor::export_jsonl`] and [`crate::Victor::import_jsonl`].
+//!
+//! Each line is one document: `{"id": ..., "content": ..., "tags": [...], "vector": [...], "metadata": {...}}`.
+//! Metadata values are plain JSON strings and numbers, and `metadata` can be left out.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::{
+    document::{Document, Metadata, MetadataValue},
+    error::Error,
+};
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub(crate) struct Record {
+    pub id: String,
+    pub content: String,
+    pub tags: Vec<String>,
+    pub vector: Vec<f32>,
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub metadata: Map<String, Value>,
+}
+
+impl Record {
+    pub(crate) fn new(
+        id: String,
+        content: String,
+        tags: Vec<String>,
+        vector: Vec<f32>,
+        metadata: &Metadata,
+    ) -> Self {
+        Self {
+            id,
+            content,
+            tags,
+            vector,
+            metadata: metadata_to_json(metadata),
+        }
+    }
+
+    /// Parse one line of a JSON Lines file. `line_number` starts at 1, and is only used for errors.
+    pub(crate) fn parse(line_number: usize, line: &str) -> Result<Self, Error> {
+        serde_json::from_str(line)
+            .map_err(|error| Error::InvalidInput(format!("line {line_number}: {error}")))
+    }
+
+    /// Split the record into its tags and the document to add with them.
+    pub(crate) fn into_document(self) -> Result<(Vec<String>, Document), Error> {
+        let metadata = metadata_from_json(self.metadata, &self.id)?;
+        let mut document = Document::new(self.content, self.vector).with_id(self.id);
+        document.metadata = metadata;
+        Ok((self.tags, document))
+    }
+}
+
+/// The reverse of [`metadata_to_json`], for the document `id`, which is only used for errors.
+pub(crate) fn metadata_from_json(
+    metadata: Map<String, Value>,
+    id: &str,
+) -> Result<Metadata, Error> {
+    metadata
+        .into_iter()
+        .map(|(key, value)| {
+            let value = match value {
+                Value::String(value) => MetadataValue::String(value),
+                Value::Number(value) => MetadataValue::Number(value.as_f64().unwrap_or_default()),
+                other => {
+                    return Err(Error::InvalidInput(format!(
+                        "metadata values must be strings or numbers, but '{key}' of '{id}' is {other}"
+                    )));
+                }
+            };
+            Ok((key, value))
+        })
+        .collect()
+}
+
+/// Metadata as a JSON object, with plain strings and numbers as values.
+pub(crate) fn metadata_to_json(metadata: &Metadata) -> Map<String, Value> {
+    metadata
+        .iter()
+        .map(|(key, value)| {
+            let value = match value {
+                MetadataValue::String(value) => Value::from(value.clone()),
+                MetadataValue::Number(value) => Value::from(*value),
+            };
+            (key.clone(), value)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_metadata() {
+        let metadata = Metadata::from([
+            ("author".to_string(), MetadataValue::from("alice")),
+            ("price".to_string(), MetadataValue::from(12.5)),
+        ]);
+        let record = Record::new(
+            "pizza-1".to_string(),
+            "Pepperoni pizza".to_string(),
+            vec!["Pizza Flavors".to_string()],
+            vec![0.5, 1.0],
+            &metadata,
+        );
+
+        let line = serde_json::to_string(&record).unwrap();
+        assert_eq!(
+            line,
+            r#"{"id":"pizza-1","content":"Pepperoni pizza","tags":["Pizza Flavors"],"vector":[0.5,1.0],"metadata":{"author":"alice","price":12.5}}"#
+        );
+        assert_eq!(Record::parse(1, &line).unwrap(), record);
+
+        let (tags, document) = record.into_document().unwrap();
+        assert_eq!(tags, vec!["Pizza Flavors"]);
+        assert_eq!(document.metadata, metadata);
+
+        let nested = r#"{"id":"a","content":"","tags":[],"vector":[],"metadata":{"nested":{}}}"#;
+        let record = Record::parse(1, nested).unwrap();
+        assert!(matches!(
+            record.into_document(),
+            Err(Error::InvalidInput(_))
+        ));
+        assert!(matches!(
+            Record::parse(3, "not json"),
+            Err(Error::InvalidInput(message)) if message.starts_with("line 3")
+        ));
+    }
+}
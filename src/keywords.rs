@@ -0,0 +1,339 @@
+//! Keyword search over document contents (see [`crate::Victor::search_keywords`]), and fusing it with vector search
+//! (see [`crate::Victor::search_hybrid`]).
+//!
+//! Contents are split into lowercase alphanumeric terms, so `"Error E1234!"` is `["error", "e1234"]`,
+//! and documents are scored against a query's terms with [BM25](https://en.wikipedia.org/wiki/Okapi_BM25).
+//! The terms can be kept in `keywords.bin` (see [`crate::Victor::build_keyword_index`]), so they don't have to be
+//! found again for every search. Adding, updating or deleting a document appends a [`Change`] to the file, which is
+//! replayed when it's read, and compacting the database folds them into the index.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    db::{append_bin, write_bin},
+    error::Error,
+    filesystem::{DirectoryHandle, FileHandle, GetFileHandleOptions},
+    search::Fusion,
+};
+
+const FILENAME: &str = "keywords.bin";
+
+/// How much repeating a term raises a document's score, before it levels off.
+const K1: f32 = 1.2;
+/// How much longer documents are penalized, from 0 (not at all) to 1.
+const B: f32 = 0.75;
+
+/// Split `text` into lowercase alphanumeric terms.
+pub(crate) fn tokenize(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|term| !term.is_empty())
+        .map(str::to_lowercase)
+}
+
+/// A change to a document's terms, as appended to `keywords.bin`.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub(crate) enum Change {
+    /// The document's terms, with how many times each appears, replacing any it had before.
+    Insert(Uuid, Vec<(String, u32)>),
+    /// The document is gone.
+    Remove(Uuid),
+}
+
+impl Change {
+    /// The change that gives document `id` the terms of `content`.
+    pub(crate) fn insert(id: Uuid, content: &str) -> Self {
+        let mut terms = HashMap::<String, u32>::new();
+        for term in tokenize(content) {
+            *terms.entry(term).or_default() += 1;
+        }
+        Change::Insert(id, terms.into_iter().collect())
+    }
+}
+
+/// The terms of a set of documents, for scoring them against queries.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+pub(crate) struct InvertedIndex {
+    /// For each term, how many times it appears in each document that has it.
+    postings: HashMap<String, HashMap<Uuid, u32>>,
+    /// How many terms each document has.
+    lengths: HashMap<Uuid, u32>,
+    /// How many changes follow the index in its file.
+    #[serde(skip)]
+    appended: usize,
+}
+
+impl InvertedIndex {
+    /// Read an index from its file, replaying the changes appended to it.
+    pub(crate) fn read(mut bytes: &[u8]) -> bincode::Result<Self> {
+        let mut index: Self = bincode::deserialize_from(&mut bytes)?;
+        while !bytes.is_empty() {
+            let change: Change = bincode::deserialize_from(&mut bytes)?;
+            index.apply(change);
+            index.appended += 1;
+        }
+        Ok(index)
+    }
+
+    /// How many changes were replayed when the index was read, see [`InvertedIndex::read`].
+    pub(crate) fn appended(&self) -> usize {
+        self.appended
+    }
+
+    /// Add a document's terms, replacing any it had before.
+    pub(crate) fn insert(&mut self, id: Uuid, content: &str) {
+        self.apply(Change::insert(id, content));
+    }
+
+    /// Apply a change read from the index's file.
+    fn apply(&mut self, change: Change) {
+        match change {
+            Change::Insert(id, terms) => {
+                self.remove(&id);
+                let mut length = 0;
+                for (term, count) in terms {
+                    self.postings.entry(term).or_default().insert(id, count);
+                    length += count;
+                }
+                self.lengths.insert(id, length);
+            }
+            Change::Remove(id) => self.remove(&id),
+        }
+    }
+
+    pub(crate) fn remove(&mut self, id: &Uuid) {
+        if self.lengths.remove(id).is_none() {
+            return;
+        }
+        self.postings.retain(|_, postings| {
+            postings.remove(id);
+            !postings.is_empty()
+        });
+    }
+
+    /// Every document with at least one of the query's terms, with its BM25 score, best first.
+    pub(crate) fn search(&self, query: &str) -> Vec<(f32, Uuid)> {
+        let count = self.lengths.len() as f32;
+        let average_length = self.lengths.values().sum::<u32>() as f32 / count.max(1.0);
+
+        let mut terms = tokenize(query).collect::<Vec<_>>();
+        terms.sort();
+        terms.dedup();
+
+        let mut scores = HashMap::<Uuid, f32>::new();
+        for term in terms {
+            let Some(postings) = self.postings.get(&term) else {
+                continue;
+            };
+            let matching = postings.len() as f32;
+            let idf = (1.0 + (count - matching + 0.5) / (matching + 0.5)).ln();
+            for (id, &frequency) in postings {
+                let frequency = frequency as f32;
+                let length = self.lengths[id] as f32;
+                let saturation = K1 * (1.0 - B + B * length / average_length.max(1.0));
+                *scores.entry(*id).or_default() +=
+                    idf * frequency * (K1 + 1.0) / (frequency + saturation);
+            }
+        }
+
+        let mut scores = scores
+            .into_iter()
+            .map(|(id, score)| (score, id))
+            .collect::<Vec<_>>();
+        scores.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+        scores
+    }
+}
+
+/// Combine the ranked results of a vector search and a keyword search, best first.
+/// Each list must be sorted best first.
+pub(crate) fn fuse(
+    vector: &[(f32, Uuid)],
+    keywords: &[(f32, Uuid)],
+    fusion: Fusion,
+) -> Vec<(f32, Uuid)> {
+    let mut fused = HashMap::<Uuid, f32>::new();
+    match fusion {
+        Fusion::ReciprocalRank { k } => {
+            for results in [vector, keywords] {
+                for (rank, (_, id)) in results.iter().enumerate() {
+                    *fused.entry(*id).or_default() += 1.0 / (k + rank as f32 + 1.0);
+                }
+            }
+        }
+        Fusion::Weighted { keyword_weight } => {
+            for (results, weight) in [(vector, 1.0 - keyword_weight), (keywords, keyword_weight)] {
+                for (score, id) in normalize(results) {
+                    *fused.entry(id).or_default() += weight * score;
+                }
+            }
+        }
+    }
+
+    let mut fused = fused
+        .into_iter()
+        .map(|(id, score)| (score, id))
+        .collect::<Vec<_>>();
+    fused.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+    fused
+}
+
+/// A BM25 score scaled to between 0 and 1, keeping its order, for [`crate::db::NearestNeighborsResult::score`].
+pub(crate) fn bm25_score(score: f32) -> f32 {
+    score / (1.0 + score)
+}
+
+/// A score from [`fuse`] scaled to between 0 and 1, for [`crate::db::NearestNeighborsResult::score`].
+/// Weighted scores already are, and reciprocal rank fusion scores are divided by the best possible score, of a
+/// result that's first in both lists.
+pub(crate) fn fused_score(score: f32, fusion: Fusion) -> f32 {
+    match fusion {
+        Fusion::ReciprocalRank { k } => score * (k + 1.0) / 2.0,
+        Fusion::Weighted { .. } => score,
+    }
+}
+
+/// Scale scores to between 0 (the worst) and 1 (the best), so scores on different scales can be added.
+fn normalize(results: &[(f32, Uuid)]) -> impl Iterator<Item = (f32, Uuid)> + '_ {
+    let max = results.first().map_or(0.0, |(score, _)| *score);
+    let min = results.last().map_or(0.0, |(score, _)| *score);
+    results.iter().map(move |(score, id)| {
+        let normalized = if max > min {
+            (score - min) / (max - min)
+        } else {
+            1.0
+        };
+        (normalized, *id)
+    })
+}
+
+/// The keyword index, if one has been built, see [`crate::Victor::build_keyword_index`].
+pub(crate) async fn read_index<D: DirectoryHandle>(
+    root: &D,
+) -> Result<Option<InvertedIndex>, Error> {
+    let index_file_handle = root
+        .get_file_handle_with_options(FILENAME, &GetFileHandleOptions { create: true })
+        .await?;
+
+    let index = index_file_handle.read().await?;
+
+    if index.is_empty() {
+        Ok(None)
+    } else {
+        InvertedIndex::read(&index)
+            .map(Some)
+            .map_err(|e| Error::Corrupted {
+                file: FILENAME.to_string(),
+                reason: e.to_string(),
+            })
+    }
+}
+
+/// Whether a keyword index has been built, without reading it.
+pub(crate) async fn has_index<D: DirectoryHandle>(root: &D) -> Result<bool, Error> {
+    let index_file_handle = root
+        .get_file_handle_with_options(FILENAME, &GetFileHandleOptions { create: true })
+        .await?;
+
+    Ok(index_file_handle.size().await? > 0)
+}
+
+/// Replace the keyword index with `index`, dropping any changes appended to it.
+pub(crate) async fn write_index<D: DirectoryHandle>(
+    root: &D,
+    index: &InvertedIndex,
+) -> Result<(), Error> {
+    write_bin(root, FILENAME, index).await
+}
+
+/// Append `changes` to the keyword index, without reading it. Does nothing if there's no keyword index.
+pub(crate) async fn append_changes<D: DirectoryHandle>(
+    root: &D,
+    changes: &[Change],
+) -> Result<(), Error> {
+    if !has_index(root).await? {
+        return Ok(());
+    }
+    append_bin(root, FILENAME, changes).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scores_keywords() {
+        assert_eq!(
+            tokenize("Error E1234: SKU-42 not found!").collect::<Vec<_>>(),
+            vec!["error", "e1234", "sku", "42", "not", "found"]
+        );
+
+        let ids = [Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4()];
+        let mut index = InvertedIndex::default();
+        index.insert(ids[0], "The printer shows error E1234");
+        index.insert(ids[1], "The printer is out of paper");
+        index.insert(ids[2], "Pepperoni pizza");
+
+        let results = index.search("printer error e1234");
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].1, ids[0]);
+        assert!(index.search("anchovies").is_empty());
+
+        // replacing a document's content replaces its terms
+        index.insert(ids[0], "Pepperoni pizza");
+        assert!(index.search("e1234").is_empty());
+        index.remove(&ids[0]);
+        index.remove(&ids[2]);
+        assert!(index.search("pizza").is_empty());
+    }
+
+    #[test]
+    fn replays_appended_changes() {
+        let ids = [Uuid::new_v4(), Uuid::new_v4()];
+        let mut index = InvertedIndex::default();
+        index.insert(ids[0], "The printer shows error E1234");
+        let mut bytes = bincode::serialize(&index).unwrap();
+
+        for change in [
+            Change::insert(ids[1], "Pepperoni pizza, pepperoni"),
+            Change::insert(ids[0], "The printer is out of paper"),
+            Change::Remove(ids[1]),
+            Change::insert(ids[1], "Pizza"),
+        ] {
+            bytes.extend(bincode::serialize(&change).unwrap());
+        }
+
+        let read = InvertedIndex::read(&bytes).unwrap();
+        assert_eq!(read.appended(), 4);
+
+        let mut expected = InvertedIndex::default();
+        expected.insert(ids[0], "The printer is out of paper");
+        expected.insert(ids[1], "Pizza");
+        assert_eq!(read.postings, expected.postings);
+        assert_eq!(read.lengths, expected.lengths);
+    }
+
+    #[test]
+    fn fuses_rankings() {
+        let [a, b, c] = [Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4()];
+        let vector = [(0.9, a), (0.8, b)];
+        let keywords = [(5.0, c), (1.0, b)];
+
+        // b is in both lists, so it comes first
+        let fused = fuse(&vector, &keywords, Fusion::ReciprocalRank { k: 60.0 });
+        assert_eq!(fused.len(), 3);
+        assert_eq!(fused[0].1, b);
+
+        // with only keywords counting, the best keyword match comes first
+        let fused = fuse(
+            &vector,
+            &keywords,
+            Fusion::Weighted {
+                keyword_weight: 1.0,
+            },
+        );
+        assert_eq!(fused[0], (1.0, c));
+    }
+}
@@ -0,0 +1,93 @@
+//! k-means clustering, used to train product quantization codebooks.
+
+/// Squared euclidean distance between two vectors of the same length.
+pub(crate) fn squared_distance(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(a, b)| (a - b) * (a - b)).sum()
+}
+
+/// The index of the centroid closest to `vector`.
+pub(crate) fn nearest(centroids: &[Vec<f32>], vector: &[f32]) -> usize {
+    centroids
+        .iter()
+        .map(|centroid| squared_distance(centroid, vector))
+        .enumerate()
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map_or(0, |(index, _)| index)
+}
+
+/// Cluster `vectors` into (at most) `k` clusters with Lloyd's algorithm, returning the centroids.
+///
+/// Centroids start out as evenly spaced samples from `vectors`, so the result is deterministic.
+pub(crate) fn kmeans(vectors: &[Vec<f32>], k: usize, iterations: usize) -> Vec<Vec<f32>> {
+    let k = k.min(vectors.len());
+    if k == 0 {
+        return Vec::new();
+    }
+    let dimension = vectors[0].len();
+
+    let mut centroids = (0..k)
+        .map(|i| vectors[i * vectors.len() / k].clone())
+        .collect::<Vec<_>>();
+
+    for _ in 0..iterations {
+        let mut sums = vec![vec![0.0; dimension]; k];
+        let mut counts = vec![0usize; k];
+        for vector in vectors {
+            let cluster = nearest(&centroids, vector);
+            counts[cluster] += 1;
+            for (sum, value) in sums[cluster].iter_mut().zip(vector) {
+                *sum += value;
+            }
+        }
+
+        let mut moved = false;
+        for ((centroid, sum), count) in centroids.iter_mut().zip(sums).zip(counts) {
+            // leave empty clusters where they are
+            if count == 0 {
+                continue;
+            }
+            let mean = sum
+                .into_iter()
+                .map(|sum| sum / count as f32)
+                .collect::<Vec<_>>();
+            moved |= mean != *centroid;
+            *centroid = mean;
+        }
+        if !moved {
+            break;
+        }
+    }
+
+    centroids
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_clusters() {
+        let vectors = vec![
+            vec![0.0, 0.0],
+            vec![0.1, 0.0],
+            vec![0.0, 0.1],
+            vec![10.0, 10.0],
+            vec![10.1, 10.0],
+            vec![10.0, 10.1],
+        ];
+
+        let centroids = kmeans(&vectors, 2, 10);
+        assert_eq!(centroids.len(), 2);
+        assert_eq!(
+            nearest(&centroids, &[0.0, 0.0]),
+            nearest(&centroids, &[0.1, 0.0])
+        );
+        assert_ne!(
+            nearest(&centroids, &[0.0, 0.0]),
+            nearest(&centroids, &[10.0, 10.0])
+        );
+
+        // can't have more clusters than vectors
+        assert_eq!(kmeans(&vectors, 10, 10).len(), 6);
+    }
+}
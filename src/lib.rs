@@ -10,6 +10,7 @@
 //!
 //! The in-memory version is useful for testing and applications where you don't need to persist data:
 //! ```rust
+//! # #[cfg(feature = "embeddings")]
 //! # tokio_test::block_on(async {
 //! // use victor_db::memory for the in-memory implementation
 //! use victor_db::memory::{Db, DirectoryHandle};
@@ -23,16 +24,21 @@
 //!         vec!["Pineapple", "Rocks"], // documents
 //!         vec!["Pizza Toppings"],     // tags (only used for filtering)
 //!     )
-//!     .await;
+//!     .await
+//!     .unwrap();
 //!
 //! // add another embedding to the database, this time with no tags
-//! victor.add_single("Cheese pizza", vec!["Pizza Flavors"]).await;
+//! victor
+//!     .add_single("Cheese pizza", vec!["Pizza Flavors"])
+//!     .await
+//!     .unwrap();
 //!
 //! // read the 10 closest results from victor that are tagged with "Pizza Toppings"
 //! // (only 2 will be returned because we only inserted two embeddings)
 //! let nearest = victor
 //!     .search("Hawaiian pizza", vec!["Pizza Toppings"], 10)
 //!     .await
+//!     .unwrap()
 //!     .first()
 //!     .unwrap()
 //!     .content
@@ -41,7 +47,7 @@
 //!
 //! // Clear the database
 //! victor.clear_db().await.unwrap();
-//! # })
+//! # });
 //! ```
 //!
 //! ## Native database
@@ -49,6 +55,7 @@
 //! Use this if you want to persist your database to disk.
 //!
 //! ```rust
+//! # #[cfg(feature = "embeddings")]
 //! # tokio_test::block_on(async {
 //! // use victor_db::native for the native filesystem implementation
 //! use victor_db::native::Db;
@@ -64,16 +71,21 @@
 //!         vec!["Pineapple", "Rocks"], // documents
 //!         vec!["Pizza Toppings"],     // tags (only used for filtering)
 //!     )
-//!     .await;
+//!     .await
+//!     .unwrap();
 //!
 //! // add another embedding to the database, this time with no tags
-//! victor.add_single("Cheese pizza", vec!["Pizza Flavors"]).await;
+//! victor
+//!     .add_single("Cheese pizza", vec!["Pizza Flavors"])
+//!     .await
+//!     .unwrap();
 //!
 //! // read the 10 closest results from victor that are tagged with "Pizza Toppings"
 //! // (only 2 will be returned because we only inserted two embeddings)
 //! let nearest = victor
 //!     .search("Hawaiian pizza", vec!["Pizza Toppings"], 10)
 //!     .await
+//!     .unwrap()
 //!     .first()
 //!     .unwrap()
 //!     .content
@@ -82,22 +94,105 @@
 //!
 //! // Clear the database
 //! victor.clear_db().await.unwrap();
-//! # })
+//! # });
 //! ```
 //!
 //! See the docs for [`Victor`] for more information.
 
 #![deny(missing_docs)]
 
+mod added;
+#[cfg(feature = "arrow")]
+mod arrow;
+mod batch;
+mod bloom;
+mod builder;
+mod bundle;
+mod cache;
+mod catalog;
+mod checksum;
+pub mod chunking;
+mod chunks;
+mod clusters;
+mod compression;
 mod db;
 mod decomposition;
+mod document;
+mod duplicates;
+mod embedder;
+mod error;
+pub mod eval;
+mod events;
+mod expiry;
 mod filesystem;
+mod filter;
+mod format;
+mod hnsw;
+mod hooks;
+mod importers;
+mod integrity;
+mod journal;
+mod jsonl;
+mod keywords;
+mod kmeans;
+mod lsh;
+mod npy;
+#[cfg(all(feature = "http-embeddings", not(target_arch = "wasm32")))]
+mod openai;
 mod packed_vector;
+mod progress;
+mod quantization;
+mod reranker;
+mod scroll;
+mod search;
+mod settings;
+#[cfg(not(target_arch = "wasm32"))]
+mod shared;
 mod similarity;
+mod stats;
+mod telemetry;
+mod tombstones;
 mod utils;
+mod versions;
 
+/// The record batch type of [`Victor::import_arrow`] and [`Victor::export_arrow`], so it can be used without
+/// depending on the same version of `arrow-array`.
+#[cfg(feature = "arrow")]
+pub use arrow_array::RecordBatch;
+pub use batch::Batch;
+pub use builder::Builder;
+pub use clusters::{Cluster, ClusterConfig};
 #[cfg(not(target_arch = "wasm32"))]
+pub use compression::Compression;
 pub use db::Victor;
+pub use decomposition::{AutoProjection, Projection};
+pub use document::{Deduplication, Document, Metadata, MetadataValue};
+pub use duplicates::DuplicateGroup;
+pub use embedder::Embedder;
+pub use error::Error;
+pub use events::Event;
+pub use filter::{Filter, TagFilter};
+pub use hnsw::HnswConfig;
+pub use hooks::SearchRequest;
+pub use importers::{ImportOptions, VectorStore};
+pub use integrity::{Discarded, IntegrityReport, Problem, RepairReport};
+pub use lsh::LshConfig;
+/// The matrix type of [`Victor::to_matrix`], so it can be used without depending on the same version of `nalgebra`.
+pub use nalgebra::DMatrix;
+#[cfg(all(feature = "http-embeddings", not(target_arch = "wasm32")))]
+pub use openai::OpenAiEmbedder;
+pub use progress::Progress;
+pub use quantization::{BinaryConfig, PqConfig, Storage};
+pub use reranker::Reranker;
+pub use scroll::{ScrollCursor, ScrollPage, ScrolledDocument};
+pub use search::{
+    Aggregation, DocumentMeta, Fusion, GroupBy, Predicate, ScoreBreakdown, Scorer, SearchOptions,
+};
+pub use settings::Settings;
+#[cfg(not(target_arch = "wasm32"))]
+pub use shared::Shared;
+pub use similarity::Metric;
+pub use stats::{ProjectionStats, Stats};
 
 #[cfg(test)]
 mod tests;
@@ -119,14 +214,53 @@ type Victor = crate::db::Victor<filesystem::web::DirectoryHandle>;
 pub mod native {
     use crate::db::Victor;
 
+    /// The directory handle type for the native filesystem, and how durably it writes.
+    pub use crate::filesystem::native::{DirectoryHandle, Durability};
+
     /// A native vector database.
-    pub type Db = Victor<crate::filesystem::native::DirectoryHandle>;
+    pub type Db = Victor<DirectoryHandle>;
+
+    /// A native vector database that can be shared between threads.
+    pub type SharedDb = crate::Shared<DirectoryHandle>;
+}
+
+/// Victor's object store implementation, for databases hosted in S3, GCS, Azure, or any other
+/// [`ObjectStore`](::object_store::ObjectStore).
+///
+/// Enable the `s3`, `gcs` or `azure` feature for the matching store, or `object-store` to bring your own.
+///
+/// ```rust
+/// # tokio_test::block_on(async {
+/// use std::sync::Arc;
+/// use victor_db::object_store::{Db, DirectoryHandle};
+///
+/// // any object store works, e.g. `object_store::aws::AmazonS3Builder::from_env().build()`
+/// let store = Arc::new(object_store::memory::InMemory::new());
+/// let mut victor = Db::new(DirectoryHandle::new(store, "databases/pizza"));
+///
+/// victor
+///     .add_single_embedding("Pepperoni pizza", vec![0.1, 0.2, 0.3], vec!["Pizza Flavors"])
+///     .await
+///     .unwrap();
+/// # })
+/// ```
+#[cfg(all(feature = "object-store", not(target_arch = "wasm32")))]
+pub mod object_store {
+    use crate::db::Victor;
+
+    /// The directory handle type for object stores.
+    pub use crate::filesystem::object_store::DirectoryHandle;
+
+    /// A vector database hosted in an object store.
+    pub type Db = Victor<DirectoryHandle>;
+
+    /// A vector database hosted in an object store that can be shared between threads.
+    pub type SharedDb = crate::Shared<DirectoryHandle>;
 }
 
 /// Victor's in-memory implementation.
 ///
 /// Use this if you want to run victor in-memory (all data is lost when the program exits).
-#[cfg(not(target_arch = "wasm32"))]
 pub mod memory {
     use crate::db::Victor;
 
@@ -135,6 +269,10 @@ pub mod memory {
 
     /// An in-memory vector database.
     pub type Db = Victor<DirectoryHandle>;
+
+    /// An in-memory vector database that can be shared between threads.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub type SharedDb = crate::Shared<DirectoryHandle>;
 }
 
 // Wasm
@@ -159,49 +297,187 @@ extern "C" {
     fn warn(s: &str);
 }
 
+/// The filesystems a [`Db`] can be stored in.
+#[cfg(target_arch = "wasm32")]
+enum Backend {
+    /// The origin private file system, in browsers.
+    Web(crate::db::Victor<filesystem::web::DirectoryHandle>),
+    /// A filesystem implemented in JavaScript, like Node's `fs`.
+    Adapter(crate::db::Victor<filesystem::adapter::DirectoryHandle>),
+    /// Kept in memory, and lost when the page closes.
+    Memory(memory::Db),
+}
+
+/// Run `$body` with `$victor` bound to the database, whatever it's stored in.
+#[cfg(target_arch = "wasm32")]
+macro_rules! with_victor {
+    ($backend:expr, $victor:ident => $body:expr) => {
+        match $backend {
+            Backend::Web($victor) => $body,
+            Backend::Adapter($victor) => $body,
+            Backend::Memory($victor) => $body,
+        }
+    };
+}
+
+/// The tags passed from JavaScript, which must all be strings.
+#[cfg(target_arch = "wasm32")]
+fn tag_strings(tags: Option<Vec<JsValue>>) -> Result<Vec<String>, JsValue> {
+    tags.unwrap_or_default()
+        .into_iter()
+        .map(|tag| {
+            tag.as_string().ok_or_else(|| {
+                JsValue::from(Error::InvalidInput(format!(
+                    "tags must be strings, not {tag:?}"
+                )))
+            })
+        })
+        .collect()
+}
+
 /// A browser-optimized vector database.
 #[cfg(target_arch = "wasm32")]
 #[wasm_bindgen]
 pub struct Db {
-    victor: crate::db::Victor<filesystem::web::DirectoryHandle>,
+    victor: Backend,
 }
 
 #[cfg(target_arch = "wasm32")]
 #[wasm_bindgen]
 impl Db {
     /// Connect to victor.
+    ///
+    /// With a `write_buffer`, up to that many inserted documents are held in memory and written together,
+    /// which is much faster than writing each one. Call `flush` to write them before they're searched for.
     #[wasm_bindgen(constructor)]
-    pub async fn new() -> Self {
+    pub async fn new(write_buffer: Option<u32>) -> Result<Db, JsValue> {
         utils::set_panic_hook();
 
-        let window = web_sys::window().ok_or(JsValue::NULL).unwrap();
+        let window = web_sys::window().ok_or(JsValue::NULL)?;
         let navigator = window.navigator();
         let file_system_directory_handle = FileSystemDirectoryHandle::from(
-            JsFuture::from(navigator.storage().get_directory())
-                .await
-                .unwrap(),
+            JsFuture::from(navigator.storage().get_directory()).await?,
         );
 
-        let victor = Victor::new(file_system_directory_handle);
+        let victor = Victor::new(file_system_directory_handle)
+            .with_write_buffer(write_buffer.unwrap_or(0) as usize);
+
+        Ok(Self {
+            victor: Backend::Web(victor),
+        })
+    }
+
+    /// Connect to a database stored by a `StorageAdapter`, for runtimes without the origin private file system,
+    /// like Node:
+    ///
+    /// ```js
+    /// import { mkdir, open, readdir, rm, stat } from "node:fs/promises";
+    /// import path from "node:path";
+    ///
+    /// function nodeStorage(dir) {
+    ///   const file = (name) => path.join(dir, name);
+    ///   return {
+    ///     exists: (name) => stat(file(name)).then(() => true, () => false),
+    ///     size: async (name) => (await stat(file(name))).size,
+    ///     async read(name, offset, length) {
+    ///       const handle = await open(file(name));
+    ///       try {
+    ///         const { size } = await handle.stat();
+    ///         const data = new Uint8Array(Math.max(0, Math.min(length, size - offset)));
+    ///         await handle.read(data, 0, data.length, offset);
+    ///         return data;
+    ///       } finally {
+    ///         await handle.close();
+    ///       }
+    ///     },
+    ///     async write(name, offset, data) {
+    ///       const handle = await open(file(name), "r+");
+    ///       try {
+    ///         await handle.write(data, 0, data.length, offset);
+    ///       } finally {
+    ///         await handle.close();
+    ///       }
+    ///     },
+    ///     async truncate(name, size) {
+    ///       await mkdir(dir, { recursive: true });
+    ///       const handle = await open(file(name), "a");
+    ///       try {
+    ///         await handle.truncate(size);
+    ///       } finally {
+    ///         await handle.close();
+    ///       }
+    ///     },
+    ///     remove: (name) => rm(file(name), { force: true }),
+    ///     list: () =>
+    ///       readdir(dir, { withFileTypes: true }).then(
+    ///         (entries) => entries.filter((entry) => entry.isFile()).map((entry) => entry.name),
+    ///         () => [],
+    ///       ),
+    ///   };
+    /// }
+    ///
+    /// const db = await Db.withStorage(nodeStorage("./victor_data"));
+    /// ```
+    #[wasm_bindgen(js_name = withStorage)]
+    pub async fn with_storage(
+        storage: filesystem::adapter::StorageAdapter,
+        write_buffer: Option<u32>,
+    ) -> Result<Db, JsValue> {
+        utils::set_panic_hook();
+
+        let victor = crate::db::Victor::<filesystem::adapter::DirectoryHandle>::new(storage)
+            .with_write_buffer(write_buffer.unwrap_or(0) as usize);
+
+        Ok(Self {
+            victor: Backend::Adapter(victor),
+        })
+    }
+
+    /// Create a database that's only kept in memory, so it doesn't need the origin private file system or any
+    /// permissions, and everything in it is lost when the page closes. Handy for demos and tests.
+    #[wasm_bindgen(js_name = inMemory)]
+    pub fn in_memory(write_buffer: Option<u32>) -> Db {
+        utils::set_panic_hook();
+
+        let victor = memory::Db::new(memory::DirectoryHandle::default())
+            .with_write_buffer(write_buffer.unwrap_or(0) as usize);
 
-        Self { victor }
+        Self {
+            victor: Backend::Memory(victor),
+        }
     }
 
     /// Add a document to the database.
-    pub async fn insert(&mut self, content: &str, embedding: &[f64], tags: Option<Vec<JsValue>>) {
+    ///
+    /// If an `id` is given, it is returned with search results, and inserting the same `id` again updates the
+    /// existing document instead of adding a duplicate.
+    pub async fn insert(
+        &mut self,
+        content: &str,
+        embedding: &[f64],
+        tags: Option<Vec<JsValue>>,
+        id: Option<String>,
+    ) -> Result<(), JsValue> {
         let embedding = embedding.iter().map(|x| *x as f32).collect::<Vec<_>>();
 
-        let tags = tags
-            .map(|tags| {
-                tags.into_iter()
-                    .map(|x| x.as_string().unwrap())
-                    .collect::<Vec<_>>()
-            })
-            .unwrap_or(vec![]);
+        let tags = tag_strings(tags)?;
+
+        with_victor!(&mut self.victor, victor => match id {
+            Some(id) => {
+                victor
+                    .add_embeddings_with_ids(vec![(id, content, embedding)], tags)
+                    .await?
+            }
+            None => victor.add_single_embedding(content, embedding, tags).await?,
+        });
 
-        self.victor
-            .add_single_embedding(content, embedding, tags)
-            .await;
+        Ok(())
+    }
+
+    /// Write every buffered document to the database.
+    pub async fn flush(&mut self) -> Result<(), JsValue> {
+        with_victor!(&mut self.victor, victor => victor.flush().await?);
+        Ok(())
     }
 
     /// Search the database for the nearest neighbors to a given embedding.
@@ -210,30 +486,78 @@ impl Db {
         embedding: &[f64],
         tags: Option<Vec<JsValue>>,
         top_n: Option<f64>,
-    ) -> JsValue {
+    ) -> Result<JsValue, JsValue> {
         let embedding = embedding.iter().map(|x| *x as f32).collect::<Vec<_>>();
 
-        let tags = tags
-            .map(|tags| {
-                tags.into_iter()
-                    .map(|x| x.as_string().unwrap())
-                    .collect::<Vec<_>>()
-            })
-            .unwrap_or(vec![]);
+        let tags = tag_strings(tags)?;
 
-        let nearest_neighbors = self
-            .victor
-            .search_embedding(embedding, tags, top_n.unwrap_or(10.0) as u32)
-            .await;
+        let nearest_neighbors = with_victor!(&self.victor, victor => {
+            victor
+                .search_embedding(embedding, tags, top_n.unwrap_or(10.0) as u32)
+                .await?
+        });
+
+        Ok(serde_wasm_bindgen::to_value(&nearest_neighbors)?)
+    }
+
+    /// Search like `search`, calling `on_progress` with the best matches found so far each time another part of
+    /// the database has been scanned, so they can be shown before the search finishes.
+    #[wasm_bindgen(js_name = searchWithProgress)]
+    pub async fn search_with_progress(
+        &mut self,
+        embedding: &[f64],
+        on_progress: js_sys::Function,
+        tags: Option<Vec<JsValue>>,
+        top_n: Option<f64>,
+    ) -> Result<JsValue, JsValue> {
+        let embedding = embedding.iter().map(|x| *x as f32).collect::<Vec<_>>();
+
+        let tags = tag_strings(tags)?;
+
+        // the final results are returned either way, so a failing callback is ignored
+        let on_progress = |provisional: &[crate::db::NearestNeighborsResult]| {
+            if let Ok(provisional) = serde_wasm_bindgen::to_value(provisional) {
+                let _ = on_progress.call1(&JsValue::NULL, &provisional);
+            }
+        };
+        let nearest_neighbors = with_victor!(&self.victor, victor => {
+            victor
+                .search_embedding_with_progress(
+                    embedding,
+                    tags,
+                    top_n.unwrap_or(10.0) as u32,
+                    &SearchOptions::default(),
+                    on_progress,
+                )
+                .await?
+        });
+
+        Ok(serde_wasm_bindgen::to_value(&nearest_neighbors)?)
+    }
+
+    /// List every tag used in the database, sorted alphabetically.
+    pub async fn tags(&self) -> Result<Vec<JsValue>, JsValue> {
+        let tags = with_victor!(&self.victor, victor => victor.tags().await?);
+        Ok(tags
+            .into_iter()
+            .map(|tag| JsValue::from_str(&tag))
+            .collect())
+    }
 
-        serde_wasm_bindgen::to_value(&nearest_neighbors).unwrap()
+    /// Replace the database with a bundle built by `Victor::export_bundle` on native,
+    /// e.g. one built on a server and fetched by the page.
+    #[wasm_bindgen(js_name = loadBundle)]
+    pub async fn load_bundle(&mut self, bundle: &[u8]) -> Result<(), JsValue> {
+        with_victor!(&mut self.victor, victor => victor.load_bundle(bundle).await?);
+        Ok(())
     }
 
     /// Clear the database, permanently removing all data.
     pub async fn clear(&mut self) {
         utils::set_panic_hook();
 
-        let result = self.victor.clear_db().await; // ignore the error if there is one
+        // ignore the error if there is one
+        let result = with_victor!(&mut self.victor, victor => victor.clear_db().await);
         if !result.is_ok() {
             console_warn!("Failed to clear victor data: {:?}", result);
         }
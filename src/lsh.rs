@@ -0,0 +1,318 @@
+//! Locality-sensitive hashing (LSH) with random hyperplanes, a cheap approximate index.
+//!
+//! Every embedding gets one signature per table, where each bit records which side of one of the table's random
+//! hyperplanes the embedding falls on. Embeddings pointing in similar directions are likely to share a signature
+//! in at least one table, so searches only need to compare against the embeddings that do.
+//!
+//! Like HNSW graphs, each db file gets its own set of signatures, stored next to it
+//! (see [`filename_for_segment`]), and nodes are identified by their position in the db file.
+//! New signatures are appended to the file as [`Change`]s, which are replayed when it's read.
+
+use std::collections::BTreeSet;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    db::{append_bin, read_optional, write_bin},
+    error::Error,
+    filesystem::{DirectoryHandle, FileHandle, GetFileHandleOptions},
+};
+
+const CONFIG_FILENAME: &str = "lsh.bin";
+
+/// Parameters for building an LSH index, see [`crate::Victor::build_lsh_index`].
+///
+/// ```rust
+/// use victor_db::LshConfig;
+///
+/// // fewer bits per signature makes buckets bigger, trading speed for recall
+/// let config = LshConfig {
+///     bits: 8,
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LshConfig {
+    /// The number of independent hash tables.
+    /// More tables improve recall, at the cost of storage and a larger candidate set.
+    pub tables: usize,
+    /// The number of hyperplanes (bits) in each table's signature, at most 64.
+    /// More bits make each bucket smaller, so searches are faster but less accurate.
+    pub bits: usize,
+    /// Db files with fewer embeddings than this are always scanned exhaustively,
+    /// since comparing against everything is cheap and exact.
+    pub exact_below: usize,
+}
+
+impl Default for LshConfig {
+    fn default() -> Self {
+        Self {
+            tables: 8,
+            bits: 12,
+            exact_below: 1000,
+        }
+    }
+}
+
+/// The name of the file holding the signatures for the db file `segment`.
+pub(crate) fn filename_for_segment(segment: &str) -> String {
+    format!("{}.lsh", segment.trim_end_matches(".bin"))
+}
+
+/// The new signature of a node (which may be new), as appended to the signatures' file.
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct Change {
+    node: u32,
+    signature: Vec<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct Lsh {
+    config: LshConfig,
+    /// `tables * bits` hyperplanes (by their normal vectors), table by table.
+    hyperplanes: Vec<Vec<f32>>,
+    /// `signatures[node][table]` is the signature of `node` in `table`.
+    signatures: Vec<Vec<u64>>,
+    /// The nodes whose signatures changed since they were read.
+    #[serde(skip)]
+    changed: BTreeSet<usize>,
+    /// How many changes follow the signatures in their file.
+    #[serde(skip)]
+    appended: usize,
+}
+
+impl Lsh {
+    pub(crate) fn new(config: LshConfig, dimension: usize) -> Self {
+        let config = LshConfig {
+            tables: config.tables.max(1),
+            bits: config.bits.clamp(1, 64),
+            ..config
+        };
+
+        // the hyperplanes only need to be random, not unpredictable, so use a fixed seed
+        let mut rng = SplitMix64(0x5eed);
+        let hyperplanes = (0..config.tables * config.bits)
+            .map(|_| (0..dimension).map(|_| rng.next_gaussian()).collect())
+            .collect();
+
+        Self {
+            config,
+            hyperplanes,
+            signatures: Vec::new(),
+            changed: BTreeSet::new(),
+            appended: 0,
+        }
+    }
+
+    /// Read signatures from their file, replaying the changes appended to it.
+    pub(crate) fn read(mut bytes: &[u8]) -> bincode::Result<Self> {
+        let mut signatures: Self = bincode::deserialize_from(&mut bytes)?;
+        while !bytes.is_empty() {
+            let change: Change = bincode::deserialize_from(&mut bytes)?;
+            signatures.set(change.node as usize, change.signature);
+            signatures.appended += 1;
+        }
+        Ok(signatures)
+    }
+
+    /// The changes to append to the signatures' file since they were read,
+    /// or `None` if so many have piled up that it should be rewritten whole instead.
+    pub(crate) fn take_changes(&mut self) -> Option<Vec<Change>> {
+        let changes = std::mem::take(&mut self.changed)
+            .into_iter()
+            .map(|node| Change {
+                node: node as u32,
+                signature: self.signatures[node].clone(),
+            })
+            .collect::<Vec<_>>();
+        self.appended += changes.len();
+        (self.appended <= self.signatures.len()).then_some(changes)
+    }
+
+    /// The number of nodes with a signature.
+    pub(crate) fn len(&self) -> usize {
+        self.signatures.len()
+    }
+
+    /// The dimension of the embeddings this index was created for.
+    pub(crate) fn dimension(&self) -> usize {
+        self.hyperplanes.first().map_or(0, Vec::len)
+    }
+
+    /// Compute the signature of `vector`, which is the embedding at position `node`.
+    ///
+    /// New nodes must be inserted in order. If `node` already has a signature, it is replaced.
+    pub(crate) fn insert(&mut self, node: usize, vector: &[f32]) {
+        let signature = self.signature(vector);
+        self.set(node, signature);
+        self.changed.insert(node);
+    }
+
+    fn set(&mut self, node: usize, signature: Vec<u64>) {
+        if node < self.signatures.len() {
+            self.signatures[node] = signature;
+        } else {
+            debug_assert_eq!(
+                node,
+                self.signatures.len(),
+                "nodes must be inserted in order"
+            );
+            self.signatures.push(signature);
+        }
+    }
+
+    /// Every node that shares a signature with `query` in at least one table, in order.
+    pub(crate) fn candidates(&self, query: &[f32]) -> Vec<usize> {
+        let query = self.signature(query);
+        self.signatures
+            .iter()
+            .enumerate()
+            .filter(|(_, signature)| signature.iter().zip(&query).any(|(a, b)| a == b))
+            .map(|(node, _)| node)
+            .collect()
+    }
+
+    fn signature(&self, vector: &[f32]) -> Vec<u64> {
+        self.hyperplanes
+            .chunks(self.config.bits)
+            .map(|table| {
+                table
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, hyperplane)| {
+                        let dot: f32 = hyperplane.iter().zip(vector).map(|(a, b)| a * b).sum();
+                        dot >= 0.0
+                    })
+                    .fold(0, |signature, (bit, _)| signature | (1 << bit))
+            })
+            .collect()
+    }
+}
+
+/// A tiny deterministic PRNG, so the hyperplanes don't depend on a random number generator crate.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in (0, 1].
+    fn next_f64(&mut self) -> f64 {
+        ((self.next_u64() >> 11) as f64 + 1.0) / (1u64 << 53) as f64
+    }
+
+    /// Standard normal, using the Box-Muller transform.
+    fn next_gaussian(&mut self) -> f32 {
+        let (u1, u2) = (self.next_f64(), self.next_f64());
+        ((-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()) as f32
+    }
+}
+
+/// The settings signatures are made with, or `None` if the database doesn't have an LSH index.
+pub(crate) async fn read_config<D: DirectoryHandle>(root: &D) -> Result<Option<LshConfig>, Error> {
+    read_optional(root, CONFIG_FILENAME).await
+}
+
+pub(crate) async fn write_config<D: DirectoryHandle>(
+    root: &D,
+    config: &LshConfig,
+) -> Result<(), Error> {
+    write_bin(root, CONFIG_FILENAME, config).await
+}
+
+/// Read the signatures for the db file `segment`, if it has any.
+pub(crate) async fn read_signatures<D: DirectoryHandle>(
+    root: &D,
+    segment: &str,
+) -> Result<Option<Lsh>, Error> {
+    let filename = filename_for_segment(segment);
+    let lsh_file_handle = root
+        .get_file_handle_with_options(&filename, &GetFileHandleOptions { create: true })
+        .await?;
+
+    let signatures = lsh_file_handle.read().await?;
+
+    if signatures.is_empty() {
+        Ok(None)
+    } else {
+        Lsh::read(&signatures)
+            .map(Some)
+            .map_err(|e| Error::Corrupted {
+                file: filename,
+                reason: e.to_string(),
+            })
+    }
+}
+
+pub(crate) async fn write_signatures<D: DirectoryHandle>(
+    root: &D,
+    segment: &str,
+    signatures: &Lsh,
+) -> Result<(), Error> {
+    write_bin(root, &filename_for_segment(segment), signatures).await
+}
+
+/// Append `changes` to the signatures for the db file `segment`, rather than rewriting them.
+pub(crate) async fn append_changes<D: DirectoryHandle>(
+    root: &D,
+    segment: &str,
+    changes: &[Change],
+) -> Result<(), Error> {
+    append_bin(root, &filename_for_segment(segment), changes).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn similar_vectors_collide() {
+        let mut lsh = Lsh::new(LshConfig::default(), 3);
+        lsh.insert(0, &[1.0, 2.0, 3.0]);
+        lsh.insert(1, &[-1.0, -2.0, -3.0]);
+        lsh.insert(2, &[1.1, 2.0, 2.9]);
+
+        assert_eq!(lsh.len(), 3);
+        assert_eq!(lsh.dimension(), 3);
+
+        let candidates = lsh.candidates(&[1.0, 2.0, 3.0]);
+        assert!(candidates.contains(&0));
+        assert!(candidates.contains(&2));
+        // exactly opposite vectors never share a bit
+        assert!(!candidates.contains(&1));
+    }
+
+    #[test]
+    fn replaces_signatures() {
+        let mut lsh = Lsh::new(LshConfig::default(), 3);
+        lsh.insert(0, &[1.0, 2.0, 3.0]);
+        lsh.insert(0, &[-1.0, -2.0, -3.0]);
+
+        assert_eq!(lsh.len(), 1);
+        assert_eq!(lsh.candidates(&[-1.0, -2.0, -3.0]), vec![0]);
+    }
+
+    #[test]
+    fn replays_appended_changes() {
+        let mut lsh = Lsh::new(LshConfig::default(), 3);
+        lsh.insert(0, &[1.0, 2.0, 3.0]);
+        lsh.insert(1, &[-1.0, -2.0, -3.0]);
+        let mut file = bincode::serialize(&lsh).unwrap();
+
+        let mut lsh = Lsh::read(&file).unwrap();
+        lsh.insert(2, &[1.1, 2.0, 2.9]);
+        lsh.insert(0, &[-1.1, -2.0, -2.9]);
+        for change in lsh.take_changes().unwrap() {
+            file.extend(bincode::serialize(&change).unwrap());
+        }
+
+        let read = Lsh::read(&file).unwrap();
+        assert_eq!(read.signatures, lsh.signatures);
+        assert_eq!(read.candidates(&[1.0, 2.0, 3.0]), vec![2]);
+    }
+}
@@ -0,0 +1,139 @@
+//! A reader for NumPy's `.npy` format, so precomputed embeddings can be imported with
+//! [`crate::Victor::import_npy`].
+//!
+//! Only what embeddings need is supported: 2-dimensional, C-ordered, little-endian `f4` or `f8` arrays.
+//! See <https://numpy.org/doc/stable/reference/generated/numpy.lib.format.html> for the format.
+
+use crate::error::Error;
+
+const MAGIC: &[u8] = b"\x93NUMPY";
+
+/// A row-major matrix of embeddings, one per row.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Matrix {
+    pub values: Vec<f32>,
+    pub rows: usize,
+    pub dimension: usize,
+}
+
+pub(crate) fn read(bytes: &[u8]) -> Result<Matrix, Error> {
+    let invalid = |reason: &str| Error::InvalidInput(format!("invalid .npy file: {reason}"));
+
+    let rest = bytes
+        .strip_prefix(MAGIC)
+        .ok_or_else(|| invalid("bad magic"))?;
+    let (header_len, rest) = match rest {
+        [1, _, a, b, rest @ ..] => (u16::from_le_bytes([*a, *b]) as usize, rest),
+        [2 | 3, _, a, b, c, d, rest @ ..] => (u32::from_le_bytes([*a, *b, *c, *d]) as usize, rest),
+        _ => return Err(invalid("unsupported version")),
+    };
+    let header = rest
+        .get(..header_len)
+        .and_then(|header| std::str::from_utf8(header).ok())
+        .ok_or_else(|| invalid("truncated header"))?;
+    let data = &rest[header_len..];
+
+    if field(header, "fortran_order") != Some("False") {
+        return Err(invalid("only C-ordered arrays are supported"));
+    }
+    let value_size = match field(header, "descr") {
+        Some("'<f4'") => 4,
+        Some("'<f8'") => 8,
+        _ => return Err(invalid("only little-endian f4 and f8 arrays are supported")),
+    };
+    let shape = field(header, "shape")
+        .and_then(|shape| shape.strip_prefix('('))
+        .and_then(|shape| shape.strip_suffix(')'))
+        .ok_or_else(|| invalid("missing shape"))?
+        .split(',')
+        .map(str::trim)
+        .filter(|dimension| !dimension.is_empty())
+        .map(|dimension| dimension.parse::<usize>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| invalid("bad shape"))?;
+    let [rows, dimension] = shape[..] else {
+        return Err(invalid("only 2-dimensional arrays are supported"));
+    };
+
+    if data.len() != rows * dimension * value_size {
+        return Err(invalid("the data doesn't match the shape"));
+    }
+    let values = if value_size == 4 {
+        data.chunks_exact(4)
+            .map(|value| f32::from_le_bytes(value.try_into().unwrap()))
+            .collect()
+    } else {
+        data.chunks_exact(8)
+            .map(|value| f64::from_le_bytes(value.try_into().unwrap()) as f32)
+            .collect()
+    };
+
+    Ok(Matrix {
+        values,
+        rows,
+        dimension,
+    })
+}
+
+/// The value of `key` in the header, a Python dict literal like `{'descr': '<f4', 'shape': (2, 3), }`.
+fn field<'a>(header: &'a str, key: &str) -> Option<&'a str> {
+    let start = header.find(&format!("'{key}':"))? + key.len() + 3;
+    let value = header[start..].trim_start();
+    // shapes contain commas, so they end at their closing parenthesis instead
+    let end = if value.starts_with('(') {
+        value.find(')')? + 1
+    } else {
+        value.find([',', '}'])?
+    };
+    Some(value[..end].trim())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn npy(descr: &str, shape: &str, data: &[u8]) -> Vec<u8> {
+        let header =
+            format!("{{'descr': '{descr}', 'fortran_order': False, 'shape': {shape}, }}\n");
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend([1, 0]);
+        bytes.extend((header.len() as u16).to_le_bytes());
+        bytes.extend(header.as_bytes());
+        bytes.extend(data);
+        bytes
+    }
+
+    #[test]
+    fn reads_matrices() {
+        let data = [1.0f32, 2.0, 3.0, 4.0, 5.0, 6.0]
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect::<Vec<_>>();
+        let expected = Matrix {
+            values: vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0],
+            rows: 2,
+            dimension: 3,
+        };
+        assert_eq!(read(&npy("<f4", "(2, 3)", &data)).unwrap(), expected);
+
+        let data = [1.0f64, 2.0, 3.0, 4.0, 5.0, 6.0]
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect::<Vec<_>>();
+        assert_eq!(read(&npy("<f8", "(2, 3)", &data)).unwrap(), expected);
+
+        assert!(matches!(
+            read(&npy("<f4", "(6,)", &data)),
+            Err(Error::InvalidInput(_))
+        ));
+        assert!(matches!(
+            read(&npy("<i4", "(2, 3)", &data)),
+            Err(Error::InvalidInput(_))
+        ));
+        assert!(matches!(
+            read(&npy("<f4", "(2, 2)", &data)),
+            Err(Error::InvalidInput(_))
+        ));
+        assert!(matches!(read(b"not npy"), Err(Error::InvalidInput(_))));
+    }
+}
@@ -0,0 +1,168 @@
+//! An [`Embedder`] for OpenAI-compatible `/embeddings` APIs.
+
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::{embedder::Embedder, error::Error};
+
+/// Failed requests are retried after this long, doubling with each attempt.
+const RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// Generates embeddings with an OpenAI-compatible `/embeddings` endpoint,
+/// like OpenAI's own or a self-hosted server, see [`crate::Victor::with_embedder`].
+///
+/// ```rust
+/// use victor_db::OpenAiEmbedder;
+///
+/// let embedder = OpenAiEmbedder::new("text-embedding-3-small")
+///     .with_base_url("http://localhost:8080/v1")
+///     .with_api_key("sk-...")
+///     .with_batch_size(64);
+/// ```
+#[derive(Debug, Clone)]
+pub struct OpenAiEmbedder {
+    client: reqwest::Client,
+    base_url: String,
+    model: String,
+    api_key: Option<String>,
+    batch_size: usize,
+    max_retries: usize,
+}
+
+#[derive(Serialize)]
+struct EmbeddingsRequest<'a> {
+    model: &'a str,
+    input: &'a [String],
+}
+
+#[derive(Deserialize)]
+struct EmbeddingsResponse {
+    data: Vec<EmbeddingData>,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingData {
+    embedding: Vec<f32>,
+    index: usize,
+}
+
+impl OpenAiEmbedder {
+    /// Use `model` from OpenAI's API, with the API key in the `OPENAI_API_KEY` environment variable (if it's set).
+    pub fn new(model: impl Into<String>) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            base_url: "https://api.openai.com/v1".to_string(),
+            model: model.into(),
+            api_key: std::env::var("OPENAI_API_KEY").ok(),
+            batch_size: 256,
+            max_retries: 3,
+        }
+    }
+
+    /// Use another OpenAI-compatible API, whose embeddings endpoint is `{base_url}/embeddings`.
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into();
+        self
+    }
+
+    /// Authenticate with `api_key`, sent as a bearer token.
+    pub fn with_api_key(mut self, api_key: impl Into<String>) -> Self {
+        self.api_key = Some(api_key.into());
+        self
+    }
+
+    /// Embed at most `batch_size` texts per request. Defaults to 256.
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Retry requests that fail because of rate limits, server errors, or connection problems up to `max_retries`
+    /// times, waiting longer after each attempt. Defaults to 3.
+    pub fn with_max_retries(mut self, max_retries: usize) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, Error> {
+        let url = format!("{}/embeddings", self.base_url.trim_end_matches('/'));
+        let mut attempt = 0;
+        loop {
+            let mut request = self.client.post(&url).json(&EmbeddingsRequest {
+                model: &self.model,
+                input: texts,
+            });
+            if let Some(api_key) = &self.api_key {
+                request = request.bearer_auth(api_key);
+            }
+
+            match request.send().await {
+                Ok(response) if response.status().is_success() => {
+                    let response: EmbeddingsResponse = response
+                        .json()
+                        .await
+                        .map_err(|error| Error::Embedding(error.to_string()))?;
+                    return into_embeddings(response, texts.len());
+                }
+                // rate limits and server errors are worth retrying, but other errors will just happen again
+                Ok(response) => {
+                    let status = response.status();
+                    let retryable = status.is_server_error()
+                        || status == reqwest::StatusCode::TOO_MANY_REQUESTS;
+                    if !retryable || attempt == self.max_retries {
+                        let body = response.text().await.unwrap_or_default();
+                        return Err(Error::Embedding(format!("{status}: {body}")));
+                    }
+                }
+                Err(error) => {
+                    let retryable = error.is_connect() || error.is_timeout();
+                    if !retryable || attempt == self.max_retries {
+                        return Err(Error::Embedding(error.to_string()));
+                    }
+                }
+            }
+
+            tokio::time::sleep(RETRY_DELAY * 2u32.pow(attempt as u32)).await;
+            attempt += 1;
+        }
+    }
+}
+
+/// The embeddings in a response, in the order of the texts they're for.
+fn into_embeddings(
+    mut response: EmbeddingsResponse,
+    expected: usize,
+) -> Result<Vec<Vec<f32>>, Error> {
+    response.data.sort_by_key(|data| data.index);
+    if response.data.len() != expected
+        || response
+            .data
+            .iter()
+            .enumerate()
+            .any(|(index, data)| data.index != index)
+    {
+        return Err(Error::Embedding(format!(
+            "expected {expected} embeddings, but got {}",
+            response.data.len()
+        )));
+    }
+
+    Ok(response
+        .data
+        .into_iter()
+        .map(|data| data.embedding)
+        .collect())
+}
+
+#[async_trait]
+impl Embedder for OpenAiEmbedder {
+    async fn embed(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>, Error> {
+        let mut embeddings = Vec::with_capacity(texts.len());
+        for batch in texts.chunks(self.batch_size) {
+            embeddings.extend(self.embed_batch(batch).await?);
+        }
+        Ok(embeddings)
+    }
+}
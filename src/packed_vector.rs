@@ -8,7 +8,7 @@ pub(crate) struct PackedVector {
 }
 
 impl PackedVector {
-    fn pack(vector: &[f32]) -> Self {
+    pub(crate) fn pack(vector: &[f32]) -> Self {
         let min = vector.iter().cloned().fold(f32::INFINITY, f32::min);
         let max = vector.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
 
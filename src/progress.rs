@@ -0,0 +1,45 @@
+//! Reporting how far along adding many documents is, see [`crate::Victor::add_with_progress`].
+
+use serde::Serialize;
+
+/// How far along adding documents is, reported as each batch is embedded and then written.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Progress {
+    /// `done` of `total` documents have had their embeddings generated.
+    Embedding {
+        /// How many documents have been embedded so far.
+        done: usize,
+        /// How many documents there are to embed.
+        total: usize,
+    },
+    /// `done` of `total` documents have been written to the database.
+    Writing {
+        /// How many documents have been written so far.
+        done: usize,
+        /// How many documents there are to write.
+        total: usize,
+    },
+}
+
+impl Progress {
+    /// How much of adding the documents is done, between 0 and 1, counting embedding and writing them as half
+    /// each. Embedding usually takes much longer, but it depends on the embedder.
+    ///
+    /// ```rust
+    /// use victor_db::Progress;
+    ///
+    /// assert_eq!(Progress::Embedding { done: 50, total: 100 }.fraction(), 0.25);
+    /// assert_eq!(Progress::Writing { done: 100, total: 100 }.fraction(), 1.0);
+    /// ```
+    pub fn fraction(&self) -> f32 {
+        let (stage, done, total) = match *self {
+            Progress::Embedding { done, total } => (0.0, done, total),
+            Progress::Writing { done, total } => (1.0, done, total),
+        };
+        let done = match total {
+            0 => 1.0,
+            total => done as f32 / total as f32,
+        };
+        (stage + done) / 2.0
+    }
+}
@@ -0,0 +1,648 @@
+//! How embeddings are encoded in db files.
+//!
+//! By default each vector is packed to 8 bits per dimension (see [`crate::packed_vector`]),
+//! unless the database was created to store full precision vectors (see [`Storage`]).
+//! With product quantization, each vector is split into subspaces, and each subspace is stored as the index of its
+//! nearest centroid in a codebook trained on the database, so a vector takes only one byte per subspace.
+//! With binary quantization, only the sign of each dimension is kept, so a vector takes one bit per dimension
+//! and can be compared with a popcount.
+
+use std::ops::Range;
+
+use half::f16;
+use serde::{Deserialize, Serialize, Serializer};
+use uuid::Uuid;
+
+use crate::{
+    db::{deserialize, Embedding},
+    error::Error,
+    kmeans,
+    packed_vector::PackedVector,
+    similarity::Metric,
+};
+
+/// How vectors are stored, unless they're quantized further. See [`crate::Victor::with_storage`].
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Storage {
+    /// 32 bits per dimension, so similarity scores are exact.
+    Full,
+    /// 16 bit (half precision) floats: half the size, and almost as accurate.
+    Half,
+    /// 8 bits per dimension, a quarter of the size at the cost of some precision.
+    #[default]
+    Packed,
+}
+
+/// Parameters for product quantization, see [`crate::Victor::train_product_quantizer`].
+///
+/// ```rust
+/// use victor_db::PqConfig;
+///
+/// // 32 bytes per embedding, for more accurate scores
+/// let config = PqConfig {
+///     subspaces: 32,
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PqConfig {
+    /// The number of subspaces each vector is split into, which is also the number of bytes it's stored in.
+    /// More subspaces are more accurate, but take more space.
+    pub subspaces: usize,
+    /// The number of centroids in each subspace's codebook, at most 256.
+    pub centroids: usize,
+    /// The maximum number of embeddings used to train the codebooks.
+    pub sample_size: usize,
+    /// The maximum number of k-means iterations used to train each codebook.
+    pub iterations: usize,
+}
+
+impl Default for PqConfig {
+    fn default() -> Self {
+        Self {
+            subspaces: 8,
+            centroids: 256,
+            sample_size: 10_000,
+            iterations: 20,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct ProductQuantizer {
+    dimension: usize,
+    /// `codebooks[subspace][code]` is a centroid for the dimensions of `subspace`.
+    codebooks: Vec<Vec<Vec<f32>>>,
+}
+
+/// Split `dimension` dimensions into `subspaces` contiguous ranges, as evenly as possible.
+fn subspace_ranges(dimension: usize, subspaces: usize) -> Vec<Range<usize>> {
+    (0..subspaces)
+        .map(|i| i * dimension / subspaces..(i + 1) * dimension / subspaces)
+        .collect()
+}
+
+impl ProductQuantizer {
+    /// Train codebooks on (a sample of) `vectors`.
+    pub(crate) fn train(vectors: &[Vec<f32>], config: PqConfig) -> Result<Self, Error> {
+        let Some(dimension) = vectors.first().map(Vec::len) else {
+            return Err(Error::InvalidInput(
+                "can't train a product quantizer without any embeddings".to_string(),
+            ));
+        };
+        if let Some(vector) = vectors.iter().find(|vector| vector.len() != dimension) {
+            return Err(Error::DimensionMismatch {
+                expected: dimension,
+                found: vector.len(),
+            });
+        }
+
+        let sample_size = config.sample_size.max(1);
+        let step = vectors.len().div_ceil(sample_size).max(1);
+        let sample = vectors.iter().step_by(step).collect::<Vec<_>>();
+
+        let subspaces = config.subspaces.clamp(1, dimension.max(1));
+        let centroids = config.centroids.clamp(1, 256);
+        let codebooks = subspace_ranges(dimension, subspaces)
+            .into_iter()
+            .map(|range| {
+                let sub_vectors = sample
+                    .iter()
+                    .map(|vector| vector[range.clone()].to_vec())
+                    .collect::<Vec<_>>();
+                kmeans::kmeans(&sub_vectors, centroids, config.iterations)
+            })
+            .collect();
+
+        Ok(Self {
+            dimension,
+            codebooks,
+        })
+    }
+
+    fn ranges(&self) -> Vec<Range<usize>> {
+        subspace_ranges(self.dimension, self.codebooks.len())
+    }
+
+    pub(crate) fn encode(&self, vector: &[f32]) -> Result<Vec<u8>, Error> {
+        if vector.len() != self.dimension {
+            return Err(Error::DimensionMismatch {
+                expected: self.dimension,
+                found: vector.len(),
+            });
+        }
+
+        Ok(self
+            .ranges()
+            .into_iter()
+            .zip(&self.codebooks)
+            .map(|(range, codebook)| kmeans::nearest(codebook, &vector[range]) as u8)
+            .collect())
+    }
+
+    pub(crate) fn decode(&self, codes: &[u8]) -> Vec<f32> {
+        self.codebooks
+            .iter()
+            .zip(codes)
+            .flat_map(|(codebook, &code)| codebook[code as usize].iter().copied())
+            .collect()
+    }
+
+    /// Precompute the comparisons between `query` and every centroid, so codes can be scored without
+    /// reconstructing their vectors (asymmetric distance computation).
+    pub(crate) fn distance_table(&self, query: &[f32]) -> Result<DistanceTable, Error> {
+        if query.len() != self.dimension {
+            return Err(Error::DimensionMismatch {
+                expected: self.dimension,
+                found: query.len(),
+            });
+        }
+
+        let mut table = DistanceTable {
+            dot: Vec::with_capacity(self.codebooks.len()),
+            norm: Vec::with_capacity(self.codebooks.len()),
+            distance: Vec::with_capacity(self.codebooks.len()),
+            manhattan: Vec::with_capacity(self.codebooks.len()),
+            query_norm: query.iter().map(|x| x * x).sum::<f32>().sqrt(),
+        };
+        for (range, codebook) in self.ranges().into_iter().zip(&self.codebooks) {
+            let query = &query[range];
+            table.dot.push(
+                codebook
+                    .iter()
+                    .map(|centroid| centroid.iter().zip(query).map(|(a, b)| a * b).sum())
+                    .collect(),
+            );
+            table.norm.push(
+                codebook
+                    .iter()
+                    .map(|centroid| centroid.iter().map(|x| x * x).sum())
+                    .collect(),
+            );
+            table.distance.push(
+                codebook
+                    .iter()
+                    .map(|centroid| kmeans::squared_distance(centroid, query))
+                    .collect(),
+            );
+            table.manhattan.push(
+                codebook
+                    .iter()
+                    .map(|centroid| centroid.iter().zip(query).map(|(a, b)| (a - b).abs()).sum())
+                    .collect(),
+            );
+        }
+        Ok(table)
+    }
+}
+
+/// A query compared against every centroid of a [`ProductQuantizer`], per subspace.
+pub(crate) struct DistanceTable {
+    dot: Vec<Vec<f32>>,
+    norm: Vec<Vec<f32>>,
+    distance: Vec<Vec<f32>>,
+    manhattan: Vec<Vec<f32>>,
+    query_norm: f32,
+}
+
+impl DistanceTable {
+    fn sum(table: &[Vec<f32>], codes: &[u8]) -> f32 {
+        table
+            .iter()
+            .zip(codes)
+            .map(|(subspace, &code)| subspace[code as usize])
+            .sum()
+    }
+
+    /// The cosine similarity between the query and the vector encoded by `codes`.
+    pub(crate) fn cosine(&self, codes: &[u8]) -> f32 {
+        let dot = Self::sum(&self.dot, codes);
+        let norm = Self::sum(&self.norm, codes).sqrt();
+        dot / (self.query_norm * norm)
+    }
+
+    /// The euclidean distance between the query and the vector encoded by `codes`.
+    pub(crate) fn euclidean(&self, codes: &[u8]) -> f32 {
+        Self::sum(&self.distance, codes).sqrt()
+    }
+
+    /// The similarity between the query and the vector encoded by `codes` under `metric`,
+    /// see [`Metric::similarity`](crate::similarity::Metric).
+    pub(crate) fn similarity(&self, metric: Metric, codes: &[u8]) -> f32 {
+        match metric {
+            Metric::Cosine => self.cosine(codes),
+            Metric::Dot => Self::sum(&self.dot, codes),
+            Metric::Euclidean => -self.euclidean(codes),
+            Metric::Manhattan => -Self::sum(&self.manhattan, codes),
+        }
+    }
+}
+
+/// An embedding stored at full precision.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct FullEmbedding {
+    pub id: Uuid,
+    pub vector: Vec<f32>,
+}
+
+/// A [`FullEmbedding`] that borrows its vector, for encoding.
+#[derive(Serialize)]
+struct FullEmbeddingRef<'a> {
+    id: Uuid,
+    vector: &'a [f32],
+}
+
+/// An embedding stored as half precision floats.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct HalfEmbedding {
+    pub id: Uuid,
+    /// The bits of each [`f16`].
+    pub vector: Vec<u16>,
+}
+
+/// A [`HalfEmbedding`] that converts a borrowed vector as it's encoded.
+#[derive(Serialize)]
+struct HalfEmbeddingRef<'a> {
+    id: Uuid,
+    #[serde(serialize_with = "serialize_half")]
+    vector: &'a [f32],
+}
+
+fn serialize_half<S: Serializer>(vector: &&[f32], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(vector.iter().map(|&value| f16::from_f32(value).to_bits()))
+}
+
+/// An [`Embedding`] with its vector already packed, which encodes the same way.
+#[derive(Serialize)]
+struct PackedEmbedding {
+    id: Uuid,
+    vector: PackedVector,
+}
+
+/// An embedding stored as product quantization codes.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct CodedEmbedding {
+    pub id: Uuid,
+    pub codes: Vec<u8>,
+}
+
+/// Parameters for binary quantization, see [`crate::Victor::enable_binary_quantization`].
+///
+/// ```rust
+/// use victor_db::BinaryConfig;
+///
+/// // store only the bits, for the smallest possible database
+/// let config = BinaryConfig { rerank: false };
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BinaryConfig {
+    /// Also store each vector (packed to 8 bits per dimension), so the best matches by Hamming distance can be
+    /// re-ranked by their exact similarity (see [`crate::SearchOptions::oversample`]).
+    ///
+    /// Without re-ranking, search scores are estimated from the Hamming distance alone.
+    pub rerank: bool,
+}
+
+impl Default for BinaryConfig {
+    fn default() -> Self {
+        Self { rerank: true }
+    }
+}
+
+/// An embedding stored as one bit per dimension.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct BinaryEmbedding {
+    pub id: Uuid,
+    pub dimension: u32,
+    pub bits: Vec<u64>,
+    /// Empty unless the database re-ranks results.
+    #[serde(
+        serialize_with = "crate::packed_vector::PackedVector::serialize_embedding",
+        deserialize_with = "crate::packed_vector::PackedVector::deserialize_embedding"
+    )]
+    pub vector: Vec<f32>,
+}
+
+/// A [`BinaryEmbedding`] with its vector already packed, which encodes the same way.
+#[derive(Serialize)]
+struct PackedBinaryEmbedding {
+    id: Uuid,
+    dimension: u32,
+    bits: Vec<u64>,
+    vector: PackedVector,
+}
+
+/// Keep only the sign of each dimension of `vector`, 64 dimensions per word.
+pub(crate) fn binarize(vector: &[f32]) -> Vec<u64> {
+    vector
+        .chunks(64)
+        .map(|chunk| {
+            chunk
+                .iter()
+                .enumerate()
+                .filter(|(_, &value)| value > 0.0)
+                .fold(0, |bits, (bit, _)| bits | (1 << bit))
+        })
+        .collect()
+}
+
+/// The number of dimensions on which two binarized vectors differ.
+pub(crate) fn hamming(a: &[u64], b: &[u64]) -> u32 {
+    a.iter().zip(b).map(|(a, b)| (a ^ b).count_ones()).sum()
+}
+
+/// Estimate a similarity from the Hamming distance between two binarized vectors:
+/// 1 when every sign matches, -1 when none do.
+pub(crate) fn hamming_similarity(distance: u32, dimension: usize) -> f32 {
+    1.0 - 2.0 * distance as f32 / dimension.max(1) as f32
+}
+
+/// How the embeddings in db files are encoded.
+pub(crate) enum Codec {
+    /// The whole vector, see [`Storage`].
+    Vector(Storage),
+    /// Product quantization codes.
+    Product(ProductQuantizer),
+    /// One bit per dimension.
+    Binary(BinaryConfig),
+}
+
+impl Codec {
+    pub(crate) fn encode(&self, embedding: &Embedding) -> Result<Vec<u8>, Error> {
+        let mut encoded = Vec::new();
+        self.encode_into(embedding.id, &embedding.vector, &mut encoded)?;
+        Ok(encoded)
+    }
+
+    /// Encode the embedding with `id` and `vector` onto the end of `encoded`, so many embeddings can be encoded
+    /// into one buffer without copying their vectors or allocating a buffer for each.
+    pub(crate) fn encode_into(
+        &self,
+        id: Uuid,
+        vector: &[f32],
+        encoded: &mut Vec<u8>,
+    ) -> Result<(), Error> {
+        let result = match self {
+            Codec::Vector(Storage::Full) => {
+                bincode::serialize_into(&mut *encoded, &FullEmbeddingRef { id, vector })
+            }
+            Codec::Vector(Storage::Half) => {
+                bincode::serialize_into(&mut *encoded, &HalfEmbeddingRef { id, vector })
+            }
+            Codec::Vector(Storage::Packed) => bincode::serialize_into(
+                &mut *encoded,
+                &PackedEmbedding {
+                    id,
+                    vector: PackedVector::pack(vector),
+                },
+            ),
+            Codec::Product(quantizer) => bincode::serialize_into(
+                &mut *encoded,
+                &CodedEmbedding {
+                    id,
+                    codes: quantizer.encode(vector)?,
+                },
+            ),
+            Codec::Binary(config) => bincode::serialize_into(
+                &mut *encoded,
+                &PackedBinaryEmbedding {
+                    id,
+                    dimension: vector.len() as u32,
+                    bits: binarize(vector),
+                    vector: PackedVector::pack(if config.rerank { vector } else { &[] }),
+                },
+            ),
+        };
+        result.expect("Failed to serialize embedding");
+        Ok(())
+    }
+
+    pub(crate) fn decode(&self, filename: &str, record: &[u8]) -> Result<Embedding, Error> {
+        match self {
+            Codec::Vector(Storage::Full) => {
+                let full: FullEmbedding = deserialize(filename, record)?;
+                Ok(Embedding {
+                    id: full.id,
+                    vector: full.vector,
+                })
+            }
+            Codec::Vector(Storage::Half) => {
+                let half: HalfEmbedding = deserialize(filename, record)?;
+                Ok(Embedding {
+                    id: half.id,
+                    vector: half
+                        .vector
+                        .into_iter()
+                        .map(|bits| f16::from_bits(bits).to_f32())
+                        .collect(),
+                })
+            }
+            Codec::Vector(Storage::Packed) => deserialize(filename, record),
+            Codec::Product(quantizer) => {
+                let coded: CodedEmbedding = deserialize(filename, record)?;
+                Ok(Embedding {
+                    id: coded.id,
+                    vector: quantizer.decode(&coded.codes),
+                })
+            }
+            Codec::Binary(_) => {
+                let binary: BinaryEmbedding = deserialize(filename, record)?;
+                if !binary.vector.is_empty() {
+                    return Ok(Embedding {
+                        id: binary.id,
+                        vector: binary.vector,
+                    });
+                }
+
+                // all that's left of the vector is the sign of each dimension
+                let vector = (0..binary.dimension as usize)
+                    .map(|i| {
+                        if binary.bits[i / 64] & (1 << (i % 64)) != 0 {
+                            1.0
+                        } else {
+                            -1.0
+                        }
+                    })
+                    .collect();
+                Ok(Embedding {
+                    id: binary.id,
+                    vector,
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use super::*;
+    use crate::similarity;
+
+    #[test]
+    fn product_quantization() {
+        let mut rng = rand::thread_rng();
+        let vectors = (0..1000)
+            .map(|_| {
+                (0..32)
+                    .map(|_| rng.gen_range(-1.0..1.0))
+                    .collect::<Vec<f32>>()
+            })
+            .collect::<Vec<_>>();
+
+        let quantizer = ProductQuantizer::train(&vectors, PqConfig::default()).unwrap();
+
+        let codes = quantizer.encode(&vectors[0]).unwrap();
+        assert_eq!(codes.len(), 8);
+        let decoded = quantizer.decode(&codes);
+        assert_eq!(decoded.len(), 32);
+
+        // scoring the codes directly gives the same result as scoring the reconstruction
+        let table = quantizer.distance_table(&vectors[1]).unwrap();
+        let cosine = similarity::cosine(&decoded, &vectors[1]).unwrap();
+        assert!((table.cosine(&codes) - cosine).abs() < 0.0001);
+        let euclidean = similarity::euclidean(&decoded, &vectors[1]).unwrap();
+        assert!((table.euclidean(&codes) - euclidean).abs() < 0.0001);
+        for metric in [Metric::Dot, Metric::Manhattan] {
+            let similarity = metric.similarity(&decoded, &vectors[1]).unwrap();
+            assert!((table.similarity(metric, &codes) - similarity).abs() < 0.0001);
+        }
+
+        // and the reconstruction is closer to the original than to other vectors
+        assert!(
+            similarity::cosine(&decoded, &vectors[0]).unwrap()
+                > similarity::cosine(&decoded, &vectors[1]).unwrap()
+        );
+    }
+
+    #[test]
+    fn binary_quantization() {
+        let vector = (0..100)
+            .map(|i| if i % 3 == 0 { 1.0 } else { -0.5 })
+            .collect::<Vec<f32>>();
+        let bits = binarize(&vector);
+        assert_eq!(bits.len(), 2);
+        assert_eq!(hamming(&bits, &bits), 0);
+
+        let opposite = vector.iter().map(|x| -x).collect::<Vec<_>>();
+        assert_eq!(hamming(&bits, &binarize(&opposite)), 100);
+        assert_eq!(hamming_similarity(100, 100), -1.0);
+
+        let embedding = Embedding {
+            id: Uuid::new_v4(),
+            vector: vector.clone(),
+        };
+        let codec = Codec::Binary(BinaryConfig { rerank: false });
+        let decoded = codec
+            .decode("test.bin", &codec.encode(&embedding).unwrap())
+            .unwrap();
+        assert_eq!(decoded.id, embedding.id);
+        assert_eq!(
+            decoded.vector,
+            vector.iter().map(|x| x.signum()).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn full_storage() {
+        let embedding = Embedding {
+            id: Uuid::new_v4(),
+            vector: vec![0.1, -0.123456, 1e-6],
+        };
+        let codec = Codec::Vector(Storage::Full);
+        let decoded = codec
+            .decode("test.bin", &codec.encode(&embedding).unwrap())
+            .unwrap();
+        assert_eq!(decoded.id, embedding.id);
+        assert_eq!(decoded.vector, embedding.vector);
+    }
+
+    #[test]
+    fn half_storage() {
+        let embedding = Embedding {
+            id: Uuid::new_v4(),
+            vector: vec![0.1, -0.123456, 1.5],
+        };
+        let codec = Codec::Vector(Storage::Half);
+        let encoded = codec.encode(&embedding).unwrap();
+        // the id and the length of each field, then 2 bytes per dimension
+        assert_eq!(encoded.len(), 8 + 16 + 8 + 3 * 2);
+
+        let decoded = codec.decode("test.bin", &encoded).unwrap();
+        assert_eq!(decoded.id, embedding.id);
+        for (decoded, original) in decoded.vector.iter().zip(&embedding.vector) {
+            assert!((decoded - original).abs() < 0.001);
+        }
+    }
+
+    #[test]
+    fn borrowed_encoding_matches_stored_layout() {
+        let embedding = Embedding {
+            id: Uuid::new_v4(),
+            vector: vec![0.1, -0.123456, 1.5],
+        };
+        let owned = [
+            bincode::serialize(&FullEmbedding {
+                id: embedding.id,
+                vector: embedding.vector.clone(),
+            }),
+            bincode::serialize(&HalfEmbedding {
+                id: embedding.id,
+                vector: embedding
+                    .vector
+                    .iter()
+                    .map(|&value| f16::from_f32(value).to_bits())
+                    .collect(),
+            }),
+            bincode::serialize(&embedding),
+            bincode::serialize(&BinaryEmbedding {
+                id: embedding.id,
+                dimension: 3,
+                bits: binarize(&embedding.vector),
+                vector: embedding.vector.clone(),
+            }),
+            bincode::serialize(&BinaryEmbedding {
+                id: embedding.id,
+                dimension: 3,
+                bits: binarize(&embedding.vector),
+                vector: Vec::new(),
+            }),
+        ];
+        let codecs = [
+            Codec::Vector(Storage::Full),
+            Codec::Vector(Storage::Half),
+            Codec::Vector(Storage::Packed),
+            Codec::Binary(BinaryConfig { rerank: true }),
+            Codec::Binary(BinaryConfig { rerank: false }),
+        ];
+
+        // encoding several into one buffer is the same as encoding each on its own
+        let mut encoded = Vec::new();
+        for (codec, owned) in codecs.iter().zip(owned) {
+            let start = encoded.len();
+            codec
+                .encode_into(embedding.id, &embedding.vector, &mut encoded)
+                .unwrap();
+            assert_eq!(encoded[start..], owned.unwrap());
+        }
+    }
+
+    #[test]
+    fn uneven_subspaces() {
+        let vectors = vec![vec![1.0, 2.0, 3.0], vec![3.0, 2.0, 1.0]];
+        let config = PqConfig {
+            subspaces: 2,
+            ..Default::default()
+        };
+        let quantizer = ProductQuantizer::train(&vectors, config).unwrap();
+
+        // with only two vectors, each is its own centroid
+        let codes = quantizer.encode(&vectors[1]).unwrap();
+        assert_eq!(quantizer.decode(&codes), vectors[1]);
+        assert!(matches!(
+            quantizer.encode(&[1.0, 2.0]),
+            Err(Error::DimensionMismatch { .. })
+        ));
+    }
+}
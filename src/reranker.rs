@@ -0,0 +1,30 @@
+//! Re-scoring the best matches of a search with a more precise (and slower) model, like a cross-encoder,
+//! for [`crate::Victor::search`] and [`crate::Victor::search_hybrid`].
+
+use async_trait::async_trait;
+
+use crate::{error::Error, filesystem::MaybeSendSync};
+
+/// Re-scores search results, see [`crate::Victor::with_reranker`].
+///
+/// ```rust
+/// use async_trait::async_trait;
+/// use victor_db::{Error, Reranker};
+///
+/// /// Prefers shorter documents, which isn't very useful.
+/// struct ShortestFirst;
+///
+/// #[async_trait]
+/// impl Reranker for ShortestFirst {
+///     async fn rerank(&self, query: &str, candidates: &[&str]) -> Result<Vec<f32>, Error> {
+///         Ok(candidates.iter().map(|content| -(content.len() as f32)).collect())
+///     }
+/// }
+/// ```
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+pub trait Reranker: MaybeSendSync {
+    /// Score how relevant the content of each of `candidates` is to `query`, returning one score per candidate,
+    /// in the same order. Higher scores are more relevant.
+    async fn rerank(&self, query: &str, candidates: &[&str]) -> Result<Vec<f32>, Error>;
+}
@@ -0,0 +1,87 @@
+//! Paging through every document, or every one matching a filter, see [`crate::Victor::scroll`].
+//!
+//! Documents are listed db file by db file, in order of their file names, and in the order they're stored in
+//! within each file. A cursor is the name of the next db file to read and the position in it to carry on from,
+//! so it stays valid as documents are added (to the ends of files, or to new ones) and deleted (which only
+//! marks them). Compacting or optimizing the database rewrites its db files, so a scroll carried on across
+//! either may skip or repeat documents.
+
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{document::Metadata, error::Error};
+
+/// Where [`crate::Victor::scroll`] carries on from, from [`ScrollPage::cursor`].
+///
+/// Cursors can be stored or sent elsewhere as strings, with [`ToString`] and [`FromStr`], to pick up a scroll
+/// later on, but what's in them isn't meant to be relied on.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ScrollCursor {
+    pub(crate) filename: String,
+    pub(crate) position: usize,
+}
+
+impl fmt::Display for ScrollCursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.position, self.filename)
+    }
+}
+
+impl FromStr for ScrollCursor {
+    type Err = Error;
+
+    fn from_str(cursor: &str) -> Result<Self, Error> {
+        let invalid = || Error::InvalidInput(format!("invalid scroll cursor {cursor:?}"));
+        let (position, filename) = cursor.split_once(':').ok_or_else(invalid)?;
+        Ok(Self {
+            filename: filename.to_string(),
+            position: position.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+/// A document listed by [`crate::Victor::scroll`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ScrolledDocument {
+    /// The id of the document's embedding.
+    pub id: Uuid,
+    /// The id the document was added with, if it was added with one.
+    pub external_id: Option<String>,
+    /// The document's content.
+    pub content: String,
+    /// The document's tags, sorted.
+    pub tags: Vec<String>,
+    /// The document's vector as it's stored, so it may have lost precision to [`crate::Storage::Half`] or
+    /// quantization. Documents with more than one vector are listed with their first.
+    pub vector: Vec<f32>,
+    /// The document's metadata.
+    pub metadata: Metadata,
+}
+
+/// One batch of documents from [`crate::Victor::scroll`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ScrollPage {
+    /// At most as many documents as the limit, in the order they're stored in.
+    pub documents: Vec<ScrolledDocument>,
+    /// Where the next page starts, or `None` if this is the last one.
+    pub cursor: Option<ScrollCursor>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cursors_round_trip_through_strings() {
+        let cursor = ScrollCursor {
+            filename: "abc.0001.bin".to_string(),
+            position: 42,
+        };
+        assert_eq!(cursor.to_string().parse::<ScrollCursor>().unwrap(), cursor);
+
+        assert!("abc.bin".parse::<ScrollCursor>().is_err());
+        assert!("x:abc.bin".parse::<ScrollCursor>().is_err());
+    }
+}
@@ -0,0 +1,667 @@
+//! Options controlling how the database is searched.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt,
+    ops::{Bound, RangeBounds},
+    sync::Arc,
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    db::NearestNeighborsResult, document::Metadata, filter::Filter, similarity::Metric, utils,
+};
+
+/// Options for [`crate::Victor::search_embedding_with_options`].
+///
+/// ```rust
+/// use victor_db::{Filter, SearchOptions};
+///
+/// let options = SearchOptions::default().with_filter(Filter::eq("author", "alice"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct SearchOptions {
+    /// Only documents whose metadata matches this filter are returned.
+    pub filter: Filter,
+    /// How many candidates to consider from each HNSW graph (see [`crate::Victor::build_hnsw_index`]).
+    /// Higher values give more accurate results, more slowly. Has no effect if the database isn't indexed.
+    ///
+    /// Candidates are filtered after they're found, so raise this when using a selective [`Filter`].
+    pub ef_search: usize,
+    /// With binary quantization (see [`crate::Victor::enable_binary_quantization`]), how many times `top_n`
+    /// of the closest matches by Hamming distance are re-ranked by their vectors.
+    /// Likewise, how many times `top_n` matches are re-scored when [`SearchOptions::rescore`]ing.
+    /// Higher values give more accurate results, more slowly.
+    pub oversample: usize,
+    /// Whether to re-score the closest `oversample` times `top_n` matches by their full-precision vectors,
+    /// if the database keeps them (see [`crate::Victor::with_retained_originals`]).
+    /// This recovers most of the accuracy lost to packing or projecting vectors.
+    pub rescore: bool,
+    /// How to compare embeddings. Defaults to the database's metric (see [`crate::Settings::metric`]).
+    ///
+    /// Indexes are built for the default metric, so they're less accurate when searching with another.
+    pub metric: Option<Metric>,
+    /// Results less similar than this are left out, even if that means returning fewer than `top_n`.
+    ///
+    /// For the distance metrics, similarities are negated distances (see [`Metric`]),
+    /// so a maximum distance of 0.5 is a `min_similarity` of -0.5.
+    pub min_similarity: Option<f32>,
+    /// How [`crate::Victor::search_hybrid`] combines vector and keyword matches.
+    pub fusion: Fusion,
+    /// How documents with more than one vector (see [`crate::Document::with_chunk`]) are scored.
+    pub aggregation: Aggregation,
+    /// With a reranker (see [`crate::Victor::with_reranker`]), how many of the best matches are re-scored
+    /// before the top `top_n` are returned. Has no effect on searches by embedding, which have no query to
+    /// re-rank against.
+    pub rerank_candidates: u32,
+    /// Whether results include their (unpacked) vector. Their id is always included.
+    pub include_vector: bool,
+    /// Whether results include their content.
+    pub include_content: bool,
+    /// Whether results include their tags, which are left out by default.
+    pub include_tags: bool,
+    /// Whether results include the scores that went into their similarity (see [`ScoreBreakdown`]),
+    /// for tuning hybrid searches and rerankers.
+    pub include_breakdown: bool,
+    /// Whether to only match documents whose tags are all named by the tag filter, so searching for `A` only
+    /// finds documents added with exactly `A`, and not those added with `A` and `B`.
+    ///
+    /// Each set of tags is stored in db files of its own, so with tag sets used as strict partitions, this keeps
+    /// a search to the files of the partitions it names.
+    pub exact_tags: bool,
+    /// Only documents added within these bounds are returned, in milliseconds since the Unix epoch.
+    /// Documents added before the database recorded when documents were added never match.
+    ///
+    /// Adding a document again with the same id updates it without changing when it was added.
+    pub added: Option<(Bound<u64>, Bound<u64>)>,
+    /// Return the top `top_n` matches of each group of documents, instead of the top `top_n` overall, so one
+    /// group can't crowd out the rest. Results are still ordered best first, and always include their tags,
+    /// which tell the groups apart.
+    pub group_by: Option<GroupBy>,
+    /// Adjusts the similarity of each candidate to the query before the best are picked, see
+    /// [`SearchOptions::with_scorer`].
+    pub scorer: Option<Scorer>,
+    /// Only documents this returns true for are returned, see [`SearchOptions::with_predicate`].
+    pub predicate: Option<Predicate>,
+    /// Weights to scale the similarity of documents with these tags by, so some sources can be favored over
+    /// others in a single search. A document with several of the tags is scaled by the product of their weights.
+    ///
+    /// Weights above 1 always make a document rank higher: positive similarities are multiplied by the weight,
+    /// and negative ones (like the negated distances of the distance metrics, see [`Metric`]) divided by it.
+    /// Keyword scores are scaled too.
+    pub tag_boosts: HashMap<String, f32>,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        Self {
+            filter: Filter::default(),
+            ef_search: 64,
+            oversample: 4,
+            rescore: true,
+            metric: None,
+            min_similarity: None,
+            fusion: Fusion::default(),
+            aggregation: Aggregation::default(),
+            rerank_candidates: 100,
+            include_vector: true,
+            include_content: true,
+            include_tags: false,
+            include_breakdown: false,
+            exact_tags: false,
+            added: None,
+            group_by: None,
+            scorer: None,
+            predicate: None,
+            tag_boosts: HashMap::new(),
+        }
+    }
+}
+
+impl SearchOptions {
+    /// Only return documents whose metadata matches `filter`.
+    pub fn with_filter(mut self, filter: Filter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Consider `ef_search` candidates from each HNSW graph, see [`SearchOptions::ef_search`].
+    pub fn with_ef_search(mut self, ef_search: usize) -> Self {
+        self.ef_search = ef_search;
+        self
+    }
+
+    /// Re-rank `oversample` times as many binary quantized matches, see [`SearchOptions::oversample`].
+    pub fn with_oversample(mut self, oversample: usize) -> Self {
+        self.oversample = oversample;
+        self
+    }
+
+    /// Whether to re-score matches by their full-precision vectors, see [`SearchOptions::rescore`].
+    pub fn with_rescore(mut self, rescore: bool) -> Self {
+        self.rescore = rescore;
+        self
+    }
+
+    /// Compare embeddings with `metric`, see [`SearchOptions::metric`].
+    pub fn with_metric(mut self, metric: Metric) -> Self {
+        self.metric = Some(metric);
+        self
+    }
+
+    /// Leave out results less similar than `min_similarity`, see [`SearchOptions::min_similarity`].
+    pub fn with_min_similarity(mut self, min_similarity: f32) -> Self {
+        self.min_similarity = Some(min_similarity);
+        self
+    }
+
+    /// Combine vector and keyword matches with `fusion`, see [`SearchOptions::fusion`].
+    pub fn with_fusion(mut self, fusion: Fusion) -> Self {
+        self.fusion = fusion;
+        self
+    }
+
+    /// Score documents with more than one vector with `aggregation`, see [`SearchOptions::aggregation`].
+    pub fn with_aggregation(mut self, aggregation: Aggregation) -> Self {
+        self.aggregation = aggregation;
+        self
+    }
+
+    /// Re-rank the best `rerank_candidates` matches, see [`SearchOptions::rerank_candidates`].
+    pub fn with_rerank_candidates(mut self, rerank_candidates: u32) -> Self {
+        self.rerank_candidates = rerank_candidates;
+        self
+    }
+
+    /// Whether results include their vector, see [`SearchOptions::include_vector`].
+    pub fn with_include_vector(mut self, include_vector: bool) -> Self {
+        self.include_vector = include_vector;
+        self
+    }
+
+    /// Whether results include their content, see [`SearchOptions::include_content`].
+    pub fn with_include_content(mut self, include_content: bool) -> Self {
+        self.include_content = include_content;
+        self
+    }
+
+    /// Whether results include their tags, see [`SearchOptions::include_tags`].
+    pub fn with_include_tags(mut self, include_tags: bool) -> Self {
+        self.include_tags = include_tags;
+        self
+    }
+
+    /// Whether results include their score breakdown, see [`SearchOptions::include_breakdown`].
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// # let mut victor = Db::new(DirectoryHandle::default());
+    /// use victor_db::SearchOptions;
+    ///
+    /// victor.add_single_embedding("The printer shows error E1234", vec![0.0, 1.0], vec!["Support"]).await.unwrap();
+    ///
+    /// let options = SearchOptions::default().with_include_breakdown(true);
+    /// let results = victor.search_hybrid_embedding(vec![0.0, 1.0], "e1234", vec!["Support"], 1, &options).await.unwrap();
+    /// let breakdown = results[0].breakdown.unwrap();
+    /// assert!(breakdown.vector.is_some() && breakdown.keyword.is_some());
+    /// assert_eq!(breakdown.fused, Some(results[0].similarity));
+    /// # })
+    /// ```
+    pub fn with_include_breakdown(mut self, include_breakdown: bool) -> Self {
+        self.include_breakdown = include_breakdown;
+        self
+    }
+
+    /// Whether to only match documents with no tags but the ones named, see [`SearchOptions::exact_tags`].
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// # let mut victor = Db::new(DirectoryHandle::default());
+    /// use victor_db::SearchOptions;
+    ///
+    /// victor.add_single_embedding("Pepperoni pizza", vec![0.1, 0.2, 0.3], vec!["Pizza"]).await.unwrap();
+    /// victor.add_single_embedding("Pineapple pizza", vec![0.1, 0.2, 0.3], vec!["Pizza", "Fruit"]).await.unwrap();
+    ///
+    /// let options = SearchOptions::default().with_exact_tags(true);
+    /// let results = victor.search_embedding_with_options(vec![0.1, 0.2, 0.3], vec!["Pizza"], 10, &options).await.unwrap();
+    /// assert_eq!(results.len(), 1);
+    /// assert_eq!(results[0].content, "Pepperoni pizza");
+    /// # })
+    /// ```
+    pub fn with_exact_tags(mut self, exact_tags: bool) -> Self {
+        self.exact_tags = exact_tags;
+        self
+    }
+
+    /// Only return documents added within `range`, in milliseconds since the Unix epoch,
+    /// see [`SearchOptions::added`].
+    pub fn with_added(mut self, range: impl RangeBounds<u64>) -> Self {
+        self.added = Some((range.start_bound().cloned(), range.end_bound().cloned()));
+        self
+    }
+
+    /// Only return documents added in the last `duration`, counting from now, see [`SearchOptions::added`].
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// # let mut victor = Db::new(DirectoryHandle::default());
+    /// use std::time::Duration;
+    /// use victor_db::SearchOptions;
+    ///
+    /// victor.add_single_embedding("Pepperoni pizza", vec![0.1, 0.2, 0.3], vec!["Pizza"]).await.unwrap();
+    ///
+    /// let last_month = SearchOptions::default().with_added_within(Duration::from_secs(30 * 24 * 60 * 60));
+    /// let results = victor.search_embedding_with_options(vec![0.1, 0.2, 0.3], vec!["Pizza"], 10, &last_month).await.unwrap();
+    /// assert_eq!(results.len(), 1);
+    ///
+    /// let next_month = SearchOptions::default().with_added(u64::MAX - 1..);
+    /// let results = victor.search_embedding_with_options(vec![0.1, 0.2, 0.3], vec!["Pizza"], 10, &next_month).await.unwrap();
+    /// assert!(results.is_empty());
+    /// # })
+    /// ```
+    pub fn with_added_within(self, duration: Duration) -> Self {
+        let now = utils::now_millis();
+        self.with_added(now.saturating_sub(duration.as_millis() as u64)..)
+    }
+
+    /// Whether a document added at `added` (if it's known) is within [`SearchOptions::added`].
+    pub(crate) fn matches_added(&self, added: Option<&u64>) -> bool {
+        match (&self.added, added) {
+            (None, _) => true,
+            (Some(range), Some(added)) => range.contains(added),
+            (Some(_), None) => false,
+        }
+    }
+
+    /// Return the top `top_n` matches of each group, see [`SearchOptions::group_by`].
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// # let mut victor = Db::new(DirectoryHandle::default());
+    /// use victor_db::{GroupBy, SearchOptions};
+    ///
+    /// victor.add_single_embedding("Pepperoni pizza", vec![0.1, 0.2, 0.3], vec!["source:menu"]).await.unwrap();
+    /// victor.add_single_embedding("Salami pizza", vec![0.1, 0.2, 0.31], vec!["source:menu"]).await.unwrap();
+    /// victor.add_single_embedding("Pizza review", vec![0.3, 0.2, 0.1], vec!["source:blog"]).await.unwrap();
+    ///
+    /// // the best match from each source
+    /// let options = SearchOptions::default().with_group_by(GroupBy::TagPrefix("source:".to_string()));
+    /// let results = victor.search_embedding_with_options(vec![0.1, 0.2, 0.3], Vec::<String>::new(), 1, &options).await.unwrap();
+    /// assert_eq!(results.len(), 2);
+    /// assert_eq!(results[1].tags, vec!["source:blog"]);
+    /// # })
+    /// ```
+    pub fn with_group_by(mut self, group_by: GroupBy) -> Self {
+        self.group_by = Some(group_by);
+        self
+    }
+
+    /// Adjust the similarity of each candidate to the query with `scorer`, given the similarity and the
+    /// candidate document, before the best candidates are picked. This boosts documents by rules of your own,
+    /// like favoring recent or popular ones.
+    ///
+    /// The adjusted score replaces the similarity everywhere it's used, including the results' `similarity`
+    /// and [`SearchOptions::min_similarity`], so it should still be higher for better matches. Only searches by
+    /// vector are adjusted, including the vector half of hybrid searches, and results have no raw metric value.
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// # let mut victor = Db::new(DirectoryHandle::default());
+    /// use victor_db::{Document, SearchOptions};
+    ///
+    /// victor
+    ///     .add_documents(
+    ///         vec![
+    ///             Document::new("Pepperoni pizza", vec![0.1, 0.2, 0.3]),
+    ///             Document::new("Salami pizza", vec![0.3, 0.2, 0.1]).with_metadata("promoted", "yes"),
+    ///         ],
+    ///         vec!["Pizza Flavors"],
+    ///     )
+    ///     .await
+    ///     .unwrap();
+    ///
+    /// // promoted documents come first
+    /// let options = SearchOptions::default().with_scorer(|similarity, document| {
+    ///     match document.metadata.contains_key("promoted") {
+    ///         true => similarity + 1.0,
+    ///         false => similarity,
+    ///     }
+    /// });
+    /// let results = victor.search_embedding_with_options(vec![0.1, 0.2, 0.3], vec!["Pizza Flavors"], 1, &options).await.unwrap();
+    /// assert_eq!(results[0].content, "Salami pizza");
+    /// # })
+    /// ```
+    pub fn with_scorer(
+        mut self,
+        scorer: impl Fn(f32, &DocumentMeta) -> f32 + Send + Sync + 'static,
+    ) -> Self {
+        self.scorer = Some(Scorer(Arc::new(scorer)));
+        self
+    }
+
+    /// Only return documents `predicate` returns true for, for filters that [`Filter`] can't express.
+    ///
+    /// Candidates are filtered while the database is scanned, before they're scored, so unlike filtering the
+    /// results afterwards, there are still `top_n` results if there are that many matching documents.
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// # let mut victor = Db::new(DirectoryHandle::default());
+    /// use victor_db::{Document, SearchOptions};
+    ///
+    /// victor
+    ///     .add_documents(
+    ///         vec![
+    ///             Document::new("Pepperoni pizza", vec![0.1, 0.2, 0.3]).with_id("PIZZA-1"),
+    ///             Document::new("Salami pizza", vec![0.1, 0.2, 0.25]).with_id("pizza-2"),
+    ///         ],
+    ///         vec!["Pizza Flavors"],
+    ///     )
+    ///     .await
+    ///     .unwrap();
+    ///
+    /// // only documents with lowercase ids
+    /// let options = SearchOptions::default().with_predicate(|document| {
+    ///     document.external_id.is_some_and(|id| id == id.to_lowercase())
+    /// });
+    /// let results = victor.search_embedding_with_options(vec![0.1, 0.2, 0.3], vec!["Pizza Flavors"], 1, &options).await.unwrap();
+    /// assert_eq!(results[0].content, "Salami pizza");
+    /// # })
+    /// ```
+    pub fn with_predicate(
+        mut self,
+        predicate: impl Fn(&DocumentMeta) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.predicate = Some(Predicate(Arc::new(predicate)));
+        self
+    }
+
+    /// Whether `document` matches the [`SearchOptions::filter`] and the [`SearchOptions::predicate`].
+    pub(crate) fn matches_document(&self, document: &DocumentMeta) -> bool {
+        self.filter.matches(document.metadata)
+            && self
+                .predicate
+                .as_ref()
+                .is_none_or(|predicate| (predicate.0)(document))
+    }
+
+    /// Whether searches need the content and metadata of candidate documents, to filter or score them.
+    pub(crate) fn needs_documents(&self) -> bool {
+        !self.filter.is_all() || self.scorer.is_some() || self.predicate.is_some()
+    }
+
+    /// Whether searches need to know when candidate documents were added.
+    pub(crate) fn needs_added(&self) -> bool {
+        self.added.is_some() || self.scorer.is_some() || self.predicate.is_some()
+    }
+
+    /// Scale the similarity of documents with each tag by its weight, see [`SearchOptions::tag_boosts`].
+    ///
+    /// ```rust
+    /// # tokio_test::block_on(async {
+    /// # use victor_db::memory::{Db, DirectoryHandle};
+    /// # let mut victor = Db::new(DirectoryHandle::default());
+    /// use victor_db::SearchOptions;
+    ///
+    /// victor.add_single_embedding("Pizza dough recipe", vec![0.1, 0.2, 0.3], vec!["forum"]).await.unwrap();
+    /// victor.add_single_embedding("Pizza dough guide", vec![0.1, 0.2, 0.25], vec!["official_docs"]).await.unwrap();
+    ///
+    /// let options = SearchOptions::default().with_tag_boosts([("official_docs", 1.2), ("forum", 0.8)]);
+    /// let results = victor.search_embedding_with_options(vec![0.1, 0.2, 0.3], Vec::<String>::new(), 1, &options).await.unwrap();
+    /// assert_eq!(results[0].content, "Pizza dough guide");
+    /// # })
+    /// ```
+    pub fn with_tag_boosts(
+        mut self,
+        tag_boosts: impl IntoIterator<Item = (impl Into<String>, f32)>,
+    ) -> Self {
+        self.tag_boosts = tag_boosts
+            .into_iter()
+            .map(|(tag, weight)| (tag.into(), weight))
+            .collect();
+        self
+    }
+
+    /// The product of the [`SearchOptions::tag_boosts`] of a document with `tags`, or `None` if none of them
+    /// are boosted.
+    pub(crate) fn boost<'a>(&self, tags: impl IntoIterator<Item = &'a String>) -> Option<f32> {
+        tags.into_iter()
+            .filter_map(|tag| self.tag_boosts.get(tag))
+            .fold(None, |boost, weight| Some(boost.unwrap_or(1.0) * weight))
+    }
+
+    /// Whether results need their tags, to be returned, grouped, boosted or seen by a scorer or predicate.
+    pub(crate) fn needs_tags(&self) -> bool {
+        self.include_tags
+            || self.group_by.is_some()
+            || !self.tag_boosts.is_empty()
+            || self.scorer.is_some()
+            || self.predicate.is_some()
+    }
+
+    /// Keep the first `top_n` of `items` (which must be sorted best first), or with [`SearchOptions::group_by`],
+    /// the first `top_n` of each group, given the tags of each item.
+    pub(crate) fn limit<T>(
+        &self,
+        items: &mut Vec<T>,
+        top_n: usize,
+        tags: impl Fn(&T) -> Vec<String>,
+    ) {
+        let Some(group_by) = &self.group_by else {
+            items.truncate(top_n);
+            return;
+        };
+        let mut counts = HashMap::new();
+        items.retain(|item| {
+            let count = counts.entry(group_by.group(tags(item))).or_insert(0);
+            *count += 1;
+            *count <= top_n
+        });
+    }
+
+    /// These options, but with every field included in results that are re-ranked or fused before they're
+    /// returned. The fields are left out afterwards, with [`SearchOptions::exclude_fields`].
+    pub(crate) fn with_all_fields(&self) -> Self {
+        Self {
+            include_vector: true,
+            include_content: true,
+            include_breakdown: true,
+            ..self.clone()
+        }
+    }
+
+    /// Leave out the fields of `result` these options don't include.
+    pub(crate) fn exclude_fields(&self, result: &mut NearestNeighborsResult) {
+        if !self.include_vector {
+            result.embedding.vector = Vec::new();
+        }
+        if !self.include_content {
+            result.content = String::new();
+        }
+        if !self.include_breakdown {
+            result.breakdown = None;
+        }
+        // tags looked up for anything else aren't returned
+        if !self.include_tags && self.group_by.is_none() {
+            result.tags = Vec::new();
+        }
+    }
+}
+
+/// The scores that went into a search result's similarity, see [`SearchOptions::include_breakdown`].
+/// Each is `None` if it played no part, say the keyword score of a document only found by its vector.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct ScoreBreakdown {
+    /// The similarity of the document's vectors to the query, by the metric (see [`Metric`]).
+    pub vector: Option<f32>,
+    /// The BM25 score of the document for the keywords.
+    pub keyword: Option<f32>,
+    /// The vector and keyword scores combined with [`SearchOptions::fusion`].
+    pub fused: Option<f32>,
+    /// The reranker's score (see [`crate::Victor::with_reranker`]), which replaces the others.
+    pub rerank: Option<f32>,
+    /// How much the document was boosted by its tags (see [`SearchOptions::tag_boosts`]). The vector and keyword
+    /// scores are from before it was boosted.
+    pub boost: Option<f32>,
+}
+
+/// How a hybrid search (see [`crate::Victor::search_hybrid`]) combines the results of searching by vector and
+/// by keyword into a single score.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Fusion {
+    /// Reciprocal rank fusion: each result scores `1 / (k + rank)` in each list it's in, starting from rank 1.
+    /// Only the order of each list matters, so it needs no tuning. `k` is usually 60.
+    ReciprocalRank {
+        /// Higher values make the top few results of each list count for less.
+        k: f32,
+    },
+    /// A weighted sum of the vector and keyword scores, each scaled to between 0 and 1.
+    Weighted {
+        /// How much keyword scores count, from 0 (only vectors) to 1 (only keywords).
+        keyword_weight: f32,
+    },
+}
+
+impl Default for Fusion {
+    fn default() -> Self {
+        Self::ReciprocalRank { k: 60.0 }
+    }
+}
+
+/// A function adjusting the similarity of search candidates, see [`SearchOptions::with_scorer`].
+#[derive(Clone)]
+pub struct Scorer(Arc<ScoreFn>);
+
+type ScoreFn = dyn Fn(f32, &DocumentMeta) -> f32 + Send + Sync;
+
+impl Scorer {
+    pub(crate) fn score(&self, similarity: f32, document: &DocumentMeta) -> f32 {
+        (self.0)(similarity, document)
+    }
+}
+
+impl fmt::Debug for Scorer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Scorer")
+    }
+}
+
+/// A function filtering search candidates, see [`SearchOptions::with_predicate`].
+#[derive(Clone)]
+pub struct Predicate(Arc<PredicateFn>);
+
+type PredicateFn = dyn Fn(&DocumentMeta) -> bool + Send + Sync;
+
+impl fmt::Debug for Predicate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Predicate")
+    }
+}
+
+/// What a scorer or predicate (see [`SearchOptions::with_scorer`] and [`SearchOptions::with_predicate`])
+/// knows about a candidate document.
+#[derive(Debug, Clone, Copy)]
+pub struct DocumentMeta<'a> {
+    /// The id of the document's embedding.
+    pub id: Uuid,
+    /// The id the document was added with, if it was added with one.
+    pub external_id: Option<&'a str>,
+    /// The document's metadata.
+    pub metadata: &'a Metadata,
+    /// When the document was added, in milliseconds since the Unix epoch, if that was recorded
+    /// (see [`SearchOptions::added`]).
+    pub added: Option<u64>,
+    /// The document's tags.
+    pub tags: &'a BTreeSet<String>,
+}
+
+/// `similarity` scaled by a tag `boost` (see [`SearchOptions::tag_boosts`]), so it's higher for boosts above 1
+/// whatever its sign.
+pub(crate) fn boosted(similarity: f32, boost: f32) -> f32 {
+    match similarity >= 0.0 {
+        true => similarity * boost,
+        false => similarity / boost,
+    }
+}
+
+/// How search results are grouped by their tags, see [`SearchOptions::group_by`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GroupBy {
+    /// Documents added with the same set of tags are grouped together.
+    TagSet,
+    /// Documents are grouped by their tag starting with this prefix, say `"source:"` for tags like
+    /// `"source:wiki"` and `"source:news"`. Documents with more than one such tag are grouped by the first, in
+    /// sorted order, and documents with none are grouped together.
+    TagPrefix(String),
+}
+
+impl GroupBy {
+    /// The group of a document with `tags`, which must be sorted.
+    pub(crate) fn group(&self, tags: Vec<String>) -> Vec<String> {
+        match self {
+            Self::TagSet => tags,
+            Self::TagPrefix(prefix) => tags
+                .into_iter()
+                .find(|tag| tag.starts_with(prefix.as_str()))
+                .into_iter()
+                .collect(),
+        }
+    }
+}
+
+/// How a document with more than one vector (see [`crate::Document::with_chunk`]) is scored from the similarities
+/// of its vectors to the query.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Aggregation {
+    /// The similarity of its closest vector, so it matches as well as its best chunk.
+    #[default]
+    Max,
+    /// The sum of the similarities of its vectors among the closest matches,
+    /// so documents with many matching chunks come first.
+    Sum,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits_each_group() {
+        let items = [
+            (1, vec!["source:a", "x"]),
+            (2, vec!["source:a"]),
+            (3, vec!["source:b"]),
+            (4, vec!["source:a"]),
+            (5, vec![]),
+            (6, vec!["y"]),
+        ]
+        .map(|(n, tags)| (n, tags.into_iter().map(String::from).collect::<Vec<_>>()));
+        let limited = |options: SearchOptions| {
+            let mut items = items.to_vec();
+            options.limit(&mut items, 2, |(_, tags)| tags.clone());
+            items.into_iter().map(|(n, _)| n).collect::<Vec<_>>()
+        };
+
+        assert_eq!(limited(SearchOptions::default()), [1, 2]);
+        assert_eq!(
+            limited(SearchOptions::default().with_group_by(GroupBy::TagSet)),
+            [1, 2, 3, 4, 5, 6]
+        );
+        // documents without the prefix are grouped together
+        assert_eq!(
+            limited(
+                SearchOptions::default().with_group_by(GroupBy::TagPrefix("source:".to_string()))
+            ),
+            [1, 2, 3, 5, 6]
+        );
+    }
+}
@@ -0,0 +1,184 @@
+//! Database-wide settings, recorded in `settings.bin` when the database is created.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    compression::Compression,
+    error::Error,
+    quantization::Storage,
+    similarity::{self, Metric},
+};
+
+/// How a database compares and stores embeddings, see [`crate::Victor::with_settings`].
+///
+/// ```rust
+/// use victor_db::{Compression, Metric, Settings, Storage};
+///
+/// // a database for a model trained for inner-product retrieval, with 384-dimensional embeddings
+/// let settings = Settings {
+///     metric: Metric::Dot,
+///     dimension: Some(384),
+///     storage: Storage::Half,
+///     compression: Compression::None,
+///     normalize: false,
+/// };
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Settings {
+    /// How embeddings are compared, unless a search chooses otherwise (see [`crate::SearchOptions::metric`]).
+    /// Indexes are also built for this metric.
+    pub metric: Metric,
+    /// The dimension of every embedding in the database.
+    /// If `None`, it's taken from the first embedding added.
+    pub dimension: Option<usize>,
+    /// How vectors are stored, unless they're quantized further.
+    pub storage: Storage,
+    /// How db files and `content.bin` are compressed.
+    pub compression: Compression,
+    /// Whether vectors are scaled to unit length (L2 normalized) when they're added and searched for,
+    /// so sources that normalize their embeddings and sources that don't can be mixed, and the dot product
+    /// gives the same results as cosine similarity. Vectors of length zero are left as they are.
+    pub normalize: bool,
+}
+
+/// The layout of `settings.bin` before format version 10, which didn't record whether vectors are normalized.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub(crate) struct SettingsV9 {
+    metric: Metric,
+    dimension: Option<usize>,
+    storage: Storage,
+    compression: Compression,
+}
+
+impl From<SettingsV9> for Settings {
+    fn from(settings: SettingsV9) -> Self {
+        Self {
+            metric: settings.metric,
+            dimension: settings.dimension,
+            storage: settings.storage,
+            compression: settings.compression,
+            normalize: false,
+        }
+    }
+}
+
+/// The layout of `settings.bin` before format version 2, which didn't record the compression.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub(crate) struct SettingsV1 {
+    metric: Metric,
+    dimension: Option<usize>,
+    storage: Storage,
+}
+
+impl From<SettingsV1> for Settings {
+    fn from(settings: SettingsV1) -> Self {
+        Self {
+            metric: settings.metric,
+            dimension: settings.dimension,
+            storage: settings.storage,
+            compression: Compression::None,
+            normalize: false,
+        }
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            metric: Metric::Cosine,
+            dimension: None,
+            storage: Storage::default(),
+            compression: Compression::default(),
+            normalize: false,
+        }
+    }
+}
+
+impl Settings {
+    /// Check that an embedding with `dimension` dimensions belongs in the database.
+    pub(crate) fn check_dimension(&self, dimension: usize) -> Result<(), Error> {
+        match self.dimension {
+            Some(expected) if expected != dimension => Err(Error::DimensionMismatch {
+                expected,
+                found: dimension,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Scale `vector` to unit length, if the database normalizes vectors.
+    pub(crate) fn normalize(&self, vector: &mut [f32]) {
+        if self.normalize {
+            similarity::normalize(vector);
+        }
+    }
+
+    /// Combine the settings a database already has with the settings it's being opened with,
+    /// which must agree on everything except a dimension that hasn't been decided yet.
+    pub(crate) fn reconcile(self, requested: Settings) -> Result<Settings, Error> {
+        if self.metric != requested.metric
+            || self.storage != requested.storage
+            || self.compression != requested.compression
+            || self.normalize != requested.normalize
+        {
+            return Err(Error::InvalidInput(format!(
+                "the database was created with {self:?}, not {requested:?}"
+            )));
+        }
+        if let Some(dimension) = requested.dimension {
+            self.check_dimension(dimension)?;
+        }
+
+        Ok(Settings {
+            dimension: self.dimension.or(requested.dimension),
+            ..self
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reconcile() {
+        let existing = Settings::default();
+
+        let requested = Settings {
+            dimension: Some(3),
+            ..Default::default()
+        };
+        assert_eq!(existing.reconcile(requested).unwrap(), requested);
+        assert_eq!(requested.reconcile(existing).unwrap(), requested);
+
+        let other_dimension = Settings {
+            dimension: Some(4),
+            ..Default::default()
+        };
+        assert!(matches!(
+            requested.reconcile(other_dimension),
+            Err(Error::DimensionMismatch {
+                expected: 3,
+                found: 4
+            })
+        ));
+
+        let other_metric = Settings {
+            metric: Metric::Dot,
+            ..Default::default()
+        };
+        assert!(matches!(
+            existing.reconcile(other_metric),
+            Err(Error::InvalidInput(_))
+        ));
+
+        let normalized = Settings {
+            normalize: true,
+            ..Default::default()
+        };
+        assert!(matches!(
+            existing.reconcile(normalized),
+            Err(Error::InvalidInput(_))
+        ));
+    }
+}
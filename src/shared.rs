@@ -0,0 +1,71 @@
+//! A database handle that can be shared between threads, see [`Shared`].
+
+use std::sync::Arc;
+
+use tokio::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use crate::{db::Victor, filesystem::DirectoryHandle};
+
+/// A [`Victor`] that can be shared between threads and tasks, like the request handlers of a multi-threaded
+/// web server. Cloning it is cheap, and every clone is the same database.
+///
+/// Anything that only reads the database (like searching) runs concurrently with other reads, while anything that
+/// changes it waits for every other read and write to finish first.
+///
+/// ```rust
+/// # tokio_test::block_on(async {
+/// use victor_db::memory::{Db, DirectoryHandle, SharedDb};
+///
+/// let victor = SharedDb::new(Db::new(DirectoryHandle::default()));
+///
+/// let writer = victor.clone();
+/// tokio::spawn(async move {
+///     writer
+///         .write()
+///         .await
+///         .add_single_embedding("Pepperoni pizza", vec![0.1, 0.2, 0.3], vec!["Pizza Flavors"])
+///         .await
+///         .unwrap();
+/// })
+/// .await
+/// .unwrap();
+///
+/// let results = victor
+///     .read()
+///     .await
+///     .search_embedding(vec![0.1, 0.2, 0.3], vec!["Pizza Flavors"], 1)
+///     .await
+///     .unwrap();
+/// assert_eq!(results[0].content, "Pepperoni pizza");
+/// # })
+/// ```
+pub struct Shared<D: DirectoryHandle>(Arc<RwLock<Victor<D>>>);
+
+impl<D: DirectoryHandle> Shared<D> {
+    /// Share `victor` between threads.
+    pub fn new(victor: Victor<D>) -> Self {
+        Self(Arc::new(RwLock::new(victor)))
+    }
+
+    /// Wait until nothing is changing the database, then lock it for reading.
+    pub async fn read(&self) -> RwLockReadGuard<'_, Victor<D>> {
+        self.0.read().await
+    }
+
+    /// Wait until nothing else is using the database, then lock it for changing.
+    pub async fn write(&self) -> RwLockWriteGuard<'_, Victor<D>> {
+        self.0.write().await
+    }
+}
+
+impl<D: DirectoryHandle> Clone for Shared<D> {
+    fn clone(&self) -> Self {
+        Self(Arc::clone(&self.0))
+    }
+}
+
+impl<D: DirectoryHandle> From<Victor<D>> for Shared<D> {
+    fn from(victor: Victor<D>) -> Self {
+        Self::new(victor)
+    }
+}
@@ -1,3 +1,136 @@
+//! Ways of comparing two vectors.
+
+use serde::{Deserialize, Serialize};
+
+/// How similar two embeddings are, see [`crate::SearchOptions::metric`].
+///
+/// Similarities are always higher for more similar embeddings,
+/// so for the distance metrics the similarity is the negated distance.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Metric {
+    /// The cosine of the angle between the embeddings, from -1 to 1. Ignores their magnitude.
+    Cosine,
+    /// The dot (inner) product, for models trained for inner-product retrieval.
+    /// The same as cosine for normalized embeddings.
+    Dot,
+    /// Euclidean (L2) distance.
+    Euclidean,
+    /// Manhattan (L1) distance.
+    Manhattan,
+}
+
+impl Metric {
+    /// The metric of a database that was created before its settings were recorded: euclidean if its embeddings
+    /// have been projected to fewer dimensions, and cosine otherwise.
+    pub(crate) fn default_for(is_projected: bool) -> Self {
+        if is_projected {
+            Metric::Euclidean
+        } else {
+            Metric::Cosine
+        }
+    }
+
+    /// The similarity of `v1` and `v2`, where higher is always more similar.
+    pub(crate) fn similarity(self, v1: &[f32], v2: &[f32]) -> Result<f32, String> {
+        match self {
+            Metric::Cosine => cosine(v1, v2),
+            Metric::Dot => dot(v1, v2),
+            Metric::Euclidean => euclidean(v1, v2).map(|distance| -distance),
+            Metric::Manhattan => manhattan(v1, v2).map(|distance| -distance),
+        }
+    }
+
+    /// A `similarity` by this metric scaled to between 0 and 1, where higher is still more similar,
+    /// so scores can be read the same way whatever the metric:
+    ///
+    /// - cosine similarities go from 0 (opposite) to 1 (the same direction),
+    /// - dot products go through the logistic function, so a dot product of 0 scores 0.5,
+    /// - distances score `1 / (1 + distance)`, so identical vectors score 1.
+    ///
+    /// Scores keep the order of the similarities they come from (though cosine similarities summed with
+    /// [`crate::Aggregation::Sum`] are capped at 1).
+    ///
+    /// ```rust
+    /// use victor_db::Metric;
+    ///
+    /// assert_eq!(Metric::Cosine.score(1.0), 1.0);
+    /// assert_eq!(Metric::Dot.score(0.0), 0.5);
+    /// assert_eq!(Metric::Euclidean.score(-1.0), 0.5);
+    /// ```
+    pub fn score(self, similarity: f32) -> f32 {
+        match self {
+            Metric::Cosine => ((1.0 + similarity) / 2.0).clamp(0.0, 1.0),
+            Metric::Dot => logistic(similarity),
+            Metric::Euclidean | Metric::Manhattan => 1.0 / (1.0 - similarity),
+        }
+    }
+
+    /// The metric's own value for a `similarity` by this metric: the cosine similarity or the dot product,
+    /// where higher is more similar, or the distance, where lower is.
+    ///
+    /// ```rust
+    /// use victor_db::Metric;
+    ///
+    /// assert_eq!(Metric::Cosine.raw(0.5), 0.5);
+    /// assert_eq!(Metric::Manhattan.raw(-2.0), 2.0);
+    /// ```
+    pub fn raw(self, similarity: f32) -> f32 {
+        match self {
+            Metric::Cosine | Metric::Dot => similarity,
+            Metric::Euclidean | Metric::Manhattan => -similarity,
+        }
+    }
+}
+
+/// The logistic function, which maps any score to between 0 and 1, keeping its order.
+pub(crate) fn logistic(score: f32) -> f32 {
+    1.0 / (1.0 + (-score).exp())
+}
+
+/// Scale `vector` to unit length, unless its length is zero.
+pub(crate) fn normalize(vector: &mut [f32]) {
+    let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        for x in vector {
+            *x /= norm;
+        }
+    }
+}
+
+/// A query for documents like the `positive` vectors and unlike the `negative` ones: the mean of the positives,
+/// pushed away from the mean of the negatives by as much again, so `2 * mean(positive) - mean(negative)`.
+/// With cosine similarity only directions matter, so every example is normalized first to weigh the same.
+/// `positive` mustn't be empty.
+pub(crate) fn recommendation(
+    positive: &[Vec<f32>],
+    negative: &[Vec<f32>],
+    metric: Metric,
+) -> Vec<f32> {
+    let mean = |vectors: &[Vec<f32>]| {
+        let mut sum = vec![0.0; positive[0].len()];
+        for vector in vectors {
+            let mut vector = vector.clone();
+            if metric == Metric::Cosine {
+                normalize(&mut vector);
+            }
+            for (sum, value) in sum.iter_mut().zip(vector) {
+                *sum += value / vectors.len() as f32;
+            }
+        }
+        sum
+    };
+
+    let query = mean(positive);
+    if negative.is_empty() {
+        return query;
+    }
+    query
+        .iter()
+        .zip(mean(negative))
+        .map(|(positive, negative)| 2.0 * positive - negative)
+        .collect()
+}
+
 pub(crate) fn cosine(v1: &[f32], v2: &[f32]) -> Result<f32, String> {
     if v1.len() != v2.len() {
         return Err(format!(
@@ -42,6 +175,30 @@ pub(crate) fn euclidean(v1: &[f32], v2: &[f32]) -> Result<f32, String> {
     Ok(sum_of_squares.sqrt())
 }
 
+pub(crate) fn dot(v1: &[f32], v2: &[f32]) -> Result<f32, String> {
+    if v1.len() != v2.len() {
+        return Err(format!(
+            "Vector lengths do not match: {} != {}",
+            v1.len(),
+            v2.len()
+        ));
+    }
+
+    Ok(v1.iter().zip(v2).map(|(a, b)| a * b).sum())
+}
+
+pub(crate) fn manhattan(v1: &[f32], v2: &[f32]) -> Result<f32, String> {
+    if v1.len() != v2.len() {
+        return Err(format!(
+            "Vector lengths do not match: {} != {}",
+            v1.len(),
+            v2.len()
+        ));
+    }
+
+    Ok(v1.iter().zip(v2).map(|(a, b)| (a - b).abs()).sum())
+}
+
 #[test]
 fn cosine_test() {
     let v1 = vec![1.0, 2.0, 3.0];
@@ -83,3 +240,93 @@ fn cosine_test_opposite() {
         expected
     );
 }
+
+#[test]
+fn normalize_test() {
+    let mut vector = vec![3.0, 4.0];
+    normalize(&mut vector);
+    assert_eq!(vector, vec![0.6, 0.8]);
+
+    let mut zero = vec![0.0, 0.0];
+    normalize(&mut zero);
+    assert_eq!(zero, vec![0.0, 0.0]);
+}
+
+#[test]
+fn recommendation_test() {
+    let positive = vec![vec![1.0, 0.0], vec![0.0, 1.0]];
+    assert_eq!(
+        recommendation(&positive, &[], Metric::Euclidean),
+        vec![0.5, 0.5]
+    );
+    assert_eq!(
+        recommendation(&positive, &[vec![1.0, 1.0]], Metric::Euclidean),
+        vec![0.0, 0.0]
+    );
+
+    // for cosine, a long example doesn't outweigh a short one
+    let query = recommendation(&[vec![10.0, 0.0], vec![0.0, 1.0]], &[], Metric::Cosine);
+    assert_eq!(query, vec![0.5, 0.5]);
+}
+
+#[test]
+fn dot_test() {
+    let v1 = vec![1.0, 2.0, 3.0];
+    let v2 = vec![3.0, 2.0, 1.0];
+    let result = dot(&v1, &v2).unwrap();
+    let expected = 10.0;
+    assert!(
+        (result - expected).abs() < 0.001,
+        "result ({}) != expected ({})",
+        result,
+        expected
+    );
+}
+
+#[test]
+fn manhattan_test() {
+    let v1 = vec![1.0, 2.0, 3.0];
+    let v2 = vec![3.0, 2.0, 1.0];
+    let result = manhattan(&v1, &v2).unwrap();
+    let expected = 4.0;
+    assert!(
+        (result - expected).abs() < 0.001,
+        "result ({}) != expected ({})",
+        result,
+        expected
+    );
+}
+
+#[test]
+fn distance_metrics_prefer_nearer() {
+    let v1 = vec![1.0, 2.0, 3.0];
+    let near = vec![1.0, 2.0, 3.5];
+    let far = vec![1.0, 2.0, 5.0];
+    for metric in [Metric::Euclidean, Metric::Manhattan] {
+        assert!(metric.similarity(&v1, &near).unwrap() > metric.similarity(&v1, &far).unwrap());
+    }
+}
+
+#[test]
+fn scores_are_normalized_and_keep_their_order() {
+    let v1 = vec![1.0, 2.0, 3.0];
+    let near = vec![1.0, 2.0, 3.5];
+    let far = vec![-1.0, -2.0, 5.0];
+    for metric in [
+        Metric::Cosine,
+        Metric::Dot,
+        Metric::Euclidean,
+        Metric::Manhattan,
+    ] {
+        let near = metric.score(metric.similarity(&v1, &near).unwrap());
+        let far = metric.score(metric.similarity(&v1, &far).unwrap());
+        assert!((0.0..=1.0).contains(&near) && (0.0..=1.0).contains(&far));
+        assert!(near > far, "{metric:?}: {near} <= {far}");
+    }
+    assert_eq!(Metric::Euclidean.score(0.0), 1.0);
+    assert_eq!(Metric::Cosine.score(-1.0), 0.0);
+    assert_eq!(
+        Metric::Euclidean.raw(Metric::Euclidean.similarity(&v1, &v1).unwrap()),
+        0.0
+    );
+}
@@ -0,0 +1,28 @@
+//! An overview of what's in a database, see [`crate::Victor::stats`].
+
+use serde::Serialize;
+
+/// An overview of a database, from [`crate::Victor::stats`].
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Stats {
+    /// How many documents the database has, not counting deleted or expired ones.
+    pub documents: usize,
+    /// How many db files the embeddings are stored in.
+    pub db_files: usize,
+    /// The dimension of embeddings as they're added and searched for, or `None` if none have been added yet.
+    pub dimension: Option<usize>,
+    /// How embeddings are projected to fewer dimensions, if they are (see [`crate::Victor::project`]).
+    pub projection: Option<ProjectionStats>,
+}
+
+/// How a database's embeddings are projected, see [`Stats::projection`].
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct ProjectionStats {
+    /// How many dimensions embeddings are stored with.
+    pub dimensions: usize,
+    /// The fraction of the variance between the embeddings the projection was trained on that it keeps,
+    /// between 0 and 1. `None` for databases projected before this was recorded.
+    pub explained_variance: Option<f32>,
+    /// Whether the original vectors are kept, see [`crate::Victor::with_retained_originals`].
+    pub retains_originals: bool,
+}
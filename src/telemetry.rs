@@ -0,0 +1,195 @@
+//! Metrics about what the database is doing, reported through the [`metrics`](https://docs.rs/metrics) facade
+//! with the `metrics` feature, so they can be scraped (say, by Prometheus) with whichever exporter the
+//! application installs. Without the feature, nothing is recorded.
+//!
+//! | Metric | Kind | Labels |
+//! |---|---|---|
+//! | `victor_documents_inserted_total` | counter | |
+//! | `victor_documents_updated_total` | counter | |
+//! | `victor_documents_deleted_total` | counter | |
+//! | `victor_searches_total` | counter | `kind` |
+//! | `victor_search_duration_seconds` | histogram | `kind` |
+//! | `victor_search_results` | histogram | `kind` |
+//! | `victor_scanned_bytes_total` | counter | |
+//! | `victor_cache_hits_total` | counter | `cache` |
+//! | `victor_cache_misses_total` | counter | `cache` |
+//! | `victor_file_bytes` | gauge | `file` |
+//!
+//! The `kind` of a search is `vector`, `text`, `hybrid`, `keyword`, `similar` or `recommend`.
+//! The `cache` looked up is `catalog`, or, with a cache budget, `segments`, `contents` or `projection`.
+
+pub(crate) fn inserted(documents: usize) {
+    #[cfg(feature = "metrics")]
+    metrics::counter!("victor_documents_inserted_total").increment(documents as u64);
+    #[cfg(not(feature = "metrics"))]
+    let _ = documents;
+}
+
+pub(crate) fn updated(documents: usize) {
+    #[cfg(feature = "metrics")]
+    metrics::counter!("victor_documents_updated_total").increment(documents as u64);
+    #[cfg(not(feature = "metrics"))]
+    let _ = documents;
+}
+
+pub(crate) fn deleted(documents: usize) {
+    #[cfg(feature = "metrics")]
+    metrics::counter!("victor_documents_deleted_total").increment(documents as u64);
+    #[cfg(not(feature = "metrics"))]
+    let _ = documents;
+}
+
+/// Bytes of encoded embeddings scanned by a search.
+pub(crate) fn scanned(bytes: usize) {
+    #[cfg(feature = "metrics")]
+    metrics::counter!("victor_scanned_bytes_total").increment(bytes as u64);
+    #[cfg(not(feature = "metrics"))]
+    let _ = bytes;
+}
+
+/// Whether something looked up in `cache` was there.
+pub(crate) fn cache(cache: &'static str, hit: bool) {
+    #[cfg(feature = "metrics")]
+    match hit {
+        true => metrics::counter!("victor_cache_hits_total", "cache" => cache).increment(1),
+        false => metrics::counter!("victor_cache_misses_total", "cache" => cache).increment(1),
+    }
+    #[cfg(not(feature = "metrics"))]
+    let _ = (cache, hit);
+}
+
+/// The size of a file in the database, once it's written.
+pub(crate) fn file_size(file: &str, bytes: usize) {
+    #[cfg(feature = "metrics")]
+    metrics::gauge!("victor_file_bytes", "file" => file.to_string()).set(bytes as f64);
+    #[cfg(not(feature = "metrics"))]
+    let _ = (file, bytes);
+}
+
+/// Times a search, from when it's started until it's finished.
+pub(crate) struct Search {
+    kind: &'static str,
+    #[cfg(feature = "metrics")]
+    started: f64,
+}
+
+impl Search {
+    pub(crate) fn start(kind: &'static str) -> Self {
+        Self {
+            kind,
+            #[cfg(feature = "metrics")]
+            started: now_seconds(),
+        }
+    }
+
+    pub(crate) fn finish(self, results: usize) {
+        #[cfg(feature = "metrics")]
+        {
+            metrics::counter!("victor_searches_total", "kind" => self.kind).increment(1);
+            metrics::histogram!("victor_search_duration_seconds", "kind" => self.kind)
+                .record(now_seconds() - self.started);
+            metrics::histogram!("victor_search_results", "kind" => self.kind)
+                .record(results as f64);
+        }
+        #[cfg(not(feature = "metrics"))]
+        let _ = (self.kind, results);
+    }
+}
+
+/// The current time in seconds, to time searches with. `Instant` isn't available on the web.
+#[cfg(feature = "metrics")]
+fn now_seconds() -> f64 {
+    #[cfg(not(target_arch = "wasm32"))]
+    return std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0.0, |since_epoch| since_epoch.as_secs_f64());
+    #[cfg(target_arch = "wasm32")]
+    return js_sys::Date::now() / 1000.0;
+}
+
+#[cfg(all(test, feature = "metrics"))]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc, Mutex,
+        },
+    };
+
+    use metrics::{
+        Counter, CounterFn, Gauge, Histogram, Key, KeyName, Metadata, Recorder, SharedString, Unit,
+    };
+
+    use crate::memory::{Db, DirectoryHandle};
+
+    /// Keeps the total of every counter, by name.
+    #[derive(Default)]
+    struct Counters(Mutex<HashMap<String, Arc<Total>>>);
+
+    #[derive(Default)]
+    struct Total(AtomicU64);
+
+    impl CounterFn for Total {
+        fn increment(&self, value: u64) {
+            self.0.fetch_add(value, Ordering::Relaxed);
+        }
+
+        fn absolute(&self, value: u64) {
+            self.0.fetch_max(value, Ordering::Relaxed);
+        }
+    }
+
+    impl Counters {
+        fn total(&self, name: &str) -> u64 {
+            let counters = self.0.lock().unwrap();
+            counters
+                .get(name)
+                .map_or(0, |total| total.0.load(Ordering::Relaxed))
+        }
+    }
+
+    impl Recorder for Counters {
+        fn describe_counter(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+        fn describe_gauge(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+        fn describe_histogram(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+        fn register_counter(&self, key: &Key, _: &Metadata<'_>) -> Counter {
+            let mut counters = self.0.lock().unwrap();
+            Counter::from_arc(counters.entry(key.name().to_string()).or_default().clone())
+        }
+
+        fn register_gauge(&self, _: &Key, _: &Metadata<'_>) -> Gauge {
+            Gauge::noop()
+        }
+
+        fn register_histogram(&self, _: &Key, _: &Metadata<'_>) -> Histogram {
+            Histogram::noop()
+        }
+    }
+
+    #[test]
+    fn counts_inserts_and_searches() {
+        let counters = Counters::default();
+        metrics::with_local_recorder(&counters, || {
+            tokio_test::block_on(async {
+                let mut victor = Db::new(DirectoryHandle::default());
+                victor
+                    .add_embeddings(
+                        vec![("hello", vec![1.0, 0.0]), ("hi", vec![0.0, 1.0])],
+                        vec!["greeting"],
+                    )
+                    .await
+                    .unwrap();
+                victor
+                    .search_embedding(vec![1.0, 0.0], vec!["greeting"], 1)
+                    .await
+                    .unwrap();
+            })
+        });
+
+        assert_eq!(counters.total("victor_documents_inserted_total"), 2);
+        assert_eq!(counters.total("victor_searches_total"), 1);
+        assert!(counters.total("victor_scanned_bytes_total") > 0);
+    }
+}
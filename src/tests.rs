@@ -1,4 +1,8 @@
-use crate::memory::{Db, DirectoryHandle};
+use crate::{
+    memory::{Db, DirectoryHandle},
+    BinaryConfig, Document, Error, Filter, HnswConfig, LshConfig, Metric, PqConfig, Projection,
+    SearchOptions, Settings, Storage, TagFilter,
+};
 
 #[tokio::test]
 async fn store_and_retrieve() {
@@ -8,11 +12,13 @@ async fn store_and_retrieve() {
 
     victor
         .add_single_embedding("hello", embedding.clone(), Vec::<String>::new())
-        .await;
+        .await
+        .unwrap();
 
     let result = victor
         .search_embedding(embedding, Vec::<String>::new(), 1)
         .await
+        .unwrap()
         .first()
         .unwrap()
         .content
@@ -30,15 +36,18 @@ async fn store_two_and_retrieve() {
 
     victor
         .add_single_embedding("hello", embedding_1.clone(), Vec::<String>::new())
-        .await;
+        .await
+        .unwrap();
     victor
         .add_single_embedding("goodbye", embedding_2.clone(), Vec::<String>::new())
-        .await;
+        .await
+        .unwrap();
 
     {
         let result = victor
             .search_embedding(embedding_1, Vec::<String>::new(), 1)
             .await
+            .unwrap()
             .first()
             .unwrap()
             .content
@@ -50,6 +59,7 @@ async fn store_two_and_retrieve() {
         let result = victor
             .search_embedding(embedding_2, Vec::<String>::new(), 1)
             .await
+            .unwrap()
             .first()
             .unwrap()
             .content
@@ -68,15 +78,18 @@ async fn store_two_and_retrieve_with_tags() {
 
     victor
         .add_single_embedding("hello", embedding_1.clone(), vec!["greetings".to_string()])
-        .await;
+        .await
+        .unwrap();
     victor
         .add_single_embedding("goodbye", embedding_2.clone(), vec!["goodbyes".to_string()])
-        .await;
+        .await
+        .unwrap();
 
     {
         let result = victor
             .search_embedding(embedding_1.clone(), Vec::<String>::new(), 1)
             .await
+            .unwrap()
             .first()
             .unwrap()
             .content
@@ -88,6 +101,7 @@ async fn store_two_and_retrieve_with_tags() {
         let result = victor
             .search_embedding(embedding_2.clone(), Vec::<String>::new(), 1)
             .await
+            .unwrap()
             .first()
             .unwrap()
             .content
@@ -100,6 +114,7 @@ async fn store_two_and_retrieve_with_tags() {
         let result = victor
             .search_embedding(embedding_1.clone(), vec!["goodbyes".to_string()], 1)
             .await
+            .unwrap()
             .first()
             .unwrap()
             .content
@@ -111,6 +126,7 @@ async fn store_two_and_retrieve_with_tags() {
         let result = victor
             .search_embedding(embedding_2, vec!["greetings".to_string()], 1)
             .await
+            .unwrap()
             .first()
             .unwrap()
             .clone();
@@ -120,15 +136,15 @@ async fn store_two_and_retrieve_with_tags() {
     {
         let result = victor
             .search_embedding(embedding_1, vec!["mysterious".to_string()], 1)
-            .await;
+            .await
+            .unwrap();
 
         assert_eq!(result.first(), None);
     }
 }
 
-#[should_panic]
 #[tokio::test]
-async fn incompatible_size_panic() {
+async fn incompatible_size() {
     let embedding_1 = vec![1.0, 2.0, 3.0];
     let embedding_2 = vec![1.0, 2.0, 3.0, 4.0];
 
@@ -136,26 +152,5176 @@ async fn incompatible_size_panic() {
 
     victor
         .add_single_embedding("hello", embedding_1, Vec::<String>::new())
-        .await;
-    victor
+        .await
+        .unwrap();
+    let result = victor
         .add_single_embedding("hello", embedding_2, Vec::<String>::new())
         .await;
+    assert!(matches!(
+        result,
+        Err(Error::DimensionMismatch {
+            expected: 3,
+            found: 4
+        })
+    ));
+
+    // searching with the wrong dimension is an error too
+    let result = victor
+        .search_embedding(vec![1.0, 2.0], Vec::<String>::new(), 1)
+        .await;
+    assert!(matches!(result, Err(Error::DimensionMismatch { .. })));
 }
 
+#[cfg(feature = "embeddings")]
 #[tokio::test]
 async fn add() {
     let mut victor = Db::new(DirectoryHandle::default());
 
     victor
         .add(vec!["pineapple", "rocks"], Vec::<String>::new())
-        .await;
+        .await
+        .unwrap();
 
     let result = victor
         .search("hawaiian pizza", Vec::<String>::new(), 1)
         .await
+        .unwrap()
         .first()
         .unwrap()
         .content
         .clone();
     assert_eq!(result, "pineapple");
 }
+
+#[tokio::test]
+async fn update() {
+    let embedding_1 = vec![1.0, 2.0, 3.0];
+    let embedding_2 = vec![-1.0, -2.0, -3.0];
+
+    let mut victor = Db::new(DirectoryHandle::default());
+
+    victor
+        .add_single_embedding("hello", embedding_1.clone(), vec!["greetings".to_string()])
+        .await
+        .unwrap();
+    victor
+        .add_single_embedding("goodbye", embedding_2.clone(), vec!["goodbyes".to_string()])
+        .await
+        .unwrap();
+
+    let id = victor
+        .search_embedding(embedding_1.clone(), Vec::<String>::new(), 1)
+        .await
+        .unwrap()
+        .first()
+        .unwrap()
+        .embedding
+        .id;
+
+    let updated = victor
+        .update(id, "hello again", vec![3.0, 2.0, 1.0])
+        .await
+        .unwrap();
+    assert!(updated);
+
+    // the old version is gone and the document kept its tags
+    let results = victor
+        .search_embedding(vec![3.0, 2.0, 1.0], vec!["greetings".to_string()], 10)
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].content, "hello again");
+    assert_eq!(results[0].embedding.id, id);
+
+    // the other document is untouched
+    let result = victor
+        .search_embedding(embedding_2, vec!["goodbyes".to_string()], 1)
+        .await
+        .unwrap();
+    assert_eq!(result[0].content, "goodbye");
+}
+
+#[tokio::test]
+async fn update_missing() {
+    let mut victor = Db::new(DirectoryHandle::default());
+
+    victor
+        .add_single_embedding("hello", vec![1.0, 2.0, 3.0], Vec::<String>::new())
+        .await
+        .unwrap();
+
+    let updated = victor
+        .update(uuid::Uuid::new_v4(), "nope", vec![1.0, 2.0, 3.0])
+        .await
+        .unwrap();
+    assert!(!updated);
+}
+
+#[tokio::test]
+async fn external_ids() {
+    let embedding_1 = vec![1.0, 2.0, 3.0];
+    let embedding_2 = vec![-1.0, -2.0, -3.0];
+
+    let mut victor = Db::new(DirectoryHandle::default());
+
+    victor
+        .add_embeddings_with_ids(
+            vec![
+                ("doc-1", "hello", embedding_1.clone()),
+                ("doc-2", "goodbye", embedding_2.clone()),
+            ],
+            Vec::<String>::new(),
+        )
+        .await
+        .unwrap();
+    victor
+        .add_single_embedding("no id", vec![1.0, 1.0, 1.0], Vec::<String>::new())
+        .await
+        .unwrap();
+
+    let result = victor
+        .search_embedding(embedding_2.clone(), Vec::<String>::new(), 1)
+        .await
+        .unwrap();
+    assert_eq!(result[0].external_id.as_deref(), Some("doc-2"));
+
+    let result = victor
+        .search_embedding(vec![1.0, 1.0, 1.0], Vec::<String>::new(), 1)
+        .await
+        .unwrap();
+    assert_eq!(result[0].external_id, None);
+
+    // adding the same id again updates the existing document
+    victor
+        .add_embeddings_with_ids(
+            vec![("doc-1", "hello again", embedding_1.clone())],
+            Vec::<String>::new(),
+        )
+        .await
+        .unwrap();
+
+    let results = victor
+        .search_embedding(embedding_1, Vec::<String>::new(), 10)
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 3);
+    assert_eq!(results[0].content, "hello again");
+    assert_eq!(results[0].external_id.as_deref(), Some("doc-1"));
+}
+
+#[tokio::test]
+async fn search_with_metadata_filter() {
+    let mut victor = Db::new(DirectoryHandle::default());
+
+    victor
+        .add_documents(
+            vec![
+                Document::new("closest", vec![1.0, 2.0, 3.0]).with_metadata("author", "bob"),
+                Document::new("close", vec![1.0, 2.0, 2.0]).with_metadata("author", "alice"),
+                Document::new("far", vec![-1.0, -2.0, -3.0]).with_metadata("author", "carol"),
+            ],
+            Vec::<String>::new(),
+        )
+        .await
+        .unwrap();
+
+    let options = SearchOptions::default().with_filter(Filter::eq("author", "alice"));
+    let results = victor
+        .search_embedding_with_options(vec![1.0, 2.0, 3.0], Vec::<String>::new(), 1, &options)
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].content, "close");
+
+    let options = SearchOptions::default().with_filter(Filter::is_in("author", ["alice", "carol"]));
+    let results = victor
+        .search_embedding_with_options(vec![1.0, 2.0, 3.0], Vec::<String>::new(), 10, &options)
+        .await
+        .unwrap();
+    let contents = results
+        .iter()
+        .map(|r| r.content.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(contents, vec!["close", "far"]);
+}
+
+#[tokio::test]
+async fn search_with_range_filter() {
+    let mut victor = Db::new(DirectoryHandle::default());
+
+    victor
+        .add_documents(
+            vec![
+                Document::new("cheap pizza", vec![1.0, 2.0, 3.0]).with_metadata("price", 8.0),
+                Document::new("fancy pizza", vec![1.0, 2.0, 3.0]).with_metadata("price", 120.0),
+            ],
+            vec!["pizza"],
+        )
+        .await
+        .unwrap();
+    victor
+        .add_documents(
+            vec![Document::new("caviar", vec![1.0, 2.0, 3.0]).with_metadata("price", 500.0)],
+            vec!["luxury"],
+        )
+        .await
+        .unwrap();
+
+    let options = SearchOptions::default().with_filter(Filter::lt("price", 100.0));
+    let results = victor
+        .search_embedding_with_options(vec![1.0, 2.0, 3.0], Vec::<String>::new(), 10, &options)
+        .await
+        .unwrap();
+    let contents = results
+        .iter()
+        .map(|r| r.content.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(contents, vec!["cheap pizza"]);
+
+    let options = SearchOptions::default().with_filter(Filter::between("price", 100.0, 1000.0));
+    let results = victor
+        .search_embedding_with_options(vec![1.0, 2.0, 3.0], Vec::<String>::new(), 10, &options)
+        .await
+        .unwrap();
+    let mut contents = results
+        .iter()
+        .map(|r| r.content.as_str())
+        .collect::<Vec<_>>();
+    contents.sort();
+    assert_eq!(contents, vec!["caviar", "fancy pizza"]);
+}
+
+#[tokio::test]
+async fn search_with_tag_filter() {
+    let mut victor = Db::new(DirectoryHandle::default());
+
+    victor
+        .add_single_embedding("a", vec![1.0, 2.0, 3.0], vec!["a"])
+        .await
+        .unwrap();
+    victor
+        .add_single_embedding("b", vec![1.0, 2.0, 3.0], vec!["b"])
+        .await
+        .unwrap();
+    victor
+        .add_single_embedding("a and c", vec![1.0, 2.0, 3.0], vec!["a", "c"])
+        .await
+        .unwrap();
+    victor
+        .add_single_embedding("b and c", vec![1.0, 2.0, 3.0], vec!["b", "c"])
+        .await
+        .unwrap();
+
+    let search = |tags: TagFilter| {
+        let victor = &victor;
+        async move {
+            let mut contents = victor
+                .search_embedding(vec![1.0, 2.0, 3.0], tags, 10)
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|result| result.content)
+                .collect::<Vec<_>>();
+            contents.sort();
+            contents
+        }
+    };
+
+    assert_eq!(
+        search(TagFilter::any(["a", "b"])).await,
+        vec!["a", "a and c", "b", "b and c"]
+    );
+    assert_eq!(
+        search(TagFilter::any(["a", "b"]) & TagFilter::tag("c")).await,
+        vec!["a and c", "b and c"]
+    );
+    assert_eq!(
+        search(TagFilter::tag("a") | TagFilter::all(["b", "c"])).await,
+        vec!["a", "a and c", "b and c"]
+    );
+}
+
+#[tokio::test]
+async fn search_excluding_tags() {
+    let mut victor = Db::new(DirectoryHandle::default());
+
+    victor
+        .add_single_embedding("current", vec![1.0, 2.0, 3.0], vec!["docs"])
+        .await
+        .unwrap();
+    victor
+        .add_single_embedding("old", vec![1.0, 2.0, 3.0], vec!["docs", "archived"])
+        .await
+        .unwrap();
+
+    let results = victor
+        .search_embedding(
+            vec![1.0, 2.0, 3.0],
+            TagFilter::tag("docs") & !TagFilter::tag("archived"),
+            10,
+        )
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].content, "current");
+}
+
+#[tokio::test]
+async fn list_tags() {
+    let mut victor = Db::new(DirectoryHandle::default());
+
+    assert!(victor.tags().await.unwrap().is_empty());
+
+    victor
+        .add_single_embedding("hello", vec![1.0, 2.0, 3.0], vec!["greetings", "english"])
+        .await
+        .unwrap();
+    victor
+        .add_single_embedding("hola", vec![1.0, 2.0, 3.0], vec!["greetings", "spanish"])
+        .await
+        .unwrap();
+    victor
+        .add_single_embedding("untagged", vec![1.0, 2.0, 3.0], Vec::<String>::new())
+        .await
+        .unwrap();
+
+    assert_eq!(
+        victor.tags().await.unwrap(),
+        vec!["english", "greetings", "spanish"]
+    );
+    assert_eq!(
+        victor.tag_sets().await.unwrap(),
+        vec![
+            vec![],
+            vec!["english", "greetings"],
+            vec!["greetings", "spanish"],
+        ]
+    );
+}
+
+#[tokio::test]
+async fn hnsw_index() {
+    let mut victor = Db::new(DirectoryHandle::default());
+
+    victor
+        .add_single_embedding("hello", vec![1.0, 2.0, 3.0], vec!["greetings"])
+        .await
+        .unwrap();
+    victor
+        .add_single_embedding("goodbye", vec![-1.0, -2.0, -3.0], vec!["greetings"])
+        .await
+        .unwrap();
+
+    victor
+        .build_hnsw_index(HnswConfig::default())
+        .await
+        .unwrap();
+
+    // documents added after the index was built are linked into it
+    victor
+        .add_single_embedding("hi", vec![3.0, 2.0, 1.0], vec!["greetings"])
+        .await
+        .unwrap();
+
+    let results = victor
+        .search_embedding(vec![3.0, 2.0, 1.0], vec!["greetings"], 1)
+        .await
+        .unwrap();
+    assert_eq!(results[0].content, "hi");
+
+    // as are updated ones
+    let id = results[0].embedding.id;
+    victor
+        .update(id, "bye", vec![-3.0, -2.0, -1.0])
+        .await
+        .unwrap();
+    let results = victor
+        .search_embedding(vec![-3.0, -2.0, -1.0], vec!["greetings"], 3)
+        .await
+        .unwrap();
+    assert_eq!(
+        results
+            .iter()
+            .map(|result| result.content.as_str())
+            .collect::<Vec<_>>(),
+        vec!["bye", "goodbye", "hello"]
+    );
+}
+
+#[tokio::test]
+async fn hnsw_index_appends_changes() {
+    use crate::{
+        db::Index,
+        filesystem::{DirectoryHandle as _, FileHandle as _, GetFileHandleOptions},
+        hnsw,
+    };
+
+    let directory = DirectoryHandle::default();
+    let mut victor = Db::new(directory.clone());
+
+    // enough nodes that linking in a new one only changes some of them
+    let documents = (0..100)
+        .map(|i| {
+            let angle = i as f32 / 10.0;
+            (format!("document {i}"), vec![angle.cos(), angle.sin(), 0.0])
+        })
+        .collect();
+    victor
+        .add_embeddings(documents, vec!["greetings"])
+        .await
+        .unwrap();
+    victor
+        .build_hnsw_index(HnswConfig::default())
+        .await
+        .unwrap();
+
+    let filename = hnsw::filename_for_segment(&Index::filename_for_part(
+        ["greetings".to_string()].into(),
+        0,
+    ));
+    let read_graph = || async {
+        directory
+            .get_file_handle_with_options(&filename, &GetFileHandleOptions { create: false })
+            .await
+            .unwrap()
+            .read()
+            .await
+            .unwrap()
+    };
+    let built = read_graph().await;
+
+    // new documents are linked in by appending to the graph, not by rewriting it
+    victor
+        .add_single_embedding("hello", vec![0.0, 0.0, 1.0], vec!["greetings"])
+        .await
+        .unwrap();
+    victor
+        .add_single_embedding("goodbye", vec![0.0, 0.0, -1.0], vec!["greetings"])
+        .await
+        .unwrap();
+    let appended = read_graph().await;
+    assert!(appended.len() > built.len());
+    assert_eq!(appended[..built.len()], built[..]);
+
+    for (vector, content) in [
+        (vec![0.0, 0.1, 1.0], "hello"),
+        (vec![0.1, 0.0, -1.0], "goodbye"),
+    ] {
+        let results = victor
+            .search_embedding(vector, vec!["greetings"], 1)
+            .await
+            .unwrap();
+        assert_eq!(results[0].content, content);
+    }
+}
+
+#[tokio::test]
+async fn lsh_index() {
+    let mut victor = Db::new(DirectoryHandle::default());
+
+    victor
+        .add_single_embedding("hello", vec![1.0, 2.0, 3.0], vec!["greetings"])
+        .await
+        .unwrap();
+
+    // index even the smallest db files, so the index is actually used
+    let config = LshConfig {
+        exact_below: 0,
+        ..Default::default()
+    };
+    victor.build_lsh_index(config).await.unwrap();
+
+    victor
+        .add_single_embedding("goodbye", vec![-1.0, -2.0, -3.0], vec!["greetings"])
+        .await
+        .unwrap();
+
+    // opposite vectors never share a bucket, so only one candidate is found
+    let results = victor
+        .search_embedding(vec![-1.0, -2.0, -3.0], vec!["greetings"], 1)
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].content, "goodbye");
+
+    // but asking for more results than there are candidates falls back to a full scan
+    let results = victor
+        .search_embedding(vec![-1.0, -2.0, -3.0], vec!["greetings"], 2)
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 2);
+}
+
+#[tokio::test]
+async fn lsh_index_appends_signatures() {
+    use crate::{
+        db::Index,
+        filesystem::{DirectoryHandle as _, FileHandle as _, GetFileHandleOptions},
+        lsh,
+    };
+
+    let directory = DirectoryHandle::default();
+    let mut victor = Db::new(directory.clone());
+
+    victor
+        .add_single_embedding("hello", vec![1.0, 2.0, 3.0], vec!["greetings"])
+        .await
+        .unwrap();
+    let config = LshConfig {
+        exact_below: 0,
+        ..Default::default()
+    };
+    victor.build_lsh_index(config).await.unwrap();
+
+    let filename = lsh::filename_for_segment(&Index::filename_for_part(
+        ["greetings".to_string()].into(),
+        0,
+    ));
+    let read_signatures = || async {
+        directory
+            .get_file_handle_with_options(&filename, &GetFileHandleOptions { create: false })
+            .await
+            .unwrap()
+            .read()
+            .await
+            .unwrap()
+    };
+    let built = read_signatures().await;
+
+    // new documents get their signatures appended, rather than the file being rewritten
+    victor
+        .add_single_embedding("goodbye", vec![-1.0, -2.0, -3.0], vec!["greetings"])
+        .await
+        .unwrap();
+    let appended = read_signatures().await;
+    assert!(appended.len() > built.len());
+    assert_eq!(appended[..built.len()], built[..]);
+
+    let results = victor
+        .search_embedding(vec![-1.0, -2.0, -3.0], vec!["greetings"], 1)
+        .await
+        .unwrap();
+    assert_eq!(results[0].content, "goodbye");
+}
+
+#[tokio::test]
+async fn product_quantization() {
+    let mut victor = Db::new(DirectoryHandle::default());
+
+    victor
+        .add_single_embedding("hello", vec![1.0, 2.0, 3.0, 4.0], vec!["greetings"])
+        .await
+        .unwrap();
+    victor
+        .add_single_embedding("goodbye", vec![-1.0, -2.0, -3.0, -4.0], vec!["greetings"])
+        .await
+        .unwrap();
+
+    let config = PqConfig {
+        subspaces: 2,
+        ..Default::default()
+    };
+    victor.train_product_quantizer(config).await.unwrap();
+
+    // embeddings added after training are encoded with the same codebooks
+    victor
+        .add_single_embedding("hi", vec![1.0, 2.0, 3.0, 4.5], Vec::<String>::new())
+        .await
+        .unwrap();
+
+    let results = victor
+        .search_embedding(vec![-1.0, -2.0, -3.0, -4.0], vec!["greetings"], 1)
+        .await
+        .unwrap();
+    assert_eq!(results[0].content, "goodbye");
+    assert!((results[0].similarity - 1.0).abs() < 0.001);
+
+    let results = victor
+        .search_embedding(vec![1.0, 2.0, 3.0, 4.0], Vec::<String>::new(), 3)
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 3);
+    assert_eq!(results[2].content, "goodbye");
+
+    // the codebooks only work with one dimension
+    let result = victor
+        .add_single_embedding("short", vec![1.0, 2.0], Vec::<String>::new())
+        .await;
+    assert!(matches!(result, Err(Error::DimensionMismatch { .. })));
+}
+
+#[tokio::test]
+async fn binary_quantization() {
+    for rerank in [true, false] {
+        let mut victor = Db::new(DirectoryHandle::default());
+
+        victor
+            .add_single_embedding("hello", vec![1.0, 2.0, -3.0, 4.0], Vec::<String>::new())
+            .await
+            .unwrap();
+        victor
+            .add_single_embedding("goodbye", vec![-1.0, -2.0, 3.0, -4.0], Vec::<String>::new())
+            .await
+            .unwrap();
+
+        victor
+            .enable_binary_quantization(BinaryConfig { rerank })
+            .await
+            .unwrap();
+
+        victor
+            .add_single_embedding("hi", vec![1.0, 2.0, -3.0, -4.0], Vec::<String>::new())
+            .await
+            .unwrap();
+
+        let results = victor
+            .search_embedding(vec![1.0, 2.0, -3.0, 4.0], Vec::<String>::new(), 3)
+            .await
+            .unwrap();
+        assert_eq!(
+            results
+                .iter()
+                .map(|result| result.content.as_str())
+                .collect::<Vec<_>>(),
+            vec!["hello", "hi", "goodbye"]
+        );
+        if !rerank {
+            // estimated from the Hamming distance
+            assert_eq!(results[1].similarity, 0.5);
+        }
+    }
+}
+
+#[tokio::test]
+async fn full_storage() {
+    let settings = Settings {
+        storage: Storage::Full,
+        ..Default::default()
+    };
+    let mut victor = Db::with_settings(DirectoryHandle::default(), settings)
+        .await
+        .unwrap();
+
+    let vector = vec![0.123, -0.456, 0.789, 1e-4];
+    victor
+        .add_single_embedding("hello", vector.clone(), Vec::<String>::new())
+        .await
+        .unwrap();
+
+    // vectors aren't packed, so they come back exactly as they were added
+    let results = victor
+        .search_embedding(vector.clone(), Vec::<String>::new(), 1)
+        .await
+        .unwrap();
+    assert_eq!(results[0].embedding.vector, vector);
+}
+
+#[tokio::test]
+async fn settings() {
+    let directory = DirectoryHandle::default();
+    let settings = Settings {
+        metric: Metric::Dot,
+        ..Default::default()
+    };
+    let mut victor = Db::with_settings(directory.clone(), settings)
+        .await
+        .unwrap();
+
+    victor
+        .add_single_embedding("hello", vec![1.0, 2.0, 3.0], vec!["greeting"])
+        .await
+        .unwrap();
+
+    // the first embedding decides the dimension
+    assert_eq!(
+        victor.settings().await.unwrap(),
+        Settings {
+            dimension: Some(3),
+            ..settings
+        }
+    );
+
+    // which every other embedding must have, even in another db file
+    let result = victor
+        .add_single_embedding("goodbye", vec![1.0, 2.0], vec!["farewell"])
+        .await;
+    assert!(matches!(
+        result,
+        Err(Error::DimensionMismatch {
+            expected: 3,
+            found: 2
+        })
+    ));
+    let result = victor
+        .search_embedding(vec![1.0, 2.0], Vec::<String>::new(), 1)
+        .await;
+    assert!(matches!(result, Err(Error::DimensionMismatch { .. })));
+
+    // searches use the database's metric
+    let results = victor
+        .search_embedding(vec![1.0, 2.0, 3.0], Vec::<String>::new(), 1)
+        .await
+        .unwrap();
+    assert!((results[0].similarity - 14.0).abs() < 0.1);
+
+    // the settings can't change once there are embeddings
+    let reopened = Db::with_settings(directory.clone(), Settings::default()).await;
+    assert!(matches!(reopened, Err(Error::InvalidInput(_))));
+    let reopened = Db::with_settings(directory, settings).await.unwrap();
+    assert_eq!(reopened.settings().await.unwrap().dimension, Some(3));
+}
+
+#[tokio::test]
+async fn metrics() {
+    let mut victor = Db::new(DirectoryHandle::default());
+
+    // pointing the same way as the query, but much longer
+    victor
+        .add_single_embedding("long", vec![10.0, 0.0], Vec::<String>::new())
+        .await
+        .unwrap();
+    // a little off, but close by
+    victor
+        .add_single_embedding("close", vec![1.0, 0.5], Vec::<String>::new())
+        .await
+        .unwrap();
+
+    let nearest = |metric| {
+        let victor = &victor;
+        async move {
+            victor
+                .search_embedding_with_options(
+                    vec![1.0, 0.0],
+                    Vec::<String>::new(),
+                    1,
+                    &SearchOptions::default().with_metric(metric),
+                )
+                .await
+                .unwrap()[0]
+                .content
+                .clone()
+        }
+    };
+
+    assert_eq!(nearest(Metric::Cosine).await, "long");
+    assert_eq!(nearest(Metric::Dot).await, "long");
+    assert_eq!(nearest(Metric::Euclidean).await, "close");
+    assert_eq!(nearest(Metric::Manhattan).await, "close");
+}
+
+#[tokio::test]
+async fn min_similarity() {
+    let mut victor = Db::new(DirectoryHandle::default());
+
+    victor
+        .add_single_embedding("same", vec![1.0, 0.0], Vec::<String>::new())
+        .await
+        .unwrap();
+    victor
+        .add_single_embedding("opposite", vec![-1.0, 0.0], Vec::<String>::new())
+        .await
+        .unwrap();
+
+    let results = victor
+        .search_embedding_with_options(
+            vec![1.0, 0.0],
+            Vec::<String>::new(),
+            10,
+            &SearchOptions::default().with_min_similarity(0.75),
+        )
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].content, "same");
+}
+
+#[tokio::test]
+async fn hybrid_search() {
+    let mut victor = Db::new(DirectoryHandle::default());
+
+    victor
+        .add_documents(
+            vec![
+                Document::new("Order SKU-4417 shipped late", vec![1.0, 0.0]),
+                Document::new("Where is my package?", vec![0.9, 0.1]),
+                Document::new("SKU-4417 is back in stock", vec![0.0, 1.0])
+                    .with_metadata("kind", "stock"),
+            ],
+            vec!["Support"],
+        )
+        .await
+        .unwrap();
+    victor
+        .add_single_embedding("SKU-4417 recall notice", vec![1.0, 0.0], vec!["News"])
+        .await
+        .unwrap();
+
+    // the first document is near the query and mentions the SKU, so it's ranked first by both
+    let results = victor
+        .search_hybrid_embedding(
+            vec![1.0, 0.0],
+            "sku 4417",
+            vec!["Support"],
+            10,
+            &SearchOptions::default(),
+        )
+        .await
+        .unwrap();
+    let contents = results
+        .iter()
+        .map(|result| result.content.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(contents.len(), 3);
+    assert_eq!(contents[0], "Order SKU-4417 shipped late");
+    assert!(!contents.contains(&"SKU-4417 recall notice"));
+
+    // keyword matches are filtered too
+    let options = SearchOptions::default().with_filter(Filter::eq("kind", "stock"));
+    let results = victor
+        .search_hybrid_embedding(vec![1.0, 0.0], "package", vec!["Support"], 10, &options)
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].content, "SKU-4417 is back in stock");
+}
+
+#[tokio::test]
+async fn keyword_index() {
+    let mut victor = Db::new(DirectoryHandle::default());
+
+    victor
+        .add_single_embedding("Error E1234 on startup", vec![1.0, 0.0], vec!["Support"])
+        .await
+        .unwrap();
+    victor.build_keyword_index().await.unwrap();
+    victor
+        .add_single_embedding("Error E5678 on shutdown", vec![0.0, 1.0], vec!["Support"])
+        .await
+        .unwrap();
+    victor
+        .add_single_embedding("Error E1234 explained", vec![0.0, 1.0], vec!["Docs"])
+        .await
+        .unwrap();
+
+    async fn search(victor: &Db, keywords: &str, tags: Vec<&str>) -> Vec<String> {
+        victor
+            .search_keywords(keywords, tags, 10)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|result| result.content)
+            .collect()
+    }
+
+    // documents added before and after the index was built are both found
+    assert_eq!(
+        search(&victor, "e1234", vec!["Support"]).await,
+        vec!["Error E1234 on startup"]
+    );
+    assert_eq!(
+        search(&victor, "e5678", vec![]).await,
+        vec!["Error E5678 on shutdown"]
+    );
+    assert_eq!(search(&victor, "error", vec![]).await.len(), 3);
+
+    let id = victor
+        .search_keywords("startup", Vec::<String>::new(), 1)
+        .await
+        .unwrap()[0]
+        .embedding
+        .id;
+    victor
+        .update(id, "Error E9999 on startup", vec![1.0, 0.0])
+        .await
+        .unwrap();
+    assert_eq!(
+        search(&victor, "e1234", vec![]).await,
+        vec!["Error E1234 explained"]
+    );
+    assert_eq!(
+        search(&victor, "e9999", vec![]).await,
+        vec!["Error E9999 on startup"]
+    );
+
+    victor.delete(id).await.unwrap();
+    assert!(search(&victor, "e9999", vec![]).await.is_empty());
+    victor.compact().await.unwrap();
+    assert_eq!(search(&victor, "error", vec![]).await.len(), 2);
+}
+
+#[tokio::test]
+async fn keyword_index_appends_changes() {
+    use crate::{
+        filesystem::{DirectoryHandle as _, FileHandle as _, GetFileHandleOptions},
+        keywords::InvertedIndex,
+    };
+
+    let directory = DirectoryHandle::default();
+    let mut victor = Db::new(directory.clone());
+
+    victor
+        .add_single_embedding("Error E1234 on startup", vec![1.0, 0.0], vec!["Support"])
+        .await
+        .unwrap();
+    victor.build_keyword_index().await.unwrap();
+
+    let read_index = || async {
+        directory
+            .get_file_handle_with_options("keywords.bin", &GetFileHandleOptions { create: false })
+            .await
+            .unwrap()
+            .read()
+            .await
+            .unwrap()
+    };
+    let built = read_index().await;
+
+    // new and deleted documents are appended to the index, rather than it being rewritten
+    victor
+        .add_single_embedding("Error E5678 on shutdown", vec![0.0, 1.0], vec!["Support"])
+        .await
+        .unwrap();
+    let id = victor
+        .search_keywords("e5678", Vec::<String>::new(), 1)
+        .await
+        .unwrap()[0]
+        .embedding
+        .id;
+    victor.delete(id).await.unwrap();
+    let appended = read_index().await;
+    assert!(appended.len() > built.len());
+    assert_eq!(appended[..built.len()], built[..]);
+    assert_eq!(InvertedIndex::read(&appended).unwrap().appended(), 2);
+
+    let results = victor
+        .search_keywords("error", Vec::<String>::new(), 10)
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 1);
+
+    // compacting folds the changes into the index
+    victor.compact().await.unwrap();
+    let compacted = read_index().await;
+    assert_eq!(InvertedIndex::read(&compacted).unwrap().appended(), 0);
+    assert_eq!(
+        InvertedIndex::read(&compacted).unwrap().search("error"),
+        InvertedIndex::read(&appended).unwrap().search("error")
+    );
+}
+
+#[tokio::test]
+async fn reranker() {
+    use crate::Reranker;
+    use async_trait::async_trait;
+
+    /// Embeds everything the same way, so only the reranker tells documents apart.
+    struct ConstantEmbedder;
+
+    #[async_trait]
+    impl crate::Embedder for ConstantEmbedder {
+        async fn embed(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>, Error> {
+            Ok(texts.iter().map(|_| vec![1.0, 0.0]).collect())
+        }
+    }
+
+    /// Scores documents by how many times they contain the query.
+    struct CountReranker;
+
+    #[async_trait]
+    impl Reranker for CountReranker {
+        async fn rerank(&self, query: &str, candidates: &[&str]) -> Result<Vec<f32>, Error> {
+            Ok(candidates
+                .iter()
+                .map(|content| content.matches(query).count() as f32)
+                .collect())
+        }
+    }
+
+    let mut victor = Db::new(DirectoryHandle::default())
+        .with_embedder(ConstantEmbedder)
+        .with_reranker(CountReranker);
+    for content in ["pizza", "pizza pizza pizza", "pasta", "pizza pizza"] {
+        victor
+            .add_single(content, Vec::<String>::new())
+            .await
+            .unwrap();
+    }
+
+    let results = victor
+        .search("pizza", Vec::<String>::new(), 2)
+        .await
+        .unwrap();
+    let contents = results
+        .iter()
+        .map(|result| result.content.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(contents, vec!["pizza pizza pizza", "pizza pizza"]);
+    assert_eq!(results[0].similarity, 3.0);
+
+    let results = victor
+        .search_hybrid("pizza", Vec::<String>::new(), 1)
+        .await
+        .unwrap();
+    assert_eq!(results[0].content, "pizza pizza pizza");
+}
+
+#[tokio::test]
+async fn include_fields() {
+    let mut victor = Db::new(DirectoryHandle::default());
+
+    victor
+        .add_single_embedding("Pineapple", vec![1.0, 0.0], vec!["Pizza Toppings", "Fruit"])
+        .await
+        .unwrap();
+
+    let search = |options: SearchOptions| {
+        let victor = &victor;
+        async move {
+            victor
+                .search_embedding_with_options(vec![1.0, 0.0], Vec::<String>::new(), 1, &options)
+                .await
+                .unwrap()
+                .remove(0)
+        }
+    };
+
+    let result = search(SearchOptions::default()).await;
+    assert_eq!(result.content, "Pineapple");
+    assert_eq!(result.embedding.vector.len(), 2);
+    assert!(result.tags.is_empty());
+
+    let result = search(
+        SearchOptions::default()
+            .with_include_vector(false)
+            .with_include_content(false)
+            .with_include_tags(true),
+    )
+    .await;
+    assert!(result.content.is_empty());
+    assert!(result.embedding.vector.is_empty());
+    assert_eq!(result.tags, vec!["Fruit", "Pizza Toppings"]);
+    assert!(result.similarity > 0.9);
+}
+
+#[tokio::test]
+async fn search_similar() {
+    let mut victor = Db::new(DirectoryHandle::default());
+
+    for (content, vector) in [
+        ("a", vec![1.0, 0.0]),
+        ("b", vec![0.9, 0.1]),
+        ("c", vec![0.0, 1.0]),
+    ] {
+        victor
+            .add_single_embedding(content, vector, Vec::<String>::new())
+            .await
+            .unwrap();
+    }
+    let id = victor
+        .search_embedding(vec![1.0, 0.0], Vec::<String>::new(), 1)
+        .await
+        .unwrap()[0]
+        .embedding
+        .id;
+
+    let similar = victor
+        .search_similar(id, Vec::<String>::new(), 10)
+        .await
+        .unwrap()
+        .unwrap();
+    let contents = similar
+        .iter()
+        .map(|result| result.content.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(contents, vec!["b", "c"]);
+
+    // it only reads, so a shared database only needs locking for reading
+    let victor = crate::memory::SharedDb::new(victor);
+    let similar = victor
+        .read()
+        .await
+        .search_similar(id, Vec::<String>::new(), 1)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(similar[0].content, "b");
+
+    let mut victor = victor.write().await;
+    victor.delete(id).await.unwrap();
+    assert!(victor
+        .search_similar(id, Vec::<String>::new(), 10)
+        .await
+        .unwrap()
+        .is_none());
+}
+
+#[tokio::test]
+async fn recommend() {
+    let mut victor = Db::new(DirectoryHandle::default());
+
+    for (content, vector) in [
+        ("a", vec![1.0, 0.0]),
+        ("b", vec![0.9, 0.1]),
+        ("c", vec![0.7, 0.7]),
+        ("d", vec![0.0, 1.0]),
+    ] {
+        victor
+            .add_single_embedding(content, vector, Vec::<String>::new())
+            .await
+            .unwrap();
+    }
+    let results = victor
+        .search_embedding(vec![1.0, 0.0], Vec::<String>::new(), 4)
+        .await
+        .unwrap();
+    let id = |content| {
+        results
+            .iter()
+            .find(|result| result.content == content)
+            .unwrap()
+            .embedding
+            .id
+    };
+    let (a, c, d) = (id("a"), id("c"), id("d"));
+
+    // without negatives, it's like searching for the mean of the positives
+    let recommended = victor
+        .recommend([a, d], [], Vec::<String>::new(), 10)
+        .await
+        .unwrap()
+        .unwrap();
+    let contents = recommended
+        .iter()
+        .map(|result| result.content.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(contents, vec!["c", "b"]);
+
+    // it only reads, so a shared database only needs locking for reading
+    let victor = crate::memory::SharedDb::new(victor);
+    let recommended = victor
+        .read()
+        .await
+        .recommend([c], [d], Vec::<String>::new(), 1)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(recommended[0].content, "b");
+
+    let mut victor = victor.write().await;
+
+    assert!(victor
+        .recommend([], [a], Vec::<String>::new(), 10)
+        .await
+        .is_err());
+    victor.delete(d).await.unwrap();
+    assert!(victor
+        .recommend([a], [d], Vec::<String>::new(), 10)
+        .await
+        .unwrap()
+        .is_none());
+}
+
+#[tokio::test]
+async fn deduplication() {
+    use crate::Deduplication;
+
+    let documents = || {
+        vec![
+            Document::new("Pepperoni pizza", vec![1.0, 0.0]).with_metadata("run", 1.0),
+            Document::new("Cheese pizza", vec![0.0, 1.0]),
+            Document::new("Pepperoni pizza", vec![1.0, 0.0]),
+        ]
+    };
+    async fn count(victor: &Db) -> usize {
+        victor
+            .search_embedding(vec![1.0, 1.0], Vec::<String>::new(), 10)
+            .await
+            .unwrap()
+            .len()
+    }
+
+    let mut victor = Db::new(DirectoryHandle::default());
+    victor
+        .add_documents(documents(), vec!["Pizza"])
+        .await
+        .unwrap();
+    assert_eq!(count(&victor).await, 3);
+
+    let mut victor = Db::new(DirectoryHandle::default()).with_deduplication(Deduplication::Skip);
+    for _ in 0..2 {
+        victor
+            .add_documents(documents(), vec!["Pizza"])
+            .await
+            .unwrap();
+    }
+    assert_eq!(count(&victor).await, 2);
+
+    let mut victor = Db::new(DirectoryHandle::default()).with_deduplication(Deduplication::Update);
+    victor
+        .add_documents(documents(), vec!["Pizza"])
+        .await
+        .unwrap();
+    victor
+        .add_documents(
+            vec![Document::new("Pepperoni pizza", vec![0.5, 0.5]).with_metadata("run", 2.0)],
+            vec!["Other"],
+        )
+        .await
+        .unwrap();
+    assert_eq!(count(&victor).await, 2);
+    // the stored document was updated in place, keeping its tags
+    let options = SearchOptions::default().with_filter(Filter::eq("run", 2.0));
+    let updated = victor
+        .search_embedding_with_options(vec![1.0, 1.0], vec!["Pizza"], 10, &options)
+        .await
+        .unwrap();
+    assert_eq!(updated.len(), 1);
+    assert_eq!(updated[0].content, "Pepperoni pizza");
+}
+
+#[tokio::test]
+async fn ttl() {
+    use std::time::Duration;
+
+    async fn contents(victor: &Db) -> Vec<String> {
+        let mut contents = victor
+            .search_embedding(vec![1.0, 0.0], Vec::<String>::new(), 10)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|result| result.content)
+            .collect::<Vec<_>>();
+        contents.sort();
+        contents
+    }
+
+    let mut victor = Db::new(DirectoryHandle::default());
+    victor
+        .add_documents(
+            vec![
+                Document::new("expired", vec![1.0, 0.0])
+                    .with_id("expired")
+                    .with_ttl(Duration::ZERO),
+                Document::new("fresh", vec![1.0, 0.0]).with_ttl(Duration::from_secs(3600)),
+                Document::new("forever", vec![1.0, 0.0]),
+            ],
+            Vec::<String>::new(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(contents(&victor).await, vec!["forever", "fresh"]);
+    assert!(victor.compact().await.unwrap() > 0);
+    assert_eq!(contents(&victor).await, vec!["forever", "fresh"]);
+
+    // adding an expired document again brings it back, without its old expiry
+    victor
+        .add_documents(
+            vec![Document::new("expired", vec![1.0, 0.0]).with_id("expired")],
+            Vec::<String>::new(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(contents(&victor).await, vec!["expired", "forever", "fresh"]);
+}
+
+#[tokio::test]
+async fn expired_documents_arent_tombstoned() {
+    use std::{collections::HashSet, time::Duration};
+
+    use crate::filesystem::{DirectoryHandle as _, FileHandle as _, GetFileHandleOptions};
+
+    let directory = DirectoryHandle::default();
+    let mut victor = Db::new(directory.clone());
+    victor
+        .add_documents(
+            vec![
+                Document::new("expired", vec![1.0, 0.0])
+                    .with_id("expired")
+                    .with_ttl(Duration::ZERO),
+                Document::new("deleted", vec![0.0, 1.0]).with_id("deleted"),
+            ],
+            Vec::<String>::new(),
+        )
+        .await
+        .unwrap();
+    let deleted = uuid::Uuid::new_v5(&uuid::Uuid::NAMESPACE_OID, b"deleted");
+    assert!(victor.delete(deleted).await.unwrap());
+
+    // only the deleted document is written down, expiring is left to its expiry
+    let tombstones = directory
+        .get_file_handle_with_options("tombstones.bin", &GetFileHandleOptions { create: false })
+        .await
+        .unwrap()
+        .read()
+        .await
+        .unwrap();
+    let tombstones: HashSet<uuid::Uuid> = bincode::deserialize(&tombstones).unwrap();
+    assert_eq!(tombstones, HashSet::from([deleted]));
+}
+
+#[tokio::test]
+async fn snapshot() {
+    let mut victor = Db::new(DirectoryHandle::default());
+    victor
+        .add_single_embedding("Pepperoni pizza", vec![1.0, 0.0], vec!["Pizza Flavors"])
+        .await
+        .unwrap();
+    victor
+        .build_hnsw_index(HnswConfig::default())
+        .await
+        .unwrap();
+
+    // snapshots replace whatever was in the destination
+    let backup = DirectoryHandle::default();
+    let mut other = Db::new(backup.clone());
+    other
+        .add_single_embedding(
+            "Cheese pizza",
+            vec![0.0, 1.0],
+            vec!["Pizza Flavors", "Other"],
+        )
+        .await
+        .unwrap();
+    victor.snapshot(backup.clone()).await.unwrap();
+
+    victor
+        .add_single_embedding("Pineapple", vec![1.0, 0.0], vec!["Pizza Flavors"])
+        .await
+        .unwrap();
+
+    let backup = Db::new(backup);
+    let results = backup
+        .search_embedding(vec![1.0, 0.0], Vec::<String>::new(), 10)
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].content, "Pepperoni pizza");
+    assert_eq!(backup.tags().await.unwrap(), vec!["Pizza Flavors"]);
+}
+
+#[tokio::test]
+async fn project() {
+    let mut victor = Db::new(DirectoryHandle::default());
+    assert!(matches!(
+        victor.project(2).await,
+        Err(Error::InvalidInput(_))
+    ));
+
+    // the embeddings mostly vary along the first two dimensions
+    for i in 0..20 {
+        let x = i as f32 / 20.0;
+        victor
+            .add_single_embedding(
+                &format!("Pizza {i}"),
+                vec![x, 1.0 - x, 0.01 * (i % 2) as f32, 0.0],
+                vec!["Pizza Flavors"],
+            )
+            .await
+            .unwrap();
+    }
+    assert!(matches!(
+        victor.project(4).await,
+        Err(Error::InvalidInput(_))
+    ));
+    victor.project(2).await.unwrap();
+    assert!(matches!(
+        victor.project(2).await,
+        Err(Error::InvalidInput(_))
+    ));
+
+    // queries and new embeddings keep their original dimension
+    victor
+        .add_single_embedding(
+            "Pineapple",
+            vec![1.0, 0.0, 0.0, 0.0],
+            vec!["Pizza Toppings"],
+        )
+        .await
+        .unwrap();
+    let results = victor
+        .search_embedding(vec![0.0, 1.0, 0.0, 0.0], vec!["Pizza Flavors"], 1)
+        .await
+        .unwrap();
+    assert_eq!(results[0].content, "Pizza 0");
+    assert_eq!(results[0].embedding.vector.len(), 2);
+    let results = victor
+        .search_embedding(vec![1.0, 0.0, 0.0, 0.0], Vec::<String>::new(), 2)
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 2);
+    assert!(results.iter().any(|result| result.content == "Pineapple"));
+
+    let stats = victor.stats().await.unwrap();
+    assert_eq!(stats.documents, 21);
+    assert_eq!(stats.dimension, Some(4));
+    let projection = stats.projection.unwrap();
+    assert_eq!(projection.dimensions, 2);
+    assert!(projection.explained_variance.unwrap() > 0.99);
+}
+
+#[tokio::test]
+async fn project_by_explained_variance() {
+    let mut victor = Db::new(DirectoryHandle::default());
+    for i in 0..20 {
+        let x = i as f32 / 20.0;
+        victor
+            .add_single_embedding(
+                &format!("Pizza {i}"),
+                vec![x, 1.0 - x, 0.01 * (i % 2) as f32, 0.0],
+                vec!["Pizza Flavors"],
+            )
+            .await
+            .unwrap();
+    }
+    assert!(matches!(
+        victor.project(Projection::ExplainedVariance(1.5)).await,
+        Err(Error::InvalidInput(_))
+    ));
+
+    // almost all of the variance is along a single direction
+    victor
+        .project(Projection::ExplainedVariance(0.95))
+        .await
+        .unwrap();
+    let projection = victor.stats().await.unwrap().projection.unwrap();
+    assert_eq!(projection.dimensions, 1);
+    assert!(projection.explained_variance.unwrap() >= 0.95);
+}
+
+#[tokio::test]
+async fn retain_originals() {
+    let mut victor = Db::new(DirectoryHandle::default()).with_retained_originals(true);
+    assert!(matches!(
+        victor.unproject().await,
+        Err(Error::InvalidInput(_))
+    ));
+    for i in 0..10 {
+        let x = i as f32 / 10.0;
+        victor
+            .add_single_embedding(
+                &format!("Pizza {i}"),
+                vec![x, 1.0 - x, 0.5, 0.0],
+                vec!["Pizza Flavors"],
+            )
+            .await
+            .unwrap();
+    }
+    victor.project(2).await.unwrap();
+
+    // embeddings added or updated after projecting keep their originals too
+    victor
+        .add_single_embedding(
+            "Pineapple",
+            vec![0.0, 0.0, 1.0, 1.0],
+            vec!["Pizza Toppings"],
+        )
+        .await
+        .unwrap();
+    let pizza = victor
+        .search_embedding(vec![0.0, 1.0, 0.5, 0.0], vec!["Pizza Flavors"], 1)
+        .await
+        .unwrap()[0]
+        .embedding
+        .id;
+    victor
+        .update(pizza, "Pizza 0", vec![0.0, 1.0, 0.5, 0.25])
+        .await
+        .unwrap();
+    let deleted = victor
+        .search_embedding(vec![0.9, 0.1, 0.5, 0.0], vec!["Pizza Flavors"], 1)
+        .await
+        .unwrap()[0]
+        .embedding
+        .id;
+    victor.delete(deleted).await.unwrap();
+    victor.compact().await.unwrap();
+
+    // projecting again starts from the originals
+    victor.project(3).await.unwrap();
+    let stats = victor.stats().await.unwrap();
+    assert_eq!(stats.documents, 10);
+    let projection = stats.projection.unwrap();
+    assert_eq!(projection.dimensions, 3);
+    assert!(projection.retains_originals);
+
+    victor.unproject().await.unwrap();
+    assert_eq!(victor.stats().await.unwrap().projection, None);
+    let results = victor
+        .search_embedding(vec![0.0, 0.0, 1.0, 1.0], Vec::<String>::new(), 1)
+        .await
+        .unwrap();
+    assert_eq!(results[0].content, "Pineapple");
+    assert_eq!(results[0].embedding.vector, vec![0.0, 0.0, 1.0, 1.0]);
+    let results = victor
+        .search_embedding(vec![0.0, 1.0, 0.5, 0.25], vec!["Pizza Flavors"], 1)
+        .await
+        .unwrap();
+    assert_eq!(results[0].embedding.id, pizza);
+    assert_eq!(results[0].embedding.vector.len(), 4);
+
+    // without the originals, a database can only be projected once
+    let mut victor = Db::new(DirectoryHandle::default());
+    victor
+        .add_single_embedding(
+            "Pepperoni pizza",
+            vec![0.1, 0.2, 0.3],
+            vec!["Pizza Flavors"],
+        )
+        .await
+        .unwrap();
+    victor
+        .add_single_embedding("Pineapple", vec![0.3, 0.2, 0.1], vec!["Pizza Toppings"])
+        .await
+        .unwrap();
+    victor.project(2).await.unwrap();
+    assert!(matches!(
+        victor.project(1).await,
+        Err(Error::InvalidInput(_))
+    ));
+    assert!(matches!(
+        victor.unproject().await,
+        Err(Error::InvalidInput(_))
+    ));
+}
+
+#[tokio::test]
+async fn rescore_with_originals() {
+    let mut victor = Db::new(DirectoryHandle::default()).with_retained_originals(true);
+    for i in 0..10 {
+        victor
+            .add_single_embedding(
+                &format!("Pizza {i}"),
+                vec![i as f32, 1.0, 0.0],
+                vec!["Pizza Flavors"],
+            )
+            .await
+            .unwrap();
+    }
+    victor
+        .add_single_embedding("Pepperoni", vec![5.0, 1.0, 0.5], vec!["Pizza Toppings"])
+        .await
+        .unwrap();
+    victor
+        .add_single_embedding("Pineapple", vec![5.0, 1.0, -0.5], vec!["Pizza Toppings"])
+        .await
+        .unwrap();
+
+    // projected to one dimension, the toppings can't be told apart
+    victor.project(1).await.unwrap();
+    let options = SearchOptions::default().with_rescore(false);
+    let results = victor
+        .search_embedding_with_options(vec![5.0, 1.0, -0.5], vec!["Pizza Toppings"], 2, &options)
+        .await
+        .unwrap();
+    assert_eq!(results[0].similarity, results[1].similarity);
+
+    // but their original vectors can
+    let results = victor
+        .search_embedding(vec![5.0, 1.0, -0.5], vec!["Pizza Toppings"], 2)
+        .await
+        .unwrap();
+    assert_eq!(results[0].content, "Pineapple");
+    assert!(results[0].similarity > results[1].similarity);
+    let similar = victor
+        .search_similar(results[0].embedding.id, Vec::<String>::new(), 1)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(similar[0].content, "Pizza 5");
+}
+
+#[tokio::test]
+async fn migrate_projection_without_explained_variance() {
+    use crate::{
+        filesystem::{
+            CreateWritableOptions, DirectoryHandle as _, FileHandle as _, GetFileHandleOptions,
+            WritableFileStream as _,
+        },
+        format,
+    };
+
+    let directory = DirectoryHandle::default();
+    let mut victor = Db::new(directory.clone());
+    victor
+        .add_single_embedding("hello", vec![1.0, 2.0, 3.0], vec!["greeting"])
+        .await
+        .unwrap();
+    victor
+        .add_single_embedding("goodbye", vec![3.0, 2.0, 1.0], vec!["greeting"])
+        .await
+        .unwrap();
+    victor.project(2).await.unwrap();
+
+    // format version 5 only recorded the eigenvectors and means
+    let file_handle = directory
+        .get_file_handle_with_options("eigen.bin", &GetFileHandleOptions { create: false })
+        .await
+        .unwrap();
+    let (eigen, means, _): (nalgebra::DMatrix<f32>, Vec<f32>, Option<f32>) =
+        bincode::deserialize(&file_handle.read().await.unwrap()).unwrap();
+    let v5 = bincode::serialize(&(eigen, means)).unwrap();
+    let mut file_handle = file_handle;
+    let mut writable = file_handle
+        .create_writable_with_options(&CreateWritableOptions {
+            keep_existing_data: false,
+        })
+        .await
+        .unwrap();
+    writable.write_at_cursor_pos(v5.clone()).await.unwrap();
+    writable.close().await.unwrap();
+    crate::checksum::record(&directory, "eigen.bin", &v5)
+        .await
+        .unwrap();
+    format::write(&directory, 5).await.unwrap();
+
+    // the old layout can still be read before migrating
+    let results = victor
+        .search_embedding(vec![1.0, 2.0, 3.0], vec!["greeting"], 1)
+        .await
+        .unwrap();
+    assert_eq!(results[0].content, "hello");
+
+    assert!(victor.migrate().await.unwrap());
+    assert_eq!(
+        format::read(&directory).await.unwrap(),
+        Some(format::VERSION)
+    );
+    let projection = victor.stats().await.unwrap().projection.unwrap();
+    assert_eq!(projection.dimensions, 2);
+    assert_eq!(projection.explained_variance, None);
+}
+
+#[tokio::test]
+async fn multiple_vectors_per_document() {
+    use crate::Aggregation;
+
+    let mut victor = Db::new(DirectoryHandle::default());
+    victor
+        .add_documents(
+            vec![
+                Document::new("Pizza guide", vec![1.0, 0.0, 0.0])
+                    .with_id("guide")
+                    .with_chunk(vec![0.0, 1.0, 0.0])
+                    .with_chunk(vec![0.9, 0.1, 0.0])
+                    .with_metadata("author", "alice"),
+                Document::new("Pepperoni pizza", vec![1.0, 0.0, 0.0]),
+                Document::new("Pineapple", vec![0.0, 0.0, 1.0]),
+            ],
+            vec!["Pizza"],
+        )
+        .await
+        .unwrap();
+
+    // the guide is found by any of its vectors, and returned once
+    let results = victor
+        .search_embedding(vec![0.0, 1.0, 0.0], vec!["Pizza"], 10)
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 3);
+    assert_eq!(results[0].content, "Pizza guide");
+    assert_eq!(results[0].external_id.as_deref(), Some("guide"));
+    let guide = results[0].embedding.id;
+    let options = SearchOptions::default().with_filter(Filter::eq("author", "alice"));
+    let results = victor
+        .search_embedding_with_options(vec![0.0, 1.0, 0.0], vec!["Pizza"], 10, &options)
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].embedding.id, guide);
+
+    // summing counts every matching vector
+    let options = SearchOptions::default().with_aggregation(Aggregation::Sum);
+    let results = victor
+        .search_embedding_with_options(vec![1.0, 0.0, 0.0], vec!["Pizza"], 2, &options)
+        .await
+        .unwrap();
+    assert_eq!(results[0].content, "Pizza guide");
+    assert!(results[0].similarity > 1.0);
+    assert_eq!(results[1].content, "Pepperoni pizza");
+    assert_eq!(victor.stats().await.unwrap().documents, 3);
+
+    // adding the document again replaces all of its vectors
+    victor
+        .add_documents(
+            vec![Document::new("Pizza guide", vec![1.0, 0.0, 0.0]).with_id("guide")],
+            vec!["Pizza"],
+        )
+        .await
+        .unwrap();
+    let results = victor
+        .search_embedding(vec![0.0, 1.0, 0.0], vec!["Pizza"], 10)
+        .await
+        .unwrap();
+    assert!(results.iter().all(|result| result.similarity < 0.5));
+
+    let document = Document::new("Pizza guide", vec![1.0, 0.0, 0.0])
+        .with_id("guide")
+        .with_chunk(vec![0.0, 1.0, 0.0]);
+    victor
+        .add_documents(vec![document], vec!["Pizza"])
+        .await
+        .unwrap();
+    assert!(victor.delete(guide).await.unwrap());
+    let results = victor
+        .search_embedding(vec![0.0, 1.0, 0.0], vec!["Pizza"], 10)
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 2);
+    assert!(results.iter().all(|result| result.embedding.id != guide));
+
+    victor.compact().await.unwrap();
+    let stats = victor.stats().await.unwrap();
+    assert_eq!(stats.documents, 2);
+}
+
+#[tokio::test]
+async fn shared_between_threads() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Db>();
+    assert_send_sync::<crate::native::Db>();
+    assert_send_sync::<crate::memory::SharedDb>();
+
+    let victor = crate::memory::SharedDb::new(Db::new(DirectoryHandle::default()));
+
+    // writes from other tasks are seen by every clone
+    let writers = (0..4)
+        .map(|i| {
+            let victor = victor.clone();
+            tokio::spawn(async move {
+                victor
+                    .write()
+                    .await
+                    .add_single_embedding(format!("{i}"), vec![i as f32, 1.0], vec!["numbers"])
+                    .await
+                    .unwrap();
+            })
+        })
+        .collect::<Vec<_>>();
+    for writer in writers {
+        writer.await.unwrap();
+    }
+
+    // and searches can run on other threads at the same time
+    let searches = (0..4)
+        .map(|i| {
+            let victor = victor.clone();
+            std::thread::spawn(move || {
+                tokio_test::block_on(async {
+                    victor
+                        .read()
+                        .await
+                        .search_embedding(vec![i as f32, 1.0], vec!["numbers"], 4)
+                        .await
+                        .unwrap()
+                        .len()
+                })
+            })
+        })
+        .collect::<Vec<_>>();
+    for search in searches {
+        assert_eq!(search.join().unwrap(), 4);
+    }
+}
+
+#[tokio::test]
+async fn write_buffer() {
+    async fn count(victor: &Db) -> usize {
+        victor
+            .search_embedding(vec![1.0, 0.0], Vec::<String>::new(), 10)
+            .await
+            .unwrap()
+            .len()
+    }
+
+    let root = DirectoryHandle::default();
+    let mut victor = Db::new(root.clone()).with_write_buffer(2);
+
+    victor
+        .add_single_embedding("a", vec![1.0, 0.0], vec!["letters"])
+        .await
+        .unwrap();
+    victor
+        .add_single_embedding("b", vec![0.0, 1.0], vec!["letters"])
+        .await
+        .unwrap();
+    assert_eq!(count(&victor).await, 0);
+
+    // going over the limit writes everything, with the tags it was added with
+    victor
+        .add_single_embedding("1", vec![1.0, 1.0], vec!["numbers"])
+        .await
+        .unwrap();
+    assert_eq!(count(&victor).await, 3);
+    assert_eq!(victor.tag_sets().await.unwrap().len(), 2);
+
+    victor
+        .add_single_embedding("c", vec![1.0, 0.5], vec!["letters"])
+        .await
+        .unwrap();
+    victor.flush().await.unwrap();
+    assert_eq!(count(&victor).await, 4);
+
+    // deleting writes buffered documents first, so they can be deleted
+    victor
+        .add_embeddings_with_ids(vec![("d", "d", vec![-1.0, 0.0])], vec!["letters"])
+        .await
+        .unwrap();
+    victor.flush().await.unwrap();
+    let id = victor
+        .search_embedding(vec![-1.0, 0.0], vec!["letters"], 1)
+        .await
+        .unwrap()[0]
+        .embedding
+        .id;
+    victor
+        .add_embeddings_with_ids(vec![("d", "d", vec![-1.0, 0.5])], vec!["letters"])
+        .await
+        .unwrap();
+    assert!(victor.delete(id).await.unwrap());
+    assert_eq!(count(&victor).await, 4);
+
+    // as does closing the database
+    victor
+        .add_single_embedding("e", vec![1.0, 0.25], vec!["letters"])
+        .await
+        .unwrap();
+    victor.close().await.unwrap();
+    let victor = Db::new(root);
+    assert_eq!(count(&victor).await, 5);
+}
+
+#[tokio::test]
+async fn search_with_progress() {
+    // a db file per tag, so there's more than one segment to scan
+    let mut victor = Db::new(DirectoryHandle::default());
+    for (content, vector, tag) in [
+        ("far", vec![0.0, 1.0], "a"),
+        ("closer", vec![1.0, 1.0], "b"),
+        ("closest", vec![1.0, 0.0], "c"),
+    ] {
+        victor
+            .add_single_embedding(content, vector, vec![tag])
+            .await
+            .unwrap();
+    }
+
+    let mut provisional = Vec::new();
+    let nearest = victor
+        .search_embedding_with_progress(
+            vec![1.0, 0.0],
+            Vec::<String>::new(),
+            1,
+            &SearchOptions::default(),
+            |results| provisional.push(results.to_vec()),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(provisional.len(), 3);
+    assert!(provisional.iter().all(|results| results.len() == 1));
+    // the matches only get better, and the last update has the final results
+    assert!(provisional
+        .windows(2)
+        .all(|pair| pair[0][0].similarity <= pair[1][0].similarity));
+    assert_eq!(provisional[2][0].content, "closest");
+    assert_eq!(nearest[0].content, "closest");
+}
+
+#[tokio::test]
+async fn add_with_progress() {
+    use crate::Progress;
+
+    struct LengthEmbedder;
+
+    #[async_trait::async_trait]
+    impl crate::Embedder for LengthEmbedder {
+        async fn embed(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>, Error> {
+            Ok(texts
+                .iter()
+                .map(|text| vec![text.len() as f32, 1.0])
+                .collect())
+        }
+    }
+
+    let mut victor = Db::new(DirectoryHandle::default()).with_embedder(LengthEmbedder);
+    let content = (0..1200).map(|i| i.to_string()).collect::<Vec<_>>();
+    let mut progress = Vec::new();
+    victor
+        .add_with_progress(content, vec!["numbers"], |update| progress.push(update))
+        .await
+        .unwrap();
+
+    assert_eq!(
+        progress,
+        vec![
+            Progress::Embedding {
+                done: 256,
+                total: 1200
+            },
+            Progress::Embedding {
+                done: 512,
+                total: 1200
+            },
+            Progress::Embedding {
+                done: 768,
+                total: 1200
+            },
+            Progress::Embedding {
+                done: 1024,
+                total: 1200
+            },
+            Progress::Embedding {
+                done: 1200,
+                total: 1200
+            },
+            Progress::Writing {
+                done: 1000,
+                total: 1200
+            },
+            Progress::Writing {
+                done: 1200,
+                total: 1200
+            },
+        ]
+    );
+    assert!(progress
+        .windows(2)
+        .all(|pair| pair[0].fraction() < pair[1].fraction()));
+    assert_eq!(victor.stats().await.unwrap().documents, 1200);
+}
+
+#[tokio::test]
+async fn add_stream() {
+    use std::sync::{Arc, Mutex};
+
+    /// Remembers how many texts it embedded at a time.
+    struct BatchEmbedder(Arc<Mutex<Vec<usize>>>);
+
+    #[async_trait::async_trait]
+    impl crate::Embedder for BatchEmbedder {
+        async fn embed(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>, Error> {
+            self.0.lock().unwrap().push(texts.len());
+            Ok(texts
+                .iter()
+                .map(|text| vec![text.len() as f32, 1.0])
+                .collect())
+        }
+    }
+
+    let batches = Arc::new(Mutex::new(Vec::new()));
+    let mut victor =
+        Db::new(DirectoryHandle::default()).with_embedder(BatchEmbedder(batches.clone()));
+    let documents = futures::stream::iter((0..600).map(|i| {
+        let tag = if i % 2 == 0 { "even" } else { "odd" };
+        (i.to_string(), vec![tag.to_string()])
+    }));
+    assert_eq!(victor.add_stream(documents).await.unwrap(), 600);
+
+    // documents are embedded a bounded batch at a time
+    assert_eq!(*batches.lock().unwrap(), vec![256, 256, 88]);
+    assert_eq!(victor.stats().await.unwrap().documents, 600);
+    assert_eq!(victor.tag_sets().await.unwrap().len(), 2);
+    let odd = victor
+        .search_embedding(vec![1.0, 1.0], vec!["odd"], 1000)
+        .await
+        .unwrap();
+    assert_eq!(odd.len(), 300);
+
+    assert_eq!(
+        victor.add_stream(futures::stream::empty()).await.unwrap(),
+        0
+    );
+}
+
+#[tokio::test]
+async fn add_document_chunked() {
+    use crate::chunking::Chunker;
+
+    struct LengthEmbedder;
+
+    #[async_trait::async_trait]
+    impl crate::Embedder for LengthEmbedder {
+        async fn embed(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>, Error> {
+            Ok(texts
+                .iter()
+                .map(|text| vec![text.len() as f32, 1.0])
+                .collect())
+        }
+    }
+
+    let mut victor = Db::new(DirectoryHandle::default()).with_embedder(LengthEmbedder);
+    let chunker = Chunker::Sentences {
+        max_size: 20,
+        overlap: 0,
+    };
+    let count = victor
+        .add_document_chunked(
+            "essay",
+            "One sentence. Another sentence. A third one.",
+            &chunker,
+            vec!["essays"],
+        )
+        .await
+        .unwrap();
+    assert_eq!(count, 3);
+    victor
+        .add_document_chunked("note", "A short note.", &chunker, vec!["essays"])
+        .await
+        .unwrap();
+
+    let options = SearchOptions::default().with_filter(Filter::eq("parent_id", "essay"));
+    let chunks = victor
+        .search_embedding_with_options(vec![10.0, 1.0], vec!["essays"], 10, &options)
+        .await
+        .unwrap();
+    assert_eq!(chunks.len(), 3);
+    let mut ids = chunks
+        .iter()
+        .map(|chunk| chunk.external_id.clone().unwrap())
+        .collect::<Vec<_>>();
+    ids.sort();
+    assert_eq!(ids, vec!["essay#0", "essay#1", "essay#2"]);
+
+    // a shorter version replaces every chunk
+    victor
+        .add_document_chunked("essay", "Just one now.", &chunker, vec!["essays"])
+        .await
+        .unwrap();
+    let chunks = victor
+        .search_embedding_with_options(vec![10.0, 1.0], vec!["essays"], 10, &options)
+        .await
+        .unwrap();
+    assert_eq!(chunks.len(), 1);
+    assert_eq!(chunks[0].content, "Just one now.");
+    assert_eq!(victor.stats().await.unwrap().documents, 2);
+}
+
+#[tokio::test]
+async fn evaluate_recall() {
+    let mut victor = Db::new(DirectoryHandle::default()).with_retained_originals(true);
+    for i in 0..10 {
+        let x = i as f32 / 10.0;
+        victor
+            .add_single_embedding(
+                &format!("Pizza {i}"),
+                vec![x, 1.0 - x, (x * 7.0).sin(), (x * 3.0).cos()],
+                vec!["Pizza Flavors"],
+            )
+            .await
+            .unwrap();
+    }
+    let queries = vec![vec![0.2, 0.8, 0.5, 0.0], vec![0.9, 0.1, -0.5, 1.0]];
+
+    let report = crate::eval::evaluate_recall(&victor, &queries, 3)
+        .await
+        .unwrap();
+    assert_eq!(report.queries, 2);
+    assert_eq!(report.recall, 1.0);
+    assert!(report.max_score_error < 0.01);
+
+    // projecting to a single dimension loses most of what tells the documents apart
+    victor = victor.with_retained_originals(false);
+    victor.project(1).await.unwrap();
+    let projected = crate::eval::evaluate_recall(&victor, &queries, 3)
+        .await
+        .unwrap();
+    assert!((0.0..=1.0).contains(&projected.recall));
+    assert!(projected.mean_score_error <= projected.max_score_error);
+
+    let empty = crate::eval::evaluate_recall(&Db::new(DirectoryHandle::default()), &queries, 3)
+        .await
+        .unwrap();
+    assert_eq!(empty.recall, 1.0);
+}
+
+#[tokio::test]
+async fn verify_integrity() {
+    use crate::{
+        db::Index,
+        filesystem::{
+            CreateWritableOptions, DirectoryHandle as _, FileHandle as _, GetFileHandleOptions,
+            WritableFileStream as _,
+        },
+        Problem,
+    };
+
+    let mut directory = DirectoryHandle::default();
+    let mut victor = Db::new(directory.clone());
+    victor
+        .add_embeddings_with_ids(vec![("a", "hello", vec![1.0, 2.0, 3.0])], vec!["greeting"])
+        .await
+        .unwrap();
+    victor
+        .add_embeddings_with_ids(
+            vec![("b", "goodbye", vec![3.0, 2.0, 1.0])],
+            vec!["farewell"],
+        )
+        .await
+        .unwrap();
+
+    let report = victor.verify_integrity().await.unwrap();
+    assert!(report.is_ok());
+    assert_eq!(report.db_files, 2);
+    assert_eq!(report.embeddings, 2);
+
+    // pretend writing another embedding was interrupted partway through
+    let greeting = Index::filename_for_part(["greeting".to_string()].into(), 0);
+    let mut file_handle = directory
+        .get_file_handle_with_options(&greeting, &GetFileHandleOptions { create: false })
+        .await
+        .unwrap();
+    let size = file_handle.size().await.unwrap();
+    let mut writable = file_handle
+        .create_writable_with_options(&CreateWritableOptions {
+            keep_existing_data: true,
+        })
+        .await
+        .unwrap();
+    writable.seek(size).await.unwrap();
+    writable.write_at_cursor_pos(vec![1; 5]).await.unwrap();
+    writable.close().await.unwrap();
+
+    // and lose a db file entirely
+    let farewell = Index::filename_for_part(["farewell".to_string()].into(), 0);
+    directory.remove_entry(&farewell).await.unwrap();
+
+    let report = victor.verify_integrity().await.unwrap();
+    assert_eq!(report.db_files, 1);
+    assert_eq!(report.embeddings, 1);
+    assert_eq!(report.problems.len(), 4);
+    assert!(report.problems.contains(&Problem::ChecksumMismatch {
+        file: greeting.clone()
+    }));
+    assert!(report.problems.contains(&Problem::PartialRecord {
+        file: greeting,
+        record_size: size - 4,
+        extra_bytes: 5,
+    }));
+    assert!(report
+        .problems
+        .contains(&Problem::MissingFile { file: farewell }));
+    assert!(report.problems.contains(&Problem::OrphanedContent {
+        id: uuid::Uuid::new_v5(&uuid::Uuid::NAMESPACE_OID, b"b")
+    }));
+}
+
+#[tokio::test]
+async fn repair() {
+    use crate::{
+        db::Index,
+        filesystem::{
+            CreateWritableOptions, DirectoryHandle as _, FileHandle as _, GetFileHandleOptions,
+            WritableFileStream as _,
+        },
+        Discarded,
+    };
+
+    let mut directory = DirectoryHandle::default();
+    let mut victor = Db::new(directory.clone());
+    victor
+        .add_embeddings_with_ids(
+            vec![
+                ("a", "hello", vec![1.0, 2.0, 3.0]),
+                ("b", "hi", vec![1.0, 2.0, 2.5]),
+            ],
+            vec!["greeting"],
+        )
+        .await
+        .unwrap();
+    victor
+        .add_embeddings_with_ids(
+            vec![("c", "goodbye", vec![3.0, 2.0, 1.0])],
+            vec!["farewell"],
+        )
+        .await
+        .unwrap();
+    let id =
+        |external_id: &str| uuid::Uuid::new_v5(&uuid::Uuid::NAMESPACE_OID, external_id.as_bytes());
+
+    // cut the last content entry short, and the end of a db file off too
+    for (filename, cut) in [
+        ("content.bin".to_string(), 3),
+        (
+            Index::filename_for_part(["greeting".to_string()].into(), 0),
+            1,
+        ),
+    ] {
+        let mut file_handle = directory
+            .get_file_handle_with_options(&filename, &GetFileHandleOptions { create: false })
+            .await
+            .unwrap();
+        let size = file_handle.size().await.unwrap();
+        let kept = file_handle.read_range(0, size - cut).await.unwrap();
+        let mut writable = file_handle
+            .create_writable_with_options(&CreateWritableOptions {
+                keep_existing_data: false,
+            })
+            .await
+            .unwrap();
+        writable.write_at_cursor_pos(kept).await.unwrap();
+        writable.close().await.unwrap();
+    }
+    let farewell = Index::filename_for_part(["farewell".to_string()].into(), 0);
+    directory.remove_entry(&farewell).await.unwrap();
+
+    // "goodbye" lost its content (and its db file), and "hi" its embedding, so both are dropped
+    let report = victor.repair().await.unwrap();
+    assert!(!report.found.is_ok());
+    let greeting = Index::filename_for_part(["greeting".to_string()].into(), 0);
+    assert!(matches!(
+        report.discarded.as_slice(),
+        [
+            Discarded::Bytes { file: content, .. },
+            Discarded::Bytes { file: segment, .. },
+            Discarded::Content { id: orphaned },
+        ] if content == "content.bin" && *segment == greeting && *orphaned == id("b")
+    ));
+
+    // what's left is intact, and can be searched and added to
+    assert!(victor.verify_integrity().await.unwrap().is_ok());
+    victor
+        .add_embeddings_with_ids(vec![("d", "hey", vec![1.0, 2.0, 3.0])], vec!["greeting"])
+        .await
+        .unwrap();
+    let results = victor
+        .search_embedding(vec![1.0, 2.0, 3.0], Vec::<String>::new(), 10)
+        .await
+        .unwrap();
+    let mut contents = results
+        .into_iter()
+        .map(|result| result.content)
+        .collect::<Vec<_>>();
+    contents.sort();
+    assert_eq!(contents, vec!["hello", "hey"]);
+}
+
+#[tokio::test]
+async fn rebuild_index() {
+    use crate::filesystem::DirectoryHandle as _;
+
+    // small db files, so each tag set has a few
+    let mut directory = DirectoryHandle::default();
+    let mut victor = Db::new(directory.clone()).with_max_segment_size(1);
+    for (content, tags) in [
+        ("Pepperoni pizza", vec!["Pizza Flavors"]),
+        ("Cheese pizza", vec!["Pizza Flavors"]),
+        ("Pineapple", vec!["Pizza Toppings", "Fruit"]),
+        ("Olives", vec!["Pizza Toppings", "Fruit"]),
+    ] {
+        victor
+            .add_single_embedding(content, vec![0.1, 0.2, 0.3], tags)
+            .await
+            .unwrap();
+    }
+
+    // without the index, none of the documents can be found
+    directory.remove_entry("index.bin").await.unwrap();
+    let results = victor
+        .search_embedding(vec![0.1, 0.2, 0.3], Vec::<String>::new(), 10)
+        .await
+        .unwrap();
+    assert!(results.is_empty());
+
+    let recovered = victor
+        .rebuild_index(vec![vec!["Pizza Flavors"]])
+        .await
+        .unwrap();
+    assert_eq!(recovered.len(), 1);
+    assert!(recovered[0][0].starts_with("recovered:"));
+    assert_eq!(
+        victor.tag_sets().await.unwrap(),
+        vec![vec!["Pizza Flavors".to_string()], recovered[0].clone()]
+    );
+
+    let results = victor
+        .search_embedding(vec![0.1, 0.2, 0.3], Vec::<String>::new(), 10)
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 4);
+    let results = victor
+        .search_embedding(vec![0.1, 0.2, 0.3], vec!["Pizza Flavors"], 10)
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 2);
+    let results = victor
+        .search_embedding(vec![0.1, 0.2, 0.3], recovered[0].clone(), 10)
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 2);
+    assert!(victor.verify_integrity().await.unwrap().is_ok());
+
+    // with nothing lost, rebuilding changes nothing
+    assert!(victor
+        .rebuild_index(Vec::<Vec<String>>::new())
+        .await
+        .unwrap()
+        .is_empty());
+    assert_eq!(victor.tag_sets().await.unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn optimize() {
+    use crate::filesystem::DirectoryHandle as _;
+
+    let directory = DirectoryHandle::default();
+    let mut victor = Db::new(directory.clone());
+    for (content, vector, tags) in [
+        ("Pepperoni pizza", vec![1.0, 0.0], vec!["Pizza Flavors"]),
+        ("Cheese pizza", vec![0.9, 0.1], vec!["Pizza Flavors"]),
+        ("Pineapple", vec![0.0, 1.0], vec!["Pizza Toppings", "Fruit"]),
+        ("Olives", vec![0.1, 0.9], vec!["Pizza Toppings"]),
+    ] {
+        victor
+            .add_single_embedding(content, vector, tags)
+            .await
+            .unwrap();
+    }
+    let olives = victor
+        .search_embedding(vec![0.1, 0.9], vec!["Pizza Toppings"], 1)
+        .await
+        .unwrap()
+        .remove(0)
+        .embedding
+        .id;
+
+    assert_eq!(victor.optimize().await.unwrap(), 3);
+    let db_files = directory
+        .list_entries()
+        .await
+        .unwrap()
+        .into_iter()
+        .filter(|name| name.starts_with("combined-") && name.ends_with(".bin"))
+        .count();
+    assert_eq!(db_files, 1);
+    // there's nothing left to merge
+    assert_eq!(victor.optimize().await.unwrap(), 0);
+
+    // searches still filter by tag, and find the tags of what they return
+    let options = SearchOptions::default().with_include_tags(true);
+    let results = victor
+        .search_embedding_with_options(vec![1.0, 0.0], vec!["Pizza Toppings"], 10, &options)
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0].content, "Olives");
+    assert_eq!(results[0].tags, vec!["Pizza Toppings"]);
+    assert_eq!(results[1].tags, vec!["Fruit", "Pizza Toppings"]);
+    let results = victor
+        .search_embedding(vec![0.0, 1.0], vec!["Pizza Flavors"], 10)
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0].content, "Cheese pizza");
+    let results = victor
+        .search_keywords("pineapple", vec!["Fruit"], 10)
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 1);
+    assert!(victor.verify_integrity().await.unwrap().is_ok());
+
+    // new documents get a db file of their own, until the next optimization
+    victor
+        .add_single_embedding("Hawaiian pizza", vec![0.5, 0.5], vec!["Pizza Flavors"])
+        .await
+        .unwrap();
+    let results = victor
+        .search_embedding(vec![0.5, 0.5], vec!["Pizza Flavors"], 10)
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 3);
+    assert_eq!(results[0].content, "Hawaiian pizza");
+
+    // deleted documents are compacted out of combined files too
+    victor.delete(olives).await.unwrap();
+    victor.compact().await.unwrap();
+    assert_eq!(victor.optimize().await.unwrap(), 2);
+    let results = victor
+        .search_embedding(vec![0.0, 1.0], Vec::<String>::new(), 10)
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 4);
+    assert_eq!(results[0].content, "Pineapple");
+    assert!(victor
+        .search_embedding(vec![0.0, 1.0], vec!["Pizza Toppings"], 10)
+        .await
+        .unwrap()
+        .iter()
+        .all(|result| result.content == "Pineapple"));
+    assert!(victor.verify_integrity().await.unwrap().is_ok());
+
+    // combined files keep their tags when the index is rebuilt
+    victor
+        .rebuild_index(Vec::<Vec<String>>::new())
+        .await
+        .unwrap();
+    let results = victor
+        .search_embedding(vec![1.0, 0.0], vec!["Pizza Flavors"], 10)
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 3);
+}
+
+#[tokio::test]
+async fn builder() {
+    let directory = DirectoryHandle::default();
+    let mut victor = Db::builder(directory.clone())
+        .metric(Metric::Euclidean)
+        .dimension(2)
+        .storage(Storage::Full)
+        .build()
+        .await
+        .unwrap();
+    assert!(matches!(
+        victor
+            .add_single_embedding("Pineapple", vec![0.1, 0.2, 0.3], Vec::<String>::new())
+            .await,
+        Err(Error::DimensionMismatch {
+            expected: 2,
+            found: 3
+        })
+    ));
+    victor
+        .add_single_embedding("Pineapple", vec![0.1, 0.2], Vec::<String>::new())
+        .await
+        .unwrap();
+
+    // settings that aren't given are read from the database, and the rest have to match
+    let reopened = Db::builder(directory.clone())
+        .storage(Storage::Full)
+        .build()
+        .await
+        .unwrap();
+    assert_eq!(
+        reopened.settings().await.unwrap(),
+        Settings {
+            metric: Metric::Euclidean,
+            dimension: Some(2),
+            storage: Storage::Full,
+            ..Default::default()
+        }
+    );
+    let result = reopened
+        .search_embedding(vec![0.1, 0.2], Vec::<String>::new(), 1)
+        .await
+        .unwrap();
+    assert_eq!(result[0].embedding.vector, vec![0.1, 0.2]);
+    assert!(matches!(
+        Db::builder(directory.clone())
+            .storage(Storage::Packed)
+            .build()
+            .await,
+        Err(Error::InvalidInput(_))
+    ));
+    assert!(matches!(
+        Db::builder(directory).dimension(3).build().await,
+        Err(Error::DimensionMismatch { .. })
+    ));
+}
+
+#[tokio::test]
+async fn schema() {
+    let directory = DirectoryHandle::default();
+    let mut victor = Db::builder(directory.clone())
+        .dimension(3)
+        .allowed_tags(["Pizza Flavors", "Pizza Toppings"])
+        .write_buffer(10)
+        .build()
+        .await
+        .unwrap();
+
+    // buffered documents are checked when they're added, not when they're written
+    assert!(matches!(
+        victor
+            .add_single_embedding("Pineapple", vec![0.1, 0.2], vec!["Pizza Toppings"])
+            .await,
+        Err(Error::DimensionMismatch {
+            expected: 3,
+            found: 2
+        })
+    ));
+    assert!(matches!(
+        victor
+            .add_documents(
+                vec![Document::new("Pineapple", vec![0.1, 0.2, 0.3]).with_chunk(vec![0.3, 0.2])],
+                vec!["Pizza Toppings"]
+            )
+            .await,
+        Err(Error::DimensionMismatch { .. })
+    ));
+    match victor
+        .add_single_embedding(
+            "Pineapple",
+            vec![0.1, 0.2, 0.3],
+            vec!["Pizza Toppings", "Fruit"],
+        )
+        .await
+    {
+        Err(Error::TagNotAllowed { tag }) => assert_eq!(tag, "Fruit"),
+        result => panic!("expected TagNotAllowed, got {result:?}"),
+    }
+    victor
+        .add_single_embedding("Pineapple", vec![0.1, 0.2, 0.3], vec!["Pizza Toppings"])
+        .await
+        .unwrap();
+    victor.flush().await.unwrap();
+
+    // searches are checked too
+    assert!(matches!(
+        victor
+            .search_embedding(vec![0.1, 0.2], Vec::<String>::new(), 1)
+            .await,
+        Err(Error::DimensionMismatch { .. })
+    ));
+
+    // the allowed tags are recorded, and can't change once there are documents
+    let reopened = Db::new(directory.clone());
+    assert_eq!(
+        reopened.allowed_tags().await.unwrap(),
+        Some(vec![
+            "Pizza Flavors".to_string(),
+            "Pizza Toppings".to_string()
+        ])
+    );
+    assert!(Db::builder(directory.clone())
+        .allowed_tags(["Pizza Toppings", "Pizza Flavors"])
+        .build()
+        .await
+        .is_ok());
+    assert!(matches!(
+        Db::builder(directory).allowed_tags(["Fruit"]).build().await,
+        Err(Error::InvalidInput(_))
+    ));
+}
+
+#[tokio::test]
+async fn normalization() {
+    let directory = DirectoryHandle::default();
+    let settings = Settings {
+        metric: Metric::Dot,
+        normalize: true,
+        ..Default::default()
+    };
+    let mut victor = Db::with_settings(directory.clone(), settings)
+        .await
+        .unwrap();
+    victor
+        .add_embeddings_with_ids(
+            vec![
+                ("a", "long", vec![30.0, 40.0]),
+                ("b", "short", vec![0.0, -0.1]),
+            ],
+            Vec::<String>::new(),
+        )
+        .await
+        .unwrap();
+
+    // both the stored vectors and the query are scaled to unit length, so the dot product is the cosine
+    let results = victor
+        .search_embedding(vec![6.0, 8.0], Vec::<String>::new(), 2)
+        .await
+        .unwrap();
+    assert_eq!(results[0].content, "long");
+    assert!((results[0].similarity - 1.0).abs() < 0.01);
+    assert!((results[0].embedding.vector[0] - 0.6).abs() < 0.01);
+    assert!((results[1].similarity + 0.8).abs() < 0.01);
+
+    // updated vectors are normalized too
+    let id = results[1].embedding.id;
+    victor.update(id, "short", vec![0.0, 10.0]).await.unwrap();
+    let results = victor
+        .search_embedding(vec![0.0, 1.0], Vec::<String>::new(), 1)
+        .await
+        .unwrap();
+    assert_eq!(results[0].embedding.id, id);
+    assert!((results[0].similarity - 1.0).abs() < 0.01);
+
+    // the choice is recorded, and can't be changed once embeddings have been added
+    let reopened = Db::with_settings(
+        directory.clone(),
+        Settings {
+            normalize: false,
+            ..settings
+        },
+    )
+    .await;
+    assert!(matches!(reopened, Err(Error::InvalidInput(_))));
+    assert!(Db::new(directory).settings().await.unwrap().normalize);
+}
+
+#[tokio::test]
+async fn clusters() {
+    use crate::ClusterConfig;
+
+    let mut victor = Db::new(DirectoryHandle::default());
+    let documents = [
+        ("Pepperoni pizza with cheese", vec![1.0, 0.1, 0.0], "pizza"),
+        ("Mushroom pizza with cheese", vec![0.9, 0.2, 0.0], "pizza"),
+        ("Pineapple pizza", vec![1.0, 0.0, 0.1], "pizza"),
+        ("Chocolate cake", vec![0.0, 0.1, 1.0], "cake"),
+        ("Carrot cake", vec![0.1, 0.0, 0.9], "cake"),
+        ("Burnt cake", vec![0.0, 0.2, 1.0], "cake"),
+    ];
+    for (content, vector, tag) in documents {
+        victor
+            .add_single_embedding(content, vector, vec![tag])
+            .await
+            .unwrap();
+    }
+    let burnt = victor
+        .search_embedding(vec![0.0, 0.2, 1.0], vec!["cake"], 1)
+        .await
+        .unwrap()[0]
+        .embedding
+        .id;
+    victor.delete(burnt).await.unwrap();
+
+    let config = ClusterConfig {
+        clusters: 2,
+        representatives: 2,
+        keywords: 2,
+        ..Default::default()
+    };
+    let clusters = victor.clusters(config).await.unwrap();
+    assert_eq!(clusters.len(), 2);
+    assert_eq!(clusters[0].size, 3);
+    assert_eq!(clusters[0].keywords, vec!["pizza", "cheese"]);
+    assert_eq!(clusters[0].representatives.len(), 2);
+    assert!(clusters[0]
+        .representatives
+        .iter()
+        .all(|result| result.tags == vec!["pizza"]));
+
+    // deleted documents are left out
+    assert_eq!(clusters[1].size, 2);
+    assert_eq!(clusters[1].keywords[0], "cake");
+    assert!(clusters[1].representatives[0].similarity > 0.9);
+
+    let empty = Db::new(DirectoryHandle::default())
+        .clusters(ClusterConfig::default())
+        .await
+        .unwrap();
+    assert!(empty.is_empty());
+}
+
+#[tokio::test]
+async fn find_duplicates() {
+    let mut victor = Db::new(DirectoryHandle::default());
+    // vectors pointing in many different directions, with a copy of every twentieth one added at the end
+    let vectors = (0..200)
+        .map(|i| {
+            let angle = i as f32 * std::f32::consts::PI / 200.0;
+            vec![angle.cos(), angle.sin(), (i % 7) as f32 * 0.3]
+        })
+        .collect::<Vec<_>>();
+    let embeddings = vectors
+        .iter()
+        .enumerate()
+        .map(|(i, vector)| (format!("{i}"), format!("document {i}"), vector.clone()))
+        .chain((0..200).step_by(20).map(|i| {
+            (
+                format!("copy of {i}"),
+                format!("document {i}"),
+                vectors[i].clone(),
+            )
+        }))
+        .collect();
+    victor
+        .add_embeddings_with_ids(embeddings, Vec::<String>::new())
+        .await
+        .unwrap();
+
+    let duplicates = victor.find_duplicates(0.9999).await.unwrap();
+    assert_eq!(duplicates.len(), 10);
+    for group in &duplicates {
+        assert_eq!(group.ids.len(), 2);
+        assert_eq!(group.pairs.len(), 1);
+        assert_eq!(
+            (group.pairs[0].0, group.pairs[0].1),
+            (group.ids[0], group.ids[1])
+        );
+    }
+
+    // deleted documents aren't duplicates of anything any more
+    victor.delete(duplicates[0].ids[1]).await.unwrap();
+    assert_eq!(victor.find_duplicates(0.9999).await.unwrap().len(), 9);
+}
+
+#[tokio::test]
+async fn outliers() {
+    let mut victor = Db::new(DirectoryHandle::default());
+    assert!(victor.outliers(5).await.unwrap().is_empty());
+
+    // a lone document has nothing to be out of place next to
+    victor
+        .add_single_embedding("Pepperoni pizza", vec![1.0, 0.1, 0.0], Vec::<String>::new())
+        .await
+        .unwrap();
+    assert!(victor.outliers(5).await.unwrap().is_empty());
+
+    for (content, vector) in [
+        ("Mushroom pizza", vec![0.9, 0.2, 0.0]),
+        ("Pineapple pizza", vec![1.0, 0.0, 0.1]),
+        ("Chocolate cake", vec![0.0, 0.1, 1.0]),
+        ("Carrot cake", vec![0.1, 0.0, 0.9]),
+        ("Cookie banner", vec![-1.0, 0.0, 0.0]),
+    ] {
+        victor
+            .add_single_embedding(content, vector, vec!["food"])
+            .await
+            .unwrap();
+    }
+
+    let outliers = victor.outliers(10).await.unwrap();
+    assert_eq!(outliers.len(), 6);
+    assert_eq!(outliers[0].content, "Cookie banner");
+    assert_eq!(outliers[0].tags, vec!["food"]);
+    assert!(outliers
+        .windows(2)
+        .all(|pair| pair[0].similarity <= pair[1].similarity));
+
+    victor.delete(outliers[0].embedding.id).await.unwrap();
+    let outliers = victor.outliers(1).await.unwrap();
+    assert_eq!(outliers.len(), 1);
+    assert_ne!(outliers[0].content, "Cookie banner");
+}
+
+#[tokio::test]
+async fn scroll() {
+    use crate::{Filter, ScrollCursor};
+
+    let mut victor = Db::new(DirectoryHandle::default()).with_max_segment_size(100);
+    for i in 0..20 {
+        let tags = match i % 2 {
+            0 => vec!["even"],
+            _ => vec!["odd"],
+        };
+        let document =
+            Document::new(format!("{i}"), vec![i as f32, 1.0]).with_metadata("i", i as f64);
+        victor.add_documents(vec![document], tags).await.unwrap();
+    }
+
+    // pages go through every document once, with the cursor passed around as a string
+    let mut listed = Vec::new();
+    let mut cursor = None;
+    loop {
+        let page = victor
+            .scroll(Vec::<String>::new(), &Filter::All, cursor, 3)
+            .await
+            .unwrap();
+        assert!(!page.documents.is_empty() && page.documents.len() <= 3);
+        listed.extend(page.documents.into_iter().map(|document| document.content));
+        match page.cursor {
+            Some(next) => cursor = Some(next.to_string().parse::<ScrollCursor>().unwrap()),
+            None => break,
+        }
+    }
+    listed.sort_by_key(|content| content.parse::<usize>().unwrap());
+    assert_eq!(listed, (0..20).map(|i| i.to_string()).collect::<Vec<_>>());
+
+    // filtered by tags and metadata, with a document deleted partway through
+    let first = victor
+        .scroll(vec!["even"], &Filter::lt("i", 10.0), None, 2)
+        .await
+        .unwrap();
+    assert_eq!(first.documents.len(), 2);
+    assert!(first
+        .documents
+        .iter()
+        .all(|document| document.tags == vec!["even"]));
+    let page = victor
+        .scroll(
+            vec!["even"],
+            &Filter::lt("i", 10.0),
+            first.cursor.clone(),
+            10,
+        )
+        .await
+        .unwrap();
+    victor.delete(page.documents[0].id).await.unwrap();
+    let rest = victor
+        .scroll(vec!["even"], &Filter::lt("i", 10.0), first.cursor, 10)
+        .await
+        .unwrap();
+    assert_eq!(page.documents.len(), 3);
+    assert_eq!(rest.documents[..], page.documents[1..]);
+    assert!(rest.cursor.is_none());
+
+    assert!(victor
+        .scroll(Vec::<String>::new(), &Filter::All, None, 0)
+        .await
+        .is_err());
+    let empty = Db::new(DirectoryHandle::default())
+        .scroll(Vec::<String>::new(), &Filter::All, None, 10)
+        .await
+        .unwrap();
+    assert!(empty.documents.is_empty() && empty.cursor.is_none());
+}
+
+#[tokio::test]
+async fn to_matrix() {
+    let mut victor = Db::with_settings(
+        DirectoryHandle::default(),
+        Settings {
+            dimension: Some(2),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    let (matrix, ids) = victor.to_matrix(Vec::<String>::new()).await.unwrap();
+    assert_eq!(matrix.shape(), (0, 2));
+    assert!(ids.is_empty());
+
+    for (content, vector) in [("a", vec![1.0, 0.0]), ("b", vec![0.0, 1.0])] {
+        victor
+            .add_single_embedding(content, vector, vec!["letters"])
+            .await
+            .unwrap();
+    }
+    let (matrix, ids) = victor.to_matrix(vec!["letters"]).await.unwrap();
+    assert_eq!(matrix.shape(), (2, 2));
+    for (row, id) in matrix.row_iter().zip(&ids) {
+        let stored = victor
+            .search_embedding(row.iter().copied().collect(), vec!["letters"], 1)
+            .await
+            .unwrap();
+        assert_eq!(stored[0].embedding.id, *id);
+    }
+
+    victor.delete(ids[0]).await.unwrap();
+    let (matrix, remaining) = victor.to_matrix(vec!["letters"]).await.unwrap();
+    assert_eq!(matrix.nrows(), 1);
+    assert_eq!(remaining, ids[1..]);
+}
+
+#[tokio::test]
+async fn add_embeddings_from_slice() {
+    use crate::Deduplication;
+
+    let data = (0..100)
+        .flat_map(|i| [(i as f32).cos(), (i as f32).sin(), 2.0])
+        .collect::<Vec<_>>();
+    let contents = (0..100).map(|i| format!("{i}")).collect::<Vec<_>>();
+
+    // straight into db files, normalized on the way in, and in as many as it takes
+    let mut victor = Db::builder(DirectoryHandle::default())
+        .storage(Storage::Full)
+        .normalize(true)
+        .max_segment_size(1000)
+        .build()
+        .await
+        .unwrap();
+    let added = victor
+        .add_embeddings_from_slice(contents.clone(), &data, 3, vec!["circle"])
+        .await
+        .unwrap();
+    assert_eq!(added, 100);
+    assert_eq!(victor.settings().await.unwrap().dimension, Some(3));
+    let nearest = victor
+        .search_embedding(data[30..33].to_vec(), vec!["circle"], 1)
+        .await
+        .unwrap();
+    assert_eq!(nearest[0].content, "10");
+    let length = nearest[0]
+        .embedding
+        .vector
+        .iter()
+        .map(|x| x * x)
+        .sum::<f32>()
+        .sqrt();
+    assert!((length - 1.0).abs() < 1e-5);
+    assert!(victor.delete(nearest[0].embedding.id).await.unwrap());
+    assert_eq!(victor.stats().await.unwrap().documents, 99);
+
+    // the same as adding them one document at a time when they have to be copied anyway
+    let mut victor = Db::new(DirectoryHandle::default()).with_deduplication(Deduplication::Skip);
+    let mut duplicated = contents[..10].to_vec();
+    duplicated[9] = duplicated[0].clone();
+    let added = victor
+        .add_embeddings_from_slice(duplicated, &data[..30], 3, vec!["circle"])
+        .await
+        .unwrap();
+    assert_eq!(added, 10);
+    assert_eq!(victor.stats().await.unwrap().documents, 9);
+
+    assert!(victor
+        .add_embeddings_from_slice(contents[..2].to_vec(), &data[..5], 3, vec!["circle"])
+        .await
+        .is_err());
+    assert!(matches!(
+        victor
+            .add_embeddings_from_slice(contents[..3].to_vec(), &data[..6], 2, vec!["circle"])
+            .await,
+        Err(Error::DimensionMismatch { .. })
+    ));
+}
+
+#[tokio::test]
+async fn added_time_range() {
+    async fn search(victor: &Db, options: SearchOptions) -> Vec<String> {
+        let mut contents = victor
+            .search_embedding_with_options(vec![1.0, 0.0], vec!["pizza"], 10, &options)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|result| result.content)
+            .collect::<Vec<_>>();
+        contents.sort();
+        contents
+    }
+
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    let now = || {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as u64
+    };
+    let mut victor = Db::new(DirectoryHandle::default());
+    victor
+        .add_embeddings_with_ids(vec![("a", "old pizza", vec![1.0, 0.0])], vec!["pizza"])
+        .await
+        .unwrap();
+    std::thread::sleep(Duration::from_millis(5));
+    let middle = now();
+    std::thread::sleep(Duration::from_millis(5));
+    victor
+        .add_embeddings_with_ids(vec![("b", "new pizza", vec![0.9, 0.1])], vec!["pizza"])
+        .await
+        .unwrap();
+
+    assert_eq!(
+        search(&victor, SearchOptions::default().with_added(middle..)).await,
+        vec!["new pizza"]
+    );
+    assert_eq!(
+        search(&victor, SearchOptions::default().with_added(..middle)).await,
+        vec!["old pizza"]
+    );
+    assert_eq!(
+        search(
+            &victor,
+            SearchOptions::default().with_added_within(Duration::from_secs(60))
+        )
+        .await,
+        vec!["new pizza", "old pizza"]
+    );
+    // updating a document doesn't change when it was added, but deleting and adding it again does
+    victor
+        .add_embeddings_with_ids(vec![("a", "old pizza", vec![1.0, 0.1])], vec!["pizza"])
+        .await
+        .unwrap();
+    assert_eq!(
+        search(&victor, SearchOptions::default().with_added(..middle)).await,
+        vec!["old pizza"]
+    );
+    let old = victor
+        .search_embedding(vec![1.0, 0.1], vec!["pizza"], 1)
+        .await
+        .unwrap();
+    victor.delete(old[0].embedding.id).await.unwrap();
+    victor.compact().await.unwrap();
+    victor
+        .add_embeddings_with_ids(vec![("a", "old pizza", vec![1.0, 0.0])], vec!["pizza"])
+        .await
+        .unwrap();
+    assert!(
+        search(&victor, SearchOptions::default().with_added(..middle))
+            .await
+            .is_empty()
+    );
+}
+
+#[tokio::test]
+async fn group_by() {
+    use crate::GroupBy;
+
+    let mut victor = Db::new(DirectoryHandle::default());
+    for i in 0..5 {
+        victor
+            .add_single_embedding(
+                format!("menu {i}"),
+                vec![1.0, 0.01 * i as f32],
+                vec!["pizza", "source:menu"],
+            )
+            .await
+            .unwrap();
+    }
+    for i in 0..3 {
+        victor
+            .add_single_embedding(
+                format!("blog {i}"),
+                vec![0.5, 0.5 + 0.1 * i as f32],
+                vec!["source:blog"],
+            )
+            .await
+            .unwrap();
+    }
+    victor
+        .add_single_embedding("wiki", vec![0.0, 1.0], vec!["source:blog", "source:wiki"])
+        .await
+        .unwrap();
+
+    let search = |options: SearchOptions| {
+        let victor = &victor;
+        async move {
+            victor
+                .search_embedding_with_options(vec![1.0, 0.0], Vec::<String>::new(), 2, &options)
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|result| result.content)
+                .collect::<Vec<_>>()
+        }
+    };
+    assert_eq!(search(SearchOptions::default()).await, ["menu 0", "menu 1"]);
+    assert_eq!(
+        search(SearchOptions::default().with_group_by(GroupBy::TagSet)).await,
+        ["menu 0", "menu 1", "blog 0", "blog 1", "wiki"]
+    );
+    // the wiki document is grouped by its first source
+    assert_eq!(
+        search(SearchOptions::default().with_group_by(GroupBy::TagPrefix("source:".to_string())))
+            .await,
+        ["menu 0", "menu 1", "blog 0", "blog 1"]
+    );
+
+    // grouped results always include their tags
+    let results = victor
+        .search_embedding_with_options(
+            vec![1.0, 0.0],
+            Vec::<String>::new(),
+            1,
+            &SearchOptions::default().with_group_by(GroupBy::TagSet),
+        )
+        .await
+        .unwrap();
+    assert_eq!(results[0].tags, ["pizza", "source:menu"]);
+}
+
+#[tokio::test]
+async fn normalized_scores() {
+    for metric in [Metric::Cosine, Metric::Euclidean] {
+        let mut victor = Db::builder(DirectoryHandle::default())
+            .metric(metric)
+            .storage(Storage::Full)
+            .build()
+            .await
+            .unwrap();
+        victor
+            .add_embeddings(
+                vec![
+                    ("pizza", vec![1.0, 0.0]),
+                    ("pasta", vec![0.6, 0.8]),
+                    ("cake", vec![-1.0, 0.0]),
+                ],
+                vec!["food"],
+            )
+            .await
+            .unwrap();
+
+        let results = victor
+            .search_embedding(vec![1.0, 0.0], vec!["food"], 3)
+            .await
+            .unwrap();
+        let scores = results
+            .iter()
+            .map(|result| result.score)
+            .collect::<Vec<_>>();
+        assert_eq!(scores[0], 1.0);
+        assert!(scores[0] > scores[1] && scores[1] > scores[2] && scores[2] >= 0.0);
+        // the raw value is the cosine similarity or the distance
+        let raw = results[2].raw.unwrap();
+        match metric {
+            Metric::Cosine => assert!((raw + 1.0).abs() < 1e-6),
+            _ => assert!((raw - 2.0).abs() < 1e-6),
+        }
+    }
+
+    let mut victor = Db::new(DirectoryHandle::default());
+    victor
+        .add_single_embedding("error E1234", vec![1.0, 0.0], vec!["support"])
+        .await
+        .unwrap();
+    let results = victor
+        .search_keywords("e1234", vec!["support"], 1)
+        .await
+        .unwrap();
+    assert!(results[0].score > 0.0 && results[0].score < 1.0);
+    assert_eq!(results[0].raw, None);
+}
+
+#[tokio::test]
+async fn score_breakdown() {
+    use crate::Reranker;
+    use async_trait::async_trait;
+
+    /// Scores documents by their length.
+    struct LengthReranker;
+
+    #[async_trait]
+    impl Reranker for LengthReranker {
+        async fn rerank(&self, _query: &str, candidates: &[&str]) -> Result<Vec<f32>, Error> {
+            Ok(candidates
+                .iter()
+                .map(|content| content.len() as f32)
+                .collect())
+        }
+    }
+
+    let mut victor = Db::new(DirectoryHandle::default());
+    victor
+        .add_embeddings(
+            vec![
+                ("The printer shows error E1234", vec![0.0, 1.0]),
+                ("The printer is out of paper", vec![1.0, 0.0]),
+            ],
+            vec!["support"],
+        )
+        .await
+        .unwrap();
+
+    // off by default
+    let results = victor
+        .search_hybrid_embedding(
+            vec![1.0, 0.0],
+            "e1234",
+            vec!["support"],
+            2,
+            &SearchOptions::default(),
+        )
+        .await
+        .unwrap();
+    assert!(results.iter().all(|result| result.breakdown.is_none()));
+
+    let options = SearchOptions::default().with_include_breakdown(true);
+    let results = victor
+        .search_hybrid_embedding(vec![1.0, 0.0], "e1234", vec!["support"], 2, &options)
+        .await
+        .unwrap();
+    for result in &results {
+        let breakdown = result.breakdown.unwrap();
+        assert!(breakdown.vector.is_some());
+        assert_eq!(breakdown.fused, Some(result.similarity));
+        assert_eq!(breakdown.rerank, None);
+        // only one of the documents has the keyword
+        assert_eq!(
+            breakdown.keyword.is_some(),
+            result.content.contains("E1234")
+        );
+    }
+
+    let results = victor
+        .search_embedding_with_options(vec![1.0, 0.0], vec!["support"], 1, &options)
+        .await
+        .unwrap();
+    assert_eq!(
+        results[0].breakdown.unwrap().vector,
+        Some(results[0].similarity)
+    );
+
+    let victor = victor.with_reranker(LengthReranker);
+    let results = victor
+        .search_hybrid_embedding(vec![1.0, 0.0], "e1234", vec!["support"], 2, &options)
+        .await
+        .unwrap();
+    assert_eq!(results[0].content, "The printer shows error E1234");
+    let breakdown = results[0].breakdown.unwrap();
+    assert_eq!(breakdown.rerank, Some(29.0));
+    assert!(breakdown.keyword.is_some() && breakdown.fused.is_some());
+}
+
+#[tokio::test]
+async fn scorer() {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    let mut victor = Db::new(DirectoryHandle::default()).with_retained_originals(true);
+    victor
+        .add_documents(
+            vec![
+                Document::new("pepperoni", vec![1.0, 0.0]).with_metadata("popularity", 0.0),
+                Document::new("salami", vec![0.9, 0.1]).with_metadata("popularity", 10.0),
+                Document::new("pineapple", vec![0.0, 1.0]).with_metadata("popularity", 200.0),
+            ],
+            vec!["pizza"],
+        )
+        .await
+        .unwrap();
+
+    let popularity = SearchOptions::default().with_scorer(|similarity, document| {
+        let popularity = document.metadata["popularity"].as_number().unwrap() as f32;
+        similarity + popularity / 100.0
+    });
+    let search = |options: SearchOptions| {
+        let victor = &victor;
+        async move {
+            victor
+                .search_embedding_with_options(vec![1.0, 0.0], vec!["pizza"], 2, &options)
+                .await
+                .unwrap()
+        }
+    };
+
+    // the scorer is applied before the best are picked, and after they're re-scored by their originals
+    let results = search(popularity.clone()).await;
+    assert_eq!(results[0].content, "pineapple");
+    assert!((results[0].similarity - 2.0).abs() < 1e-3);
+    assert_eq!(results[1].content, "salami");
+    assert_eq!(results[0].raw, None);
+    let results = search(popularity.with_rescore(false)).await;
+    assert_eq!(results[0].content, "pineapple");
+
+    // scorers see when documents were added
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64;
+    let recent = SearchOptions::default().with_scorer(move |similarity, document| {
+        assert!(document.added.is_some_and(|added| added <= now));
+        assert!(document.external_id.is_none());
+        similarity
+    });
+    assert_eq!(search(recent).await[0].content, "pepperoni");
+}
+
+#[tokio::test]
+async fn tag_boosts() {
+    for metric in [Metric::Cosine, Metric::Euclidean] {
+        let mut victor = Db::builder(DirectoryHandle::default())
+            .metric(metric)
+            .storage(Storage::Full)
+            .build()
+            .await
+            .unwrap();
+        victor
+            .add_single_embedding("forum post", vec![1.0, 0.0], vec!["forum"])
+            .await
+            .unwrap();
+        victor
+            .add_single_embedding("official docs", vec![0.8, 0.6], vec!["official_docs"])
+            .await
+            .unwrap();
+        victor
+            .add_single_embedding("blog post", vec![0.6, 0.8], vec!["blog"])
+            .await
+            .unwrap();
+
+        let search = |options: SearchOptions| {
+            let victor = &victor;
+            async move {
+                victor
+                    .search_embedding_with_options(
+                        vec![1.0, 0.1],
+                        Vec::<String>::new(),
+                        3,
+                        &options.with_include_breakdown(true),
+                    )
+                    .await
+                    .unwrap()
+            }
+        };
+        let contents = |results: &[crate::db::NearestNeighborsResult]| {
+            results
+                .iter()
+                .map(|result| result.content.clone())
+                .collect::<Vec<_>>()
+        };
+
+        let results = search(SearchOptions::default()).await;
+        assert_eq!(
+            contents(&results),
+            ["forum post", "official docs", "blog post"]
+        );
+
+        // boosts above 1 raise a document whatever the sign of its similarity
+        let boosted = SearchOptions::default().with_tag_boosts([
+            ("official_docs", 3.0),
+            ("forum", 0.5),
+            ("unused", 2.0),
+        ]);
+        let results = search(boosted).await;
+        let expected = match metric {
+            Metric::Cosine => ["official docs", "blog post", "forum post"],
+            _ => ["official docs", "forum post", "blog post"],
+        };
+        assert_eq!(contents(&results), expected);
+        // tags are only looked up for boosting, not returned
+        assert!(results.iter().all(|result| result.tags.is_empty()));
+        let breakdown = results[0].breakdown.unwrap();
+        assert_eq!(breakdown.boost, Some(3.0));
+        let unboosted = match metric {
+            Metric::Cosine => 0.86 / 1.01f32.sqrt(),
+            _ => -(0.2f32.powi(2) + 0.5f32.powi(2)).sqrt(),
+        };
+        assert!((breakdown.vector.unwrap() - unboosted).abs() < 1e-5);
+        assert_eq!(results[0].raw, None);
+        let blog = results
+            .iter()
+            .find(|result| result.content == "blog post")
+            .unwrap();
+        assert_eq!(blog.breakdown.unwrap().boost, None);
+        assert!(blog.raw.is_some());
+    }
+
+    // keyword scores are boosted too
+    let mut victor = Db::new(DirectoryHandle::default());
+    victor
+        .add_single_embedding("pizza pizza", vec![1.0, 0.0], vec!["forum"])
+        .await
+        .unwrap();
+    victor
+        .add_single_embedding("pizza", vec![1.0, 0.0], vec!["official_docs"])
+        .await
+        .unwrap();
+    let options = SearchOptions::default()
+        .with_tag_boosts([("official_docs", 10.0)])
+        .with_fusion(crate::Fusion::Weighted {
+            keyword_weight: 1.0,
+        });
+    let results = victor
+        .search_hybrid_embedding(vec![1.0, 0.0], "pizza", Vec::<String>::new(), 1, &options)
+        .await
+        .unwrap();
+    assert_eq!(results[0].content, "pizza");
+}
+
+#[tokio::test]
+async fn predicate() {
+    let mut victor = Db::new(DirectoryHandle::default());
+    for i in 0..20 {
+        victor
+            .add_documents(
+                vec![
+                    Document::new(format!("pizza {i}"), vec![1.0, 0.01 * i as f32])
+                        .with_metadata("slices", i as f64),
+                ],
+                vec![if i % 2 == 0 { "even" } else { "odd" }],
+            )
+            .await
+            .unwrap();
+    }
+
+    // the best matches are left out, but there are still enough results
+    let options = SearchOptions::default().with_predicate(|document| {
+        document.metadata["slices"].as_number().unwrap() >= 10.0
+            && document.tags.contains("odd")
+            && document.added.is_some()
+    });
+    let results = victor
+        .search_embedding_with_options(vec![1.0, 0.0], Vec::<String>::new(), 3, &options)
+        .await
+        .unwrap();
+    let contents = results
+        .iter()
+        .map(|result| result.content.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(contents, ["pizza 11", "pizza 13", "pizza 15"]);
+    assert!(results.iter().all(|result| result.tags.is_empty()));
+
+    // keyword matches are filtered too
+    let results = victor
+        .search_hybrid_embedding(vec![1.0, 0.0], "pizza", Vec::<String>::new(), 20, &options)
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 5);
+}
+
+#[tokio::test]
+async fn batch() {
+    async fn contents(victor: &Db) -> Vec<String> {
+        let mut contents = victor
+            .search_embedding(vec![1.0, 1.0], Vec::<String>::new(), 10)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|result| result.content)
+            .collect::<Vec<_>>();
+        contents.sort();
+        contents
+    }
+
+    let mut victor = Db::new(DirectoryHandle::default());
+    victor
+        .add_embeddings_with_ids(
+            vec![("a", "hello", vec![1.0, 0.0]), ("b", "hi", vec![0.0, 1.0])],
+            vec!["greeting"],
+        )
+        .await
+        .unwrap();
+    let a = uuid::Uuid::new_v5(&uuid::Uuid::NAMESPACE_OID, b"a");
+    let b = uuid::Uuid::new_v5(&uuid::Uuid::NAMESPACE_OID, b"b");
+
+    // a batch that fails part way through leaves nothing behind, even the changes before the one that failed
+    let mut batch = victor.batch();
+    batch
+        .add_documents(vec![Document::new("hey", vec![1.0, 1.0])], vec!["greeting"])
+        .add_documents(
+            vec![Document::new("bonjour", vec![1.0, 1.0])],
+            vec!["french"],
+        )
+        .update(a, "hello there", vec![1.0, 0.5])
+        .delete(b)
+        .add_documents(
+            vec![Document::new("too many dimensions", vec![1.0, 1.0, 1.0])],
+            vec!["greeting"],
+        );
+    assert_eq!(batch.len(), 5);
+    assert!(matches!(
+        batch.commit().await,
+        Err(Error::DimensionMismatch { .. })
+    ));
+    assert_eq!(contents(&victor).await, vec!["hello", "hi"]);
+    assert_eq!(victor.tags().await.unwrap(), vec!["greeting"]);
+    assert!(!victor.recover().await.unwrap());
+    assert!(victor.verify_integrity().await.unwrap().is_ok());
+
+    // and one that doesn't applies every change
+    let mut batch = victor.batch();
+    batch
+        .add_documents(vec![Document::new("hey", vec![1.0, 1.0])], vec!["greeting"])
+        .update(a, "hello there", vec![1.0, 0.5])
+        .delete(b);
+    batch.commit().await.unwrap();
+    assert_eq!(contents(&victor).await, vec!["hello there", "hey"]);
+    assert!(victor.verify_integrity().await.unwrap().is_ok());
+}
+
+#[tokio::test]
+async fn recover_interrupted_batch() {
+    use crate::batch::{self, Backup};
+
+    let directory = DirectoryHandle::default();
+    let mut victor = Db::new(directory.clone());
+
+    // pretend the process died after this insert, part of a batch that started on an empty database
+    victor
+        .add_embeddings_with_ids(vec![("a", "hello", vec![1.0, 2.0, 3.0])], vec!["greeting"])
+        .await
+        .unwrap();
+    batch::write(&directory, &Backup::default()).await.unwrap();
+
+    assert!(victor.recover().await.unwrap());
+    assert!(!victor.recover().await.unwrap());
+    assert!(victor.tags().await.unwrap().is_empty());
+    assert!(victor
+        .search_embedding(vec![1.0, 2.0, 3.0], Vec::<String>::new(), 10)
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[tokio::test]
+async fn versions() {
+    async fn content(victor: &Db) -> String {
+        victor
+            .search_embedding(vec![1.0, 0.0], Vec::<String>::new(), 1)
+            .await
+            .unwrap()
+            .remove(0)
+            .content
+    }
+
+    let mut victor = Db::new(DirectoryHandle::default());
+    let add = |content: &'static str| Document::new(content, vec![1.0, 0.0]).with_id("a");
+    let a = uuid::Uuid::new_v5(&uuid::Uuid::NAMESPACE_OID, b"a");
+    assert_eq!(victor.version(a).await.unwrap(), None);
+
+    victor
+        .add_documents(vec![add("hello")], vec!["greeting"])
+        .await
+        .unwrap();
+    assert_eq!(victor.version(a).await.unwrap(), Some(1));
+    victor
+        .add_documents(vec![add("hi")], vec!["greeting"])
+        .await
+        .unwrap();
+    assert_eq!(victor.version(a).await.unwrap(), Some(2));
+
+    // a stale update changes nothing
+    assert!(matches!(
+        victor.update_if_version(a, 1, "hey", vec![0.0, 1.0]).await,
+        Err(Error::VersionConflict {
+            expected: 1,
+            found: Some(2),
+            ..
+        })
+    ));
+    assert_eq!(content(&victor).await, "hi");
+    assert_eq!(
+        victor
+            .update_if_version(a, 2, "hey", vec![0.0, 1.0])
+            .await
+            .unwrap(),
+        3
+    );
+    assert_eq!(content(&victor).await, "hey");
+
+    assert!(matches!(
+        victor.delete_if_version(a, 2).await,
+        Err(Error::VersionConflict { found: Some(3), .. })
+    ));
+    victor.delete_if_version(a, 3).await.unwrap();
+    assert_eq!(victor.version(a).await.unwrap(), None);
+    assert!(matches!(
+        victor.delete_if_version(a, 3).await,
+        Err(Error::VersionConflict { found: None, .. })
+    ));
+
+    // adding a deleted document again carries on from its last version
+    victor
+        .add_documents(vec![add("hello")], vec!["greeting"])
+        .await
+        .unwrap();
+    assert_eq!(victor.version(a).await.unwrap(), Some(4));
+}
+
+#[tokio::test]
+async fn subscribe() {
+    use crate::Event;
+    use futures::{FutureExt, Stream, StreamExt};
+
+    fn received(events: &mut (impl Stream<Item = Event> + Unpin)) -> Vec<Event> {
+        std::iter::from_fn(|| events.next().now_or_never().flatten()).collect()
+    }
+
+    let mut victor = Db::new(DirectoryHandle::default());
+    let mut events = victor.subscribe();
+    let a = uuid::Uuid::new_v5(&uuid::Uuid::NAMESPACE_OID, b"a");
+    let b = uuid::Uuid::new_v5(&uuid::Uuid::NAMESPACE_OID, b"b");
+    let document = |id: &str| Document::new("hello", vec![1.0, 0.0]).with_id(id);
+
+    victor
+        .add_documents(vec![document("a"), document("b")], vec!["greeting"])
+        .await
+        .unwrap();
+    victor
+        .add_documents(vec![document("a")], vec!["greeting"])
+        .await
+        .unwrap();
+    victor.update(b, "hi", vec![0.0, 1.0]).await.unwrap();
+    victor.delete(a).await.unwrap();
+    victor
+        .add_documents(vec![document("a")], vec!["greeting"])
+        .await
+        .unwrap();
+    assert_eq!(
+        received(&mut events),
+        vec![
+            Event::Inserted { ids: vec![a, b] },
+            Event::Updated { ids: vec![a] },
+            Event::Updated { ids: vec![b] },
+            Event::Deleted { ids: vec![a] },
+            Event::Inserted { ids: vec![a] },
+        ]
+    );
+
+    // the changes of a batch are only reported if it's committed
+    let mut batch = victor.batch();
+    batch.delete(a).update(b, "hey", vec![1.0, 0.0, 0.0]);
+    assert!(batch.commit().await.is_err());
+    assert!(received(&mut events).is_empty());
+    let mut batch = victor.batch();
+    batch.delete(a);
+    batch.commit().await.unwrap();
+    assert_eq!(received(&mut events), vec![Event::Deleted { ids: vec![a] }]);
+
+    // dropped streams are unsubscribed, and other subscribers are unaffected
+    let other = victor.subscribe();
+    drop(other);
+    victor.compact().await.unwrap();
+    victor.clear_db().await.unwrap();
+    assert!(matches!(
+        received(&mut events)[..],
+        [Event::Compacted { reclaimed }, Event::Cleared] if reclaimed > 0
+    ));
+}
+
+#[tokio::test]
+async fn hooks() {
+    use std::sync::{Arc, Mutex};
+
+    let searches = Arc::new(Mutex::new(Vec::new()));
+    let logged = searches.clone();
+    let mut victor = Db::new(DirectoryHandle::default())
+        .with_on_insert(|document, _| match document.content() {
+            "" => Err(Error::InvalidInput("empty document".to_string())),
+            content => {
+                let length = content.len() as f64;
+                document
+                    .metadata_mut()
+                    .insert("length".to_string(), length.into());
+                Ok(())
+            }
+        })
+        .with_on_delete(|_| Err(Error::InvalidInput("documents are kept".to_string())))
+        .with_before_search(move |request| {
+            logged
+                .lock()
+                .unwrap()
+                .push((request.query.map(String::from), request.top_n));
+            Ok(())
+        })
+        .with_after_search(|_, results| results.truncate(1));
+
+    victor
+        .add_documents(
+            vec![
+                Document::new("hello", vec![1.0, 0.0]),
+                Document::new("hi", vec![0.0, 1.0]),
+            ],
+            vec!["greeting"],
+        )
+        .await
+        .unwrap();
+    // a rejected document keeps the others it's added with out too, even in a batch
+    let rejected = vec![
+        Document::new("hey", vec![1.0, 1.0]),
+        Document::new("", vec![1.0, 1.0]),
+    ];
+    assert!(victor
+        .add_documents(rejected.clone(), vec!["greeting"])
+        .await
+        .is_err());
+    let mut batch = victor.batch();
+    batch.add_documents(rejected, vec!["greeting"]);
+    assert!(batch.commit().await.is_err());
+
+    let options = SearchOptions::default().with_filter(Filter::eq("length", 2.0));
+    let results = victor
+        .search_embedding_with_options(vec![1.0, 1.0], vec!["greeting"], 10, &options)
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].content, "hi");
+
+    let id = results[0].embedding.id;
+    assert!(victor.delete(id).await.is_err());
+
+    // hybrid searches are logged once, with their keywords, and every result but the first is dropped
+    let results = victor
+        .search_hybrid_embedding(
+            vec![1.0, 1.0],
+            "hello",
+            vec!["greeting"],
+            10,
+            &SearchOptions::default(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(
+        *searches.lock().unwrap(),
+        vec![(None, 10), (Some("hello".to_string()), 10)]
+    );
+}
+
+#[tokio::test]
+async fn segment_cache() {
+    async fn nearest(victor: &Db) -> String {
+        victor
+            .search_embedding(vec![1.0, 0.0], vec!["greeting"], 1)
+            .await
+            .unwrap()
+            .remove(0)
+            .content
+    }
+
+    let mut victor = Db::new(DirectoryHandle::default()).with_cache_budget(1024 * 1024);
+    let a = uuid::Uuid::new_v5(&uuid::Uuid::NAMESPACE_OID, b"a");
+    victor
+        .add_documents(
+            vec![
+                Document::new("hello", vec![1.0, 0.0]).with_id("a"),
+                Document::new("hi", vec![0.0, 1.0]),
+            ],
+            vec!["greeting"],
+        )
+        .await
+        .unwrap();
+    assert_eq!(victor.cache_size(), 0);
+
+    assert_eq!(nearest(&victor).await, "hello");
+    assert!(victor.cache_size() > 0);
+    assert_eq!(nearest(&victor).await, "hello");
+
+    // rewriting the file in place means it's read again
+    victor.update(a, "hey", vec![0.0, 1.0]).await.unwrap();
+    assert_eq!(nearest(&victor).await, "hi");
+
+    victor.clear_db().await.unwrap();
+    assert_eq!(victor.cache_size(), 0);
+
+    // files bigger than the budget aren't kept
+    let mut victor = Db::new(DirectoryHandle::default()).with_cache_budget(1);
+    victor
+        .add_embeddings(vec![("hello", vec![1.0, 0.0])], vec!["greeting"])
+        .await
+        .unwrap();
+    assert_eq!(nearest(&victor).await, "hello");
+    assert_eq!(victor.cache_size(), 0);
+}
+
+#[tokio::test]
+async fn content_cache_counts_decoded_size() {
+    use crate::{db::Embedding, Compression};
+
+    let settings = Settings {
+        compression: Compression::Zstd,
+        ..Default::default()
+    };
+    let mut victor = Db::with_settings(DirectoryHandle::default(), settings)
+        .await
+        .unwrap()
+        .with_cache_budget(4096);
+
+    // the content compresses to far less than the budget, but takes up more than it once it's read
+    victor
+        .add_embeddings(vec![("a".repeat(10_000), vec![1.0, 0.0])], vec!["greeting"])
+        .await
+        .unwrap();
+    let results = victor
+        .search_embedding(vec![1.0, 0.0], vec!["greeting"], 1)
+        .await
+        .unwrap();
+    assert_eq!(results[0].content.len(), 10_000);
+
+    // so only the db file is kept
+    assert_eq!(
+        victor.cache_size(),
+        std::mem::size_of::<Embedding>() + 2 * std::mem::size_of::<f32>()
+    );
+}
+
+#[tokio::test]
+async fn warm() {
+    let mut victor = Db::new(DirectoryHandle::default());
+    victor
+        .add_embeddings(vec![("hello", vec![1.0, 0.0])], vec!["greeting"])
+        .await
+        .unwrap();
+    victor
+        .add_embeddings(vec![("pizza", vec![0.0, 1.0])], vec!["food"])
+        .await
+        .unwrap();
+
+    // without a budget there's nothing to warm
+    victor.warm(vec!["greeting"]).await.unwrap();
+    assert_eq!(victor.cache_size(), 0);
+
+    let victor = victor.with_cache_budget(1024 * 1024);
+    victor.warm(vec!["greeting"]).await.unwrap();
+    let warmed = victor.cache_size();
+    assert!(warmed > 0);
+
+    // searching what was warmed doesn't read anything more into the cache
+    let results = victor
+        .search_embedding(vec![1.0, 0.0], vec!["greeting"], 1)
+        .await
+        .unwrap();
+    assert_eq!(results[0].content, "hello");
+    assert_eq!(victor.cache_size(), warmed);
+
+    // but other tags' files weren't warmed
+    victor.warm(Vec::<String>::new()).await.unwrap();
+    assert!(victor.cache_size() > warmed);
+}
+
+#[tokio::test]
+async fn catalog_sees_other_handles() {
+    async fn count(victor: &Db, vector: Vec<f32>) -> usize {
+        victor
+            .search_embedding(vector, vec!["letters"], 10)
+            .await
+            .unwrap()
+            .len()
+    }
+
+    let root = DirectoryHandle::default();
+    // opening with settings builds the catalog
+    let mut a = Db::with_settings(root.clone(), Settings::default())
+        .await
+        .unwrap();
+    a.add_embeddings_with_ids(vec![("a", "a", vec![1.0, 0.0])], vec!["letters"])
+        .await
+        .unwrap();
+
+    // another handle adds a document to the same db file after the catalog was built
+    let mut b = Db::new(root.clone());
+    b.add_embeddings_with_ids(vec![("b", "b", vec![0.0, 1.0])], vec!["letters"])
+        .await
+        .unwrap();
+    let id = uuid::Uuid::new_v5(&uuid::Uuid::NAMESPACE_OID, b"b");
+
+    assert_eq!(a.version(id).await.unwrap(), Some(1));
+    assert!(a.update(id, "b", vec![0.0, 1.0]).await.unwrap());
+    assert_eq!(
+        a.update_if_version(id, 2, "b", vec![0.0, 1.0])
+            .await
+            .unwrap(),
+        3
+    );
+
+    // adding it again replaces it rather than storing a second copy
+    a.add_embeddings_with_ids(vec![("b", "b", vec![0.0, 1.0])], vec!["letters"])
+        .await
+        .unwrap();
+    assert_eq!(count(&a, vec![0.0, 1.0]).await, 2);
+
+    // documents the first handle adds after the other one's are found where they were appended
+    a.add_embeddings_with_ids(vec![("c", "c", vec![1.0, 1.0])], vec!["letters"])
+        .await
+        .unwrap();
+    let c = uuid::Uuid::new_v5(&uuid::Uuid::NAMESPACE_OID, b"c");
+    assert!(a.update(c, "c", vec![1.0, 0.5]).await.unwrap());
+
+    assert!(a.delete(id).await.unwrap());
+    assert_eq!(count(&b, vec![0.0, 1.0]).await, 2);
+}
+
+#[tokio::test]
+async fn read_range() {
+    use crate::filesystem::{
+        CreateWritableOptions, DirectoryHandle as _, FileHandle as _, GetFileHandleOptions,
+        WritableFileStream as _,
+    };
+
+    let directory = DirectoryHandle::default();
+    let mut file_handle = directory
+        .get_file_handle_with_options("test.bin", &GetFileHandleOptions { create: true })
+        .await
+        .unwrap();
+    let mut writable = file_handle
+        .create_writable_with_options(&CreateWritableOptions {
+            keep_existing_data: false,
+        })
+        .await
+        .unwrap();
+    writable
+        .write_at_cursor_pos(vec![1, 2, 3, 4, 5])
+        .await
+        .unwrap();
+    writable.close().await.unwrap();
+
+    assert_eq!(file_handle.read_range(1, 2).await.unwrap(), vec![2, 3]);
+    // reads stop at the end of the file
+    assert_eq!(file_handle.read_range(3, 10).await.unwrap(), vec![4, 5]);
+    assert_eq!(
+        file_handle.read_range(10, 10).await.unwrap(),
+        Vec::<u8>::new()
+    );
+}
+
+#[cfg(feature = "mmap")]
+#[tokio::test]
+async fn mapped_search() {
+    let path = std::env::temp_dir().join(format!("victor-mmap-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir(&path).unwrap();
+
+    let mut victor = crate::native::Db::new(path.clone());
+    victor
+        .add_single_embedding("hello", vec![1.0, 2.0, 3.0], Vec::<String>::new())
+        .await
+        .unwrap();
+    victor
+        .add_single_embedding("goodbye", vec![-1.0, -2.0, -3.0], Vec::<String>::new())
+        .await
+        .unwrap();
+
+    let results = victor
+        .search_embedding(vec![1.0, 2.0, 3.0], Vec::<String>::new(), 1)
+        .await
+        .unwrap();
+    assert_eq!(results[0].content, "hello");
+
+    std::fs::remove_dir_all(path).unwrap();
+}
+
+#[tokio::test]
+async fn durable_writes() {
+    use crate::native::{DirectoryHandle, Durability};
+
+    for durability in [Durability::SyncOnClose, Durability::SyncOnFlush] {
+        let path = std::env::temp_dir().join(format!("victor-durable-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&path).unwrap();
+
+        let mut victor =
+            crate::native::Db::new(DirectoryHandle::from(path.clone()).with_durability(durability));
+        victor
+            .add_single_embedding("hello", vec![1.0, 2.0, 3.0], Vec::<String>::new())
+            .await
+            .unwrap();
+        victor
+            .add_single_embedding("goodbye", vec![-1.0, -2.0, -3.0], Vec::<String>::new())
+            .await
+            .unwrap();
+
+        // the data is all there when the database is opened again
+        let reopened = crate::native::Db::new(path.clone());
+        let results = reopened
+            .search_embedding(vec![1.0, 2.0, 3.0], Vec::<String>::new(), 2)
+            .await
+            .unwrap();
+        assert_eq!(results[0].content, "hello");
+        assert_eq!(results.len(), 2);
+
+        std::fs::remove_dir_all(path).unwrap();
+    }
+}
+
+#[tokio::test]
+async fn recover_interrupted_insert() {
+    use crate::{
+        db::Index,
+        filesystem::{DirectoryHandle as _, FileHandle as _, GetFileHandleOptions},
+        journal,
+    };
+
+    let directory = DirectoryHandle::default();
+    let mut victor = Db::new(directory.clone());
+    victor
+        .add_embeddings_with_ids(vec![("a", "hello", vec![1.0, 2.0, 3.0])], vec!["greeting"])
+        .await
+        .unwrap();
+
+    let segment = Index::filename_for_part(["greeting".to_string()].into(), 0);
+    let segment_size = directory
+        .get_file_handle_with_options(&segment, &GetFileHandleOptions { create: false })
+        .await
+        .unwrap()
+        .size()
+        .await
+        .unwrap();
+
+    // pretend the process died after writing this insert, but before finishing it
+    let mut journal = journal::start(&directory).await.unwrap();
+    victor
+        .add_embeddings_with_ids(vec![("b", "hi", vec![1.0, 2.0, 3.0])], vec!["greeting"])
+        .await
+        .unwrap();
+    journal.segment = Some((segment, segment_size));
+    journal.ids = vec![uuid::Uuid::new_v5(&uuid::Uuid::NAMESPACE_OID, b"b")];
+    journal::write(&directory, &journal).await.unwrap();
+
+    assert!(victor.recover().await.unwrap());
+    assert!(!victor.recover().await.unwrap());
+
+    let results = victor
+        .search_embedding(vec![1.0, 2.0, 3.0], Vec::<String>::new(), 10)
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].content, "hello");
+
+    // the rolled back document can be added again
+    victor
+        .add_embeddings_with_ids(vec![("b", "hi", vec![1.0, 2.0, 3.0])], vec!["greeting"])
+        .await
+        .unwrap();
+    let results = victor
+        .search_embedding(vec![1.0, 2.0, 3.0], Vec::<String>::new(), 10)
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 2);
+    assert!(victor.verify_integrity().await.unwrap().is_ok());
+}
+
+#[tokio::test]
+async fn recover_interrupted_update() {
+    use crate::{
+        db::Index,
+        filesystem::{DirectoryHandle as _, FileHandle as _, GetFileHandleOptions},
+        journal::{self, Rewritten},
+    };
+
+    let directory = DirectoryHandle::default();
+    let mut victor = Db::new(directory.clone());
+    victor
+        .add_embeddings_with_ids(vec![("a", "hello", vec![1.0, 2.0, 3.0])], vec!["greeting"])
+        .await
+        .unwrap();
+    let before = victor
+        .search_embedding(vec![1.0, 2.0, 3.0], Vec::<String>::new(), 10)
+        .await
+        .unwrap();
+
+    // the only record, right after the header
+    let segment = Index::filename_for_part(["greeting".to_string()].into(), 0);
+    let file = directory
+        .get_file_handle_with_options(&segment, &GetFileHandleOptions { create: false })
+        .await
+        .unwrap()
+        .read()
+        .await
+        .unwrap();
+    let offset = std::mem::size_of::<u32>();
+
+    // pretend the process died after rewriting the document, but before finishing it
+    let mut journal = journal::start(&directory).await.unwrap();
+    victor
+        .add_embeddings_with_ids(vec![("a", "bye", vec![-1.0, -2.0, -3.0])], vec!["greeting"])
+        .await
+        .unwrap();
+    journal.rewritten.push(Rewritten {
+        segment,
+        offset,
+        record: file[offset..].to_vec(),
+    });
+    journal::write(&directory, &journal).await.unwrap();
+
+    assert!(victor.recover().await.unwrap());
+    assert!(!victor.recover().await.unwrap());
+
+    // both the vector and the content are back the way they were
+    let results = victor
+        .search_embedding(vec![1.0, 2.0, 3.0], Vec::<String>::new(), 10)
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].embedding.id, before[0].embedding.id);
+    assert_eq!(results[0].embedding.vector, before[0].embedding.vector);
+    assert_eq!(results[0].content, "hello");
+    assert!(victor.verify_integrity().await.unwrap().is_ok());
+}
+
+#[tokio::test]
+async fn detect_corruption() {
+    use crate::{
+        db::Index,
+        filesystem::{
+            CreateWritableOptions, DirectoryHandle as _, FileHandle as _, GetFileHandleOptions,
+            WritableFileStream as _,
+        },
+    };
+
+    let directory = DirectoryHandle::default();
+    let mut victor = Db::new(directory.clone());
+    victor
+        .add_single_embedding("hello", vec![1.0, 2.0, 3.0], vec!["greeting"])
+        .await
+        .unwrap();
+
+    // flip a bit in the last byte of the db file, which still has a valid layout
+    let segment = Index::filename_for_part(["greeting".to_string()].into(), 0);
+    let mut file_handle = directory
+        .get_file_handle_with_options(&segment, &GetFileHandleOptions { create: false })
+        .await
+        .unwrap();
+    let size = file_handle.size().await.unwrap();
+    let last = file_handle.read_range(size - 1, 1).await.unwrap();
+    let mut writable = file_handle
+        .create_writable_with_options(&CreateWritableOptions {
+            keep_existing_data: true,
+        })
+        .await
+        .unwrap();
+    writable.seek(size - 1).await.unwrap();
+    writable
+        .write_at_cursor_pos(vec![last[0] ^ 1])
+        .await
+        .unwrap();
+    writable.close().await.unwrap();
+
+    let result = victor
+        .search_embedding(vec![1.0, 2.0, 3.0], Vec::<String>::new(), 1)
+        .await;
+    assert!(matches!(result, Err(Error::Corrupted { file, .. }) if file == segment));
+
+    // truncate the content file
+    victor.clear_db().await.unwrap();
+    victor
+        .add_single_embedding("hello", vec![1.0, 2.0, 3.0], vec!["greeting"])
+        .await
+        .unwrap();
+    let mut file_handle = directory
+        .get_file_handle_with_options("content.bin", &GetFileHandleOptions { create: false })
+        .await
+        .unwrap();
+    let size = file_handle.size().await.unwrap();
+    let kept = file_handle.read_range(0, size - 1).await.unwrap();
+    let mut writable = file_handle
+        .create_writable_with_options(&CreateWritableOptions {
+            keep_existing_data: false,
+        })
+        .await
+        .unwrap();
+    writable.write_at_cursor_pos(kept).await.unwrap();
+    writable.close().await.unwrap();
+
+    let result = victor
+        .search_embedding(vec![1.0, 2.0, 3.0], Vec::<String>::new(), 1)
+        .await;
+    assert!(
+        matches!(result, Err(Error::Corrupted { file, reason }) if file == "content.bin" && reason.starts_with("checksum mismatch"))
+    );
+}
+
+#[tokio::test]
+async fn migrate_legacy_database() {
+    use crate::{
+        filesystem::{DirectoryHandle as _, FileHandle as _, GetFileHandleOptions},
+        format,
+    };
+
+    let mut directory = DirectoryHandle::default();
+    let mut victor = Db::new(directory.clone());
+    victor
+        .add_single_embedding("hello", vec![1.0, 2.0, 3.0], vec!["greeting"])
+        .await
+        .unwrap();
+    assert_eq!(
+        format::read(&directory).await.unwrap(),
+        Some(format::VERSION)
+    );
+
+    // databases written before the format was versioned don't have these files
+    downgrade_contents(&directory).await;
+    for filename in ["format.bin", "settings.bin", "checksums.bin"] {
+        directory.remove_entry(filename).await.unwrap();
+    }
+
+    assert!(victor.migrate().await.unwrap());
+    assert!(!victor.migrate().await.unwrap());
+    assert_eq!(
+        format::read(&directory).await.unwrap(),
+        Some(format::VERSION)
+    );
+    for filename in ["settings.bin", "checksums.bin"] {
+        let size = directory
+            .get_file_handle_with_options(filename, &GetFileHandleOptions { create: false })
+            .await
+            .unwrap()
+            .size()
+            .await
+            .unwrap();
+        assert!(size > 0);
+    }
+
+    let results = victor
+        .search_embedding(vec![1.0, 2.0, 3.0], Vec::<String>::new(), 1)
+        .await
+        .unwrap();
+    assert_eq!(results[0].content, "hello");
+
+    // a newer version of victor wrote this database
+    format::write(&directory, format::VERSION + 1)
+        .await
+        .unwrap();
+    let result = victor
+        .search_embedding(vec![1.0, 2.0, 3.0], Vec::<String>::new(), 1)
+        .await;
+    assert!(matches!(result, Err(Error::UnsupportedVersion { .. })));
+    assert!(matches!(
+        victor.migrate().await,
+        Err(Error::UnsupportedVersion { .. })
+    ));
+}
+
+#[tokio::test]
+async fn compression() {
+    use crate::{
+        db::Index,
+        filesystem::{DirectoryHandle as _, FileHandle as _, GetFileHandleOptions},
+        Compression,
+    };
+
+    let mut sizes = Vec::new();
+    for compression in [Compression::None, Compression::Zstd, Compression::Deflate] {
+        let directory = DirectoryHandle::default();
+        let settings = Settings {
+            storage: Storage::Full,
+            compression,
+            ..Default::default()
+        };
+        let mut victor = Db::with_settings(directory.clone(), settings)
+            .await
+            .unwrap();
+
+        // two appends, so compressed files have two frames
+        for i in 0..2 {
+            let embeddings = (0..50)
+                .map(|j| {
+                    (
+                        format!("pizza {i} {j}"),
+                        vec![1.0, 2.0, (i * 50 + j) as f32],
+                    )
+                })
+                .collect();
+            victor
+                .add_embeddings(embeddings, vec!["pizza"])
+                .await
+                .unwrap();
+        }
+
+        let results = victor
+            .search_embedding(vec![1.0, 2.0, 70.0], vec!["pizza"], 1)
+            .await
+            .unwrap();
+        assert_eq!(results[0].content, "pizza 1 20");
+        assert_eq!(results[0].embedding.vector, vec![1.0, 2.0, 70.0]);
+
+        // updates rewrite compressed files
+        let id = results[0].embedding.id;
+        assert!(victor
+            .update(id, "updated", vec![1.0, 2.0, 1000.0])
+            .await
+            .unwrap());
+        let results = victor
+            .search_embedding(vec![1.0, 2.0, 1000.0], vec!["pizza"], 1)
+            .await
+            .unwrap();
+        assert_eq!(results[0].content, "updated");
+        assert_eq!(results[0].embedding.vector, vec![1.0, 2.0, 1000.0]);
+
+        let segment = Index::filename_for_part(["pizza".to_string()].into(), 0);
+        let mut size = 0;
+        for filename in [segment.as_str(), "content.bin"] {
+            size += directory
+                .get_file_handle_with_options(filename, &GetFileHandleOptions { create: false })
+                .await
+                .unwrap()
+                .size()
+                .await
+                .unwrap();
+        }
+        sizes.push(size);
+
+        // the compression can't change once the database has embeddings
+        let result = Db::with_settings(directory.clone(), Settings::default()).await;
+        assert!(matches!(result, Err(Error::InvalidInput(_))));
+    }
+
+    assert!(sizes[1] < sizes[0]);
+    assert!(sizes[2] < sizes[0]);
+}
+
+#[tokio::test]
+async fn migrate_settings_without_compression() {
+    use crate::{
+        filesystem::{
+            CreateWritableOptions, DirectoryHandle as _, FileHandle as _, GetFileHandleOptions,
+            WritableFileStream as _,
+        },
+        format,
+    };
+
+    let directory = DirectoryHandle::default();
+    let settings = Settings {
+        metric: Metric::Dot,
+        ..Default::default()
+    };
+    let mut victor = Db::with_settings(directory.clone(), settings)
+        .await
+        .unwrap();
+    victor
+        .add_single_embedding("hello", vec![1.0, 2.0, 3.0], vec!["greeting"])
+        .await
+        .unwrap();
+
+    // format version 1 recorded the settings without their compression
+    let v1 = bincode::serialize(&(Metric::Dot, Some(3usize), Storage::default())).unwrap();
+    let mut file_handle = directory
+        .get_file_handle_with_options("settings.bin", &GetFileHandleOptions { create: false })
+        .await
+        .unwrap();
+    let mut writable = file_handle
+        .create_writable_with_options(&CreateWritableOptions {
+            keep_existing_data: false,
+        })
+        .await
+        .unwrap();
+    writable.write_at_cursor_pos(v1).await.unwrap();
+    writable.close().await.unwrap();
+    downgrade_contents(&directory).await;
+    format::write(&directory, 1).await.unwrap();
+
+    let expected = Settings {
+        dimension: Some(3),
+        ..settings
+    };
+    // the old layout can still be read before migrating
+    assert_eq!(victor.settings().await.unwrap(), expected);
+
+    assert!(victor.migrate().await.unwrap());
+    assert_eq!(
+        format::read(&directory).await.unwrap(),
+        Some(format::VERSION)
+    );
+    assert_eq!(victor.settings().await.unwrap(), expected);
+}
+
+#[tokio::test]
+async fn delete_and_compact() {
+    let mut victor = Db::new(DirectoryHandle::default());
+    victor
+        .build_hnsw_index(HnswConfig::default())
+        .await
+        .unwrap();
+    victor
+        .add_embeddings_with_ids(
+            (0..20)
+                .map(|i| {
+                    (
+                        format!("{i}"),
+                        format!("pizza {i}"),
+                        vec![1.0, 2.0, i as f32],
+                    )
+                })
+                .collect(),
+            vec!["pizza"],
+        )
+        .await
+        .unwrap();
+
+    async fn search(victor: &Db, i: usize) -> Vec<crate::db::NearestNeighborsResult> {
+        victor
+            .search_embedding(vec![1.0, 2.0, i as f32], vec!["pizza"], 1)
+            .await
+            .unwrap()
+    }
+
+    let id = search(&victor, 3).await[0].embedding.id;
+    assert!(victor.delete(id).await.unwrap());
+    assert!(!victor.delete(id).await.unwrap());
+    assert!(!victor.delete(uuid::Uuid::new_v4()).await.unwrap());
+    assert_ne!(search(&victor, 3).await[0].content, "pizza 3");
+
+    // deleted documents can't be updated, but can be added again
+    assert!(!victor
+        .update(id, "updated", vec![1.0, 2.0, 3.0])
+        .await
+        .unwrap());
+    victor
+        .add_embeddings_with_ids(vec![("3", "pizza 3", vec![1.0, 2.0, 3.0])], vec!["pizza"])
+        .await
+        .unwrap();
+    assert_eq!(search(&victor, 3).await[0].content, "pizza 3");
+
+    for i in [3, 4, 5] {
+        let id = search(&victor, i).await[0].embedding.id;
+        assert!(victor.delete(id).await.unwrap());
+    }
+    assert!(victor.compact().await.unwrap() > 0);
+    assert_eq!(victor.compact().await.unwrap(), 0);
+
+    // the graph was rebuilt for the embeddings that are left
+    for i in (0..20).filter(|i| ![3, 4, 5].contains(i)) {
+        assert_eq!(search(&victor, i).await[0].content, format!("pizza {i}"));
+    }
+    let all = victor
+        .search_embedding(vec![1.0, 2.0, 0.0], vec!["pizza"], 100)
+        .await
+        .unwrap();
+    assert_eq!(all.len(), 17);
+}
+
+#[tokio::test]
+async fn split_segments() {
+    use crate::{
+        db::Index,
+        filesystem::{DirectoryHandle as _, FileHandle as _, GetFileHandleOptions},
+    };
+
+    let directory = DirectoryHandle::default();
+    let mut victor = Db::new(directory.clone()).with_max_segment_size(100);
+    for i in 0..10 {
+        victor
+            .add_embeddings_with_ids(
+                vec![(
+                    format!("{i}"),
+                    format!("pizza {i}"),
+                    vec![1.0, 2.0, i as f32],
+                )],
+                vec!["pizza"],
+            )
+            .await
+            .unwrap();
+    }
+
+    // each file stops growing once it passes 100 bytes, so the documents are spread over several
+    let tags = std::collections::BTreeSet::from(["pizza".to_string()]);
+    for part in 0..3 {
+        let filename = Index::filename_for_part(tags.clone(), part);
+        let size = directory
+            .get_file_handle_with_options(&filename, &GetFileHandleOptions { create: false })
+            .await
+            .unwrap()
+            .size()
+            .await
+            .unwrap();
+        assert!(size > 0 && size < 200);
+    }
+
+    for i in 0..10 {
+        let results = victor
+            .search_embedding(vec![1.0, 2.0, i as f32], vec!["pizza"], 1)
+            .await
+            .unwrap();
+        assert_eq!(results[0].content, format!("pizza {i}"));
+    }
+
+    // documents in any part can be updated and deleted
+    victor
+        .add_embeddings_with_ids(vec![("9", "updated", vec![1.0, 2.0, 100.0])], vec!["pizza"])
+        .await
+        .unwrap();
+    let results = victor
+        .search_embedding(vec![1.0, 2.0, 100.0], vec!["pizza"], 1)
+        .await
+        .unwrap();
+    assert_eq!(results[0].content, "updated");
+    assert!(victor.delete(results[0].embedding.id).await.unwrap());
+    victor.compact().await.unwrap();
+    let results = victor
+        .search_embedding(vec![1.0, 2.0, 0.0], vec!["pizza"], 100)
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 9);
+}
+
+#[tokio::test]
+async fn migrate_index_without_parts() {
+    use crate::{
+        filesystem::{
+            CreateWritableOptions, DirectoryHandle as _, FileHandle as _, GetFileHandleOptions,
+            WritableFileStream as _,
+        },
+        format,
+    };
+    use std::collections::{BTreeSet, HashSet};
+
+    let directory = DirectoryHandle::default();
+    let mut victor = Db::new(directory.clone());
+    victor
+        .add_single_embedding("hello", vec![1.0, 2.0, 3.0], vec!["greeting"])
+        .await
+        .unwrap();
+
+    // format version 3 only recorded the tag sets
+    let files: HashSet<BTreeSet<String>> = [["greeting".to_string()].into()].into();
+    let v3 = bincode::serialize(&files).unwrap();
+    let mut file_handle = directory
+        .get_file_handle_with_options("index.bin", &GetFileHandleOptions { create: false })
+        .await
+        .unwrap();
+    let mut writable = file_handle
+        .create_writable_with_options(&CreateWritableOptions {
+            keep_existing_data: false,
+        })
+        .await
+        .unwrap();
+    writable.write_at_cursor_pos(v3.clone()).await.unwrap();
+    writable.close().await.unwrap();
+    crate::checksum::record(&directory, "index.bin", &v3)
+        .await
+        .unwrap();
+    downgrade_contents(&directory).await;
+    format::write(&directory, 3).await.unwrap();
+
+    // the old layout can still be read before migrating
+    let results = victor
+        .search_embedding(vec![1.0, 2.0, 3.0], vec!["greeting"], 1)
+        .await
+        .unwrap();
+    assert_eq!(results[0].content, "hello");
+
+    assert!(victor.migrate().await.unwrap());
+    assert_eq!(
+        format::read(&directory).await.unwrap(),
+        Some(format::VERSION)
+    );
+    let results = victor
+        .search_embedding(vec![1.0, 2.0, 3.0], vec!["greeting"], 1)
+        .await
+        .unwrap();
+    assert_eq!(results[0].content, "hello");
+}
+
+/// Rewrite `content.bin` as a single map, like databases written before format version 5.
+async fn downgrade_contents(directory: &DirectoryHandle) {
+    use crate::{
+        db::Content,
+        filesystem::{
+            CreateWritableOptions, DirectoryHandle as _, FileHandle as _, GetFileHandleOptions,
+            WritableFileStream as _,
+        },
+    };
+    use std::collections::HashMap;
+
+    let mut file_handle = directory
+        .get_file_handle_with_options("content.bin", &GetFileHandleOptions { create: false })
+        .await
+        .unwrap();
+    let log = file_handle.read().await.unwrap();
+    let mut rest = &log[..];
+    let mut contents = HashMap::<uuid::Uuid, Content>::new();
+    while !rest.is_empty() {
+        let (id, content) = bincode::deserialize_from(&mut rest).unwrap();
+        contents.insert(id, content);
+    }
+
+    let map = bincode::serialize(&contents).unwrap();
+    let mut writable = file_handle
+        .create_writable_with_options(&CreateWritableOptions {
+            keep_existing_data: false,
+        })
+        .await
+        .unwrap();
+    writable.write_at_cursor_pos(map.clone()).await.unwrap();
+    writable.close().await.unwrap();
+    crate::checksum::record(directory, "content.bin", &map)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn append_only_contents() {
+    use crate::filesystem::{DirectoryHandle as _, FileHandle as _, GetFileHandleOptions};
+
+    let directory = DirectoryHandle::default();
+    let mut victor = Db::new(directory.clone());
+    let content_size = || async {
+        directory
+            .get_file_handle_with_options("content.bin", &GetFileHandleOptions { create: false })
+            .await
+            .unwrap()
+            .size()
+            .await
+            .unwrap()
+    };
+
+    let mut sizes = Vec::new();
+    for content in ["first", "second", "third"] {
+        victor
+            .add_embeddings_with_ids(vec![("a", content, vec![1.0, 2.0, 3.0])], vec!["greeting"])
+            .await
+            .unwrap();
+        sizes.push(content_size().await);
+    }
+    // every version is appended, and the latest one wins
+    assert!(sizes[0] < sizes[1] && sizes[1] < sizes[2]);
+    let results = victor
+        .search_embedding(vec![1.0, 2.0, 3.0], Vec::<String>::new(), 10)
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].content, "third");
+
+    // compacting drops the replaced versions
+    assert_eq!(
+        victor.compact().await.unwrap(),
+        (sizes[2] - sizes[0]) as u64
+    );
+    assert_eq!(content_size().await, sizes[0]);
+    let results = victor
+        .search_embedding(vec![1.0, 2.0, 3.0], Vec::<String>::new(), 10)
+        .await
+        .unwrap();
+    assert_eq!(results[0].content, "third");
+}
+
+#[tokio::test]
+async fn catalog() {
+    let directory = DirectoryHandle::default();
+    let mut victor = Db::new(directory.clone());
+    for i in 0..5 {
+        victor
+            .add_embeddings_with_ids(
+                vec![(
+                    format!("{i}"),
+                    format!("pizza {i}"),
+                    vec![1.0, 2.0, i as f32],
+                )],
+                vec!["pizza"],
+            )
+            .await
+            .unwrap();
+    }
+    let ids = victor
+        .search_embedding(vec![1.0, 2.0, 4.0], vec!["pizza"], 5)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|result| (result.content, result.embedding.id))
+        .collect::<std::collections::HashMap<_, _>>();
+
+    // the catalog is built when the database is opened
+    let mut reopened = Db::with_settings(directory.clone(), Settings::default())
+        .await
+        .unwrap();
+    assert!(reopened
+        .update(ids["pizza 4"], "updated", vec![1.0, 2.0, 4.0])
+        .await
+        .unwrap());
+    assert!(!reopened
+        .update(uuid::Uuid::new_v4(), "missing", vec![1.0, 2.0, 3.0])
+        .await
+        .unwrap());
+
+    // compacting through the other handle moves embeddings, so the first handle's catalog is stale
+    assert!(reopened.delete(ids["pizza 0"]).await.unwrap());
+    reopened.compact().await.unwrap();
+    assert!(victor
+        .update(ids["pizza 4"], "updated again", vec![1.0, 2.0, 4.0])
+        .await
+        .unwrap());
+    let results = victor
+        .search_embedding(vec![1.0, 2.0, 4.0], vec!["pizza"], 1)
+        .await
+        .unwrap();
+    assert_eq!(results[0].content, "updated again");
+}
+
+#[tokio::test]
+async fn bloom_filters() {
+    use crate::{
+        bloom,
+        db::Index,
+        filesystem::{
+            CreateWritableOptions, DirectoryHandle as _, FileHandle as _, GetFileHandleOptions,
+            WritableFileStream as _,
+        },
+    };
+
+    let mut directory = DirectoryHandle::default();
+    let mut victor = Db::new(directory.clone());
+    victor
+        .add_embeddings_with_ids(vec![("a", "hello", vec![1.0, 2.0, 3.0])], vec!["greeting"])
+        .await
+        .unwrap();
+    victor
+        .add_embeddings_with_ids(
+            vec![("b", "goodbye", vec![3.0, 2.0, 1.0])],
+            vec!["farewell"],
+        )
+        .await
+        .unwrap();
+    let hello = victor
+        .search_embedding(vec![1.0, 2.0, 3.0], vec!["greeting"], 1)
+        .await
+        .unwrap()[0]
+        .embedding
+        .id;
+
+    // damage the farewell file without updating its checksum, so reading it would fail
+    let farewell = Index::filename_for_part(["farewell".to_string()].into(), 0);
+    let mut file_handle = directory
+        .get_file_handle_with_options(&farewell, &GetFileHandleOptions { create: false })
+        .await
+        .unwrap();
+    let mut writable = file_handle
+        .create_writable_with_options(&CreateWritableOptions {
+            keep_existing_data: true,
+        })
+        .await
+        .unwrap();
+    writable.seek(4).await.unwrap();
+    writable.write_at_cursor_pos(vec![0xff; 8]).await.unwrap();
+    writable.close().await.unwrap();
+
+    // without a catalog, finding the greeting by id only reads the file its bloom filter points to
+    let mut reopened = Db::new(directory.clone());
+    assert!(reopened
+        .update(hello, "hi", vec![1.0, 2.0, 3.0])
+        .await
+        .unwrap());
+    assert!(reopened.delete(hello).await.unwrap());
+
+    // db files without a filter are always read, so then the damage is noticed
+    directory
+        .remove_entry(&bloom::filename_for_segment(&farewell))
+        .await
+        .unwrap();
+    let result = Db::new(directory).delete(uuid::Uuid::new_v4()).await;
+    assert!(matches!(result, Err(Error::Corrupted { .. })));
+}
+
+#[cfg(feature = "object-store")]
+#[tokio::test]
+async fn object_store() {
+    use std::sync::Arc;
+
+    let store = Arc::new(::object_store::memory::InMemory::new());
+    let mut victor = crate::object_store::Db::new(crate::object_store::DirectoryHandle::new(
+        store.clone(),
+        "victor",
+    ));
+    for i in 0..5 {
+        victor
+            .add_embeddings_with_ids(
+                vec![(
+                    format!("{i}"),
+                    format!("pizza {i}"),
+                    vec![1.0, 2.0, i as f32],
+                )],
+                vec!["pizza"],
+            )
+            .await
+            .unwrap();
+    }
+
+    // a second handle on the same store sees everything the first one wrote
+    let mut reopened = crate::object_store::Db::with_settings(
+        crate::object_store::DirectoryHandle::new(store, "victor"),
+        Settings::default(),
+    )
+    .await
+    .unwrap();
+    let results = reopened
+        .search_embedding(vec![1.0, 2.0, 3.0], vec!["pizza"], 1)
+        .await
+        .unwrap();
+    assert_eq!(results[0].content, "pizza 3");
+
+    assert!(reopened
+        .update(results[0].embedding.id, "updated", vec![1.0, 2.0, 3.0])
+        .await
+        .unwrap());
+    assert!(reopened.delete(results[0].embedding.id).await.unwrap());
+    reopened.compact().await.unwrap();
+    let results = reopened
+        .search_embedding(vec![1.0, 2.0, 3.0], vec!["pizza"], 10)
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 4);
+
+    reopened.clear_db().await.unwrap();
+}
+
+#[tokio::test]
+async fn bundle() {
+    let mut server = Db::new(DirectoryHandle::default()).with_max_segment_size(100);
+    for i in 0..5 {
+        server
+            .add_embeddings_with_ids(
+                vec![(
+                    format!("{i}"),
+                    format!("pizza {i}"),
+                    vec![1.0, 2.0, i as f32],
+                )],
+                vec!["pizza"],
+            )
+            .await
+            .unwrap();
+    }
+    server.build_lsh_index(LshConfig::default()).await.unwrap();
+    let bundle = server.export_bundle().await.unwrap();
+
+    // loading a bundle replaces whatever was there before
+    let mut client = Db::new(DirectoryHandle::default());
+    client
+        .add_single_embedding("calzone", vec![1.0, 2.0, 3.0], vec!["pizza"])
+        .await
+        .unwrap();
+    client.load_bundle(&bundle).await.unwrap();
+    let results = client
+        .search_embedding(vec![1.0, 2.0, 3.0], vec!["pizza"], 10)
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 5);
+    assert_eq!(results[0].content, "pizza 3");
+
+    // the loaded database can be written to like any other
+    client
+        .add_embeddings_with_ids(vec![("5", "pizza 5", vec![1.0, 2.0, 5.0])], vec!["pizza"])
+        .await
+        .unwrap();
+
+    assert!(matches!(
+        client.load_bundle(b"not a bundle").await,
+        Err(Error::Corrupted { .. })
+    ));
+}
+
+#[tokio::test]
+async fn export_and_import() {
+    let mut victor = Db::new(DirectoryHandle::default());
+    victor
+        .add_embeddings_with_ids(
+            vec![
+                ("a", "pepperoni", vec![1.0, 2.0, 3.0]),
+                ("b", "hawaiian", vec![-1.0, -2.0, -3.0]),
+            ],
+            vec!["pizza"],
+        )
+        .await
+        .unwrap();
+    let mut archive = Vec::new();
+    victor.export_to(&mut archive).await.unwrap();
+
+    // move the database to the native filesystem, and back again
+    let path = std::env::temp_dir().join(format!("victor-export-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir(&path).unwrap();
+    let mut native = crate::native::Db::new(path.clone());
+    native.import_from(archive.as_slice()).await.unwrap();
+    let results = native
+        .search_embedding(vec![-1.0, -2.0, -3.0], vec!["pizza"], 1)
+        .await
+        .unwrap();
+    assert_eq!(results[0].content, "hawaiian");
+
+    let mut archive = Vec::new();
+    native.export_to(&mut archive).await.unwrap();
+    let mut memory = Db::new(DirectoryHandle::default());
+    memory.import_from(archive.as_slice()).await.unwrap();
+    let results = memory
+        .search_embedding(vec![1.0, 2.0, 3.0], vec!["pizza"], 2)
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0].content, "pepperoni");
+
+    std::fs::remove_dir_all(path).unwrap();
+}
+
+#[tokio::test]
+async fn jsonl() {
+    let mut victor = Db::new(DirectoryHandle::default());
+    victor
+        .add_documents(
+            vec![
+                Document::new("pepperoni", vec![1.0, 2.0, 3.0])
+                    .with_id("a")
+                    .with_metadata("price", 10),
+                Document::new("hawaiian", vec![-1.0, -2.0, -3.0]).with_metadata("author", "bob"),
+            ],
+            vec!["pizza"],
+        )
+        .await
+        .unwrap();
+    victor
+        .add_single_embedding("garlic bread", vec![3.0, 2.0, 1.0], vec!["sides"])
+        .await
+        .unwrap();
+    let id = victor
+        .search_embedding(vec![1.0, 2.0, 3.0], vec!["pizza"], 1)
+        .await
+        .unwrap()[0]
+        .embedding
+        .id;
+    victor.delete(id).await.unwrap();
+
+    // deleted documents aren't exported
+    let mut jsonl = Vec::new();
+    assert_eq!(victor.export_jsonl(&mut jsonl).await.unwrap(), 2);
+
+    let mut copy = Db::new(DirectoryHandle::default());
+    assert_eq!(copy.import_jsonl(jsonl.as_slice()).await.unwrap(), 2);
+    let results = copy
+        .search_embedding(vec![-1.0, -2.0, -3.0], vec!["pizza"], 10)
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].content, "hawaiian");
+    let mut exported = Vec::new();
+    copy.export_jsonl(&mut exported).await.unwrap();
+    assert!(String::from_utf8(exported)
+        .unwrap()
+        .contains(r#""metadata":{"author":"bob"}"#));
+    assert_eq!(copy.tags().await.unwrap(), vec!["pizza", "sides"]);
+
+    // importing the same documents again updates them instead of adding duplicates
+    assert_eq!(copy.import_jsonl(jsonl.as_slice()).await.unwrap(), 2);
+    let results = copy
+        .search_embedding(vec![1.0, 2.0, 3.0], Vec::<String>::new(), 10)
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 2);
+
+    assert!(matches!(
+        copy.import_jsonl("{\"id\": 1}".as_bytes()).await,
+        Err(Error::InvalidInput(_))
+    ));
+}
+
+#[cfg(feature = "parquet")]
+#[tokio::test]
+async fn parquet() {
+    use arrow_array::{cast::AsArray, types::Float32Type, Array};
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    // store full vectors, so they're exported exactly
+    let settings = Settings {
+        storage: Storage::Full,
+        ..Default::default()
+    };
+    let mut victor = Db::with_settings(DirectoryHandle::default(), settings)
+        .await
+        .unwrap();
+    victor
+        .add_documents(
+            vec![
+                Document::new("pepperoni", vec![1.0, 2.0, 3.0])
+                    .with_id("a")
+                    .with_metadata("price", 10),
+                Document::new("hawaiian", vec![-1.0, -2.0, -3.0]).with_id("b"),
+            ],
+            vec!["pizza", "classic"],
+        )
+        .await
+        .unwrap();
+
+    let path = std::env::temp_dir().join(format!("victor-{}.parquet", uuid::Uuid::new_v4()));
+    let exported = victor
+        .export_parquet(std::fs::File::create(&path).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(exported, 2);
+
+    let batches = ParquetRecordBatchReaderBuilder::try_new(std::fs::File::open(&path).unwrap())
+        .unwrap()
+        .build()
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    std::fs::remove_file(path).unwrap();
+    assert_eq!(batches.len(), 1);
+    let batch = &batches[0];
+    assert_eq!(batch.num_rows(), 2);
+
+    let ids = batch.column_by_name("id").unwrap().as_string::<i32>();
+    let a = (0..2).find(|&row| ids.value(row) == "a").unwrap();
+    let contents = batch.column_by_name("content").unwrap().as_string::<i32>();
+    assert_eq!(contents.value(a), "pepperoni");
+    let tags = batch.column_by_name("tags").unwrap().as_list::<i32>();
+    assert_eq!(tags.value(a).as_string::<i32>().len(), 2);
+    let vectors = batch.column_by_name("vector").unwrap().as_fixed_size_list();
+    assert_eq!(
+        vectors.value(a).as_primitive::<Float32Type>().values(),
+        &[1.0, 2.0, 3.0]
+    );
+    let metadata = batch.column_by_name("metadata").unwrap().as_string::<i32>();
+    assert_eq!(metadata.value(a), r#"{"price":10.0}"#);
+}
+
+#[cfg(feature = "arrow")]
+#[tokio::test]
+async fn arrow() {
+    use std::sync::Arc;
+
+    use arrow_array::{
+        builder::{ListBuilder, StringBuilder},
+        types::Float32Type,
+        ArrayRef, Float32Array, ListArray, RecordBatch, StringArray,
+    };
+
+    // the kind of batch other tools write: variable-size vector lists, and some nulls
+    let vectors = ListArray::from_iter_primitive::<Float32Type, _, _>([
+        Some(vec![Some(1.0), Some(0.0)]),
+        Some(vec![Some(0.0), Some(1.0)]),
+        Some(vec![Some(0.7), Some(0.7)]),
+    ]);
+    let mut tags = ListBuilder::new(StringBuilder::new());
+    tags.append_value([Some("pizza")]);
+    tags.append_value([Some("pizza"), Some("sweet")]);
+    tags.append_null();
+    let columns: Vec<(&str, ArrayRef)> = vec![
+        (
+            "content",
+            Arc::new(StringArray::from(vec!["pepperoni", "pineapple", "plain"])),
+        ),
+        ("vector", Arc::new(vectors)),
+        (
+            "id",
+            Arc::new(StringArray::from(vec![Some("a"), None, None])),
+        ),
+        ("tags", Arc::new(tags.finish())),
+        (
+            "metadata",
+            Arc::new(StringArray::from(vec![None, Some(r#"{"price":2}"#), None])),
+        ),
+    ];
+    let batch = RecordBatch::try_from_iter(columns).unwrap();
+
+    let mut victor = Db::new(DirectoryHandle::default());
+    assert_eq!(victor.import_arrow(&batch).await.unwrap(), 3);
+    let nearest = victor
+        .search_embedding(vec![0.0, 1.0], vec!["sweet"], 1)
+        .await
+        .unwrap();
+    assert_eq!(nearest[0].content, "pineapple");
+    let nearest = victor
+        .search_embedding(vec![1.0, 0.0], vec!["pizza"], 1)
+        .await
+        .unwrap();
+    assert_eq!(nearest[0].external_id.as_deref(), Some("a"));
+
+    // what's exported can be imported again
+    let exported = victor.export_arrow().await.unwrap();
+    assert_eq!(exported.num_rows(), 3);
+    let mut copy = Db::new(DirectoryHandle::default());
+    assert_eq!(copy.import_arrow(&exported).await.unwrap(), 3);
+    let options = SearchOptions::default().with_filter(Filter::eq("price", 2));
+    let nearest = copy
+        .search_embedding_with_options(vec![0.0, 1.0], vec!["sweet"], 1, &options)
+        .await
+        .unwrap();
+    assert_eq!(nearest[0].content, "pineapple");
+
+    let floats: ArrayRef = Arc::new(Float32Array::from(vec![1.0]));
+    let contents: ArrayRef = Arc::new(StringArray::from(vec!["not a list"]));
+    let batch = RecordBatch::try_from_iter([("content", contents), ("vector", floats)]).unwrap();
+    assert!(matches!(
+        victor.import_arrow(&batch).await,
+        Err(Error::InvalidInput(_))
+    ));
+}
+
+#[tokio::test]
+async fn import_npy() {
+    // a 1500x2 float32 array, so it's imported in more than one batch
+    let rows = 1500;
+    let header = format!("{{'descr': '<f4', 'fortran_order': False, 'shape': ({rows}, 2), }}\n");
+    let mut npy = b"\x93NUMPY\x01\x00".to_vec();
+    npy.extend((header.len() as u16).to_le_bytes());
+    npy.extend(header.as_bytes());
+    for row in 0..rows {
+        npy.extend(1.0f32.to_le_bytes());
+        npy.extend((row as f32).to_le_bytes());
+    }
+    let contents = (0..rows)
+        .map(|row| format!("row {row}"))
+        .collect::<Vec<_>>();
+
+    let mut victor = Db::new(DirectoryHandle::default());
+    assert_eq!(
+        victor
+            .import_npy(&npy, contents.clone(), vec!["rows"])
+            .await
+            .unwrap(),
+        rows
+    );
+    let results = victor
+        .search_embedding(vec![1.0, 1.0], vec!["rows"], 2 * rows as u32)
+        .await
+        .unwrap();
+    assert_eq!(results.len(), rows);
+    assert!(results.iter().any(|result| result.content == "row 1499"));
+
+    // every row needs content
+    assert!(matches!(
+        victor
+            .import_npy(&npy, contents[1..].to_vec(), vec!["rows"])
+            .await,
+        Err(Error::InvalidInput(_))
+    ));
+    assert!(matches!(
+        victor
+            .import_matrix(&[1.0, 2.0, 3.0], 2, vec!["a", "b"], vec!["rows"])
+            .await,
+        Err(Error::InvalidInput(_))
+    ));
+}
+
+#[tokio::test]
+async fn custom_embedder() {
+    struct LengthEmbedder;
+
+    #[async_trait::async_trait]
+    impl crate::Embedder for LengthEmbedder {
+        async fn embed(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>, Error> {
+            Ok(texts
+                .iter()
+                .map(|text| vec![text.len() as f32, 1.0])
+                .collect())
+        }
+    }
+
+    let mut victor = Db::new(DirectoryHandle::default()).with_embedder(LengthEmbedder);
+    victor
+        .add(vec!["a", "a much longer document"], vec!["docs"])
+        .await
+        .unwrap();
+    let results = victor.search("b", vec!["docs"], 1).await.unwrap();
+    assert_eq!(results[0].content, "a");
+}
+
+/// Serve `responses` to OpenAI-compatible embeddings requests, one per connection, returning the server's URL.
+///
+/// A `None` response is a server error, and otherwise each text is embedded as `[len, 1.0]`.
+#[cfg(feature = "http-embeddings")]
+fn serve_embeddings(responses: Vec<Option<()>>) -> String {
+    use std::io::{BufRead, BufReader, Read, Write};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/v1", listener.local_addr().unwrap());
+    std::thread::spawn(move || {
+        for response in responses {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut content_length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if let Some((name, value)) = line.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        content_length = value.trim().parse().unwrap();
+                    }
+                }
+                if line == "\r\n" {
+                    break;
+                }
+            }
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).unwrap();
+
+            let (status, body) = match response {
+                Some(()) => {
+                    let request: serde_json::Value = serde_json::from_slice(&body).unwrap();
+                    let data = request["input"]
+                        .as_array()
+                        .unwrap()
+                        .iter()
+                        .enumerate()
+                        .map(|(index, text)| {
+                            let len = text.as_str().unwrap().len();
+                            serde_json::json!({"embedding": [len, 1.0], "index": index})
+                        })
+                        .collect::<Vec<_>>();
+                    ("200 OK", serde_json::json!({ "data": data }).to_string())
+                }
+                None => ("500 Internal Server Error", "overloaded".to_string()),
+            };
+            let mut stream = reader.into_inner();
+            write!(
+                stream,
+                "HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            )
+            .unwrap();
+        }
+    });
+
+    url
+}
+
+#[cfg(feature = "http-embeddings")]
+#[tokio::test]
+async fn openai_embedder() {
+    use crate::OpenAiEmbedder;
+
+    // the first request fails and is retried, then the three documents are embedded two at a time
+    let url = serve_embeddings(vec![None, Some(()), Some(()), Some(())]);
+    let embedder = OpenAiEmbedder::new("test")
+        .with_base_url(url)
+        .with_batch_size(2);
+    let mut victor = Db::new(DirectoryHandle::default()).with_embedder(embedder);
+    victor
+        .add(vec!["a", "bb", "a much longer document"], vec!["docs"])
+        .await
+        .unwrap();
+    let results = victor.search("c", vec!["docs"], 3).await.unwrap();
+    assert_eq!(results.len(), 3);
+    assert_eq!(results[0].content, "a");
+
+    // errors that won't go away aren't retried
+    let url = serve_embeddings(vec![None]);
+    let embedder = OpenAiEmbedder::new("test")
+        .with_base_url(url)
+        .with_max_retries(0);
+    let victor = Db::new(DirectoryHandle::default()).with_embedder(embedder);
+    assert!(matches!(
+        victor.search("c", vec!["docs"], 1).await,
+        Err(Error::Embedding(_))
+    ));
+}
@@ -0,0 +1,51 @@
+//! Deleted documents, recorded in `tombstones.bin` until [`crate::Victor::compact`] drops their embeddings.
+//!
+//! Searches and listings also leave out documents that have expired (see [`crate::expiry`]), and the extra vectors
+//! of both (see [`crate::chunks`]), but those are never written down as tombstones: see [`removed`].
+
+use std::collections::HashSet;
+
+use uuid::Uuid;
+
+use crate::{
+    chunks,
+    db::{read_bin, write_bin},
+    error::Error,
+    expiry,
+    filesystem::DirectoryHandle,
+    utils,
+};
+
+const FILENAME: &str = "tombstones.bin";
+
+/// The ids of deleted documents that haven't been compacted away yet (see [`crate::Victor::delete`]).
+pub(crate) async fn read<D: DirectoryHandle>(root: &D) -> Result<HashSet<Uuid>, Error> {
+    read_bin(root, FILENAME).await
+}
+
+pub(crate) async fn write<D: DirectoryHandle>(
+    root: &D,
+    tombstones: &HashSet<Uuid>,
+) -> Result<(), Error> {
+    write_bin(root, FILENAME, tombstones).await
+}
+
+/// The ids to leave out of searches and listings until they're compacted away: deleted documents,
+/// documents that have expired (see [`crate::Document::with_ttl`]), and the extra vectors of both.
+pub(crate) async fn removed<D: DirectoryHandle>(root: &D) -> Result<HashSet<Uuid>, Error> {
+    let mut removed = read(root).await?;
+    removed.extend(expiry::expired(
+        &expiry::read(root).await?,
+        utils::now_millis(),
+    ));
+
+    // the extra vectors of deleted documents are deleted with them
+    let deleted_chunks = chunks::read(root)
+        .await?
+        .into_iter()
+        .filter(|(_, document)| removed.contains(document))
+        .map(|(chunk, _)| chunk)
+        .collect::<Vec<_>>();
+    removed.extend(deleted_chunks);
+    Ok(removed)
+}
@@ -8,3 +8,18 @@ pub fn set_panic_hook() {
     // https://github.com/rustwasm/console_error_panic_hook#readme
     console_error_panic_hook::set_once();
 }
+
+/// The current time, in milliseconds since the Unix epoch.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn now_millis() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |since_epoch| since_epoch.as_millis() as u64)
+}
+
+/// The current time, in milliseconds since the Unix epoch.
+/// `SystemTime` isn't available on the web, so this asks JavaScript.
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn now_millis() -> u64 {
+    js_sys::Date::now() as u64
+}
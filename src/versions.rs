@@ -0,0 +1,39 @@
+//! The version of each document that's changed since it was added, recorded in `versions.bin`
+//! (see [`crate::Victor::version`]).
+//!
+//! Documents start at version 1, which isn't recorded, so only documents that have been updated have an entry.
+
+use std::collections::HashMap;
+
+use uuid::Uuid;
+
+use crate::{
+    db::{read_bin, write_bin},
+    error::Error,
+    filesystem::DirectoryHandle,
+};
+
+const FILENAME: &str = "versions.bin";
+
+pub(crate) async fn read<D: DirectoryHandle>(root: &D) -> Result<HashMap<Uuid, u64>, Error> {
+    read_bin(root, FILENAME).await
+}
+
+pub(crate) async fn write<D: DirectoryHandle>(
+    root: &D,
+    versions: &HashMap<Uuid, u64>,
+) -> Result<(), Error> {
+    write_bin(root, FILENAME, versions).await
+}
+
+/// Move each of `ids` to its next version.
+pub(crate) async fn bump<D: DirectoryHandle>(
+    root: &D,
+    ids: impl IntoIterator<Item = Uuid>,
+) -> Result<(), Error> {
+    let mut versions = read(root).await?;
+    for id in ids {
+        *versions.entry(id).or_insert(1) += 1;
+    }
+    write(root, &versions).await
+}